@@ -11,7 +11,7 @@ use tari_jwt::{
     Ristretto256SigningKey,
 };
 use tari_payment_engine::{
-    db_types::{LoginToken, NewOrder, NewPayment, OrderId, Role},
+    db_types::{LoginToken, NewOrder, NewPayment, OrderId, Role, DEFAULT_MERCHANT},
     traits::{AuthManagement, NewWalletInfo, PaymentGatewayDatabase, WalletManagement},
 };
 use tari_payment_server::config::OrderIdField;
@@ -31,6 +31,9 @@ fn seed_orders() -> [NewOrder; 5] {
             total_price: MicroTari::from_tari(100),
             original_price: None,
             created_at: Utc.with_ymd_and_hms(2024, 3, 10, 15, 0, 0).unwrap(),
+            rate_id: None,
+            quote_expires_at: None,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
         },
         NewOrder {
             order_id: OrderId::new("2"),
@@ -42,6 +45,9 @@ fn seed_orders() -> [NewOrder; 5] {
             total_price: MicroTari::from_tari(200),
             original_price: None,
             created_at: Utc.with_ymd_and_hms(2024, 3, 10, 15, 30, 0).unwrap(),
+            rate_id: None,
+            quote_expires_at: None,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
         },
         NewOrder {
             order_id: OrderId::new("3"),
@@ -53,6 +59,9 @@ fn seed_orders() -> [NewOrder; 5] {
             total_price: MicroTari::from_tari(65),
             original_price: None,
             created_at: Utc.with_ymd_and_hms(2024, 3, 11, 16, 0, 0).unwrap(),
+            rate_id: None,
+            quote_expires_at: None,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
         },
         NewOrder {
             order_id: OrderId::new("4"),
@@ -64,6 +73,9 @@ fn seed_orders() -> [NewOrder; 5] {
             total_price: MicroTari::from_tari(350),
             original_price: None,
             created_at: Utc.with_ymd_and_hms(2024, 3, 11, 17, 0, 0).unwrap(),
+            rate_id: None,
+            quote_expires_at: None,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
         },
         NewOrder {
             order_id: OrderId::new("5"),
@@ -75,6 +87,9 @@ fn seed_orders() -> [NewOrder; 5] {
             total_price: MicroTari::from_tari(25),
             original_price: None,
             created_at: Utc.with_ymd_and_hms(2024, 3, 12, 18, 0, 0).unwrap(),
+            rate_id: None,
+            quote_expires_at: None,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
         },
     ]
 }
@@ -87,6 +102,7 @@ fn seed_payments() -> [NewPayment; 5] {
             txid: "alicepayment001".to_string(),
             memo: None,
             order_id: None,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
         },
         NewPayment {
             sender: "14wqR3rjyVbjgXDyLVaL97p3CksHc84cz9hLLMMTMYDjtBt".parse().unwrap(), // Alice
@@ -94,6 +110,7 @@ fn seed_payments() -> [NewPayment; 5] {
             txid: "alicepayment002".to_string(),
             memo: None,
             order_id: None,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
         },
         NewPayment {
             sender: "14XubwVbMhtp18SHrjfVKk7TRCx2yk7gZBbsjTPRWCXkCEp".parse().unwrap(), // Bob
@@ -101,6 +118,7 @@ fn seed_payments() -> [NewPayment; 5] {
             txid: "bobpayment001".to_string(),
             memo: None,
             order_id: None,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
         },
         NewPayment {
             sender: "14XubwVbMhtp18SHrjfVKk7TRCx2yk7gZBbsjTPRWCXkCEp".parse().unwrap(), // Bob
@@ -108,6 +126,7 @@ fn seed_payments() -> [NewPayment; 5] {
             txid: "bobpayment002".to_string(),
             memo: None,
             order_id: None,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
         },
         NewPayment {
             sender: "142Eyn9FMCsBVRsFBc2zqfgBxPTTpX9dYjtrPABa9whREdia".parse().unwrap(), // Anon
@@ -115,6 +134,7 @@ fn seed_payments() -> [NewPayment; 5] {
             txid: "anonpayment001".to_string(),
             memo: None,
             order_id: None,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
         },
     ]
 }
@@ -144,7 +164,12 @@ impl SuperAdmin {
     }
 
     pub fn token(&self, nonce: u64) -> String {
-        let claims = LoginToken { address: self.0.address.clone(), nonce, desired_roles: vec![Role::SuperAdmin] };
+        let claims = LoginToken {
+            address: self.0.address.clone(),
+            nonce,
+            desired_roles: vec![Role::SuperAdmin],
+            merchant: None,
+        };
         let claims = Claims::new(claims);
         let header = Header::empty().with_token_type("JWT");
         Ristretto256.token(&header, &claims, &Ristretto256SigningKey(self.0.secret.clone())).unwrap()
@@ -185,7 +210,7 @@ impl SeedUsers {
 
     pub fn token_for(&self, name: &str, nonce: u64, roles: Vec<Role>) -> String {
         let user = self.user(name);
-        let claims = LoginToken { address: user.address.clone(), nonce, desired_roles: roles };
+        let claims = LoginToken { address: user.address.clone(), nonce, desired_roles: roles, merchant: None };
         let claims = Claims::new(claims);
         let header = Header::empty().with_token_type("JWT");
         Ristretto256.token(&header, &claims, &Ristretto256SigningKey(user.secret.clone())).unwrap()
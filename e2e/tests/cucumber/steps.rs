@@ -197,6 +197,8 @@ async fn create_access_token(world: &mut TPGWorld, user: String) {
     let claims = JwtClaims {
         address: user.address.clone(),
         roles: vec![Role::User, Role::ReadAll, Role::Write, Role::SuperAdmin],
+        merchant: None,
+        session: Some(1),
     };
     let claims = Claims::new(claims);
     let header = Header::empty().with_token_type("JWT");
@@ -345,6 +347,12 @@ async fn check_event_trigger(world: &mut TPGWorld, event_type: String, step: &St
         EventType::PaymentReceived(e) => serde_json::to_string(&e),
         EventType::Confirmation(e) => serde_json::to_string(&e),
         EventType::OrderClaimed(e) => serde_json::to_string(&e),
+        EventType::RefundApproved(e) | EventType::RefundSent(e) => serde_json::to_string(&e),
+        EventType::OrderHeld(e) => serde_json::to_string(&e),
+        EventType::PaymentReverted(e) => serde_json::to_string(&e),
+        EventType::ApprovalRequested(e) | EventType::OperationApproved(e) | EventType::OperationRejected(e) => {
+            serde_json::to_string(&e)
+        },
     }
     .expect("Failed to serialize event");
     let expected = step.docstring().expect("No expected OrderModifiedEvent in docstring");
@@ -17,7 +17,10 @@ use tari_payment_engine::{
     SqliteDatabase,
 };
 use tari_payment_server::{
+    auth::{RevocationList, RolePermissions},
     config::{AuthConfig, ServerConfig},
+    event_stream::EventStream,
+    integrations::storefront::Storefronts,
     server::create_server_instance,
 };
 
@@ -49,6 +52,7 @@ impl Default for TPGWorld {
             port: 20000 + rand::random::<u16>() % 10_000,
             database_url: url.clone(),
             auth: AuthConfig::default(),
+            sessions: Default::default(),
             use_x_forwarded_for: false,
             use_forwarded: false,
             disable_wallet_whitelist: false,
@@ -56,7 +60,18 @@ impl Default for TPGWorld {
             unclaimed_order_timeout: Duration::seconds(2),
             unpaid_order_timeout: Duration::seconds(4),
             shopify_config: Default::default(),
+            merchants: Vec::new(),
+            woocommerce: None,
             strict_mode: true,
+            outbox: Default::default(),
+            overpayment_policy: Default::default(),
+            approval_policy: Default::default(),
+            rate_limits: Default::default(),
+            metrics_address: None,
+            rate_quotes: None,
+            rate_feed: None,
+            rate_circuit_breaker: Default::default(),
+            wallet_grpc: None,
         };
         Self {
             config,
@@ -155,7 +170,19 @@ impl TPGWorld {
             });
             let handlers = EventHandlers::new(1, hooks);
             let producers = handlers.producers();
-            let srv = create_server_instance(config, db, producers).expect("Error creating server instance");
+            let storefronts = Storefronts::from_config(&config).expect("Error creating storefronts");
+            let revocations = RevocationList::default();
+            let role_permissions = RolePermissions::default();
+            let srv = create_server_instance(
+                config,
+                db,
+                storefronts,
+                producers,
+                EventStream::default(),
+                revocations,
+                role_permissions,
+            )
+            .expect("Error creating server instance");
             // Start the event handlers
             tokio::spawn(async move {
                 handlers.start_handlers().await;
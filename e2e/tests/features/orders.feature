@@ -95,6 +95,11 @@ Feature: Orders endpoint
     When Alice GETs to "/api/order/id/2" with body
     Then I receive a 200 Ok response with the message 'null'
 
+  Scenario: Tokens without the orders.read_own permission cannot access the `/order/id/{}` endpoint
+    When Admin authenticates with nonce = 1 and roles = "read_all"
+    When Admin GETs to "/api/order/id/2" with body
+    Then I receive a 403 Forbidden response with the message 'Insufficient permissions.'
+
   Scenario: Standard user cannot enumerate the order/id endpoint
     When Alice authenticates with nonce = 1 and roles = "user"
     When Alice GETs to "/api/order/id/some_random_order" with body
@@ -13,8 +13,10 @@ use thiserror::Error;
 use tpg_common::MicroTari;
 
 use crate::{
+    events::EventType,
     helpers::{extract_and_verify_memo_signature, MemoSignatureError},
     tpe_api::order_objects::{address_to_base58, str_to_address},
+    traits::PaymentGatewayError,
 };
 
 //--------------------------------------     PublicKey       ---------------------------------------------------------
@@ -122,6 +124,17 @@ impl Type<Sqlite> for SerializedTariAddress {
     }
 }
 
+#[cfg(feature = "postgres")]
+impl Type<sqlx::Postgres> for SerializedTariAddress {
+    fn type_info() -> <sqlx::Postgres as Database>::TypeInfo {
+        <String as Type<sqlx::Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &<sqlx::Postgres as Database>::TypeInfo) -> bool {
+        <String as Type<sqlx::Postgres>>::compatible(ty)
+    }
+}
+
 impl PartialEq for SerializedTariAddress {
     fn eq(&self, other: &Self) -> bool {
         self.0 == other.0
@@ -174,7 +187,7 @@ impl From<String> for OrderStatusType {
 
 #[derive(Debug, Clone, Error)]
 #[error("Invalid conversion from string: {0}")]
-pub struct ConversionError(String);
+pub struct ConversionError(pub(crate) String);
 
 impl FromStr for OrderStatusType {
     type Err = ConversionError;
@@ -227,6 +240,15 @@ impl OrderId {
     }
 }
 
+//--------------------------------------      Merchants        ---------------------------------------------------------
+/// The merchant that orders belong to when no other merchant is given. Single-storefront installations only ever use
+/// this merchant.
+pub const DEFAULT_MERCHANT: &str = "default";
+
+pub fn default_merchant() -> String {
+    DEFAULT_MERCHANT.to_string()
+}
+
 //--------------------------------------     OrderStatus       ---------------------------------------------------------
 #[derive(Debug, Clone)]
 pub struct OrderStatus {
@@ -250,6 +272,22 @@ pub struct Order {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub status: OrderStatusType,
+    /// The id of the exchange rate used to price the order, if it was priced in another currency
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_id: Option<i64>,
+    /// When the price quote expires. Once it has expired, the order cannot be paid until it has been re-priced.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote_expires_at: Option<DateTime<Utc>>,
+    /// The merchant whose storefront created the order
+    #[serde(default = "default_merchant")]
+    pub merchant_id: String,
+}
+
+impl Order {
+    /// True if the order was priced at an exchange rate that is no longer valid
+    pub fn quote_has_expired(&self) -> bool {
+        self.quote_expires_at.is_some_and(|t| t <= Utc::now())
+    }
 }
 
 impl PartialEq for Order {
@@ -284,6 +322,12 @@ pub struct NewOrder {
     pub currency: String,
     /// The time the order was created on Shopify
     pub created_at: DateTime<Utc>,
+    /// The id of the exchange rate used to convert the original price into Tari
+    pub rate_id: Option<i64>,
+    /// When the exchange rate quote for this order expires
+    pub quote_expires_at: Option<DateTime<Utc>>,
+    /// The merchant whose storefront created the order
+    pub merchant_id: String,
 }
 
 impl NewOrder {
@@ -298,9 +342,25 @@ impl NewOrder {
             currency: "XTR".to_string(),
             created_at: Utc::now(),
             address: None,
+            rate_id: None,
+            quote_expires_at: None,
+            merchant_id: default_merchant(),
         }
     }
 
+    /// Assigns the order to the given merchant
+    pub fn with_merchant<S: Into<String>>(mut self, merchant_id: S) -> Self {
+        self.merchant_id = merchant_id.into();
+        self
+    }
+
+    /// Records the exchange rate the order was priced at, and when that price quote expires
+    pub fn with_quote(mut self, rate_id: i64, quote_expires_at: DateTime<Utc>) -> Self {
+        self.rate_id = Some(rate_id);
+        self.quote_expires_at = Some(quote_expires_at);
+        self
+    }
+
     /// Tries to extract the address from the memo
     pub fn try_extract_address(&mut self) -> Result<(), MemoSignatureError> {
         let sig = extract_and_verify_memo_signature(self)?;
@@ -316,7 +376,8 @@ impl NewOrder {
             self.memo == order.memo &&
             self.total_price == order.total_price &&
             self.currency == order.currency &&
-            self.created_at == order.created_at
+            self.created_at == order.created_at &&
+            self.merchant_id == order.merchant_id
     }
 }
 
@@ -354,6 +415,16 @@ pub struct Payment {
     pub payment_type: PaymentType,
     pub status: TransferStatus,
     pub order_id: Option<OrderId>,
+    /// The height of the block that the transaction was mined in, if it is known
+    #[serde(default)]
+    pub block_height: Option<i64>,
+    /// How many blocks deep the transaction was, the last time it was checked. The count stops being updated once the
+    /// payment is confirmed.
+    #[serde(default)]
+    pub confirmations: i64,
+    /// The merchant whose wallet received the payment. The funds can only be spent on that merchant's orders.
+    #[serde(default = "default_merchant")]
+    pub merchant_id: String,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
@@ -408,36 +479,54 @@ pub struct NewPayment {
     pub memo: Option<String>,
     /// The order number associated with this payment. Generally extracted from the memo.
     pub order_id: Option<OrderId>,
+    /// The merchant whose wallet received the payment
+    #[serde(default = "default_merchant")]
+    pub merchant_id: String,
 }
 
 impl NewPayment {
     pub fn new(sender: TariAddress, amount: MicroTari, txid: String) -> Self {
-        Self { sender: sender.into(), amount, txid, memo: None, order_id: None }
+        Self { sender: sender.into(), amount, txid, memo: None, order_id: None, merchant_id: default_merchant() }
     }
 
     pub fn with_memo<S: Into<String>>(&mut self, memo: S) {
         self.memo = Some(memo.into());
     }
+
+    /// Assigns the payment to the given merchant
+    pub fn with_merchant<S: Into<String>>(mut self, merchant_id: S) -> Self {
+        self.merchant_id = merchant_id.into();
+        self
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CreditNote {
     pub customer_id: String,
     /// The amount to credit the user
     pub amount: MicroTari,
     /// The reason for the credit note
     pub reason: Option<String>,
+    /// The merchant whose orders the credit can be spent on
+    #[serde(default = "default_merchant")]
+    pub merchant_id: String,
 }
 
 impl CreditNote {
     pub fn new(customer_id: String, amount: MicroTari) -> Self {
-        Self { customer_id, amount, reason: None }
+        Self { customer_id, amount, reason: None, merchant_id: default_merchant() }
     }
 
     pub fn with_reason<S: Into<String>>(mut self, reason: S) -> Self {
         self.reason = Some(reason.into());
         self
     }
+
+    /// Issues the credit note on behalf of the given merchant
+    pub fn with_merchant<S: Into<String>>(mut self, merchant_id: S) -> Self {
+        self.merchant_id = merchant_id.into();
+        self
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -445,11 +534,17 @@ pub struct AddressBalance {
     address: SerializedTariAddress,
     /// the sum of all Tari wallet transfers that have been confirmed
     total_confirmed: MicroTari,
-    /// the total value of all orders that have been fulfilled
+    /// the total value of all orders that have been fulfilled, less any refunds against those orders
     total_paid: MicroTari,
-    /// the current balance of the address (total_confirmed - total_paid)
+    /// the total value of all approved refunds to this address
+    total_refunded: MicroTari,
+    /// the current balance of the address (total_confirmed - total_paid - total_refunded)
     current_balance: MicroTari,
     last_update: DateTime<Utc>,
+    /// The merchant that the funds can be spent with, or `None` if this is the address's total across all merchants
+    #[sqlx(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    merchant_id: Option<String>,
 }
 
 impl AddressBalance {
@@ -458,11 +553,43 @@ impl AddressBalance {
             address: SerializedTariAddress::from(address),
             total_confirmed: MicroTari::from_tari(0),
             total_paid: MicroTari::from_tari(0),
+            total_refunded: MicroTari::from_tari(0),
             current_balance: MicroTari::from_tari(0),
             last_update: Utc::now(),
+            merchant_id: None,
         }
     }
 
+    /// An empty balance of `address` with the given merchant
+    pub fn for_merchant(address: TariAddress, merchant_id: &str) -> Self {
+        Self { merchant_id: Some(merchant_id.to_string()), ..Self::new(address) }
+    }
+
+    /// Builds a balance from its totals, for backends that compute the `address_balance` view themselves.
+    pub(crate) fn from_totals(
+        address: TariAddress,
+        total_confirmed: MicroTari,
+        total_paid: MicroTari,
+        total_refunded: MicroTari,
+        last_update: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            address: SerializedTariAddress::from(address),
+            total_confirmed,
+            total_paid,
+            total_refunded,
+            current_balance: total_confirmed - total_paid - total_refunded,
+            last_update,
+            merchant_id: None,
+        }
+    }
+
+    /// Scopes a computed balance to the given merchant.
+    pub(crate) fn with_merchant(mut self, merchant_id: &str) -> Self {
+        self.merchant_id = Some(merchant_id.to_string());
+        self
+    }
+
     pub fn address(&self) -> &TariAddress {
         self.address.as_address()
     }
@@ -475,6 +602,10 @@ impl AddressBalance {
         self.total_paid
     }
 
+    pub fn total_refunded(&self) -> MicroTari {
+        self.total_refunded
+    }
+
     pub fn current_balance(&self) -> MicroTari {
         self.current_balance
     }
@@ -482,12 +613,17 @@ impl AddressBalance {
     pub fn last_update(&self) -> DateTime<Utc> {
         self.last_update
     }
+
+    pub fn merchant_id(&self) -> Option<&str> {
+        self.merchant_id.as_deref()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CustomerBalance {
     total_confirmed: MicroTari,
     total_paid: MicroTari,
+    total_refunded: MicroTari,
     current_balance: MicroTari,
     addresses: Vec<AddressBalance>,
 }
@@ -496,8 +632,9 @@ impl CustomerBalance {
     pub fn new(balances: Vec<AddressBalance>) -> Self {
         let total_confirmed = balances.iter().map(|b| b.total_confirmed).sum();
         let total_paid = balances.iter().map(|b| b.total_paid).sum();
+        let total_refunded = balances.iter().map(|b| b.total_refunded).sum();
         let current_balance = balances.iter().map(|b| b.current_balance).sum();
-        Self { total_confirmed, total_paid, current_balance, addresses: balances }
+        Self { total_confirmed, total_paid, total_refunded, current_balance, addresses: balances }
     }
 
     pub fn total_confirmed(&self) -> MicroTari {
@@ -508,6 +645,10 @@ impl CustomerBalance {
         self.total_paid
     }
 
+    pub fn total_refunded(&self) -> MicroTari {
+        self.total_refunded
+    }
+
     pub fn current_balance(&self) -> MicroTari {
         self.current_balance
     }
@@ -523,6 +664,8 @@ pub struct NewSettlementJournalEntry {
     pub payment_address: SerializedTariAddress,
     pub settlement_type: SettlementType,
     pub amount: MicroTari,
+    /// The merchant of the order, whose funds paid for it
+    pub merchant_id: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -533,6 +676,8 @@ pub struct SettlementJournalEntry {
     pub payment_address: SerializedTariAddress,
     pub settlement_type: SettlementType,
     pub amount: MicroTari,
+    #[serde(default = "default_merchant")]
+    pub merchant_id: String,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
@@ -601,8 +746,13 @@ impl FromStr for TransferStatus {
 
 pub type Roles = Vec<Role>;
 
+/// A role is a named group of [`Permission`]s that can be assigned to an address.
+///
+/// The built-in roles always exist. Other roles are defined in the database, and can be created, edited and deleted
+/// at runtime (see [`crate::traits::AuthManagement::save_role`]). Role names are lowercase, and may contain digits and
+/// underscores.
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
+#[serde(try_from = "String", into = "String")]
 pub enum Role {
     ReadAll,
     Write,
@@ -611,6 +761,62 @@ pub enum Role {
     PaymentWallet,
     // Give access to very sensitive operations, such as adding new payment wallets.
     SuperAdmin,
+    /// A role that was defined in the database
+    Custom(String),
+}
+
+impl Role {
+    pub fn is_built_in(&self) -> bool {
+        !matches!(self, Role::Custom(_))
+    }
+
+    /// The permissions that the built-in roles are created with. The `super_admin` role always holds every permission.
+    pub fn default_permissions(&self) -> Vec<Permission> {
+        use Permission::*;
+        match self {
+            Role::ReadAll => vec![
+                AccountsRead,
+                OrdersRead,
+                PaymentsRead,
+                RefundsRead,
+                RatesRead,
+                EventsRead,
+                WebhooksRead,
+                LedgerRead,
+                ReportsExport,
+                SessionsRead,
+                WalletsRead,
+                RolesRead,
+                AuditRead,
+                ApprovalsRead,
+            ],
+            Role::Write => vec![
+                AccountsSettle,
+                OrdersFulfil,
+                OrdersCancel,
+                OrdersEdit,
+                OrdersPrice,
+                OrdersReassign,
+                OrdersReset,
+                OrdersProcess,
+                PaymentsReconcile,
+                CreditIssue,
+                RefundsRequest,
+                RefundsApprove,
+                RefundsSend,
+                RatesUpdate,
+                EventsReplay,
+                ApprovalsDecide,
+            ],
+            Role::User => vec![OrdersReadOwn],
+            Role::SuperAdmin => Permission::ALL.to_vec(),
+            Role::PaymentWallet | Role::Custom(_) => vec![],
+        }
+    }
+
+    pub fn built_in() -> [Role; 5] {
+        [Role::User, Role::ReadAll, Role::Write, Role::PaymentWallet, Role::SuperAdmin]
+    }
 }
 
 impl FromStr for Role {
@@ -623,11 +829,32 @@ impl FromStr for Role {
             "user" => Ok(Self::User),
             "payment_wallet" => Ok(Self::PaymentWallet),
             "super_admin" => Ok(Self::SuperAdmin),
+            s if is_valid_role_name(s) => Ok(Self::Custom(s.to_string())),
             s => Err(ConversionError(format!("Invalid role: {s}"))),
         }
     }
 }
 
+fn is_valid_role_name(name: &str) -> bool {
+    name.len() <= 64 &&
+        name.starts_with(|c: char| c.is_ascii_lowercase()) &&
+        name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+impl TryFrom<String> for Role {
+    type Error = ConversionError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Role> for String {
+    fn from(role: Role) -> Self {
+        role.to_string()
+    }
+}
+
 pub fn admin() -> Roles {
     vec![Role::ReadAll, Role::Write, Role::User]
 }
@@ -640,16 +867,280 @@ impl Display for Role {
             Role::User => write!(f, "user"),
             Role::PaymentWallet => write!(f, "payment_wallet"),
             Role::SuperAdmin => write!(f, "super_admin"),
+            Role::Custom(name) => write!(f, "{name}"),
         }
     }
 }
 
+/// A single operation that routes can require. Permissions are granted to addresses through their [`Role`]s.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Permission {
+    /// View any account's balance and history, and the list of customer ids and addresses
+    AccountsRead,
+    /// Settle the balance of an account against its orders
+    AccountsSettle,
+    /// View any order
+    OrdersRead,
+    /// View the orders that belong to your own address
+    OrdersReadOwn,
+    OrdersFulfil,
+    OrdersCancel,
+    /// Edit order memos
+    OrdersEdit,
+    OrdersPrice,
+    OrdersReassign,
+    OrdersReset,
+    /// Rescan open orders and release held orders
+    OrdersProcess,
+    PaymentsRead,
+    PaymentsReconcile,
+    CreditIssue,
+    RefundsRead,
+    RefundsRequest,
+    /// Approve or reject refund requests
+    RefundsApprove,
+    /// Mark approved refunds as sent
+    RefundsSend,
+    RatesRead,
+    RatesUpdate,
+    /// View the event outbox and the live event stream for every address
+    EventsRead,
+    /// Replay events and webhook deliveries
+    EventsReplay,
+    WebhooksRead,
+    WebhooksManage,
+    LedgerRead,
+    ReportsExport,
+    SessionsRead,
+    /// Revoke other addresses' sessions
+    SessionsManage,
+    WalletsRead,
+    WalletsManage,
+    RolesRead,
+    /// Assign roles to addresses, and edit the permissions that roles hold
+    RolesManage,
+    /// View the admin audit log
+    AuditRead,
+    /// View the operations that are waiting for a second admin's approval
+    ApprovalsRead,
+    /// Approve or reject other admins' operations. Approvers also need the permission for the operation itself.
+    ApprovalsDecide,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 35] = [
+        Permission::AccountsRead,
+        Permission::AccountsSettle,
+        Permission::OrdersRead,
+        Permission::OrdersReadOwn,
+        Permission::OrdersFulfil,
+        Permission::OrdersCancel,
+        Permission::OrdersEdit,
+        Permission::OrdersPrice,
+        Permission::OrdersReassign,
+        Permission::OrdersReset,
+        Permission::OrdersProcess,
+        Permission::PaymentsRead,
+        Permission::PaymentsReconcile,
+        Permission::CreditIssue,
+        Permission::RefundsRead,
+        Permission::RefundsRequest,
+        Permission::RefundsApprove,
+        Permission::RefundsSend,
+        Permission::RatesRead,
+        Permission::RatesUpdate,
+        Permission::EventsRead,
+        Permission::EventsReplay,
+        Permission::WebhooksRead,
+        Permission::WebhooksManage,
+        Permission::LedgerRead,
+        Permission::ReportsExport,
+        Permission::SessionsRead,
+        Permission::SessionsManage,
+        Permission::WalletsRead,
+        Permission::WalletsManage,
+        Permission::RolesRead,
+        Permission::RolesManage,
+        Permission::AuditRead,
+        Permission::ApprovalsRead,
+        Permission::ApprovalsDecide,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Permission::AccountsRead => "accounts.read",
+            Permission::AccountsSettle => "accounts.settle",
+            Permission::OrdersRead => "orders.read",
+            Permission::OrdersReadOwn => "orders.read_own",
+            Permission::OrdersFulfil => "orders.fulfil",
+            Permission::OrdersCancel => "orders.cancel",
+            Permission::OrdersEdit => "orders.edit",
+            Permission::OrdersPrice => "orders.price",
+            Permission::OrdersReassign => "orders.reassign",
+            Permission::OrdersReset => "orders.reset",
+            Permission::OrdersProcess => "orders.process",
+            Permission::PaymentsRead => "payments.read",
+            Permission::PaymentsReconcile => "payments.reconcile",
+            Permission::CreditIssue => "credit.issue",
+            Permission::RefundsRead => "refunds.read",
+            Permission::RefundsRequest => "refunds.request",
+            Permission::RefundsApprove => "refunds.approve",
+            Permission::RefundsSend => "refunds.send",
+            Permission::RatesRead => "rates.read",
+            Permission::RatesUpdate => "rates.update",
+            Permission::EventsRead => "events.read",
+            Permission::EventsReplay => "events.replay",
+            Permission::WebhooksRead => "webhooks.read",
+            Permission::WebhooksManage => "webhooks.manage",
+            Permission::LedgerRead => "ledger.read",
+            Permission::ReportsExport => "reports.export",
+            Permission::SessionsRead => "sessions.read",
+            Permission::SessionsManage => "sessions.manage",
+            Permission::WalletsRead => "wallets.read",
+            Permission::WalletsManage => "wallets.manage",
+            Permission::RolesRead => "roles.read",
+            Permission::RolesManage => "roles.manage",
+            Permission::AuditRead => "audit.read",
+            Permission::ApprovalsRead => "approvals.read",
+            Permission::ApprovalsDecide => "approvals.decide",
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = ConversionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Permission::ALL
+            .into_iter()
+            .find(|p| p.as_str() == s)
+            .ok_or_else(|| ConversionError(format!("Invalid permission: {s}")))
+    }
+}
+
+impl TryFrom<String> for Permission {
+    type Error = ConversionError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Permission> for String {
+    fn from(permission: Permission) -> Self {
+        permission.as_str().to_string()
+    }
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A role, and the permissions that it grants
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoleDefinition {
+    pub role: Role,
+    pub permissions: Vec<Permission>,
+}
+
+impl RoleDefinition {
+    pub fn new(role: Role, permissions: &[Permission]) -> Self {
+        let mut permissions = permissions.to_vec();
+        permissions.sort();
+        permissions.dedup();
+        Self { role, permissions }
+    }
+
+    /// The built-in roles with their default permissions, ordered by name
+    pub fn built_in() -> Vec<Self> {
+        let mut roles = Role::built_in()
+            .into_iter()
+            .map(|r| {
+                let permissions = r.default_permissions();
+                Self::new(r, &permissions)
+            })
+            .collect::<Vec<_>>();
+        roles.sort_by_key(|d| d.role.to_string());
+        roles
+    }
+}
+
 //--------------------------------------    Authentication  ---------------------------------------------------------
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LoginToken {
     pub address: TariAddress,
     pub nonce: u64,
     pub desired_roles: Roles,
+    /// If given, the roles are requested for this merchant only, and the access token is scoped to its orders
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merchant: Option<String>,
+}
+
+/// A login session. A session is opened every time a user logs in, and holds the refresh token that is traded for new
+/// access tokens. Every access token carries the id of its session, so revoking the session invalidates the access
+/// tokens that were issued for it as well as the refresh token.
+///
+/// Only a hash of the refresh token is stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthSession {
+    pub id: i64,
+    pub address: SerializedTariAddress,
+    /// The roles that were granted when the user logged in
+    pub roles: Roles,
+    /// The merchant the session is scoped to, if any
+    pub merchant: Option<String>,
+    #[serde(skip)]
+    pub refresh_token_hash: String,
+    pub created_at: DateTime<Utc>,
+    /// The last time an access token was issued for this session
+    pub refreshed_at: DateTime<Utc>,
+    /// The refresh token cannot be used after this time. Users have to log in again.
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+impl AuthSession {
+    /// True if the session has not been revoked and has not expired yet
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at > Utc::now()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NewAuthSession {
+    pub address: TariAddress,
+    pub roles: Roles,
+    pub merchant: Option<String>,
+    pub refresh_token_hash: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl NewAuthSession {
+    pub fn new(login_token: &LoginToken, refresh_token_hash: String, expires_at: DateTime<Utc>) -> Self {
+        Self {
+            address: login_token.address.clone(),
+            roles: login_token.desired_roles.clone(),
+            merchant: login_token.merchant.clone(),
+            refresh_token_hash,
+            expires_at,
+        }
+    }
+
+    /// The roles as they are stored in the database, i.e. a comma-separated list
+    pub fn roles_list(&self) -> String {
+        roles_to_list(&self.roles)
+    }
+}
+
+pub fn roles_to_list(roles: &[Role]) -> String {
+    roles.iter().map(Role::to_string).collect::<Vec<_>>().join(",")
+}
+
+pub fn roles_from_list(list: &str) -> Result<Roles, ConversionError> {
+    list.split(',').map(str::trim).filter(|s| !s.is_empty()).map(Role::from_str).collect()
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -683,3 +1174,845 @@ impl CustomerOrderBalance {
         Self { customer_id, total_current, total_paid, total_expired, total_cancelled }
     }
 }
+
+//--------------------------------------     Event outbox      -------------------------------------------------------
+/// An event that has been written to the outbox, along with its delivery state.
+///
+/// Events are written in the same transaction as the state change that caused them, and are delivered by the
+/// [`crate::events::OutboxDispatcher`]. An event with no `next_attempt_at` and no `delivered_at` has run out of
+/// retries and will only be delivered again if it is replayed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxEvent {
+    pub id: i64,
+    pub event: EventType,
+    pub created_at: DateTime<Utc>,
+    pub attempts: i64,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+impl OutboxEvent {
+    pub fn event_type(&self) -> &'static str {
+        self.event.name()
+    }
+
+    pub fn is_delivered(&self) -> bool {
+        self.delivered_at.is_some()
+    }
+}
+
+//--------------------------------------       Webhooks        -------------------------------------------------------
+/// A callback URL that is notified whenever an event of the given type is emitted.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct WebhookSubscription {
+    pub id: i64,
+    pub url: String,
+    /// The event type name, as returned by [`EventType::name`].
+    pub event_type: String,
+    /// The key used to sign deliveries. It is never sent back to API clients.
+    #[serde(skip_serializing, default)]
+    pub secret: String,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewWebhookSubscription {
+    pub url: String,
+    pub event_type: String,
+    pub secret: String,
+}
+
+/// One event, destined for one webhook subscription. Each delivery is retried independently of the others, and the
+/// table doubles as the delivery log.
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub subscription_id: i64,
+    /// The id of the outbox event that triggered this delivery
+    pub event_id: i64,
+    pub event_type: String,
+    /// The exact request body that is sent (and signed) on every attempt
+    pub payload: String,
+    pub created_at: DateTime<Utc>,
+    pub attempts: i64,
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    /// The HTTP status code of the last attempt, if the subscriber responded at all
+    pub last_status: Option<i64>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+impl WebhookDelivery {
+    pub fn is_delivered(&self) -> bool {
+        self.delivered_at.is_some()
+    }
+}
+
+/// The JSON body of a webhook delivery.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub event_id: i64,
+    pub event_type: String,
+    pub created_at: DateTime<Utc>,
+    pub event: EventType,
+}
+
+impl From<&OutboxEvent> for WebhookPayload {
+    fn from(event: &OutboxEvent) -> Self {
+        Self {
+            event_id: event.id,
+            event_type: event.event_type().to_string(),
+            created_at: event.created_at,
+            event: event.event.clone(),
+        }
+    }
+}
+
+//--------------------------------------        Refunds        -------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "snake_case")]
+pub enum RefundStatus {
+    /// The refund is waiting for an admin to approve it. It does not affect the address balance yet.
+    Requested,
+    /// The refund has been debited from the address balance, and is waiting for the payout wallet to send it.
+    Approved,
+    /// The Tari has been returned to the customer.
+    Sent,
+    /// The refund was turned down. It does not affect the address balance.
+    Rejected,
+}
+
+impl Display for RefundStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RefundStatus::Requested => write!(f, "Requested"),
+            RefundStatus::Approved => write!(f, "Approved"),
+            RefundStatus::Sent => write!(f, "Sent"),
+            RefundStatus::Rejected => write!(f, "Rejected"),
+        }
+    }
+}
+
+impl FromStr for RefundStatus {
+    type Err = ConversionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Requested" => Ok(Self::Requested),
+            "Approved" => Ok(Self::Approved),
+            "Sent" => Ok(Self::Sent),
+            "Rejected" => Ok(Self::Rejected),
+            s => Err(ConversionError(format!("Invalid refund status: {s}"))),
+        }
+    }
+}
+
+/// A request to return Tari to a customer.
+///
+/// If `order_id` is set, the refund returns (part of) the payment for that order, and `address` must be one of the
+/// addresses that paid for it. Otherwise, the refund comes out of the address's unspent balance with `merchant_id`,
+/// e.g. after an overpayment. Refunds for an order always belong to the order's merchant.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewRefund {
+    pub address: SerializedTariAddress,
+    pub order_id: Option<OrderId>,
+    pub amount: MicroTari,
+    pub reason: String,
+    #[serde(default = "default_merchant")]
+    pub merchant_id: String,
+}
+
+impl NewRefund {
+    pub fn new(address: TariAddress, amount: MicroTari, reason: String) -> Self {
+        Self { address: address.into(), order_id: None, amount, reason, merchant_id: default_merchant() }
+    }
+
+    /// Pays the refund out of the address's balance with the given merchant
+    pub fn with_merchant<S: Into<String>>(mut self, merchant_id: S) -> Self {
+        self.merchant_id = merchant_id.into();
+        self
+    }
+
+    pub fn for_order(mut self, order_id: OrderId) -> Self {
+        self.order_id = Some(order_id);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct Refund {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// The address the refund is paid out to
+    pub address: SerializedTariAddress,
+    pub order_id: Option<OrderId>,
+    pub amount: MicroTari,
+    pub reason: String,
+    pub status: RefundStatus,
+    /// The transaction id of the payout, once the refund has been sent
+    pub payout_txid: Option<String>,
+    /// The merchant whose funds the refund is paid out of
+    #[serde(default = "default_merchant")]
+    pub merchant_id: String,
+}
+
+impl Refund {
+    /// Whether the refund has been debited from the address balance
+    pub fn is_debited(&self) -> bool {
+        matches!(self.status, RefundStatus::Approved | RefundStatus::Sent)
+    }
+}
+
+//--------------------------------------      HeldOrder       -------------------------------------------------------
+/// A new order that was not priced, because the exchange rate circuit breaker tripped when it arrived.
+///
+/// Held orders are not in the orders table, so they cannot be claimed or paid. Once an admin releases a held order,
+/// it is priced at the exchange rate of the day and processed like any other new order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct HeldOrder {
+    pub id: i64,
+    pub order_id: OrderId,
+    pub alt_order_id: Option<OrderId>,
+    pub customer_id: String,
+    pub memo: Option<String>,
+    /// The price of the order, in `currency` units
+    pub original_price: String,
+    pub currency: String,
+    /// The time the order was created on the storefront
+    pub order_created_at: DateTime<Utc>,
+    /// Why the order was held
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+    /// When the order was released. Orders that are still being held have no release time.
+    pub released_at: Option<DateTime<Utc>>,
+    pub merchant_id: String,
+}
+
+impl HeldOrder {
+    pub fn is_released(&self) -> bool {
+        self.released_at.is_some()
+    }
+}
+
+impl From<HeldOrder> for NewOrder {
+    /// Converts the held order back into a new order. The order has not been priced yet, so the total price is zero.
+    fn from(held: HeldOrder) -> Self {
+        Self {
+            order_id: held.order_id,
+            alt_order_id: held.alt_order_id,
+            customer_id: held.customer_id,
+            memo: held.memo,
+            address: None,
+            total_price: MicroTari::from(0),
+            original_price: Some(held.original_price),
+            currency: held.currency,
+            created_at: held.order_created_at,
+            rate_id: None,
+            quote_expires_at: None,
+            merchant_id: held.merchant_id,
+        }
+    }
+}
+
+//--------------------------------------        Ledger         -------------------------------------------------------
+/// The accounts in the double-entry ledger.
+///
+/// Accounts that are kept per address or per merchant are told apart by [`LedgerAccount::account_id`]. The others
+/// have a single account, with an empty id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Type)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerAccountType {
+    /// The Tari held in the hot wallet. Debited when payments arrive, credited when refunds are paid out.
+    HotWallet,
+    /// Payments that have been received, but not confirmed yet. One account per sender address.
+    PendingPayments,
+    /// Confirmed funds that have not been spent on orders yet. One account per address.
+    CustomerWallet,
+    /// Settled orders, less refunds against orders. One account per merchant.
+    MerchantRevenue,
+    /// Approved refunds that have not been paid out yet.
+    Refunds,
+    /// Credit notes issued to customers.
+    Credits,
+    /// Network fees. The payout wallet does not report its fees yet, so nothing is posted here automatically.
+    Fees,
+}
+
+impl Display for LedgerAccountType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LedgerAccountType::HotWallet => write!(f, "HotWallet"),
+            LedgerAccountType::PendingPayments => write!(f, "PendingPayments"),
+            LedgerAccountType::CustomerWallet => write!(f, "CustomerWallet"),
+            LedgerAccountType::MerchantRevenue => write!(f, "MerchantRevenue"),
+            LedgerAccountType::Refunds => write!(f, "Refunds"),
+            LedgerAccountType::Credits => write!(f, "Credits"),
+            LedgerAccountType::Fees => write!(f, "Fees"),
+        }
+    }
+}
+
+impl FromStr for LedgerAccountType {
+    type Err = ConversionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "HotWallet" => Ok(Self::HotWallet),
+            "PendingPayments" => Ok(Self::PendingPayments),
+            "CustomerWallet" => Ok(Self::CustomerWallet),
+            "MerchantRevenue" => Ok(Self::MerchantRevenue),
+            "Refunds" => Ok(Self::Refunds),
+            "Credits" => Ok(Self::Credits),
+            "Fees" => Ok(Self::Fees),
+            s => Err(ConversionError(format!("Invalid ledger account type: {s}"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "snake_case")]
+pub enum EntrySide {
+    Debit,
+    Credit,
+}
+
+impl Display for EntrySide {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EntrySide::Debit => write!(f, "Debit"),
+            EntrySide::Credit => write!(f, "Credit"),
+        }
+    }
+}
+
+/// The business event that a ledger transaction records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerTransactionType {
+    /// The balances carried over from before the ledger was introduced
+    OpeningBalances,
+    PaymentReceived,
+    PaymentConfirmed,
+    PaymentCancelled,
+    /// A confirmed payment whose transaction was lost in a chain reorg
+    PaymentReverted,
+    CreditNote,
+    Settlement,
+    RefundApproved,
+    RefundSent,
+}
+
+impl Display for LedgerTransactionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LedgerTransactionType::OpeningBalances => write!(f, "OpeningBalances"),
+            LedgerTransactionType::PaymentReceived => write!(f, "PaymentReceived"),
+            LedgerTransactionType::PaymentConfirmed => write!(f, "PaymentConfirmed"),
+            LedgerTransactionType::PaymentCancelled => write!(f, "PaymentCancelled"),
+            LedgerTransactionType::PaymentReverted => write!(f, "PaymentReverted"),
+            LedgerTransactionType::CreditNote => write!(f, "CreditNote"),
+            LedgerTransactionType::Settlement => write!(f, "Settlement"),
+            LedgerTransactionType::RefundApproved => write!(f, "RefundApproved"),
+            LedgerTransactionType::RefundSent => write!(f, "RefundSent"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct LedgerAccount {
+    pub account_type: LedgerAccountType,
+    /// The address or merchant id that the account belongs to, or empty for single accounts
+    #[serde(default)]
+    pub account_id: String,
+}
+
+impl LedgerAccount {
+    pub fn new<S: Into<String>>(account_type: LedgerAccountType, account_id: S) -> Self {
+        Self { account_type, account_id: account_id.into() }
+    }
+
+    pub fn hot_wallet() -> Self {
+        Self::new(LedgerAccountType::HotWallet, "")
+    }
+
+    pub fn pending_payments(address: &TariAddress) -> Self {
+        Self::new(LedgerAccountType::PendingPayments, address.to_base58())
+    }
+
+    pub fn customer_wallet(address: &TariAddress) -> Self {
+        Self::new(LedgerAccountType::CustomerWallet, address.to_base58())
+    }
+
+    pub fn merchant_revenue(merchant_id: &str) -> Self {
+        Self::new(LedgerAccountType::MerchantRevenue, merchant_id)
+    }
+
+    pub fn refunds() -> Self {
+        Self::new(LedgerAccountType::Refunds, "")
+    }
+
+    pub fn credits() -> Self {
+        Self::new(LedgerAccountType::Credits, "")
+    }
+}
+
+impl Display for LedgerAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.account_id.is_empty() {
+            write!(f, "{}", self.account_type)
+        } else {
+            write!(f, "{}:{}", self.account_type, self.account_id)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewLedgerEntry {
+    pub account: LedgerAccount,
+    pub side: EntrySide,
+    pub amount: MicroTari,
+}
+
+/// A set of ledger entries that are posted together. The debits and credits must balance.
+///
+/// The constructors encode the posting rules for each business event. They mirror the `address_balance` view, so that
+/// an address's `CustomerWallet` balance is its current balance, and its `PendingPayments` balance is its pending
+/// balance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewLedgerTransaction {
+    pub transaction_type: LedgerTransactionType,
+    /// The payment txid, order id or refund id that the transaction belongs to
+    pub reference: String,
+    pub entries: Vec<NewLedgerEntry>,
+}
+
+impl NewLedgerTransaction {
+    /// Debits `debit` and credits `credit` with `amount`. A negative amount is posted the other way around.
+    pub fn transfer(
+        transaction_type: LedgerTransactionType,
+        reference: String,
+        debit: LedgerAccount,
+        credit: LedgerAccount,
+        amount: MicroTari,
+    ) -> Self {
+        let (debit, credit, amount) =
+            if amount < MicroTari::from(0) { (credit, debit, -amount) } else { (debit, credit, amount) };
+        let entries = vec![NewLedgerEntry { account: debit, side: EntrySide::Debit, amount }, NewLedgerEntry {
+            account: credit,
+            side: EntrySide::Credit,
+            amount,
+        }];
+        Self { transaction_type, reference, entries }
+    }
+
+    /// The Tari arrives in the hot wallet, and is held for the sender until it is confirmed.
+    pub fn payment_received(payment: &Payment) -> Self {
+        let sender = payment.sender.as_address();
+        Self::transfer(
+            LedgerTransactionType::PaymentReceived,
+            payment.txid.clone(),
+            LedgerAccount::hot_wallet(),
+            LedgerAccount::pending_payments(sender),
+            payment.amount,
+        )
+    }
+
+    /// The sender can spend the payment once it is confirmed.
+    pub fn payment_confirmed(payment: &Payment) -> Self {
+        let sender = payment.sender.as_address();
+        Self::transfer(
+            LedgerTransactionType::PaymentConfirmed,
+            payment.txid.clone(),
+            LedgerAccount::pending_payments(sender),
+            LedgerAccount::customer_wallet(sender),
+            payment.amount,
+        )
+    }
+
+    /// An unconfirmed payment that the wallet cancelled never reaches the hot wallet.
+    pub fn payment_cancelled(payment: &Payment) -> Self {
+        let sender = payment.sender.as_address();
+        Self::transfer(
+            LedgerTransactionType::PaymentCancelled,
+            payment.txid.clone(),
+            LedgerAccount::pending_payments(sender),
+            LedgerAccount::hot_wallet(),
+            payment.amount,
+        )
+    }
+
+    /// A confirmed payment goes back to pending when its transaction is lost in a reorg.
+    pub fn payment_reverted(payment: &Payment) -> Self {
+        let sender = payment.sender.as_address();
+        Self::transfer(
+            LedgerTransactionType::PaymentReverted,
+            payment.txid.clone(),
+            LedgerAccount::customer_wallet(sender),
+            LedgerAccount::pending_payments(sender),
+            payment.amount,
+        )
+    }
+
+    /// A credit note is spendable straight away. It is funded by the merchant, not the hot wallet.
+    pub fn credit_note(payment: &Payment) -> Self {
+        Self::transfer(
+            LedgerTransactionType::CreditNote,
+            payment.txid.clone(),
+            LedgerAccount::credits(),
+            LedgerAccount::customer_wallet(payment.sender.as_address()),
+            payment.amount,
+        )
+    }
+
+    /// The address's funds pay for the order. Reversing (negative) journal entries move the funds back.
+    pub fn settlement(entry: &SettlementJournalEntry, merchant_id: &str) -> Self {
+        Self::transfer(
+            LedgerTransactionType::Settlement,
+            entry.order_id.to_string(),
+            LedgerAccount::customer_wallet(entry.payment_address.as_address()),
+            LedgerAccount::merchant_revenue(merchant_id),
+            entry.amount,
+        )
+    }
+
+    /// An approved refund is owed to the customer until it is sent. A refund against an order comes out of the
+    /// revenue of the order's merchant. Otherwise it comes out of the address's unspent funds.
+    pub fn refund_approved(refund: &Refund, merchant_id: Option<&str>) -> Self {
+        let source = match merchant_id {
+            Some(merchant_id) => LedgerAccount::merchant_revenue(merchant_id),
+            None => LedgerAccount::customer_wallet(refund.address.as_address()),
+        };
+        Self::transfer(
+            LedgerTransactionType::RefundApproved,
+            refund.id.to_string(),
+            source,
+            LedgerAccount::refunds(),
+            refund.amount,
+        )
+    }
+
+    /// The refund leaves the hot wallet.
+    pub fn refund_sent(refund: &Refund) -> Self {
+        Self::transfer(
+            LedgerTransactionType::RefundSent,
+            refund.id.to_string(),
+            LedgerAccount::refunds(),
+            LedgerAccount::hot_wallet(),
+            refund.amount,
+        )
+    }
+
+    pub fn total(&self, side: EntrySide) -> MicroTari {
+        self.entries.iter().filter(|e| e.side == side).map(|e| e.amount).sum()
+    }
+
+    /// True if the debits equal the credits, and no entry is negative.
+    pub fn is_balanced(&self) -> bool {
+        let zero = MicroTari::from(0);
+        self.entries.iter().all(|e| e.amount >= zero) && self.total(EntrySide::Debit) == self.total(EntrySide::Credit)
+    }
+}
+
+/// A posted ledger entry, along with the transaction it belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct LedgerEntry {
+    pub id: i64,
+    pub transaction_id: i64,
+    pub created_at: DateTime<Utc>,
+    pub transaction_type: LedgerTransactionType,
+    pub reference: String,
+    pub account_type: LedgerAccountType,
+    pub account_id: String,
+    pub side: EntrySide,
+    pub amount: MicroTari,
+}
+
+impl LedgerEntry {
+    pub fn account(&self) -> LedgerAccount {
+        LedgerAccount::new(self.account_type, self.account_id.clone())
+    }
+}
+
+/// The total debits and credits posted to an account.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct LedgerBalance {
+    pub account_type: LedgerAccountType,
+    pub account_id: String,
+    pub debits: MicroTari,
+    pub credits: MicroTari,
+}
+
+impl LedgerBalance {
+    pub fn account(&self) -> LedgerAccount {
+        LedgerAccount::new(self.account_type, self.account_id.clone())
+    }
+
+    /// Debits less credits. Asset and expense accounts (`HotWallet`, `Credits`, `Fees`) have positive balances, and
+    /// the rest have negative balances.
+    pub fn balance(&self) -> MicroTari {
+        self.debits - self.credits
+    }
+}
+
+//--------------------------------------    Admin audit log    -------------------------------------------------------
+/// How a privileged API call ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Succeeded,
+    /// The caller had the necessary permissions, but the call failed
+    Failed,
+    /// The caller was refused access to the endpoint
+    Denied,
+}
+
+impl Display for AuditOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuditOutcome::Succeeded => write!(f, "Succeeded"),
+            AuditOutcome::Failed => write!(f, "Failed"),
+            AuditOutcome::Denied => write!(f, "Denied"),
+        }
+    }
+}
+
+impl FromStr for AuditOutcome {
+    type Err = ConversionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Succeeded" => Ok(Self::Succeeded),
+            "Failed" => Ok(Self::Failed),
+            "Denied" => Ok(Self::Denied),
+            s => Err(ConversionError(format!("Invalid audit outcome: {s}"))),
+        }
+    }
+}
+
+/// A privileged API call, as it is recorded in the admin audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AdminAction {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    /// The address in the access token that made the call
+    pub actor: SerializedTariAddress,
+    /// The roles in the access token
+    pub roles: Roles,
+    /// The merchant the access token is scoped to, if any
+    pub merchant: Option<String>,
+    /// The name of the route, e.g. `update_price`
+    pub endpoint: String,
+    pub method: String,
+    pub path: String,
+    /// The path parameters, query parameters and request body of the call
+    pub parameters: serde_json::Value,
+    pub reason: Option<String>,
+    pub client_ip: Option<String>,
+    pub outcome: AuditOutcome,
+    /// The HTTP status code of the response
+    pub status_code: i64,
+    /// The error message, if the call was refused or failed
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewAdminAction {
+    pub actor: TariAddress,
+    pub roles: Roles,
+    pub merchant: Option<String>,
+    pub endpoint: String,
+    pub method: String,
+    pub path: String,
+    pub parameters: serde_json::Value,
+    pub reason: Option<String>,
+    pub client_ip: Option<String>,
+    pub outcome: AuditOutcome,
+    pub status_code: i64,
+    pub error: Option<String>,
+}
+
+impl NewAdminAction {
+    /// The roles as they are stored in the database, i.e. a comma-separated list
+    pub fn roles_list(&self) -> String {
+        roles_to_list(&self.roles)
+    }
+}
+
+/// Criteria for searching the admin audit log. Empty fields match every record.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdminActionFilter {
+    pub actor: Option<SerializedTariAddress>,
+    pub endpoint: Option<String>,
+    pub outcome: Option<AuditOutcome>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl AdminActionFilter {
+    /// Whether `action` meets all the criteria of the filter
+    pub fn matches(&self, action: &AdminAction) -> bool {
+        self.actor.as_ref().map_or(true, |a| a == &action.actor) &&
+            self.endpoint.as_ref().map_or(true, |e| e == &action.endpoint) &&
+            self.outcome.map_or(true, |o| o == action.outcome) &&
+            self.since.map_or(true, |t| action.created_at >= t) &&
+            self.until.map_or(true, |t| action.created_at <= t)
+    }
+}
+
+//--------------------------------------     Approvals       ---------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalStatus {
+    /// The operation is waiting for a second admin to approve it
+    Pending,
+    /// The operation was approved, and has been carried out
+    Approved,
+    /// The operation was turned down, and will not be carried out
+    Rejected,
+    /// Nobody approved the operation before the request expired
+    Expired,
+    /// The operation was approved, but could not be carried out, e.g. because the order had been paid in the meantime
+    Failed,
+}
+
+impl Display for ApprovalStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApprovalStatus::Pending => write!(f, "Pending"),
+            ApprovalStatus::Approved => write!(f, "Approved"),
+            ApprovalStatus::Rejected => write!(f, "Rejected"),
+            ApprovalStatus::Expired => write!(f, "Expired"),
+            ApprovalStatus::Failed => write!(f, "Failed"),
+        }
+    }
+}
+
+impl FromStr for ApprovalStatus {
+    type Err = ConversionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Pending" => Ok(Self::Pending),
+            "Approved" => Ok(Self::Approved),
+            "Rejected" => Ok(Self::Rejected),
+            "Expired" => Ok(Self::Expired),
+            "Failed" => Ok(Self::Failed),
+            s => Err(ConversionError(format!("Invalid approval status: {s}"))),
+        }
+    }
+}
+
+/// A high-risk admin operation that is held back until a second admin approves it. The operation is stored with
+/// everything that is needed to carry it out once it has been approved.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ApprovalOperation {
+    /// Issue a credit note (see [`crate::OrderFlowApi::issue_credit_note`])
+    IssueCredit { note: CreditNote },
+    /// Mark an order as paid (see [`crate::OrderFlowApi::mark_new_order_as_paid`])
+    FulfilOrder { order_id: OrderId, reason: String },
+    /// Change the price of an order (see [`crate::OrderFlowApi::update_price_for_order`]). The price at the time of
+    /// the request is kept for the approver's information.
+    UpdatePrice { order_id: OrderId, old_price: MicroTari, new_price: MicroTari, reason: String },
+}
+
+impl ApprovalOperation {
+    /// The permission needed to carry out the operation. Approvers must hold it too.
+    pub fn permission(&self) -> Permission {
+        match self {
+            ApprovalOperation::IssueCredit { .. } => Permission::CreditIssue,
+            ApprovalOperation::FulfilOrder { .. } => Permission::OrdersFulfil,
+            ApprovalOperation::UpdatePrice { .. } => Permission::OrdersPrice,
+        }
+    }
+
+    /// The order that the operation changes, if any
+    pub fn order_id(&self) -> Option<&OrderId> {
+        match self {
+            ApprovalOperation::IssueCredit { .. } => None,
+            ApprovalOperation::FulfilOrder { order_id, .. } | ApprovalOperation::UpdatePrice { order_id, .. } => {
+                Some(order_id)
+            },
+        }
+    }
+}
+
+impl Display for ApprovalOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApprovalOperation::IssueCredit { note } => {
+                write!(f, "Credit of {} for customer {}", note.amount, note.customer_id)
+            },
+            ApprovalOperation::FulfilOrder { order_id, .. } => write!(f, "Mark order {order_id} as paid"),
+            ApprovalOperation::UpdatePrice { order_id, old_price, new_price, .. } => {
+                write!(f, "Change the price of order {order_id} from {old_price} to {new_price}")
+            },
+        }
+    }
+}
+
+/// A request for a second admin to approve an [`ApprovalOperation`].
+///
+/// Requests start out `Pending`. Only a different address from the one that asked for the operation can approve or
+/// reject it, and only until the request expires.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApprovalRequest {
+    pub id: i64,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Pending requests expire at this time
+    pub expires_at: DateTime<Utc>,
+    /// The address that asked for the operation
+    pub requested_by: SerializedTariAddress,
+    pub operation: ApprovalOperation,
+    pub status: ApprovalStatus,
+    /// The address that approved or rejected the request
+    pub decided_by: Option<SerializedTariAddress>,
+    /// The reason the request was rejected, or the error if the operation failed after it was approved
+    pub decision_reason: Option<String>,
+}
+
+impl ApprovalRequest {
+    pub fn is_expired(&self) -> bool {
+        self.status == ApprovalStatus::Expired ||
+            (self.status == ApprovalStatus::Pending && self.expires_at <= Utc::now())
+    }
+
+    /// Checks that `approver` can approve or reject the request, i.e. that the request is still pending and has not
+    /// expired, and that `approver` is not the address that asked for the operation.
+    pub fn check_decision(&self, approver: &TariAddress) -> Result<(), PaymentGatewayError> {
+        let id = self.id;
+        if self.is_expired() {
+            return Err(PaymentGatewayError::InvalidApproval(format!("Approval request {id} has expired")));
+        }
+        if self.status != ApprovalStatus::Pending {
+            return Err(PaymentGatewayError::InvalidApproval(format!(
+                "Approval request {id} has status {} instead of 'Pending'",
+                self.status
+            )));
+        }
+        if self.requested_by.as_address() == approver {
+            return Err(PaymentGatewayError::InvalidApproval(format!(
+                "Approval request {id} must be decided by a different admin from the one that made it"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NewApprovalRequest {
+    pub requested_by: TariAddress,
+    pub operation: ApprovalOperation,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl NewApprovalRequest {
+    pub fn new(requested_by: TariAddress, operation: ApprovalOperation, expires_at: DateTime<Utc>) -> Self {
+        Self { requested_by, operation, expires_at }
+    }
+}
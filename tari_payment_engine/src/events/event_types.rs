@@ -4,7 +4,17 @@ use tari_common_types::tari_address::TariAddress;
 use tpg_common::MicroTari;
 
 use crate::{
-    db_types::{Order, OrderStatus, OrderStatusType, Payment, PublicKey, SerializedTariAddress},
+    db_types::{
+        ApprovalRequest,
+        HeldOrder,
+        Order,
+        OrderStatus,
+        OrderStatusType,
+        Payment,
+        PublicKey,
+        Refund,
+        SerializedTariAddress,
+    },
     order_objects::OrderChanged,
 };
 
@@ -87,6 +97,53 @@ impl From<Payment> for PaymentEvent {
     }
 }
 
+/// A confirmed payment was lost in a chain reorg, and is `Received` again. `orders_reverted` are the orders it paid
+/// for, which are `New` again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentRevertedEvent {
+    pub payment: Payment,
+    pub orders_reverted: Vec<Order>,
+}
+
+impl PaymentRevertedEvent {
+    pub fn new(payment: Payment, orders_reverted: Vec<Order>) -> Self {
+        Self { payment, orders_reverted }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefundEvent {
+    pub refund: Refund,
+}
+
+impl RefundEvent {
+    pub fn new(refund: Refund) -> Self {
+        Self { refund }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderHeldEvent {
+    pub held_order: HeldOrder,
+}
+
+impl OrderHeldEvent {
+    pub fn new(held_order: HeldOrder) -> Self {
+        Self { held_order }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApprovalEvent {
+    pub request: ApprovalRequest,
+}
+
+impl ApprovalEvent {
+    pub fn new(request: ApprovalRequest) -> Self {
+        Self { request }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[allow(clippy::large_enum_variant)]
 pub enum EventType {
@@ -97,4 +154,59 @@ pub enum EventType {
     OrderClaimed(OrderClaimedEvent),
     PaymentReceived(PaymentEvent),
     Confirmation(PaymentEvent),
+    /// A refund has been approved and debited from the customer's balance. The payout wallet should send it.
+    RefundApproved(RefundEvent),
+    /// A refund has been paid out to the customer.
+    RefundSent(RefundEvent),
+    /// A new order was held instead of being priced, because the exchange rate circuit breaker tripped. An admin
+    /// needs to review and release it.
+    OrderHeld(OrderHeldEvent),
+    /// A confirmed payment was lost in a chain reorg. The orders it paid for are no longer paid.
+    PaymentReverted(PaymentRevertedEvent),
+    /// A high-risk operation is waiting for a second admin's approval.
+    ApprovalRequested(ApprovalEvent),
+    /// A second admin approved a high-risk operation, and it is being carried out.
+    OperationApproved(ApprovalEvent),
+    /// A second admin turned down a high-risk operation.
+    OperationRejected(ApprovalEvent),
+}
+
+/// The names of all the event types, as returned by [`EventType::name`].
+pub const EVENT_TYPE_NAMES: [&str; 14] = [
+    "NewOrder",
+    "OrderPaid",
+    "OrderAnnulled",
+    "OrderModified",
+    "OrderClaimed",
+    "PaymentReceived",
+    "Confirmation",
+    "RefundApproved",
+    "RefundSent",
+    "OrderHeld",
+    "PaymentReverted",
+    "ApprovalRequested",
+    "OperationApproved",
+    "OperationRejected",
+];
+
+impl EventType {
+    /// A short, stable name for the event type. This is what gets stored in the `event_type` column of the outbox.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventType::NewOrder(_) => "NewOrder",
+            EventType::OrderPaid(_) => "OrderPaid",
+            EventType::OrderAnnulled(_) => "OrderAnnulled",
+            EventType::OrderModified(_) => "OrderModified",
+            EventType::OrderClaimed(_) => "OrderClaimed",
+            EventType::PaymentReceived(_) => "PaymentReceived",
+            EventType::Confirmation(_) => "Confirmation",
+            EventType::RefundApproved(_) => "RefundApproved",
+            EventType::RefundSent(_) => "RefundSent",
+            EventType::OrderHeld(_) => "OrderHeld",
+            EventType::PaymentReverted(_) => "PaymentReverted",
+            EventType::ApprovalRequested(_) => "ApprovalRequested",
+            EventType::OperationApproved(_) => "OperationApproved",
+            EventType::OperationRejected(_) => "OperationRejected",
+        }
+    }
 }
@@ -1,14 +1,18 @@
 use std::{future::Future, pin::Pin, sync::Arc};
 
 use crate::events::{
+    ApprovalEvent,
     EventHandler,
     EventProducer,
     Handler,
     OrderAnnulledEvent,
     OrderClaimedEvent,
     OrderEvent,
+    OrderHeldEvent,
     OrderModifiedEvent,
     PaymentEvent,
+    PaymentRevertedEvent,
+    RefundEvent,
 };
 
 /// A container struct for holding event producers for the different event types.
@@ -25,6 +29,13 @@ pub struct EventProducers {
     pub order_claimed_producer: Vec<EventProducer<OrderClaimedEvent>>,
     pub payment_received_producer: Vec<EventProducer<PaymentEvent>>,
     pub payment_confirmed_producer: Vec<EventProducer<PaymentEvent>>,
+    pub refund_approved_producer: Vec<EventProducer<RefundEvent>>,
+    pub refund_sent_producer: Vec<EventProducer<RefundEvent>>,
+    pub order_held_producer: Vec<EventProducer<OrderHeldEvent>>,
+    pub payment_reverted_producer: Vec<EventProducer<PaymentRevertedEvent>>,
+    pub approval_requested_producer: Vec<EventProducer<ApprovalEvent>>,
+    pub operation_approved_producer: Vec<EventProducer<ApprovalEvent>>,
+    pub operation_rejected_producer: Vec<EventProducer<ApprovalEvent>>,
 }
 
 /// A container struct for holding event handlers for the different event types. These handlers are typically hooks
@@ -37,6 +48,13 @@ pub struct EventHandlers {
     pub on_order_claimed: Option<EventHandler<OrderClaimedEvent>>,
     pub on_payment_received: Option<EventHandler<PaymentEvent>>,
     pub on_payment_confirmed: Option<EventHandler<PaymentEvent>>,
+    pub on_refund_approved: Option<EventHandler<RefundEvent>>,
+    pub on_refund_sent: Option<EventHandler<RefundEvent>>,
+    pub on_order_held: Option<EventHandler<OrderHeldEvent>>,
+    pub on_payment_reverted: Option<EventHandler<PaymentRevertedEvent>>,
+    pub on_approval_requested: Option<EventHandler<ApprovalEvent>>,
+    pub on_operation_approved: Option<EventHandler<ApprovalEvent>>,
+    pub on_operation_rejected: Option<EventHandler<ApprovalEvent>>,
 }
 
 impl EventHandlers {
@@ -48,6 +66,13 @@ impl EventHandlers {
         let on_order_claimed = hooks.on_order_claimed.map(|f| EventHandler::new(buffer_size, f));
         let on_payment_received = hooks.on_payment_received.map(|f| EventHandler::new(buffer_size, f));
         let on_payment_confirmed = hooks.on_payment_confirmed.map(|f| EventHandler::new(buffer_size, f));
+        let on_refund_approved = hooks.on_refund_approved.map(|f| EventHandler::new(buffer_size, f));
+        let on_refund_sent = hooks.on_refund_sent.map(|f| EventHandler::new(buffer_size, f));
+        let on_order_held = hooks.on_order_held.map(|f| EventHandler::new(buffer_size, f));
+        let on_payment_reverted = hooks.on_payment_reverted.map(|f| EventHandler::new(buffer_size, f));
+        let on_approval_requested = hooks.on_approval_requested.map(|f| EventHandler::new(buffer_size, f));
+        let on_operation_approved = hooks.on_operation_approved.map(|f| EventHandler::new(buffer_size, f));
+        let on_operation_rejected = hooks.on_operation_rejected.map(|f| EventHandler::new(buffer_size, f));
         Self {
             on_order_paid,
             on_new_order,
@@ -56,6 +81,13 @@ impl EventHandlers {
             on_order_claimed,
             on_payment_received,
             on_payment_confirmed,
+            on_refund_approved,
+            on_refund_sent,
+            on_order_held,
+            on_payment_reverted,
+            on_approval_requested,
+            on_operation_approved,
+            on_operation_rejected,
         }
     }
 
@@ -83,6 +115,27 @@ impl EventHandlers {
         if let Some(handler) = &self.on_payment_confirmed {
             producers.payment_confirmed_producer.push(handler.subscribe());
         }
+        if let Some(handler) = &self.on_refund_approved {
+            producers.refund_approved_producer.push(handler.subscribe());
+        }
+        if let Some(handler) = &self.on_refund_sent {
+            producers.refund_sent_producer.push(handler.subscribe());
+        }
+        if let Some(handler) = &self.on_order_held {
+            producers.order_held_producer.push(handler.subscribe());
+        }
+        if let Some(handler) = &self.on_payment_reverted {
+            producers.payment_reverted_producer.push(handler.subscribe());
+        }
+        if let Some(handler) = &self.on_approval_requested {
+            producers.approval_requested_producer.push(handler.subscribe());
+        }
+        if let Some(handler) = &self.on_operation_approved {
+            producers.operation_approved_producer.push(handler.subscribe());
+        }
+        if let Some(handler) = &self.on_operation_rejected {
+            producers.operation_rejected_producer.push(handler.subscribe());
+        }
     }
 
     pub fn producers(&self) -> EventProducers {
@@ -127,6 +180,41 @@ impl EventHandlers {
                 handler.start_handler().await;
             });
         }
+        if let Some(handler) = self.on_refund_approved {
+            tokio::spawn(async move {
+                handler.start_handler().await;
+            });
+        }
+        if let Some(handler) = self.on_refund_sent {
+            tokio::spawn(async move {
+                handler.start_handler().await;
+            });
+        }
+        if let Some(handler) = self.on_order_held {
+            tokio::spawn(async move {
+                handler.start_handler().await;
+            });
+        }
+        if let Some(handler) = self.on_payment_reverted {
+            tokio::spawn(async move {
+                handler.start_handler().await;
+            });
+        }
+        if let Some(handler) = self.on_approval_requested {
+            tokio::spawn(async move {
+                handler.start_handler().await;
+            });
+        }
+        if let Some(handler) = self.on_operation_approved {
+            tokio::spawn(async move {
+                handler.start_handler().await;
+            });
+        }
+        if let Some(handler) = self.on_operation_rejected {
+            tokio::spawn(async move {
+                handler.start_handler().await;
+            });
+        }
     }
 }
 
@@ -146,6 +234,13 @@ pub struct EventHooks {
     pub on_order_claimed: Option<Handler<OrderClaimedEvent>>,
     pub on_payment_received: Option<Handler<PaymentEvent>>,
     pub on_payment_confirmed: Option<Handler<PaymentEvent>>,
+    pub on_refund_approved: Option<Handler<RefundEvent>>,
+    pub on_refund_sent: Option<Handler<RefundEvent>>,
+    pub on_order_held: Option<Handler<OrderHeldEvent>>,
+    pub on_payment_reverted: Option<Handler<PaymentRevertedEvent>>,
+    pub on_approval_requested: Option<Handler<ApprovalEvent>>,
+    pub on_operation_approved: Option<Handler<ApprovalEvent>>,
+    pub on_operation_rejected: Option<Handler<ApprovalEvent>>,
 }
 
 impl EventHooks {
@@ -190,4 +285,46 @@ impl EventHooks {
         self.on_payment_confirmed = Some(Arc::new(f));
         self
     }
+
+    pub fn on_refund_approved<F>(&mut self, f: F) -> &mut Self
+    where F: (Fn(RefundEvent) -> Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync + 'static {
+        self.on_refund_approved = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_refund_sent<F>(&mut self, f: F) -> &mut Self
+    where F: (Fn(RefundEvent) -> Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync + 'static {
+        self.on_refund_sent = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_order_held<F>(&mut self, f: F) -> &mut Self
+    where F: (Fn(OrderHeldEvent) -> Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync + 'static {
+        self.on_order_held = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_payment_reverted<F>(&mut self, f: F) -> &mut Self
+    where F: (Fn(PaymentRevertedEvent) -> Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync + 'static {
+        self.on_payment_reverted = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_approval_requested<F>(&mut self, f: F) -> &mut Self
+    where F: (Fn(ApprovalEvent) -> Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync + 'static {
+        self.on_approval_requested = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_operation_approved<F>(&mut self, f: F) -> &mut Self
+    where F: (Fn(ApprovalEvent) -> Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync + 'static {
+        self.on_operation_approved = Some(Arc::new(f));
+        self
+    }
+
+    pub fn on_operation_rejected<F>(&mut self, f: F) -> &mut Self
+    where F: (Fn(ApprovalEvent) -> Pin<Box<dyn Future<Output = ()> + Send>>) + Send + Sync + 'static {
+        self.on_operation_rejected = Some(Arc::new(f));
+        self
+    }
 }
@@ -1,7 +1,9 @@
 mod channel;
 mod event_types;
 mod hooks;
+mod outbox;
 
 pub use channel::{EventHandler, EventProducer, Handler};
 pub use event_types::*;
 pub use hooks::{EventHandlers, EventHooks, EventProducers};
+pub use outbox::{DispatchSummary, OutboxConfig, OutboxDispatcher, OutboxHandler};
@@ -0,0 +1,316 @@
+//! Durable event delivery
+//!
+//! The backends write every event to an outbox (see [`EventOutbox`]) in the same transaction as the state change that
+//! caused it, so events survive a crash or restart. The [`OutboxDispatcher`] polls the outbox, claims the due events
+//! and hands each one to its handlers. A claim is a lease, so several dispatchers (one per server instance) can share
+//! an outbox without delivering the same event at the same time, and an event claimed by a dispatcher that dies is
+//! picked up again once its lease runs out.
+//!
+//! Each handler's success is recorded separately. An event is marked as delivered once every handler has succeeded.
+//! If any handler fails, only the failed handlers are retried, with exponential backoff until `max_attempts` is
+//! reached, after which the event is parked until an admin replays it.
+//!
+//! Delivery is at-least-once. A handler can see the same event more than once (after a retry, a replay, or a crash
+//! between running the handler and recording the delivery), so handlers must be idempotent.
+use std::{future::Future, pin::Pin};
+
+use chrono::{DateTime, Duration, Utc};
+use log::*;
+
+use crate::{
+    db_types::OutboxEvent,
+    traits::{EventOutbox, OutboxError},
+};
+
+/// A fallible event handler. Returning an error schedules the event for another delivery attempt.
+///
+/// The handler receives the outbox record rather than the bare event, so that it can use the outbox id to
+/// de-duplicate deliveries. Handlers run on the dispatcher's task, so their futures need not be `Send`.
+pub type OutboxHandler = Box<dyn Fn(OutboxEvent) -> Pin<Box<dyn Future<Output = Result<(), String>>>>>;
+
+#[derive(Debug, Clone)]
+pub struct OutboxConfig {
+    /// How often the outbox is checked for due events
+    pub poll_interval: std::time::Duration,
+    /// The maximum number of events delivered in a single pass
+    pub batch_size: i64,
+    /// The number of failed delivery attempts after which an event is parked
+    pub max_attempts: i64,
+    /// The delay before the first retry. Each subsequent retry doubles the delay, up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// How long a claimed event is leased to the dispatcher. This must be longer than the handlers take to run,
+    /// otherwise another dispatcher could claim the event while it is still being delivered.
+    pub lease: Duration,
+}
+
+impl OutboxConfig {
+    /// The delay before the next delivery attempt, given the number of failed attempts so far.
+    pub fn backoff(&self, failed_attempts: i64) -> Duration {
+        let mut delay = self.initial_backoff;
+        for _ in 1..failed_attempts {
+            if delay >= self.max_backoff {
+                break;
+            }
+            delay = delay * 2;
+        }
+        delay.min(self.max_backoff)
+    }
+
+    /// When to make the next delivery attempt after `failed_attempts` failures, or `None` if the retries have been
+    /// used up.
+    pub fn next_attempt_at(&self, failed_attempts: i64) -> Option<DateTime<Utc>> {
+        (failed_attempts < self.max_attempts).then(|| Utc::now() + self.backoff(failed_attempts))
+    }
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval: std::time::Duration::from_secs(5),
+            batch_size: 50,
+            max_attempts: 10,
+            initial_backoff: Duration::seconds(10),
+            max_backoff: Duration::hours(1),
+            lease: Duration::minutes(5),
+        }
+    }
+}
+
+/// The outcome of a single pass over the outbox.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DispatchSummary {
+    pub delivered: usize,
+    /// Failed deliveries that have been scheduled for a retry
+    pub failed: usize,
+    /// Failed deliveries that have run out of retries
+    pub parked: usize,
+}
+
+pub struct OutboxDispatcher<B> {
+    db: B,
+    config: OutboxConfig,
+    /// Handlers and the names their deliveries are recorded under
+    handlers: Vec<(String, OutboxHandler)>,
+}
+
+impl<B: EventOutbox> OutboxDispatcher<B> {
+    pub fn new(db: B, config: OutboxConfig) -> Self {
+        Self { db, config, handlers: Vec::new() }
+    }
+
+    /// Adds a handler. The name identifies the handler's deliveries in the outbox, so it must be unique and should
+    /// not change between releases.
+    pub fn add_handler<F>(&mut self, name: &str, f: F) -> &mut Self
+    where F: (Fn(OutboxEvent) -> Pin<Box<dyn Future<Output = Result<(), String>>>>) + 'static {
+        self.handlers.push((name.to_string(), Box::new(f)));
+        self
+    }
+
+    /// Claims and delivers every event that is currently due, and records the outcome of each delivery.
+    pub async fn dispatch_due_events(&self) -> Result<DispatchSummary, OutboxError> {
+        let events = self.db.claim_due_events(self.config.batch_size, self.config.lease).await?;
+        let mut summary = DispatchSummary::default();
+        for event in events {
+            let id = event.id;
+            let errors = self.deliver(&event).await?;
+            if errors.is_empty() {
+                self.db.mark_event_delivered(id).await?;
+                trace!("📤️ Outbox event {id} ({}) delivered", event.event_type());
+                summary.delivered += 1;
+                continue;
+            }
+            let attempts = event.attempts + 1;
+            let error = errors.join("; ");
+            let retry_at = self.config.next_attempt_at(attempts);
+            self.db.record_failed_delivery(id, &error, retry_at).await?;
+            match retry_at {
+                Some(t) => {
+                    debug!("📤️ Outbox event {id} delivery attempt {attempts} failed. Retrying at {t}. {error}");
+                    summary.failed += 1;
+                },
+                None => {
+                    warn!(
+                        "📤️ Outbox event {id} ({}) could not be delivered after {attempts} attempts and has been \
+                         parked. It can be replayed from the admin API. Last error: {error}",
+                        event.event_type()
+                    );
+                    summary.parked += 1;
+                },
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Polls the outbox forever. This future never completes.
+    pub async fn run(self) {
+        info!("📤️ Starting outbox dispatcher with {} handler(s)", self.handlers.len());
+        let mut timer = tokio::time::interval(self.config.poll_interval);
+        loop {
+            timer.tick().await;
+            match self.dispatch_due_events().await {
+                Ok(summary) if summary != DispatchSummary::default() => {
+                    info!(
+                        "📤️ Outbox pass complete. {} delivered, {} scheduled for retry, {} parked",
+                        summary.delivered, summary.failed, summary.parked
+                    );
+                },
+                Ok(_) => {},
+                Err(e) => error!("📤️ Could not dispatch outbox events. {e}"),
+            }
+        }
+    }
+
+    /// Runs the handlers that have not delivered the event yet, and returns the errors of those that failed.
+    async fn deliver(&self, event: &OutboxEvent) -> Result<Vec<String>, OutboxError> {
+        let delivered = self.db.fetch_delivered_handlers(event.id).await?;
+        let mut errors = Vec::new();
+        for (name, handler) in self.handlers.iter().filter(|(name, _)| !delivered.contains(name)) {
+            match (handler)(event.clone()).await {
+                Ok(()) => self.db.mark_handler_delivered(event.id, name).await?,
+                Err(e) => errors.push(format!("{name}: {e}")),
+            }
+        }
+        Ok(errors)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use tpg_common::MicroTari;
+
+    use super::*;
+    use crate::{
+        db_types::{NewOrder, OrderId},
+        events::EventType,
+        tpe_api::account_objects::Pagination,
+        traits::PaymentGatewayDatabase,
+        InMemoryDatabase,
+    };
+
+    fn config() -> OutboxConfig {
+        OutboxConfig { max_attempts: 2, initial_backoff: Duration::zero(), ..OutboxConfig::default() }
+    }
+
+    async fn insert_order(db: &InMemoryDatabase) {
+        let order = NewOrder::new(OrderId::new("oid-1"), "alice".into(), MicroTari::from_tari(10));
+        db.insert_order(order).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn events_are_delivered_once() {
+        let db = InMemoryDatabase::new();
+        insert_order(&db).await;
+        let count = Arc::new(AtomicUsize::new(0));
+        let c2 = Arc::clone(&count);
+        let mut dispatcher = OutboxDispatcher::new(db.clone(), config());
+        dispatcher.add_handler("counter", move |ev| {
+            assert!(matches!(ev.event, EventType::NewOrder(_)));
+            c2.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Ok(()) })
+        });
+        let summary = dispatcher.dispatch_due_events().await.unwrap();
+        assert_eq!(summary, DispatchSummary { delivered: 1, failed: 0, parked: 0 });
+        let summary = dispatcher.dispatch_due_events().await.unwrap();
+        assert_eq!(summary, DispatchSummary::default());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        let event = db.fetch_outbox_event(1).await.unwrap().unwrap();
+        assert!(event.is_delivered());
+        assert_eq!(event.attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn failed_events_are_retried_then_parked_until_replayed() {
+        let db = InMemoryDatabase::new();
+        insert_order(&db).await;
+        let healthy = Arc::new(AtomicBool::new(false));
+        let h2 = Arc::clone(&healthy);
+        let mut dispatcher = OutboxDispatcher::new(db.clone(), config());
+        dispatcher.add_handler("storefront", move |_| {
+            let healthy = h2.load(Ordering::SeqCst);
+            Box::pin(async move {
+                if healthy {
+                    Ok(())
+                } else {
+                    Err("Storefront is down".to_string())
+                }
+            })
+        });
+        let summary = dispatcher.dispatch_due_events().await.unwrap();
+        assert_eq!(summary, DispatchSummary { delivered: 0, failed: 1, parked: 0 });
+        let summary = dispatcher.dispatch_due_events().await.unwrap();
+        assert_eq!(summary, DispatchSummary { delivered: 0, failed: 0, parked: 1 });
+        // Parked events are not retried
+        assert_eq!(dispatcher.dispatch_due_events().await.unwrap(), DispatchSummary::default());
+
+        let all = Pagination { offset: None, count: None };
+        let undelivered = db.fetch_undelivered_events(&all).await.unwrap();
+        assert_eq!(undelivered.len(), 1);
+        assert_eq!(undelivered[0].attempts, 2);
+        assert_eq!(undelivered[0].last_error.as_deref(), Some("storefront: Storefront is down"));
+        assert!(undelivered[0].next_attempt_at.is_none());
+
+        healthy.store(true, Ordering::SeqCst);
+        db.replay_event(undelivered[0].id).await.unwrap();
+        let summary = dispatcher.dispatch_due_events().await.unwrap();
+        assert_eq!(summary, DispatchSummary { delivered: 1, failed: 0, parked: 0 });
+        assert!(db.fetch_undelivered_events(&all).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn only_failed_handlers_are_retried() {
+        let db = InMemoryDatabase::new();
+        insert_order(&db).await;
+        let count = Arc::new(AtomicUsize::new(0));
+        let c2 = Arc::clone(&count);
+        let healthy = Arc::new(AtomicBool::new(false));
+        let h2 = Arc::clone(&healthy);
+        let mut dispatcher = OutboxDispatcher::new(db.clone(), config());
+        dispatcher.add_handler("counter", move |_| {
+            c2.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Ok(()) })
+        });
+        dispatcher.add_handler("storefront", move |_| {
+            let healthy = h2.load(Ordering::SeqCst);
+            Box::pin(async move {
+                if healthy {
+                    Ok(())
+                } else {
+                    Err("Storefront is down".to_string())
+                }
+            })
+        });
+        let summary = dispatcher.dispatch_due_events().await.unwrap();
+        assert_eq!(summary, DispatchSummary { delivered: 0, failed: 1, parked: 0 });
+        healthy.store(true, Ordering::SeqCst);
+        let summary = dispatcher.dispatch_due_events().await.unwrap();
+        assert_eq!(summary, DispatchSummary { delivered: 1, failed: 0, parked: 0 });
+        // The counter succeeded the first time round, so the retry did not run it again
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        // Replaying a delivered event runs every handler
+        db.replay_event(1).await.unwrap();
+        dispatcher.dispatch_due_events().await.unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit() {
+        let config = OutboxConfig {
+            initial_backoff: Duration::seconds(10),
+            max_backoff: Duration::seconds(60),
+            ..OutboxConfig::default()
+        };
+        assert_eq!(config.backoff(1), Duration::seconds(10));
+        assert_eq!(config.backoff(2), Duration::seconds(20));
+        assert_eq!(config.backoff(3), Duration::seconds(40));
+        assert_eq!(config.backoff(4), Duration::seconds(60));
+        assert_eq!(config.backoff(100), Duration::seconds(60));
+        assert!(config.next_attempt_at(config.max_attempts - 1).is_some());
+        assert!(config.next_attempt_at(config.max_attempts).is_none());
+    }
+}
@@ -10,6 +10,8 @@ use tari_crypto::{ristretto::RistrettoPublicKey, tari_utilities::ByteArray};
 use crate::db_types::OrderId;
 
 pub const DONATION_WALLET_ADDRESS: &str = "143UtnSymZykCAm95xAKKKe8nowL6zif8qb1h7yHxgtD9XZ";
+/// The hex prefix shared by all the addresses created by [`create_dummy_address_for_cust_id`]
+const DUMMY_ADDRESS_HEX_PREFIX: &str = "0002000000ba5e4d0000";
 
 /// Creates a dummy TariAddress for a given customer id. The address is created by hashing the customer id and
 /// then setting the first 8 bytes to a specific prefix and the last byte to 0. The resulting hash is then
@@ -38,6 +40,12 @@ pub fn create_dummy_address_for_cust_id(cust_id: &str) -> TariAddress {
     TariAddress::new_single_address(key, Network::MainNet, TariAddressFeatures::create_interactive_only())
 }
 
+/// Returns true if the address is a dummy address created by [`create_dummy_address_for_cust_id`]. Nobody holds the
+/// keys to these addresses, so nothing should ever be sent to them.
+pub fn is_dummy_address(address: &TariAddress) -> bool {
+    address.to_hex().starts_with(DUMMY_ADDRESS_HEX_PREFIX)
+}
+
 /// Returns the Tari wallet address that should be used to make payments.
 ///
 /// This value should be set in the environment variable `TPG_PAYMENT_WALLET_ADDRESS`.
@@ -104,6 +112,7 @@ mod test {
         let address = create_dummy_address_for_cust_id("orderid-X-67483:3321a/2024-05-01:18:08.004");
         assert_eq!(address.to_hex(), "0002000000ba5e4d00008eb731b31738fe74d7c5475687ca0dce26e03fbad621b80376");
         assert_eq!(address.to_base58(), "13111eLuVvxBfXApWbkBesNSD4zzbV5WCXXnbD87bF6aT7");
+        assert!(!is_dummy_address(&TariAddress::from_str(DONATION_WALLET_ADDRESS).unwrap()));
     }
 
     #[test]
@@ -112,6 +121,7 @@ mod test {
             let id: String = rand::thread_rng().sample_iter(&Alphanumeric).take(16).map(char::from).collect();
             let address = create_dummy_address_for_cust_id(&id);
             assert!(address.to_hex().starts_with("0002000000ba5e4d0000"));
+            assert!(is_dummy_address(&address));
         }
     }
 }
@@ -8,6 +8,7 @@ pub use gumbo::{
     create_dummy_address_for_cust_id,
     extract_order_id_from_str,
     get_payment_wallet_address,
+    is_dummy_address,
     is_forbidden_pattern,
 };
 pub use memo_signature::{extract_and_verify_memo_signature, MemoSignature, MemoSignatureError};
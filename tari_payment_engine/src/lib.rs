@@ -8,13 +8,15 @@
 //!    engine. It is responsible for managing orders, authentication, payments and accounts. Specific backends (e.g.
 //!    Postgres or SQLite) need to implement the traits in this module in order to act as a backend for the Tari Payment
 //!    Server.
-//! 2. Sqlite database implementation ([`mod@sqlite`]). You should never need to access the database directly. Instead,
-//!    use the public API provided by the payment engine.
+//! 2. Sqlite and Postgres database implementations ([`mod@sqlite`], `postgres`). You should never need to access the
+//!    database directly. Instead, use the public API provided by the payment engine. The Postgres backend is enabled
+//!    with the `postgres` feature. An in-memory backend ([`mod@memory`]) is always available, and serves as a reference
+//!    model and test fixture.
 //! 3. The [`mod@db_types`] module defined the data types used in the database.
 //! 4. The [`mod@events`] module defines the events that can be subscribed to. These events are emitted when certain
 //!    actions occur within the payment engine. For example, when a new order is created, an `OrderCreated` event is
 //!    emitted. A simple Pub-Sub mechanism is used so that you can easily hook into these events and perform custom
-//!    actions.
+//!    actions. Backends also write every event to a durable outbox, which the `OutboxDispatcher` delivers with retries.
 //! 5. The [`mod@traits`] module the public contract specification that backends must implement in order to be used by
 //!    the payment engine.
 
@@ -22,11 +24,12 @@
 pub mod sqlite;
 
 #[cfg(feature = "postgres")]
-mod postgres;
+pub mod postgres;
 
 pub mod db_types;
 pub mod events;
 pub mod helpers;
+pub mod memory;
 pub mod tpe_api;
 
 pub mod traits;
@@ -34,6 +37,9 @@ pub mod traits;
 #[cfg(any(feature = "test_utils", test))]
 pub mod test_utils;
 
+pub use memory::InMemoryDatabase;
+#[cfg(feature = "postgres")]
+pub use postgres::PostgresDatabase;
 #[cfg(feature = "sqlite")]
 pub use sqlite::SqliteDatabase;
 pub use tpe_api::{
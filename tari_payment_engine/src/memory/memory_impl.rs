@@ -0,0 +1,1392 @@
+//! `InMemoryDatabase` is a concrete implementation of a Tari Payment engine backend that keeps all of its state in
+//! memory.
+//!
+//! The business logic follows [`crate::SqliteDatabase`] step for step, so that it can act as a reference model for
+//! the settlement logic. Every method that mutates state runs as a "transaction": the closure passed to
+//! [`InMemoryDatabase::transaction`] works on a copy of the state, which only replaces the live state if the closure
+//! succeeds. This mirrors the rollback-on-error behaviour of the SQL backends.
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use chrono::{DateTime, Duration, Utc};
+use log::*;
+use tari_common_types::tari_address::TariAddress;
+use tpg_common::MicroTari;
+
+use super::state::{self, MemoryState};
+use crate::{
+    db_types::{
+        AddressBalance,
+        AdminAction,
+        AdminActionFilter,
+        ApprovalRequest,
+        ApprovalStatus,
+        AuthSession,
+        CreditNote,
+        CustomerBalance,
+        CustomerOrderBalance,
+        CustomerOrders,
+        HeldOrder,
+        LedgerAccount,
+        LedgerBalance,
+        LedgerEntry,
+        NewAdminAction,
+        NewApprovalRequest,
+        NewAuthSession,
+        NewLedgerTransaction,
+        NewOrder,
+        NewPayment,
+        NewRefund,
+        NewSettlementJournalEntry,
+        NewWebhookSubscription,
+        Order,
+        OrderId,
+        OrderStatusType,
+        OutboxEvent,
+        Payment,
+        Refund,
+        RefundStatus,
+        Role,
+        RoleDefinition,
+        SerializedTariAddress,
+        SettlementJournalEntry,
+        SettlementType,
+        TransferStatus,
+        WebhookDelivery,
+        WebhookSubscription,
+    },
+    events::{
+        ApprovalEvent,
+        EventType,
+        OrderAnnulledEvent,
+        OrderClaimedEvent,
+        OrderEvent,
+        OrderHeldEvent,
+        OrderModifiedEvent,
+        PaymentEvent,
+        PaymentRevertedEvent,
+        RefundEvent,
+    },
+    order_objects::{ModifyOrderRequest, OrderChanged, OrderQueryFilter},
+    tpe_api::{
+        account_objects::{AddressHistory, CustomerHistory, Pagination, TrialBalance},
+        exchange_objects::ExchangeRate,
+        rate_limit_objects::{RateLimit, RateLimitDecision},
+    },
+    traits::{
+        AccountApiError,
+        AccountManagement,
+        AuditLog,
+        AuditLogError,
+        AuthApiError,
+        AuthManagement,
+        EventOutbox,
+        ExchangeRateError,
+        ExchangeRates,
+        ExpiryResult,
+        MultiAccountPayment,
+        NewWalletInfo,
+        OrderMovedResult,
+        OutboxError,
+        PaymentGatewayDatabase,
+        PaymentGatewayError,
+        PaymentReversal,
+        RateLimitError,
+        RateLimitStore,
+        WalletAuth,
+        WalletAuthApiError,
+        WalletInfo,
+        WalletManagement,
+        WalletManagementError,
+        WebhookError,
+        WebhookManagement,
+    },
+};
+
+const MEMORY_DB_URL: &str = "memory://";
+
+/// A backend that keeps all orders, payments, accounts and auth data in memory. Clones share the same underlying
+/// state, in the same way that clones of [`crate::SqliteDatabase`] share a connection pool.
+#[derive(Clone, Default)]
+pub struct InMemoryDatabase {
+    state: Arc<Mutex<MemoryState>>,
+}
+
+impl Debug for InMemoryDatabase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "InMemoryDatabase")
+    }
+}
+
+impl PaymentGatewayDatabase for InMemoryDatabase {
+    fn url(&self) -> &str {
+        MEMORY_DB_URL
+    }
+
+    async fn claim_order(
+        &self,
+        order_id: &OrderId,
+        address: &TariAddress,
+        strict_mode: bool,
+    ) -> Result<Order, PaymentGatewayError> {
+        self.transaction(|state| Self::claim_order_with_state(order_id, address, strict_mode, state))
+    }
+
+    async fn auto_claim_order(
+        &self,
+        order: &Order,
+        strict_mode: bool,
+    ) -> Result<Option<(TariAddress, Order)>, PaymentGatewayError> {
+        if order.status != OrderStatusType::Unclaimed {
+            error!("🖇️️ Order {} is not 'Unclaimed' and cannot be auto-claimed", order.order_id);
+            return Err(PaymentGatewayError::OrderModificationForbidden);
+        }
+        self.transaction(|state| {
+            let alt_id = if strict_mode { None } else { order.alt_id.as_ref() };
+            let merchant_id = &order.merchant_id;
+            let mut address = state::merchant_balances_for_order_id(&order.order_id, alt_id, merchant_id, state);
+            if address.is_empty() {
+                address = state::merchant_balances_for_customer_id(&order.customer_id, merchant_id, state);
+            }
+            // The first address is either explicitly lined to the order, or the most recent one
+            let Some(address) = address.first().map(|a| a.address().clone()) else {
+                return Ok(None);
+            };
+            let order = state::update_order_status(order.id, OrderStatusType::New, state)?;
+            state::link_address_to_customer(&address, &order.customer_id, state);
+            state::enqueue_event(
+                EventType::OrderClaimed(OrderClaimedEvent::new(order.clone(), address.clone())),
+                state,
+            );
+            Ok(Some((address, order)))
+        })
+    }
+
+    async fn insert_order(&self, order: NewOrder) -> Result<(Order, bool), PaymentGatewayError> {
+        self.transaction(|state| {
+            let (order, inserted) = state::idempotent_insert_order(order, state);
+            if inserted {
+                state::enqueue_event(EventType::NewOrder(OrderEvent::new(order.clone())), state);
+            }
+            Ok((order, inserted))
+        })
+    }
+
+    async fn process_new_payment(
+        &self,
+        payment: NewPayment,
+        strict_mode: bool,
+    ) -> Result<Payment, PaymentGatewayError> {
+        self.transaction(|state| {
+            let maybe_order_id = payment.order_id.clone();
+            debug!("🗃️ Payment {} received from [{}]", payment.txid, payment.sender.as_address());
+            let payment = state::idempotent_insert_payment(payment, state)?;
+            state::post_ledger_transaction(&NewLedgerTransaction::payment_received(&payment), state)?;
+            state::enqueue_event(EventType::PaymentReceived(PaymentEvent::new(payment.clone())), state);
+            if let Some(order_id) = maybe_order_id {
+                match Self::claim_order_with_state(&order_id, payment.sender.as_address(), strict_mode, state) {
+                    Ok(_) => info!("🗃️ Address {} linked to order {order_id}", payment.sender.as_address()),
+                    Err(PaymentGatewayError::OrderNotFound(id)) => {
+                        info!("🗃️ Order {id} is not in the database, and so it can't be matched.");
+                    },
+                    Err(e) => return Err(e),
+                };
+            }
+            Ok(payment)
+        })
+    }
+
+    async fn fetch_pending_payments_for_address(
+        &self,
+        address: &TariAddress,
+    ) -> Result<Vec<Payment>, PaymentGatewayError> {
+        Ok(self.read(|state| state::pending_payments(address, state)))
+    }
+
+    async fn process_credit_note_for_customer(&self, note: CreditNote) -> Result<Payment, PaymentGatewayError> {
+        self.transaction(|state| {
+            let payment = state::credit_note(&note, state)?;
+            state::post_ledger_transaction(&NewLedgerTransaction::credit_note(&payment), state)?;
+            state::enqueue_event(EventType::PaymentReceived(PaymentEvent::new(payment.clone())), state);
+            state::link_address_to_customer(payment.sender.as_address(), &note.customer_id, state);
+            Ok(payment)
+        })
+    }
+
+    async fn fetch_payable_orders_for_address(&self, address: &TariAddress) -> Result<Vec<Order>, PaymentGatewayError> {
+        Ok(self.read(|state| state::fetch_payable_orders_for_address(address, state)))
+    }
+
+    /// Tries to pay for a single order from any wallet associated with the order's customer Id.
+    ///
+    /// See [`crate::SqliteDatabase`] for the full description of the settlement rules.
+    async fn try_pay_order(
+        &self,
+        order: &Order,
+        strict_mode: bool,
+    ) -> Result<Option<MultiAccountPayment>, PaymentGatewayError> {
+        self.transaction(|state| {
+            // The caller's copy may predate a re-pricing, or the expiry of its quote
+            let order = &state::fetch_order_by_order_id(&order.order_id, state)
+                .ok_or_else(|| PaymentGatewayError::OrderNotFound(order.order_id.clone()))?;
+            if order.quote_has_expired() {
+                return Err(PaymentGatewayError::QuoteExpired(order.order_id.clone()));
+            }
+            let alt_id = if strict_mode { order.alt_id.as_ref() } else { None };
+            // Only the funds held with the order's merchant can pay for it
+            let merchant_id = &order.merchant_id;
+            let order_balances = state::merchant_balances_for_order_id(&order.order_id, alt_id, merchant_id, state);
+            let mut balances = state::merchant_balances_for_customer_id(&order.customer_id, merchant_id, state);
+            balances.extend(order_balances);
+            let mut total_due = order.total_price;
+            let total_credit = balances.iter().map(|b| b.current_balance()).sum();
+            if balances.is_empty() || (total_due > total_credit) {
+                return Err(PaymentGatewayError::AccountError(AccountApiError::InsufficientFunds));
+            }
+            balances.sort_by_key(|b| Reverse(b.current_balance()));
+            let settlement_type = if balances[0].current_balance() >= total_due {
+                SettlementType::Single
+            } else {
+                SettlementType::Multiple
+            };
+            let mut result = MultiAccountPayment::new(vec![], vec![]);
+            let zero = MicroTari::from(0);
+            for account in balances {
+                let amount_paid = account.current_balance().min(total_due);
+                total_due -= amount_paid;
+                let settlement = NewSettlementJournalEntry {
+                    order_id: order.order_id.clone(),
+                    payment_address: SerializedTariAddress::from(account.address()),
+                    amount: amount_paid,
+                    settlement_type,
+                    merchant_id: order.merchant_id.clone(),
+                };
+                let settlement = state::insert_settlement(settlement, state);
+                state::post_ledger_transaction(
+                    &NewLedgerTransaction::settlement(&settlement, &order.merchant_id),
+                    state,
+                )?;
+                result.settlements.push(settlement);
+                if total_due == zero {
+                    break;
+                }
+            }
+            if total_due == zero {
+                let paid_order = state::update_order_status(order.id, OrderStatusType::Paid, state)?;
+                state::enqueue_event(EventType::OrderPaid(OrderEvent::new(paid_order.clone())), state);
+                result.orders_paid.push(paid_order);
+            }
+            Ok(if result.orders_paid.is_empty() { None } else { Some(result) })
+        })
+    }
+
+    async fn try_pay_orders_from_address(
+        &self,
+        address: &TariAddress,
+        orders: &[&Order],
+    ) -> Result<Option<MultiAccountPayment>, PaymentGatewayError> {
+        self.transaction(|state| Self::pay_orders_for_address_with_state(address, orders, state))
+    }
+
+    async fn update_payment_status(&self, txid: &str, status: TransferStatus) -> Result<Payment, PaymentGatewayError> {
+        self.transaction(|state| {
+            let Some(payment) = state::fetch_payment(txid, state) else {
+                return Err(PaymentGatewayError::PaymentStatusUpdateError(format!("Payment {txid} not found")));
+            };
+            let old_status = payment.status;
+            if old_status == status {
+                debug!("🗃️ Payment {txid} already has status {status}. No action to take");
+                return Err(PaymentGatewayError::PaymentModificationNoOp);
+            }
+            if old_status != TransferStatus::Received {
+                error!("🗃️ Payment {txid} cannot be transitioned from {old_status} to {status}.");
+                return Err(PaymentGatewayError::PaymentStatusUpdateError(format!(
+                    "Payment {txid} has status {status} instead of 'Received'"
+                )));
+            }
+            let payment = state::update_payment_status(txid, status, state)?;
+            match status {
+                TransferStatus::Confirmed => {
+                    state::post_ledger_transaction(&NewLedgerTransaction::payment_confirmed(&payment), state)?;
+                    state::enqueue_event(EventType::Confirmation(PaymentEvent::new(payment.clone())), state);
+                },
+                TransferStatus::Cancelled => {
+                    state::post_ledger_transaction(&NewLedgerTransaction::payment_cancelled(&payment), state)?;
+                },
+                TransferStatus::Received => {},
+            }
+            Ok(payment)
+        })
+    }
+
+    async fn fetch_payment_by_tx_id(&self, tx_id: &str) -> Result<Payment, PaymentGatewayError> {
+        self.read(|state| state::fetch_payment(tx_id, state))
+            .ok_or_else(|| PaymentGatewayError::PaymentNotFound(tx_id.into()))
+    }
+
+    async fn update_payment_confirmations(
+        &self,
+        tx_id: &str,
+        block_height: Option<i64>,
+        confirmations: i64,
+    ) -> Result<Payment, PaymentGatewayError> {
+        self.transaction(|state| state::update_payment_confirmations(tx_id, block_height, confirmations, state))
+    }
+
+    async fn revert_payment(&self, tx_id: &str) -> Result<PaymentReversal, PaymentGatewayError> {
+        self.transaction(|state| {
+            let payment =
+                state::fetch_payment(tx_id, state).ok_or_else(|| PaymentGatewayError::PaymentNotFound(tx_id.into()))?;
+            if payment.status != TransferStatus::Confirmed {
+                return Err(PaymentGatewayError::PaymentStatusUpdateError(format!(
+                    "Payment {tx_id} has status {} instead of 'Confirmed'",
+                    payment.status
+                )));
+            }
+            let address = payment.sender.as_address().clone();
+            // Only the orders of the payment's merchant were paid from it
+            let balance = state::fetch_merchant_balance(&address, &payment.merchant_id, state);
+            let shortfall = payment.amount - balance.current_balance();
+            state::update_payment_status(tx_id, TransferStatus::Received, state)?;
+            let payment = state::update_payment_confirmations(tx_id, None, 0, state)?;
+            state::post_ledger_transaction(&NewLedgerTransaction::payment_reverted(&payment), state)?;
+            let mut orders_reverted = Vec::new();
+            let mut settlements = Vec::new();
+            if shortfall > MicroTari::from(0) {
+                let mut entries = state::settlements_for_address(&address, state);
+                entries.retain(|e| e.merchant_id == payment.merchant_id);
+                for order_id in PaymentReversal::orders_to_revert(&entries, shortfall) {
+                    let order = state::fetch_order_by_order_id(&order_id, state)
+                        .ok_or_else(|| PaymentGatewayError::OrderNotFound(order_id.clone()))?;
+                    for entry in PaymentReversal::reversing_entries(&state::settlements_for_order(&order_id, state)) {
+                        let settlement = state::insert_settlement(entry, state);
+                        let transaction = NewLedgerTransaction::settlement(&settlement, &order.merchant_id);
+                        state::post_ledger_transaction(&transaction, state)?;
+                        settlements.push(settlement);
+                    }
+                    orders_reverted.push(state::update_order_status(order.id, OrderStatusType::New, state)?);
+                }
+            }
+            let event = PaymentRevertedEvent::new(payment.clone(), orders_reverted.clone());
+            state::enqueue_event(EventType::PaymentReverted(event), state);
+            Ok(PaymentReversal::new(payment, orders_reverted, settlements))
+        })
+    }
+
+    async fn mark_new_or_unclaimed_order_as_paid(
+        &self,
+        order: Order,
+        reason: &str,
+    ) -> Result<Order, PaymentGatewayError> {
+        if ![OrderStatusType::New, OrderStatusType::Unclaimed].contains(&order.status) {
+            error!(
+                "🗃️ Order {} is not in 'New' or 'Unclaimed' status. Cannot override this and mark it as paid",
+                order.id
+            );
+            return Err(PaymentGatewayError::OrderModificationForbidden);
+        }
+        self.transaction(|state| {
+            let reason = format!("Admin credit overrode for order {}. Reason: {reason}", order.order_id);
+            let note = CreditNote::new(order.customer_id.clone(), order.total_price)
+                .with_reason(reason)
+                .with_merchant(&order.merchant_id);
+            let payment = state::credit_note(&note, state)?;
+            state::post_ledger_transaction(&NewLedgerTransaction::credit_note(&payment), state)?;
+            state::enqueue_event(EventType::PaymentReceived(PaymentEvent::new(payment.clone())), state);
+            let address = payment.sender.to_address();
+            if order.status == OrderStatusType::Unclaimed {
+                Self::claim_order_with_state(&order.order_id, &address, true, state)?;
+            }
+            let result = Self::pay_orders_for_address_with_state(&address, &[&order], state)?;
+            match result {
+                Some(mut payment) => Ok(payment.orders_paid.remove(0)),
+                None => {
+                    error!(
+                        "🗃️ Order {} could not be paid for after issuing a credit note for the full amount.",
+                        order.id
+                    );
+                    Err(PaymentGatewayError::OrderNotFound(order.order_id.clone()))
+                },
+            }
+        })
+    }
+
+    async fn cancel_or_expire_order(
+        &self,
+        id: &OrderId,
+        new_status: OrderStatusType,
+        reason: &str,
+        strict_mode: bool,
+    ) -> Result<Order, PaymentGatewayError> {
+        self.transaction(|state| {
+            let order = state::fetch_order_by_id(id, strict_mode, state)?;
+            if ![OrderStatusType::New, OrderStatusType::Unclaimed].contains(&order.status) {
+                error!("🗃️ Order {} is not in 'New' status. Cannot call cancel_or_expire_order", order.id);
+                return Err(PaymentGatewayError::OrderModificationForbidden);
+            }
+            let update = ModifyOrderRequest::default().with_new_status(new_status).with_new_memo(reason);
+            let order = state::update_order(&order.order_id, update, state)?
+                .ok_or_else(|| AccountApiError::OrderDoesNotExist(order.order_id.clone()))?;
+            state::enqueue_event(EventType::OrderAnnulled(OrderAnnulledEvent::new(order.clone())), state);
+            Ok(order)
+        })
+    }
+
+    async fn reset_order(&self, order_id: &OrderId) -> Result<OrderChanged, PaymentGatewayError> {
+        self.transaction(|state| {
+            let old_order = state::fetch_order_by_order_id(order_id, state)
+                .ok_or_else(|| AccountApiError::OrderDoesNotExist(order_id.clone()))?;
+            if !matches!(old_order.status, OrderStatusType::Expired | OrderStatusType::Cancelled) {
+                error!("🗃️ Order {} is not in 'Expired' or 'Cancelled' status. Cannot call reset_order", old_order.id);
+                return Err(PaymentGatewayError::OrderModificationForbidden);
+            }
+            let update = ModifyOrderRequest::default().with_new_status(OrderStatusType::New);
+            let updated_order = state::update_order(&old_order.order_id, update, state)?
+                .ok_or_else(|| AccountApiError::OrderDoesNotExist(old_order.order_id.clone()))?;
+            let result = OrderChanged::new(old_order, updated_order);
+            let modified = OrderModifiedEvent::new("status".to_string(), result.clone());
+            state::enqueue_event(EventType::OrderModified(modified), state);
+            state::enqueue_event(EventType::NewOrder(OrderEvent::new(result.new_order.clone())), state);
+            Ok(result)
+        })
+    }
+
+    async fn modify_customer_id_for_order(
+        &self,
+        id: &OrderId,
+        new_cid: &str,
+        strict_mode: bool,
+    ) -> Result<OrderMovedResult, PaymentGatewayError> {
+        let (old_order, mut new_order) = self.transaction(|state| {
+            let old_order = state::fetch_order_by_id(id, strict_mode, state)?;
+            if matches!(old_order.status, OrderStatusType::Paid) {
+                return Err(PaymentGatewayError::OrderModificationForbidden);
+            }
+            if new_cid == old_order.customer_id {
+                debug!("🗃️ Order {id} is being reassigned to the same customer. No action taken.");
+                return Err(PaymentGatewayError::OrderModificationNoOp);
+            }
+            let update = ModifyOrderRequest::default().with_new_customer_id(new_cid);
+            let new_order = state::update_order(&old_order.order_id, update, state)?
+                .ok_or_else(|| AccountApiError::OrderDoesNotExist(id.clone()))?;
+            let changes = OrderChanged::new(old_order.clone(), new_order.clone());
+            state::enqueue_event(
+                EventType::OrderModified(OrderModifiedEvent::new("customer_id".into(), changes)),
+                state,
+            );
+            Ok((old_order, new_order))
+        })?;
+        let mut settlements = Vec::new();
+        if let OrderStatusType::New = new_order.status {
+            match self.try_pay_order(&new_order, strict_mode).await {
+                Ok(Some(payment)) => {
+                    let mut orders_paid;
+                    MultiAccountPayment { settlements, orders_paid, .. } = payment;
+                    orders_paid.drain(..1).for_each(|o| new_order = o);
+                },
+                Ok(None) => { /* noop */ },
+                Err(PaymentGatewayError::AccountError(AccountApiError::InsufficientFunds)) => {
+                    debug!("🗃️ There weren't enough funds to pay for order {id} from the new customer id {new_cid}");
+                },
+                Err(e) => return Err(e),
+            };
+        }
+        Ok(OrderMovedResult::new(old_order, new_order, settlements))
+    }
+
+    async fn modify_memo_for_order(&self, order_id: &OrderId, new_memo: &str) -> Result<Order, PaymentGatewayError> {
+        self.transaction(|state| {
+            let update = ModifyOrderRequest::default().with_new_memo(new_memo);
+            let old_order = state::fetch_order_by_order_id(order_id, state)
+                .ok_or_else(|| AccountApiError::OrderDoesNotExist(order_id.clone()))?;
+            let order = state::update_order(order_id, update, state)?
+                .ok_or_else(|| AccountApiError::OrderDoesNotExist(order_id.clone()))?;
+            let modified = OrderModifiedEvent::new("memo".to_string(), OrderChanged::new(old_order, order.clone()));
+            state::enqueue_event(EventType::OrderModified(modified), state);
+            Ok(order)
+        })
+    }
+
+    async fn modify_total_price_for_order(
+        &self,
+        id: &OrderId,
+        new_total_price: MicroTari,
+        strict_mode: bool,
+    ) -> Result<OrderChanged, PaymentGatewayError> {
+        self.transaction(|state| {
+            let old_order = state::fetch_order_by_id(id, strict_mode, state)?;
+            if !matches!(old_order.status, OrderStatusType::New) {
+                info!("🗃️ Order {id}'s price cannot be changed since it is already {}", old_order.status);
+                return Err(PaymentGatewayError::OrderModificationForbidden);
+            }
+            if old_order.total_price == new_total_price {
+                info!("🗃️ Order {id}'s price is already {new_total_price}. No action taken.");
+                return Err(PaymentGatewayError::OrderModificationNoOp);
+            }
+            let update = ModifyOrderRequest::default().with_new_total_price(new_total_price);
+            let new_order = state::update_order(&old_order.order_id, update, state)?
+                .ok_or_else(|| AccountApiError::OrderDoesNotExist(id.clone()))?;
+            let delta = OrderChanged::new(old_order, new_order);
+            let modified = OrderModifiedEvent::new("total_price".to_string(), delta.clone());
+            state::enqueue_event(EventType::OrderModified(modified), state);
+            Ok(delta)
+        })
+    }
+
+    async fn fetch_orders_with_expired_quotes(&self) -> Result<Vec<Order>, PaymentGatewayError> {
+        self.read(|state| Ok(state::fetch_orders_with_expired_quotes(state)))
+    }
+
+    async fn requote_order(
+        &self,
+        order_id: &OrderId,
+        new_total_price: MicroTari,
+        rate_id: i64,
+        quote_expires_at: DateTime<Utc>,
+    ) -> Result<OrderChanged, PaymentGatewayError> {
+        self.transaction(|state| {
+            let old_order = state::fetch_order_by_order_id(order_id, state)
+                .ok_or_else(|| PaymentGatewayError::OrderNotFound(order_id.clone()))?;
+            if !matches!(old_order.status, OrderStatusType::New | OrderStatusType::Unclaimed) {
+                info!("🗃️ Order {order_id} cannot be re-priced since it is already {}", old_order.status);
+                return Err(PaymentGatewayError::OrderModificationForbidden);
+            }
+            let new_order = state::update_quote(order_id, new_total_price, rate_id, quote_expires_at, state)?;
+            let delta = OrderChanged::new(old_order, new_order);
+            let modified = OrderModifiedEvent::new("total_price".to_string(), delta.clone());
+            state::enqueue_event(EventType::OrderModified(modified), state);
+            Ok(delta)
+        })
+    }
+
+    async fn expire_old_orders(
+        &self,
+        unclaimed_limit: Duration,
+        unpaid_limit: Duration,
+    ) -> Result<ExpiryResult, PaymentGatewayError> {
+        self.transaction(|state| {
+            let unclaimed_orders = state::expire_orders(OrderStatusType::Unclaimed, unclaimed_limit, state);
+            let unpaid_orders = state::expire_orders(OrderStatusType::New, unpaid_limit, state);
+            for order in unclaimed_orders.iter().chain(unpaid_orders.iter()) {
+                state::enqueue_event(EventType::OrderAnnulled(OrderAnnulledEvent::new(order.clone())), state);
+            }
+            Ok(ExpiryResult::new(unclaimed_orders, unpaid_orders))
+        })
+    }
+
+    async fn request_refund(&self, refund: NewRefund) -> Result<Refund, PaymentGatewayError> {
+        self.transaction(|state| {
+            let (address, order_id) = (refund.address.as_address(), refund.order_id.as_ref());
+            Self::check_refund(address, order_id, &refund.merchant_id, refund.amount, state)?;
+            let refund = state::insert_refund(refund, state);
+            let address = refund.address.as_address().to_base58();
+            info!("🗃️ Refund {} of {} to {address} has been requested", refund.id, refund.amount);
+            Ok(refund)
+        })
+    }
+
+    async fn approve_refund(&self, id: i64) -> Result<Refund, PaymentGatewayError> {
+        self.transaction(|state| {
+            let refund = Self::fetch_refund_with_status(id, RefundStatus::Requested, state)?;
+            let (address, order_id) = (refund.address.as_address(), refund.order_id.as_ref());
+            Self::check_refund(address, order_id, &refund.merchant_id, refund.amount, state)?;
+            let refund = state::update_refund(id, RefundStatus::Approved, None, None, state)?;
+            // A refund against an order comes out of the revenue of the order's merchant
+            let merchant_id = refund
+                .order_id
+                .as_ref()
+                .and_then(|order_id| state::fetch_order_by_order_id(order_id, state))
+                .map(|order| order.merchant_id);
+            let transaction = NewLedgerTransaction::refund_approved(&refund, merchant_id.as_deref());
+            state::post_ledger_transaction(&transaction, state)?;
+            state::enqueue_event(EventType::RefundApproved(RefundEvent::new(refund.clone())), state);
+            info!("🗃️ Refund {id} of {} has been approved", refund.amount);
+            Ok(refund)
+        })
+    }
+
+    async fn reject_refund(&self, id: i64, reason: &str) -> Result<Refund, PaymentGatewayError> {
+        self.transaction(|state| {
+            let refund = Self::fetch_refund_with_status(id, RefundStatus::Requested, state)?;
+            let reason = format!("{}. Rejected: {reason}", refund.reason);
+            let refund = state::update_refund(id, RefundStatus::Rejected, Some(&reason), None, state)?;
+            info!("🗃️ Refund {id} has been rejected");
+            Ok(refund)
+        })
+    }
+
+    async fn mark_refund_sent(&self, id: i64, payout_txid: &str) -> Result<Refund, PaymentGatewayError> {
+        self.transaction(|state| {
+            Self::fetch_refund_with_status(id, RefundStatus::Approved, state)?;
+            let refund = state::update_refund(id, RefundStatus::Sent, None, Some(payout_txid), state)?;
+            state::post_ledger_transaction(&NewLedgerTransaction::refund_sent(&refund), state)?;
+            state::enqueue_event(EventType::RefundSent(RefundEvent::new(refund.clone())), state);
+            info!("🗃️ Refund {id} has been sent in transaction {payout_txid}");
+            Ok(refund)
+        })
+    }
+
+    async fn hold_order(&self, order: &NewOrder, reason: &str) -> Result<HeldOrder, PaymentGatewayError> {
+        self.transaction(|state| {
+            let id = &order.order_id;
+            if state::fetch_order_by_order_id(id, state).is_some() || state::fetch_held_order(id, state).is_some() {
+                return Err(PaymentGatewayError::OrderAlreadyExists(id.clone()));
+            }
+            let held_order = state::insert_held_order(order, reason, state);
+            state::enqueue_event(EventType::OrderHeld(OrderHeldEvent::new(held_order.clone())), state);
+            warn!("🗃️ Order {id} is being held. {reason}");
+            Ok(held_order)
+        })
+    }
+
+    async fn mark_held_order_released(&self, order_id: &OrderId) -> Result<HeldOrder, PaymentGatewayError> {
+        self.transaction(|state| {
+            let held_order = state::release_held_order(order_id, state)
+                .ok_or_else(|| PaymentGatewayError::OrderNotHeld(order_id.clone()))?;
+            info!("🗃️ Held order {order_id} has been released");
+            Ok(held_order)
+        })
+    }
+
+    async fn request_approval(&self, request: NewApprovalRequest) -> Result<ApprovalRequest, PaymentGatewayError> {
+        self.transaction(|state| {
+            let request = state::insert_approval_request(request, state);
+            state::enqueue_event(EventType::ApprovalRequested(ApprovalEvent::new(request.clone())), state);
+            info!("🗃️ Approval request {} has been recorded. {}", request.id, request.operation);
+            Ok(request)
+        })
+    }
+
+    async fn approve_request(&self, id: i64, approver: &TariAddress) -> Result<ApprovalRequest, PaymentGatewayError> {
+        self.transaction(|state| {
+            let request =
+                state::fetch_approval_request(id, state).ok_or(PaymentGatewayError::ApprovalRequestNotFound(id))?;
+            request.check_decision(approver)?;
+            let request = state::update_approval_request(
+                id,
+                ApprovalStatus::Pending,
+                ApprovalStatus::Approved,
+                Some(approver),
+                None,
+                state,
+            )?;
+            state::enqueue_event(EventType::OperationApproved(ApprovalEvent::new(request.clone())), state);
+            info!("🗃️ Approval request {id} has been approved by {}", approver.to_base58());
+            Ok(request)
+        })
+    }
+
+    async fn reject_request(
+        &self,
+        id: i64,
+        approver: &TariAddress,
+        reason: &str,
+    ) -> Result<ApprovalRequest, PaymentGatewayError> {
+        self.transaction(|state| {
+            let request =
+                state::fetch_approval_request(id, state).ok_or(PaymentGatewayError::ApprovalRequestNotFound(id))?;
+            request.check_decision(approver)?;
+            let request = state::update_approval_request(
+                id,
+                ApprovalStatus::Pending,
+                ApprovalStatus::Rejected,
+                Some(approver),
+                Some(reason),
+                state,
+            )?;
+            state::enqueue_event(EventType::OperationRejected(ApprovalEvent::new(request.clone())), state);
+            info!("🗃️ Approval request {id} has been rejected by {}", approver.to_base58());
+            Ok(request)
+        })
+    }
+
+    async fn mark_approval_failed(&self, id: i64, error: &str) -> Result<ApprovalRequest, PaymentGatewayError> {
+        self.transaction(|state| {
+            Self::fetch_approval_with_status(id, ApprovalStatus::Approved, state)?;
+            let request = state::update_approval_request(
+                id,
+                ApprovalStatus::Approved,
+                ApprovalStatus::Failed,
+                None,
+                Some(error),
+                state,
+            )?;
+            info!("🗃️ Approval request {id} has failed. {error}");
+            Ok(request)
+        })
+    }
+
+    async fn expire_approval_requests(&self) -> Result<Vec<ApprovalRequest>, PaymentGatewayError> {
+        self.transaction(|state| {
+            let expired = state::expire_approval_requests(state);
+            if !expired.is_empty() {
+                info!("🗃️ {} approval requests have expired", expired.len());
+            }
+            Ok(expired)
+        })
+    }
+}
+
+impl AccountManagement for InMemoryDatabase {
+    async fn fetch_orders_for_address(&self, address: &TariAddress) -> Result<Vec<Order>, AccountApiError> {
+        Ok(self.read(|state| state::orders_for_address(address, state)))
+    }
+
+    async fn fetch_order_by_order_id(&self, order_id: &OrderId) -> Result<Option<Order>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_order_by_order_id(order_id, state)))
+    }
+
+    async fn fetch_order_by_alt_id(&self, alt: &OrderId) -> Result<Option<Order>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_order_by_alt_id(alt, state)))
+    }
+
+    async fn fetch_order_by_id_or_alt(&self, id: &OrderId) -> Result<Option<Order>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_order_by_id_or_alt(id, state)))
+    }
+
+    async fn fetch_payments_for_address(&self, address: &TariAddress) -> Result<Vec<Payment>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_payments_for_address(address, state)))
+    }
+
+    async fn history_for_address(&self, address: &TariAddress) -> Result<AddressHistory, AccountApiError> {
+        let history = self.read(|state| {
+            let balance = state::fetch_address_balance(address, state);
+            let payments = state::fetch_payments_for_address(address, state);
+            let orders = state::orders_for_address(address, state);
+            let settlements = state::settlements_for_address(address, state);
+            let address = SerializedTariAddress::from(address.clone());
+            AddressHistory::new(address, balance, orders, payments, settlements)
+        });
+        Ok(history)
+    }
+
+    async fn history_for_customer(&self, customer_id: &str) -> Result<CustomerHistory, AccountApiError> {
+        let (balances, order_balance, orders, settlements) = self.read(|state| {
+            let balances = state::balances_for_customer_id(customer_id, state);
+            let order_balance = state::customer_order_balance(customer_id, state);
+            let query = OrderQueryFilter::default().with_customer_id(customer_id.to_string());
+            let orders = state::search_orders(query, state);
+            let settlements = state::settlements_for_customer_id(customer_id, state);
+            (balances, order_balance, orders, settlements)
+        });
+        let history = CustomerHistory::builder(customer_id.to_string())
+            .balance(CustomerBalance::new(balances))
+            .order_balance(order_balance)
+            .orders(orders)
+            .settlements(settlements)
+            .build()?;
+        Ok(history)
+    }
+
+    async fn search_orders(&self, query: OrderQueryFilter) -> Result<Vec<Order>, AccountApiError> {
+        Ok(self.read(|state| state::search_orders(query, state)))
+    }
+
+    async fn creditors(&self) -> Result<Vec<CustomerOrders>, AccountApiError> {
+        Ok(self.read(state::creditors))
+    }
+
+    async fn fetch_customer_ids(&self, pagination: &Pagination) -> Result<Vec<String>, AccountApiError> {
+        Ok(self.read(|state| state::customer_ids(pagination, state)))
+    }
+
+    async fn fetch_addresses(&self, pagination: &Pagination) -> Result<Vec<TariAddress>, AccountApiError> {
+        Ok(self.read(|state| state::addresses(pagination, state)))
+    }
+
+    async fn fetch_address_balance(&self, address: &TariAddress) -> Result<AddressBalance, AccountApiError> {
+        Ok(self.read(|state| state::fetch_address_balance(address, state)))
+    }
+
+    async fn fetch_customer_balance(&self, customer_id: &str) -> Result<CustomerBalance, AccountApiError> {
+        let balances = self.read(|state| state::balances_for_customer_id(customer_id, state));
+        Ok(CustomerBalance::new(balances))
+    }
+
+    async fn fetch_customer_order_balance(&self, customer_id: &str) -> Result<CustomerOrderBalance, AccountApiError> {
+        Ok(self.read(|state| state::customer_order_balance(customer_id, state)))
+    }
+
+    async fn fetch_customer_ids_for_address(&self, address: &TariAddress) -> Result<Vec<String>, AccountApiError> {
+        Ok(self.read(|state| state::customer_ids_for_address(address, state)))
+    }
+
+    async fn fetch_payments_for_order(&self, order_id: &OrderId) -> Result<Vec<Payment>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_payments_for_order(order_id, state)))
+    }
+
+    async fn fetch_onchain_payments(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Payment>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_onchain_payments(since, state)))
+    }
+
+    async fn fetch_idle_balances(&self, idle: Duration) -> Result<Vec<AddressBalance>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_idle_balances(idle, state)))
+    }
+
+    async fn fetch_refund(&self, id: i64) -> Result<Option<Refund>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_refund(id, state)))
+    }
+
+    async fn fetch_refunds(
+        &self,
+        status: Option<RefundStatus>,
+        pagination: &Pagination,
+    ) -> Result<Vec<Refund>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_refunds(status, pagination, state)))
+    }
+
+    async fn fetch_held_order(&self, order_id: &OrderId) -> Result<Option<HeldOrder>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_held_order(order_id, state)))
+    }
+
+    async fn fetch_held_orders(&self, pagination: &Pagination) -> Result<Vec<HeldOrder>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_held_orders(pagination, state)))
+    }
+
+    async fn fetch_ledger_entries(
+        &self,
+        account: &LedgerAccount,
+        pagination: &Pagination,
+    ) -> Result<Vec<LedgerEntry>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_ledger_entries(account, pagination, state)))
+    }
+
+    async fn fetch_ledger_balances(&self) -> Result<Vec<LedgerBalance>, AccountApiError> {
+        Ok(self.read(state::ledger_balances))
+    }
+
+    async fn fetch_trial_balance(&self) -> Result<TrialBalance, AccountApiError> {
+        Ok(self.read(|state| {
+            TrialBalance::new(state::ledger_balances(state), state::unbalanced_ledger_transactions(state))
+        }))
+    }
+
+    async fn fetch_orders_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Order>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_orders_between(from, to, state)))
+    }
+
+    async fn fetch_payments_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Payment>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_payments_between(from, to, state)))
+    }
+
+    async fn fetch_settlements_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<SettlementJournalEntry>, AccountApiError> {
+        Ok(self.read(|state| state::settlements_between(from, to, state)))
+    }
+
+    async fn fetch_approval_request(&self, id: i64) -> Result<Option<ApprovalRequest>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_approval_request(id, state)))
+    }
+
+    async fn fetch_approval_requests(
+        &self,
+        status: Option<ApprovalStatus>,
+        pagination: &Pagination,
+    ) -> Result<Vec<ApprovalRequest>, AccountApiError> {
+        Ok(self.read(|state| state::fetch_approval_requests(status, pagination, state)))
+    }
+}
+
+impl AuthManagement for InMemoryDatabase {
+    async fn check_auth_account_exists(&self, address: &TariAddress) -> Result<bool, AuthApiError> {
+        Ok(self.read(|state| state::auth_account_exists(address, state)))
+    }
+
+    async fn check_address_has_roles(&self, address: &TariAddress, roles: &[Role]) -> Result<(), AuthApiError> {
+        self.read(|state| state::address_has_roles(address, roles, state))
+    }
+
+    async fn fetch_roles_for_address(&self, address: &TariAddress) -> Result<Vec<Role>, AuthApiError> {
+        Ok(self.read(|state| state::roles_for_address(address, state)).into_iter().collect())
+    }
+
+    async fn create_auth_log(&self, address: &TariAddress, nonce: u64) -> Result<(), AuthApiError> {
+        self.transaction(|state| state::upsert_nonce_for_address(address, nonce, state))
+    }
+
+    async fn update_nonce_for_address(&self, address: &TariAddress, nonce: u64) -> Result<(), AuthApiError> {
+        self.transaction(|state| state::upsert_nonce_for_address(address, nonce, state))
+    }
+
+    async fn assign_roles(&self, address: &TariAddress, roles: &[Role]) -> Result<(), AuthApiError> {
+        self.transaction(|state| state::assign_roles(address, roles, state))?;
+        debug!("🔑️ Roles {roles:?} assigned to {}", address.to_base58());
+        Ok(())
+    }
+
+    async fn remove_roles(&self, address: &TariAddress, roles: &[Role]) -> Result<u64, AuthApiError> {
+        self.transaction(|state| Ok(state::remove_roles(address, roles, state)))
+    }
+
+    async fn fetch_merchant_roles_for_address(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+    ) -> Result<Vec<Role>, AuthApiError> {
+        Ok(self.read(|state| state::merchant_roles_for_address(address, merchant_id, state)).into_iter().collect())
+    }
+
+    async fn assign_merchant_roles(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+        roles: &[Role],
+    ) -> Result<(), AuthApiError> {
+        self.transaction(|state| state::assign_merchant_roles(address, merchant_id, roles, state))?;
+        debug!("🔑️ Roles {roles:?} assigned to {} for merchant {merchant_id}", address.to_base58());
+        Ok(())
+    }
+
+    async fn remove_merchant_roles(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+        roles: &[Role],
+    ) -> Result<u64, AuthApiError> {
+        self.transaction(|state| Ok(state::remove_merchant_roles(address, merchant_id, roles, state)))
+    }
+
+    async fn fetch_role_definitions(&self) -> Result<Vec<RoleDefinition>, AuthApiError> {
+        Ok(self.read(state::role_definitions))
+    }
+
+    async fn save_role(&self, role: &RoleDefinition) -> Result<(), AuthApiError> {
+        self.transaction(|state| state::save_role(role, state))?;
+        debug!("🔑️ Role {} now grants {:?}", role.role, role.permissions);
+        Ok(())
+    }
+
+    async fn delete_role(&self, role: &Role) -> Result<(), AuthApiError> {
+        self.transaction(|state| state::delete_role(role, state))?;
+        debug!("🔑️ Role {role} deleted");
+        Ok(())
+    }
+
+    async fn create_session(&self, session: NewAuthSession) -> Result<AuthSession, AuthApiError> {
+        let session = self.transaction(|state| Ok::<_, AuthApiError>(state::insert_session(session, state)))?;
+        debug!("🔑️ Session #{} opened for {}", session.id, session.address);
+        Ok(session)
+    }
+
+    async fn fetch_session(&self, id: i64) -> Result<Option<AuthSession>, AuthApiError> {
+        Ok(self.read(|state| state::fetch_session(id, state)))
+    }
+
+    async fn refresh_session(
+        &self,
+        refresh_token_hash: &str,
+        new_refresh_token_hash: &str,
+    ) -> Result<AuthSession, AuthApiError> {
+        self.transaction(|state| state::refresh_session(refresh_token_hash, new_refresh_token_hash, state))
+    }
+
+    async fn fetch_sessions_for_address(&self, address: &TariAddress) -> Result<Vec<AuthSession>, AuthApiError> {
+        Ok(self.read(|state| state::sessions_for_address(address, state)))
+    }
+
+    async fn revoke_session(&self, id: i64) -> Result<AuthSession, AuthApiError> {
+        let session = self.transaction(|state| state::revoke_session(id, state))?;
+        debug!("🔑️ Session #{id} for {} revoked", session.address);
+        Ok(session)
+    }
+
+    async fn revoke_sessions_for_address(&self, address: &TariAddress) -> Result<Vec<i64>, AuthApiError> {
+        let ids =
+            self.transaction(|state| Ok::<_, AuthApiError>(state::revoke_sessions_for_address(address, state)))?;
+        debug!("🔑️ {} sessions for {} revoked", ids.len(), address.to_base58());
+        Ok(ids)
+    }
+
+    async fn fetch_revoked_sessions(&self, since: DateTime<Utc>) -> Result<Vec<i64>, AuthApiError> {
+        Ok(self.read(|state| state::revoked_sessions(since, state)))
+    }
+}
+
+impl WalletAuth for InMemoryDatabase {
+    async fn get_wallet_info(&self, wallet_address: &TariAddress) -> Result<WalletInfo, WalletAuthApiError> {
+        self.read(|state| state::fetch_wallet_info_for_address(wallet_address, state))
+    }
+
+    async fn update_wallet_nonce(
+        &self,
+        wallet_address: &TariAddress,
+        new_nonce: i64,
+    ) -> Result<(), WalletAuthApiError> {
+        self.transaction(|state| state::update_wallet_nonce(wallet_address, new_nonce, state))
+    }
+}
+
+impl WalletManagement for InMemoryDatabase {
+    async fn register_wallet(&self, wallet: NewWalletInfo) -> Result<(), WalletManagementError> {
+        self.transaction(|state| state::register_wallet(wallet, state))
+    }
+
+    async fn deregister_wallet(&self, wallet_address: &TariAddress) -> Result<(), WalletManagementError> {
+        self.transaction(|state| state::deregister_wallet(wallet_address, state))
+    }
+
+    async fn fetch_authorized_wallets(&self) -> Result<Vec<WalletInfo>, WalletManagementError> {
+        Ok(self.read(state::fetch_authorized_wallets))
+    }
+}
+
+impl ExchangeRates for InMemoryDatabase {
+    async fn fetch_last_rate(&self, currency: &str) -> Result<ExchangeRate, ExchangeRateError> {
+        self.read(|state| state::fetch_last_rate(currency, state))
+    }
+
+    async fn fetch_merchant_rate(&self, merchant_id: &str, currency: &str) -> Result<ExchangeRate, ExchangeRateError> {
+        self.read(|state| state::fetch_merchant_rate(merchant_id, currency, state))
+    }
+
+    async fn fetch_rate_by_id(&self, id: i64) -> Result<ExchangeRate, ExchangeRateError> {
+        self.read(|state| state::fetch_rate_by_id(id, state))
+    }
+
+    async fn fetch_rate_at(&self, currency: &str, timestamp: DateTime<Utc>) -> Result<ExchangeRate, ExchangeRateError> {
+        self.read(|state| state::fetch_rate_at(currency, timestamp, state))
+    }
+
+    async fn fetch_previous_rate(&self, rate: &ExchangeRate) -> Result<Option<ExchangeRate>, ExchangeRateError> {
+        Ok(self.read(|state| state::fetch_previous_rate(rate, state)))
+    }
+
+    async fn fetch_rate_history(
+        &self,
+        merchant_id: &str,
+        currency: &str,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        pagination: &Pagination,
+    ) -> Result<Vec<ExchangeRate>, ExchangeRateError> {
+        Ok(self.read(|state| state::fetch_rate_history(merchant_id, currency, since, until, pagination, state)))
+    }
+
+    /// The `updated_at` field of the exchange rate is ignored and set to the current time.
+    async fn set_exchange_rate(&self, new_rate: &ExchangeRate) -> Result<(), ExchangeRateError> {
+        self.transaction(|state| {
+            state::set_exchange_rate(new_rate, state);
+            Ok(())
+        })
+    }
+
+    async fn confirm_exchange_rate(&self, rate: &ExchangeRate) -> Result<(), ExchangeRateError> {
+        self.transaction(|state| state::confirm_exchange_rate(rate.id, state))
+    }
+}
+
+impl EventOutbox for InMemoryDatabase {
+    async fn enqueue_events(&self, events: &[EventType]) -> Result<Vec<OutboxEvent>, OutboxError> {
+        self.transaction(|state| Ok(events.iter().map(|e| state::enqueue_event(e.clone(), state)).collect()))
+    }
+
+    async fn fetch_due_events(&self, limit: i64) -> Result<Vec<OutboxEvent>, OutboxError> {
+        Ok(self.read(|state| state::fetch_due_events(limit, state)))
+    }
+
+    async fn claim_due_events(&self, limit: i64, lease: Duration) -> Result<Vec<OutboxEvent>, OutboxError> {
+        self.transaction(|state| Ok(state::claim_due_events(limit, Utc::now() + lease, state)))
+    }
+
+    async fn fetch_undelivered_events(&self, pagination: &Pagination) -> Result<Vec<OutboxEvent>, OutboxError> {
+        Ok(self.read(|state| state::fetch_undelivered_events(pagination, state)))
+    }
+
+    async fn fetch_outbox_event(&self, id: i64) -> Result<Option<OutboxEvent>, OutboxError> {
+        Ok(self.read(|state| state::fetch_outbox_event(id, state)))
+    }
+
+    async fn mark_event_delivered(&self, id: i64) -> Result<OutboxEvent, OutboxError> {
+        self.transaction(|state| state::mark_event_delivered(id, state))
+    }
+
+    async fn record_failed_delivery(
+        &self,
+        id: i64,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<OutboxEvent, OutboxError> {
+        self.transaction(|state| state::record_failed_delivery(id, error, retry_at, state))
+    }
+
+    async fn fetch_delivered_handlers(&self, id: i64) -> Result<Vec<String>, OutboxError> {
+        Ok(self.read(|state| state::fetch_delivered_handlers(id, state)))
+    }
+
+    async fn mark_handler_delivered(&self, id: i64, handler: &str) -> Result<(), OutboxError> {
+        self.transaction(|state| {
+            state::mark_handler_delivered(id, handler, state);
+            Ok(())
+        })
+    }
+
+    async fn replay_event(&self, id: i64) -> Result<OutboxEvent, OutboxError> {
+        self.transaction(|state| state::replay_event(id, state))
+    }
+}
+
+impl WebhookManagement for InMemoryDatabase {
+    async fn create_webhook(&self, webhook: &NewWebhookSubscription) -> Result<WebhookSubscription, WebhookError> {
+        self.transaction(|state| state::insert_webhook(webhook, state))
+    }
+
+    async fn fetch_webhooks(&self) -> Result<Vec<WebhookSubscription>, WebhookError> {
+        Ok(self.read(state::fetch_webhooks))
+    }
+
+    async fn fetch_webhook(&self, id: i64) -> Result<Option<WebhookSubscription>, WebhookError> {
+        Ok(self.read(|state| state::fetch_webhook(id, state)))
+    }
+
+    async fn set_webhook_active(&self, id: i64, active: bool) -> Result<WebhookSubscription, WebhookError> {
+        self.transaction(|state| state::set_webhook_active(id, active, state))
+    }
+
+    async fn delete_webhook(&self, id: i64) -> Result<(), WebhookError> {
+        self.transaction(|state| state::delete_webhook(id, state))
+    }
+
+    async fn queue_webhook_deliveries(&self, event: &OutboxEvent) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        self.transaction(|state| state::queue_webhook_deliveries(event, state))
+    }
+
+    async fn fetch_due_webhook_deliveries(&self, limit: i64) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        Ok(self.read(|state| state::fetch_due_webhook_deliveries(limit, state)))
+    }
+
+    async fn claim_due_webhook_deliveries(
+        &self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        self.transaction(|state| Ok(state::claim_due_webhook_deliveries(limit, Utc::now() + lease, state)))
+    }
+
+    async fn fetch_webhook_deliveries(
+        &self,
+        subscription_id: Option<i64>,
+        pagination: &Pagination,
+    ) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        Ok(self.read(|state| state::fetch_webhook_deliveries(subscription_id, pagination, state)))
+    }
+
+    async fn mark_webhook_delivered(&self, id: i64, status: i64) -> Result<WebhookDelivery, WebhookError> {
+        self.transaction(|state| state::mark_webhook_delivered(id, status, state))
+    }
+
+    async fn record_failed_webhook_delivery(
+        &self,
+        id: i64,
+        status: Option<i64>,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<WebhookDelivery, WebhookError> {
+        self.transaction(|state| state::record_failed_webhook_delivery(id, status, error, retry_at, state))
+    }
+
+    async fn replay_webhook_delivery(&self, id: i64) -> Result<WebhookDelivery, WebhookError> {
+        self.transaction(|state| state::replay_webhook_delivery(id, state))
+    }
+}
+
+impl AuditLog for InMemoryDatabase {
+    async fn record_admin_action(&self, action: &NewAdminAction) -> Result<AdminAction, AuditLogError> {
+        self.transaction(|state| Ok(state::insert_admin_action(action.clone(), state)))
+    }
+
+    async fn fetch_admin_actions(
+        &self,
+        filter: &AdminActionFilter,
+        pagination: &Pagination,
+    ) -> Result<Vec<AdminAction>, AuditLogError> {
+        Ok(self.read(|state| state::fetch_admin_actions(filter, pagination, state)))
+    }
+}
+
+impl RateLimitStore for InMemoryDatabase {
+    async fn take_token(&self, key: &str, limit: &RateLimit) -> Result<RateLimitDecision, RateLimitError> {
+        self.transaction(|state| Ok(state::take_rate_limit_token(key, limit, state)))
+    }
+
+    async fn prune_rate_limits(&self) -> Result<u64, RateLimitError> {
+        self.transaction(|state| Ok(state::prune_rate_limits(state)))
+    }
+}
+
+impl InMemoryDatabase {
+    /// Creates a new, empty, in-memory database
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` against a snapshot of the current state. The snapshot replaces the live state only if `f` succeeds,
+    /// so a failure part-way through leaves no trace, just like a rolled-back SQL transaction.
+    fn transaction<T, E, F>(&self, f: F) -> Result<T, E>
+    where F: FnOnce(&mut MemoryState) -> Result<T, E> {
+        let mut live = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut snapshot = live.clone();
+        let result = f(&mut snapshot)?;
+        *live = snapshot;
+        Ok(result)
+    }
+
+    fn read<T, F>(&self, f: F) -> T
+    where F: FnOnce(&MemoryState) -> T {
+        let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        f(&state)
+    }
+
+    /// Each order is paid from the funds that the address holds with the order's merchant. Once a merchant's order
+    /// cannot be paid, none of that merchant's later orders are paid either.
+    fn pay_orders_for_address_with_state(
+        address: &TariAddress,
+        orders: &[&Order],
+        state: &mut MemoryState,
+    ) -> Result<Option<MultiAccountPayment>, PaymentGatewayError> {
+        let mut credit = HashMap::<&str, MicroTari>::new();
+        let mut unpayable = HashSet::<&str>::new();
+        let mut paid_orders = Vec::with_capacity(orders.len());
+        let mut settlements = Vec::with_capacity(orders.len());
+        for &order in orders {
+            if order.quote_has_expired() {
+                debug!(
+                    "🗃️ The price quote for order [{}] has expired. It will be paid once it is re-priced",
+                    order.order_id
+                );
+                continue;
+            }
+            let merchant_id = order.merchant_id.as_str();
+            if unpayable.contains(merchant_id) {
+                continue;
+            }
+            let remaining_credit = match credit.get(merchant_id) {
+                Some(remaining) => *remaining,
+                None => state::fetch_merchant_balance(address, merchant_id, state).current_balance(),
+            };
+            // We must be able to pay for the entire order, or no deal.
+            if order.total_price > remaining_credit {
+                unpayable.insert(merchant_id);
+                continue;
+            }
+            credit.insert(merchant_id, remaining_credit - order.total_price);
+            let settlement = NewSettlementJournalEntry {
+                order_id: order.order_id.clone(),
+                payment_address: SerializedTariAddress::from(address.clone()),
+                amount: order.total_price,
+                settlement_type: SettlementType::Single,
+                merchant_id: order.merchant_id.clone(),
+            };
+            let settlement = state::insert_settlement(settlement, state);
+            state::post_ledger_transaction(&NewLedgerTransaction::settlement(&settlement, &order.merchant_id), state)?;
+            settlements.push(settlement);
+            let updated_order = state::update_order_status(order.id, OrderStatusType::Paid, state)?;
+            state::enqueue_event(EventType::OrderPaid(OrderEvent::new(updated_order.clone())), state);
+            debug!("🗃️ Order {} paid for during multi-account payment", order.id);
+            paid_orders.push(updated_order);
+        }
+        let result = (!paid_orders.is_empty()).then(|| MultiAccountPayment::new(paid_orders, settlements));
+        Ok(result)
+    }
+
+    fn fetch_refund_with_status(
+        id: i64,
+        status: RefundStatus,
+        state: &MemoryState,
+    ) -> Result<Refund, PaymentGatewayError> {
+        let refund = state::fetch_refund(id, state).ok_or(PaymentGatewayError::RefundNotFound(id))?;
+        if refund.status != status {
+            return Err(PaymentGatewayError::InvalidRefund(format!(
+                "Refund {id} has status {} instead of '{status}'",
+                refund.status
+            )));
+        }
+        Ok(refund)
+    }
+
+    fn fetch_approval_with_status(
+        id: i64,
+        status: ApprovalStatus,
+        state: &MemoryState,
+    ) -> Result<ApprovalRequest, PaymentGatewayError> {
+        let request =
+            state::fetch_approval_request(id, state).ok_or(PaymentGatewayError::ApprovalRequestNotFound(id))?;
+        if request.status != status {
+            return Err(PaymentGatewayError::InvalidApproval(format!(
+                "Approval request {id} has status {} instead of '{status}'",
+                request.status
+            )));
+        }
+        Ok(request)
+    }
+
+    /// Checks that `amount` can be refunded to `address`, either from what the address paid towards the order, or
+    /// from its current balance with `merchant_id`.
+    fn check_refund(
+        address: &TariAddress,
+        order_id: Option<&OrderId>,
+        merchant_id: &str,
+        amount: MicroTari,
+        state: &MemoryState,
+    ) -> Result<(), PaymentGatewayError> {
+        if amount <= MicroTari::from(0) {
+            return Err(PaymentGatewayError::InvalidRefund("The refund amount must be positive".into()));
+        }
+        let available = match order_id {
+            Some(order_id) => {
+                let order = state::fetch_order_by_order_id(order_id, state)
+                    .ok_or_else(|| PaymentGatewayError::OrderNotFound(order_id.clone()))?;
+                if order.status != OrderStatusType::Paid {
+                    return Err(PaymentGatewayError::InvalidRefund(format!(
+                        "Order {order_id} has status {} and cannot be refunded",
+                        order.status
+                    )));
+                }
+                state::refundable_for_order(order_id, address, state)
+            },
+            None => state::fetch_merchant_balance(address, merchant_id, state).current_balance(),
+        };
+        if amount > available {
+            return Err(PaymentGatewayError::InvalidRefund(format!(
+                "{amount} exceeds the {available} that can be refunded to {}",
+                address.to_base58()
+            )));
+        }
+        Ok(())
+    }
+
+    fn claim_order_with_state(
+        id: &OrderId,
+        address: &TariAddress,
+        strict_mode: bool,
+        state: &mut MemoryState,
+    ) -> Result<Order, PaymentGatewayError> {
+        let order = state::fetch_order_by_id(id, strict_mode, state)?;
+        let addr58 = address.to_base58();
+        if order.status != OrderStatusType::Unclaimed {
+            warn!(
+                "🖇️️ Order {} is not 'Unclaimed' and {addr58} is trying to claim it. The current status is {}",
+                order.order_id, order.status
+            );
+        }
+        let order = state::update_order_status(order.id, OrderStatusType::New, state)?;
+        state::link_address_to_customer(address, &order.customer_id, state);
+        state::enqueue_event(EventType::OrderClaimed(OrderClaimedEvent::new(order.clone(), address.clone())), state);
+        info!("🗃️ Address {addr58} has been linked with customer id {}", order.customer_id);
+        Ok(order)
+    }
+}
@@ -0,0 +1,17 @@
+//! In-memory database module for the Tari Payment Engine.
+//!
+//! [`InMemoryDatabase`] implements every backend trait in [`crate::traits`] without any external storage. It has two
+//! jobs:
+//! * It is the reference model for the settlement logic. The business logic is a line-for-line port of
+//!   [`crate::SqliteDatabase`], and the SQL tables, views and triggers are replaced by plain Rust in [`mod@state`], so
+//!   it's easy to read what the expected behaviour of a backend is.
+//! * It is a fast fixture for tests that need a working backend but don't care about persistence.
+//!
+//! The shared backend conformance suite (see `test_utils::conformance`) runs against this backend and the SQL
+//! backends, so that they cannot drift apart.
+//!
+//! Nothing is persisted. All data is lost when the last clone of the database is dropped.
+mod memory_impl;
+
+pub mod state;
+pub use memory_impl::InMemoryDatabase;
@@ -0,0 +1,1625 @@
+//! # In-memory "tables"
+//!
+//! [`MemoryState`] holds the equivalent of every table and view in the SQL backends. The free functions in this
+//! module mirror the low-level functions in `sqlite::db` one-for-one, and accept a `&mut MemoryState` in place of a
+//! database connection. Constraints that the SQL backends enforce with triggers and unique indices (strictly
+//! increasing nonces, unique transaction ids, etc.) are enforced here explicitly.
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    time::Instant,
+};
+
+use chrono::{DateTime, Duration, Utc};
+use tari_common_types::tari_address::TariAddress;
+use tpg_common::MicroTari;
+
+use crate::{
+    db_types::{
+        AddressBalance,
+        AdminAction,
+        AdminActionFilter,
+        ApprovalRequest,
+        ApprovalStatus,
+        AuthSession,
+        CreditNote,
+        CustomerOrderBalance,
+        CustomerOrders,
+        EntrySide,
+        HeldOrder,
+        LedgerAccount,
+        LedgerBalance,
+        LedgerEntry,
+        NewAdminAction,
+        NewApprovalRequest,
+        NewAuthSession,
+        NewLedgerTransaction,
+        NewOrder,
+        NewPayment,
+        NewRefund,
+        NewSettlementJournalEntry,
+        NewWebhookSubscription,
+        Order,
+        OrderId,
+        OrderStatusType,
+        OutboxEvent,
+        Payment,
+        PaymentType,
+        Permission,
+        Refund,
+        RefundStatus,
+        Role,
+        RoleDefinition,
+        SerializedTariAddress,
+        SettlementJournalEntry,
+        TransferStatus,
+        WebhookDelivery,
+        WebhookPayload,
+        WebhookSubscription,
+        DEFAULT_MERCHANT,
+    },
+    events::EventType,
+    helpers::create_dummy_address_for_cust_id,
+    order_objects::{ModifyOrderRequest, OrderQueryFilter},
+    tpe_api::{
+        account_objects::Pagination,
+        exchange_objects::ExchangeRate,
+        rate_limit_objects::{RateLimit, RateLimitDecision, TokenBucket},
+    },
+    traits::{
+        AuthApiError,
+        ExchangeRateError,
+        NewWalletInfo,
+        OutboxError,
+        PaymentGatewayError,
+        WalletAuthApiError,
+        WalletInfo,
+        WalletManagementError,
+        WebhookError,
+    },
+};
+
+pub static DEFAULT_ROLES: &[Role] = &[Role::User];
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryState {
+    last_order_id: i64,
+    last_settlement_id: i64,
+    last_outbox_id: i64,
+    last_webhook_id: i64,
+    last_webhook_delivery_id: i64,
+    last_refund_id: i64,
+    last_rate_id: i64,
+    last_held_order_id: i64,
+    last_ledger_transaction_id: i64,
+    last_ledger_entry_id: i64,
+    last_session_id: i64,
+    last_admin_action_id: i64,
+    last_approval_request_id: i64,
+    orders: Vec<Order>,
+    payments: Vec<Payment>,
+    /// (address, customer_id) pairs, in insertion order
+    links: Vec<(String, String)>,
+    settlements: Vec<SettlementJournalEntry>,
+    auth_log: HashMap<String, u64>,
+    role_assignments: HashSet<(String, Role)>,
+    /// (address, merchant_id, role) triples
+    merchant_role_assignments: HashSet<(String, String, Role)>,
+    /// The permissions of custom roles, and of built-in roles that have been edited
+    role_permissions: HashMap<Role, Vec<Permission>>,
+    sessions: Vec<AuthSession>,
+    wallets: Vec<WalletInfo>,
+    exchange_rates: Vec<ExchangeRate>,
+    outbox: Vec<OutboxEvent>,
+    /// The time until which each claimed outbox event is leased
+    outbox_leases: HashMap<i64, DateTime<Utc>>,
+    /// (event_id, handler) pairs for the handlers that have delivered an event
+    outbox_handlers: HashSet<(i64, String)>,
+    webhooks: Vec<WebhookSubscription>,
+    webhook_deliveries: Vec<WebhookDelivery>,
+    /// The time until which each claimed webhook delivery is leased
+    webhook_leases: HashMap<i64, DateTime<Utc>>,
+    refunds: Vec<Refund>,
+    held_orders: Vec<HeldOrder>,
+    ledger: Vec<LedgerEntry>,
+    admin_actions: Vec<AdminAction>,
+    approval_requests: Vec<ApprovalRequest>,
+    rate_limits: HashMap<String, TokenBucket>,
+}
+
+//--------------------------------------        Orders       ---------------------------------------------------------
+
+/// Inserts the order, returning `false` in the second parameter if the order already exists.
+pub fn idempotent_insert_order(order: NewOrder, state: &mut MemoryState) -> (Order, bool) {
+    if let Some(existing) = fetch_order_by_order_id(&order.order_id, state) {
+        return (existing, false);
+    }
+    state.last_order_id += 1;
+    let order = Order {
+        id: state.last_order_id,
+        order_id: order.order_id,
+        alt_id: order.alt_order_id,
+        customer_id: order.customer_id,
+        memo: order.memo,
+        total_price: order.total_price,
+        original_price: order.original_price,
+        currency: order.currency,
+        created_at: order.created_at,
+        updated_at: Utc::now(),
+        status: OrderStatusType::Unclaimed,
+        rate_id: order.rate_id,
+        quote_expires_at: order.quote_expires_at,
+        merchant_id: order.merchant_id,
+    };
+    state.orders.push(order.clone());
+    (order, true)
+}
+
+pub fn fetch_order_by_order_id(order_id: &OrderId, state: &MemoryState) -> Option<Order> {
+    state.orders.iter().find(|o| &o.order_id == order_id).cloned()
+}
+
+pub fn fetch_order_by_alt_id(alt: &OrderId, state: &MemoryState) -> Option<Order> {
+    state.orders.iter().find(|o| o.alt_id.as_ref() == Some(alt)).cloned()
+}
+
+/// If an order_id and alt_id match on different orders, then the one matching the order_id is returned.
+pub fn fetch_order_by_id_or_alt(id: &OrderId, state: &MemoryState) -> Option<Order> {
+    fetch_order_by_order_id(id, state).or_else(|| fetch_order_by_alt_id(id, state))
+}
+
+pub fn fetch_order_by_id(id: &OrderId, strict_mode: bool, state: &MemoryState) -> Result<Order, PaymentGatewayError> {
+    if strict_mode { fetch_order_by_order_id(id, state) } else { fetch_order_by_id_or_alt(id, state) }
+        .ok_or_else(|| PaymentGatewayError::OrderNotFound(id.clone()))
+}
+
+/// Resulting orders are ordered by `created_at` in ascending order
+pub fn search_orders(query: OrderQueryFilter, state: &MemoryState) -> Vec<Order> {
+    let memo = query.memo.map(|m| m.to_lowercase());
+    let statuses = query.status.filter(|s| !s.is_empty());
+    let mut orders = state
+        .orders
+        .iter()
+        .filter(|o| match &memo {
+            Some(m) => o.memo.as_ref().map(|om| om.to_lowercase().contains(m)).unwrap_or(false),
+            None => true,
+        })
+        .filter(|o| query.order_id.as_ref().map(|id| &o.order_id == id).unwrap_or(true))
+        .filter(|o| query.alt_id.as_ref().map(|id| o.alt_id.as_ref() == Some(id)).unwrap_or(true))
+        .filter(|o| query.customer_id.as_ref().map(|c| &o.customer_id == c).unwrap_or(true))
+        .filter(|o| query.currency.as_ref().map(|c| &o.currency == c).unwrap_or(true))
+        .filter(|o| query.merchant_id.as_ref().map(|m| &o.merchant_id == m).unwrap_or(true))
+        .filter(|o| statuses.as_ref().map(|s| s.contains(&o.status)).unwrap_or(true))
+        .filter(|o| query.since.map(|t| o.created_at >= t).unwrap_or(true))
+        .filter(|o| query.until.map(|t| o.created_at <= t).unwrap_or(true))
+        .cloned()
+        .collect::<Vec<_>>();
+    orders.sort_by_key(|o| o.created_at);
+    orders
+}
+
+pub fn update_order_status(
+    id: i64,
+    status: OrderStatusType,
+    state: &mut MemoryState,
+) -> Result<Order, PaymentGatewayError> {
+    let order = state.orders.iter_mut().find(|o| o.id == id).ok_or(PaymentGatewayError::OrderIdNotFound(id))?;
+    order.status = status;
+    order.updated_at = Utc::now();
+    Ok(order.clone())
+}
+
+pub fn update_order(
+    id: &OrderId,
+    update: ModifyOrderRequest,
+    state: &mut MemoryState,
+) -> Result<Option<Order>, PaymentGatewayError> {
+    if update.is_empty() {
+        return Err(PaymentGatewayError::OrderModificationNoOp);
+    }
+    let Some(order) = state.orders.iter_mut().find(|o| &o.order_id == id) else {
+        return Ok(None);
+    };
+    order.updated_at = Utc::now();
+    if let Some(status) = update.new_status {
+        order.status = status;
+    }
+    if let Some(memo) = update.new_memo {
+        order.memo = Some(memo);
+    }
+    if let Some(total_price) = update.new_total_price {
+        order.total_price = total_price;
+    }
+    if let Some(original_price) = update.new_original_price {
+        order.original_price = Some(original_price);
+    }
+    if let Some(currency) = update.new_currency {
+        order.currency = currency;
+    }
+    if let Some(cust_id) = update.new_customer_id {
+        order.customer_id = cust_id;
+    }
+    Ok(Some(order.clone()))
+}
+
+/// Oldest quotes first
+pub fn fetch_orders_with_expired_quotes(state: &MemoryState) -> Vec<Order> {
+    let mut orders = state
+        .orders
+        .iter()
+        .filter(|o| matches!(o.status, OrderStatusType::New | OrderStatusType::Unclaimed) && o.quote_has_expired())
+        .cloned()
+        .collect::<Vec<_>>();
+    orders.sort_by_key(|o| o.quote_expires_at);
+    orders
+}
+
+/// Leaves `updated_at` alone, so that re-pricing an order does not postpone its expiry.
+pub fn update_quote(
+    order_id: &OrderId,
+    total_price: MicroTari,
+    rate_id: i64,
+    quote_expires_at: DateTime<Utc>,
+    state: &mut MemoryState,
+) -> Result<Order, PaymentGatewayError> {
+    let order = state
+        .orders
+        .iter_mut()
+        .find(|o| &o.order_id == order_id)
+        .ok_or_else(|| PaymentGatewayError::OrderNotFound(order_id.clone()))?;
+    order.total_price = total_price;
+    order.rate_id = Some(rate_id);
+    order.quote_expires_at = Some(quote_expires_at);
+    Ok(order.clone())
+}
+
+pub fn expire_orders(status: OrderStatusType, limit: Duration, state: &mut MemoryState) -> Vec<Order> {
+    let now = Utc::now();
+    let limit = limit.num_seconds();
+    state
+        .orders
+        .iter_mut()
+        .filter(|o| o.status == status && (now.timestamp() - o.updated_at.timestamp()) > limit)
+        .map(|o| {
+            o.status = OrderStatusType::Expired;
+            o.updated_at = now;
+            o.clone()
+        })
+        .collect()
+}
+
+/// Orders belonging to any customer id linked to the address
+pub fn orders_for_address(address: &TariAddress, state: &MemoryState) -> Vec<Order> {
+    let customer_ids = customer_ids_for_address(address, state);
+    state.orders.iter().filter(|o| customer_ids.contains(&o.customer_id)).cloned().collect()
+}
+
+/// A payable order is one that is "New" or "Unclaimed" and is associated with the address.
+pub fn fetch_payable_orders_for_address(address: &TariAddress, state: &MemoryState) -> Vec<Order> {
+    orders_for_address(address, state)
+        .into_iter()
+        .filter(|o| matches!(o.status, OrderStatusType::New | OrderStatusType::Unclaimed))
+        .collect()
+}
+
+pub fn fetch_orders_between(from: DateTime<Utc>, to: DateTime<Utc>, state: &MemoryState) -> Vec<Order> {
+    let mut orders =
+        state.orders.iter().filter(|o| o.created_at >= from && o.created_at < to).cloned().collect::<Vec<_>>();
+    orders.sort_by_key(|o| (o.created_at, o.id));
+    orders
+}
+
+//--------------------------------------      Transfers      ---------------------------------------------------------
+
+pub fn idempotent_insert_payment(
+    transfer: NewPayment,
+    state: &mut MemoryState,
+) -> Result<Payment, PaymentGatewayError> {
+    if state.payments.iter().any(|p| p.txid == transfer.txid) {
+        return Err(PaymentGatewayError::PaymentAlreadyExists(transfer.txid));
+    }
+    let now = Utc::now();
+    let payment = Payment {
+        txid: transfer.txid,
+        created_at: now,
+        updated_at: now,
+        sender: transfer.sender,
+        amount: transfer.amount,
+        memo: transfer.memo,
+        payment_type: PaymentType::OnChain,
+        status: TransferStatus::Received,
+        order_id: transfer.order_id,
+        block_height: None,
+        confirmations: 0,
+        merchant_id: transfer.merchant_id,
+    };
+    state.payments.push(payment.clone());
+    Ok(payment)
+}
+
+/// Issues a credit note against the customer id, using a dummy address that is unique to the customer id.
+pub fn credit_note(note: &CreditNote, state: &mut MemoryState) -> Result<Payment, PaymentGatewayError> {
+    let now = Utc::now();
+    let txid = format!("credit_note_{}:{}:{}", note.customer_id, note.amount, now.timestamp());
+    if state.payments.iter().any(|p| p.txid == txid) {
+        return Err(PaymentGatewayError::PaymentAlreadyExists(txid));
+    }
+    let address = create_dummy_address_for_cust_id(&note.customer_id);
+    let memo = format!("Credit note: {}", note.reason.as_deref().unwrap_or("No reason given"));
+    let payment = Payment {
+        txid,
+        created_at: now,
+        updated_at: now,
+        sender: SerializedTariAddress::from(address),
+        amount: note.amount,
+        memo: Some(memo),
+        payment_type: PaymentType::Manual,
+        status: TransferStatus::Confirmed,
+        order_id: None,
+        block_height: None,
+        confirmations: 0,
+        merchant_id: note.merchant_id.clone(),
+    };
+    state.payments.push(payment.clone());
+    Ok(payment)
+}
+
+pub fn update_payment_status(
+    txid: &str,
+    status: TransferStatus,
+    state: &mut MemoryState,
+) -> Result<Payment, PaymentGatewayError> {
+    let payment = state
+        .payments
+        .iter_mut()
+        .find(|p| p.txid == txid)
+        .ok_or(PaymentGatewayError::PaymentStatusUpdateError(format!("Payment for {txid} does not exist")))?;
+    payment.status = status;
+    payment.updated_at = Utc::now();
+    Ok(payment.clone())
+}
+
+pub fn update_payment_confirmations(
+    txid: &str,
+    block_height: Option<i64>,
+    confirmations: i64,
+    state: &mut MemoryState,
+) -> Result<Payment, PaymentGatewayError> {
+    let payment = state
+        .payments
+        .iter_mut()
+        .find(|p| p.txid == txid)
+        .ok_or_else(|| PaymentGatewayError::PaymentNotFound(txid.to_string()))?;
+    payment.block_height = block_height;
+    payment.confirmations = confirmations;
+    payment.updated_at = Utc::now();
+    Ok(payment.clone())
+}
+
+pub fn fetch_payment(txid: &str, state: &MemoryState) -> Option<Payment> {
+    state.payments.iter().find(|p| p.txid == txid).cloned()
+}
+
+pub fn fetch_payments_for_address(address: &TariAddress, state: &MemoryState) -> Vec<Payment> {
+    state.payments.iter().filter(|p| p.sender.as_address() == address).cloned().collect()
+}
+
+pub fn fetch_onchain_payments(since: Option<DateTime<Utc>>, state: &MemoryState) -> Vec<Payment> {
+    let mut payments = state
+        .payments
+        .iter()
+        .filter(|p| p.payment_type == PaymentType::OnChain && since.map_or(true, |t| p.created_at >= t))
+        .cloned()
+        .collect::<Vec<_>>();
+    payments.sort_by_key(|p| p.created_at);
+    payments
+}
+
+pub fn pending_payments(address: &TariAddress, state: &MemoryState) -> Vec<Payment> {
+    let mut payments = fetch_payments_for_address(address, state)
+        .into_iter()
+        .filter(|p| p.status == TransferStatus::Received)
+        .collect::<Vec<_>>();
+    payments.sort_by_key(|p| p.created_at);
+    payments
+}
+
+pub fn fetch_payments_for_order(order_id: &OrderId, state: &MemoryState) -> Vec<Payment> {
+    state.payments.iter().filter(|p| p.order_id.as_ref() == Some(order_id)).cloned().collect()
+}
+
+pub fn fetch_payments_between(from: DateTime<Utc>, to: DateTime<Utc>, state: &MemoryState) -> Vec<Payment> {
+    let mut payments =
+        state.payments.iter().filter(|p| p.created_at >= from && p.created_at < to).cloned().collect::<Vec<_>>();
+    payments.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.txid.cmp(&b.txid)));
+    payments
+}
+
+//--------------------------------------       Accounts      ---------------------------------------------------------
+
+/// Links an address to a customer id. This function is idempotent.
+pub fn link_address_to_customer(address: &TariAddress, customer_id: &str, state: &mut MemoryState) {
+    let address = address.to_base58();
+    if !state.links.iter().any(|(a, c)| a == &address && c == customer_id) {
+        state.links.push((address, customer_id.to_string()));
+    }
+}
+
+/// The equivalent of the `address_balance` view, or of the `merchant_balance` view if `merchant_id` is given. Only
+/// addresses with at least one confirmed payment (to that merchant) have a balance.
+fn address_balance(
+    address: &SerializedTariAddress,
+    merchant_id: Option<&str>,
+    state: &MemoryState,
+) -> Option<AddressBalance> {
+    let for_merchant = |m: &String| merchant_id.map(|id| id == m).unwrap_or(true);
+    let confirmed = state
+        .payments
+        .iter()
+        .filter(|p| &p.sender == address && p.status == TransferStatus::Confirmed && for_merchant(&p.merchant_id))
+        .collect::<Vec<_>>();
+    if confirmed.is_empty() {
+        return None;
+    }
+    let total_confirmed = confirmed.iter().map(|p| p.amount).sum::<MicroTari>();
+    let last_payment = confirmed.iter().map(|p| p.updated_at).max().unwrap_or_else(Utc::now);
+    let settlements = state
+        .settlements
+        .iter()
+        .filter(|s| &s.payment_address == address && for_merchant(&s.merchant_id))
+        .collect::<Vec<_>>();
+    let refunds = state
+        .refunds
+        .iter()
+        .filter(|r| &r.address == address && r.is_debited() && for_merchant(&r.merchant_id))
+        .collect::<Vec<_>>();
+    let total_refunded = refunds.iter().map(|r| r.amount).sum::<MicroTari>();
+    // Refunds against an order hand back part of what was paid for it
+    let refunded_for_orders = refunds.iter().filter(|r| r.order_id.is_some()).map(|r| r.amount).sum::<MicroTari>();
+    let total_paid = settlements.iter().map(|s| s.amount).sum::<MicroTari>() - refunded_for_orders;
+    let last_update = settlements
+        .iter()
+        .map(|s| s.created_at)
+        .chain(refunds.iter().map(|r| r.updated_at))
+        .max()
+        .unwrap_or(last_payment);
+    let address = address.as_address().clone();
+    let balance = AddressBalance::from_totals(address, total_confirmed, total_paid, total_refunded, last_update);
+    Some(match merchant_id {
+        Some(merchant_id) => balance.with_merchant(merchant_id),
+        None => balance,
+    })
+}
+
+fn balances_for_addresses<'a, I: IntoIterator<Item = &'a SerializedTariAddress>>(
+    addresses: I,
+    merchant_id: Option<&str>,
+    state: &MemoryState,
+) -> Vec<AddressBalance> {
+    let mut seen = HashSet::new();
+    let mut balances = addresses
+        .into_iter()
+        .filter(|a| seen.insert((*a).clone()))
+        .filter_map(|a| address_balance(a, merchant_id, state))
+        .collect::<Vec<_>>();
+    balances.sort_by_key(|b| std::cmp::Reverse(b.last_update()));
+    balances
+}
+
+fn customer_addresses(customer_id: &str, state: &MemoryState) -> Vec<SerializedTariAddress> {
+    state
+        .links
+        .iter()
+        .filter(|(_, c)| c == customer_id)
+        .filter_map(|(a, _)| SerializedTariAddress::from_str(a).ok())
+        .collect()
+}
+
+pub fn balances_for_customer_id(customer_id: &str, state: &MemoryState) -> Vec<AddressBalance> {
+    balances_for_addresses(customer_addresses(customer_id, state).iter(), None, state)
+}
+
+pub fn merchant_balances_for_customer_id(
+    customer_id: &str,
+    merchant_id: &str,
+    state: &MemoryState,
+) -> Vec<AddressBalance> {
+    balances_for_addresses(customer_addresses(customer_id, state).iter(), Some(merchant_id), state)
+}
+
+pub fn merchant_balances_for_order_id(
+    order_id: &OrderId,
+    alt_id: Option<&OrderId>,
+    merchant_id: &str,
+    state: &MemoryState,
+) -> Vec<AddressBalance> {
+    let senders = state
+        .payments
+        .iter()
+        .filter(|p| p.order_id.as_ref().map(|id| id == order_id || Some(id) == alt_id).unwrap_or(false))
+        .map(|p| &p.sender);
+    balances_for_addresses(senders, Some(merchant_id), state)
+}
+
+pub fn fetch_address_balance(address: &TariAddress, state: &MemoryState) -> AddressBalance {
+    address_balance(&SerializedTariAddress::from(address), None, state)
+        .unwrap_or_else(|| AddressBalance::new(address.clone()))
+}
+
+pub fn fetch_merchant_balance(address: &TariAddress, merchant_id: &str, state: &MemoryState) -> AddressBalance {
+    address_balance(&SerializedTariAddress::from(address), Some(merchant_id), state)
+        .unwrap_or_else(|| AddressBalance::for_merchant(address.clone(), merchant_id))
+}
+
+pub fn fetch_idle_balances(idle: Duration, state: &MemoryState) -> Vec<AddressBalance> {
+    let now = Utc::now();
+    let mut balances = state
+        .payments
+        .iter()
+        .map(|p| (&p.sender, p.merchant_id.as_str()))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .filter_map(|(a, m)| address_balance(a, Some(m), state))
+        .filter(|b| b.current_balance() > MicroTari::from(0) && now - b.last_update() >= idle)
+        .collect::<Vec<_>>();
+    balances.sort_by_key(|b| b.last_update());
+    balances
+}
+
+pub fn insert_settlement(settlement: NewSettlementJournalEntry, state: &mut MemoryState) -> SettlementJournalEntry {
+    state.last_settlement_id += 1;
+    let entry = SettlementJournalEntry {
+        id: state.last_settlement_id,
+        created_at: Utc::now(),
+        order_id: settlement.order_id,
+        payment_address: settlement.payment_address,
+        settlement_type: settlement.settlement_type,
+        amount: settlement.amount,
+        merchant_id: settlement.merchant_id,
+    };
+    state.settlements.push(entry.clone());
+    entry
+}
+
+pub fn settlements_for_address(address: &TariAddress, state: &MemoryState) -> Vec<SettlementJournalEntry> {
+    state.settlements.iter().filter(|s| s.payment_address.as_address() == address).cloned().collect()
+}
+
+pub fn settlements_for_order(order_id: &OrderId, state: &MemoryState) -> Vec<SettlementJournalEntry> {
+    state.settlements.iter().filter(|s| &s.order_id == order_id).cloned().collect()
+}
+
+pub fn settlements_for_customer_id(customer_id: &str, state: &MemoryState) -> Vec<SettlementJournalEntry> {
+    let paid_orders = state
+        .orders
+        .iter()
+        .filter(|o| o.customer_id == customer_id && o.status == OrderStatusType::Paid)
+        .map(|o| &o.order_id)
+        .collect::<Vec<_>>();
+    state.settlements.iter().filter(|s| paid_orders.contains(&&s.order_id)).cloned().collect()
+}
+
+pub fn settlements_between(from: DateTime<Utc>, to: DateTime<Utc>, state: &MemoryState) -> Vec<SettlementJournalEntry> {
+    state.settlements.iter().filter(|s| s.created_at >= from && s.created_at < to).cloned().collect()
+}
+
+/// The equivalent of the `customer_order_balance` view
+fn customer_orders(state: &MemoryState) -> Vec<CustomerOrders> {
+    let mut result: Vec<CustomerOrders> = Vec::new();
+    for order in &state.orders {
+        match result.iter_mut().find(|c| c.customer_id == order.customer_id && c.status == order.status) {
+            Some(entry) => entry.total_orders = entry.total_orders + order.total_price,
+            None => result.push(CustomerOrders {
+                customer_id: order.customer_id.clone(),
+                status: order.status,
+                total_orders: order.total_price,
+            }),
+        }
+    }
+    result
+}
+
+pub fn creditors(state: &MemoryState) -> Vec<CustomerOrders> {
+    customer_orders(state)
+        .into_iter()
+        .filter(|c| c.status == OrderStatusType::New && c.total_orders > MicroTari::from(0))
+        .collect()
+}
+
+pub fn customer_order_balance(customer_id: &str, state: &MemoryState) -> CustomerOrderBalance {
+    let orders = customer_orders(state).into_iter().filter(|c| c.customer_id == customer_id).collect::<Vec<_>>();
+    CustomerOrderBalance::new(&orders)
+}
+
+pub fn customer_ids(pagination: &Pagination, state: &MemoryState) -> Vec<String> {
+    let mut ids = state.orders.iter().map(|o| o.customer_id.clone()).collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+    paginate(ids, pagination)
+}
+
+pub fn addresses(pagination: &Pagination, state: &MemoryState) -> Vec<TariAddress> {
+    let mut senders = state.payments.iter().map(|p| p.sender.as_base58()).collect::<Vec<_>>();
+    senders.sort();
+    senders.dedup();
+    paginate(senders, pagination).into_iter().filter_map(|a| TariAddress::from_base58(&a).ok()).collect()
+}
+
+pub fn customer_ids_for_address(address: &TariAddress, state: &MemoryState) -> Vec<String> {
+    let address = address.to_base58();
+    state.links.iter().filter(|(a, _)| a == &address).map(|(_, c)| c.clone()).collect()
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn paginate<T>(items: Vec<T>, pagination: &Pagination) -> Vec<T> {
+    match pagination.count {
+        Some(count) => {
+            let offset = pagination.offset.unwrap_or(0).max(0) as usize;
+            items.into_iter().skip(offset).take(count.max(0) as usize).collect()
+        },
+        None => items,
+    }
+}
+
+//--------------------------------------         Auth        ---------------------------------------------------------
+
+pub fn auth_account_exists(address: &TariAddress, state: &MemoryState) -> bool {
+    state.auth_log.contains_key(&address.to_base58())
+}
+
+pub fn roles_for_address(address: &TariAddress, state: &MemoryState) -> HashSet<Role> {
+    let address = address.to_base58();
+    let mut roles =
+        state.role_assignments.iter().filter(|(a, _)| a == &address).map(|(_, r)| r.clone()).collect::<HashSet<_>>();
+    roles.extend(DEFAULT_ROLES.iter().cloned());
+    roles
+}
+
+pub fn address_has_roles(address: &TariAddress, roles: &[Role], state: &MemoryState) -> Result<(), AuthApiError> {
+    let assigned = roles_for_address(address, state);
+    let missing = roles.iter().filter(|r| !assigned.contains(r)).count();
+    if missing == 0 {
+        Ok(())
+    } else {
+        Err(AuthApiError::RoleNotAllowed(missing))
+    }
+}
+
+/// Nonces must strictly increase, in the same way the `auth_log_update_nonce` trigger enforces this in SQL.
+pub fn upsert_nonce_for_address(
+    address: &TariAddress,
+    nonce: u64,
+    state: &mut MemoryState,
+) -> Result<(), AuthApiError> {
+    let address = address.to_base58();
+    match state.auth_log.get(&address) {
+        Some(&last_nonce) if nonce <= last_nonce => Err(AuthApiError::InvalidNonce),
+        _ => {
+            state.auth_log.insert(address, nonce);
+            Ok(())
+        },
+    }
+}
+
+pub fn assign_roles(address: &TariAddress, roles: &[Role], state: &mut MemoryState) -> Result<(), AuthApiError> {
+    check_roles_exist(roles, state)?;
+    let address = address.to_base58();
+    if roles.iter().any(|r| state.role_assignments.contains(&(address.clone(), r.clone()))) {
+        return Err(AuthApiError::DatabaseError(format!("One or more of the roles are already assigned to {address}")));
+    }
+    roles.iter().for_each(|r| {
+        state.role_assignments.insert((address.clone(), r.clone()));
+    });
+    Ok(())
+}
+
+pub fn remove_roles(address: &TariAddress, roles: &[Role], state: &mut MemoryState) -> u64 {
+    let address = address.to_base58();
+    roles.iter().filter(|r| state.role_assignments.remove(&(address.clone(), (*r).clone()))).count() as u64
+}
+
+pub fn merchant_roles_for_address(address: &TariAddress, merchant_id: &str, state: &MemoryState) -> HashSet<Role> {
+    let address = address.to_base58();
+    state
+        .merchant_role_assignments
+        .iter()
+        .filter(|(a, m, _)| a == &address && m == merchant_id)
+        .map(|(_, _, r)| r.clone())
+        .collect()
+}
+
+pub fn assign_merchant_roles(
+    address: &TariAddress,
+    merchant_id: &str,
+    roles: &[Role],
+    state: &mut MemoryState,
+) -> Result<(), AuthApiError> {
+    check_roles_exist(roles, state)?;
+    let address = address.to_base58();
+    roles.iter().for_each(|r| {
+        state.merchant_role_assignments.insert((address.clone(), merchant_id.to_string(), r.clone()));
+    });
+    Ok(())
+}
+
+pub fn remove_merchant_roles(address: &TariAddress, merchant_id: &str, roles: &[Role], state: &mut MemoryState) -> u64 {
+    let address = address.to_base58();
+    roles
+        .iter()
+        .filter(|r| state.merchant_role_assignments.remove(&(address.clone(), merchant_id.to_string(), (*r).clone())))
+        .count() as u64
+}
+
+/// Custom roles must be defined before they can be assigned, in the same way the `roles` table enforces this in SQL.
+fn check_roles_exist(roles: &[Role], state: &MemoryState) -> Result<(), AuthApiError> {
+    if roles.iter().all(|r| r.is_built_in() || state.role_permissions.contains_key(r)) {
+        Ok(())
+    } else {
+        Err(AuthApiError::RoleNotFound)
+    }
+}
+
+pub fn role_definitions(state: &MemoryState) -> Vec<RoleDefinition> {
+    let mut roles = RoleDefinition::built_in()
+        .into_iter()
+        .filter(|d| !state.role_permissions.contains_key(&d.role))
+        .chain(state.role_permissions.iter().map(|(r, p)| RoleDefinition::new(r.clone(), p)))
+        .collect::<Vec<_>>();
+    roles.sort_by_key(|d| d.role.to_string());
+    roles
+}
+
+pub fn save_role(role: &RoleDefinition, state: &mut MemoryState) -> Result<(), AuthApiError> {
+    if role.role == Role::SuperAdmin {
+        return Err(AuthApiError::BuiltInRole(role.role.clone()));
+    }
+    state.role_permissions.insert(role.role.clone(), role.permissions.clone());
+    Ok(())
+}
+
+pub fn delete_role(role: &Role, state: &mut MemoryState) -> Result<(), AuthApiError> {
+    if role.is_built_in() {
+        return Err(AuthApiError::BuiltInRole(role.clone()));
+    }
+    state.role_permissions.remove(role).ok_or(AuthApiError::RoleNotFound)?;
+    state.role_assignments.retain(|(_, r)| r != role);
+    state.merchant_role_assignments.retain(|(_, _, r)| r != role);
+    Ok(())
+}
+
+//--------------------------------------       Sessions      ---------------------------------------------------------
+
+pub fn insert_session(session: NewAuthSession, state: &mut MemoryState) -> AuthSession {
+    state.last_session_id += 1;
+    let now = Utc::now();
+    let session = AuthSession {
+        id: state.last_session_id,
+        address: session.address.into(),
+        roles: session.roles,
+        merchant: session.merchant,
+        refresh_token_hash: session.refresh_token_hash,
+        created_at: now,
+        refreshed_at: now,
+        expires_at: session.expires_at,
+        revoked_at: None,
+    };
+    state.sessions.push(session.clone());
+    session
+}
+
+pub fn fetch_session(id: i64, state: &MemoryState) -> Option<AuthSession> {
+    state.sessions.iter().find(|s| s.id == id).cloned()
+}
+
+/// Refresh token hashes are unique, and a used refresh token no longer matches, as with the SQL backends.
+pub fn refresh_session(
+    refresh_token_hash: &str,
+    new_refresh_token_hash: &str,
+    state: &mut MemoryState,
+) -> Result<AuthSession, AuthApiError> {
+    if state.sessions.iter().any(|s| s.refresh_token_hash == new_refresh_token_hash) {
+        return Err(AuthApiError::DatabaseError("The refresh token is already in use".to_string()));
+    }
+    let session = state
+        .sessions
+        .iter_mut()
+        .find(|s| s.refresh_token_hash == refresh_token_hash && s.is_active())
+        .ok_or(AuthApiError::InvalidRefreshToken)?;
+    session.refresh_token_hash = new_refresh_token_hash.to_string();
+    session.refreshed_at = Utc::now();
+    Ok(session.clone())
+}
+
+pub fn sessions_for_address(address: &TariAddress, state: &MemoryState) -> Vec<AuthSession> {
+    let mut sessions = state
+        .sessions
+        .iter()
+        .filter(|s| s.address.as_address() == address && s.is_active())
+        .cloned()
+        .collect::<Vec<_>>();
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.id));
+    sessions
+}
+
+pub fn revoke_session(id: i64, state: &mut MemoryState) -> Result<AuthSession, AuthApiError> {
+    let session = state.sessions.iter_mut().find(|s| s.id == id).ok_or(AuthApiError::SessionNotFound(id))?;
+    if session.revoked_at.is_none() {
+        session.revoked_at = Some(Utc::now());
+    }
+    Ok(session.clone())
+}
+
+pub fn revoke_sessions_for_address(address: &TariAddress, state: &mut MemoryState) -> Vec<i64> {
+    let now = Utc::now();
+    state
+        .sessions
+        .iter_mut()
+        .filter(|s| s.address.as_address() == address && s.is_active())
+        .map(|s| {
+            s.revoked_at = Some(now);
+            s.id
+        })
+        .collect()
+}
+
+pub fn revoked_sessions(since: DateTime<Utc>, state: &MemoryState) -> Vec<i64> {
+    state.sessions.iter().filter(|s| s.revoked_at.is_some_and(|t| t >= since)).map(|s| s.id).collect()
+}
+
+//--------------------------------------      Wallet auth    ---------------------------------------------------------
+
+pub fn fetch_wallet_info_for_address(
+    address: &TariAddress,
+    state: &MemoryState,
+) -> Result<WalletInfo, WalletAuthApiError> {
+    state.wallets.iter().find(|w| w.address.as_address() == address).cloned().ok_or(WalletAuthApiError::WalletNotFound)
+}
+
+/// Nonces must strictly increase, in the same way the `wallet_auth_update_nonce` trigger enforces this in SQL.
+pub fn update_wallet_nonce(
+    address: &TariAddress,
+    new_nonce: i64,
+    state: &mut MemoryState,
+) -> Result<(), WalletAuthApiError> {
+    let wallet = state
+        .wallets
+        .iter_mut()
+        .find(|w| w.address.as_address() == address)
+        .ok_or(WalletAuthApiError::WalletNotFound)?;
+    if new_nonce <= wallet.last_nonce {
+        return Err(WalletAuthApiError::InvalidNonce);
+    }
+    wallet.last_nonce = new_nonce;
+    Ok(())
+}
+
+pub fn register_wallet(info: NewWalletInfo, state: &mut MemoryState) -> Result<(), WalletManagementError> {
+    if state.wallets.iter().any(|w| w.address == info.address) {
+        return Err(WalletManagementError::DatabaseError(format!("Wallet {} is already registered", info.address)));
+    }
+    let wallet = WalletInfo {
+        address: info.address,
+        ip_address: info.ip_address,
+        last_nonce: info.initial_nonce.unwrap_or(0),
+        merchant_id: info.merchant_id,
+    };
+    state.wallets.push(wallet);
+    Ok(())
+}
+
+pub fn deregister_wallet(address: &TariAddress, state: &mut MemoryState) -> Result<(), WalletManagementError> {
+    let n = state.wallets.len();
+    state.wallets.retain(|w| w.address.as_address() != address);
+    if state.wallets.len() == n {
+        return Err(WalletManagementError::DatabaseError("Wallet not found".to_string()));
+    }
+    Ok(())
+}
+
+pub fn fetch_authorized_wallets(state: &MemoryState) -> Vec<WalletInfo> {
+    state.wallets.clone()
+}
+
+//--------------------------------------    Exchange rates   ---------------------------------------------------------
+
+pub fn fetch_last_rate(currency: &str, state: &MemoryState) -> Result<ExchangeRate, ExchangeRateError> {
+    fetch_merchant_rate(DEFAULT_MERCHANT, currency, state)
+}
+
+pub fn fetch_merchant_rate(
+    merchant_id: &str,
+    currency: &str,
+    state: &MemoryState,
+) -> Result<ExchangeRate, ExchangeRateError> {
+    let last_rate = |merchant_id: &str| {
+        state.exchange_rates.iter().rev().find(|r| r.base_currency == currency && r.merchant_id == merchant_id)
+    };
+    last_rate(merchant_id)
+        .or_else(|| last_rate(DEFAULT_MERCHANT))
+        .cloned()
+        .ok_or_else(|| ExchangeRateError::RateDoesNotExist(currency.to_string()))
+}
+
+pub fn fetch_rate_by_id(id: i64, state: &MemoryState) -> Result<ExchangeRate, ExchangeRateError> {
+    state
+        .exchange_rates
+        .iter()
+        .find(|r| r.id == id)
+        .cloned()
+        .ok_or_else(|| ExchangeRateError::RateDoesNotExist(format!("#{id}")))
+}
+
+pub fn fetch_rate_at(
+    currency: &str,
+    timestamp: DateTime<Utc>,
+    state: &MemoryState,
+) -> Result<ExchangeRate, ExchangeRateError> {
+    state
+        .exchange_rates
+        .iter()
+        .rev()
+        .find(|r| r.base_currency == currency && r.merchant_id == DEFAULT_MERCHANT && r.updated_at <= timestamp)
+        .cloned()
+        .ok_or_else(|| ExchangeRateError::RateDoesNotExist(format!("{currency} at {timestamp}")))
+}
+
+pub fn fetch_previous_rate(rate: &ExchangeRate, state: &MemoryState) -> Option<ExchangeRate> {
+    state
+        .exchange_rates
+        .iter()
+        .rev()
+        .find(|r| r.base_currency == rate.base_currency && r.merchant_id == rate.merchant_id && r.id < rate.id)
+        .cloned()
+}
+
+pub fn fetch_rate_history(
+    merchant_id: &str,
+    currency: &str,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    pagination: &Pagination,
+    state: &MemoryState,
+) -> Vec<ExchangeRate> {
+    let offset = pagination.offset.and_then(|o| usize::try_from(o).ok()).unwrap_or(0);
+    let count = pagination.count.and_then(|c| usize::try_from(c).ok()).unwrap_or(usize::MAX);
+    state
+        .exchange_rates
+        .iter()
+        .filter(|r| r.base_currency == currency && r.merchant_id == merchant_id)
+        .filter(|r| since.map_or(true, |t| r.updated_at >= t) && until.map_or(true, |t| r.updated_at <= t))
+        .skip(offset)
+        .take(count)
+        .cloned()
+        .collect()
+}
+
+/// The `updated_at` field of the exchange rate is ignored and set to the current time.
+pub fn set_exchange_rate(rate: &ExchangeRate, state: &mut MemoryState) {
+    state.last_rate_id += 1;
+    let mut rate = ExchangeRate::new(rate.base_currency.clone(), rate.rate, None).for_merchant(&rate.merchant_id);
+    rate.id = state.last_rate_id;
+    state.exchange_rates.push(rate);
+}
+
+pub fn confirm_exchange_rate(id: i64, state: &mut MemoryState) -> Result<(), ExchangeRateError> {
+    let rate = state
+        .exchange_rates
+        .iter_mut()
+        .find(|r| r.id == id)
+        .ok_or_else(|| ExchangeRateError::RateDoesNotExist(format!("#{id}")))?;
+    rate.confirmed_at = Utc::now();
+    Ok(())
+}
+
+//--------------------------------------     Event outbox    ---------------------------------------------------------
+
+pub fn enqueue_event(event: EventType, state: &mut MemoryState) -> OutboxEvent {
+    state.last_outbox_id += 1;
+    let now = Utc::now();
+    let event = OutboxEvent {
+        id: state.last_outbox_id,
+        event,
+        created_at: now,
+        attempts: 0,
+        next_attempt_at: Some(now),
+        last_error: None,
+        delivered_at: None,
+    };
+    state.outbox.push(event.clone());
+    event
+}
+
+pub fn fetch_outbox_event(id: i64, state: &MemoryState) -> Option<OutboxEvent> {
+    state.outbox.iter().find(|e| e.id == id).cloned()
+}
+
+fn is_due(event: &OutboxEvent, now: DateTime<Utc>, state: &MemoryState) -> bool {
+    event.delivered_at.is_none() &&
+        event.next_attempt_at.map(|t| t <= now).unwrap_or(false) &&
+        state.outbox_leases.get(&event.id).map(|t| *t <= now).unwrap_or(true)
+}
+
+pub fn fetch_due_events(limit: i64, state: &MemoryState) -> Vec<OutboxEvent> {
+    let now = Utc::now();
+    let limit = usize::try_from(limit).unwrap_or(0);
+    state.outbox.iter().filter(|e| is_due(e, now, state)).take(limit).cloned().collect()
+}
+
+pub fn claim_due_events(limit: i64, locked_until: DateTime<Utc>, state: &mut MemoryState) -> Vec<OutboxEvent> {
+    let events = fetch_due_events(limit, state);
+    for event in &events {
+        state.outbox_leases.insert(event.id, locked_until);
+    }
+    events
+}
+
+pub fn fetch_delivered_handlers(id: i64, state: &MemoryState) -> Vec<String> {
+    state.outbox_handlers.iter().filter(|(event_id, _)| *event_id == id).map(|(_, h)| h.clone()).collect()
+}
+
+pub fn mark_handler_delivered(id: i64, handler: &str, state: &mut MemoryState) {
+    state.outbox_handlers.insert((id, handler.to_string()));
+}
+
+pub fn fetch_undelivered_events(pagination: &Pagination, state: &MemoryState) -> Vec<OutboxEvent> {
+    let offset = pagination.offset.and_then(|o| usize::try_from(o).ok()).unwrap_or(0);
+    let count = pagination.count.and_then(|c| usize::try_from(c).ok()).unwrap_or(usize::MAX);
+    state.outbox.iter().filter(|e| e.delivered_at.is_none()).skip(offset).take(count).cloned().collect()
+}
+
+fn update_outbox_event<F: FnOnce(&mut OutboxEvent)>(
+    id: i64,
+    state: &mut MemoryState,
+    f: F,
+) -> Result<OutboxEvent, OutboxError> {
+    let event = state.outbox.iter_mut().find(|e| e.id == id).ok_or(OutboxError::EventNotFound(id))?;
+    f(event);
+    state.outbox_leases.remove(&id);
+    Ok(event.clone())
+}
+
+pub fn mark_event_delivered(id: i64, state: &mut MemoryState) -> Result<OutboxEvent, OutboxError> {
+    update_outbox_event(id, state, |e| {
+        e.attempts += 1;
+        e.delivered_at = Some(Utc::now());
+        e.next_attempt_at = None;
+        e.last_error = None;
+    })
+}
+
+pub fn record_failed_delivery(
+    id: i64,
+    error: &str,
+    retry_at: Option<DateTime<Utc>>,
+    state: &mut MemoryState,
+) -> Result<OutboxEvent, OutboxError> {
+    update_outbox_event(id, state, |e| {
+        e.attempts += 1;
+        e.last_error = Some(error.to_string());
+        e.next_attempt_at = retry_at;
+    })
+}
+
+pub fn replay_event(id: i64, state: &mut MemoryState) -> Result<OutboxEvent, OutboxError> {
+    if fetch_outbox_event(id, state).map(|e| e.is_delivered()).unwrap_or(false) {
+        state.outbox_handlers.retain(|(event_id, _)| *event_id != id);
+    }
+    update_outbox_event(id, state, |e| {
+        e.next_attempt_at = Some(Utc::now());
+        e.delivered_at = None;
+    })
+}
+
+//--------------------------------------       Webhooks        ---------------------------------------------------------
+
+pub fn insert_webhook(
+    webhook: &NewWebhookSubscription,
+    state: &mut MemoryState,
+) -> Result<WebhookSubscription, WebhookError> {
+    if state.webhooks.iter().any(|w| w.url == webhook.url && w.event_type == webhook.event_type) {
+        return Err(WebhookError::InvalidSubscription("The URL is already subscribed to this event type".to_string()));
+    }
+    state.last_webhook_id += 1;
+    let now = Utc::now();
+    let subscription = WebhookSubscription {
+        id: state.last_webhook_id,
+        url: webhook.url.clone(),
+        event_type: webhook.event_type.clone(),
+        secret: webhook.secret.clone(),
+        active: true,
+        created_at: now,
+        updated_at: now,
+    };
+    state.webhooks.push(subscription.clone());
+    Ok(subscription)
+}
+
+pub fn fetch_webhook(id: i64, state: &MemoryState) -> Option<WebhookSubscription> {
+    state.webhooks.iter().find(|w| w.id == id).cloned()
+}
+
+pub fn fetch_webhooks(state: &MemoryState) -> Vec<WebhookSubscription> {
+    state.webhooks.clone()
+}
+
+pub fn set_webhook_active(id: i64, active: bool, state: &mut MemoryState) -> Result<WebhookSubscription, WebhookError> {
+    let webhook = state.webhooks.iter_mut().find(|w| w.id == id).ok_or(WebhookError::SubscriptionNotFound(id))?;
+    webhook.active = active;
+    webhook.updated_at = Utc::now();
+    Ok(webhook.clone())
+}
+
+pub fn delete_webhook(id: i64, state: &mut MemoryState) -> Result<(), WebhookError> {
+    if !state.webhooks.iter().any(|w| w.id == id) {
+        return Err(WebhookError::SubscriptionNotFound(id));
+    }
+    state.webhooks.retain(|w| w.id != id);
+    state.webhook_deliveries.retain(|d| d.subscription_id != id);
+    Ok(())
+}
+
+pub fn queue_webhook_deliveries(
+    event: &OutboxEvent,
+    state: &mut MemoryState,
+) -> Result<Vec<WebhookDelivery>, WebhookError> {
+    let payload = serde_json::to_string(&WebhookPayload::from(event))?;
+    let subscribers = state
+        .webhooks
+        .iter()
+        .filter(|w| w.active && w.event_type == event.event_type())
+        .map(|w| w.id)
+        .filter(|id| !state.webhook_deliveries.iter().any(|d| d.subscription_id == *id && d.event_id == event.id))
+        .collect::<Vec<i64>>();
+    let now = Utc::now();
+    let mut result = Vec::with_capacity(subscribers.len());
+    for subscription_id in subscribers {
+        state.last_webhook_delivery_id += 1;
+        let delivery = WebhookDelivery {
+            id: state.last_webhook_delivery_id,
+            subscription_id,
+            event_id: event.id,
+            event_type: event.event_type().to_string(),
+            payload: payload.clone(),
+            created_at: now,
+            attempts: 0,
+            next_attempt_at: Some(now),
+            last_status: None,
+            last_error: None,
+            delivered_at: None,
+        };
+        state.webhook_deliveries.push(delivery.clone());
+        result.push(delivery);
+    }
+    Ok(result)
+}
+
+pub fn fetch_due_webhook_deliveries(limit: i64, state: &MemoryState) -> Vec<WebhookDelivery> {
+    let now = Utc::now();
+    let limit = usize::try_from(limit).unwrap_or(0);
+    let active = state.webhooks.iter().filter(|w| w.active).map(|w| w.id).collect::<HashSet<i64>>();
+    state
+        .webhook_deliveries
+        .iter()
+        .filter(|d| active.contains(&d.subscription_id))
+        .filter(|d| d.delivered_at.is_none() && d.next_attempt_at.map(|t| t <= now).unwrap_or(false))
+        .filter(|d| state.webhook_leases.get(&d.id).map(|t| *t <= now).unwrap_or(true))
+        .take(limit)
+        .cloned()
+        .collect()
+}
+
+pub fn claim_due_webhook_deliveries(
+    limit: i64,
+    locked_until: DateTime<Utc>,
+    state: &mut MemoryState,
+) -> Vec<WebhookDelivery> {
+    let deliveries = fetch_due_webhook_deliveries(limit, state);
+    for delivery in &deliveries {
+        state.webhook_leases.insert(delivery.id, locked_until);
+    }
+    deliveries
+}
+
+pub fn fetch_webhook_deliveries(
+    subscription_id: Option<i64>,
+    pagination: &Pagination,
+    state: &MemoryState,
+) -> Vec<WebhookDelivery> {
+    let offset = pagination.offset.and_then(|o| usize::try_from(o).ok()).unwrap_or(0);
+    let count = pagination.count.and_then(|c| usize::try_from(c).ok()).unwrap_or(usize::MAX);
+    state
+        .webhook_deliveries
+        .iter()
+        .rev()
+        .filter(|d| subscription_id.map(|id| d.subscription_id == id).unwrap_or(true))
+        .skip(offset)
+        .take(count)
+        .cloned()
+        .collect()
+}
+
+fn update_webhook_delivery<F: FnOnce(&mut WebhookDelivery)>(
+    id: i64,
+    state: &mut MemoryState,
+    f: F,
+) -> Result<WebhookDelivery, WebhookError> {
+    let delivery =
+        state.webhook_deliveries.iter_mut().find(|d| d.id == id).ok_or(WebhookError::DeliveryNotFound(id))?;
+    f(delivery);
+    state.webhook_leases.remove(&id);
+    Ok(delivery.clone())
+}
+
+pub fn mark_webhook_delivered(id: i64, status: i64, state: &mut MemoryState) -> Result<WebhookDelivery, WebhookError> {
+    update_webhook_delivery(id, state, |d| {
+        d.attempts += 1;
+        d.delivered_at = Some(Utc::now());
+        d.next_attempt_at = None;
+        d.last_status = Some(status);
+        d.last_error = None;
+    })
+}
+
+pub fn record_failed_webhook_delivery(
+    id: i64,
+    status: Option<i64>,
+    error: &str,
+    retry_at: Option<DateTime<Utc>>,
+    state: &mut MemoryState,
+) -> Result<WebhookDelivery, WebhookError> {
+    update_webhook_delivery(id, state, |d| {
+        d.attempts += 1;
+        d.last_status = status;
+        d.last_error = Some(error.to_string());
+        d.next_attempt_at = retry_at;
+    })
+}
+
+pub fn replay_webhook_delivery(id: i64, state: &mut MemoryState) -> Result<WebhookDelivery, WebhookError> {
+    update_webhook_delivery(id, state, |d| {
+        d.next_attempt_at = Some(Utc::now());
+        d.delivered_at = None;
+    })
+}
+
+//--------------------------------------        Refunds        -------------------------------------------------------
+
+/// Inserts the refund. A refund for an order always belongs to the order's merchant.
+pub fn insert_refund(refund: NewRefund, state: &mut MemoryState) -> Refund {
+    state.last_refund_id += 1;
+    let now = Utc::now();
+    let merchant_id = refund
+        .order_id
+        .as_ref()
+        .and_then(|id| fetch_order_by_order_id(id, state))
+        .map(|o| o.merchant_id)
+        .unwrap_or(refund.merchant_id);
+    let refund = Refund {
+        id: state.last_refund_id,
+        created_at: now,
+        updated_at: now,
+        address: refund.address,
+        order_id: refund.order_id,
+        amount: refund.amount,
+        reason: refund.reason,
+        status: RefundStatus::Requested,
+        payout_txid: None,
+        merchant_id,
+    };
+    state.refunds.push(refund.clone());
+    refund
+}
+
+pub fn fetch_refund(id: i64, state: &MemoryState) -> Option<Refund> {
+    state.refunds.iter().find(|r| r.id == id).cloned()
+}
+
+pub fn fetch_refunds(status: Option<RefundStatus>, pagination: &Pagination, state: &MemoryState) -> Vec<Refund> {
+    let offset = pagination.offset.and_then(|o| usize::try_from(o).ok()).unwrap_or(0);
+    let count = pagination.count.and_then(|c| usize::try_from(c).ok()).unwrap_or(usize::MAX);
+    state
+        .refunds
+        .iter()
+        .rev()
+        .filter(|r| status.map(|s| r.status == s).unwrap_or(true))
+        .skip(offset)
+        .take(count)
+        .cloned()
+        .collect()
+}
+
+/// Sets the status of the refund. If `reason` or `payout_txid` are given, they replace the current values.
+pub fn update_refund(
+    id: i64,
+    status: RefundStatus,
+    reason: Option<&str>,
+    payout_txid: Option<&str>,
+    state: &mut MemoryState,
+) -> Result<Refund, PaymentGatewayError> {
+    let refund = state.refunds.iter_mut().find(|r| r.id == id).ok_or(PaymentGatewayError::RefundNotFound(id))?;
+    refund.status = status;
+    if let Some(reason) = reason {
+        refund.reason = reason.to_string();
+    }
+    if let Some(txid) = payout_txid {
+        refund.payout_txid = Some(txid.to_string());
+    }
+    refund.updated_at = Utc::now();
+    Ok(refund.clone())
+}
+
+/// The amount that `address` paid towards the order, less the refunds against the order that have already been
+/// debited from the address.
+pub fn refundable_for_order(order_id: &OrderId, address: &TariAddress, state: &MemoryState) -> MicroTari {
+    let paid = state
+        .settlements
+        .iter()
+        .filter(|s| &s.order_id == order_id && s.payment_address.as_address() == address)
+        .map(|s| s.amount)
+        .sum::<MicroTari>();
+    let refunded = state
+        .refunds
+        .iter()
+        .filter(|r| r.order_id.as_ref() == Some(order_id) && r.address.as_address() == address && r.is_debited())
+        .map(|r| r.amount)
+        .sum::<MicroTari>();
+    paid - refunded
+}
+
+//--------------------------------------     Held orders     ---------------------------------------------------------
+
+pub fn insert_held_order(order: &NewOrder, reason: &str, state: &mut MemoryState) -> HeldOrder {
+    state.last_held_order_id += 1;
+    let held_order = HeldOrder {
+        id: state.last_held_order_id,
+        order_id: order.order_id.clone(),
+        alt_order_id: order.alt_order_id.clone(),
+        customer_id: order.customer_id.clone(),
+        memo: order.memo.clone(),
+        original_price: order.original_price.clone().unwrap_or_default(),
+        currency: order.currency.clone(),
+        order_created_at: order.created_at,
+        reason: reason.to_string(),
+        created_at: Utc::now(),
+        released_at: None,
+        merchant_id: order.merchant_id.clone(),
+    };
+    state.held_orders.push(held_order.clone());
+    held_order
+}
+
+pub fn fetch_held_order(order_id: &OrderId, state: &MemoryState) -> Option<HeldOrder> {
+    state.held_orders.iter().find(|o| &o.order_id == order_id).cloned()
+}
+
+pub fn fetch_held_orders(pagination: &Pagination, state: &MemoryState) -> Vec<HeldOrder> {
+    let offset = pagination.offset.and_then(|o| usize::try_from(o).ok()).unwrap_or(0);
+    let count = pagination.count.and_then(|c| usize::try_from(c).ok()).unwrap_or(usize::MAX);
+    state.held_orders.iter().filter(|o| !o.is_released()).skip(offset).take(count).cloned().collect()
+}
+
+/// Marks the order as released. Returns `None` if the order is not being held.
+pub fn release_held_order(order_id: &OrderId, state: &mut MemoryState) -> Option<HeldOrder> {
+    let held_order = state.held_orders.iter_mut().find(|o| &o.order_id == order_id && !o.is_released())?;
+    held_order.released_at = Some(Utc::now());
+    Some(held_order.clone())
+}
+
+//--------------------------------------       Ledger        ---------------------------------------------------------
+
+/// Posts the transaction and its entries. A transaction whose debits and credits do not balance is refused.
+pub fn post_ledger_transaction(
+    transaction: &NewLedgerTransaction,
+    state: &mut MemoryState,
+) -> Result<(), PaymentGatewayError> {
+    if !transaction.is_balanced() {
+        return Err(PaymentGatewayError::UnbalancedLedgerTransaction(transaction.reference.clone()));
+    }
+    state.last_ledger_transaction_id += 1;
+    let created_at = Utc::now();
+    for entry in &transaction.entries {
+        state.last_ledger_entry_id += 1;
+        state.ledger.push(LedgerEntry {
+            id: state.last_ledger_entry_id,
+            transaction_id: state.last_ledger_transaction_id,
+            created_at,
+            transaction_type: transaction.transaction_type,
+            reference: transaction.reference.clone(),
+            account_type: entry.account.account_type,
+            account_id: entry.account.account_id.clone(),
+            side: entry.side,
+            amount: entry.amount,
+        });
+    }
+    Ok(())
+}
+
+pub fn fetch_ledger_entries(account: &LedgerAccount, pagination: &Pagination, state: &MemoryState) -> Vec<LedgerEntry> {
+    let offset = pagination.offset.and_then(|o| usize::try_from(o).ok()).unwrap_or(0);
+    let count = pagination.count.and_then(|c| usize::try_from(c).ok()).unwrap_or(usize::MAX);
+    state.ledger.iter().filter(|e| &e.account() == account).skip(offset).take(count).cloned().collect()
+}
+
+pub fn ledger_balances(state: &MemoryState) -> Vec<LedgerBalance> {
+    let mut balances: Vec<LedgerBalance> = Vec::new();
+    for entry in &state.ledger {
+        let index = match balances.iter().position(|b| b.account() == entry.account()) {
+            Some(index) => index,
+            None => {
+                let zero = MicroTari::from(0);
+                let account_id = entry.account_id.clone();
+                balances.push(LedgerBalance {
+                    account_type: entry.account_type,
+                    account_id,
+                    debits: zero,
+                    credits: zero,
+                });
+                balances.len() - 1
+            },
+        };
+        match entry.side {
+            EntrySide::Debit => balances[index].debits += entry.amount,
+            EntrySide::Credit => balances[index].credits += entry.amount,
+        }
+    }
+    // Same order as the SQL backends
+    balances.sort_by_key(|b| (b.account_type.to_string(), b.account_id.clone()));
+    balances
+}
+
+/// The ids of the transactions whose debits and credits do not match.
+pub fn unbalanced_ledger_transactions(state: &MemoryState) -> Vec<i64> {
+    let mut totals: Vec<(i64, MicroTari)> = Vec::new();
+    for entry in &state.ledger {
+        let amount = match entry.side {
+            EntrySide::Debit => entry.amount,
+            EntrySide::Credit => -entry.amount,
+        };
+        match totals.iter_mut().find(|(id, _)| *id == entry.transaction_id) {
+            Some((_, total)) => *total += amount,
+            None => totals.push((entry.transaction_id, amount)),
+        }
+    }
+    totals.into_iter().filter(|(_, total)| *total != MicroTari::from(0)).map(|(id, _)| id).collect()
+}
+
+//--------------------------------------   Admin audit log   ---------------------------------------------------------
+
+pub fn insert_admin_action(action: NewAdminAction, state: &mut MemoryState) -> AdminAction {
+    state.last_admin_action_id += 1;
+    let action = AdminAction {
+        id: state.last_admin_action_id,
+        created_at: Utc::now(),
+        actor: action.actor.into(),
+        roles: action.roles,
+        merchant: action.merchant,
+        endpoint: action.endpoint,
+        method: action.method,
+        path: action.path,
+        parameters: action.parameters,
+        reason: action.reason,
+        client_ip: action.client_ip,
+        outcome: action.outcome,
+        status_code: action.status_code,
+        error: action.error,
+    };
+    state.admin_actions.push(action.clone());
+    action
+}
+
+/// Newest first, as with the SQL backends
+pub fn fetch_admin_actions(
+    filter: &AdminActionFilter,
+    pagination: &Pagination,
+    state: &MemoryState,
+) -> Vec<AdminAction> {
+    let offset = pagination.offset.and_then(|o| usize::try_from(o).ok()).unwrap_or(0);
+    let count = pagination.count.and_then(|c| usize::try_from(c).ok()).unwrap_or(usize::MAX);
+    state.admin_actions.iter().rev().filter(|a| filter.matches(a)).skip(offset).take(count).cloned().collect()
+}
+
+//--------------------------------------      Approvals      ---------------------------------------------------------
+
+pub fn insert_approval_request(request: NewApprovalRequest, state: &mut MemoryState) -> ApprovalRequest {
+    state.last_approval_request_id += 1;
+    let now = Utc::now();
+    let request = ApprovalRequest {
+        id: state.last_approval_request_id,
+        created_at: now,
+        updated_at: now,
+        expires_at: request.expires_at,
+        requested_by: request.requested_by.into(),
+        operation: request.operation,
+        status: ApprovalStatus::Pending,
+        decided_by: None,
+        decision_reason: None,
+    };
+    state.approval_requests.push(request.clone());
+    request
+}
+
+pub fn fetch_approval_request(id: i64, state: &MemoryState) -> Option<ApprovalRequest> {
+    state.approval_requests.iter().find(|r| r.id == id).cloned()
+}
+
+pub fn fetch_approval_requests(
+    status: Option<ApprovalStatus>,
+    pagination: &Pagination,
+    state: &MemoryState,
+) -> Vec<ApprovalRequest> {
+    let offset = pagination.offset.and_then(|o| usize::try_from(o).ok()).unwrap_or(0);
+    let count = pagination.count.and_then(|c| usize::try_from(c).ok()).unwrap_or(usize::MAX);
+    state
+        .approval_requests
+        .iter()
+        .rev()
+        .filter(|r| status.map(|s| r.status == s).unwrap_or(true))
+        .skip(offset)
+        .take(count)
+        .cloned()
+        .collect()
+}
+
+/// Moves the request from the `current` status to `status`. If `decided_by` or `reason` are given, they replace the
+/// current values.
+pub fn update_approval_request(
+    id: i64,
+    current: ApprovalStatus,
+    status: ApprovalStatus,
+    decided_by: Option<&TariAddress>,
+    reason: Option<&str>,
+    state: &mut MemoryState,
+) -> Result<ApprovalRequest, PaymentGatewayError> {
+    let request = state
+        .approval_requests
+        .iter_mut()
+        .find(|r| r.id == id)
+        .ok_or(PaymentGatewayError::ApprovalRequestNotFound(id))?;
+    if request.status != current {
+        return Err(PaymentGatewayError::InvalidApproval(format!(
+            "Approval request {id} is no longer '{current}', so it cannot be moved to '{status}'"
+        )));
+    }
+    if decided_by.is_some_and(|a| a == request.requested_by.as_address()) {
+        return Err(PaymentGatewayError::DatabaseError(
+            "CHECK constraint failed: decided_by IS NULL OR decided_by != requested_by".to_string(),
+        ));
+    }
+    request.status = status;
+    if let Some(address) = decided_by {
+        request.decided_by = Some(address.clone().into());
+    }
+    if let Some(reason) = reason {
+        request.decision_reason = Some(reason.to_string());
+    }
+    request.updated_at = Utc::now();
+    Ok(request.clone())
+}
+
+pub fn expire_approval_requests(state: &mut MemoryState) -> Vec<ApprovalRequest> {
+    let now = Utc::now();
+    state
+        .approval_requests
+        .iter_mut()
+        .filter(|r| r.status == ApprovalStatus::Pending && r.expires_at <= now)
+        .map(|r| {
+            r.status = ApprovalStatus::Expired;
+            r.updated_at = now;
+            r.clone()
+        })
+        .collect()
+}
+
+//--------------------------------------     Rate limits     ---------------------------------------------------------
+
+pub fn take_rate_limit_token(key: &str, limit: &RateLimit, state: &mut MemoryState) -> RateLimitDecision {
+    let now = Instant::now();
+    state.rate_limits.entry(key.to_string()).or_insert_with(|| TokenBucket::full(limit, now)).take(limit, now)
+}
+
+pub fn prune_rate_limits(state: &mut MemoryState) -> u64 {
+    let now = Instant::now();
+    let count = state.rate_limits.len();
+    state.rate_limits.retain(|_, bucket| !bucket.is_expired(now));
+    (count - state.rate_limits.len()) as u64
+}
@@ -0,0 +1,326 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{postgres::PgRow, PgConnection, QueryBuilder, Row};
+use tari_common_types::tari_address::TariAddress;
+
+use crate::{
+    db_types::{
+        AddressBalance,
+        CustomerOrderBalance,
+        CustomerOrders,
+        NewSettlementJournalEntry,
+        Order,
+        OrderId,
+        SettlementJournalEntry,
+    },
+    tpe_api::account_objects::Pagination,
+    traits::AccountApiError,
+};
+
+/// Links an address to a customer id. This function is idempotent due to the `ON CONFLICT DO NOTHING` clause.
+pub(crate) async fn link_address_to_customer(
+    address: &TariAddress,
+    customer_id: &str,
+    conn: &mut PgConnection,
+) -> Result<(), AccountApiError> {
+    sqlx::query(
+        "INSERT INTO address_customer_id_link (address, customer_id) VALUES ($1, $2) ON CONFLICT (address, \
+         customer_id) DO NOTHING",
+    )
+    .bind(address.to_base58())
+    .bind(customer_id)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub(crate) async fn balances_for_customer_id(
+    customer_id: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<AddressBalance>, AccountApiError> {
+    let addresses: Vec<AddressBalance> = sqlx::query_as(
+        r#"
+    SELECT * FROM address_balance
+    WHERE address in (SELECT address from address_customer_id_link WHERE customer_id = $1)
+    ORDER BY last_update DESC
+    "#,
+    )
+    .bind(customer_id)
+    .fetch_all(conn)
+    .await?;
+    Ok(addresses)
+}
+
+pub(crate) async fn fetch_address_balance(
+    address: &TariAddress,
+    conn: &mut PgConnection,
+) -> Result<AddressBalance, AccountApiError> {
+    let balance: Option<AddressBalance> = sqlx::query_as("SELECT * FROM address_balance WHERE address = $1")
+        .bind(address.to_base58())
+        .fetch_optional(conn)
+        .await?;
+    Ok(balance.unwrap_or_else(|| AddressBalance::new(address.clone())))
+}
+
+/// The balances that the customer's addresses hold with `merchant_id`, i.e. the funds that can pay for that merchant's
+/// orders.
+pub(crate) async fn merchant_balances_for_customer_id(
+    customer_id: &str,
+    merchant_id: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<AddressBalance>, AccountApiError> {
+    let addresses: Vec<AddressBalance> = sqlx::query_as(
+        r#"
+    SELECT * FROM merchant_balance
+    WHERE merchant_id = $2 AND address in (SELECT address from address_customer_id_link WHERE customer_id = $1)
+    ORDER BY last_update DESC
+    "#,
+    )
+    .bind(customer_id)
+    .bind(merchant_id)
+    .fetch_all(conn)
+    .await?;
+    Ok(addresses)
+}
+
+/// The balances with `merchant_id` of the addresses that sent payments for the order.
+pub(crate) async fn merchant_balances_for_order_id(
+    order_id: &OrderId,
+    alt_id: Option<&OrderId>,
+    merchant_id: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<AddressBalance>, AccountApiError> {
+    let where_clause = match alt_id {
+        None => "order_id = $2",
+        Some(_) => "order_id = $2 OR order_id = $3",
+    };
+    let q_str = format!(
+        "SELECT * FROM merchant_balance WHERE merchant_id = $1 AND address in (SELECT sender from payments WHERE \
+         {where_clause}) ORDER BY last_update DESC"
+    );
+    let mut query = sqlx::query_as(&q_str).bind(merchant_id).bind(order_id.as_str());
+    if let Some(alt_id) = alt_id {
+        query = query.bind(alt_id.as_str());
+    }
+    let addresses: Vec<AddressBalance> = query.fetch_all(conn).await?;
+    Ok(addresses)
+}
+
+/// The funds that `address` holds with `merchant_id`
+pub(crate) async fn fetch_merchant_balance(
+    address: &TariAddress,
+    merchant_id: &str,
+    conn: &mut PgConnection,
+) -> Result<AddressBalance, AccountApiError> {
+    let balance: Option<AddressBalance> =
+        sqlx::query_as("SELECT * FROM merchant_balance WHERE address = $1 AND merchant_id = $2")
+            .bind(address.to_base58())
+            .bind(merchant_id)
+            .fetch_optional(conn)
+            .await?;
+    Ok(balance.unwrap_or_else(|| AddressBalance::for_merchant(address.clone(), merchant_id)))
+}
+
+/// Serialises changes to the balances of the given addresses until the end of the transaction.
+///
+/// Balances are derived from the `payments` and `settlement_journal` tables, so there is no single row to lock.
+/// A transaction-level advisory lock keyed on the address is taken instead. The addresses are locked in a fixed order
+/// so that two transactions locking overlapping sets of addresses cannot deadlock.
+pub(crate) async fn lock_addresses(addresses: &[TariAddress], conn: &mut PgConnection) -> Result<(), AccountApiError> {
+    let mut keys = addresses.iter().map(|a| a.to_base58()).collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+    for key in keys {
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))").bind(key).execute(&mut *conn).await?;
+    }
+    Ok(())
+}
+
+pub(crate) async fn fetch_idle_balances(
+    idle: Duration,
+    conn: &mut PgConnection,
+) -> Result<Vec<AddressBalance>, AccountApiError> {
+    let balances = sqlx::query_as(
+        "SELECT * FROM merchant_balance WHERE current_balance > 0 AND EXTRACT(EPOCH FROM (CURRENT_TIMESTAMP - \
+         last_update)) >= $1 ORDER BY last_update",
+    )
+    .bind(idle.num_seconds())
+    .fetch_all(conn)
+    .await?;
+    Ok(balances)
+}
+
+pub(crate) async fn insert_settlement(
+    settlement: NewSettlementJournalEntry,
+    conn: &mut PgConnection,
+) -> Result<SettlementJournalEntry, AccountApiError> {
+    let result = sqlx::query_as(
+        r#"
+    INSERT INTO settlement_journal (order_id, payment_address, amount, settlement_type, merchant_id)
+    VALUES ($1, $2, $3, $4, $5)
+    RETURNING *
+    "#,
+    )
+    .bind(settlement.order_id)
+    .bind(settlement.payment_address.as_base58())
+    .bind(settlement.amount)
+    .bind(settlement.settlement_type)
+    .bind(settlement.merchant_id)
+    .fetch_one(conn)
+    .await?;
+    Ok(result)
+}
+
+pub(crate) async fn settlements_for_address(
+    address: &TariAddress,
+    conn: &mut PgConnection,
+) -> Result<Vec<SettlementJournalEntry>, AccountApiError> {
+    let settlements: Vec<SettlementJournalEntry> =
+        sqlx::query_as("SELECT * FROM settlement_journal WHERE payment_address = $1")
+            .bind(address.to_base58())
+            .fetch_all(conn)
+            .await?;
+    Ok(settlements)
+}
+
+pub(crate) async fn settlements_for_order(
+    order_id: &OrderId,
+    conn: &mut PgConnection,
+) -> Result<Vec<SettlementJournalEntry>, AccountApiError> {
+    let settlements: Vec<SettlementJournalEntry> =
+        sqlx::query_as("SELECT * FROM settlement_journal WHERE order_id = $1 ORDER BY id")
+            .bind(order_id.as_str())
+            .fetch_all(conn)
+            .await?;
+    Ok(settlements)
+}
+
+pub(crate) async fn settlements_for_customer_id(
+    customer_id: &str,
+    conn: &mut PgConnection,
+) -> Result<Vec<SettlementJournalEntry>, AccountApiError> {
+    let settlements: Vec<SettlementJournalEntry> = sqlx::query_as(
+        r#"
+    SELECT * FROM settlement_journal WHERE order_id in (
+      SELECT order_id FROM orders WHERE customer_id = $1 AND status = 'Paid'
+    )"#,
+    )
+    .bind(customer_id)
+    .fetch_all(conn)
+    .await?;
+    Ok(settlements)
+}
+
+/// Fetches the settlement journal entries that were made in the period `[from, to)`, oldest first.
+pub(crate) async fn settlements_between(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    conn: &mut PgConnection,
+) -> Result<Vec<SettlementJournalEntry>, AccountApiError> {
+    let settlements =
+        sqlx::query_as("SELECT * FROM settlement_journal WHERE created_at >= $1 AND created_at < $2 ORDER BY id")
+            .bind(from)
+            .bind(to)
+            .fetch_all(conn)
+            .await?;
+    Ok(settlements)
+}
+
+pub(crate) async fn orders_for_address(
+    address: &TariAddress,
+    conn: &mut PgConnection,
+) -> Result<Vec<Order>, AccountApiError> {
+    let accounts: Vec<Order> = sqlx::query_as(
+        r#"
+    SELECT
+        orders.id as id,
+        orders.order_id as order_id,
+        orders.alt_id as alt_id,
+        orders.customer_id as customer_id,
+        orders.memo as memo,
+        orders.total_price as total_price,
+        orders.original_price as original_price,
+        orders.currency as currency,
+        orders.created_at as created_at,
+        orders.updated_at as updated_at,
+        orders.status as status,
+        orders.rate_id as rate_id,
+        orders.quote_expires_at as quote_expires_at,
+        orders.merchant_id as merchant_id
+    FROM orders JOIN address_customer_id_link ON orders.customer_id = address_customer_id_link.customer_id
+    WHERE address = $1
+    "#,
+    )
+    .bind(address.to_base58())
+    .fetch_all(conn)
+    .await?;
+    Ok(accounts)
+}
+
+pub(crate) async fn creditors(conn: &mut PgConnection) -> Result<Vec<CustomerOrders>, AccountApiError> {
+    let addresses: Vec<CustomerOrders> =
+        sqlx::query_as("SELECT * FROM customer_order_balance WHERE status = 'New' AND total_orders > 0")
+            .fetch_all(conn)
+            .await?;
+    Ok(addresses)
+}
+
+pub(crate) async fn customer_order_balance(
+    cust_id: &str,
+    conn: &mut PgConnection,
+) -> Result<CustomerOrderBalance, AccountApiError> {
+    let orders: Vec<CustomerOrders> = sqlx::query_as("SELECT * FROM customer_order_balance WHERE customer_id = $1")
+        .bind(cust_id)
+        .fetch_all(conn)
+        .await?;
+    let balances = CustomerOrderBalance::new(&orders);
+    Ok(balances)
+}
+
+pub(crate) async fn customer_ids(
+    pagination: &Pagination,
+    conn: &mut PgConnection,
+) -> Result<Vec<String>, AccountApiError> {
+    let rows =
+        with_pagination("SELECT DISTINCT customer_id FROM orders ORDER BY customer_id", pagination, conn).await?;
+    let customer_ids = rows.into_iter().map(|r| r.get("customer_id")).collect::<Vec<String>>();
+    Ok(customer_ids)
+}
+
+pub(crate) async fn addresses(
+    pagination: &Pagination,
+    conn: &mut PgConnection,
+) -> Result<Vec<TariAddress>, AccountApiError> {
+    let rows = with_pagination("SELECT DISTINCT sender FROM payments ORDER BY sender ASC", pagination, conn).await?;
+    let addresses = rows.into_iter().filter_map(|r| TariAddress::from_base58(r.get("sender")).ok()).collect();
+    Ok(addresses)
+}
+
+pub(crate) async fn customer_ids_for_address(
+    address: &TariAddress,
+    conn: &mut PgConnection,
+) -> Result<Vec<String>, AccountApiError> {
+    let rows = sqlx::query("SELECT customer_id FROM address_customer_id_link WHERE address = $1")
+        .bind(address.to_base58())
+        .fetch_all(conn)
+        .await?;
+    let customer_ids = rows.into_iter().map(|r| r.get("customer_id")).collect::<Vec<String>>();
+    Ok(customer_ids)
+}
+
+async fn with_pagination(
+    q: &str,
+    pagination: &Pagination,
+    conn: &mut PgConnection,
+) -> Result<Vec<PgRow>, AccountApiError> {
+    let mut builder = QueryBuilder::new(q);
+    if let Some(count) = pagination.count {
+        builder.push(" LIMIT ");
+        builder.push_bind(count);
+    }
+    if let Some(offset) = pagination.offset {
+        builder.push(" OFFSET ");
+        builder.push_bind(offset);
+    }
+    let rows = builder.build().fetch_all(conn).await?;
+    Ok(rows)
+}
@@ -0,0 +1,125 @@
+//! Postgres database operations for approval requests
+//!
+//! Generally clients should never call these methods directly, and prefer to use the
+//! [`crate::traits::PaymentGatewayDatabase`] and [`crate::traits::AccountManagement`] trait methods that are
+//! implemented on the [`crate::PostgresDatabase`] struct instead.
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, PgConnection};
+use tari_common_types::tari_address::TariAddress;
+
+use crate::{
+    db_types::{ApprovalRequest, ApprovalStatus, NewApprovalRequest, SerializedTariAddress},
+    tpe_api::account_objects::Pagination,
+};
+
+#[derive(FromRow)]
+struct ApprovalRequestRow {
+    id: i64,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    requested_by: SerializedTariAddress,
+    operation: String,
+    status: ApprovalStatus,
+    decided_by: Option<SerializedTariAddress>,
+    decision_reason: Option<String>,
+}
+
+impl TryFrom<ApprovalRequestRow> for ApprovalRequest {
+    type Error = sqlx::Error;
+
+    fn try_from(row: ApprovalRequestRow) -> Result<Self, Self::Error> {
+        let operation = serde_json::from_str(&row.operation).map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+        Ok(Self {
+            id: row.id,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+            expires_at: row.expires_at,
+            requested_by: row.requested_by,
+            operation,
+            status: row.status,
+            decided_by: row.decided_by,
+            decision_reason: row.decision_reason,
+        })
+    }
+}
+
+pub(crate) async fn insert_approval_request(
+    request: NewApprovalRequest,
+    conn: &mut PgConnection,
+) -> Result<ApprovalRequest, sqlx::Error> {
+    let operation = serde_json::to_string(&request.operation).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+    let row: ApprovalRequestRow = sqlx::query_as(
+        "INSERT INTO approval_requests (expires_at, requested_by, operation) VALUES ($1, $2, $3) RETURNING *",
+    )
+    .bind(request.expires_at)
+    .bind(request.requested_by.to_base58())
+    .bind(operation)
+    .fetch_one(conn)
+    .await?;
+    row.try_into()
+}
+
+pub(crate) async fn fetch_approval_request(
+    id: i64,
+    conn: &mut PgConnection,
+) -> Result<Option<ApprovalRequest>, sqlx::Error> {
+    let row: Option<ApprovalRequestRow> =
+        sqlx::query_as("SELECT * FROM approval_requests WHERE id = $1").bind(id).fetch_optional(conn).await?;
+    row.map(ApprovalRequest::try_from).transpose()
+}
+
+pub(crate) async fn fetch_approval_requests(
+    status: Option<ApprovalStatus>,
+    pagination: &Pagination,
+    conn: &mut PgConnection,
+) -> Result<Vec<ApprovalRequest>, sqlx::Error> {
+    // A NULL limit or offset is the same as leaving the clause out
+    let rows: Vec<ApprovalRequestRow> = sqlx::query_as(
+        "SELECT * FROM approval_requests WHERE $1::ApprovalStatus IS NULL OR status = $1 ORDER BY id DESC LIMIT $2 \
+         OFFSET $3",
+    )
+    .bind(status)
+    .bind(pagination.count)
+    .bind(pagination.offset)
+    .fetch_all(conn)
+    .await?;
+    rows.into_iter().map(ApprovalRequest::try_from).collect()
+}
+
+/// Moves the request from the `current` status to `status`. If `decided_by` or `reason` are given, they replace the
+/// current values.
+///
+/// Returns `None` if the request is no longer in the `current` status, e.g. because another admin has already decided
+/// on it.
+pub(crate) async fn update_approval_request(
+    id: i64,
+    current: ApprovalStatus,
+    status: ApprovalStatus,
+    decided_by: Option<&TariAddress>,
+    reason: Option<&str>,
+    conn: &mut PgConnection,
+) -> Result<Option<ApprovalRequest>, sqlx::Error> {
+    let row: Option<ApprovalRequestRow> = sqlx::query_as(
+        "UPDATE approval_requests SET status = $1, decided_by = COALESCE($2, decided_by), decision_reason = \
+         COALESCE($3, decision_reason), updated_at = CURRENT_TIMESTAMP WHERE id = $4 AND status = $5 RETURNING *",
+    )
+    .bind(status)
+    .bind(decided_by.map(|a| a.to_base58()))
+    .bind(reason)
+    .bind(id)
+    .bind(current)
+    .fetch_optional(conn)
+    .await?;
+    row.map(ApprovalRequest::try_from).transpose()
+}
+
+pub(crate) async fn expire_approval_requests(conn: &mut PgConnection) -> Result<Vec<ApprovalRequest>, sqlx::Error> {
+    let rows: Vec<ApprovalRequestRow> = sqlx::query_as(
+        "UPDATE approval_requests SET status = 'Expired', updated_at = CURRENT_TIMESTAMP WHERE status = 'Pending' AND \
+         expires_at <= now() RETURNING *",
+    )
+    .fetch_all(conn)
+    .await?;
+    rows.into_iter().map(ApprovalRequest::try_from).collect()
+}
@@ -0,0 +1,305 @@
+//! Postgres database operations for the tari payment authentication
+//!
+//! Generally clients should never call these methods directly, and prefer to use the [`crate::traits::AuthManagement`]
+//! trait methods that are implemented on the [`crate::PostgresDatabase`] struct instead.
+
+use std::collections::{HashMap, HashSet};
+
+use log::{debug, error, warn};
+use sqlx::{PgConnection, QueryBuilder, Row};
+use tari_common_types::tari_address::TariAddress;
+
+use super::is_nonce_violation;
+use crate::{
+    db_types::{Permission, Role, RoleDefinition},
+    traits::AuthApiError,
+};
+
+pub static DEFAULT_ROLES: &[Role] = &[Role::User];
+
+pub async fn auth_account_exists(address: &TariAddress, conn: &mut PgConnection) -> Result<bool, AuthApiError> {
+    let address = address.to_base58();
+    let count: i64 = sqlx::query("SELECT count(address) FROM auth_log WHERE address = $1")
+        .bind(&address)
+        .fetch_one(conn)
+        .await?
+        .get(0);
+    match count {
+        0 => Ok(false),
+        1 => Ok(true),
+        n => {
+            error!("Account {address} appears multiple {n} times in database. This must be 0|1!");
+            Err(AuthApiError::DatabaseError(
+                "Account appears multiple times in database. Report this to the developers".to_string(),
+            ))
+        },
+    }
+}
+
+pub async fn roles_for_address(address: &TariAddress, conn: &mut PgConnection) -> Result<HashSet<Role>, AuthApiError> {
+    let address = address.to_base58();
+    let result = sqlx::query(
+        r#"SELECT name FROM
+            role_assignments LEFT JOIN roles ON role_assignments.role_id = roles.id
+            WHERE address = $1"#,
+    )
+    .bind(address)
+    .fetch_all(conn)
+    .await
+    .map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+    let mut roles = result
+        .iter()
+        .filter_map(|r| r.get::<Option<String>, _>("name"))
+        .map(|r| r.parse::<Role>().map_err(|_| AuthApiError::RoleNotFound))
+        .collect::<Result<HashSet<Role>, _>>()?;
+    // Add in default roles
+    roles.extend(DEFAULT_ROLES.iter().cloned());
+    Ok(roles)
+}
+
+pub async fn address_has_roles(
+    address: &TariAddress,
+    roles: &[Role],
+    conn: &mut PgConnection,
+) -> Result<(), AuthApiError> {
+    // Empty roles are always true
+    if roles.is_empty() {
+        return Ok(());
+    }
+    // Check default roles
+    if roles.iter().all(|r| DEFAULT_ROLES.contains(r)) {
+        return Ok(());
+    }
+    let additional_roles = roles.iter().filter(|r| !DEFAULT_ROLES.contains(r)).collect::<Vec<_>>();
+    let role_names = additional_roles.iter().map(|r| r.to_string()).collect::<Vec<String>>();
+    #[allow(clippy::cast_possible_truncation)]
+    let num_matching_roles = sqlx::query(
+        r#"SELECT count(name)
+            FROM role_assignments LEFT JOIN roles ON role_assignments.role_id = roles.id
+            WHERE address = $1 AND name = ANY($2)"#,
+    )
+    .bind(address.to_base58())
+    .bind(role_names)
+    .fetch_one(conn)
+    .await?
+    .get::<i64, usize>(0) as usize;
+    if num_matching_roles == additional_roles.len() {
+        Ok(())
+    } else {
+        let n = additional_roles.len().saturating_sub(num_matching_roles);
+        Err(AuthApiError::RoleNotAllowed(n))
+    }
+}
+
+pub async fn upsert_nonce_for_address(
+    address: &TariAddress,
+    nonce: u64,
+    conn: &mut PgConnection,
+) -> Result<(), AuthApiError> {
+    let address = address.to_base58();
+    #[allow(clippy::cast_possible_wrap)]
+    let nonce = nonce as i64;
+    let res = sqlx::query(
+        r#"INSERT INTO auth_log (last_nonce, address) VALUES ($1, $2) ON CONFLICT(address) DO
+    UPDATE SET last_nonce = excluded.last_nonce"#,
+    )
+    .bind(nonce)
+    .bind(address)
+    .execute(conn)
+    .await;
+    debug!("{res:?}");
+    res.map_err(|e| if is_nonce_violation(&e) { AuthApiError::InvalidNonce } else { AuthApiError::from(e) }).and_then(
+        |res| match res.rows_affected() {
+            0 => Err(AuthApiError::AddressNotFound),
+            1 => Ok(()),
+            _ => unreachable!("Updating auth log should only affect one row"),
+        },
+    )
+}
+
+async fn fetch_roles(conn: &mut PgConnection) -> Result<HashMap<Role, i64>, AuthApiError> {
+    let result = sqlx::query("SELECT id, name FROM roles").fetch_all(conn).await?;
+    let roles = result
+        .iter()
+        .map(|r| {
+            r.get::<String, _>("name")
+                .parse::<Role>()
+                .map(|role| (role, r.get::<i64, _>("id")))
+                .map_err(|_| AuthApiError::RoleNotFound)
+        })
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    debug!("Fetched current roles table: {:?}", roles);
+    Ok(roles)
+}
+
+pub async fn assign_roles(address: &TariAddress, roles: &[Role], conn: &mut PgConnection) -> Result<(), AuthApiError> {
+    let all_roles = fetch_roles(conn).await?;
+
+    let role_ids = roles
+        .iter()
+        .map(|r| all_roles.get(r).ok_or(AuthApiError::RoleNotFound).copied())
+        .collect::<Result<Vec<i64>, _>>()?;
+    let address = address.to_base58();
+
+    let mut qb = QueryBuilder::new("INSERT INTO role_assignments (address, role_id) ");
+    qb.push_values(role_ids, |mut row, role_id| {
+        row.push_bind(address.clone()).push_bind(role_id);
+    });
+    qb.push(" ON CONFLICT (address, role_id) DO NOTHING");
+    let res = qb.build().execute(conn).await?;
+
+    if res.rows_affected() == roles.len() as u64 {
+        Ok(())
+    } else {
+        error!("Expected to insert {} roles, but inserted {}", roles.len(), res.rows_affected());
+        Err(AuthApiError::DatabaseError(
+            "Inserted unexpected number of Roles. Report this to the developers".to_string(),
+        ))
+    }
+}
+
+pub async fn remove_roles(address: &TariAddress, roles: &[Role], conn: &mut PgConnection) -> Result<u64, AuthApiError> {
+    let all_roles = fetch_roles(conn).await?;
+
+    let role_ids = roles
+        .iter()
+        .map(|r| all_roles.get(r).ok_or(AuthApiError::RoleNotFound).copied())
+        .collect::<Result<Vec<i64>, _>>()?;
+
+    let res = sqlx::query("DELETE FROM role_assignments WHERE address = $1 AND role_id = ANY($2)")
+        .bind(address.to_base58())
+        .bind(role_ids)
+        .execute(conn)
+        .await?;
+
+    Ok(res.rows_affected())
+}
+
+/// Fetches the roles that were assigned to the address for the given merchant only.
+pub async fn merchant_roles_for_address(
+    address: &TariAddress,
+    merchant_id: &str,
+    conn: &mut PgConnection,
+) -> Result<HashSet<Role>, AuthApiError> {
+    let rows = sqlx::query(
+        r#"SELECT name FROM
+            merchant_role_assignments LEFT JOIN roles ON merchant_role_assignments.role_id = roles.id
+            WHERE address = $1 AND merchant_id = $2"#,
+    )
+    .bind(address.to_base58())
+    .bind(merchant_id)
+    .fetch_all(conn)
+    .await?;
+    rows.iter().map(|r| r.get::<String, _>("name").parse::<Role>().map_err(|_| AuthApiError::RoleNotFound)).collect()
+}
+
+pub async fn assign_merchant_roles(
+    address: &TariAddress,
+    merchant_id: &str,
+    roles: &[Role],
+    conn: &mut PgConnection,
+) -> Result<(), AuthApiError> {
+    if roles.is_empty() {
+        return Ok(());
+    }
+    let all_roles = fetch_roles(conn).await?;
+    let role_ids = roles
+        .iter()
+        .map(|r| all_roles.get(r).ok_or(AuthApiError::RoleNotFound).copied())
+        .collect::<Result<Vec<i64>, _>>()?;
+    let address = address.to_base58();
+    let mut qb = QueryBuilder::new("INSERT INTO merchant_role_assignments (address, merchant_id, role_id) ");
+    qb.push_values(role_ids, |mut row, role_id| {
+        row.push_bind(address.clone()).push_bind(merchant_id).push_bind(role_id);
+    });
+    qb.push(" ON CONFLICT (address, merchant_id, role_id) DO NOTHING");
+    qb.build().execute(conn).await?;
+    Ok(())
+}
+
+pub async fn remove_merchant_roles(
+    address: &TariAddress,
+    merchant_id: &str,
+    roles: &[Role],
+    conn: &mut PgConnection,
+) -> Result<u64, AuthApiError> {
+    let all_roles = fetch_roles(conn).await?;
+    let role_ids = roles
+        .iter()
+        .map(|r| all_roles.get(r).ok_or(AuthApiError::RoleNotFound).copied())
+        .collect::<Result<Vec<i64>, _>>()?;
+    let res = sqlx::query(
+        "DELETE FROM merchant_role_assignments WHERE address = $1 AND merchant_id = $2 AND role_id = ANY($3)",
+    )
+    .bind(address.to_base58())
+    .bind(merchant_id)
+    .bind(role_ids)
+    .execute(conn)
+    .await?;
+    Ok(res.rows_affected())
+}
+
+/// Fetches every role, and the permissions that it grants. Permissions that this version of the code does not know
+/// about are skipped.
+pub async fn fetch_role_definitions(conn: &mut PgConnection) -> Result<Vec<RoleDefinition>, AuthApiError> {
+    let rows = sqlx::query(
+        "SELECT name, permission FROM roles LEFT JOIN role_permissions ON roles.id = role_permissions.role_id ORDER \
+         BY name",
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut definitions: Vec<RoleDefinition> = Vec::new();
+    for row in rows {
+        let role = row.get::<String, _>("name").parse::<Role>().map_err(|_| AuthApiError::RoleNotFound)?;
+        if definitions.last().map_or(true, |d| d.role != role) {
+            definitions.push(RoleDefinition::new(role, &[]));
+        }
+        let Some(permission) = row.get::<Option<String>, _>("permission") else {
+            continue;
+        };
+        match (permission.parse::<Permission>(), definitions.last_mut()) {
+            (Ok(permission), Some(definition)) => definition.permissions.push(permission),
+            (Err(e), _) => warn!("Ignoring unknown permission in the database. {e}"),
+            (Ok(_), None) => {},
+        }
+    }
+    definitions.iter_mut().for_each(|d| d.permissions.sort());
+    definitions.sort_by_key(|d| d.role.to_string());
+    Ok(definitions)
+}
+
+/// Creates the role if it does not exist, and replaces its permissions. Call this from inside a transaction.
+pub async fn save_role(role: &RoleDefinition, conn: &mut PgConnection) -> Result<(), AuthApiError> {
+    if role.role == Role::SuperAdmin {
+        return Err(AuthApiError::BuiltInRole(role.role.clone()));
+    }
+    let name = role.role.to_string();
+    sqlx::query("INSERT INTO roles (name) VALUES ($1) ON CONFLICT (name) DO NOTHING")
+        .bind(&name)
+        .execute(&mut *conn)
+        .await?;
+    let role_id: i64 =
+        sqlx::query_scalar("SELECT id FROM roles WHERE name = $1").bind(&name).fetch_one(&mut *conn).await?;
+    sqlx::query("DELETE FROM role_permissions WHERE role_id = $1").bind(role_id).execute(&mut *conn).await?;
+    if role.permissions.is_empty() {
+        return Ok(());
+    }
+    let mut qb = QueryBuilder::new("INSERT INTO role_permissions (role_id, permission) ");
+    qb.push_values(&role.permissions, |mut row, permission| {
+        row.push_bind(role_id).push_bind(permission.as_str());
+    });
+    qb.build().execute(conn).await?;
+    Ok(())
+}
+
+/// Deletes a custom role. Its assignments and permissions are removed by the `ON DELETE CASCADE` clauses.
+pub async fn delete_role(role: &Role, conn: &mut PgConnection) -> Result<(), AuthApiError> {
+    if role.is_built_in() {
+        return Err(AuthApiError::BuiltInRole(role.clone()));
+    }
+    let res = sqlx::query("DELETE FROM roles WHERE name = $1").bind(role.to_string()).execute(conn).await?;
+    if res.rows_affected() == 0 {
+        return Err(AuthApiError::RoleNotFound);
+    }
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+
+use crate::{
+    db_types::DEFAULT_MERCHANT,
+    tpe_api::{account_objects::Pagination, exchange_objects::ExchangeRate},
+    traits::ExchangeRateError,
+};
+
+pub async fn fetch_last_rate(currency: &str, conn: &mut PgConnection) -> Result<ExchangeRate, ExchangeRateError> {
+    fetch_merchant_rate(DEFAULT_MERCHANT, currency, conn).await
+}
+
+/// Fetches the last rate that `merchant_id` set for `currency`, or the default merchant's rate if it has not set one.
+pub async fn fetch_merchant_rate(
+    merchant_id: &str,
+    currency: &str,
+    conn: &mut PgConnection,
+) -> Result<ExchangeRate, ExchangeRateError> {
+    let result = sqlx::query_as(
+        r#"SELECT id, base_currency, rate, updated_at, COALESCE(confirmed_at, updated_at) AS confirmed_at,
+        merchant_id
+        FROM exchange_rates WHERE base_currency = $1 AND merchant_id IN ($2, $3)
+        ORDER BY merchant_id = $2 DESC, updated_at DESC, id DESC LIMIT 1"#,
+    )
+    .bind(currency)
+    .bind(merchant_id)
+    .bind(DEFAULT_MERCHANT)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?
+    .ok_or_else(|| ExchangeRateError::RateDoesNotExist(currency.to_string()))?;
+    Ok(result)
+}
+
+pub async fn fetch_rate_by_id(id: i64, conn: &mut PgConnection) -> Result<ExchangeRate, ExchangeRateError> {
+    let result = sqlx::query_as(
+        "SELECT id, base_currency, rate, updated_at, COALESCE(confirmed_at, updated_at) AS confirmed_at, merchant_id \
+         FROM exchange_rates WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?
+    .ok_or_else(|| ExchangeRateError::RateDoesNotExist(format!("#{id}")))?;
+    Ok(result)
+}
+
+/// Fetches the default merchant's rate that was in effect at `timestamp`, i.e. the last rate that was set at or
+/// before that time.
+pub async fn fetch_rate_at(
+    currency: &str,
+    timestamp: DateTime<Utc>,
+    conn: &mut PgConnection,
+) -> Result<ExchangeRate, ExchangeRateError> {
+    let result = sqlx::query_as(
+        r#"SELECT id, base_currency, rate, updated_at, COALESCE(confirmed_at, updated_at) AS confirmed_at,
+        merchant_id
+        FROM exchange_rates
+        WHERE base_currency = $1 AND merchant_id = $3 AND updated_at <= $2 ORDER BY updated_at DESC, id DESC LIMIT 1"#,
+    )
+    .bind(currency)
+    .bind(timestamp)
+    .bind(DEFAULT_MERCHANT)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?
+    .ok_or_else(|| ExchangeRateError::RateDoesNotExist(format!("{currency} at {timestamp}")))?;
+    Ok(result)
+}
+
+/// Fetches the rate for the same currency and merchant that was stored just before `rate`.
+pub async fn fetch_previous_rate(
+    rate: &ExchangeRate,
+    conn: &mut PgConnection,
+) -> Result<Option<ExchangeRate>, ExchangeRateError> {
+    sqlx::query_as(
+        r#"SELECT id, base_currency, rate, updated_at, COALESCE(confirmed_at, updated_at) AS confirmed_at,
+        merchant_id
+        FROM exchange_rates
+        WHERE base_currency = $1 AND merchant_id = $2 AND id < $3 ORDER BY id DESC LIMIT 1"#,
+    )
+    .bind(&rate.base_currency)
+    .bind(&rate.merchant_id)
+    .bind(rate.id)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))
+}
+
+/// Fetches the rates that `merchant_id` set for `currency` between `since` and `until` (inclusive), oldest first.
+pub async fn fetch_rate_history(
+    merchant_id: &str,
+    currency: &str,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    pagination: &Pagination,
+    conn: &mut PgConnection,
+) -> Result<Vec<ExchangeRate>, ExchangeRateError> {
+    // A NULL limit or offset is the same as leaving the clause out
+    sqlx::query_as(
+        r#"SELECT id, base_currency, rate, updated_at, COALESCE(confirmed_at, updated_at) AS confirmed_at,
+        merchant_id
+        FROM exchange_rates
+        WHERE base_currency = $1 AND merchant_id = $6
+          AND ($2::TIMESTAMPTZ IS NULL OR updated_at >= $2)
+          AND ($3::TIMESTAMPTZ IS NULL OR updated_at <= $3)
+        ORDER BY updated_at, id LIMIT $4 OFFSET $5"#,
+    )
+    .bind(currency)
+    .bind(since)
+    .bind(until)
+    .bind(pagination.count)
+    .bind(pagination.offset)
+    .bind(merchant_id)
+    .fetch_all(conn)
+    .await
+    .map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))
+}
+
+pub async fn set_exchange_rate(rate: &ExchangeRate, conn: &mut PgConnection) -> Result<(), ExchangeRateError> {
+    sqlx::query(r#"INSERT INTO exchange_rates (base_currency, rate, merchant_id) VALUES ($1, $2, $3)"#)
+        .bind(&rate.base_currency)
+        .bind(rate.rate)
+        .bind(&rate.merchant_id)
+        .execute(conn)
+        .await
+        .map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+    Ok(())
+}
+
+/// Marks the rate as confirmed by a rate source just now. The rate's `updated_at`, and so the rate history, is
+/// unchanged.
+pub async fn confirm_exchange_rate(id: i64, conn: &mut PgConnection) -> Result<(), ExchangeRateError> {
+    let result = sqlx::query("UPDATE exchange_rates SET confirmed_at = CURRENT_TIMESTAMP WHERE id = $1")
+        .bind(id)
+        .execute(conn)
+        .await
+        .map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+    if result.rows_affected() == 0 {
+        return Err(ExchangeRateError::RateDoesNotExist(format!("#{id}")));
+    }
+    Ok(())
+}
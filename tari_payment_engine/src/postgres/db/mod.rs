@@ -0,0 +1,54 @@
+//! # PostgreSQL Database methods
+//!
+//! This module contains "low-level" Postgres database interactions.
+//!
+//! As with the SQLite backend, all these interactions are simple functions that accept a `&mut PgConnection`
+//! argument. Callers can obtain a connection from a pool, or create an atomic transaction as the need arises and call
+//! through to the functions without any other changes.
+use std::env;
+
+use log::info;
+use sqlx::{postgres::PgPoolOptions, Error as SqlxError, PgPool};
+
+pub mod accounts;
+pub mod approvals;
+pub mod audit;
+pub mod auth;
+pub mod exchange_rates;
+pub mod held_orders;
+pub mod ledger;
+pub mod orders;
+pub mod outbox;
+pub mod rate_limits;
+pub mod refunds;
+pub mod sessions;
+pub mod transfers;
+pub mod wallet_auth;
+pub mod webhooks;
+
+const POSTGRES_DB_URL: &str = "postgres://localhost/tari_store";
+
+/// The SQLSTATE raised by the `enforce_increasing_nonce` trigger.
+pub(crate) const NONCE_VIOLATION_CODE: &str = "TPG01";
+
+pub fn db_url() -> String {
+    let result = env::var("TPG_DATABASE_URL").unwrap_or_else(|_| {
+        info!("TPG_DATABASE_URL is not set. Using the default.");
+        POSTGRES_DB_URL.to_string()
+    });
+    info!("Using database URL: {result}");
+    result
+}
+
+pub async fn new_pool(url: &str, max_connections: u32) -> Result<PgPool, SqlxError> {
+    let pool = PgPoolOptions::new().max_connections(max_connections).connect(url).await?;
+    Ok(pool)
+}
+
+/// Returns true if the error was raised by one of the nonce triggers
+pub(crate) fn is_nonce_violation(e: &SqlxError) -> bool {
+    match e {
+        SqlxError::Database(de) => de.code().map(|c| c.as_ref() == NONCE_VIOLATION_CODE).unwrap_or(false),
+        _ => false,
+    }
+}
@@ -0,0 +1,329 @@
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, trace};
+use sqlx::{postgres::PgRow, FromRow, PgConnection, QueryBuilder};
+use tari_common_types::tari_address::TariAddress;
+use tpg_common::MicroTari;
+
+use crate::{
+    db_types::{NewOrder, Order, OrderId, OrderStatusType},
+    order_objects::{ModifyOrderRequest, OrderQueryFilter},
+    traits::PaymentGatewayError,
+};
+
+/// Inserts the order into the database, returning `false` in the second parameter if the order already exists.
+pub async fn idempotent_insert(order: NewOrder, conn: &mut PgConnection) -> Result<(Order, bool), PaymentGatewayError> {
+    let order_id = order.order_id.clone();
+    if let Some(order) = insert_order(order, conn).await? {
+        debug!("📝️ Order [{}] inserted with id {}", order.order_id, order.id);
+        return Ok((order, true));
+    }
+    let existing = fetch_order_by_order_id(&order_id, conn).await?;
+    existing.map(|order| (order, false)).ok_or(PaymentGatewayError::OrderNotFound(order_id))
+}
+
+/// Inserts a new order into the database using the given connection, returning `None` if an order with the same
+/// `order_id` already exists. The existence check is part of the insert, so concurrent inserts of the same order
+/// cannot both succeed.
+async fn insert_order(order: NewOrder, conn: &mut PgConnection) -> Result<Option<Order>, PaymentGatewayError> {
+    let order_id = order.order_id.clone();
+    let order = sqlx::query_as(
+        r#"
+            INSERT INTO orders (
+                order_id,
+                alt_id,
+                customer_id,
+                memo,
+                total_price,
+                original_price,
+                currency,
+                created_at,
+                rate_id,
+                quote_expires_at,
+                merchant_id
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (order_id) DO NOTHING
+            RETURNING *;
+        "#,
+    )
+    .bind(order.order_id)
+    .bind(order.alt_order_id)
+    .bind(order.customer_id)
+    .bind(order.memo)
+    .bind(order.total_price.value())
+    .bind(order.original_price)
+    .bind(order.currency)
+    .bind(order.created_at)
+    .bind(order.rate_id)
+    .bind(order.quote_expires_at)
+    .bind(order.merchant_id)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(err) if err.is_unique_violation() => PaymentGatewayError::OrderAlreadyExists(order_id),
+        _ => PaymentGatewayError::from(e),
+    })?;
+    Ok(order)
+}
+
+/// Returns the entry in the orders table for the corresponding `order_id`
+pub async fn fetch_order_by_order_id(
+    order_id: &OrderId,
+    conn: &mut PgConnection,
+) -> Result<Option<Order>, sqlx::Error> {
+    let order =
+        sqlx::query_as("SELECT * FROM orders WHERE order_id = $1").bind(order_id.as_str()).fetch_optional(conn).await?;
+    Ok(order)
+}
+
+/// Returns the entry in the orders table for the corresponding `alt_order_id`
+pub async fn fetch_order_by_alt_id(alt: &OrderId, conn: &mut PgConnection) -> Result<Option<Order>, sqlx::Error> {
+    let order =
+        sqlx::query_as("SELECT * FROM orders WHERE alt_id = $1").bind(alt.as_str()).fetch_optional(conn).await?;
+    Ok(order)
+}
+
+/// Returns the entry in the orders table for the corresponding `order_id` or `alt_id`.
+/// If an order_id and alt_id match on different orders, then the one matching the order_id is returned.
+pub async fn fetch_order_by_id_or_alt(id: &OrderId, conn: &mut PgConnection) -> Result<Option<Order>, sqlx::Error> {
+    // Postgres sorts NULLs last in ascending order, so they must be explicitly placed first to match SQLite
+    let order = sqlx::query_as(
+        "SELECT * FROM orders WHERE order_id = $1 or alt_id = $1 ORDER BY alt_id ASC NULLS FIRST LIMIT 1",
+    )
+    .bind(id.as_str())
+    .fetch_optional(conn)
+    .await?;
+    Ok(order)
+}
+
+/// Fetches the orders that were created in the period `[from, to)`, oldest first.
+pub async fn fetch_orders_between(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    conn: &mut PgConnection,
+) -> Result<Vec<Order>, sqlx::Error> {
+    let orders =
+        sqlx::query_as("SELECT * FROM orders WHERE created_at >= $1 AND created_at < $2 ORDER BY created_at, id")
+            .bind(from)
+            .bind(to)
+            .fetch_all(conn)
+            .await?;
+    Ok(orders)
+}
+
+/// Checks whether the order with the given `OrderId` already exists in the database. If it does exist, the `id` of the
+/// order is returned. If it does not exist, `None` is returned.
+pub async fn order_exists(order_id: &OrderId, conn: &mut PgConnection) -> Result<Option<i64>, PaymentGatewayError> {
+    let order = fetch_order_by_order_id(order_id, conn).await?;
+    Ok(order.map(|o| o.id))
+}
+
+/// Fetches orders according to criteria specified in the `OrderQueryFilter`
+///
+/// Resulting orders are ordered by `created_at` in ascending order
+pub async fn search_orders(query: OrderQueryFilter, conn: &mut PgConnection) -> Result<Vec<Order>, sqlx::Error> {
+    let mut builder = QueryBuilder::new(
+        r#"
+    SELECT * FROM orders
+    "#,
+    );
+    if !query.is_empty() {
+        builder.push("WHERE ");
+    }
+    let mut where_clause = builder.separated(" AND ");
+    if let Some(memo) = query.memo {
+        where_clause.push("memo LIKE ");
+        where_clause.push_bind_unseparated(format!("%{memo}%"));
+    }
+    if let Some(order_id) = query.order_id {
+        where_clause.push("order_id = ");
+        where_clause.push_bind_unseparated(order_id.to_string());
+    }
+    if let Some(alt_id) = query.alt_id {
+        where_clause.push("alt_id = ");
+        where_clause.push_bind_unseparated(alt_id.to_string());
+    }
+    if let Some(cid) = query.customer_id {
+        where_clause.push("customer_id=");
+        where_clause.push_bind_unseparated(cid);
+    }
+    if let Some(currency) = query.currency {
+        where_clause.push("currency=");
+        where_clause.push_bind_unseparated(currency);
+    }
+    if let Some(merchant_id) = query.merchant_id {
+        where_clause.push("merchant_id=");
+        where_clause.push_bind_unseparated(merchant_id);
+    }
+    if query.status.as_ref().map(|s| !s.is_empty()).unwrap_or(false) {
+        let mut statuses = vec![];
+        query.status.as_ref().unwrap().iter().for_each(|s| {
+            statuses.push(format!("'{s}'"));
+        });
+        let status_clause = statuses.join(",");
+        where_clause.push(format!("status IN ({status_clause})"));
+    }
+    if let Some(since) = query.since {
+        where_clause.push("created_at >= ");
+        where_clause.push_bind_unseparated(since);
+    }
+    if let Some(until) = query.until {
+        where_clause.push("created_at <= ");
+        where_clause.push_bind_unseparated(until);
+    }
+    builder.push(" ORDER BY created_at ASC");
+
+    trace!("📝️ Executing query: {}", builder.sql());
+    let query = builder.build_query_as::<Order>();
+    let orders = query.fetch_all(conn).await?;
+    trace!("Result of fetch_orders: {:?}", orders.len());
+    Ok(orders)
+}
+
+/// Fetches the order with the given row id and locks its row until the end of the transaction, so that concurrent
+/// settlements of the same order are applied one after the other.
+pub(crate) async fn lock_order(id: i64, conn: &mut PgConnection) -> Result<Option<Order>, sqlx::Error> {
+    let order = sqlx::query_as("SELECT * FROM orders WHERE id = $1 FOR UPDATE").bind(id).fetch_optional(conn).await?;
+    Ok(order)
+}
+
+pub(crate) async fn update_order_status(
+    id: i64,
+    status: OrderStatusType,
+    conn: &mut PgConnection,
+) -> Result<Order, PaymentGatewayError> {
+    let result: Option<Order> =
+        sqlx::query_as("UPDATE orders SET status = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2 RETURNING *")
+            .bind(status)
+            .bind(id)
+            .fetch_optional(conn)
+            .await?;
+    result.ok_or(PaymentGatewayError::OrderIdNotFound(id))
+}
+
+pub(crate) async fn update_order(
+    id: &OrderId,
+    update: ModifyOrderRequest,
+    conn: &mut PgConnection,
+) -> Result<Option<Order>, PaymentGatewayError> {
+    if update.is_empty() {
+        debug!("📝️ No fields to update for order {id}. Update request skipped.");
+        return Err(PaymentGatewayError::OrderModificationNoOp);
+    }
+    let mut builder = QueryBuilder::new("UPDATE orders SET updated_at = CURRENT_TIMESTAMP, ");
+    let mut set_clause = builder.separated(", ");
+    if let Some(status) = update.new_status {
+        set_clause.push("status = ");
+        set_clause.push_bind_unseparated(status);
+    }
+    if let Some(memo) = update.new_memo {
+        set_clause.push("memo = ");
+        set_clause.push_bind_unseparated(memo);
+    }
+    if let Some(total_price) = update.new_total_price {
+        set_clause.push("total_price = ");
+        set_clause.push_bind_unseparated(total_price);
+    }
+    if let Some(original_price) = update.new_original_price {
+        set_clause.push("original_price = ");
+        set_clause.push_bind_unseparated(original_price);
+    }
+    if let Some(currency) = update.new_currency {
+        set_clause.push("currency = ");
+        set_clause.push_bind_unseparated(currency);
+    }
+    if let Some(cust_id) = update.new_customer_id {
+        set_clause.push("customer_id = ");
+        set_clause.push_bind_unseparated(cust_id);
+    }
+    builder.push(" WHERE order_id = ");
+    builder.push_bind(id.as_str());
+    builder.push(" RETURNING *");
+    trace!("📝️ Executing query: {}", builder.sql());
+    let res = builder.build().fetch_optional(conn).await?.map(|row: PgRow| Order::from_row(&row)).transpose()?;
+    trace!("📝️ Result of update_order: {res:?}");
+    Ok(res)
+}
+
+pub(crate) async fn expire_orders(
+    status: OrderStatusType,
+    limit: Duration,
+    conn: &mut PgConnection,
+) -> Result<Vec<Order>, PaymentGatewayError> {
+    let rows = sqlx::query_as(
+        "UPDATE orders SET updated_at = CURRENT_TIMESTAMP, status = 'Expired' WHERE status = $1 AND EXTRACT(EPOCH \
+         FROM (CURRENT_TIMESTAMP - updated_at)) > $2 RETURNING *;",
+    )
+    .bind(status)
+    .bind(limit.num_seconds())
+    .fetch_all(conn)
+    .await?;
+    Ok(rows)
+}
+
+/// Fetches the unpaid orders whose exchange rate quote has expired, oldest quotes first.
+pub(crate) async fn fetch_orders_with_expired_quotes(
+    conn: &mut PgConnection,
+) -> Result<Vec<Order>, PaymentGatewayError> {
+    let orders = sqlx::query_as(
+        "SELECT * FROM orders WHERE status IN ('New', 'Unclaimed') AND quote_expires_at <= CURRENT_TIMESTAMP ORDER BY \
+         quote_expires_at",
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(orders)
+}
+
+/// Sets a new price and exchange rate quote for the order. Unlike [`update_order`], this leaves `updated_at` alone,
+/// so that re-pricing an order does not postpone its expiry.
+pub(crate) async fn update_quote(
+    order_id: &OrderId,
+    total_price: MicroTari,
+    rate_id: i64,
+    quote_expires_at: DateTime<Utc>,
+    conn: &mut PgConnection,
+) -> Result<Order, PaymentGatewayError> {
+    let order = sqlx::query_as(
+        "UPDATE orders SET total_price = $1, rate_id = $2, quote_expires_at = $3 WHERE order_id = $4 RETURNING *",
+    )
+    .bind(total_price.value())
+    .bind(rate_id)
+    .bind(quote_expires_at)
+    .bind(order_id.as_str())
+    .fetch_one(conn)
+    .await?;
+    Ok(order)
+}
+
+/// Fetches all payable orders for the given address. A payable order is one that is "New" or "Unclaimed"
+/// i.e. it has not been paid and is associated with the address.
+pub(crate) async fn fetch_payable_orders_for_address(
+    address: &TariAddress,
+    conn: &mut PgConnection,
+) -> Result<Vec<Order>, PaymentGatewayError> {
+    let result: Vec<Order> = sqlx::query_as(
+        r#"
+        SELECT
+            orders.id as id,
+            order_id,
+            alt_id,
+            orders.customer_id as customer_id,
+            memo,
+            total_price,
+            original_price,
+            currency,
+            orders.created_at as created_at,
+            orders.updated_at as updated_at,
+            status,
+            rate_id,
+            quote_expires_at,
+            merchant_id
+        FROM orders JOIN address_customer_id_link ON orders.customer_id = address_customer_id_link.customer_id
+        WHERE
+         status in ('New', 'Unclaimed') AND
+         address = $1"#,
+    )
+    .bind(address.to_base58())
+    .fetch_all(conn)
+    .await?;
+    Ok(result)
+}
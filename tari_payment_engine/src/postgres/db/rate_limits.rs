@@ -0,0 +1,49 @@
+//! Postgres database operations for shared rate limits
+//!
+//! Generally clients should never call these methods directly, and prefer to use the
+//! [`crate::traits::RateLimitStore`] trait methods that are implemented on the [`crate::PostgresDatabase`] struct
+//! instead.
+use sqlx::PgConnection;
+
+use crate::tpe_api::rate_limit_objects::{RateLimit, RateLimitDecision};
+
+/// Refills the bucket for `key` and takes a token from it in a single statement. Concurrent requests for the same key
+/// wait on the row lock, so they cannot take the same token. New buckets start out full.
+pub(crate) async fn take_token(
+    key: &str,
+    limit: &RateLimit,
+    conn: &mut PgConnection,
+) -> Result<RateLimitDecision, sqlx::Error> {
+    let (tokens, allowed): (f64, bool) = sqlx::query_as(
+        r#"
+        INSERT INTO rate_limit_buckets (key, tokens, allowed, updated_at, expires_at)
+        VALUES ($1, $2 - 1.0, true, now(), now() + make_interval(secs => $4))
+        ON CONFLICT (key) DO UPDATE SET
+            tokens = CASE
+                WHEN LEAST($2, rate_limit_buckets.tokens +
+                    EXTRACT(EPOCH FROM now() - rate_limit_buckets.updated_at)::DOUBLE PRECISION * $3) >= 1.0
+                THEN LEAST($2, rate_limit_buckets.tokens +
+                    EXTRACT(EPOCH FROM now() - rate_limit_buckets.updated_at)::DOUBLE PRECISION * $3) - 1.0
+                ELSE LEAST($2, rate_limit_buckets.tokens +
+                    EXTRACT(EPOCH FROM now() - rate_limit_buckets.updated_at)::DOUBLE PRECISION * $3)
+            END,
+            allowed = LEAST($2, rate_limit_buckets.tokens +
+                EXTRACT(EPOCH FROM now() - rate_limit_buckets.updated_at)::DOUBLE PRECISION * $3) >= 1.0,
+            updated_at = now(),
+            expires_at = now() + make_interval(secs => $4)
+        RETURNING tokens, allowed
+        "#,
+    )
+    .bind(key)
+    .bind(limit.capacity())
+    .bind(limit.refill_rate())
+    .bind(limit.period.as_secs_f64())
+    .fetch_one(conn)
+    .await?;
+    Ok(limit.decision(tokens, allowed))
+}
+
+pub(crate) async fn prune_rate_limits(conn: &mut PgConnection) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM rate_limit_buckets WHERE expires_at <= now()").execute(conn).await?;
+    Ok(result.rows_affected())
+}
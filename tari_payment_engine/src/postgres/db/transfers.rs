@@ -0,0 +1,174 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use tari_common_types::tari_address::TariAddress;
+
+use crate::{
+    db_types::{CreditNote, NewPayment, OrderId, Payment, PaymentType, TransferStatus},
+    helpers::create_dummy_address_for_cust_id,
+    traits::PaymentGatewayError,
+};
+
+pub async fn idempotent_insert(transfer: NewPayment, conn: &mut PgConnection) -> Result<Payment, PaymentGatewayError> {
+    let txid = transfer.txid.clone();
+    let address = transfer.sender.as_address().to_base58();
+    let payment = sqlx::query_as(
+        r#"
+            INSERT INTO payments (txid, sender, amount, memo, order_id, merchant_id) VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *;
+        "#,
+    )
+    .bind(transfer.txid)
+    .bind(address)
+    .bind(transfer.amount)
+    .bind(transfer.memo)
+    .bind(transfer.order_id)
+    .bind(transfer.merchant_id)
+    .fetch_one(conn)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(err) if err.is_unique_violation() => PaymentGatewayError::PaymentAlreadyExists(txid),
+        _ => PaymentGatewayError::from(e),
+    })?;
+    Ok(payment)
+}
+
+/// Issues a credit note against the customer id. Since payments require a sender address,
+/// a dummy address is created that is unique to the customer id and easily identifiable as a dummy address.
+///
+/// If the credit note is successfully issued, the address of the dummy address is returned.
+pub async fn credit_note(note: &CreditNote, conn: &mut PgConnection) -> Result<Payment, PaymentGatewayError> {
+    let timestamp = Utc::now().timestamp();
+    let txid = format!("credit_note_{}:{}:{timestamp}", note.customer_id, note.amount);
+    let address = create_dummy_address_for_cust_id(&note.customer_id);
+    let base58_addr = address.to_base58();
+    let memo = format!("Credit note: {}", note.reason.as_deref().unwrap_or("No reason given"));
+    let payment = sqlx::query_as(
+        r#"
+            INSERT INTO payments (txid, sender, amount, memo, payment_type, status, merchant_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *;
+        "#,
+    )
+    .bind(txid.clone())
+    .bind(base58_addr)
+    .bind(note.amount)
+    .bind(memo)
+    .bind(PaymentType::Manual)
+    .bind(TransferStatus::Confirmed)
+    .bind(&note.merchant_id)
+    .fetch_one(conn)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(err) if err.is_unique_violation() => PaymentGatewayError::PaymentAlreadyExists(txid),
+        _ => PaymentGatewayError::from(e),
+    })?;
+    Ok(payment)
+}
+
+pub async fn update_status(
+    txid: &str,
+    status: TransferStatus,
+    conn: &mut PgConnection,
+) -> Result<Payment, PaymentGatewayError> {
+    let payment =
+        sqlx::query_as("UPDATE payments SET status = $1, updated_at = CURRENT_TIMESTAMP WHERE txid = $2 RETURNING *")
+            .bind(status)
+            .bind(txid)
+            .fetch_optional(conn)
+            .await?
+            .ok_or(PaymentGatewayError::PaymentStatusUpdateError(format!("Payment for {txid} does not exist")))?;
+    Ok(payment)
+}
+
+/// Records the height of the block that the payment's transaction was mined in, and how many blocks deep it is.
+pub async fn update_confirmations(
+    txid: &str,
+    block_height: Option<i64>,
+    confirmations: i64,
+    conn: &mut PgConnection,
+) -> Result<Payment, PaymentGatewayError> {
+    let payment = sqlx::query_as(
+        "UPDATE payments SET block_height = $1, confirmations = $2, updated_at = CURRENT_TIMESTAMP WHERE txid = $3 \
+         RETURNING *",
+    )
+    .bind(block_height)
+    .bind(confirmations)
+    .bind(txid)
+    .fetch_optional(conn)
+    .await?
+    .ok_or_else(|| PaymentGatewayError::PaymentNotFound(txid.to_string()))?;
+    Ok(payment)
+}
+
+pub async fn fetch_payment(txid: &str, conn: &mut PgConnection) -> Result<Option<Payment>, PaymentGatewayError> {
+    let payment = sqlx::query_as(r#"SELECT * FROM payments WHERE txid = $1"#).bind(txid).fetch_optional(conn).await?;
+    Ok(payment)
+}
+
+/// Fetches the payment and locks its row until the end of the transaction. Concurrent status changes to the same
+/// payment, e.g. from a wallet notification and the wallet watcher, are applied one after the other.
+pub async fn lock_payment(txid: &str, conn: &mut PgConnection) -> Result<Option<Payment>, PaymentGatewayError> {
+    let payment =
+        sqlx::query_as("SELECT * FROM payments WHERE txid = $1 FOR UPDATE").bind(txid).fetch_optional(conn).await?;
+    Ok(payment)
+}
+
+pub async fn fetch_payments_for_address(
+    address: &TariAddress,
+    conn: &mut PgConnection,
+) -> Result<Vec<Payment>, sqlx::Error> {
+    let payments =
+        sqlx::query_as(r#"SELECT * FROM payments WHERE sender = $1"#).bind(address.to_base58()).fetch_all(conn).await?;
+    Ok(payments)
+}
+
+pub async fn pending_payments(address: &TariAddress, conn: &mut PgConnection) -> Result<Vec<Payment>, sqlx::Error> {
+    let address = address.to_base58();
+    let payments = sqlx::query_as(
+        r#"SELECT * FROM payments
+    WHERE status = 'Received'
+    AND sender = $1
+    ORDER BY created_at"#,
+    )
+    .bind(address)
+    .fetch_all(conn)
+    .await?;
+    Ok(payments)
+}
+
+pub async fn fetch_onchain_payments(
+    since: Option<DateTime<Utc>>,
+    conn: &mut PgConnection,
+) -> Result<Vec<Payment>, sqlx::Error> {
+    let payments = sqlx::query_as(
+        "SELECT * FROM payments WHERE payment_type = 'OnChain' AND ($1 IS NULL OR created_at >= $1) ORDER BY \
+         created_at, txid",
+    )
+    .bind(since)
+    .fetch_all(conn)
+    .await?;
+    Ok(payments)
+}
+
+/// Fetches the payments, including credit notes, that were received in the period `[from, to)`, oldest first.
+pub async fn fetch_payments_between(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    conn: &mut PgConnection,
+) -> Result<Vec<Payment>, sqlx::Error> {
+    let payments =
+        sqlx::query_as("SELECT * FROM payments WHERE created_at >= $1 AND created_at < $2 ORDER BY created_at, txid")
+            .bind(from)
+            .bind(to)
+            .fetch_all(conn)
+            .await?;
+    Ok(payments)
+}
+
+pub async fn fetch_payments_for_order(
+    order_id: &OrderId,
+    conn: &mut PgConnection,
+) -> Result<Vec<Payment>, sqlx::Error> {
+    let payments =
+        sqlx::query_as(r#"SELECT * FROM payments WHERE order_id = $1"#).bind(order_id.as_str()).fetch_all(conn).await?;
+    Ok(payments)
+}
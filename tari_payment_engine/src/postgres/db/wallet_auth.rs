@@ -0,0 +1,101 @@
+use std::net::IpAddr;
+
+use sqlx::{query, PgConnection, Row};
+use tari_common_types::tari_address::TariAddress;
+
+use super::is_nonce_violation;
+use crate::{
+    db_types::SerializedTariAddress,
+    traits::{NewWalletInfo, WalletAuthApiError, WalletInfo, WalletManagementError},
+};
+
+pub async fn fetch_wallet_info_for_address(
+    address: &TariAddress,
+    conn: &mut PgConnection,
+) -> Result<WalletInfo, WalletAuthApiError> {
+    let address = address.to_base58();
+    query(r#"SELECT * FROM wallet_auth WHERE address = $1"#)
+        .bind(address)
+        .fetch_optional(conn)
+        .await?
+        .and_then(|row| {
+            let ip_address = row.get::<&str, _>("ip_address").parse::<IpAddr>().ok()?;
+            let address = row.get("address");
+            let last_nonce = row.get("last_nonce");
+            let merchant_id = row.get("merchant_id");
+            Some(WalletInfo { address, ip_address, last_nonce, merchant_id })
+        })
+        .ok_or(WalletAuthApiError::WalletNotFound)
+}
+
+pub async fn update_wallet_nonce(
+    address: &TariAddress,
+    new_nonce: i64,
+    conn: &mut PgConnection,
+) -> Result<(), WalletAuthApiError> {
+    let address = address.to_base58();
+    let result = query(r#"UPDATE wallet_auth SET last_nonce = $1 WHERE address = $2"#)
+        .bind(new_nonce)
+        .bind(address)
+        .execute(conn)
+        .await
+        .map_err(
+            |e| if is_nonce_violation(&e) { WalletAuthApiError::InvalidNonce } else { WalletAuthApiError::from(e) },
+        )?;
+    if result.rows_affected() == 0 {
+        return Err(WalletAuthApiError::WalletNotFound);
+    }
+    Ok(())
+}
+
+pub(crate) async fn register_wallet(info: NewWalletInfo, conn: &mut PgConnection) -> Result<(), WalletManagementError> {
+    let address = info.address.as_base58();
+    let ip_address = info.ip_address.to_string();
+    let nonce = info.initial_nonce.unwrap_or(0);
+    let result =
+        query(r#"INSERT INTO wallet_auth (address, ip_address, last_nonce, merchant_id) VALUES ($1, $2, $3, $4)"#)
+            .bind(address)
+            .bind(ip_address)
+            .bind(nonce)
+            .bind(&info.merchant_id)
+            .execute(conn)
+            .await?;
+    if result.rows_affected() == 0 {
+        return Err(WalletManagementError::DatabaseError("Wallet could not be registered".to_string()));
+    }
+    Ok(())
+}
+
+pub(crate) async fn deregister_wallet(
+    address: &TariAddress,
+    conn: &mut PgConnection,
+) -> Result<(), WalletManagementError> {
+    let address = address.to_base58();
+    let result = query(r#"DELETE FROM wallet_auth WHERE address = $1"#).bind(address).execute(conn).await?;
+    if result.rows_affected() == 0 {
+        return Err(WalletManagementError::DatabaseError("Wallet not found".to_string()));
+    }
+    Ok(())
+}
+
+pub(crate) async fn fetch_authorized_wallets(
+    conn: &mut PgConnection,
+) -> Result<Vec<WalletInfo>, WalletManagementError> {
+    query("SELECT * FROM wallet_auth")
+        .fetch_all(conn)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let ip_address = row
+                .get::<&str, _>("ip_address")
+                .parse::<IpAddr>()
+                .map_err(|e| WalletManagementError::DatabaseError(format!("Invalid IP address. {e}")))?;
+            let address = TariAddress::from_base58(row.get("address"))
+                .map_err(|e| WalletManagementError::DatabaseError(format!("Invalid TariAddress. {e}")))?;
+            let address = SerializedTariAddress::from(address);
+            let last_nonce = row.get("last_nonce");
+            let merchant_id = row.get("merchant_id");
+            Ok(WalletInfo { address, ip_address, last_nonce, merchant_id })
+        })
+        .collect::<Result<Vec<WalletInfo>, WalletManagementError>>()
+}
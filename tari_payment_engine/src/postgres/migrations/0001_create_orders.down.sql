@@ -0,0 +1,11 @@
+DROP TRIGGER IF EXISTS orders_no_delete ON orders;
+DROP FUNCTION IF EXISTS forbid_delete;
+
+DROP INDEX IF EXISTS orders_customer_idx;
+DROP INDEX IF EXISTS orders_status_idx;
+DROP INDEX IF EXISTS orders_cid_oid_idx;
+DROP INDEX IF EXISTS orders_order_history;
+DROP INDEX IF EXISTS orders_order_id_idx;
+
+DROP TABLE IF EXISTS orders;
+DROP TYPE IF EXISTS OrderStatusType;
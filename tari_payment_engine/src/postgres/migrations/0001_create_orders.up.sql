@@ -0,0 +1,34 @@
+CREATE TYPE OrderStatusType AS ENUM ('Paid', 'Cancelled', 'Expired', 'New', 'Unclaimed');
+
+CREATE TABLE orders (
+    id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    order_id TEXT UNIQUE NOT NULL,
+    customer_id TEXT NOT NULL,
+    memo TEXT,
+    total_price BIGINT NOT NULL,
+    -- The price of the order in the storefront currency. Optional.
+    original_price TEXT,
+    -- The currency of the order in the store
+    currency TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    status OrderStatusType NOT NULL DEFAULT 'Unclaimed'
+);
+
+CREATE INDEX orders_order_id_idx ON orders (order_id);
+CREATE INDEX orders_order_history ON orders (order_id, id);
+CREATE INDEX orders_cid_oid_idx ON orders (customer_id, order_id);
+
+CREATE INDEX orders_status_idx ON orders (status);
+CREATE INDEX orders_customer_idx ON orders (customer_id);
+
+-- Shared trigger function that rejects DELETE queries. The message is passed in as the first trigger argument.
+CREATE FUNCTION forbid_delete() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION '%', TG_ARGV[0];
+END;
+$$ LANGUAGE plpgsql;
+
+-- Do not allow deletes on the orders table
+CREATE TRIGGER orders_no_delete BEFORE DELETE ON orders
+    FOR EACH ROW EXECUTE FUNCTION forbid_delete('Delete not allowed on orders table. Set status to Cancelled instead');
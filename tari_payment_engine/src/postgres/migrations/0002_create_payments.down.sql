@@ -0,0 +1,9 @@
+DROP TRIGGER IF EXISTS payments_no_delete ON payments;
+DROP INDEX IF EXISTS payments_status_idx;
+DROP INDEX IF EXISTS payments_sender_idx;
+DROP INDEX IF EXISTS payments_id_orderid;
+DROP INDEX IF EXISTS payments_id_idx;
+
+DROP TABLE IF EXISTS payments;
+DROP TYPE IF EXISTS TransferStatus;
+DROP TYPE IF EXISTS PaymentType;
@@ -0,0 +1,23 @@
+CREATE TYPE PaymentType AS ENUM ('OnChain', 'Manual');
+CREATE TYPE TransferStatus AS ENUM ('Received', 'Confirmed', 'Cancelled');
+
+CREATE TABLE payments (
+    txid         TEXT PRIMARY KEY NOT NULL,
+    created_at   TIMESTAMPTZ    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at   TIMESTAMPTZ    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    sender       TEXT           NOT NULL,
+    amount       BIGINT         NOT NULL,
+    memo         TEXT,
+    order_id     TEXT,
+    payment_type PaymentType    NOT NULL DEFAULT 'OnChain',
+    status       TransferStatus NOT NULL DEFAULT 'Received'
+);
+
+-- Do not allow deletes on the payments table
+CREATE TRIGGER payments_no_delete BEFORE DELETE ON payments
+    FOR EACH ROW EXECUTE FUNCTION forbid_delete('Delete not allowed on payments table. Set status to Cancelled instead');
+
+CREATE INDEX payments_id_idx ON payments (txid);
+CREATE INDEX payments_id_orderid ON payments (order_id);
+CREATE INDEX payments_sender_idx ON payments (sender);
+CREATE INDEX payments_status_idx ON payments (status);
@@ -0,0 +1,14 @@
+DROP VIEW IF EXISTS customer_order_balance;
+DROP VIEW IF EXISTS address_balance;
+DROP VIEW IF EXISTS address_pending_balance;
+
+DROP INDEX IF EXISTS settlement_journal_order_id_idx;
+DROP INDEX IF EXISTS settlement_journal_address_idx;
+DROP TABLE IF EXISTS settlement_journal;
+DROP TYPE IF EXISTS SettlementType;
+
+DROP INDEX IF EXISTS join_links;
+DROP INDEX IF EXISTS custid_links;
+DROP INDEX IF EXISTS address_links;
+
+DROP TABLE IF EXISTS address_customer_id_link;
@@ -0,0 +1,66 @@
+CREATE TABLE IF NOT EXISTS address_customer_id_link
+(
+    id              BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    address         TEXT NOT NULL,
+    customer_id     TEXT NOT NULL,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    -- Inserts use ON CONFLICT DO NOTHING to keep linking idempotent
+    UNIQUE (address, customer_id)
+);
+
+CREATE INDEX IF NOT EXISTS address_links ON address_customer_id_link (address);
+CREATE INDEX IF NOT EXISTS custid_links ON address_customer_id_link (customer_id);
+CREATE INDEX IF NOT EXISTS join_links ON address_customer_id_link (address, customer_id);
+
+CREATE TYPE SettlementType AS ENUM ('Multiple', 'Single');
+
+CREATE TABLE settlement_journal (
+    id              BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    created_at      TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    order_id        TEXT NOT NULL REFERENCES orders (order_id),
+    payment_address TEXT NOT NULL,
+    settlement_type SettlementType NOT NULL,
+    amount          BIGINT NOT NULL
+);
+
+CREATE INDEX settlement_journal_address_idx ON settlement_journal (payment_address);
+CREATE INDEX settlement_journal_order_id_idx ON settlement_journal (order_id);
+
+-- Postgres' SUM over BIGINT returns NUMERIC, so the aggregates are cast back to BIGINT to decode as MicroTari
+CREATE VIEW address_pending_balance (address, status, balance) AS
+SELECT
+    sender,
+    status,
+    SUM(amount)::BIGINT
+FROM payments
+WHERE status = 'Received'
+GROUP BY sender, status;
+
+CREATE VIEW address_balance (address, total_confirmed, total_paid, current_balance, last_update) AS
+WITH
+    wallets AS (
+    SELECT sender, SUM(amount)::BIGINT AS total_confirmed, MAX(updated_at) AS updated_at
+    FROM payments
+    WHERE status = 'Confirmed'
+    GROUP BY sender
+),
+    settlements AS (
+    SELECT SUM(amount)::BIGINT AS total, payment_address, MAX(created_at) AS created_at
+    FROM settlement_journal
+    GROUP BY payment_address
+)
+SELECT
+    wallets.sender AS address,
+    wallets.total_confirmed AS total_confirmed,
+    COALESCE(settlements.total, 0) AS total_paid,
+    wallets.total_confirmed - COALESCE(settlements.total, 0) AS current_balance,
+    COALESCE(settlements.created_at, wallets.updated_at) AS last_update
+FROM wallets
+LEFT OUTER JOIN settlements ON wallets.sender = settlements.payment_address;
+
+CREATE VIEW customer_order_balance (customer_id, status, total_orders) AS
+SELECT
+    customer_id,
+    status,
+    SUM(total_price)::BIGINT
+FROM orders GROUP BY customer_id, status;
@@ -0,0 +1,114 @@
+-- order_id = 1
+-- customer_id = 2
+-- memo = 4
+-- total_price = 8
+-- currency = 16
+-- status = 32
+-- original_price = 64
+
+CREATE TABLE orders_log
+(
+    id              BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    oid             BIGINT      NOT NULL REFERENCES orders (id),
+    columns_changed INTEGER     NOT NULL,
+    old_order_id    TEXT,
+    new_order_id    TEXT,
+    old_customer_id TEXT,
+    new_customer_id TEXT,
+    old_memo        TEXT,
+    new_memo        TEXT,
+    old_total_price BIGINT,
+    new_total_price BIGINT,
+    old_original_price TEXT,
+    new_original_price TEXT,
+    old_currency    TEXT,
+    new_currency    TEXT,
+    old_status      TEXT,
+    new_status      TEXT,
+    updated_at      TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX orders_log_oid ON orders_log (oid);
+CREATE INDEX orders_log_updated_at ON orders_log (updated_at);
+CREATE INDEX orders_log_columns_changed ON orders_log (columns_changed);
+
+-- Trigger function to log changes to orders
+CREATE FUNCTION orders_log_update() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO orders_log (oid,
+                            columns_changed,
+                            old_order_id,
+                            new_order_id,
+                            old_customer_id,
+                            new_customer_id,
+                            old_memo,
+                            new_memo,
+                            old_total_price,
+                            new_total_price,
+                            old_original_price,
+                            new_original_price,
+                            old_currency,
+                            new_currency,
+                            old_status,
+                            new_status,
+                            updated_at)
+    VALUES (NEW.id,
+            (CASE WHEN OLD.order_id IS DISTINCT FROM NEW.order_id THEN 1 ELSE 0 END) +
+            (CASE WHEN OLD.customer_id IS DISTINCT FROM NEW.customer_id THEN 2 ELSE 0 END) +
+            (CASE WHEN OLD.memo IS DISTINCT FROM NEW.memo THEN 4 ELSE 0 END) +
+            (CASE WHEN OLD.total_price IS DISTINCT FROM NEW.total_price THEN 8 ELSE 0 END) +
+            (CASE WHEN OLD.currency IS DISTINCT FROM NEW.currency THEN 16 ELSE 0 END) +
+            (CASE WHEN OLD.status IS DISTINCT FROM NEW.status THEN 32 ELSE 0 END) +
+            (CASE WHEN OLD.original_price IS DISTINCT FROM NEW.original_price THEN 64 ELSE 0 END),
+            NULLIF(OLD.order_id, NEW.order_id),
+            NULLIF(NEW.order_id, OLD.order_id),
+            NULLIF(OLD.customer_id, NEW.customer_id),
+            NULLIF(NEW.customer_id, OLD.customer_id),
+            NULLIF(OLD.memo, NEW.memo),
+            NULLIF(NEW.memo, OLD.memo),
+            NULLIF(OLD.total_price, NEW.total_price),
+            NULLIF(NEW.total_price, OLD.total_price),
+            NULLIF(OLD.original_price, NEW.original_price),
+            NULLIF(NEW.original_price, OLD.original_price),
+            NULLIF(OLD.currency, NEW.currency),
+            NULLIF(NEW.currency, OLD.currency),
+            NULLIF(OLD.status::TEXT, NEW.status::TEXT),
+            NULLIF(NEW.status::TEXT, OLD.status::TEXT),
+            NEW.updated_at);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER orders_log_update
+    AFTER UPDATE
+    ON orders
+    FOR EACH ROW EXECUTE FUNCTION orders_log_update();
+
+CREATE FUNCTION orders_log_insert() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO orders_log (oid,
+                            columns_changed,
+                            new_order_id,
+                            new_customer_id,
+                            new_memo,
+                            new_total_price,
+                            new_currency,
+                            new_status,
+                            updated_at)
+    VALUES (NEW.id,
+            1 + 2 + 4 + 8 + 16 + 32,
+            NEW.order_id,
+            NEW.customer_id,
+            NEW.memo,
+            NEW.total_price,
+            NEW.currency,
+            NEW.status::TEXT,
+            NEW.updated_at);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER orders_log_insert
+    AFTER INSERT
+    ON orders
+    FOR EACH ROW EXECUTE FUNCTION orders_log_insert();
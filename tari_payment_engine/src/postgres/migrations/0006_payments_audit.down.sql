@@ -0,0 +1,10 @@
+DROP TRIGGER IF EXISTS payments_log_insert ON payments;
+DROP TRIGGER IF EXISTS payments_log_update ON payments;
+DROP FUNCTION IF EXISTS payments_log_insert;
+DROP FUNCTION IF EXISTS payments_log_update;
+
+DROP INDEX IF EXISTS payments_log_columns_changed;
+DROP INDEX IF EXISTS payments_log_updated_at;
+DROP INDEX IF EXISTS payments_log_txid;
+
+DROP TABLE IF EXISTS payments_log;
@@ -0,0 +1,109 @@
+-- sender = 1
+-- amount = 2
+-- memo = 4
+-- payment_type = 8
+-- status = 16
+-- order_id = 32
+
+CREATE TABLE payments_log
+(
+    id               BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    txid             TEXT        NOT NULL REFERENCES payments (txid),
+    columns_changed  INTEGER     NOT NULL,
+    old_sender       TEXT,
+    new_sender       TEXT,
+    old_amount       BIGINT,
+    new_amount       BIGINT,
+    old_memo         TEXT,
+    new_memo         TEXT,
+    old_payment_type TEXT,
+    new_payment_type TEXT,
+    old_status       TEXT,
+    new_status       TEXT,
+    old_order_id     TEXT,
+    new_order_id     TEXT,
+    updated_at       TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX payments_log_txid ON payments_log (txid);
+CREATE INDEX payments_log_updated_at ON payments_log (updated_at);
+CREATE INDEX payments_log_columns_changed ON payments_log (columns_changed);
+
+-- Trigger function to log changes to payments
+CREATE FUNCTION payments_log_update() RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.txid != OLD.txid THEN
+        RAISE EXCEPTION 'txid cannot be changed';
+    END IF;
+    INSERT INTO payments_log (txid,
+                              columns_changed,
+                              old_sender,
+                              new_sender,
+                              old_amount,
+                              new_amount,
+                              old_memo,
+                              new_memo,
+                              old_payment_type,
+                              new_payment_type,
+                              old_status,
+                              new_status,
+                              old_order_id,
+                              new_order_id,
+                              updated_at)
+    VALUES (NEW.txid,
+            (CASE WHEN OLD.sender IS DISTINCT FROM NEW.sender THEN 1 ELSE 0 END) +
+            (CASE WHEN OLD.amount IS DISTINCT FROM NEW.amount THEN 2 ELSE 0 END) +
+            (CASE WHEN OLD.memo IS DISTINCT FROM NEW.memo THEN 4 ELSE 0 END) +
+            (CASE WHEN OLD.payment_type IS DISTINCT FROM NEW.payment_type THEN 8 ELSE 0 END) +
+            (CASE WHEN OLD.status IS DISTINCT FROM NEW.status THEN 16 ELSE 0 END) +
+            (CASE WHEN OLD.order_id IS DISTINCT FROM NEW.order_id THEN 32 ELSE 0 END),
+            NULLIF(OLD.sender, NEW.sender),
+            NULLIF(NEW.sender, OLD.sender),
+            NULLIF(OLD.amount, NEW.amount),
+            NULLIF(NEW.amount, OLD.amount),
+            NULLIF(OLD.memo, NEW.memo),
+            NULLIF(NEW.memo, OLD.memo),
+            NULLIF(OLD.payment_type::TEXT, NEW.payment_type::TEXT),
+            NULLIF(NEW.payment_type::TEXT, OLD.payment_type::TEXT),
+            NULLIF(OLD.status::TEXT, NEW.status::TEXT),
+            NULLIF(NEW.status::TEXT, OLD.status::TEXT),
+            NULLIF(OLD.order_id, NEW.order_id),
+            NULLIF(NEW.order_id, OLD.order_id),
+            NEW.updated_at);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER payments_log_update
+    AFTER UPDATE
+    ON payments
+    FOR EACH ROW EXECUTE FUNCTION payments_log_update();
+
+CREATE FUNCTION payments_log_insert() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO payments_log (txid,
+                              columns_changed,
+                              new_sender,
+                              new_amount,
+                              new_memo,
+                              new_payment_type,
+                              new_status,
+                              new_order_id,
+                              updated_at
+    ) VALUES (NEW.txid,
+              1 + 2 + 4 + 8 + 16 + (CASE WHEN NEW.order_id IS NOT NULL THEN 32 ELSE 0 END),
+              NEW.sender,
+              NEW.amount,
+              NEW.memo,
+              NEW.payment_type::TEXT,
+              NEW.status::TEXT,
+              NEW.order_id,
+              NEW.updated_at);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER payments_log_insert
+    AFTER INSERT
+    ON payments
+    FOR EACH ROW EXECUTE FUNCTION payments_log_insert();
@@ -0,0 +1,43 @@
+CREATE TABLE auth_log (
+    address TEXT NOT NULL UNIQUE,
+    last_nonce BIGINT NOT NULL
+);
+
+-- Nonce violations are raised with a dedicated SQLSTATE ('TPG01') so that the backend can map them to InvalidNonce.
+CREATE FUNCTION enforce_increasing_nonce() RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.last_nonce <= OLD.last_nonce THEN
+        RAISE EXCEPTION 'nonce must strictly increase' USING ERRCODE = 'TPG01';
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER auth_log_update_nonce
+    BEFORE UPDATE OF last_nonce ON auth_log
+    FOR EACH ROW EXECUTE FUNCTION enforce_increasing_nonce();
+
+CREATE TABLE roles (
+    id BIGINT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL
+);
+
+INSERT INTO roles VALUES
+      (1, 'user'),
+      (2, 'read_all'),
+      (3, 'write'),
+      (4, 'payment_wallet'),
+      (5, 'super_admin')
+;
+
+CREATE TABLE role_assignments
+(
+    address TEXT NOT NULL,
+    role_id BIGINT NOT NULL REFERENCES roles (id) ON DELETE CASCADE,
+    -- Inserts use ON CONFLICT DO NOTHING, mirroring the SQLite ON CONFLICT IGNORE clause
+    PRIMARY KEY (address, role_id)
+);
+
+CREATE INDEX auth_log_address_idx ON auth_log(address);
+CREATE INDEX role_assignments_address_idx ON role_assignments(address);
+CREATE INDEX role_assignments_role_id_idx ON role_assignments(role_id);
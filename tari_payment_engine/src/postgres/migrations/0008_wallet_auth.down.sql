@@ -0,0 +1,13 @@
+DROP TRIGGER IF EXISTS on_wallet_auth_update ON wallet_auth;
+DROP TRIGGER IF EXISTS on_wallet_auth_insert ON wallet_auth;
+DROP FUNCTION IF EXISTS on_wallet_auth_update;
+DROP FUNCTION IF EXISTS on_wallet_auth_insert;
+
+DROP INDEX IF EXISTS wallet_auth_updated_idx;
+DROP INDEX IF EXISTS wallet_auth_address_idx;
+
+DROP TABLE IF EXISTS wallet_auth_log;
+
+DROP TRIGGER IF EXISTS wallet_auth_update_nonce ON wallet_auth;
+
+DROP TABLE IF EXISTS wallet_auth;
@@ -0,0 +1,52 @@
+-- Tracks state for hot wallet authentication
+CREATE TABLE wallet_auth (
+    address TEXT NOT NULL PRIMARY KEY,
+    ip_address TEXT NOT NULL,
+    last_nonce BIGINT NOT NULL
+);
+
+CREATE TRIGGER wallet_auth_update_nonce
+    BEFORE UPDATE OF last_nonce ON wallet_auth
+    FOR EACH ROW EXECUTE FUNCTION enforce_increasing_nonce();
+
+CREATE TABLE wallet_auth_log (
+    id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    changed TEXT NOT NULL,
+    address TEXT NOT NULL,
+    ip_address TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX wallet_auth_address_idx ON wallet_auth_log(address);
+CREATE INDEX wallet_auth_updated_idx ON wallet_auth_log(updated_at);
+
+CREATE FUNCTION on_wallet_auth_insert() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO wallet_auth_log (address, ip_address, changed)
+    VALUES (NEW.address, NEW.ip_address, 'New Entry');
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER on_wallet_auth_insert
+    AFTER INSERT ON wallet_auth
+    FOR EACH ROW EXECUTE FUNCTION on_wallet_auth_insert();
+
+CREATE FUNCTION on_wallet_auth_update() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO wallet_auth_log (address, ip_address, changed)
+    VALUES (
+      NEW.address,
+      NEW.ip_address,
+      concat_ws(',',
+        CASE WHEN OLD.address != NEW.address THEN 'address' END,
+        CASE WHEN OLD.ip_address != NEW.ip_address THEN 'ip_address' END
+      )
+    );
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER on_wallet_auth_update
+    AFTER UPDATE OF address, ip_address ON wallet_auth
+    FOR EACH ROW EXECUTE FUNCTION on_wallet_auth_update();
@@ -0,0 +1,3 @@
+DROP TRIGGER IF EXISTS exchange_rates_no_delete ON exchange_rates;
+DROP INDEX IF EXISTS exchange_rates_currency;
+DROP TABLE IF EXISTS exchange_rates;
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS exchange_rates (
+    id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    base_currency TEXT NOT NULL,
+    rate BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS exchange_rates_currency ON exchange_rates (base_currency);
+
+--Disable deletes
+CREATE TRIGGER exchange_rates_no_delete BEFORE DELETE ON exchange_rates
+    FOR EACH ROW EXECUTE FUNCTION forbid_delete('Deletes are not allowed on exchange_rates');
@@ -0,0 +1,5 @@
+DROP INDEX IF EXISTS payments_alt_order_id_idx;
+DROP INDEX IF EXISTS orders_alt_id_idx;
+
+ALTER TABLE payments DROP COLUMN IF EXISTS alt_order_id;
+ALTER TABLE orders DROP COLUMN IF EXISTS alt_id;
@@ -0,0 +1,15 @@
+-- Durable queue of events. Rows are written in the same transaction as the state change that caused them, and are
+-- delivered to the event hooks by the outbox dispatcher.
+CREATE TABLE IF NOT EXISTS event_outbox (
+    id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    event_type TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    attempts BIGINT NOT NULL DEFAULT 0,
+    -- NULL once the event has been delivered, or has run out of retries
+    next_attempt_at TIMESTAMPTZ DEFAULT CURRENT_TIMESTAMP,
+    last_error TEXT,
+    delivered_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS event_outbox_undelivered ON event_outbox (delivered_at, next_attempt_at);
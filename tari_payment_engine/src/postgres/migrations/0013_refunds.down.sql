@@ -0,0 +1,37 @@
+DROP VIEW IF EXISTS address_balance;
+CREATE VIEW address_balance (address, total_confirmed, total_paid, current_balance, last_update) AS
+WITH
+    wallets AS (
+    SELECT sender, SUM(amount)::BIGINT AS total_confirmed, MAX(updated_at) AS updated_at
+    FROM payments
+    WHERE status = 'Confirmed'
+    GROUP BY sender
+),
+    settlements AS (
+    SELECT SUM(amount)::BIGINT AS total, payment_address, MAX(created_at) AS created_at
+    FROM settlement_journal
+    GROUP BY payment_address
+)
+SELECT
+    wallets.sender AS address,
+    wallets.total_confirmed AS total_confirmed,
+    COALESCE(settlements.total, 0) AS total_paid,
+    wallets.total_confirmed - COALESCE(settlements.total, 0) AS current_balance,
+    COALESCE(settlements.created_at, wallets.updated_at) AS last_update
+FROM wallets
+LEFT OUTER JOIN settlements ON wallets.sender = settlements.payment_address;
+
+DROP TRIGGER IF EXISTS refunds_log_insert ON refunds;
+DROP TRIGGER IF EXISTS refunds_log_update ON refunds;
+DROP FUNCTION IF EXISTS refunds_log_insert;
+DROP FUNCTION IF EXISTS refunds_log_update;
+DROP INDEX IF EXISTS refunds_log_updated_at;
+DROP INDEX IF EXISTS refunds_log_refund_id;
+DROP TABLE IF EXISTS refunds_log;
+
+DROP TRIGGER IF EXISTS refunds_no_delete ON refunds;
+DROP INDEX IF EXISTS refunds_status_idx;
+DROP INDEX IF EXISTS refunds_order_id_idx;
+DROP INDEX IF EXISTS refunds_address_idx;
+DROP TABLE IF EXISTS refunds;
+DROP TYPE IF EXISTS RefundStatus;
@@ -0,0 +1,138 @@
+-- Tari returned to a customer, either from their unspent balance or against an order they have paid for.
+-- Refunds only affect the address balance once they have been approved.
+CREATE TYPE RefundStatus AS ENUM ('Requested', 'Approved', 'Sent', 'Rejected');
+
+CREATE TABLE refunds (
+    id          BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    created_at  TIMESTAMPTZ  NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at  TIMESTAMPTZ  NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    address     TEXT         NOT NULL,
+    -- NULL for refunds of an unspent (e.g. overpaid) balance
+    order_id    TEXT REFERENCES orders (order_id),
+    amount      BIGINT       NOT NULL CHECK (amount > 0),
+    reason      TEXT         NOT NULL,
+    status      RefundStatus NOT NULL DEFAULT 'Requested',
+    -- The transaction id of the payout, once it has been sent
+    payout_txid TEXT
+);
+
+CREATE INDEX refunds_address_idx ON refunds (address);
+CREATE INDEX refunds_order_id_idx ON refunds (order_id);
+CREATE INDEX refunds_status_idx ON refunds (status);
+
+-- Do not allow deletes on the refunds table
+CREATE TRIGGER refunds_no_delete BEFORE DELETE ON refunds
+    FOR EACH ROW EXECUTE FUNCTION forbid_delete('Delete not allowed on refunds table. Set status to Rejected instead');
+
+-- amount = 1
+-- reason = 2
+-- status = 4
+-- payout_txid = 8
+CREATE TABLE refunds_log
+(
+    id              BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    refund_id       BIGINT      NOT NULL REFERENCES refunds (id),
+    columns_changed INTEGER     NOT NULL,
+    old_amount      BIGINT,
+    new_amount      BIGINT,
+    old_reason      TEXT,
+    new_reason      TEXT,
+    old_status      TEXT,
+    new_status      TEXT,
+    old_payout_txid TEXT,
+    new_payout_txid TEXT,
+    updated_at      TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX refunds_log_refund_id ON refunds_log (refund_id);
+CREATE INDEX refunds_log_updated_at ON refunds_log (updated_at);
+
+CREATE FUNCTION refunds_log_update() RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.address != OLD.address OR NEW.order_id IS DISTINCT FROM OLD.order_id THEN
+        RAISE EXCEPTION 'The address and order of a refund cannot be changed';
+    END IF;
+    INSERT INTO refunds_log (refund_id,
+                             columns_changed,
+                             old_amount,
+                             new_amount,
+                             old_reason,
+                             new_reason,
+                             old_status,
+                             new_status,
+                             old_payout_txid,
+                             new_payout_txid,
+                             updated_at)
+    VALUES (NEW.id,
+            (CASE WHEN OLD.amount IS DISTINCT FROM NEW.amount THEN 1 ELSE 0 END) +
+            (CASE WHEN OLD.reason IS DISTINCT FROM NEW.reason THEN 2 ELSE 0 END) +
+            (CASE WHEN OLD.status IS DISTINCT FROM NEW.status THEN 4 ELSE 0 END) +
+            (CASE WHEN OLD.payout_txid IS DISTINCT FROM NEW.payout_txid THEN 8 ELSE 0 END),
+            NULLIF(OLD.amount, NEW.amount),
+            NULLIF(NEW.amount, OLD.amount),
+            NULLIF(OLD.reason, NEW.reason),
+            NULLIF(NEW.reason, OLD.reason),
+            NULLIF(OLD.status::TEXT, NEW.status::TEXT),
+            NULLIF(NEW.status::TEXT, OLD.status::TEXT),
+            NULLIF(OLD.payout_txid, NEW.payout_txid),
+            NULLIF(NEW.payout_txid, OLD.payout_txid),
+            NEW.updated_at);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER refunds_log_update
+    AFTER UPDATE
+    ON refunds
+    FOR EACH ROW EXECUTE FUNCTION refunds_log_update();
+
+CREATE FUNCTION refunds_log_insert() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO refunds_log (refund_id, columns_changed, new_amount, new_reason, new_status, updated_at)
+    VALUES (NEW.id, 1 + 2 + 4, NEW.amount, NEW.reason, NEW.status::TEXT, NEW.updated_at);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER refunds_log_insert
+    AFTER INSERT
+    ON refunds
+    FOR EACH ROW EXECUTE FUNCTION refunds_log_insert();
+
+-- Approved and sent refunds are debited from the address balance. A refund against an order returns part of the
+-- order payment, so it is deducted from `total_paid` as well, and leaves the current balance unchanged.
+DROP VIEW IF EXISTS address_balance;
+CREATE VIEW address_balance (address, total_confirmed, total_paid, total_refunded, current_balance, last_update) AS
+WITH
+    wallets AS (
+    SELECT sender, SUM(amount)::BIGINT AS total_confirmed, MAX(updated_at) AS updated_at
+    FROM payments
+    WHERE status = 'Confirmed'
+    GROUP BY sender
+),
+    settlements AS (
+    SELECT SUM(amount)::BIGINT AS total, payment_address, MAX(created_at) AS created_at
+    FROM settlement_journal
+    GROUP BY payment_address
+),
+    refunded AS (
+    SELECT
+        address,
+        SUM(amount)::BIGINT AS total,
+        SUM(CASE WHEN order_id IS NULL THEN 0 ELSE amount END)::BIGINT AS for_orders,
+        MAX(updated_at) AS updated_at
+    FROM refunds
+    WHERE status IN ('Approved', 'Sent')
+    GROUP BY address
+)
+SELECT
+    wallets.sender AS address,
+    wallets.total_confirmed AS total_confirmed,
+    COALESCE(settlements.total, 0) - COALESCE(refunded.for_orders, 0) AS total_paid,
+    COALESCE(refunded.total, 0) AS total_refunded,
+    wallets.total_confirmed - COALESCE(settlements.total, 0) - COALESCE(refunded.total, 0)
+        + COALESCE(refunded.for_orders, 0) AS current_balance,
+    GREATEST(COALESCE(settlements.created_at, wallets.updated_at), refunded.updated_at) AS last_update
+FROM wallets
+LEFT OUTER JOIN settlements ON wallets.sender = settlements.payment_address
+LEFT OUTER JOIN refunded ON wallets.sender = refunded.address;
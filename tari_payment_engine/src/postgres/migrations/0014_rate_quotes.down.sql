@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS orders_quote_expires_at_idx;
+ALTER TABLE orders DROP COLUMN IF EXISTS quote_expires_at;
+ALTER TABLE orders DROP COLUMN IF EXISTS rate_id;
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS held_orders_released_at_idx;
+DROP TABLE IF EXISTS held_orders;
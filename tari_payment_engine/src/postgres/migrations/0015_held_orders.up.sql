@@ -0,0 +1,20 @@
+-- New orders that were not priced because the exchange rate was stale or volatile. They are only inserted into the
+-- orders table once an admin releases them, at which point they are priced at the prevailing rate.
+CREATE TABLE held_orders (
+    id               BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    order_id         TEXT UNIQUE NOT NULL,
+    alt_order_id     TEXT,
+    customer_id      TEXT NOT NULL,
+    memo             TEXT,
+    -- The price of the order in the storefront currency
+    original_price   TEXT NOT NULL,
+    currency         TEXT NOT NULL,
+    -- The time the order was created on the storefront
+    order_created_at TIMESTAMPTZ NOT NULL,
+    -- Why the circuit breaker tripped
+    reason           TEXT NOT NULL,
+    created_at       TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    released_at      TIMESTAMPTZ
+);
+
+CREATE INDEX held_orders_released_at_idx ON held_orders (released_at);
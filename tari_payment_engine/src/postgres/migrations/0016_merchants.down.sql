@@ -0,0 +1,4 @@
+DROP TABLE IF EXISTS merchant_role_assignments;
+DROP INDEX IF EXISTS orders_merchant_id_idx;
+ALTER TABLE held_orders DROP COLUMN IF EXISTS merchant_id;
+ALTER TABLE orders DROP COLUMN IF EXISTS merchant_id;
@@ -0,0 +1,16 @@
+-- Several storefronts can be served from one instance. Orders belong to the merchant whose storefront created them.
+-- Existing orders belong to the default merchant.
+ALTER TABLE orders ADD COLUMN merchant_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE held_orders ADD COLUMN merchant_id TEXT NOT NULL DEFAULT 'default';
+
+CREATE INDEX orders_merchant_id_idx ON orders (merchant_id);
+
+-- Roles that only apply to a single merchant's orders. Roles in role_assignments apply to every merchant.
+CREATE TABLE merchant_role_assignments
+(
+    address     TEXT   NOT NULL,
+    merchant_id TEXT   NOT NULL,
+    role_id     BIGINT NOT NULL REFERENCES roles (id) ON DELETE CASCADE,
+    -- Inserts use ON CONFLICT DO NOTHING, mirroring the SQLite ON CONFLICT IGNORE clause
+    PRIMARY KEY (address, merchant_id, role_id)
+);
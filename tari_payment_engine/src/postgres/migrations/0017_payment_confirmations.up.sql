@@ -0,0 +1,4 @@
+-- Where each payment's transaction is in the chain. The block height is NULL until the transaction is mined, and the
+-- confirmation count includes the block the transaction was mined in.
+ALTER TABLE payments ADD COLUMN block_height BIGINT;
+ALTER TABLE payments ADD COLUMN confirmations BIGINT NOT NULL DEFAULT 0;
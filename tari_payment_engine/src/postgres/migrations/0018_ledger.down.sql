@@ -0,0 +1,10 @@
+DROP TRIGGER IF EXISTS ledger_entries_no_change ON ledger_entries;
+DROP TRIGGER IF EXISTS ledger_transactions_no_change ON ledger_transactions;
+DROP INDEX IF EXISTS ledger_entries_transaction_id_idx;
+DROP INDEX IF EXISTS ledger_entries_account_idx;
+DROP TABLE IF EXISTS ledger_entries;
+DROP INDEX IF EXISTS ledger_transactions_reference_idx;
+DROP TABLE IF EXISTS ledger_transactions;
+DROP TYPE IF EXISTS EntrySide;
+DROP TYPE IF EXISTS LedgerAccountType;
+DROP TYPE IF EXISTS LedgerTransactionType;
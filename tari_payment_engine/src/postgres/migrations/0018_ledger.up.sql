@@ -0,0 +1,115 @@
+-- A double-entry ledger underneath the balances. Every payment, confirmation, cancellation, credit note, settlement and
+-- refund posts a transaction whose debits and credits balance.
+CREATE TYPE LedgerTransactionType AS ENUM (
+    'OpeningBalances',
+    'PaymentReceived',
+    'PaymentConfirmed',
+    'PaymentCancelled',
+    'PaymentReverted',
+    'CreditNote',
+    'Settlement',
+    'RefundApproved',
+    'RefundSent'
+    );
+CREATE TYPE LedgerAccountType AS ENUM (
+    'HotWallet',
+    'PendingPayments',
+    'CustomerWallet',
+    'MerchantRevenue',
+    'Refunds',
+    'Credits',
+    'Fees'
+    );
+CREATE TYPE EntrySide AS ENUM ('Debit', 'Credit');
+
+CREATE TABLE ledger_transactions
+(
+    id               BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    created_at       TIMESTAMPTZ           NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    transaction_type LedgerTransactionType NOT NULL,
+    -- The payment txid, order id or refund id that the transaction belongs to
+    reference        TEXT                  NOT NULL
+);
+
+CREATE INDEX ledger_transactions_reference_idx ON ledger_transactions (reference);
+
+CREATE TABLE ledger_entries
+(
+    id             BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    transaction_id BIGINT            NOT NULL REFERENCES ledger_transactions (id),
+    account_type   LedgerAccountType NOT NULL,
+    -- The address or merchant id that the account belongs to, or empty for single accounts
+    account_id     TEXT              NOT NULL DEFAULT '',
+    side           EntrySide         NOT NULL,
+    amount         BIGINT            NOT NULL CHECK (amount >= 0)
+);
+
+CREATE INDEX ledger_entries_account_idx ON ledger_entries (account_type, account_id);
+CREATE INDEX ledger_entries_transaction_id_idx ON ledger_entries (transaction_id);
+
+-- The ledger is append-only. Mistakes are corrected by posting another transaction.
+CREATE TRIGGER ledger_transactions_no_change BEFORE UPDATE OR DELETE ON ledger_transactions
+    FOR EACH ROW EXECUTE FUNCTION forbid_delete('The ledger is append-only. Post a correcting transaction instead');
+CREATE TRIGGER ledger_entries_no_change BEFORE UPDATE OR DELETE ON ledger_entries
+    FOR EACH ROW EXECUTE FUNCTION forbid_delete('The ledger is append-only. Post a correcting transaction instead');
+
+-- Carry the existing balances over into a single opening transaction, so that the ledger agrees with the
+-- address_balance view from the start.
+INSERT INTO ledger_transactions (transaction_type, reference)
+SELECT 'OpeningBalances', 'opening_balances'
+WHERE EXISTS (SELECT 1 FROM payments);
+
+-- Each balance is debits less credits
+WITH
+    opening (account_type, account_id, balance) AS (
+    SELECT 'HotWallet', '', SUM(amount)::BIGINT
+    FROM payments
+    WHERE payment_type = 'OnChain' AND status IN ('Received', 'Confirmed')
+    UNION ALL
+    SELECT 'HotWallet', '', -SUM(amount)::BIGINT
+    FROM refunds
+    WHERE status = 'Sent'
+    UNION ALL
+    SELECT 'PendingPayments', sender, -SUM(amount)::BIGINT
+    FROM payments
+    WHERE payment_type = 'OnChain' AND status = 'Received'
+    GROUP BY sender
+    UNION ALL
+    SELECT 'CustomerWallet', address, -current_balance
+    FROM address_balance
+    UNION ALL
+    SELECT 'MerchantRevenue', orders.merchant_id, -SUM(settlement_journal.amount)::BIGINT
+    FROM settlement_journal
+    JOIN orders ON orders.order_id = settlement_journal.order_id
+    GROUP BY orders.merchant_id
+    UNION ALL
+    SELECT 'MerchantRevenue', orders.merchant_id, SUM(refunds.amount)::BIGINT
+    FROM refunds
+    JOIN orders ON orders.order_id = refunds.order_id
+    WHERE refunds.status IN ('Approved', 'Sent')
+    GROUP BY orders.merchant_id
+    UNION ALL
+    SELECT 'Refunds', '', -SUM(amount)::BIGINT
+    FROM refunds
+    WHERE status = 'Approved'
+    UNION ALL
+    SELECT 'Credits', '', SUM(amount)::BIGINT
+    FROM payments
+    WHERE payment_type = 'Manual' AND status = 'Confirmed'
+),
+    balances AS (
+    SELECT account_type, account_id, SUM(balance)::BIGINT AS balance
+    FROM opening
+    WHERE balance IS NOT NULL
+    GROUP BY account_type, account_id
+)
+INSERT INTO ledger_entries (transaction_id, account_type, account_id, side, amount)
+SELECT
+    ledger_transactions.id,
+    balances.account_type::LedgerAccountType,
+    balances.account_id,
+    (CASE WHEN balances.balance > 0 THEN 'Debit' ELSE 'Credit' END)::EntrySide,
+    ABS(balances.balance)
+FROM balances
+JOIN ledger_transactions ON ledger_transactions.transaction_type = 'OpeningBalances'
+WHERE balances.balance != 0;
@@ -0,0 +1,17 @@
+-- Login sessions. Every access token carries the id of the session it was issued for, so revoking a session
+-- invalidates its access tokens as well as its refresh token. Only a hash of the refresh token is stored.
+CREATE TABLE auth_sessions (
+    id                 BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    address            TEXT NOT NULL,
+    -- Comma-separated list of the roles that were granted at login
+    roles              TEXT NOT NULL,
+    merchant_id        TEXT,
+    refresh_token_hash TEXT UNIQUE NOT NULL,
+    created_at         TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    refreshed_at       TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at         TIMESTAMPTZ NOT NULL,
+    revoked_at         TIMESTAMPTZ
+);
+
+CREATE INDEX auth_sessions_address_idx ON auth_sessions (address);
+CREATE INDEX auth_sessions_revoked_at_idx ON auth_sessions (revoked_at);
@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS role_permissions;
+DELETE FROM roles WHERE id > 5;
+ALTER TABLE roles ALTER COLUMN id DROP DEFAULT;
+DROP SEQUENCE IF EXISTS roles_id_seq;
+DROP INDEX IF EXISTS roles_name_idx;
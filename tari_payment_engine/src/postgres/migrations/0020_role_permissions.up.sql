@@ -0,0 +1,77 @@
+-- Routes require named permissions, and roles group permissions together. Roles and the permissions that they grant
+-- can be edited at runtime. The built-in roles are created with the permissions that they used to imply:
+-- 2 = read_all, 3 = write, 5 = super_admin. The user (1) and payment_wallet (4) roles grant no permissions.
+CREATE UNIQUE INDEX roles_name_idx ON roles (name);
+
+-- Custom roles are numbered after the built-in ones
+CREATE SEQUENCE roles_id_seq OWNED BY roles.id;
+SELECT setval('roles_id_seq', (SELECT max(id) FROM roles));
+ALTER TABLE roles ALTER COLUMN id SET DEFAULT nextval('roles_id_seq');
+
+CREATE TABLE role_permissions
+(
+    role_id    BIGINT NOT NULL REFERENCES roles (id) ON DELETE CASCADE,
+    permission TEXT   NOT NULL,
+    -- Inserts use ON CONFLICT DO NOTHING, mirroring the SQLite ON CONFLICT IGNORE clause
+    PRIMARY KEY (role_id, permission)
+);
+
+INSERT INTO role_permissions (role_id, permission) VALUES
+    (2, 'accounts.read'),
+    (2, 'orders.read'),
+    (2, 'payments.read'),
+    (2, 'refunds.read'),
+    (2, 'rates.read'),
+    (2, 'events.read'),
+    (2, 'webhooks.read'),
+    (2, 'ledger.read'),
+    (2, 'reports.export'),
+    (2, 'sessions.read'),
+    (2, 'wallets.read'),
+    (2, 'roles.read'),
+    (3, 'accounts.settle'),
+    (3, 'orders.fulfil'),
+    (3, 'orders.cancel'),
+    (3, 'orders.edit'),
+    (3, 'orders.price'),
+    (3, 'orders.reassign'),
+    (3, 'orders.reset'),
+    (3, 'orders.process'),
+    (3, 'payments.reconcile'),
+    (3, 'credit.issue'),
+    (3, 'refunds.request'),
+    (3, 'refunds.approve'),
+    (3, 'refunds.send'),
+    (3, 'rates.update'),
+    (3, 'events.replay'),
+    (5, 'accounts.read'),
+    (5, 'orders.read'),
+    (5, 'payments.read'),
+    (5, 'refunds.read'),
+    (5, 'rates.read'),
+    (5, 'events.read'),
+    (5, 'webhooks.read'),
+    (5, 'ledger.read'),
+    (5, 'reports.export'),
+    (5, 'sessions.read'),
+    (5, 'wallets.read'),
+    (5, 'roles.read'),
+    (5, 'accounts.settle'),
+    (5, 'orders.fulfil'),
+    (5, 'orders.cancel'),
+    (5, 'orders.edit'),
+    (5, 'orders.price'),
+    (5, 'orders.reassign'),
+    (5, 'orders.reset'),
+    (5, 'orders.process'),
+    (5, 'payments.reconcile'),
+    (5, 'credit.issue'),
+    (5, 'refunds.request'),
+    (5, 'refunds.approve'),
+    (5, 'refunds.send'),
+    (5, 'rates.update'),
+    (5, 'events.replay'),
+    (5, 'webhooks.manage'),
+    (5, 'sessions.manage'),
+    (5, 'wallets.manage'),
+    (5, 'roles.manage');
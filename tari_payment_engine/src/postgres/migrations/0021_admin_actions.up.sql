@@ -0,0 +1,34 @@
+-- The admin audit log. Every privileged API call is recorded, whether or not it succeeded, along with who made it and
+-- why. The orders_log and payments_log tables record what changed; this table records who changed it.
+CREATE TYPE AuditOutcome AS ENUM ('Succeeded', 'Failed', 'Denied');
+
+CREATE TABLE admin_actions (
+    id          BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    created_at  TIMESTAMPTZ  NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    actor       TEXT         NOT NULL,
+    -- Comma-separated list of the roles in the caller's access token
+    roles       TEXT         NOT NULL,
+    merchant_id TEXT,
+    -- The name of the route, e.g. update_price
+    endpoint    TEXT         NOT NULL,
+    method      TEXT         NOT NULL,
+    path        TEXT         NOT NULL,
+    -- JSON object holding the path and query parameters and the request body
+    parameters  TEXT         NOT NULL,
+    reason      TEXT,
+    client_ip   TEXT,
+    outcome     AuditOutcome NOT NULL,
+    status_code BIGINT       NOT NULL,
+    error       TEXT
+);
+
+CREATE INDEX admin_actions_actor_idx ON admin_actions (actor);
+CREATE INDEX admin_actions_endpoint_idx ON admin_actions (endpoint);
+CREATE INDEX admin_actions_created_at_idx ON admin_actions (created_at);
+
+CREATE TRIGGER admin_actions_no_change BEFORE UPDATE OR DELETE ON admin_actions
+    FOR EACH ROW EXECUTE FUNCTION forbid_delete('The admin audit log is append-only');
+
+-- Reading the audit log is a permission of its own. The read_all and super_admin roles are granted it.
+INSERT INTO role_permissions (role_id, permission)
+SELECT id, 'audit.read' FROM roles WHERE name IN ('read_all', 'super_admin');
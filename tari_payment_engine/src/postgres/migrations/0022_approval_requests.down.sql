@@ -0,0 +1,7 @@
+DELETE FROM role_permissions WHERE permission IN ('approvals.read', 'approvals.decide');
+DROP TRIGGER IF EXISTS approval_requests_no_edit ON approval_requests;
+DROP FUNCTION IF EXISTS approval_requests_no_edit;
+DROP TRIGGER IF EXISTS approval_requests_no_delete ON approval_requests;
+DROP INDEX IF EXISTS approval_requests_status_idx;
+DROP TABLE IF EXISTS approval_requests;
+DROP TYPE IF EXISTS ApprovalStatus;
@@ -0,0 +1,11 @@
+-- Token buckets for rate limits that several server instances share. The buckets are cheap to lose, so the table is
+-- not written to the WAL. A bucket is full again once it expires, so expired buckets can be deleted at any time.
+CREATE UNLOGGED TABLE rate_limit_buckets (
+    key        TEXT PRIMARY KEY,
+    tokens     DOUBLE PRECISION NOT NULL,
+    allowed    BOOLEAN          NOT NULL,
+    updated_at TIMESTAMPTZ      NOT NULL,
+    expires_at TIMESTAMPTZ      NOT NULL
+);
+
+CREATE INDEX rate_limit_buckets_expires_at_idx ON rate_limit_buckets (expires_at);
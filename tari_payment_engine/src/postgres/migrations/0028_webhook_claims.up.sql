@@ -0,0 +1,2 @@
+-- The webhook worker leases the deliveries it is making until locked_until, so that other workers skip them.
+ALTER TABLE webhook_deliveries ADD COLUMN locked_until TIMESTAMPTZ;
@@ -1 +1,36 @@
+//! PostgreSQL database module for the Tari Payment Engine.
+//!
+//! This backend mirrors the SQLite implementation and keeps as much of the data integrity
+//! logic inside the database as possible. Postgres triggers are written as `plpgsql` functions, but their behaviour
+//! is identical to their SQLite counterparts.
+//!
+//! In particular, triggers are used to:
+//! * Prevent DELETE queries on the `orders`, `payments` and `exchange_rates` tables. Orders and payments are never
+//!   deleted, only cancelled.
+//! * Enforce that nonces are monotonically increasing every time that a user or payment wallet authenticates. Nonce
+//!   violations are raised with the custom SQLSTATE `TPG01`.
+//!
+//! ## Enumerated types
+//! Order statuses, payment types, transfer statuses and settlement types are stored as native Postgres `ENUM` types,
+//! matching the names that `sqlx` derives for the corresponding Rust enums.
+//!
+//! ## Audit logs
+//! The database maintains an audit log of all changes to the `orders` and `payments` tables. Any INSERT, or UPDATE
+//! queries are logged to the `orders_log` or `payments_log` tables (DELETES are forbidden) through their
+//! respective triggers.
+//!
+//! We also track any changes to the authorized wallet table in the `wallet_auth_log` table.
+//!
+//! ## Concurrency
+//! Unlike SQLite, Postgres lets several writers in at once, and several server instances may share one database.
+//! Flows that read a status or balance and then write based on it lock what they read first. Payment and order rows
+//! are locked with `SELECT … FOR UPDATE`. Address balances are derived from other tables, so they are guarded by a
+//! transaction-level advisory lock on the address instead.
+//!
+//! ## Migrations
+//! The Postgres migrations live in `src/postgres/migrations`. `taritools setup migrate` applies them when
+//! `TPG_DATABASE_URL` points to a Postgres database.
+mod postgres_impl;
 
+pub mod db;
+pub use postgres_impl::PostgresDatabase;
@@ -0,0 +1,1776 @@
+//! `PostgresDatabase` is a concrete implementation of a Tari Payment engine backend.
+//!
+//! It uses PostgreSQL as the backend and implements all the traits defined in the [`traits`] module. The business
+//! logic is deliberately kept identical to the SQLite backend; only the low-level queries in [`super::db`] differ
+//! between the two.
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+};
+
+use chrono::{DateTime, Duration, Utc};
+use log::*;
+use sqlx::{PgConnection, PgPool};
+use tari_common_types::tari_address::TariAddress;
+use tpg_common::MicroTari;
+
+use super::db::{
+    accounts,
+    approvals,
+    audit,
+    auth,
+    db_url,
+    exchange_rates,
+    held_orders,
+    ledger,
+    new_pool,
+    orders,
+    outbox,
+    rate_limits,
+    refunds,
+    sessions,
+    transfers,
+    wallet_auth,
+    webhooks,
+};
+use crate::{
+    db_types::{
+        AddressBalance,
+        AdminAction,
+        AdminActionFilter,
+        ApprovalRequest,
+        ApprovalStatus,
+        AuthSession,
+        CreditNote,
+        CustomerBalance,
+        CustomerOrderBalance,
+        CustomerOrders,
+        HeldOrder,
+        LedgerAccount,
+        LedgerBalance,
+        LedgerEntry,
+        NewAdminAction,
+        NewApprovalRequest,
+        NewAuthSession,
+        NewLedgerTransaction,
+        NewOrder,
+        NewPayment,
+        NewRefund,
+        NewSettlementJournalEntry,
+        NewWebhookSubscription,
+        Order,
+        OrderId,
+        OrderStatusType,
+        OutboxEvent,
+        Payment,
+        Refund,
+        RefundStatus,
+        Role,
+        RoleDefinition,
+        SerializedTariAddress,
+        SettlementJournalEntry,
+        SettlementType,
+        TransferStatus,
+        WebhookDelivery,
+        WebhookSubscription,
+    },
+    events::{
+        ApprovalEvent,
+        EventType,
+        OrderAnnulledEvent,
+        OrderClaimedEvent,
+        OrderEvent,
+        OrderHeldEvent,
+        OrderModifiedEvent,
+        PaymentEvent,
+        PaymentRevertedEvent,
+        RefundEvent,
+    },
+    order_objects::{ModifyOrderRequest, OrderChanged, OrderQueryFilter},
+    postgres::db::orders::{fetch_order_by_id_or_alt, fetch_order_by_order_id},
+    tpe_api::{
+        account_objects::{AddressHistory, CustomerHistory, Pagination, TrialBalance},
+        exchange_objects::ExchangeRate,
+        rate_limit_objects::{RateLimit, RateLimitDecision},
+    },
+    traits::{
+        AccountApiError,
+        AccountManagement,
+        AuditLog,
+        AuditLogError,
+        AuthApiError,
+        AuthManagement,
+        EventOutbox,
+        ExchangeRateError,
+        ExchangeRates,
+        ExpiryResult,
+        MultiAccountPayment,
+        NewWalletInfo,
+        OrderMovedResult,
+        OutboxError,
+        PaymentGatewayDatabase,
+        PaymentGatewayError,
+        PaymentReversal,
+        RateLimitError,
+        RateLimitStore,
+        WalletAuth,
+        WalletAuthApiError,
+        WalletInfo,
+        WalletManagement,
+        WalletManagementError,
+        WebhookError,
+        WebhookManagement,
+    },
+};
+
+#[derive(Clone)]
+pub struct PostgresDatabase {
+    url: String,
+    pool: PgPool,
+}
+
+impl Debug for PostgresDatabase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "PostgresDatabase ({:?})", self.pool)
+    }
+}
+
+impl PaymentGatewayDatabase for PostgresDatabase {
+    fn url(&self) -> &str {
+        self.url.as_str()
+    }
+
+    async fn claim_order(
+        &self,
+        order_id: &OrderId,
+        address: &TariAddress,
+        strict_mode: bool,
+    ) -> Result<Order, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let order = self.claim_order_with_conn(order_id, address, strict_mode, &mut tx).await?;
+        tx.commit().await?;
+        Ok(order)
+    }
+
+    async fn auto_claim_order(
+        &self,
+        order: &Order,
+        strict_mode: bool,
+    ) -> Result<Option<(TariAddress, Order)>, PaymentGatewayError> {
+        if order.status != OrderStatusType::Unclaimed {
+            error!("🖇️️ Order {} is not 'Unclaimed' and cannot be auto-claimed", order.order_id);
+            return Err(PaymentGatewayError::OrderModificationForbidden);
+        }
+        let mut tx = self.pool.begin().await?;
+        let cust_id = &order.customer_id;
+        let alt_id = if strict_mode { None } else { order.alt_id.as_ref() };
+        trace!(
+            "🖇️️ Checking balances for order {} ({:?}, strict mode: {strict_mode}) for customer {}",
+            order.order_id,
+            alt_id,
+            cust_id
+        );
+        let merchant_id = &order.merchant_id;
+        let mut address =
+            accounts::merchant_balances_for_order_id(&order.order_id, alt_id, merchant_id, &mut tx).await?;
+        if address.is_empty() {
+            address = accounts::merchant_balances_for_customer_id(cust_id, merchant_id, &mut tx).await?;
+        }
+        // The first address is either explicitly lined to the order, or the most recent one
+        let Some(address) = address.first().map(|a| a.address().clone()) else {
+            // We could omit the tx commit here, 'cos we're not making any changes
+            tx.commit().await?;
+            return Ok(None);
+        };
+        let order = orders::update_order_status(order.id, OrderStatusType::New, &mut tx).await?;
+        accounts::link_address_to_customer(&address, &order.customer_id, &mut tx).await?;
+        let event = EventType::OrderClaimed(OrderClaimedEvent::new(order.clone(), address.clone()));
+        outbox::enqueue(&event, &mut tx).await?;
+        tx.commit().await?;
+        Ok(Some((address, order)))
+    }
+
+    async fn insert_order(&self, order: NewOrder) -> Result<(Order, bool), PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let (order, inserted) = orders::idempotent_insert(order, &mut tx).await?;
+        if inserted {
+            outbox::enqueue(&EventType::NewOrder(OrderEvent::new(order.clone())), &mut tx).await?;
+        }
+        tx.commit().await?;
+        Ok((order, inserted))
+    }
+
+    /// Takes a new payment, and in a single atomic transaction,
+    /// * calls `save_payment` to store the payment in the database. If the payment already exists, nothing further is
+    ///   done.
+    /// * The payment is marked as `Unconfirmed`
+    /// * Adds the payment amount to the account's total received, and total pending
+    ///
+    /// Returns the newly created Payment record.
+    async fn process_new_payment(
+        &self,
+        payment: NewPayment,
+        strict_mode: bool,
+    ) -> Result<Payment, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let maybe_order_id = payment.order_id.clone();
+        debug!("🗃️ Payment {} received from [{}]", payment.txid, payment.sender.as_address());
+        let payment = transfers::idempotent_insert(payment, &mut tx).await?;
+        ledger::post(&NewLedgerTransaction::payment_received(&payment), &mut tx).await?;
+        outbox::enqueue(&EventType::PaymentReceived(PaymentEvent::new(payment.clone())), &mut tx).await?;
+        // If the order id is already known, link the address and customer_id
+        if let Some(order_id) = maybe_order_id {
+            info!(
+                "🗃️ The payment {} contains an order id ({order_id}), so I'm going to try and claim an existing order \
+                 with strict mode: {strict_mode}",
+                payment.txid
+            );
+            match self.claim_order_with_conn(&order_id, payment.sender.as_address(), strict_mode, &mut tx).await {
+                Ok(_) => info!("🗃️ Address {} linked to order {order_id}", payment.sender.as_address()),
+                Err(PaymentGatewayError::OrderNotFound(id)) => {
+                    info!(
+                        "🗃️ Order {id} is not in the database, and so it can't be matched. Either the order has not \
+                         come through from the storefront yet, or it has been mistyped."
+                    );
+                },
+                Err(e) => return Err(e),
+            };
+        }
+        debug!("🗃️ Transfer {} processed. {} credited to pending account", payment.txid, payment.amount);
+        tx.commit().await?;
+        Ok(payment)
+    }
+
+    async fn fetch_pending_payments_for_address(
+        &self,
+        address: &TariAddress,
+    ) -> Result<Vec<Payment>, PaymentGatewayError> {
+        let mut conn = self.pool.acquire().await?;
+        let payments = transfers::pending_payments(address, &mut conn).await?;
+        Ok(payments)
+    }
+
+    async fn process_credit_note_for_customer(&self, note: CreditNote) -> Result<Payment, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let payment = transfers::credit_note(&note, &mut tx).await?;
+        ledger::post(&NewLedgerTransaction::credit_note(&payment), &mut tx).await?;
+        outbox::enqueue(&EventType::PaymentReceived(PaymentEvent::new(payment.clone())), &mut tx).await?;
+        debug!("🗃️ Credit note for {} created with address {}", note.customer_id, payment.sender.as_address());
+        let address = payment.sender.as_address();
+        accounts::link_address_to_customer(address, &note.customer_id, &mut tx).await?;
+        debug!("🗃️ Dummy wallet {} linked to customer id {}", address.to_base58(), note.customer_id);
+        tx.commit().await?;
+        Ok(payment)
+    }
+
+    async fn fetch_payable_orders_for_address(&self, address: &TariAddress) -> Result<Vec<Order>, PaymentGatewayError> {
+        let mut conn = self.pool.acquire().await?;
+        let orders = orders::fetch_payable_orders_for_address(address, &mut conn).await?;
+        Ok(orders)
+    }
+
+    /// Tries to pay for a single order from any wallet associated with the order's customer Id.
+    ///
+    /// It's possible to pay for the order from multiple wallets, in which case the settlement type will be `Multiple`,
+    /// with the sum total of the payments being equal to the order's total price.
+    async fn try_pay_order(
+        &self,
+        order: &Order,
+        strict_mode: bool,
+    ) -> Result<Option<MultiAccountPayment>, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        // The caller's copy may predate a re-pricing, or the expiry of its quote
+        let order = &orders::fetch_order_by_order_id(&order.order_id, &mut tx)
+            .await?
+            .ok_or_else(|| PaymentGatewayError::OrderNotFound(order.order_id.clone()))?;
+        if order.quote_has_expired() {
+            return Err(PaymentGatewayError::QuoteExpired(order.order_id.clone()));
+        }
+        // First check for any payments that contain the order id
+        let alt_id = if strict_mode { order.alt_id.as_ref() } else { None };
+        // Only the funds held with the order's merchant can pay for it
+        let merchant_id = &order.merchant_id;
+        let order_balances =
+            accounts::merchant_balances_for_order_id(&order.order_id, alt_id, merchant_id, &mut tx).await?;
+        debug!("🗃️ Found {} payments explicitly lined to order {}", order_balances.len(), order.order_id);
+        let mut balances =
+            accounts::merchant_balances_for_customer_id(&order.customer_id, merchant_id, &mut tx).await?;
+        balances.extend(order_balances);
+        // Another transaction may be spending from the same addresses, or paying the same order. Lock them, and then
+        // re-read the balances and the order, so that the checks below see the latest committed state.
+        let addresses = balances.iter().map(|b| b.address().clone()).collect::<Vec<_>>();
+        accounts::lock_addresses(&addresses, &mut tx).await?;
+        let order =
+            orders::lock_order(order.id, &mut tx).await?.ok_or(PaymentGatewayError::OrderIdNotFound(order.id))?;
+        if ![OrderStatusType::New, OrderStatusType::Unclaimed].contains(&order.status) {
+            debug!("🗃️ Order {} has status {} and cannot be paid", order.order_id, order.status);
+            return Ok(None);
+        }
+        let merchant_id = &order.merchant_id;
+        let order_balances =
+            accounts::merchant_balances_for_order_id(&order.order_id, alt_id, merchant_id, &mut tx).await?;
+        let mut balances =
+            accounts::merchant_balances_for_customer_id(&order.customer_id, merchant_id, &mut tx).await?;
+        balances.extend(order_balances);
+        // Only the addresses that are locked may be spent from
+        balances.retain(|b| addresses.contains(b.address()));
+        let mut total_due = order.total_price;
+        let total_credit = balances.iter().map(|b| b.current_balance()).sum();
+        debug!(
+            "🗃️ Found {} payments in total for customer {} with total current balance of {}",
+            balances.len(),
+            order.customer_id,
+            total_credit
+        );
+        if balances.is_empty() || (total_due > total_credit) {
+            let err = PaymentGatewayError::AccountError(AccountApiError::InsufficientFunds);
+            return Err(err);
+        }
+        // Sort the balances in descending order of current balance
+        balances.sort_by_key(|b| Reverse(b.current_balance()));
+        // Preferably, use a `Single` journal entry type
+        let settlement_type =
+            if balances[0].current_balance() >= total_due { SettlementType::Single } else { SettlementType::Multiple };
+        let mut result = MultiAccountPayment::new(vec![], vec![]);
+        let zero = MicroTari::from(0);
+        for account in balances {
+            let address = SerializedTariAddress::from(account.address());
+            let amount_paid = account.current_balance().min(total_due);
+            total_due -= amount_paid;
+            let settlement = NewSettlementJournalEntry {
+                order_id: order.order_id.clone(),
+                payment_address: address,
+                amount: amount_paid,
+                settlement_type,
+                merchant_id: order.merchant_id.clone(),
+            };
+            let settlement = accounts::insert_settlement(settlement, &mut tx).await?;
+            ledger::post(&NewLedgerTransaction::settlement(&settlement, &order.merchant_id), &mut tx).await?;
+            result.settlements.push(settlement);
+            if total_due == zero {
+                break;
+            }
+        }
+        if total_due == zero {
+            let paid_order = orders::update_order_status(order.id, OrderStatusType::Paid, &mut tx).await?;
+            outbox::enqueue(&EventType::OrderPaid(OrderEvent::new(paid_order.clone())), &mut tx).await?;
+            result.orders_paid.push(paid_order);
+        }
+        tx.commit().await?;
+        Ok(if result.orders_paid.is_empty() { None } else { Some(result) })
+    }
+
+    /// Tries to fulfil the orders using the address as payment source.
+    ///
+    /// This method will not try and use other addresses that are also linked to the customer ids in the order list.
+    async fn try_pay_orders_from_address(
+        &self,
+        address: &TariAddress,
+        orders: &[&Order],
+    ) -> Result<Option<MultiAccountPayment>, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let result = self.pay_orders_for_address_with_conn(address, orders, &mut tx).await?;
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    async fn update_payment_status(&self, txid: &str, status: TransferStatus) -> Result<Payment, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let Some(payment) = transfers::lock_payment(txid, &mut tx).await? else {
+            return Err(PaymentGatewayError::PaymentStatusUpdateError(format!("Payment {txid} not found")));
+        };
+        let old_status = payment.status;
+        trace!("🗃️ Updating payment: Payment {txid} is currently {old_status}");
+        use TransferStatus::*;
+        if old_status == status {
+            debug!("🗃️ Payment {txid} already has status {status}. No action to take");
+            return Err(PaymentGatewayError::PaymentModificationNoOp);
+        }
+        if old_status != Received {
+            error!(
+                "🗃️ Payment {txid} cannot be transitioned from {old_status} to {status}.If there is a valid use case, \
+                 perform a manual adjustment now and submit a ticket so that it can be handled properly in the future."
+            );
+            return Err(PaymentGatewayError::PaymentStatusUpdateError(format!(
+                "Payment {txid} has status {status} instead of 'Received'"
+            )));
+        }
+
+        let payment = transfers::update_status(txid, status, &mut tx).await?;
+        match status {
+            Confirmed => {
+                ledger::post(&NewLedgerTransaction::payment_confirmed(&payment), &mut tx).await?;
+                outbox::enqueue(&EventType::Confirmation(PaymentEvent::new(payment.clone())), &mut tx).await?;
+            },
+            Cancelled => ledger::post(&NewLedgerTransaction::payment_cancelled(&payment), &mut tx).await?,
+            Received => {},
+        }
+        tx.commit().await?;
+        debug!("🗃️ Payment [{txid}] is now {status}.");
+        Ok(payment)
+    }
+
+    async fn fetch_payment_by_tx_id(&self, tx_id: &str) -> Result<Payment, PaymentGatewayError> {
+        let mut conn = self.pool.acquire().await?;
+        let payment = transfers::fetch_payment(tx_id, &mut conn).await?;
+        payment.ok_or_else(|| PaymentGatewayError::PaymentNotFound(tx_id.into()))
+    }
+
+    async fn update_payment_confirmations(
+        &self,
+        tx_id: &str,
+        block_height: Option<i64>,
+        confirmations: i64,
+    ) -> Result<Payment, PaymentGatewayError> {
+        let mut conn = self.pool.acquire().await?;
+        let payment = transfers::update_confirmations(tx_id, block_height, confirmations, &mut conn).await?;
+        Ok(payment)
+    }
+
+    async fn revert_payment(&self, tx_id: &str) -> Result<PaymentReversal, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let payment = transfers::lock_payment(tx_id, &mut tx)
+            .await?
+            .ok_or_else(|| PaymentGatewayError::PaymentNotFound(tx_id.into()))?;
+        if payment.status != TransferStatus::Confirmed {
+            return Err(PaymentGatewayError::PaymentStatusUpdateError(format!(
+                "Payment {tx_id} has status {} instead of 'Confirmed'",
+                payment.status
+            )));
+        }
+        let address = payment.sender.as_address();
+        accounts::lock_addresses(&[address.clone()], &mut tx).await?;
+        // The balance still includes the payment at this point. Only the orders of the payment's merchant were paid
+        // from it.
+        let balance = accounts::fetch_merchant_balance(address, &payment.merchant_id, &mut tx).await?;
+        let shortfall = payment.amount - balance.current_balance();
+        transfers::update_status(tx_id, TransferStatus::Received, &mut tx).await?;
+        let reverted = transfers::update_confirmations(tx_id, None, 0, &mut tx).await?;
+        ledger::post(&NewLedgerTransaction::payment_reverted(&reverted), &mut tx).await?;
+        let mut orders_reverted = Vec::new();
+        let mut settlements = Vec::new();
+        if shortfall > MicroTari::from(0) {
+            debug!("🗃️ Reverting payment {tx_id} leaves {} short by {shortfall}", address.to_base58());
+            let mut entries = accounts::settlements_for_address(address, &mut tx).await?;
+            entries.retain(|e| e.merchant_id == payment.merchant_id);
+            for order_id in PaymentReversal::orders_to_revert(&entries, shortfall) {
+                let order = fetch_order_by_order_id(&order_id, &mut tx)
+                    .await?
+                    .ok_or_else(|| PaymentGatewayError::OrderNotFound(order_id.clone()))?;
+                let order = orders::lock_order(order.id, &mut tx)
+                    .await?
+                    .ok_or(PaymentGatewayError::OrderIdNotFound(order.id))?;
+                let paid = accounts::settlements_for_order(&order_id, &mut tx).await?;
+                for entry in PaymentReversal::reversing_entries(&paid) {
+                    let settlement = accounts::insert_settlement(entry, &mut tx).await?;
+                    ledger::post(&NewLedgerTransaction::settlement(&settlement, &order.merchant_id), &mut tx).await?;
+                    settlements.push(settlement);
+                }
+                orders_reverted.push(orders::update_order_status(order.id, OrderStatusType::New, &mut tx).await?);
+            }
+        }
+        let event = PaymentRevertedEvent::new(reverted.clone(), orders_reverted.clone());
+        outbox::enqueue(&EventType::PaymentReverted(event), &mut tx).await?;
+        tx.commit().await?;
+        debug!("🗃️ Payment [{tx_id}] has been reverted. {} orders are unpaid again.", orders_reverted.len());
+        Ok(PaymentReversal::new(reverted, orders_reverted, settlements))
+    }
+
+    /// A manual order status transition from `New` to `Paid` status.
+    /// A credit note for the `total_price` is created.
+    async fn mark_new_or_unclaimed_order_as_paid(
+        &self,
+        order: Order,
+        reason: &str,
+    ) -> Result<Order, PaymentGatewayError> {
+        if ![OrderStatusType::New, OrderStatusType::Unclaimed].contains(&order.status) {
+            error!(
+                "🗃️ Order {} is not in 'New' or 'Unclaimed' status. Cannot override this and mark it as paid",
+                order.id
+            );
+            return Err(PaymentGatewayError::OrderModificationForbidden);
+        }
+        let mut tx = self.pool.begin().await?;
+        let reason = format!("Admin credit overrode for order {}. Reason: {reason}", order.order_id);
+        let note = CreditNote::new(order.customer_id.clone(), order.total_price)
+            .with_reason(reason)
+            .with_merchant(&order.merchant_id);
+        let payment = transfers::credit_note(&note, &mut tx).await?;
+        ledger::post(&NewLedgerTransaction::credit_note(&payment), &mut tx).await?;
+        outbox::enqueue(&EventType::PaymentReceived(PaymentEvent::new(payment.clone())), &mut tx).await?;
+        let address = payment.sender.to_address();
+        debug!(
+            "🗃️ Credit note: Customer {} received note for {} with address {}",
+            order.customer_id,
+            order.total_price,
+            address.to_base58(),
+        );
+        // Claim the order for the credit note address
+        if order.status == OrderStatusType::Unclaimed {
+            self.claim_order_with_conn(&order.order_id, &address, true, &mut tx).await?;
+        }
+        let result = self.pay_orders_for_address_with_conn(&address, &[&order], &mut tx).await?;
+        if result.is_none() {
+            error!(
+                "🗃️ Order {} could not be paid for atfer issuing a credit note for the full amount. This is most \
+                 likely a bug",
+                order.id
+            );
+            return Err(PaymentGatewayError::OrderNotFound(order.order_id));
+        }
+        tx.commit().await?;
+        let updated_order = result.unwrap().orders_paid.remove(0);
+        Ok(updated_order)
+    }
+
+    /// A manual order status transition from `New` to `Expired` or `Cancelled` status.
+    ///
+    /// The side effects for expiring or cancelling an order are the same. The only difference is that Expired orders
+    /// are triggered automatically based on time, whereas cancelling an order is triggered by an admin or a shopify
+    /// webhook.
+    ///
+    /// * The order status is updated in the database.
+    /// * The total orders for the account are updated.
+    async fn cancel_or_expire_order(
+        &self,
+        id: &OrderId,
+        new_status: OrderStatusType,
+        reason: &str,
+        strict_mode: bool,
+    ) -> Result<Order, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let order = Self::fetch_order_by_id(id, strict_mode, &mut tx).await?;
+        if !&[OrderStatusType::New, OrderStatusType::Unclaimed].contains(&order.status) {
+            error!("🗃️ Order {} is not in 'New' status. Cannot call cancel_or_expire_order", order.id);
+            return Err(PaymentGatewayError::OrderModificationForbidden);
+        }
+        let update = ModifyOrderRequest::default().with_new_status(new_status).with_new_memo(reason);
+        let order = orders::update_order(&order.order_id, update, &mut tx)
+            .await?
+            .ok_or_else(|| AccountApiError::OrderDoesNotExist(order.order_id.clone()))?;
+        outbox::enqueue(&EventType::OrderAnnulled(OrderAnnulledEvent::new(order.clone())), &mut tx).await?;
+        tx.commit().await?;
+        Ok(order)
+    }
+
+    /// Manually reset an order from `Expired` or `Cancelled` status to `New` status.
+    ///
+    /// This method is called by the default implementation of [`modify_status_for_order`] when the new status
+    /// is `New`. This is often done as a follow-up step to changing the customer id for an order.
+    ///
+    /// The side effects for resetting an order are the same for both Expired and Cancelled orders.
+    /// The effect is as if a new order comes in with the given details.
+    ///
+    /// * The order status is updated in the database.
+    async fn reset_order(&self, order_id: &OrderId) -> Result<OrderChanged, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let old_order = orders::fetch_order_by_order_id(order_id, &mut tx)
+            .await?
+            .ok_or_else(|| AccountApiError::OrderDoesNotExist(order_id.clone()))?;
+        if !matches!(old_order.status, OrderStatusType::Expired | OrderStatusType::Cancelled) {
+            error!("🗃️ Order {} is not in 'Expired' or 'Cancelled' status. Cannot call reset_order", old_order.id);
+            return Err(PaymentGatewayError::OrderModificationForbidden);
+        }
+        let update = ModifyOrderRequest::default().with_new_status(OrderStatusType::New);
+        let updated_order = orders::update_order(&old_order.order_id, update, &mut tx)
+            .await?
+            .ok_or_else(|| AccountApiError::OrderDoesNotExist(old_order.order_id.clone()))?;
+        let result = OrderChanged::new(old_order, updated_order);
+        let modified = OrderModifiedEvent::new("status".to_string(), result.clone());
+        outbox::enqueue(&EventType::OrderModified(modified), &mut tx).await?;
+        outbox::enqueue(&EventType::NewOrder(OrderEvent::new(result.new_order.clone())), &mut tx).await?;
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    /// Change the customer id for the given `order_id`. This function has several side effects:
+    /// - The `customer_id` field of the order is updated in the database.
+    /// - If the new customer does not exist, a new one is created.
+    /// - If the order status was `Expired`, or `Cancelled`, it is **not** automatically reset to `New`. The admin must
+    ///   follow up with a "change status" call to reset the order.
+    ///
+    /// ## Returns:
+    /// - The updated order, if it was paid for by the new account.
+    ///
+    /// ## Failure modes:
+    /// - If the order does not exist, an error is returned.
+    /// - If the order status is already `Paid`, an error is returned.
+    async fn modify_customer_id_for_order(
+        &self,
+        id: &OrderId,
+        new_cid: &str,
+        strict_mode: bool,
+    ) -> Result<OrderMovedResult, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let old_order = Self::fetch_order_by_id(id, strict_mode, &mut tx).await?;
+        // Cannot change customer id on orders that have already been paid
+        if matches!(old_order.status, OrderStatusType::Paid) {
+            return Err(PaymentGatewayError::OrderModificationForbidden);
+        }
+        if new_cid == old_order.customer_id {
+            debug!("🗃️ Order {id} is being reassigned to the same customer. No action taken.");
+            tx.rollback().await?;
+            return Err(PaymentGatewayError::OrderModificationNoOp);
+        }
+        let update = ModifyOrderRequest::default().with_new_customer_id(new_cid);
+        let mut new_order = orders::update_order(&old_order.order_id, update, &mut tx).await?.ok_or_else(|| {
+            error!(
+                "Order {id} does not exist, but we fetched it within this same transaction. This should not happen. \
+                 There's a data race of sorts happening here and should be sorted out."
+            );
+            AccountApiError::OrderDoesNotExist(id.clone())
+        })?;
+        let changes = OrderChanged::new(old_order.clone(), new_order.clone());
+        let modified = OrderModifiedEvent::new("customer_id".to_string(), changes);
+        outbox::enqueue(&EventType::OrderModified(modified), &mut tx).await?;
+        // Order is either expired, cancelled or new by now. If expired or cancelled, we don't need to make any
+        // adjustments but new orders need to be accounted for.
+        tx.commit().await?;
+
+        let mut settlements = Vec::new();
+        if let OrderStatusType::New = new_order.status {
+            match self.try_pay_order(&new_order, strict_mode).await {
+                Ok(Some(payment)) => {
+                    let mut orders_paid;
+                    MultiAccountPayment { settlements, orders_paid, .. } = payment;
+                    orders_paid.drain(..1).for_each(|o| new_order = o);
+                },
+                Ok(None) => { /* noop */ },
+                Err(PaymentGatewayError::AccountError(AccountApiError::InsufficientFunds)) => {
+                    debug!(
+                        "🗃️ There weren't enough funds to pay for order {id} from the new customer id {} immediately",
+                        new_cid
+                    );
+                },
+                Err(e) => return Err(e),
+            };
+        }
+        let result = OrderMovedResult::new(old_order, new_order, settlements);
+        Ok(result)
+    }
+
+    /// Changes the memo field for an order.
+    /// Changing the memo does not trigger any other flows, does not affect
+    /// the order status, and does not affect order fulfillment.
+    ///
+    /// ## Returns:
+    /// The modified order
+    async fn modify_memo_for_order(&self, order_id: &OrderId, new_memo: &str) -> Result<Order, PaymentGatewayError> {
+        let update = ModifyOrderRequest::default().with_new_memo(new_memo);
+        let mut tx = self.pool.begin().await?;
+        let old_order = orders::fetch_order_by_order_id(order_id, &mut tx)
+            .await?
+            .ok_or_else(|| AccountApiError::OrderDoesNotExist(order_id.clone()))?;
+        let order = orders::update_order(order_id, update, &mut tx)
+            .await?
+            .ok_or_else(|| AccountApiError::OrderDoesNotExist(order_id.clone()))?;
+        let modified = OrderModifiedEvent::new("memo".to_string(), OrderChanged::new(old_order, order.clone()));
+        outbox::enqueue(&EventType::OrderModified(modified), &mut tx).await?;
+        tx.commit().await?;
+        Ok(order)
+    }
+
+    /// Changes the total price for an order.
+    ///
+    /// To return successfully, the order must exist, and have `New` status.
+    /// This function has several side effects:
+    /// - The `total_price` field of the order is updated in the database.
+    /// - The total orders for the account are updated.
+    ///
+    /// ## Failure modes:
+    /// - If the order does not exist.
+    /// - If the order status was `Expired`, or `Cancelled` or `Paid`.
+    async fn modify_total_price_for_order(
+        &self,
+        id: &OrderId,
+        new_total_price: MicroTari,
+        strict_mode: bool,
+    ) -> Result<OrderChanged, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let old_order = Self::fetch_order_by_id(id, strict_mode, &mut tx).await?;
+        if !matches!(old_order.status, OrderStatusType::New) {
+            info!("🗃️ Order {id}'s price cannot be changed since it is already {}", old_order.status);
+            return Err(PaymentGatewayError::OrderModificationForbidden);
+        }
+        if old_order.total_price == new_total_price {
+            info!("🗃️ Order {id}'s price is already {new_total_price}. No action taken.");
+            return Err(PaymentGatewayError::OrderModificationNoOp);
+        }
+        let update = ModifyOrderRequest::default().with_new_total_price(new_total_price);
+        let new_order = orders::update_order(&old_order.order_id, update, &mut tx).await?.ok_or_else(|| {
+            let msg = format!(
+                "Order {id} does not exist, but we fetched in within this same transaction. This represents a bug and \
+                 the transaction will be rolled back"
+            );
+            error!("{msg}");
+            PaymentGatewayError::DatabaseError(msg)
+        })?;
+        let delta = OrderChanged::new(old_order, new_order);
+        let modified = OrderModifiedEvent::new("total_price".to_string(), delta.clone());
+        outbox::enqueue(&EventType::OrderModified(modified), &mut tx).await?;
+        tx.commit().await?;
+        Ok(delta)
+    }
+
+    async fn fetch_orders_with_expired_quotes(&self) -> Result<Vec<Order>, PaymentGatewayError> {
+        let mut conn = self.pool.acquire().await?;
+        orders::fetch_orders_with_expired_quotes(&mut conn).await
+    }
+
+    async fn requote_order(
+        &self,
+        order_id: &OrderId,
+        new_total_price: MicroTari,
+        rate_id: i64,
+        quote_expires_at: DateTime<Utc>,
+    ) -> Result<OrderChanged, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let old_order = orders::fetch_order_by_order_id(order_id, &mut tx)
+            .await?
+            .ok_or_else(|| PaymentGatewayError::OrderNotFound(order_id.clone()))?;
+        if !matches!(old_order.status, OrderStatusType::New | OrderStatusType::Unclaimed) {
+            info!("🗃️ Order {order_id} cannot be re-priced since it is already {}", old_order.status);
+            return Err(PaymentGatewayError::OrderModificationForbidden);
+        }
+        let new_order = orders::update_quote(order_id, new_total_price, rate_id, quote_expires_at, &mut tx).await?;
+        let delta = OrderChanged::new(old_order, new_order);
+        let modified = OrderModifiedEvent::new("total_price".to_string(), delta.clone());
+        outbox::enqueue(&EventType::OrderModified(modified), &mut tx).await?;
+        tx.commit().await?;
+        Ok(delta)
+    }
+
+    async fn expire_old_orders(
+        &self,
+        unclaimed_limit: Duration,
+        unpaid_limit: Duration,
+    ) -> Result<ExpiryResult, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let unclaimed_orders = orders::expire_orders(OrderStatusType::Unclaimed, unclaimed_limit, &mut tx).await?;
+        let unpaid_orders = orders::expire_orders(OrderStatusType::New, unpaid_limit, &mut tx).await?;
+        for order in unclaimed_orders.iter().chain(unpaid_orders.iter()) {
+            outbox::enqueue(&EventType::OrderAnnulled(OrderAnnulledEvent::new(order.clone())), &mut tx).await?;
+        }
+        tx.commit().await?;
+        Ok(ExpiryResult::new(unclaimed_orders, unpaid_orders))
+    }
+
+    async fn request_refund(&self, refund: NewRefund) -> Result<Refund, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        accounts::lock_addresses(&[refund.address.as_address().clone()], &mut tx).await?;
+        let (address, order_id) = (refund.address.as_address(), refund.order_id.as_ref());
+        Self::check_refund(address, order_id, &refund.merchant_id, refund.amount, &mut tx).await?;
+        let refund = refunds::insert_refund(refund, &mut tx).await?;
+        tx.commit().await?;
+        let address = refund.address.as_address().to_base58();
+        info!("🗃️ Refund {} of {} to {address} has been requested", refund.id, refund.amount);
+        Ok(refund)
+    }
+
+    async fn approve_refund(&self, id: i64) -> Result<Refund, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let refund = Self::fetch_refund_with_status(id, RefundStatus::Requested, &mut tx).await?;
+        accounts::lock_addresses(&[refund.address.as_address().clone()], &mut tx).await?;
+        let (address, order_id) = (refund.address.as_address(), refund.order_id.as_ref());
+        Self::check_refund(address, order_id, &refund.merchant_id, refund.amount, &mut tx).await?;
+        let refund =
+            Self::update_refund(id, RefundStatus::Requested, RefundStatus::Approved, None, None, &mut tx).await?;
+        // A refund against an order comes out of the revenue of the order's merchant
+        let merchant_id = match &refund.order_id {
+            Some(order_id) => fetch_order_by_order_id(order_id, &mut tx).await?.map(|order| order.merchant_id),
+            None => None,
+        };
+        ledger::post(&NewLedgerTransaction::refund_approved(&refund, merchant_id.as_deref()), &mut tx).await?;
+        outbox::enqueue(&EventType::RefundApproved(RefundEvent::new(refund.clone())), &mut tx).await?;
+        tx.commit().await?;
+        info!("🗃️ Refund {id} of {} has been approved", refund.amount);
+        Ok(refund)
+    }
+
+    async fn reject_refund(&self, id: i64, reason: &str) -> Result<Refund, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let refund = Self::fetch_refund_with_status(id, RefundStatus::Requested, &mut tx).await?;
+        let reason = format!("{}. Rejected: {reason}", refund.reason);
+        let refund =
+            Self::update_refund(id, RefundStatus::Requested, RefundStatus::Rejected, Some(&reason), None, &mut tx)
+                .await?;
+        tx.commit().await?;
+        info!("🗃️ Refund {id} has been rejected");
+        Ok(refund)
+    }
+
+    async fn mark_refund_sent(&self, id: i64, payout_txid: &str) -> Result<Refund, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        Self::fetch_refund_with_status(id, RefundStatus::Approved, &mut tx).await?;
+        let refund =
+            Self::update_refund(id, RefundStatus::Approved, RefundStatus::Sent, None, Some(payout_txid), &mut tx)
+                .await?;
+        ledger::post(&NewLedgerTransaction::refund_sent(&refund), &mut tx).await?;
+        outbox::enqueue(&EventType::RefundSent(RefundEvent::new(refund.clone())), &mut tx).await?;
+        tx.commit().await?;
+        info!("🗃️ Refund {id} has been sent in transaction {payout_txid}");
+        Ok(refund)
+    }
+
+    async fn hold_order(&self, order: &NewOrder, reason: &str) -> Result<HeldOrder, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let id = &order.order_id;
+        if orders::order_exists(id, &mut tx).await?.is_some() ||
+            held_orders::fetch_held_order(id, &mut tx).await?.is_some()
+        {
+            return Err(PaymentGatewayError::OrderAlreadyExists(id.clone()));
+        }
+        let held_order = held_orders::insert_held_order(order, reason, &mut tx).await?;
+        outbox::enqueue(&EventType::OrderHeld(OrderHeldEvent::new(held_order.clone())), &mut tx).await?;
+        tx.commit().await?;
+        warn!("🗃️ Order {id} is being held. {reason}");
+        Ok(held_order)
+    }
+
+    async fn mark_held_order_released(&self, order_id: &OrderId) -> Result<HeldOrder, PaymentGatewayError> {
+        let mut conn = self.pool.acquire().await?;
+        let held_order = held_orders::release_held_order(order_id, &mut conn)
+            .await?
+            .ok_or_else(|| PaymentGatewayError::OrderNotHeld(order_id.clone()))?;
+        info!("🗃️ Held order {order_id} has been released");
+        Ok(held_order)
+    }
+
+    async fn request_approval(&self, request: NewApprovalRequest) -> Result<ApprovalRequest, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let request = approvals::insert_approval_request(request, &mut tx).await?;
+        outbox::enqueue(&EventType::ApprovalRequested(ApprovalEvent::new(request.clone())), &mut tx).await?;
+        tx.commit().await?;
+        info!("🗃️ Approval request {} has been recorded. {}", request.id, request.operation);
+        Ok(request)
+    }
+
+    async fn approve_request(&self, id: i64, approver: &TariAddress) -> Result<ApprovalRequest, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let request = approvals::fetch_approval_request(id, &mut tx)
+            .await?
+            .ok_or(PaymentGatewayError::ApprovalRequestNotFound(id))?;
+        request.check_decision(approver)?;
+        let request = Self::update_approval_request(
+            id,
+            ApprovalStatus::Pending,
+            ApprovalStatus::Approved,
+            Some(approver),
+            None,
+            &mut tx,
+        )
+        .await?;
+        outbox::enqueue(&EventType::OperationApproved(ApprovalEvent::new(request.clone())), &mut tx).await?;
+        tx.commit().await?;
+        info!("🗃️ Approval request {id} has been approved by {}", approver.to_base58());
+        Ok(request)
+    }
+
+    async fn reject_request(
+        &self,
+        id: i64,
+        approver: &TariAddress,
+        reason: &str,
+    ) -> Result<ApprovalRequest, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let request = approvals::fetch_approval_request(id, &mut tx)
+            .await?
+            .ok_or(PaymentGatewayError::ApprovalRequestNotFound(id))?;
+        request.check_decision(approver)?;
+        let request = Self::update_approval_request(
+            id,
+            ApprovalStatus::Pending,
+            ApprovalStatus::Rejected,
+            Some(approver),
+            Some(reason),
+            &mut tx,
+        )
+        .await?;
+        outbox::enqueue(&EventType::OperationRejected(ApprovalEvent::new(request.clone())), &mut tx).await?;
+        tx.commit().await?;
+        info!("🗃️ Approval request {id} has been rejected by {}", approver.to_base58());
+        Ok(request)
+    }
+
+    async fn mark_approval_failed(&self, id: i64, error: &str) -> Result<ApprovalRequest, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        Self::fetch_approval_with_status(id, ApprovalStatus::Approved, &mut tx).await?;
+        let request = Self::update_approval_request(
+            id,
+            ApprovalStatus::Approved,
+            ApprovalStatus::Failed,
+            None,
+            Some(error),
+            &mut tx,
+        )
+        .await?;
+        tx.commit().await?;
+        info!("🗃️ Approval request {id} has failed. {error}");
+        Ok(request)
+    }
+
+    async fn expire_approval_requests(&self) -> Result<Vec<ApprovalRequest>, PaymentGatewayError> {
+        let mut conn = self.pool.acquire().await?;
+        let expired = approvals::expire_approval_requests(&mut conn).await?;
+        if !expired.is_empty() {
+            info!("🗃️ {} approval requests have expired", expired.len());
+        }
+        Ok(expired)
+    }
+
+    async fn close(&mut self) -> Result<(), PaymentGatewayError> {
+        self.pool.close().await;
+        Ok(())
+    }
+}
+
+impl AccountManagement for PostgresDatabase {
+    async fn fetch_orders_for_address(&self, address: &TariAddress) -> Result<Vec<Order>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let orders = accounts::orders_for_address(address, &mut conn).await?;
+        Ok(orders)
+    }
+
+    async fn fetch_order_by_order_id(&self, order_id: &OrderId) -> Result<Option<Order>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let order = orders::fetch_order_by_order_id(order_id, &mut conn).await?;
+        Ok(order)
+    }
+
+    async fn fetch_order_by_alt_id(&self, alt: &OrderId) -> Result<Option<Order>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let order = orders::fetch_order_by_alt_id(alt, &mut conn).await?;
+        Ok(order)
+    }
+
+    async fn fetch_order_by_id_or_alt(&self, id: &OrderId) -> Result<Option<Order>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let order = orders::fetch_order_by_id_or_alt(id, &mut conn).await?;
+        Ok(order)
+    }
+
+    async fn fetch_payments_for_address(&self, address: &TariAddress) -> Result<Vec<Payment>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let payments = transfers::fetch_payments_for_address(address, &mut conn).await?;
+        Ok(payments)
+    }
+
+    async fn history_for_address(&self, address: &TariAddress) -> Result<AddressHistory, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let balance = accounts::fetch_address_balance(address, &mut conn).await?;
+        let payments = transfers::fetch_payments_for_address(address, &mut conn).await?;
+        let orders = accounts::orders_for_address(address, &mut conn).await?;
+        let settlements = accounts::settlements_for_address(address, &mut conn).await?;
+        let address = SerializedTariAddress::from(address.clone());
+        let history = AddressHistory::new(address, balance, orders, payments, settlements);
+        Ok(history)
+    }
+
+    async fn history_for_customer(&self, customer_id: &str) -> Result<CustomerHistory, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let balances = accounts::balances_for_customer_id(customer_id, &mut conn).await?;
+        let balance = CustomerBalance::new(balances);
+        let order_balance = accounts::customer_order_balance(customer_id, &mut conn).await?;
+        let query = OrderQueryFilter::default().with_customer_id(customer_id.to_string());
+        let orders = orders::search_orders(query, &mut conn).await?;
+        let settlements = accounts::settlements_for_customer_id(customer_id, &mut conn).await?;
+        let history = CustomerHistory::builder(customer_id.to_string())
+            .balance(balance)
+            .order_balance(order_balance)
+            .orders(orders)
+            .settlements(settlements)
+            .build()?;
+        Ok(history)
+    }
+
+    async fn search_orders(&self, query: OrderQueryFilter) -> Result<Vec<Order>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let orders = orders::search_orders(query, &mut conn).await?;
+        Ok(orders)
+    }
+
+    async fn creditors(&self) -> Result<Vec<CustomerOrders>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let accounts = accounts::creditors(&mut conn).await?;
+        Ok(accounts)
+    }
+
+    async fn fetch_customer_ids(&self, pagination: &Pagination) -> Result<Vec<String>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let ids = accounts::customer_ids(pagination, &mut conn).await?;
+        Ok(ids)
+    }
+
+    async fn fetch_addresses(&self, pagination: &Pagination) -> Result<Vec<TariAddress>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let addresses = accounts::addresses(pagination, &mut conn).await?;
+        Ok(addresses)
+    }
+
+    async fn fetch_address_balance(&self, address: &TariAddress) -> Result<AddressBalance, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let account = accounts::fetch_address_balance(address, &mut conn).await?;
+        Ok(account)
+    }
+
+    async fn fetch_customer_balance(&self, customer_id: &str) -> Result<CustomerBalance, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let balances = accounts::balances_for_customer_id(customer_id, &mut conn).await?;
+        let balance = CustomerBalance::new(balances);
+        Ok(balance)
+    }
+
+    async fn fetch_customer_order_balance(&self, customer_id: &str) -> Result<CustomerOrderBalance, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let balances = accounts::customer_order_balance(customer_id, &mut conn).await?;
+        Ok(balances)
+    }
+
+    async fn fetch_customer_ids_for_address(&self, address: &TariAddress) -> Result<Vec<String>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let ids = accounts::customer_ids_for_address(address, &mut conn).await?;
+        Ok(ids)
+    }
+
+    async fn fetch_payments_for_order(&self, order_id: &OrderId) -> Result<Vec<Payment>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let ids = transfers::fetch_payments_for_order(order_id, &mut conn).await?;
+        Ok(ids)
+    }
+
+    async fn fetch_onchain_payments(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Payment>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let payments = transfers::fetch_onchain_payments(since, &mut conn).await?;
+        Ok(payments)
+    }
+
+    async fn fetch_idle_balances(&self, idle: Duration) -> Result<Vec<AddressBalance>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let balances = accounts::fetch_idle_balances(idle, &mut conn).await?;
+        Ok(balances)
+    }
+
+    async fn fetch_refund(&self, id: i64) -> Result<Option<Refund>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let refund = refunds::fetch_refund(id, &mut conn).await?;
+        Ok(refund)
+    }
+
+    async fn fetch_refunds(
+        &self,
+        status: Option<RefundStatus>,
+        pagination: &Pagination,
+    ) -> Result<Vec<Refund>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let refunds = refunds::fetch_refunds(status, pagination, &mut conn).await?;
+        Ok(refunds)
+    }
+
+    async fn fetch_approval_request(&self, id: i64) -> Result<Option<ApprovalRequest>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let request = approvals::fetch_approval_request(id, &mut conn).await?;
+        Ok(request)
+    }
+
+    async fn fetch_approval_requests(
+        &self,
+        status: Option<ApprovalStatus>,
+        pagination: &Pagination,
+    ) -> Result<Vec<ApprovalRequest>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let requests = approvals::fetch_approval_requests(status, pagination, &mut conn).await?;
+        Ok(requests)
+    }
+
+    async fn fetch_held_order(&self, order_id: &OrderId) -> Result<Option<HeldOrder>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let held_order = held_orders::fetch_held_order(order_id, &mut conn).await?;
+        Ok(held_order)
+    }
+
+    async fn fetch_held_orders(&self, pagination: &Pagination) -> Result<Vec<HeldOrder>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let held_orders = held_orders::fetch_held_orders(pagination, &mut conn).await?;
+        Ok(held_orders)
+    }
+
+    async fn fetch_ledger_entries(
+        &self,
+        account: &LedgerAccount,
+        pagination: &Pagination,
+    ) -> Result<Vec<LedgerEntry>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let entries = ledger::fetch_entries(account, pagination, &mut conn).await?;
+        Ok(entries)
+    }
+
+    async fn fetch_ledger_balances(&self) -> Result<Vec<LedgerBalance>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let balances = ledger::fetch_balances(&mut conn).await?;
+        Ok(balances)
+    }
+
+    async fn fetch_trial_balance(&self) -> Result<TrialBalance, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let balances = ledger::fetch_balances(&mut conn).await?;
+        let unbalanced = ledger::fetch_unbalanced_transactions(&mut conn).await?;
+        Ok(TrialBalance::new(balances, unbalanced))
+    }
+
+    async fn fetch_orders_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Order>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let orders = orders::fetch_orders_between(from, to, &mut conn).await?;
+        Ok(orders)
+    }
+
+    async fn fetch_payments_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Payment>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let payments = transfers::fetch_payments_between(from, to, &mut conn).await?;
+        Ok(payments)
+    }
+
+    async fn fetch_settlements_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<SettlementJournalEntry>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        accounts::settlements_between(from, to, &mut conn).await
+    }
+}
+
+impl AuthManagement for PostgresDatabase {
+    async fn check_auth_account_exists(&self, address: &TariAddress) -> Result<bool, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::auth_account_exists(address, &mut conn).await
+    }
+
+    async fn check_address_has_roles(&self, address: &TariAddress, roles: &[Role]) -> Result<(), AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::address_has_roles(address, roles, &mut conn).await
+    }
+
+    async fn fetch_roles_for_address(&self, address: &TariAddress) -> Result<Vec<Role>, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        let roles = auth::roles_for_address(address, &mut conn).await?;
+        Ok(roles.into_iter().collect())
+    }
+
+    async fn create_auth_log(&self, _address: &TariAddress, _nonce: u64) -> Result<(), AuthApiError> {
+        // Postgres uses upsert
+        Ok(())
+    }
+
+    // Overriding this because we can use upserts
+    async fn upsert_nonce_for_address(&self, address: &TariAddress, nonce: u64) -> Result<(), AuthApiError> {
+        self.update_nonce_for_address(address, nonce).await
+    }
+
+    // This implementation is an upsert under the hood
+    async fn update_nonce_for_address(&self, address: &TariAddress, nonce: u64) -> Result<(), AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::upsert_nonce_for_address(address, nonce, &mut conn).await
+    }
+
+    async fn assign_roles(&self, address: &TariAddress, roles: &[Role]) -> Result<(), AuthApiError> {
+        let mut tx = self.pool.begin().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::assign_roles(address, roles, &mut tx).await?;
+        tx.commit().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        debug!("🔑️ Roles {roles:?} assigned to {}", address.to_base58());
+        Ok(())
+    }
+
+    async fn remove_roles(&self, address: &TariAddress, roles: &[Role]) -> Result<u64, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::remove_roles(address, roles, &mut conn).await
+    }
+
+    async fn fetch_merchant_roles_for_address(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+    ) -> Result<Vec<Role>, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        let roles = auth::merchant_roles_for_address(address, merchant_id, &mut conn).await?;
+        Ok(roles.into_iter().collect())
+    }
+
+    async fn assign_merchant_roles(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+        roles: &[Role],
+    ) -> Result<(), AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::assign_merchant_roles(address, merchant_id, roles, &mut conn).await?;
+        debug!("🔑️ Roles {roles:?} assigned to {} for merchant {merchant_id}", address.to_base58());
+        Ok(())
+    }
+
+    async fn remove_merchant_roles(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+        roles: &[Role],
+    ) -> Result<u64, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::remove_merchant_roles(address, merchant_id, roles, &mut conn).await
+    }
+
+    async fn fetch_role_definitions(&self) -> Result<Vec<RoleDefinition>, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::fetch_role_definitions(&mut conn).await
+    }
+
+    async fn save_role(&self, role: &RoleDefinition) -> Result<(), AuthApiError> {
+        let mut tx = self.pool.begin().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::save_role(role, &mut tx).await?;
+        tx.commit().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        debug!("🔑️ Role {} now grants {:?}", role.role, role.permissions);
+        Ok(())
+    }
+
+    async fn delete_role(&self, role: &Role) -> Result<(), AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::delete_role(role, &mut conn).await?;
+        debug!("🔑️ Role {role} deleted");
+        Ok(())
+    }
+
+    async fn create_session(&self, session: NewAuthSession) -> Result<AuthSession, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        let session = sessions::insert_session(session, &mut conn).await?;
+        debug!("🔑️ Session #{} opened for {}", session.id, session.address);
+        Ok(session)
+    }
+
+    async fn fetch_session(&self, id: i64) -> Result<Option<AuthSession>, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        sessions::fetch_session(id, &mut conn).await
+    }
+
+    async fn refresh_session(
+        &self,
+        refresh_token_hash: &str,
+        new_refresh_token_hash: &str,
+    ) -> Result<AuthSession, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        sessions::refresh_session(refresh_token_hash, new_refresh_token_hash, &mut conn).await
+    }
+
+    async fn fetch_sessions_for_address(&self, address: &TariAddress) -> Result<Vec<AuthSession>, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        sessions::fetch_sessions_for_address(address, &mut conn).await
+    }
+
+    async fn revoke_session(&self, id: i64) -> Result<AuthSession, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        let session = sessions::revoke_session(id, &mut conn).await?;
+        debug!("🔑️ Session #{id} for {} revoked", session.address);
+        Ok(session)
+    }
+
+    async fn revoke_sessions_for_address(&self, address: &TariAddress) -> Result<Vec<i64>, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        let ids = sessions::revoke_sessions_for_address(address, &mut conn).await?;
+        debug!("🔑️ {} sessions for {} revoked", ids.len(), address.to_base58());
+        Ok(ids)
+    }
+
+    async fn fetch_revoked_sessions(&self, since: DateTime<Utc>) -> Result<Vec<i64>, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        sessions::fetch_revoked_sessions(since, &mut conn).await
+    }
+}
+
+impl WalletAuth for PostgresDatabase {
+    async fn get_wallet_info(&self, wallet_address: &TariAddress) -> Result<WalletInfo, WalletAuthApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let result = wallet_auth::fetch_wallet_info_for_address(wallet_address, &mut conn).await?;
+        Ok(result)
+    }
+
+    async fn update_wallet_nonce(
+        &self,
+        wallet_address: &TariAddress,
+        new_nonce: i64,
+    ) -> Result<(), WalletAuthApiError> {
+        let mut conn = self.pool.acquire().await?;
+        wallet_auth::update_wallet_nonce(wallet_address, new_nonce, &mut conn).await?;
+        Ok(())
+    }
+}
+
+impl WalletManagement for PostgresDatabase {
+    async fn register_wallet(&self, wallet: NewWalletInfo) -> Result<(), WalletManagementError> {
+        let mut conn = self.pool.acquire().await?;
+        wallet_auth::register_wallet(wallet, &mut conn).await
+    }
+
+    async fn deregister_wallet(&self, _wallet_address: &TariAddress) -> Result<(), WalletManagementError> {
+        let mut conn = self.pool.acquire().await?;
+        wallet_auth::deregister_wallet(_wallet_address, &mut conn).await
+    }
+
+    async fn fetch_authorized_wallets(&self) -> Result<Vec<WalletInfo>, WalletManagementError> {
+        let mut conn = self.pool.acquire().await?;
+        wallet_auth::fetch_authorized_wallets(&mut conn).await
+    }
+}
+
+impl ExchangeRates for PostgresDatabase {
+    async fn fetch_last_rate(&self, currency: &str) -> Result<ExchangeRate, ExchangeRateError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+        exchange_rates::fetch_last_rate(currency, &mut conn).await
+    }
+
+    async fn fetch_merchant_rate(&self, merchant_id: &str, currency: &str) -> Result<ExchangeRate, ExchangeRateError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+        exchange_rates::fetch_merchant_rate(merchant_id, currency, &mut conn).await
+    }
+
+    async fn fetch_rate_by_id(&self, id: i64) -> Result<ExchangeRate, ExchangeRateError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+        exchange_rates::fetch_rate_by_id(id, &mut conn).await
+    }
+
+    async fn fetch_rate_at(&self, currency: &str, timestamp: DateTime<Utc>) -> Result<ExchangeRate, ExchangeRateError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+        exchange_rates::fetch_rate_at(currency, timestamp, &mut conn).await
+    }
+
+    async fn fetch_previous_rate(&self, rate: &ExchangeRate) -> Result<Option<ExchangeRate>, ExchangeRateError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+        exchange_rates::fetch_previous_rate(rate, &mut conn).await
+    }
+
+    async fn fetch_rate_history(
+        &self,
+        merchant_id: &str,
+        currency: &str,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        pagination: &Pagination,
+    ) -> Result<Vec<ExchangeRate>, ExchangeRateError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+        exchange_rates::fetch_rate_history(merchant_id, currency, since, until, pagination, &mut conn).await
+    }
+
+    /// Save the exchange rate for the given currency to the backend storage
+    ///
+    /// The `updated_at` field of the exchange rate is ignored. The backend will set this field to the current time.
+    async fn set_exchange_rate(&self, new_rate: &ExchangeRate) -> Result<(), ExchangeRateError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+        exchange_rates::set_exchange_rate(new_rate, &mut conn).await
+    }
+
+    async fn confirm_exchange_rate(&self, rate: &ExchangeRate) -> Result<(), ExchangeRateError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+        exchange_rates::confirm_exchange_rate(rate.id, &mut conn).await
+    }
+}
+
+impl EventOutbox for PostgresDatabase {
+    async fn enqueue_events(&self, events: &[EventType]) -> Result<Vec<OutboxEvent>, OutboxError> {
+        let mut tx = self.pool.begin().await?;
+        let mut result = Vec::with_capacity(events.len());
+        for event in events {
+            let id = outbox::enqueue(event, &mut tx).await?;
+            let event = outbox::fetch_event(id, &mut tx).await?.ok_or(OutboxError::EventNotFound(id))?;
+            result.push(event);
+        }
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    async fn fetch_due_events(&self, limit: i64) -> Result<Vec<OutboxEvent>, OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::fetch_due_events(limit, &mut conn).await
+    }
+
+    async fn claim_due_events(&self, limit: i64, lease: Duration) -> Result<Vec<OutboxEvent>, OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::claim_due_events(limit, Utc::now() + lease, &mut conn).await
+    }
+
+    async fn fetch_undelivered_events(&self, pagination: &Pagination) -> Result<Vec<OutboxEvent>, OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::fetch_undelivered_events(pagination, &mut conn).await
+    }
+
+    async fn fetch_outbox_event(&self, id: i64) -> Result<Option<OutboxEvent>, OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::fetch_event(id, &mut conn).await
+    }
+
+    async fn mark_event_delivered(&self, id: i64) -> Result<OutboxEvent, OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::mark_delivered(id, &mut conn).await
+    }
+
+    async fn record_failed_delivery(
+        &self,
+        id: i64,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<OutboxEvent, OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::record_failure(id, error, retry_at, &mut conn).await
+    }
+
+    async fn fetch_delivered_handlers(&self, id: i64) -> Result<Vec<String>, OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::fetch_delivered_handlers(id, &mut conn).await
+    }
+
+    async fn mark_handler_delivered(&self, id: i64, handler: &str) -> Result<(), OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::mark_handler_delivered(id, handler, &mut conn).await
+    }
+
+    async fn replay_event(&self, id: i64) -> Result<OutboxEvent, OutboxError> {
+        let mut tx = self.pool.begin().await?;
+        let event = outbox::replay(id, &mut tx).await?;
+        tx.commit().await?;
+        Ok(event)
+    }
+}
+
+impl WebhookManagement for PostgresDatabase {
+    async fn create_webhook(&self, webhook: &NewWebhookSubscription) -> Result<WebhookSubscription, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::insert_subscription(webhook, &mut conn).await
+    }
+
+    async fn fetch_webhooks(&self) -> Result<Vec<WebhookSubscription>, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::fetch_subscriptions(&mut conn).await
+    }
+
+    async fn fetch_webhook(&self, id: i64) -> Result<Option<WebhookSubscription>, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::fetch_subscription(id, &mut conn).await
+    }
+
+    async fn set_webhook_active(&self, id: i64, active: bool) -> Result<WebhookSubscription, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::set_active(id, active, &mut conn).await
+    }
+
+    async fn delete_webhook(&self, id: i64) -> Result<(), WebhookError> {
+        let mut tx = self.pool.begin().await?;
+        webhooks::delete_subscription(id, &mut tx).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn queue_webhook_deliveries(&self, event: &OutboxEvent) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::queue_deliveries(event, &mut conn).await
+    }
+
+    async fn fetch_due_webhook_deliveries(&self, limit: i64) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::fetch_due_deliveries(limit, &mut conn).await
+    }
+
+    async fn claim_due_webhook_deliveries(
+        &self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::claim_due_deliveries(limit, Utc::now() + lease, &mut conn).await
+    }
+
+    async fn fetch_webhook_deliveries(
+        &self,
+        subscription_id: Option<i64>,
+        pagination: &Pagination,
+    ) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::fetch_deliveries(subscription_id, pagination, &mut conn).await
+    }
+
+    async fn mark_webhook_delivered(&self, id: i64, status: i64) -> Result<WebhookDelivery, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::mark_delivered(id, status, &mut conn).await
+    }
+
+    async fn record_failed_webhook_delivery(
+        &self,
+        id: i64,
+        status: Option<i64>,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<WebhookDelivery, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::record_failure(id, status, error, retry_at, &mut conn).await
+    }
+
+    async fn replay_webhook_delivery(&self, id: i64) -> Result<WebhookDelivery, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::replay(id, &mut conn).await
+    }
+}
+
+impl AuditLog for PostgresDatabase {
+    async fn record_admin_action(&self, action: &NewAdminAction) -> Result<AdminAction, AuditLogError> {
+        let mut conn = self.pool.acquire().await?;
+        audit::insert_admin_action(action, &mut conn).await
+    }
+
+    async fn fetch_admin_actions(
+        &self,
+        filter: &AdminActionFilter,
+        pagination: &Pagination,
+    ) -> Result<Vec<AdminAction>, AuditLogError> {
+        let mut conn = self.pool.acquire().await?;
+        audit::fetch_admin_actions(filter, pagination, &mut conn).await
+    }
+}
+
+impl RateLimitStore for PostgresDatabase {
+    async fn take_token(&self, key: &str, limit: &RateLimit) -> Result<RateLimitDecision, RateLimitError> {
+        let mut conn = self.pool.acquire().await?;
+        Ok(rate_limits::take_token(key, limit, &mut conn).await?)
+    }
+
+    async fn prune_rate_limits(&self) -> Result<u64, RateLimitError> {
+        let mut conn = self.pool.acquire().await?;
+        Ok(rate_limits::prune_rate_limits(&mut conn).await?)
+    }
+}
+
+impl PostgresDatabase {
+    /// Creates a new database API object
+    pub async fn new(max_connections: u32) -> Result<Self, sqlx::Error> {
+        let url = db_url();
+        PostgresDatabase::new_with_url(url.as_str(), max_connections).await
+    }
+
+    pub async fn new_with_url(url: &str, max_connections: u32) -> Result<Self, sqlx::Error> {
+        trace!("Creating new database connection pool with url {url}");
+        let pool = new_pool(url, max_connections).await?;
+        let url = url.to_string();
+        Ok(Self { url, pool })
+    }
+
+    /// Returns a reference to the database connection pool.
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Each order is paid from the funds that the address holds with the order's merchant. Once a merchant's order
+    /// cannot be paid, none of that merchant's later orders are paid either.
+    async fn pay_orders_for_address_with_conn(
+        &self,
+        address: &TariAddress,
+        orders: &[&Order],
+        tx: &mut sqlx::PgConnection,
+    ) -> Result<Option<MultiAccountPayment>, PaymentGatewayError> {
+        accounts::lock_addresses(&[address.clone()], tx).await?;
+        let mut credit = HashMap::<String, MicroTari>::new();
+        let mut unpayable = HashSet::<String>::new();
+        let mut paid_orders = Vec::with_capacity(orders.len());
+        let mut settlements = Vec::with_capacity(orders.len());
+        for &order in orders {
+            // The order may have been paid, or repriced, since it was fetched
+            let Some(order) = orders::lock_order(order.id, tx).await? else {
+                continue;
+            };
+            if ![OrderStatusType::New, OrderStatusType::Unclaimed].contains(&order.status) {
+                debug!("🗃️ Order [{}] has status {} and will not be paid again", order.order_id, order.status);
+                continue;
+            }
+            if order.quote_has_expired() {
+                debug!(
+                    "🗃️ The price quote for order [{}] has expired. It will be paid once it is re-priced",
+                    order.order_id
+                );
+                continue;
+            }
+            if unpayable.contains(&order.merchant_id) {
+                continue;
+            }
+            let remaining_credit = match credit.get(&order.merchant_id) {
+                Some(remaining) => *remaining,
+                None => {
+                    let balance = accounts::fetch_merchant_balance(address, &order.merchant_id, tx).await?;
+                    let merchant_id = &order.merchant_id;
+                    trace!("🗃️ Balance of {} with {merchant_id} is {}", address.to_base58(), balance.current_balance());
+                    balance.current_balance()
+                },
+            };
+            // We must be able to pay for the entire order, or no deal.
+            trace!("🗃️ Checking if there's enough credit ({remaining_credit}) to pay for order [{}]", order.order_id);
+            if order.total_price > remaining_credit {
+                unpayable.insert(order.merchant_id.clone());
+                continue;
+            }
+            trace!("🗃️ Order [{}] can be paid", order.order_id);
+            credit.insert(order.merchant_id.clone(), remaining_credit - order.total_price);
+            let settlement = NewSettlementJournalEntry {
+                order_id: order.order_id.clone(),
+                payment_address: SerializedTariAddress::from(address.clone()),
+                amount: order.total_price,
+                settlement_type: SettlementType::Single,
+                merchant_id: order.merchant_id.clone(),
+            };
+            let settlement = accounts::insert_settlement(settlement, tx).await?;
+            ledger::post(&NewLedgerTransaction::settlement(&settlement, &order.merchant_id), tx).await?;
+            trace!("🗃️ Settlement journal entry created for order [{}] (id: {})", order.order_id, order.id);
+            settlements.push(settlement);
+            let updated_order = orders::update_order_status(order.id, OrderStatusType::Paid, tx).await?;
+            outbox::enqueue(&EventType::OrderPaid(OrderEvent::new(updated_order.clone())), tx).await?;
+            debug!("🗃️ Order {} paid for during multi-account payment", order.id);
+            paid_orders.push(updated_order);
+        }
+
+        let result = (!paid_orders.is_empty()).then(|| MultiAccountPayment::new(paid_orders, settlements));
+        Ok(result)
+    }
+
+    async fn fetch_refund_with_status(
+        id: i64,
+        status: RefundStatus,
+        conn: &mut PgConnection,
+    ) -> Result<Refund, PaymentGatewayError> {
+        let refund = refunds::fetch_refund(id, conn).await?.ok_or(PaymentGatewayError::RefundNotFound(id))?;
+        if refund.status != status {
+            return Err(PaymentGatewayError::InvalidRefund(format!(
+                "Refund {id} has status {} instead of '{status}'",
+                refund.status
+            )));
+        }
+        Ok(refund)
+    }
+
+    /// Moves the refund on from the `current` status. Concurrent calls for the same refund both pass the status check
+    /// in [`Self::fetch_refund_with_status`], but only the first one gets to update the refund.
+    async fn update_refund(
+        id: i64,
+        current: RefundStatus,
+        status: RefundStatus,
+        reason: Option<&str>,
+        payout_txid: Option<&str>,
+        conn: &mut PgConnection,
+    ) -> Result<Refund, PaymentGatewayError> {
+        refunds::update_refund(id, current, status, reason, payout_txid, conn).await?.ok_or_else(|| {
+            PaymentGatewayError::InvalidRefund(format!(
+                "Refund {id} is no longer '{current}', so it cannot be moved to '{status}'"
+            ))
+        })
+    }
+
+    /// Moves the approval request on from the `current` status. Concurrent decisions on the same request both pass
+    /// [`ApprovalRequest::check_decision`], but only the first one gets to update the request.
+    async fn update_approval_request(
+        id: i64,
+        current: ApprovalStatus,
+        status: ApprovalStatus,
+        decided_by: Option<&TariAddress>,
+        reason: Option<&str>,
+        conn: &mut PgConnection,
+    ) -> Result<ApprovalRequest, PaymentGatewayError> {
+        approvals::update_approval_request(id, current, status, decided_by, reason, conn).await?.ok_or_else(|| {
+            PaymentGatewayError::InvalidApproval(format!(
+                "Approval request {id} is no longer '{current}', so it cannot be moved to '{status}'"
+            ))
+        })
+    }
+
+    async fn fetch_approval_with_status(
+        id: i64,
+        status: ApprovalStatus,
+        conn: &mut PgConnection,
+    ) -> Result<ApprovalRequest, PaymentGatewayError> {
+        let request = approvals::fetch_approval_request(id, conn)
+            .await?
+            .ok_or(PaymentGatewayError::ApprovalRequestNotFound(id))?;
+        if request.status != status {
+            return Err(PaymentGatewayError::InvalidApproval(format!(
+                "Approval request {id} has status {} instead of '{status}'",
+                request.status
+            )));
+        }
+        Ok(request)
+    }
+
+    /// Checks that `amount` can be refunded to `address`, either from what the address paid towards the order, or
+    /// from its current balance with `merchant_id`.
+    async fn check_refund(
+        address: &TariAddress,
+        order_id: Option<&OrderId>,
+        merchant_id: &str,
+        amount: MicroTari,
+        conn: &mut PgConnection,
+    ) -> Result<(), PaymentGatewayError> {
+        if amount <= MicroTari::from(0) {
+            return Err(PaymentGatewayError::InvalidRefund("The refund amount must be positive".into()));
+        }
+        let available = match order_id {
+            Some(order_id) => {
+                let order = fetch_order_by_order_id(order_id, conn)
+                    .await?
+                    .ok_or_else(|| PaymentGatewayError::OrderNotFound(order_id.clone()))?;
+                if order.status != OrderStatusType::Paid {
+                    return Err(PaymentGatewayError::InvalidRefund(format!(
+                        "Order {order_id} has status {} and cannot be refunded",
+                        order.status
+                    )));
+                }
+                refunds::refundable_for_order(order_id, address, conn).await?
+            },
+            None => accounts::fetch_merchant_balance(address, merchant_id, conn).await?.current_balance(),
+        };
+        if amount > available {
+            return Err(PaymentGatewayError::InvalidRefund(format!(
+                "{amount} exceeds the {available} that can be refunded to {}",
+                address.to_base58()
+            )));
+        }
+        Ok(())
+    }
+
+    async fn fetch_order_by_id(
+        id: &OrderId,
+        strict_mode: bool,
+        conn: &mut PgConnection,
+    ) -> Result<Order, PaymentGatewayError> {
+        if strict_mode { fetch_order_by_order_id(id, conn).await? } else { fetch_order_by_id_or_alt(id, conn).await? }
+            .ok_or_else(|| PaymentGatewayError::OrderNotFound(id.clone()))
+    }
+
+    async fn claim_order_with_conn(
+        &self,
+        id: &OrderId,
+        address: &TariAddress,
+        strict_mode: bool,
+        tx: &mut sqlx::PgConnection,
+    ) -> Result<Order, PaymentGatewayError> {
+        let order = Self::fetch_order_by_id(id, strict_mode, tx).await?;
+        let addr58 = address.to_base58();
+        if order.status != OrderStatusType::Unclaimed {
+            warn!(
+                "🖇️️ Order {} is not 'Unclaimed' and {addr58} is trying to claim it. The current status is {}",
+                order.order_id, order.status
+            );
+        }
+        let order = orders::update_order_status(order.id, OrderStatusType::New, tx).await?;
+        accounts::link_address_to_customer(address, &order.customer_id, tx).await?;
+        let event = EventType::OrderClaimed(OrderClaimedEvent::new(order.clone(), address.clone()));
+        outbox::enqueue(&event, tx).await?;
+        info!("🗃️ Address {addr58} has been linked with customer id {}", order.customer_id);
+        Ok(order)
+    }
+}
@@ -1,3 +1,4 @@
+use chrono::{DateTime, Duration, Utc};
 use sqlx::{sqlite::SqliteRow, Row, SqliteConnection};
 use tari_common_types::tari_address::TariAddress;
 
@@ -45,20 +46,54 @@ pub(crate) async fn balances_for_customer_id(
     Ok(addresses)
 }
 
-pub(crate) async fn balances_for_order_id(
+pub(crate) async fn fetch_address_balance(
+    address: &TariAddress,
+    conn: &mut SqliteConnection,
+) -> Result<AddressBalance, AccountApiError> {
+    let balance: Option<AddressBalance> = sqlx::query_as("SELECT * FROM address_balance WHERE address = $1")
+        .bind(address.to_base58())
+        .fetch_optional(conn)
+        .await?;
+    Ok(balance.unwrap_or_else(|| AddressBalance::new(address.clone())))
+}
+
+/// The balances that the customer's addresses hold with `merchant_id`, i.e. the funds that can pay for that merchant's
+/// orders.
+pub(crate) async fn merchant_balances_for_customer_id(
+    customer_id: &str,
+    merchant_id: &str,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<AddressBalance>, AccountApiError> {
+    let addresses: Vec<AddressBalance> = sqlx::query_as(
+        r#"
+    SELECT * FROM merchant_balance
+    WHERE merchant_id = $2 AND address in (SELECT address from address_customer_id_link WHERE customer_id = $1)
+    ORDER BY last_update DESC
+    "#,
+    )
+    .bind(customer_id)
+    .bind(merchant_id)
+    .fetch_all(conn)
+    .await?;
+    Ok(addresses)
+}
+
+/// The balances with `merchant_id` of the addresses that sent payments for the order.
+pub(crate) async fn merchant_balances_for_order_id(
     order_id: &OrderId,
     alt_id: Option<&OrderId>,
+    merchant_id: &str,
     conn: &mut SqliteConnection,
 ) -> Result<Vec<AddressBalance>, AccountApiError> {
     let where_clause = match alt_id {
-        None => "order_id = $1",
-        Some(_) => "order_id = $1 OR order_id = $2",
+        None => "order_id = $2",
+        Some(_) => "order_id = $2 OR order_id = $3",
     };
     let q_str = format!(
-        "SELECT * FROM address_balance WHERE address in (SELECT sender from payments WHERE {where_clause}) ORDER BY \
-         last_update DESC"
+        "SELECT * FROM merchant_balance WHERE merchant_id = $1 AND address in (SELECT sender from payments WHERE \
+         {where_clause}) ORDER BY last_update DESC"
     );
-    let mut query = sqlx::query_as(&q_str).bind(order_id.as_str());
+    let mut query = sqlx::query_as(&q_str).bind(merchant_id).bind(order_id.as_str());
     if let Some(alt_id) = alt_id {
         query = query.bind(alt_id.as_str());
     }
@@ -66,15 +101,33 @@ pub(crate) async fn balances_for_order_id(
     Ok(addresses)
 }
 
-pub(crate) async fn fetch_address_balance(
+/// The funds that `address` holds with `merchant_id`
+pub(crate) async fn fetch_merchant_balance(
     address: &TariAddress,
+    merchant_id: &str,
     conn: &mut SqliteConnection,
 ) -> Result<AddressBalance, AccountApiError> {
-    let balance: Option<AddressBalance> = sqlx::query_as("SELECT * FROM address_balance WHERE address = $1")
-        .bind(address.to_base58())
-        .fetch_optional(conn)
-        .await?;
-    Ok(balance.unwrap_or_else(|| AddressBalance::new(address.clone())))
+    let balance: Option<AddressBalance> =
+        sqlx::query_as("SELECT * FROM merchant_balance WHERE address = $1 AND merchant_id = $2")
+            .bind(address.to_base58())
+            .bind(merchant_id)
+            .fetch_optional(conn)
+            .await?;
+    Ok(balance.unwrap_or_else(|| AddressBalance::for_merchant(address.clone(), merchant_id)))
+}
+
+pub(crate) async fn fetch_idle_balances(
+    idle: Duration,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<AddressBalance>, AccountApiError> {
+    let balances = sqlx::query_as(
+        "SELECT * FROM merchant_balance WHERE current_balance > 0 AND (unixepoch(CURRENT_TIMESTAMP) - \
+         unixepoch(last_update)) >= $1 ORDER BY last_update",
+    )
+    .bind(idle.num_seconds())
+    .fetch_all(conn)
+    .await?;
+    Ok(balances)
 }
 
 pub(crate) async fn insert_settlement(
@@ -83,8 +136,8 @@ pub(crate) async fn insert_settlement(
 ) -> Result<SettlementJournalEntry, AccountApiError> {
     let result = sqlx::query_as(
         r#"
-    INSERT INTO settlement_journal (order_id, payment_address, amount, settlement_type)
-    VALUES (?, ?, ?, ?)
+    INSERT INTO settlement_journal (order_id, payment_address, amount, settlement_type, merchant_id)
+    VALUES (?, ?, ?, ?, ?)
     RETURNING *
     "#,
     )
@@ -92,6 +145,7 @@ pub(crate) async fn insert_settlement(
     .bind(settlement.payment_address.as_base58())
     .bind(settlement.amount)
     .bind(settlement.settlement_type)
+    .bind(settlement.merchant_id)
     .fetch_one(conn)
     .await?;
     Ok(result)
@@ -109,6 +163,18 @@ pub(crate) async fn settlements_for_address(
     Ok(settlements)
 }
 
+pub(crate) async fn settlements_for_order(
+    order_id: &OrderId,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<SettlementJournalEntry>, AccountApiError> {
+    let settlements: Vec<SettlementJournalEntry> =
+        sqlx::query_as("SELECT * FROM settlement_journal WHERE order_id = $1 ORDER BY id")
+            .bind(order_id.as_str())
+            .fetch_all(conn)
+            .await?;
+    Ok(settlements)
+}
+
 pub(crate) async fn settlements_for_customer_id(
     customer_id: &str,
     conn: &mut SqliteConnection,
@@ -125,6 +191,22 @@ pub(crate) async fn settlements_for_customer_id(
     Ok(settlements)
 }
 
+/// Fetches the settlement journal entries that were made in the period `[from, to)`, oldest first.
+pub(crate) async fn settlements_between(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<SettlementJournalEntry>, AccountApiError> {
+    let settlements = sqlx::query_as(
+        "SELECT * FROM settlement_journal WHERE unixepoch(created_at) >= $1 AND unixepoch(created_at) < $2 ORDER BY id",
+    )
+    .bind(from.timestamp())
+    .bind(to.timestamp())
+    .fetch_all(conn)
+    .await?;
+    Ok(settlements)
+}
+
 pub(crate) async fn orders_for_address(
     address: &TariAddress,
     conn: &mut SqliteConnection,
@@ -142,7 +224,10 @@ pub(crate) async fn orders_for_address(
         orders.currency as currency,
         orders.created_at as created_at,
         orders.updated_at as updated_at,
-        orders.status as status
+        orders.status as status,
+        orders.rate_id as rate_id,
+        orders.quote_expires_at as quote_expires_at,
+        orders.merchant_id as merchant_id
     FROM orders JOIN address_customer_id_link ON orders.customer_id = address_customer_id_link.customer_id
     WHERE address = $1
     "#,
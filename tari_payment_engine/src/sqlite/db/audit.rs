@@ -0,0 +1,110 @@
+//! Sqlite database operations for the admin audit log
+//!
+//! Generally clients should never call these methods directly, and prefer to use the [`crate::traits::AuditLog`]
+//! trait methods that are implemented on the [`crate::SqliteDatabase`] struct instead.
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, QueryBuilder, SqliteConnection};
+
+use crate::{
+    db_types::{roles_from_list, AdminAction, AdminActionFilter, AuditOutcome, NewAdminAction, SerializedTariAddress},
+    tpe_api::account_objects::Pagination,
+    traits::AuditLogError,
+};
+
+#[derive(FromRow)]
+struct AdminActionRow {
+    id: i64,
+    created_at: DateTime<Utc>,
+    actor: SerializedTariAddress,
+    roles: String,
+    merchant_id: Option<String>,
+    endpoint: String,
+    method: String,
+    path: String,
+    parameters: String,
+    reason: Option<String>,
+    client_ip: Option<String>,
+    outcome: AuditOutcome,
+    status_code: i64,
+    error: Option<String>,
+}
+
+impl TryFrom<AdminActionRow> for AdminAction {
+    type Error = AuditLogError;
+
+    fn try_from(row: AdminActionRow) -> Result<Self, Self::Error> {
+        let roles = roles_from_list(&row.roles).map_err(|e| AuditLogError::SerializationError(e.to_string()))?;
+        Ok(Self {
+            id: row.id,
+            created_at: row.created_at,
+            actor: row.actor,
+            roles,
+            merchant: row.merchant_id,
+            endpoint: row.endpoint,
+            method: row.method,
+            path: row.path,
+            parameters: serde_json::from_str(&row.parameters)?,
+            reason: row.reason,
+            client_ip: row.client_ip,
+            outcome: row.outcome,
+            status_code: row.status_code,
+            error: row.error,
+        })
+    }
+}
+
+pub(crate) async fn insert_admin_action(
+    action: &NewAdminAction,
+    conn: &mut SqliteConnection,
+) -> Result<AdminAction, AuditLogError> {
+    let row: AdminActionRow = sqlx::query_as(
+        "INSERT INTO admin_actions (actor, roles, merchant_id, endpoint, method, path, parameters, reason, client_ip, \
+         outcome, status_code, error) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING *",
+    )
+    .bind(action.actor.to_base58())
+    .bind(action.roles_list())
+    .bind(&action.merchant)
+    .bind(&action.endpoint)
+    .bind(&action.method)
+    .bind(&action.path)
+    .bind(serde_json::to_string(&action.parameters)?)
+    .bind(&action.reason)
+    .bind(&action.client_ip)
+    .bind(action.outcome)
+    .bind(action.status_code)
+    .bind(&action.error)
+    .fetch_one(conn)
+    .await?;
+    row.try_into()
+}
+
+pub(crate) async fn fetch_admin_actions(
+    filter: &AdminActionFilter,
+    pagination: &Pagination,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<AdminAction>, AuditLogError> {
+    let mut builder = QueryBuilder::new("SELECT * FROM admin_actions WHERE 1 = 1");
+    if let Some(actor) = &filter.actor {
+        builder.push(" AND actor = ").push_bind(actor.as_base58());
+    }
+    if let Some(endpoint) = &filter.endpoint {
+        builder.push(" AND endpoint = ").push_bind(endpoint.clone());
+    }
+    if let Some(outcome) = filter.outcome {
+        builder.push(" AND outcome = ").push_bind(outcome);
+    }
+    if let Some(since) = filter.since {
+        builder.push(" AND unixepoch(created_at) >= ").push_bind(since.timestamp());
+    }
+    if let Some(until) = filter.until {
+        builder.push(" AND unixepoch(created_at) <= ").push_bind(until.timestamp());
+    }
+    // SQLite only accepts an OFFSET after a LIMIT. A negative limit means "no limit".
+    builder
+        .push(" ORDER BY id DESC LIMIT ")
+        .push_bind(pagination.count.unwrap_or(-1))
+        .push(" OFFSET ")
+        .push_bind(pagination.offset.unwrap_or(0));
+    let rows: Vec<AdminActionRow> = builder.build_query_as().fetch_all(conn).await?;
+    rows.into_iter().map(AdminAction::try_from).collect()
+}
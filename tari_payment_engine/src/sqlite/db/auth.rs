@@ -5,11 +5,14 @@
 
 use std::collections::{HashMap, HashSet};
 
-use log::{debug, error};
+use log::{debug, error, warn};
 use sqlx::{QueryBuilder, Row, SqliteConnection};
 use tari_common_types::tari_address::TariAddress;
 
-use crate::{db_types::Role, traits::AuthApiError};
+use crate::{
+    db_types::{Permission, Role, RoleDefinition},
+    traits::AuthApiError,
+};
 
 pub static DEFAULT_ROLES: &[Role] = &[Role::User];
 
@@ -191,3 +194,138 @@ pub async fn remove_roles(
 
     Ok(res.rows_affected())
 }
+
+/// Fetches the roles that were assigned to the address for the given merchant only.
+pub async fn merchant_roles_for_address(
+    address: &TariAddress,
+    merchant_id: &str,
+    conn: &mut SqliteConnection,
+) -> Result<HashSet<Role>, AuthApiError> {
+    let rows = sqlx::query(
+        r#"SELECT name FROM
+            merchant_role_assignments LEFT JOIN roles ON merchant_role_assignments.role_id = roles.id
+            WHERE address = $1 AND merchant_id = $2"#,
+    )
+    .bind(address.to_base58())
+    .bind(merchant_id)
+    .fetch_all(conn)
+    .await?;
+    rows.iter().map(|r| r.get::<String, _>("name").parse::<Role>().map_err(|_| AuthApiError::RoleNotFound)).collect()
+}
+
+pub async fn assign_merchant_roles(
+    address: &TariAddress,
+    merchant_id: &str,
+    roles: &[Role],
+    conn: &mut SqliteConnection,
+) -> Result<(), AuthApiError> {
+    if roles.is_empty() {
+        return Ok(());
+    }
+    let all_roles = fetch_roles(conn).await?;
+    let role_ids = roles
+        .iter()
+        .map(|r| all_roles.get(r).ok_or(AuthApiError::RoleNotFound).copied())
+        .collect::<Result<Vec<i64>, _>>()?;
+    let address = address.to_base58();
+    // Roles that are already assigned are ignored by the ON CONFLICT IGNORE clause on the table
+    let mut qb = QueryBuilder::new("INSERT INTO merchant_role_assignments (address, merchant_id, role_id) ");
+    qb.push_values(role_ids, |mut row, role_id| {
+        row.push_bind(address.clone()).push_bind(merchant_id).push_bind(role_id);
+    });
+    qb.build().execute(conn).await?;
+    Ok(())
+}
+
+pub async fn remove_merchant_roles(
+    address: &TariAddress,
+    merchant_id: &str,
+    roles: &[Role],
+    conn: &mut SqliteConnection,
+) -> Result<u64, AuthApiError> {
+    if roles.is_empty() {
+        return Ok(0);
+    }
+    let all_roles = fetch_roles(conn).await?;
+    let role_ids = roles
+        .iter()
+        .map(|r| all_roles.get(r).ok_or(AuthApiError::RoleNotFound).copied())
+        .collect::<Result<Vec<i64>, _>>()?;
+    let mut qb = QueryBuilder::new("DELETE FROM merchant_role_assignments WHERE address = ");
+    qb.push_bind(address.to_base58());
+    qb.push(" AND merchant_id = ");
+    qb.push_bind(merchant_id);
+    qb.push(" AND role_id IN (");
+    let mut values = qb.separated(", ");
+    role_ids.iter().for_each(|id| {
+        values.push_bind(*id);
+    });
+    qb.push(")");
+    let res = qb.build().execute(conn).await?;
+    Ok(res.rows_affected())
+}
+
+/// Fetches every role, and the permissions that it grants. Permissions that this version of the code does not know
+/// about are skipped.
+pub async fn fetch_role_definitions(conn: &mut SqliteConnection) -> Result<Vec<RoleDefinition>, AuthApiError> {
+    let rows = sqlx::query(
+        "SELECT name, permission FROM roles LEFT JOIN role_permissions ON roles.id = role_permissions.role_id ORDER \
+         BY name",
+    )
+    .fetch_all(conn)
+    .await?;
+    let mut definitions: Vec<RoleDefinition> = Vec::new();
+    for row in rows {
+        let role = row.get::<String, _>("name").parse::<Role>().map_err(|_| AuthApiError::RoleNotFound)?;
+        if definitions.last().map_or(true, |d| d.role != role) {
+            definitions.push(RoleDefinition::new(role, &[]));
+        }
+        let Some(permission) = row.get::<Option<String>, _>("permission") else {
+            continue;
+        };
+        match (permission.parse::<Permission>(), definitions.last_mut()) {
+            (Ok(permission), Some(definition)) => definition.permissions.push(permission),
+            (Err(e), _) => warn!("Ignoring unknown permission in the database. {e}"),
+            (Ok(_), None) => {},
+        }
+    }
+    definitions.iter_mut().for_each(|d| d.permissions.sort());
+    definitions.sort_by_key(|d| d.role.to_string());
+    Ok(definitions)
+}
+
+/// Creates the role if it does not exist, and replaces its permissions. Call this from inside a transaction.
+pub async fn save_role(role: &RoleDefinition, conn: &mut SqliteConnection) -> Result<(), AuthApiError> {
+    if role.role == Role::SuperAdmin {
+        return Err(AuthApiError::BuiltInRole(role.role.clone()));
+    }
+    let name = role.role.to_string();
+    sqlx::query("INSERT INTO roles (name) VALUES ($1) ON CONFLICT (name) DO NOTHING")
+        .bind(&name)
+        .execute(&mut *conn)
+        .await?;
+    let role_id: i64 =
+        sqlx::query_scalar("SELECT id FROM roles WHERE name = $1").bind(&name).fetch_one(&mut *conn).await?;
+    sqlx::query("DELETE FROM role_permissions WHERE role_id = $1").bind(role_id).execute(&mut *conn).await?;
+    if role.permissions.is_empty() {
+        return Ok(());
+    }
+    let mut qb = QueryBuilder::new("INSERT INTO role_permissions (role_id, permission) ");
+    qb.push_values(&role.permissions, |mut row, permission| {
+        row.push_bind(role_id).push_bind(permission.as_str());
+    });
+    qb.build().execute(conn).await?;
+    Ok(())
+}
+
+/// Deletes a custom role. Its assignments and permissions are removed by the `ON DELETE CASCADE` clauses.
+pub async fn delete_role(role: &Role, conn: &mut SqliteConnection) -> Result<(), AuthApiError> {
+    if role.is_built_in() {
+        return Err(AuthApiError::BuiltInRole(role.clone()));
+    }
+    let res = sqlx::query("DELETE FROM roles WHERE name = $1").bind(role.to_string()).execute(conn).await?;
+    if res.rows_affected() == 0 {
+        return Err(AuthApiError::RoleNotFound);
+    }
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use sqlx::SqliteConnection;
+
+use crate::{
+    db_types::{HeldOrder, NewOrder, OrderId},
+    tpe_api::account_objects::Pagination,
+};
+
+pub(crate) async fn insert_held_order(
+    order: &NewOrder,
+    reason: &str,
+    conn: &mut SqliteConnection,
+) -> Result<HeldOrder, sqlx::Error> {
+    sqlx::query_as(
+        r#"INSERT INTO held_orders
+        (order_id, alt_order_id, customer_id, memo, original_price, currency, order_created_at, reason, merchant_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING *"#,
+    )
+    .bind(order.order_id.as_str())
+    .bind(order.alt_order_id.as_ref().map(OrderId::as_str))
+    .bind(&order.customer_id)
+    .bind(&order.memo)
+    .bind(order.original_price.as_deref().unwrap_or_default())
+    .bind(&order.currency)
+    .bind(order.created_at)
+    .bind(reason)
+    .bind(&order.merchant_id)
+    .fetch_one(conn)
+    .await
+}
+
+pub(crate) async fn fetch_held_order(
+    order_id: &OrderId,
+    conn: &mut SqliteConnection,
+) -> Result<Option<HeldOrder>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM held_orders WHERE order_id = $1").bind(order_id.as_str()).fetch_optional(conn).await
+}
+
+/// Fetches the orders that have not been released yet, oldest first.
+pub(crate) async fn fetch_held_orders(
+    pagination: &Pagination,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<HeldOrder>, sqlx::Error> {
+    // SQLite only accepts an OFFSET after a LIMIT. A negative limit means "no limit".
+    sqlx::query_as("SELECT * FROM held_orders WHERE released_at IS NULL ORDER BY id LIMIT $1 OFFSET $2")
+        .bind(pagination.count.unwrap_or(-1))
+        .bind(pagination.offset.unwrap_or(0))
+        .fetch_all(conn)
+        .await
+}
+
+/// Marks the order as released. Returns `None` if the order is not being held.
+pub(crate) async fn release_held_order(
+    order_id: &OrderId,
+    conn: &mut SqliteConnection,
+) -> Result<Option<HeldOrder>, sqlx::Error> {
+    sqlx::query_as(
+        "UPDATE held_orders SET released_at = CURRENT_TIMESTAMP WHERE order_id = $1 AND released_at IS NULL RETURNING \
+         *",
+    )
+    .bind(order_id.as_str())
+    .fetch_optional(conn)
+    .await
+}
@@ -0,0 +1,104 @@
+use sqlx::SqliteConnection;
+
+use crate::{
+    db_types::{LedgerAccount, LedgerBalance, LedgerEntry, NewLedgerTransaction},
+    tpe_api::account_objects::Pagination,
+    traits::PaymentGatewayError,
+};
+
+/// Posts the transaction and its entries. A transaction whose debits and credits do not balance is refused.
+pub(crate) async fn post(
+    transaction: &NewLedgerTransaction,
+    conn: &mut SqliteConnection,
+) -> Result<(), PaymentGatewayError> {
+    if !transaction.is_balanced() {
+        return Err(PaymentGatewayError::UnbalancedLedgerTransaction(transaction.reference.clone()));
+    }
+    let id: i64 = sqlx::query_scalar(
+        "INSERT INTO ledger_transactions (transaction_type, reference) VALUES ($1, $2) RETURNING id",
+    )
+    .bind(transaction.transaction_type)
+    .bind(&transaction.reference)
+    .fetch_one(&mut *conn)
+    .await?;
+    for entry in &transaction.entries {
+        sqlx::query(
+            "INSERT INTO ledger_entries (transaction_id, account_type, account_id, side, amount) VALUES ($1, $2, $3, \
+             $4, $5)",
+        )
+        .bind(id)
+        .bind(entry.account.account_type)
+        .bind(&entry.account.account_id)
+        .bind(entry.side)
+        .bind(entry.amount)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Fetches the entries posted to the account, oldest first.
+pub(crate) async fn fetch_entries(
+    account: &LedgerAccount,
+    pagination: &Pagination,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<LedgerEntry>, sqlx::Error> {
+    // SQLite only accepts an OFFSET after a LIMIT. A negative limit means "no limit".
+    sqlx::query_as(
+        r#"
+        SELECT
+            ledger_entries.id,
+            ledger_entries.transaction_id,
+            ledger_transactions.created_at,
+            ledger_transactions.transaction_type,
+            ledger_transactions.reference,
+            ledger_entries.account_type,
+            ledger_entries.account_id,
+            ledger_entries.side,
+            ledger_entries.amount
+        FROM ledger_entries
+        JOIN ledger_transactions ON ledger_transactions.id = ledger_entries.transaction_id
+        WHERE ledger_entries.account_type = $1 AND ledger_entries.account_id = $2
+        ORDER BY ledger_entries.id
+        LIMIT $3 OFFSET $4
+        "#,
+    )
+    .bind(account.account_type)
+    .bind(&account.account_id)
+    .bind(pagination.count.unwrap_or(-1))
+    .bind(pagination.offset.unwrap_or(0))
+    .fetch_all(conn)
+    .await
+}
+
+pub(crate) async fn fetch_balances(conn: &mut SqliteConnection) -> Result<Vec<LedgerBalance>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT
+            account_type,
+            account_id,
+            sum(iif(side = 'Debit', amount, 0)) AS debits,
+            sum(iif(side = 'Credit', amount, 0)) AS credits
+        FROM ledger_entries
+        GROUP BY account_type, account_id
+        ORDER BY account_type, account_id
+        "#,
+    )
+    .fetch_all(conn)
+    .await
+}
+
+/// The ids of the transactions whose debits and credits do not match.
+pub(crate) async fn fetch_unbalanced_transactions(conn: &mut SqliteConnection) -> Result<Vec<i64>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"
+        SELECT transaction_id
+        FROM ledger_entries
+        GROUP BY transaction_id
+        HAVING sum(iif(side = 'Debit', amount, -amount)) != 0
+        ORDER BY transaction_id
+        "#,
+    )
+    .fetch_all(conn)
+    .await
+}
@@ -11,11 +11,20 @@ use log::info;
 use sqlx::{sqlite::SqlitePoolOptions, Error as SqlxError, SqlitePool};
 
 pub mod accounts;
+pub mod approvals;
+pub mod audit;
 pub mod auth;
 pub mod exchange_rates;
+pub mod held_orders;
+pub mod ledger;
 pub mod orders;
+pub mod outbox;
+pub mod rate_limits;
+pub mod refunds;
+pub mod sessions;
 pub mod transfers;
 pub mod wallet_auth;
+pub mod webhooks;
 
 const SQLITE_DB_URL: &str = "sqlite://data/tari_store.db";
 
@@ -1,7 +1,8 @@
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
 use log::{debug, trace};
 use sqlx::{sqlite::SqliteRow, FromRow, QueryBuilder, SqliteConnection};
 use tari_common_types::tari_address::TariAddress;
+use tpg_common::MicroTari;
 
 use crate::{
     db_types::{NewOrder, Order, OrderId, OrderStatusType},
@@ -14,23 +15,23 @@ pub async fn idempotent_insert(
     order: NewOrder,
     conn: &mut SqliteConnection,
 ) -> Result<(Order, bool), PaymentGatewayError> {
-    let inserted = match fetch_order_by_order_id(&order.order_id, conn).await? {
-        Some(order) => (order, false),
-        None => {
-            let order = insert_order(order, conn).await?;
-            debug!("📝️ Order [{}] inserted with id {}", order.order_id, order.id);
-            (order, true)
-        },
-    };
-    Ok(inserted)
+    let order_id = order.order_id.clone();
+    if let Some(order) = insert_order(order, conn).await? {
+        debug!("📝️ Order [{}] inserted with id {}", order.order_id, order.id);
+        return Ok((order, true));
+    }
+    let existing = fetch_order_by_order_id(&order_id, conn).await?;
+    existing.map(|order| (order, false)).ok_or(PaymentGatewayError::OrderNotFound(order_id))
 }
 
-/// Inserts a new order into the database using the given connection. This is not atomic. You can embed this call
-/// inside a transaction if you need to ensure atomicity, and pass `&mut *tx` as the connection argument.
+/// Inserts a new order into the database using the given connection, returning `None` if an order with the same
+/// `order_id` already exists. The existence check is part of the insert, so concurrent inserts of the same order
+/// cannot both succeed.
 ///
 /// If a Tari Address is provided, and it already exists in the database, the order status is set to 'New'.
 /// If the address is not found in the database, or if it is not provided, the order status is set to 'Unclaimed'.
-async fn insert_order(order: NewOrder, conn: &mut SqliteConnection) -> Result<Order, PaymentGatewayError> {
+async fn insert_order(order: NewOrder, conn: &mut SqliteConnection) -> Result<Option<Order>, PaymentGatewayError> {
+    let order_id = order.order_id.clone();
     let order = sqlx::query_as(
         r#"
             INSERT INTO orders (
@@ -41,8 +42,12 @@ async fn insert_order(order: NewOrder, conn: &mut SqliteConnection) -> Result<Or
                 total_price,
                 original_price,
                 currency,
-                created_at
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                created_at,
+                rate_id,
+                quote_expires_at,
+                merchant_id
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (order_id) DO NOTHING
             RETURNING *;
         "#,
     )
@@ -54,8 +59,15 @@ async fn insert_order(order: NewOrder, conn: &mut SqliteConnection) -> Result<Or
     .bind(order.original_price)
     .bind(order.currency)
     .bind(order.created_at)
-    .fetch_one(conn)
-    .await?;
+    .bind(order.rate_id)
+    .bind(order.quote_expires_at)
+    .bind(order.merchant_id)
+    .fetch_optional(conn)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(err) if err.is_unique_violation() => PaymentGatewayError::OrderAlreadyExists(order_id),
+        _ => PaymentGatewayError::from(e),
+    })?;
     // The DB should trigger an automatic status entry for the order
     Ok(order)
 }
@@ -87,6 +99,22 @@ pub async fn fetch_order_by_id_or_alt(id: &OrderId, conn: &mut SqliteConnection)
     Ok(order)
 }
 
+/// Fetches the orders that were created in the period `[from, to)`, oldest first.
+pub async fn fetch_orders_between(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<Order>, sqlx::Error> {
+    let orders = sqlx::query_as(
+        "SELECT * FROM orders WHERE unixepoch(created_at) >= $1 AND unixepoch(created_at) < $2 ORDER BY created_at, id",
+    )
+    .bind(from.timestamp())
+    .bind(to.timestamp())
+    .fetch_all(conn)
+    .await?;
+    Ok(orders)
+}
+
 /// Checks whether the order with the given `OrderId` already exists in the database. If it does exist, the `id` of the
 /// order is returned. If it does not exist, `None` is returned.
 pub async fn order_exists(order_id: &OrderId, conn: &mut SqliteConnection) -> Result<Option<i64>, PaymentGatewayError> {
@@ -127,6 +155,10 @@ pub async fn search_orders(query: OrderQueryFilter, conn: &mut SqliteConnection)
         where_clause.push("currency=");
         where_clause.push_bind_unseparated(currency);
     }
+    if let Some(merchant_id) = query.merchant_id {
+        where_clause.push("merchant_id=");
+        where_clause.push_bind_unseparated(merchant_id);
+    }
     if query.status.as_ref().map(|s| !s.is_empty()).unwrap_or(false) {
         let mut statuses = vec![];
         query.status.as_ref().unwrap().iter().for_each(|s| {
@@ -229,6 +261,40 @@ pub(crate) async fn expire_orders(
     Ok(rows)
 }
 
+/// Fetches the unpaid orders whose exchange rate quote has expired, oldest quotes first.
+pub(crate) async fn fetch_orders_with_expired_quotes(
+    conn: &mut SqliteConnection,
+) -> Result<Vec<Order>, PaymentGatewayError> {
+    let orders = sqlx::query_as(
+        "SELECT * FROM orders WHERE status IN ('New', 'Unclaimed') AND unixepoch(quote_expires_at) <= \
+         unixepoch(CURRENT_TIMESTAMP) ORDER BY quote_expires_at",
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(orders)
+}
+
+/// Sets a new price and exchange rate quote for the order. Unlike [`update_order`], this leaves `updated_at` alone,
+/// so that re-pricing an order does not postpone its expiry.
+pub(crate) async fn update_quote(
+    order_id: &OrderId,
+    total_price: MicroTari,
+    rate_id: i64,
+    quote_expires_at: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Order, PaymentGatewayError> {
+    let order = sqlx::query_as(
+        "UPDATE orders SET total_price = $1, rate_id = $2, quote_expires_at = $3 WHERE order_id = $4 RETURNING *",
+    )
+    .bind(total_price.value())
+    .bind(rate_id)
+    .bind(quote_expires_at)
+    .bind(order_id.as_str())
+    .fetch_one(conn)
+    .await?;
+    Ok(order)
+}
+
 /// Fetches all payable orders for the given address. A payable order is one that is "New" or "Unclaimed"
 /// i.e. it has not been paid and is associated with the address.
 pub(crate) async fn fetch_payable_orders_for_address(
@@ -248,7 +314,10 @@ pub(crate) async fn fetch_payable_orders_for_address(
             currency,
             orders.created_at as created_at,
             orders.updated_at as updated_at,
-            status
+            status,
+            rate_id,
+            quote_expires_at,
+            merchant_id
         FROM orders JOIN address_customer_id_link ON orders.customer_id = address_customer_id_link.customer_id
         WHERE
          status in ('New', 'Unclaimed') AND
@@ -0,0 +1,170 @@
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, SqliteConnection};
+
+use crate::{db_types::OutboxEvent, events::EventType, tpe_api::account_objects::Pagination, traits::OutboxError};
+
+#[derive(FromRow)]
+struct OutboxRow {
+    id: i64,
+    payload: String,
+    created_at: DateTime<Utc>,
+    attempts: i64,
+    next_attempt_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    delivered_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<OutboxRow> for OutboxEvent {
+    type Error = OutboxError;
+
+    fn try_from(row: OutboxRow) -> Result<Self, Self::Error> {
+        let event = serde_json::from_str::<EventType>(&row.payload)?;
+        Ok(Self {
+            id: row.id,
+            event,
+            created_at: row.created_at,
+            attempts: row.attempts,
+            next_attempt_at: row.next_attempt_at,
+            last_error: row.last_error,
+            delivered_at: row.delivered_at,
+        })
+    }
+}
+
+fn into_event(row: Option<OutboxRow>, id: i64) -> Result<OutboxEvent, OutboxError> {
+    row.ok_or(OutboxError::EventNotFound(id))?.try_into()
+}
+
+/// Writes the event to the outbox. Call this with the same transaction as the state change that produced the event.
+pub(crate) async fn enqueue(event: &EventType, conn: &mut SqliteConnection) -> Result<i64, sqlx::Error> {
+    let payload = serde_json::to_string(event).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+    let id: i64 = sqlx::query_scalar("INSERT INTO event_outbox (event_type, payload) VALUES ($1, $2) RETURNING id")
+        .bind(event.name())
+        .bind(payload)
+        .fetch_one(conn)
+        .await?;
+    Ok(id)
+}
+
+pub(crate) async fn fetch_event(id: i64, conn: &mut SqliteConnection) -> Result<Option<OutboxEvent>, OutboxError> {
+    let row: Option<OutboxRow> =
+        sqlx::query_as("SELECT * FROM event_outbox WHERE id = $1").bind(id).fetch_optional(conn).await?;
+    row.map(OutboxEvent::try_from).transpose()
+}
+
+pub(crate) async fn fetch_due_events(limit: i64, conn: &mut SqliteConnection) -> Result<Vec<OutboxEvent>, OutboxError> {
+    let rows: Vec<OutboxRow> = sqlx::query_as(
+        "SELECT * FROM event_outbox WHERE delivered_at IS NULL AND next_attempt_at IS NOT NULL AND \
+         unixepoch(next_attempt_at) <= unixepoch('now') AND (locked_until IS NULL OR unixepoch(locked_until) <= \
+         unixepoch('now')) ORDER BY id LIMIT $1",
+    )
+    .bind(limit)
+    .fetch_all(conn)
+    .await?;
+    rows.into_iter().map(OutboxEvent::try_from).collect()
+}
+
+/// Claims the due events by leasing them until `locked_until`. SQLite serializes writers, so the single UPDATE is
+/// enough to stop two dispatchers from claiming the same event.
+pub(crate) async fn claim_due_events(
+    limit: i64,
+    locked_until: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<OutboxEvent>, OutboxError> {
+    let mut rows: Vec<OutboxRow> = sqlx::query_as(
+        "UPDATE event_outbox SET locked_until = $1 WHERE id IN (SELECT id FROM event_outbox WHERE delivered_at IS \
+         NULL AND next_attempt_at IS NOT NULL AND unixepoch(next_attempt_at) <= unixepoch('now') AND (locked_until IS \
+         NULL OR unixepoch(locked_until) <= unixepoch('now')) ORDER BY id LIMIT $2) RETURNING *",
+    )
+    .bind(locked_until)
+    .bind(limit)
+    .fetch_all(conn)
+    .await?;
+    // RETURNING does not guarantee any order
+    rows.sort_by_key(|r| r.id);
+    rows.into_iter().map(OutboxEvent::try_from).collect()
+}
+
+pub(crate) async fn fetch_undelivered_events(
+    pagination: &Pagination,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<OutboxEvent>, OutboxError> {
+    // SQLite only accepts an OFFSET after a LIMIT. A negative limit means "no limit".
+    let rows: Vec<OutboxRow> =
+        sqlx::query_as("SELECT * FROM event_outbox WHERE delivered_at IS NULL ORDER BY id LIMIT $1 OFFSET $2")
+            .bind(pagination.count.unwrap_or(-1))
+            .bind(pagination.offset.unwrap_or(0))
+            .fetch_all(conn)
+            .await?;
+    rows.into_iter().map(OutboxEvent::try_from).collect()
+}
+
+pub(crate) async fn mark_delivered(id: i64, conn: &mut SqliteConnection) -> Result<OutboxEvent, OutboxError> {
+    let row: Option<OutboxRow> = sqlx::query_as(
+        "UPDATE event_outbox SET delivered_at = CURRENT_TIMESTAMP, next_attempt_at = NULL, attempts = attempts + 1, \
+         last_error = NULL, locked_until = NULL WHERE id = $1 RETURNING *",
+    )
+    .bind(id)
+    .fetch_optional(conn)
+    .await?;
+    into_event(row, id)
+}
+
+pub(crate) async fn record_failure(
+    id: i64,
+    error: &str,
+    retry_at: Option<DateTime<Utc>>,
+    conn: &mut SqliteConnection,
+) -> Result<OutboxEvent, OutboxError> {
+    let row: Option<OutboxRow> = sqlx::query_as(
+        "UPDATE event_outbox SET attempts = attempts + 1, last_error = $1, next_attempt_at = $2, locked_until = NULL \
+         WHERE id = $3 RETURNING *",
+    )
+    .bind(error)
+    .bind(retry_at)
+    .bind(id)
+    .fetch_optional(conn)
+    .await?;
+    into_event(row, id)
+}
+
+pub(crate) async fn fetch_delivered_handlers(id: i64, conn: &mut SqliteConnection) -> Result<Vec<String>, OutboxError> {
+    let handlers = sqlx::query_scalar("SELECT handler FROM event_outbox_handlers WHERE event_id = $1")
+        .bind(id)
+        .fetch_all(conn)
+        .await?;
+    Ok(handlers)
+}
+
+pub(crate) async fn mark_handler_delivered(
+    id: i64,
+    handler: &str,
+    conn: &mut SqliteConnection,
+) -> Result<(), OutboxError> {
+    sqlx::query("INSERT INTO event_outbox_handlers (event_id, handler) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+        .bind(id)
+        .bind(handler)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Schedules the event for redelivery. Call this in a transaction. If the event had been delivered, every handler
+/// runs again; otherwise only the handlers that have not delivered it yet do.
+pub(crate) async fn replay(id: i64, conn: &mut SqliteConnection) -> Result<OutboxEvent, OutboxError> {
+    sqlx::query(
+        "DELETE FROM event_outbox_handlers WHERE event_id = $1 AND EXISTS (SELECT 1 FROM event_outbox WHERE id = $1 \
+         AND delivered_at IS NOT NULL)",
+    )
+    .bind(id)
+    .execute(&mut *conn)
+    .await?;
+    let row: Option<OutboxRow> = sqlx::query_as(
+        "UPDATE event_outbox SET next_attempt_at = CURRENT_TIMESTAMP, delivered_at = NULL, locked_until = NULL WHERE \
+         id = $1 RETURNING *",
+    )
+    .bind(id)
+    .fetch_optional(conn)
+    .await?;
+    into_event(row, id)
+}
@@ -0,0 +1,46 @@
+//! Sqlite database operations for shared rate limits
+//!
+//! Generally clients should never call these methods directly, and prefer to use the
+//! [`crate::traits::RateLimitStore`] trait methods that are implemented on the [`crate::SqliteDatabase`] struct
+//! instead.
+use sqlx::SqliteConnection;
+
+use crate::tpe_api::rate_limit_objects::{RateLimit, RateLimitDecision};
+
+/// Refills the bucket for `key` and takes a token from it in a single statement, so that concurrent requests see each
+/// other's tokens. New buckets start out full.
+pub(crate) async fn take_token(
+    key: &str,
+    limit: &RateLimit,
+    conn: &mut SqliteConnection,
+) -> Result<RateLimitDecision, sqlx::Error> {
+    let (tokens, allowed): (f64, bool) = sqlx::query_as(
+        r#"
+        INSERT INTO rate_limit_buckets (key, tokens, allowed, updated_at, expires_at)
+        VALUES ($1, $2 - 1.0, 1, julianday('now'), julianday('now') + $4 / 86400.0)
+        ON CONFLICT (key) DO UPDATE SET
+            tokens = CASE
+                WHEN min($2, tokens + (julianday('now') - updated_at) * 86400.0 * $3) >= 1.0
+                THEN min($2, tokens + (julianday('now') - updated_at) * 86400.0 * $3) - 1.0
+                ELSE min($2, tokens + (julianday('now') - updated_at) * 86400.0 * $3)
+            END,
+            allowed = min($2, tokens + (julianday('now') - updated_at) * 86400.0 * $3) >= 1.0,
+            updated_at = julianday('now'),
+            expires_at = julianday('now') + $4 / 86400.0
+        RETURNING tokens, allowed
+        "#,
+    )
+    .bind(key)
+    .bind(limit.capacity())
+    .bind(limit.refill_rate())
+    .bind(limit.period.as_secs_f64())
+    .fetch_one(conn)
+    .await?;
+    Ok(limit.decision(tokens, allowed))
+}
+
+pub(crate) async fn prune_rate_limits(conn: &mut SqliteConnection) -> Result<u64, sqlx::Error> {
+    let result =
+        sqlx::query("DELETE FROM rate_limit_buckets WHERE expires_at <= julianday('now')").execute(conn).await?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,83 @@
+use sqlx::SqliteConnection;
+use tari_common_types::tari_address::TariAddress;
+use tpg_common::MicroTari;
+
+use crate::{
+    db_types::{NewRefund, OrderId, Refund, RefundStatus},
+    tpe_api::account_objects::Pagination,
+};
+
+/// Inserts the refund. A refund for an order always belongs to the order's merchant.
+pub(crate) async fn insert_refund(refund: NewRefund, conn: &mut SqliteConnection) -> Result<Refund, sqlx::Error> {
+    sqlx::query_as(
+        "INSERT INTO refunds (address, order_id, amount, reason, merchant_id) VALUES ($1, $2, $3, $4, \
+         COALESCE((SELECT merchant_id FROM orders WHERE order_id = $2), $5)) RETURNING *",
+    )
+    .bind(refund.address.as_address().to_base58())
+    .bind(refund.order_id)
+    .bind(refund.amount)
+    .bind(refund.reason)
+    .bind(refund.merchant_id)
+    .fetch_one(conn)
+    .await
+}
+
+pub(crate) async fn fetch_refund(id: i64, conn: &mut SqliteConnection) -> Result<Option<Refund>, sqlx::Error> {
+    sqlx::query_as("SELECT * FROM refunds WHERE id = $1").bind(id).fetch_optional(conn).await
+}
+
+pub(crate) async fn fetch_refunds(
+    status: Option<RefundStatus>,
+    pagination: &Pagination,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<Refund>, sqlx::Error> {
+    // SQLite only accepts an OFFSET after a LIMIT. A negative limit means "no limit".
+    sqlx::query_as("SELECT * FROM refunds WHERE ($1 IS NULL OR status = $1) ORDER BY id DESC LIMIT $2 OFFSET $3")
+        .bind(status.map(|s| s.to_string()))
+        .bind(pagination.count.unwrap_or(-1))
+        .bind(pagination.offset.unwrap_or(0))
+        .fetch_all(conn)
+        .await
+}
+
+/// Sets the status of the refund. If `reason` or `payout_txid` are given, they replace the current values.
+pub(crate) async fn update_refund(
+    id: i64,
+    status: RefundStatus,
+    reason: Option<&str>,
+    payout_txid: Option<&str>,
+    conn: &mut SqliteConnection,
+) -> Result<Refund, sqlx::Error> {
+    sqlx::query_as(
+        "UPDATE refunds SET status = $1, reason = COALESCE($2, reason), payout_txid = COALESCE($3, payout_txid), \
+         updated_at = CURRENT_TIMESTAMP WHERE id = $4 RETURNING *",
+    )
+    .bind(status.to_string())
+    .bind(reason)
+    .bind(payout_txid)
+    .bind(id)
+    .fetch_one(conn)
+    .await
+}
+
+/// The amount that `address` paid towards the order, less the refunds against the order that have already been
+/// debited from the address.
+pub(crate) async fn refundable_for_order(
+    order_id: &OrderId,
+    address: &TariAddress,
+    conn: &mut SqliteConnection,
+) -> Result<MicroTari, sqlx::Error> {
+    let amount: i64 = sqlx::query_scalar(
+        r#"
+        SELECT
+            (SELECT COALESCE(SUM(amount), 0) FROM settlement_journal WHERE order_id = $1 AND payment_address = $2) -
+            (SELECT COALESCE(SUM(amount), 0) FROM refunds
+             WHERE order_id = $1 AND address = $2 AND status IN ('Approved', 'Sent'))
+        "#,
+    )
+    .bind(order_id.as_str())
+    .bind(address.to_base58())
+    .fetch_one(conn)
+    .await?;
+    Ok(MicroTari::from(amount))
+}
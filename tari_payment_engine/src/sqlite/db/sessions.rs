@@ -0,0 +1,133 @@
+//! Sqlite database operations for login sessions
+//!
+//! Generally clients should never call these methods directly, and prefer to use the [`crate::traits::AuthManagement`]
+//! trait methods that are implemented on the [`crate::SqliteDatabase`] struct instead.
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, SqliteConnection};
+use tari_common_types::tari_address::TariAddress;
+
+use crate::{
+    db_types::{roles_from_list, AuthSession, NewAuthSession, SerializedTariAddress},
+    traits::AuthApiError,
+};
+
+#[derive(FromRow)]
+struct SessionRow {
+    id: i64,
+    address: SerializedTariAddress,
+    roles: String,
+    merchant_id: Option<String>,
+    refresh_token_hash: String,
+    created_at: DateTime<Utc>,
+    refreshed_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<SessionRow> for AuthSession {
+    type Error = AuthApiError;
+
+    fn try_from(row: SessionRow) -> Result<Self, Self::Error> {
+        let roles = roles_from_list(&row.roles).map_err(|_| AuthApiError::RoleNotFound)?;
+        Ok(Self {
+            id: row.id,
+            address: row.address,
+            roles,
+            merchant: row.merchant_id,
+            refresh_token_hash: row.refresh_token_hash,
+            created_at: row.created_at,
+            refreshed_at: row.refreshed_at,
+            expires_at: row.expires_at,
+            revoked_at: row.revoked_at,
+        })
+    }
+}
+
+pub(crate) async fn insert_session(
+    session: NewAuthSession,
+    conn: &mut SqliteConnection,
+) -> Result<AuthSession, AuthApiError> {
+    let row: SessionRow = sqlx::query_as(
+        "INSERT INTO auth_sessions (address, roles, merchant_id, refresh_token_hash, expires_at) VALUES ($1, $2, $3, \
+         $4, $5) RETURNING *",
+    )
+    .bind(session.address.to_base58())
+    .bind(session.roles_list())
+    .bind(session.merchant)
+    .bind(session.refresh_token_hash)
+    .bind(session.expires_at)
+    .fetch_one(conn)
+    .await?;
+    row.try_into()
+}
+
+pub(crate) async fn fetch_session(id: i64, conn: &mut SqliteConnection) -> Result<Option<AuthSession>, AuthApiError> {
+    let row: Option<SessionRow> =
+        sqlx::query_as("SELECT * FROM auth_sessions WHERE id = $1").bind(id).fetch_optional(conn).await?;
+    row.map(AuthSession::try_from).transpose()
+}
+
+/// Swaps the refresh token of an active session. The old token only matches once, so a refresh token that has been
+/// used already is rejected.
+pub(crate) async fn refresh_session(
+    refresh_token_hash: &str,
+    new_refresh_token_hash: &str,
+    conn: &mut SqliteConnection,
+) -> Result<AuthSession, AuthApiError> {
+    let row: Option<SessionRow> = sqlx::query_as(
+        "UPDATE auth_sessions SET refresh_token_hash = $1, refreshed_at = CURRENT_TIMESTAMP WHERE refresh_token_hash \
+         = $2 AND revoked_at IS NULL AND unixepoch(expires_at) > unixepoch('now') RETURNING *",
+    )
+    .bind(new_refresh_token_hash)
+    .bind(refresh_token_hash)
+    .fetch_optional(conn)
+    .await?;
+    row.ok_or(AuthApiError::InvalidRefreshToken)?.try_into()
+}
+
+pub(crate) async fn fetch_sessions_for_address(
+    address: &TariAddress,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<AuthSession>, AuthApiError> {
+    let rows: Vec<SessionRow> = sqlx::query_as(
+        "SELECT * FROM auth_sessions WHERE address = $1 AND revoked_at IS NULL AND unixepoch(expires_at) > \
+         unixepoch('now') ORDER BY id DESC",
+    )
+    .bind(address.to_base58())
+    .fetch_all(conn)
+    .await?;
+    rows.into_iter().map(AuthSession::try_from).collect()
+}
+
+pub(crate) async fn revoke_session(id: i64, conn: &mut SqliteConnection) -> Result<AuthSession, AuthApiError> {
+    sqlx::query("UPDATE auth_sessions SET revoked_at = CURRENT_TIMESTAMP WHERE id = $1 AND revoked_at IS NULL")
+        .bind(id)
+        .execute(&mut *conn)
+        .await?;
+    fetch_session(id, conn).await?.ok_or(AuthApiError::SessionNotFound(id))
+}
+
+pub(crate) async fn revoke_sessions_for_address(
+    address: &TariAddress,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<i64>, AuthApiError> {
+    let ids = sqlx::query_scalar(
+        "UPDATE auth_sessions SET revoked_at = CURRENT_TIMESTAMP WHERE address = $1 AND revoked_at IS NULL AND \
+         unixepoch(expires_at) > unixepoch('now') RETURNING id",
+    )
+    .bind(address.to_base58())
+    .fetch_all(conn)
+    .await?;
+    Ok(ids)
+}
+
+pub(crate) async fn fetch_revoked_sessions(
+    since: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<i64>, AuthApiError> {
+    let ids = sqlx::query_scalar("SELECT id FROM auth_sessions WHERE unixepoch(revoked_at) >= $1 ORDER BY id")
+        .bind(since.timestamp())
+        .fetch_all(conn)
+        .await?;
+    Ok(ids)
+}
@@ -1,4 +1,4 @@
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use sqlx::SqliteConnection;
 use tari_common_types::tari_address::TariAddress;
 
@@ -16,7 +16,7 @@ pub async fn idempotent_insert(
     let address = transfer.sender.as_address().to_base58();
     let payment = sqlx::query_as(
         r#"
-            INSERT INTO payments (txid, sender, amount, memo, order_id) VALUES ($1, $2, $3, $4, $5)
+            INSERT INTO payments (txid, sender, amount, memo, order_id, merchant_id) VALUES ($1, $2, $3, $4, $5, $6)
             RETURNING *;
         "#,
     )
@@ -25,6 +25,7 @@ pub async fn idempotent_insert(
     .bind(transfer.amount)
     .bind(transfer.memo)
     .bind(transfer.order_id)
+    .bind(transfer.merchant_id)
     .fetch_one(conn)
     .await
     .map_err(|e| match e {
@@ -46,14 +47,15 @@ pub async fn credit_note(note: &CreditNote, conn: &mut SqliteConnection) -> Resu
     let memo = format!("Credit note: {}", note.reason.as_deref().unwrap_or("No reason given"));
     let payment = sqlx::query_as(
         r#"
-            INSERT INTO payments (txid, sender, amount, memo, payment_type, status)
-            VALUES ($1, $2, $3, $4, 'Manual', 'Confirmed') RETURNING *;
+            INSERT INTO payments (txid, sender, amount, memo, payment_type, status, merchant_id)
+            VALUES ($1, $2, $3, $4, 'Manual', 'Confirmed', $5) RETURNING *;
         "#,
     )
     .bind(txid.clone())
     .bind(base58_addr)
     .bind(note.amount)
     .bind(memo)
+    .bind(&note.merchant_id)
     .fetch_one(conn)
     .await
     .map_err(|e| match e {
@@ -78,6 +80,26 @@ pub async fn update_status(
     Ok(payment)
 }
 
+/// Records the height of the block that the payment's transaction was mined in, and how many blocks deep it is.
+pub async fn update_confirmations(
+    txid: &str,
+    block_height: Option<i64>,
+    confirmations: i64,
+    conn: &mut SqliteConnection,
+) -> Result<Payment, PaymentGatewayError> {
+    let payment = sqlx::query_as(
+        "UPDATE payments SET block_height = $1, confirmations = $2, updated_at = CURRENT_TIMESTAMP WHERE txid = $3 \
+         RETURNING *",
+    )
+    .bind(block_height)
+    .bind(confirmations)
+    .bind(txid)
+    .fetch_optional(conn)
+    .await?
+    .ok_or_else(|| PaymentGatewayError::PaymentNotFound(txid.to_string()))?;
+    Ok(payment)
+}
+
 pub async fn fetch_payment(txid: &str, conn: &mut SqliteConnection) -> Result<Option<Payment>, PaymentGatewayError> {
     let payment = sqlx::query_as(r#"SELECT * FROM payments WHERE txid = ?"#).bind(txid).fetch_optional(conn).await?;
     Ok(payment)
@@ -106,6 +128,37 @@ pub async fn pending_payments(address: &TariAddress, conn: &mut SqliteConnection
     Ok(payments)
 }
 
+pub async fn fetch_onchain_payments(
+    since: Option<DateTime<Utc>>,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<Payment>, sqlx::Error> {
+    let payments = sqlx::query_as(
+        "SELECT * FROM payments WHERE payment_type = 'OnChain' AND ($1 IS NULL OR unixepoch(created_at) >= $1) ORDER \
+         BY created_at, txid",
+    )
+    .bind(since.map(|t| t.timestamp()))
+    .fetch_all(conn)
+    .await?;
+    Ok(payments)
+}
+
+/// Fetches the payments, including credit notes, that were received in the period `[from, to)`, oldest first.
+pub async fn fetch_payments_between(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<Payment>, sqlx::Error> {
+    let payments = sqlx::query_as(
+        "SELECT * FROM payments WHERE unixepoch(created_at) >= $1 AND unixepoch(created_at) < $2 ORDER BY created_at, \
+         txid",
+    )
+    .bind(from.timestamp())
+    .bind(to.timestamp())
+    .fetch_all(conn)
+    .await?;
+    Ok(payments)
+}
+
 pub async fn fetch_payments_for_order(
     order_id: &OrderId,
     conn: &mut SqliteConnection,
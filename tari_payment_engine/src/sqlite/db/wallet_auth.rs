@@ -21,7 +21,8 @@ pub async fn fetch_wallet_info_for_address(
             let ip_address = row.get::<&str, _>("ip_address").parse::<IpAddr>().ok()?;
             let address = row.get("address");
             let last_nonce = row.get("last_nonce");
-            Some(WalletInfo { address, ip_address, last_nonce })
+            let merchant_id = row.get("merchant_id");
+            Some(WalletInfo { address, ip_address, last_nonce, merchant_id })
         })
         .ok_or(WalletAuthApiError::WalletNotFound)
 }
@@ -60,10 +61,11 @@ pub(crate) async fn register_wallet(
     let ip_address = info.ip_address.to_string();
     let nonce = info.initial_nonce.unwrap_or(0);
     let result = query!(
-        r#"INSERT INTO wallet_auth (address, ip_address, last_nonce) VALUES (?, ?, ?)"#,
+        r#"INSERT INTO wallet_auth (address, ip_address, last_nonce, merchant_id) VALUES (?, ?, ?, ?)"#,
         address,
         ip_address,
-        nonce
+        nonce,
+        info.merchant_id
     )
     .execute(conn)
     .await?;
@@ -101,7 +103,8 @@ pub(crate) async fn fetch_authorized_wallets(
                 .map_err(|e| WalletManagementError::DatabaseError(format!("Invalid TariAddress. {e}")))?;
             let address = SerializedTariAddress::from(address);
             let last_nonce = row.get("last_nonce");
-            Ok(WalletInfo { address, ip_address, last_nonce })
+            let merchant_id = row.get("merchant_id");
+            Ok(WalletInfo { address, ip_address, last_nonce, merchant_id })
         })
         .collect::<Result<Vec<WalletInfo>, WalletManagementError>>()
 }
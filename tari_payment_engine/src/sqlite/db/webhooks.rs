@@ -0,0 +1,180 @@
+use chrono::{DateTime, Utc};
+use sqlx::SqliteConnection;
+
+use crate::{
+    db_types::{NewWebhookSubscription, OutboxEvent, WebhookDelivery, WebhookPayload, WebhookSubscription},
+    tpe_api::account_objects::Pagination,
+    traits::WebhookError,
+};
+
+pub(crate) async fn insert_subscription(
+    webhook: &NewWebhookSubscription,
+    conn: &mut SqliteConnection,
+) -> Result<WebhookSubscription, WebhookError> {
+    let subscription =
+        sqlx::query_as("INSERT INTO webhook_subscriptions (url, event_type, secret) VALUES ($1, $2, $3) RETURNING *")
+            .bind(&webhook.url)
+            .bind(&webhook.event_type)
+            .bind(&webhook.secret)
+            .fetch_one(conn)
+            .await?;
+    Ok(subscription)
+}
+
+pub(crate) async fn fetch_subscriptions(conn: &mut SqliteConnection) -> Result<Vec<WebhookSubscription>, WebhookError> {
+    let subscriptions = sqlx::query_as("SELECT * FROM webhook_subscriptions ORDER BY id").fetch_all(conn).await?;
+    Ok(subscriptions)
+}
+
+pub(crate) async fn fetch_subscription(
+    id: i64,
+    conn: &mut SqliteConnection,
+) -> Result<Option<WebhookSubscription>, WebhookError> {
+    let subscription =
+        sqlx::query_as("SELECT * FROM webhook_subscriptions WHERE id = $1").bind(id).fetch_optional(conn).await?;
+    Ok(subscription)
+}
+
+pub(crate) async fn set_active(
+    id: i64,
+    active: bool,
+    conn: &mut SqliteConnection,
+) -> Result<WebhookSubscription, WebhookError> {
+    let subscription: Option<WebhookSubscription> = sqlx::query_as(
+        "UPDATE webhook_subscriptions SET active = $1, updated_at = CURRENT_TIMESTAMP WHERE id = $2 RETURNING *",
+    )
+    .bind(active)
+    .bind(id)
+    .fetch_optional(conn)
+    .await?;
+    subscription.ok_or(WebhookError::SubscriptionNotFound(id))
+}
+
+pub(crate) async fn delete_subscription(id: i64, conn: &mut SqliteConnection) -> Result<(), WebhookError> {
+    sqlx::query("DELETE FROM webhook_deliveries WHERE subscription_id = $1").bind(id).execute(&mut *conn).await?;
+    let result = sqlx::query("DELETE FROM webhook_subscriptions WHERE id = $1").bind(id).execute(conn).await?;
+    if result.rows_affected() == 0 {
+        return Err(WebhookError::SubscriptionNotFound(id));
+    }
+    Ok(())
+}
+
+pub(crate) async fn queue_deliveries(
+    event: &OutboxEvent,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<WebhookDelivery>, WebhookError> {
+    let payload = serde_json::to_string(&WebhookPayload::from(event))?;
+    let deliveries = sqlx::query_as(
+        "INSERT INTO webhook_deliveries (subscription_id, event_id, event_type, payload) SELECT id, $1, $2, $3 FROM \
+         webhook_subscriptions WHERE event_type = $2 AND active ON CONFLICT (subscription_id, event_id) DO NOTHING \
+         RETURNING *",
+    )
+    .bind(event.id)
+    .bind(event.event_type())
+    .bind(payload)
+    .fetch_all(conn)
+    .await?;
+    Ok(deliveries)
+}
+
+pub(crate) async fn fetch_due_deliveries(
+    limit: i64,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<WebhookDelivery>, WebhookError> {
+    let deliveries = sqlx::query_as(
+        "SELECT d.* FROM webhook_deliveries d JOIN webhook_subscriptions s ON s.id = d.subscription_id WHERE s.active \
+         AND d.delivered_at IS NULL AND d.next_attempt_at IS NOT NULL AND unixepoch(d.next_attempt_at) <= \
+         unixepoch('now') AND (d.locked_until IS NULL OR unixepoch(d.locked_until) <= unixepoch('now')) ORDER BY d.id \
+         LIMIT $1",
+    )
+    .bind(limit)
+    .fetch_all(conn)
+    .await?;
+    Ok(deliveries)
+}
+
+/// Claims the due deliveries by leasing them until `locked_until`. SQLite serializes writers, so the single UPDATE
+/// is enough to stop two workers from claiming the same delivery.
+pub(crate) async fn claim_due_deliveries(
+    limit: i64,
+    locked_until: DateTime<Utc>,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<WebhookDelivery>, WebhookError> {
+    let mut deliveries: Vec<WebhookDelivery> = sqlx::query_as(
+        "UPDATE webhook_deliveries SET locked_until = $1 WHERE id IN (SELECT d.id FROM webhook_deliveries d JOIN \
+         webhook_subscriptions s ON s.id = d.subscription_id WHERE s.active AND d.delivered_at IS NULL AND \
+         d.next_attempt_at IS NOT NULL AND unixepoch(d.next_attempt_at) <= unixepoch('now') AND (d.locked_until IS \
+         NULL OR unixepoch(d.locked_until) <= unixepoch('now')) ORDER BY d.id LIMIT $2) RETURNING *",
+    )
+    .bind(locked_until)
+    .bind(limit)
+    .fetch_all(conn)
+    .await?;
+    // RETURNING does not guarantee any order
+    deliveries.sort_by_key(|d| d.id);
+    Ok(deliveries)
+}
+
+pub(crate) async fn fetch_deliveries(
+    subscription_id: Option<i64>,
+    pagination: &Pagination,
+    conn: &mut SqliteConnection,
+) -> Result<Vec<WebhookDelivery>, WebhookError> {
+    // SQLite only accepts an OFFSET after a LIMIT. A negative limit means "no limit".
+    let deliveries = sqlx::query_as(
+        "SELECT * FROM webhook_deliveries WHERE $1 IS NULL OR subscription_id = $1 ORDER BY id DESC LIMIT $2 OFFSET $3",
+    )
+    .bind(subscription_id)
+    .bind(pagination.count.unwrap_or(-1))
+    .bind(pagination.offset.unwrap_or(0))
+    .fetch_all(conn)
+    .await?;
+    Ok(deliveries)
+}
+
+pub(crate) async fn mark_delivered(
+    id: i64,
+    status: i64,
+    conn: &mut SqliteConnection,
+) -> Result<WebhookDelivery, WebhookError> {
+    let delivery: Option<WebhookDelivery> = sqlx::query_as(
+        "UPDATE webhook_deliveries SET delivered_at = CURRENT_TIMESTAMP, next_attempt_at = NULL, attempts = attempts \
+         + 1, last_status = $1, last_error = NULL, locked_until = NULL WHERE id = $2 RETURNING *",
+    )
+    .bind(status)
+    .bind(id)
+    .fetch_optional(conn)
+    .await?;
+    delivery.ok_or(WebhookError::DeliveryNotFound(id))
+}
+
+pub(crate) async fn record_failure(
+    id: i64,
+    status: Option<i64>,
+    error: &str,
+    retry_at: Option<DateTime<Utc>>,
+    conn: &mut SqliteConnection,
+) -> Result<WebhookDelivery, WebhookError> {
+    let delivery: Option<WebhookDelivery> = sqlx::query_as(
+        "UPDATE webhook_deliveries SET attempts = attempts + 1, last_status = $1, last_error = $2, next_attempt_at = \
+         $3, locked_until = NULL WHERE id = $4 RETURNING *",
+    )
+    .bind(status)
+    .bind(error)
+    .bind(retry_at)
+    .bind(id)
+    .fetch_optional(conn)
+    .await?;
+    delivery.ok_or(WebhookError::DeliveryNotFound(id))
+}
+
+pub(crate) async fn replay(id: i64, conn: &mut SqliteConnection) -> Result<WebhookDelivery, WebhookError> {
+    let delivery: Option<WebhookDelivery> = sqlx::query_as(
+        "UPDATE webhook_deliveries SET next_attempt_at = CURRENT_TIMESTAMP, delivered_at = NULL, locked_until = NULL \
+         WHERE id = $1 RETURNING *",
+    )
+    .bind(id)
+    .fetch_optional(conn)
+    .await?;
+    delivery.ok_or(WebhookError::DeliveryNotFound(id))
+}
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS event_outbox_undelivered;
+DROP TABLE IF EXISTS event_outbox;
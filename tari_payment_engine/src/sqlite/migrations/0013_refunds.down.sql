@@ -0,0 +1,34 @@
+DROP VIEW IF EXISTS address_balance;
+CREATE VIEW IF NOT EXISTS address_balance (address, total_confirmed, total_paid, current_balance, last_update) AS
+WITH
+    wallets AS (
+    SELECT sender, sum(amount) as total_confirmed, updated_at
+    FROM payments
+    WHERE status = 'Confirmed'
+    GROUP BY sender
+),
+    settlements AS (
+    SELECT sum(amount) as total, payment_address, created_at
+    FROM settlement_journal
+    GROUP BY payment_address
+)
+SELECT
+    wallets.sender as address,
+    wallets.total_confirmed as total_confirmed,
+    coalesce(settlements.total, 0) as total_paid,
+    wallets.total_confirmed - coalesce(settlements.total, 0) as current_balance,
+    coalesce(settlements.created_at, wallets.updated_at) as last_update
+FROM wallets
+LEFT OUTER JOIN settlements ON wallets.sender = settlements.payment_address;
+
+DROP TRIGGER IF EXISTS refunds_log_insert;
+DROP TRIGGER IF EXISTS refunds_log_update;
+DROP INDEX IF EXISTS refunds_log_updated_at;
+DROP INDEX IF EXISTS refunds_log_refund_id;
+DROP TABLE IF EXISTS refunds_log;
+
+DROP TRIGGER IF EXISTS refunds_no_delete;
+DROP INDEX IF EXISTS refunds_status_idx;
+DROP INDEX IF EXISTS refunds_order_id_idx;
+DROP INDEX IF EXISTS refunds_address_idx;
+DROP TABLE IF EXISTS refunds;
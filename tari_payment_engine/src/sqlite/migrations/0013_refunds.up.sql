@@ -0,0 +1,124 @@
+-- Tari returned to a customer, either from their unspent balance or against an order they have paid for.
+-- Refunds only affect the address balance once they have been approved.
+CREATE TABLE refunds (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    created_at  DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at  DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    address     TEXT     NOT NULL,
+    -- NULL for refunds of an unspent (e.g. overpaid) balance
+    order_id    TEXT REFERENCES orders (order_id),
+    amount      INTEGER  NOT NULL CHECK (amount > 0),
+    reason      TEXT     NOT NULL,
+    status      TEXT     NOT NULL CHECK (status IN ('Requested', 'Approved', 'Sent', 'Rejected')) DEFAULT 'Requested',
+    -- The transaction id of the payout, once it has been sent
+    payout_txid TEXT
+);
+
+CREATE INDEX refunds_address_idx ON refunds (address);
+CREATE INDEX refunds_order_id_idx ON refunds (order_id);
+CREATE INDEX refunds_status_idx ON refunds (status);
+
+-- Do not allow deletes on the refunds table
+CREATE TRIGGER refunds_no_delete BEFORE DELETE ON refunds
+BEGIN
+    SELECT RAISE(FAIL, 'Delete not allowed on refunds table. Set status to Rejected instead');
+END;
+
+-- amount = 1
+-- reason = 2
+-- status = 4
+-- payout_txid = 8
+CREATE TABLE refunds_log
+(
+    id              INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    refund_id       INTEGER  NOT NULL REFERENCES refunds (id),
+    columns_changed INTEGER  NOT NULL,
+    old_amount      INTEGER,
+    new_amount      INTEGER,
+    old_reason      TEXT,
+    new_reason      TEXT,
+    old_status      TEXT,
+    new_status      TEXT,
+    old_payout_txid TEXT,
+    new_payout_txid TEXT,
+    updated_at      DATETIME NOT NULL
+);
+
+CREATE INDEX refunds_log_refund_id ON refunds_log (refund_id);
+CREATE INDEX refunds_log_updated_at ON refunds_log (updated_at);
+
+CREATE TRIGGER refunds_log_update AFTER UPDATE ON refunds
+BEGIN
+    SELECT RAISE(FAIL, 'The address and order of a refund cannot be changed')
+    WHERE NEW.address != OLD.address OR NEW.order_id IS NOT OLD.order_id;
+    INSERT INTO refunds_log (refund_id,
+                             columns_changed,
+                             old_amount,
+                             new_amount,
+                             old_reason,
+                             new_reason,
+                             old_status,
+                             new_status,
+                             old_payout_txid,
+                             new_payout_txid,
+                             updated_at)
+    VALUES (NEW.id,
+            iif(OLD.amount != NEW.amount, 1, 0) +
+            iif(OLD.reason != NEW.reason, 2, 0) +
+            iif(OLD.status != NEW.status, 4, 0) +
+            iif(OLD.payout_txid IS NOT NEW.payout_txid, 8, 0),
+            nullif(OLD.amount, NEW.amount),
+            nullif(NEW.amount, OLD.amount),
+            nullif(OLD.reason, NEW.reason),
+            nullif(NEW.reason, OLD.reason),
+            nullif(OLD.status, NEW.status),
+            nullif(NEW.status, OLD.status),
+            nullif(OLD.payout_txid, NEW.payout_txid),
+            nullif(NEW.payout_txid, OLD.payout_txid),
+            NEW.updated_at);
+END;
+
+CREATE TRIGGER refunds_log_insert AFTER INSERT ON refunds
+BEGIN
+    INSERT INTO refunds_log (refund_id, columns_changed, new_amount, new_reason, new_status, updated_at)
+    VALUES (NEW.id, 1 + 2 + 4, NEW.amount, NEW.reason, NEW.status, NEW.updated_at);
+END;
+
+-- Approved and sent refunds are debited from the address balance. A refund against an order returns part of the
+-- order payment, so it is deducted from `total_paid` as well, and leaves the current balance unchanged.
+DROP VIEW IF EXISTS address_balance;
+CREATE VIEW address_balance (address, total_confirmed, total_paid, total_refunded, current_balance, last_update) AS
+WITH
+    wallets AS (
+    SELECT sender, sum(amount) as total_confirmed, updated_at
+    FROM payments
+    WHERE status = 'Confirmed'
+    GROUP BY sender
+),
+    settlements AS (
+    SELECT sum(amount) as total, payment_address, created_at
+    FROM settlement_journal
+    GROUP BY payment_address
+),
+    refunded AS (
+    SELECT
+        address,
+        sum(amount) as total,
+        sum(iif(order_id IS NULL, 0, amount)) as for_orders,
+        max(updated_at) as updated_at
+    FROM refunds
+    WHERE status IN ('Approved', 'Sent')
+    GROUP BY address
+)
+SELECT
+    wallets.sender as address,
+    wallets.total_confirmed as total_confirmed,
+    coalesce(settlements.total, 0) - coalesce(refunded.for_orders, 0) as total_paid,
+    coalesce(refunded.total, 0) as total_refunded,
+    wallets.total_confirmed - coalesce(settlements.total, 0) - coalesce(refunded.total, 0)
+        + coalesce(refunded.for_orders, 0) as current_balance,
+    max(coalesce(settlements.created_at, wallets.updated_at), coalesce(refunded.updated_at, wallets.updated_at))
+        as last_update
+FROM wallets
+LEFT OUTER JOIN settlements ON wallets.sender = settlements.payment_address
+LEFT OUTER JOIN refunded ON wallets.sender = refunded.address;
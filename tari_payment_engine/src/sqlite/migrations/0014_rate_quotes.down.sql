@@ -0,0 +1 @@
+DROP INDEX IF EXISTS orders_quote_expires_at_idx;
@@ -0,0 +1,6 @@
+-- Orders that are priced in another currency record the exchange rate they were quoted at, and when that quote
+-- expires. An order cannot be paid once its quote has expired; it must first be re-priced at the current rate.
+ALTER TABLE orders ADD COLUMN rate_id INTEGER;
+ALTER TABLE orders ADD COLUMN quote_expires_at TIMESTAMP;
+
+CREATE INDEX orders_quote_expires_at_idx ON orders (quote_expires_at);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS merchant_role_assignments;
+DROP INDEX IF EXISTS orders_merchant_id_idx;
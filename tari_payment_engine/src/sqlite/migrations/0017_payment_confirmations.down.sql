@@ -0,0 +1,2 @@
+ALTER TABLE payments DROP COLUMN confirmations;
+ALTER TABLE payments DROP COLUMN block_height;
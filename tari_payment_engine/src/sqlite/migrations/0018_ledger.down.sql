@@ -0,0 +1,9 @@
+DROP TRIGGER IF EXISTS ledger_entries_no_delete;
+DROP TRIGGER IF EXISTS ledger_entries_no_change;
+DROP TRIGGER IF EXISTS ledger_transactions_no_delete;
+DROP TRIGGER IF EXISTS ledger_transactions_no_change;
+DROP INDEX IF EXISTS ledger_entries_transaction_id_idx;
+DROP INDEX IF EXISTS ledger_entries_account_idx;
+DROP TABLE IF EXISTS ledger_entries;
+DROP INDEX IF EXISTS ledger_transactions_reference_idx;
+DROP TABLE IF EXISTS ledger_transactions;
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS auth_sessions_revoked_at_idx;
+DROP INDEX IF EXISTS auth_sessions_address_idx;
+DROP TABLE IF EXISTS auth_sessions;
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS role_permissions;
+DELETE FROM roles WHERE id > 5;
+DROP INDEX IF EXISTS roles_name_idx;
@@ -0,0 +1,7 @@
+DELETE FROM role_permissions WHERE permission = 'audit.read';
+DROP TRIGGER IF EXISTS admin_actions_no_delete;
+DROP TRIGGER IF EXISTS admin_actions_no_change;
+DROP INDEX IF EXISTS admin_actions_created_at_idx;
+DROP INDEX IF EXISTS admin_actions_endpoint_idx;
+DROP INDEX IF EXISTS admin_actions_actor_idx;
+DROP TABLE IF EXISTS admin_actions;
@@ -0,0 +1,39 @@
+-- The admin audit log. Every privileged API call is recorded, whether or not it succeeded, along with who made it and
+-- why. The orders_log and payments_log tables record what changed; this table records who changed it.
+CREATE TABLE admin_actions (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    created_at  DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    actor       TEXT     NOT NULL,
+    -- Comma-separated list of the roles in the caller's access token
+    roles       TEXT     NOT NULL,
+    merchant_id TEXT,
+    -- The name of the route, e.g. update_price
+    endpoint    TEXT     NOT NULL,
+    method      TEXT     NOT NULL,
+    path        TEXT     NOT NULL,
+    -- JSON object holding the path and query parameters and the request body
+    parameters  TEXT     NOT NULL,
+    reason      TEXT,
+    client_ip   TEXT,
+    outcome     TEXT     NOT NULL CHECK (outcome IN ('Succeeded', 'Failed', 'Denied')),
+    status_code INTEGER  NOT NULL,
+    error       TEXT
+);
+
+CREATE INDEX admin_actions_actor_idx ON admin_actions (actor);
+CREATE INDEX admin_actions_endpoint_idx ON admin_actions (endpoint);
+CREATE INDEX admin_actions_created_at_idx ON admin_actions (created_at);
+
+CREATE TRIGGER admin_actions_no_change BEFORE UPDATE ON admin_actions
+BEGIN
+    SELECT RAISE(FAIL, 'The admin audit log is append-only');
+END;
+
+CREATE TRIGGER admin_actions_no_delete BEFORE DELETE ON admin_actions
+BEGIN
+    SELECT RAISE(FAIL, 'The admin audit log is append-only');
+END;
+
+-- Reading the audit log is a permission of its own. The read_all and super_admin roles are granted it.
+INSERT INTO role_permissions (role_id, permission)
+SELECT id, 'audit.read' FROM roles WHERE name IN ('read_all', 'super_admin');
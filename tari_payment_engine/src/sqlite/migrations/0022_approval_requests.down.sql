@@ -0,0 +1,5 @@
+DELETE FROM role_permissions WHERE permission IN ('approvals.read', 'approvals.decide');
+DROP TRIGGER IF EXISTS approval_requests_no_edit;
+DROP TRIGGER IF EXISTS approval_requests_no_delete;
+DROP INDEX IF EXISTS approval_requests_status_idx;
+DROP TABLE IF EXISTS approval_requests;
@@ -0,0 +1,35 @@
+-- High-risk admin operations that wait for a second admin's approval before they are carried out. The operation is
+-- stored as JSON, so that it can be carried out as it was requested once it has been approved.
+CREATE TABLE approval_requests (
+    id              INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    created_at      DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at      DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at      DATETIME NOT NULL,
+    requested_by    TEXT     NOT NULL,
+    operation       TEXT     NOT NULL,
+    status          TEXT     NOT NULL CHECK (status IN ('Pending', 'Approved', 'Rejected', 'Expired', 'Failed'))
+                             DEFAULT 'Pending',
+    decided_by      TEXT CHECK (decided_by IS NULL OR decided_by != requested_by),
+    -- Why the request was rejected, or why the operation failed after it was approved
+    decision_reason TEXT
+);
+
+CREATE INDEX approval_requests_status_idx ON approval_requests (status);
+
+CREATE TRIGGER approval_requests_no_delete BEFORE DELETE ON approval_requests
+BEGIN
+    SELECT RAISE(FAIL, 'Delete not allowed on approval_requests table. Reject the request instead');
+END;
+
+CREATE TRIGGER approval_requests_no_edit BEFORE UPDATE ON approval_requests
+BEGIN
+    SELECT RAISE(FAIL, 'The operation and requester of an approval request cannot be changed')
+    WHERE NEW.operation != OLD.operation OR NEW.requested_by != OLD.requested_by;
+END;
+
+-- Viewing approval requests and deciding on them are permissions of their own. The read_all and super_admin roles can
+-- view them, and the write and super_admin roles can decide on them.
+INSERT INTO role_permissions (role_id, permission)
+SELECT id, 'approvals.read' FROM roles WHERE name IN ('read_all', 'super_admin');
+INSERT INTO role_permissions (role_id, permission)
+SELECT id, 'approvals.decide' FROM roles WHERE name IN ('write', 'super_admin');
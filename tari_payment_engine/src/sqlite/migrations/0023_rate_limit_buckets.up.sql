@@ -0,0 +1,12 @@
+-- Token buckets for rate limits that several server instances share. Times are Julian day numbers, which keep the
+-- sub-second precision that refilling the buckets needs. A bucket is full again once it expires, so expired buckets
+-- can be deleted at any time.
+CREATE TABLE rate_limit_buckets (
+    key        TEXT PRIMARY KEY NOT NULL,
+    tokens     REAL    NOT NULL,
+    allowed    BOOLEAN NOT NULL,
+    updated_at REAL    NOT NULL,
+    expires_at REAL    NOT NULL
+);
+
+CREATE INDEX rate_limit_buckets_expires_at_idx ON rate_limit_buckets (expires_at);
@@ -0,0 +1,3 @@
+-- When a rate source last reported each rate. It is NULL until the rate feed polls a rate that is unchanged from the
+-- stored one, and updated_at, which dates the rate history, is left alone.
+ALTER TABLE exchange_rates ADD COLUMN confirmed_at INTEGER;
@@ -0,0 +1,8 @@
+DROP VIEW IF EXISTS merchant_balance;
+DROP INDEX IF EXISTS exchange_rates_merchant_currency_idx;
+DROP INDEX IF EXISTS payments_merchant_id_idx;
+ALTER TABLE exchange_rates DROP COLUMN merchant_id;
+ALTER TABLE refunds DROP COLUMN merchant_id;
+ALTER TABLE settlement_journal DROP COLUMN merchant_id;
+ALTER TABLE wallet_auth DROP COLUMN merchant_id;
+ALTER TABLE payments DROP COLUMN merchant_id;
@@ -0,0 +1,51 @@
+-- Payments, wallets, refunds and exchange rates belong to a merchant, like orders do. A customer's funds can only be
+-- spent on the orders of the merchant whose wallet received them. Existing rows belong to the default merchant.
+ALTER TABLE payments ADD COLUMN merchant_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE wallet_auth ADD COLUMN merchant_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE settlement_journal ADD COLUMN merchant_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE refunds ADD COLUMN merchant_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE exchange_rates ADD COLUMN merchant_id TEXT NOT NULL DEFAULT 'default';
+
+CREATE INDEX payments_merchant_id_idx ON payments (merchant_id);
+CREATE INDEX exchange_rates_merchant_currency_idx ON exchange_rates (merchant_id, base_currency);
+
+-- The funds that each address holds with each merchant. address_balance is the sum of these over all merchants.
+CREATE VIEW merchant_balance (address, merchant_id, total_confirmed, total_paid, total_refunded, current_balance,
+                              last_update) AS
+WITH
+    wallets AS (
+    SELECT sender, merchant_id, sum(amount) as total_confirmed, max(updated_at) as updated_at
+    FROM payments
+    WHERE status = 'Confirmed'
+    GROUP BY sender, merchant_id
+),
+    settlements AS (
+    SELECT sum(amount) as total, payment_address, merchant_id, max(created_at) as created_at
+    FROM settlement_journal
+    GROUP BY payment_address, merchant_id
+),
+    refunded AS (
+    SELECT
+        address,
+        merchant_id,
+        sum(amount) as total,
+        sum(iif(order_id IS NULL, 0, amount)) as for_orders,
+        max(updated_at) as updated_at
+    FROM refunds
+    WHERE status IN ('Approved', 'Sent')
+    GROUP BY address, merchant_id
+)
+SELECT
+    wallets.sender as address,
+    wallets.merchant_id as merchant_id,
+    wallets.total_confirmed as total_confirmed,
+    coalesce(settlements.total, 0) - coalesce(refunded.for_orders, 0) as total_paid,
+    coalesce(refunded.total, 0) as total_refunded,
+    wallets.total_confirmed - coalesce(settlements.total, 0) - coalesce(refunded.total, 0)
+        + coalesce(refunded.for_orders, 0) as current_balance,
+    max(coalesce(settlements.created_at, wallets.updated_at), coalesce(refunded.updated_at, wallets.updated_at))
+        as last_update
+FROM wallets
+LEFT OUTER JOIN settlements
+    ON wallets.sender = settlements.payment_address AND wallets.merchant_id = settlements.merchant_id
+LEFT OUTER JOIN refunded ON wallets.sender = refunded.address AND wallets.merchant_id = refunded.merchant_id;
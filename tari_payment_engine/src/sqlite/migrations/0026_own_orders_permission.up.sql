@@ -0,0 +1,3 @@
+-- Fetching an order by its id needs a permission of its own, which the user and super_admin roles grant.
+INSERT INTO role_permissions (role_id, permission)
+SELECT id, 'orders.read_own' FROM roles WHERE name IN ('user', 'super_admin');
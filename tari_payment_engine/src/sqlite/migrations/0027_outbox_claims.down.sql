@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS event_outbox_handlers;
+ALTER TABLE event_outbox DROP COLUMN locked_until;
@@ -0,0 +1,10 @@
+-- A dispatcher leases the events it is delivering until locked_until, so that other dispatchers skip them.
+ALTER TABLE event_outbox ADD COLUMN locked_until DATETIME;
+
+-- The handlers that have already delivered an event. A retry only runs the handlers that are missing here.
+CREATE TABLE IF NOT EXISTS event_outbox_handlers (
+    event_id INTEGER NOT NULL REFERENCES event_outbox (id),
+    handler TEXT NOT NULL,
+    delivered_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (event_id, handler)
+);
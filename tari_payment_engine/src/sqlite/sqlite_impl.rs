@@ -1,58 +1,124 @@
 //! `SqliteDatabase` is a concrete implementation of a Tari Payment engine backend.
 //!
 //! Unsurprisingly, it uses SQLite as the backend and implements all the traits defined in the [`traits`] module.
-use std::{cmp::Reverse, fmt::Debug};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+};
 
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
 use log::*;
 use sqlx::{SqliteConnection, SqlitePool};
 use tari_common_types::tari_address::TariAddress;
 use tpg_common::MicroTari;
 
-use super::db::{accounts, auth, db_url, exchange_rates, new_pool, orders, transfers, wallet_auth};
+use super::db::{
+    accounts,
+    approvals,
+    audit,
+    auth,
+    db_url,
+    exchange_rates,
+    held_orders,
+    ledger,
+    new_pool,
+    orders,
+    outbox,
+    rate_limits,
+    refunds,
+    sessions,
+    transfers,
+    wallet_auth,
+    webhooks,
+};
 use crate::{
     db_types::{
         AddressBalance,
+        AdminAction,
+        AdminActionFilter,
+        ApprovalRequest,
+        ApprovalStatus,
+        AuthSession,
         CreditNote,
         CustomerBalance,
         CustomerOrderBalance,
         CustomerOrders,
+        HeldOrder,
+        LedgerAccount,
+        LedgerBalance,
+        LedgerEntry,
+        NewAdminAction,
+        NewApprovalRequest,
+        NewAuthSession,
+        NewLedgerTransaction,
         NewOrder,
         NewPayment,
+        NewRefund,
         NewSettlementJournalEntry,
+        NewWebhookSubscription,
         Order,
         OrderId,
         OrderStatusType,
+        OutboxEvent,
         Payment,
+        Refund,
+        RefundStatus,
         Role,
+        RoleDefinition,
         SerializedTariAddress,
+        SettlementJournalEntry,
         SettlementType,
         TransferStatus,
+        WebhookDelivery,
+        WebhookSubscription,
+    },
+    events::{
+        ApprovalEvent,
+        EventType,
+        OrderAnnulledEvent,
+        OrderClaimedEvent,
+        OrderEvent,
+        OrderHeldEvent,
+        OrderModifiedEvent,
+        PaymentEvent,
+        PaymentRevertedEvent,
+        RefundEvent,
     },
     order_objects::{ModifyOrderRequest, OrderChanged, OrderQueryFilter},
     sqlite::db::orders::{fetch_order_by_id_or_alt, fetch_order_by_order_id},
     tpe_api::{
-        account_objects::{AddressHistory, CustomerHistory, Pagination},
+        account_objects::{AddressHistory, CustomerHistory, Pagination, TrialBalance},
         exchange_objects::ExchangeRate,
+        rate_limit_objects::{RateLimit, RateLimitDecision},
     },
     traits::{
         AccountApiError,
         AccountManagement,
+        AuditLog,
+        AuditLogError,
         AuthApiError,
         AuthManagement,
+        EventOutbox,
         ExchangeRateError,
         ExchangeRates,
         ExpiryResult,
         MultiAccountPayment,
         NewWalletInfo,
         OrderMovedResult,
+        OutboxError,
         PaymentGatewayDatabase,
         PaymentGatewayError,
+        PaymentReversal,
+        RateLimitError,
+        RateLimitStore,
         WalletAuth,
         WalletAuthApiError,
         WalletInfo,
         WalletManagement,
         WalletManagementError,
+        WebhookError,
+        WebhookManagement,
     },
 };
 
@@ -103,9 +169,11 @@ impl PaymentGatewayDatabase for SqliteDatabase {
             alt_id,
             cust_id
         );
-        let mut address = accounts::balances_for_order_id(&order.order_id, alt_id, &mut tx).await?;
+        let merchant_id = &order.merchant_id;
+        let mut address =
+            accounts::merchant_balances_for_order_id(&order.order_id, alt_id, merchant_id, &mut tx).await?;
         if address.is_empty() {
-            address = accounts::balances_for_customer_id(cust_id, &mut tx).await?;
+            address = accounts::merchant_balances_for_customer_id(cust_id, merchant_id, &mut tx).await?;
         }
         // The first address is either explicitly lined to the order, or the most recent one
         let Some(address) = address.first().map(|a| a.address().clone()) else {
@@ -115,14 +183,20 @@ impl PaymentGatewayDatabase for SqliteDatabase {
         };
         let order = orders::update_order_status(order.id, OrderStatusType::New, &mut tx).await?;
         accounts::link_address_to_customer(&address, &order.customer_id, &mut tx).await?;
+        let event = EventType::OrderClaimed(OrderClaimedEvent::new(order.clone(), address.clone()));
+        outbox::enqueue(&event, &mut tx).await?;
         tx.commit().await?;
         Ok(Some((address, order)))
     }
 
     async fn insert_order(&self, order: NewOrder) -> Result<(Order, bool), PaymentGatewayError> {
-        let mut conn = self.pool.acquire().await?;
-        let result = orders::idempotent_insert(order, &mut conn).await?;
-        Ok(result)
+        let mut tx = self.pool.begin().await?;
+        let (order, inserted) = orders::idempotent_insert(order, &mut tx).await?;
+        if inserted {
+            outbox::enqueue(&EventType::NewOrder(OrderEvent::new(order.clone())), &mut tx).await?;
+        }
+        tx.commit().await?;
+        Ok((order, inserted))
     }
 
     /// Takes a new payment, and in a single atomic transaction,
@@ -141,6 +215,8 @@ impl PaymentGatewayDatabase for SqliteDatabase {
         let maybe_order_id = payment.order_id.clone();
         debug!("🗃️ Payment {} received from [{}]", payment.txid, payment.sender.as_address());
         let payment = transfers::idempotent_insert(payment, &mut tx).await?;
+        ledger::post(&NewLedgerTransaction::payment_received(&payment), &mut tx).await?;
+        outbox::enqueue(&EventType::PaymentReceived(PaymentEvent::new(payment.clone())), &mut tx).await?;
         // If the order id is already known, link the address and customer_id
         if let Some(order_id) = maybe_order_id {
             info!(
@@ -176,6 +252,8 @@ impl PaymentGatewayDatabase for SqliteDatabase {
     async fn process_credit_note_for_customer(&self, note: CreditNote) -> Result<Payment, PaymentGatewayError> {
         let mut tx = self.pool.begin().await?;
         let payment = transfers::credit_note(&note, &mut tx).await?;
+        ledger::post(&NewLedgerTransaction::credit_note(&payment), &mut tx).await?;
+        outbox::enqueue(&EventType::PaymentReceived(PaymentEvent::new(payment.clone())), &mut tx).await?;
         debug!("🗃️ Credit note for {} created with address {}", note.customer_id, payment.sender.as_address());
         let address = payment.sender.as_address();
         accounts::link_address_to_customer(address, &note.customer_id, &mut tx).await?;
@@ -200,11 +278,22 @@ impl PaymentGatewayDatabase for SqliteDatabase {
         strict_mode: bool,
     ) -> Result<Option<MultiAccountPayment>, PaymentGatewayError> {
         let mut tx = self.pool.begin().await?;
+        // The caller's copy may predate a re-pricing, or the expiry of its quote
+        let order = &orders::fetch_order_by_order_id(&order.order_id, &mut tx)
+            .await?
+            .ok_or_else(|| PaymentGatewayError::OrderNotFound(order.order_id.clone()))?;
+        if order.quote_has_expired() {
+            return Err(PaymentGatewayError::QuoteExpired(order.order_id.clone()));
+        }
         // First check for any payments that contain the order id
         let alt_id = if strict_mode { order.alt_id.as_ref() } else { None };
-        let order_balances = accounts::balances_for_order_id(&order.order_id, alt_id, &mut tx).await?;
+        // Only the funds held with the order's merchant can pay for it
+        let merchant_id = &order.merchant_id;
+        let order_balances =
+            accounts::merchant_balances_for_order_id(&order.order_id, alt_id, merchant_id, &mut tx).await?;
         debug!("🗃️ Found {} payments explicitly lined to order {}", order_balances.len(), order.order_id);
-        let mut balances = accounts::balances_for_customer_id(&order.customer_id, &mut tx).await?;
+        let mut balances =
+            accounts::merchant_balances_for_customer_id(&order.customer_id, merchant_id, &mut tx).await?;
         balances.extend(order_balances);
         let mut total_due = order.total_price;
         let total_credit = balances.iter().map(|b| b.current_balance()).sum();
@@ -234,8 +323,10 @@ impl PaymentGatewayDatabase for SqliteDatabase {
                 payment_address: address,
                 amount: amount_paid,
                 settlement_type,
+                merchant_id: order.merchant_id.clone(),
             };
             let settlement = accounts::insert_settlement(settlement, &mut tx).await?;
+            ledger::post(&NewLedgerTransaction::settlement(&settlement, &order.merchant_id), &mut tx).await?;
             result.settlements.push(settlement);
             if total_due == zero {
                 break;
@@ -243,6 +334,7 @@ impl PaymentGatewayDatabase for SqliteDatabase {
         }
         if total_due == zero {
             let paid_order = orders::update_order_status(order.id, OrderStatusType::Paid, &mut tx).await?;
+            outbox::enqueue(&EventType::OrderPaid(OrderEvent::new(paid_order.clone())), &mut tx).await?;
             result.orders_paid.push(paid_order);
         }
         tx.commit().await?;
@@ -264,8 +356,8 @@ impl PaymentGatewayDatabase for SqliteDatabase {
     }
 
     async fn update_payment_status(&self, txid: &str, status: TransferStatus) -> Result<Payment, PaymentGatewayError> {
-        let mut conn = self.pool.acquire().await?;
-        let Some(payment) = transfers::fetch_payment(txid, &mut conn).await? else {
+        let mut tx = self.pool.begin().await?;
+        let Some(payment) = transfers::fetch_payment(txid, &mut tx).await? else {
             return Err(PaymentGatewayError::PaymentStatusUpdateError(format!("Payment {txid} not found")));
         };
         let old_status = payment.status;
@@ -285,7 +377,16 @@ impl PaymentGatewayDatabase for SqliteDatabase {
             )));
         }
 
-        let payment = transfers::update_status(txid, status, &mut conn).await?;
+        let payment = transfers::update_status(txid, status, &mut tx).await?;
+        match status {
+            Confirmed => {
+                ledger::post(&NewLedgerTransaction::payment_confirmed(&payment), &mut tx).await?;
+                outbox::enqueue(&EventType::Confirmation(PaymentEvent::new(payment.clone())), &mut tx).await?;
+            },
+            Cancelled => ledger::post(&NewLedgerTransaction::payment_cancelled(&payment), &mut tx).await?,
+            Received => {},
+        }
+        tx.commit().await?;
         debug!("🗃️ Payment [{txid}] is now {status}.");
         Ok(payment)
     }
@@ -296,6 +397,62 @@ impl PaymentGatewayDatabase for SqliteDatabase {
         payment.ok_or_else(|| PaymentGatewayError::PaymentNotFound(tx_id.into()))
     }
 
+    async fn update_payment_confirmations(
+        &self,
+        tx_id: &str,
+        block_height: Option<i64>,
+        confirmations: i64,
+    ) -> Result<Payment, PaymentGatewayError> {
+        let mut conn = self.pool.acquire().await?;
+        let payment = transfers::update_confirmations(tx_id, block_height, confirmations, &mut conn).await?;
+        Ok(payment)
+    }
+
+    async fn revert_payment(&self, tx_id: &str) -> Result<PaymentReversal, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let payment = transfers::fetch_payment(tx_id, &mut tx)
+            .await?
+            .ok_or_else(|| PaymentGatewayError::PaymentNotFound(tx_id.into()))?;
+        if payment.status != TransferStatus::Confirmed {
+            return Err(PaymentGatewayError::PaymentStatusUpdateError(format!(
+                "Payment {tx_id} has status {} instead of 'Confirmed'",
+                payment.status
+            )));
+        }
+        let address = payment.sender.as_address();
+        // The balance still includes the payment at this point. Only the orders of the payment's merchant were paid
+        // from it.
+        let balance = accounts::fetch_merchant_balance(address, &payment.merchant_id, &mut tx).await?;
+        let shortfall = payment.amount - balance.current_balance();
+        transfers::update_status(tx_id, TransferStatus::Received, &mut tx).await?;
+        let reverted = transfers::update_confirmations(tx_id, None, 0, &mut tx).await?;
+        ledger::post(&NewLedgerTransaction::payment_reverted(&reverted), &mut tx).await?;
+        let mut orders_reverted = Vec::new();
+        let mut settlements = Vec::new();
+        if shortfall > MicroTari::from(0) {
+            debug!("🗃️ Reverting payment {tx_id} leaves {} short by {shortfall}", address.to_base58());
+            let mut entries = accounts::settlements_for_address(address, &mut tx).await?;
+            entries.retain(|e| e.merchant_id == payment.merchant_id);
+            for order_id in PaymentReversal::orders_to_revert(&entries, shortfall) {
+                let order = fetch_order_by_order_id(&order_id, &mut tx)
+                    .await?
+                    .ok_or_else(|| PaymentGatewayError::OrderNotFound(order_id.clone()))?;
+                let paid = accounts::settlements_for_order(&order_id, &mut tx).await?;
+                for entry in PaymentReversal::reversing_entries(&paid) {
+                    let settlement = accounts::insert_settlement(entry, &mut tx).await?;
+                    ledger::post(&NewLedgerTransaction::settlement(&settlement, &order.merchant_id), &mut tx).await?;
+                    settlements.push(settlement);
+                }
+                orders_reverted.push(orders::update_order_status(order.id, OrderStatusType::New, &mut tx).await?);
+            }
+        }
+        let event = PaymentRevertedEvent::new(reverted.clone(), orders_reverted.clone());
+        outbox::enqueue(&EventType::PaymentReverted(event), &mut tx).await?;
+        tx.commit().await?;
+        debug!("🗃️ Payment [{tx_id}] has been reverted. {} orders are unpaid again.", orders_reverted.len());
+        Ok(PaymentReversal::new(reverted, orders_reverted, settlements))
+    }
+
     /// A manual order status transition from `New` to `Paid` status.
     /// A credit note for the `total_price` is created.
     async fn mark_new_or_unclaimed_order_as_paid(
@@ -312,8 +469,12 @@ impl PaymentGatewayDatabase for SqliteDatabase {
         }
         let mut tx = self.pool.begin().await?;
         let reason = format!("Admin credit overrode for order {}. Reason: {reason}", order.order_id);
-        let note = CreditNote::new(order.customer_id.clone(), order.total_price).with_reason(reason);
+        let note = CreditNote::new(order.customer_id.clone(), order.total_price)
+            .with_reason(reason)
+            .with_merchant(&order.merchant_id);
         let payment = transfers::credit_note(&note, &mut tx).await?;
+        ledger::post(&NewLedgerTransaction::credit_note(&payment), &mut tx).await?;
+        outbox::enqueue(&EventType::PaymentReceived(PaymentEvent::new(payment.clone())), &mut tx).await?;
         let address = payment.sender.to_address();
         debug!(
             "🗃️ Credit note: Customer {} received note for {} with address {}",
@@ -364,6 +525,7 @@ impl PaymentGatewayDatabase for SqliteDatabase {
         let order = orders::update_order(&order.order_id, update, &mut tx)
             .await?
             .ok_or_else(|| AccountApiError::OrderDoesNotExist(order.order_id.clone()))?;
+        outbox::enqueue(&EventType::OrderAnnulled(OrderAnnulledEvent::new(order.clone())), &mut tx).await?;
         tx.commit().await?;
         Ok(order)
     }
@@ -390,8 +552,11 @@ impl PaymentGatewayDatabase for SqliteDatabase {
         let updated_order = orders::update_order(&old_order.order_id, update, &mut tx)
             .await?
             .ok_or_else(|| AccountApiError::OrderDoesNotExist(old_order.order_id.clone()))?;
-        tx.commit().await?;
         let result = OrderChanged::new(old_order, updated_order);
+        let modified = OrderModifiedEvent::new("status".to_string(), result.clone());
+        outbox::enqueue(&EventType::OrderModified(modified), &mut tx).await?;
+        outbox::enqueue(&EventType::NewOrder(OrderEvent::new(result.new_order.clone())), &mut tx).await?;
+        tx.commit().await?;
         Ok(result)
     }
 
@@ -432,6 +597,9 @@ impl PaymentGatewayDatabase for SqliteDatabase {
             );
             AccountApiError::OrderDoesNotExist(id.clone())
         })?;
+        let changes = OrderChanged::new(old_order.clone(), new_order.clone());
+        let modified = OrderModifiedEvent::new("customer_id".to_string(), changes);
+        outbox::enqueue(&EventType::OrderModified(modified), &mut tx).await?;
         // Order is either expired, cancelled or new by now. If expired or cancelled, we don't need to make any
         // adjustments but new orders need to be accounted for.
         tx.commit().await?;
@@ -466,10 +634,16 @@ impl PaymentGatewayDatabase for SqliteDatabase {
     /// The modified order
     async fn modify_memo_for_order(&self, order_id: &OrderId, new_memo: &str) -> Result<Order, PaymentGatewayError> {
         let update = ModifyOrderRequest::default().with_new_memo(new_memo);
-        let mut conn = self.pool.acquire().await?;
-        let order = orders::update_order(order_id, update, &mut conn)
+        let mut tx = self.pool.begin().await?;
+        let old_order = orders::fetch_order_by_order_id(order_id, &mut tx)
             .await?
             .ok_or_else(|| AccountApiError::OrderDoesNotExist(order_id.clone()))?;
+        let order = orders::update_order(order_id, update, &mut tx)
+            .await?
+            .ok_or_else(|| AccountApiError::OrderDoesNotExist(order_id.clone()))?;
+        let modified = OrderModifiedEvent::new("memo".to_string(), OrderChanged::new(old_order, order.clone()));
+        outbox::enqueue(&EventType::OrderModified(modified), &mut tx).await?;
+        tx.commit().await?;
         Ok(order)
     }
 
@@ -508,8 +682,38 @@ impl PaymentGatewayDatabase for SqliteDatabase {
             error!("{msg}");
             PaymentGatewayError::DatabaseError(msg)
         })?;
+        let delta = OrderChanged::new(old_order, new_order);
+        let modified = OrderModifiedEvent::new("total_price".to_string(), delta.clone());
+        outbox::enqueue(&EventType::OrderModified(modified), &mut tx).await?;
         tx.commit().await?;
+        Ok(delta)
+    }
+
+    async fn fetch_orders_with_expired_quotes(&self) -> Result<Vec<Order>, PaymentGatewayError> {
+        let mut conn = self.pool.acquire().await?;
+        orders::fetch_orders_with_expired_quotes(&mut conn).await
+    }
+
+    async fn requote_order(
+        &self,
+        order_id: &OrderId,
+        new_total_price: MicroTari,
+        rate_id: i64,
+        quote_expires_at: DateTime<Utc>,
+    ) -> Result<OrderChanged, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let old_order = orders::fetch_order_by_order_id(order_id, &mut tx)
+            .await?
+            .ok_or_else(|| PaymentGatewayError::OrderNotFound(order_id.clone()))?;
+        if !matches!(old_order.status, OrderStatusType::New | OrderStatusType::Unclaimed) {
+            info!("🗃️ Order {order_id} cannot be re-priced since it is already {}", old_order.status);
+            return Err(PaymentGatewayError::OrderModificationForbidden);
+        }
+        let new_order = orders::update_quote(order_id, new_total_price, rate_id, quote_expires_at, &mut tx).await?;
         let delta = OrderChanged::new(old_order, new_order);
+        let modified = OrderModifiedEvent::new("total_price".to_string(), delta.clone());
+        outbox::enqueue(&EventType::OrderModified(modified), &mut tx).await?;
+        tx.commit().await?;
         Ok(delta)
     }
 
@@ -521,10 +725,169 @@ impl PaymentGatewayDatabase for SqliteDatabase {
         let mut tx = self.pool.begin().await?;
         let unclaimed_orders = orders::expire_orders(OrderStatusType::Unclaimed, unclaimed_limit, &mut tx).await?;
         let unpaid_orders = orders::expire_orders(OrderStatusType::New, unpaid_limit, &mut tx).await?;
+        for order in unclaimed_orders.iter().chain(unpaid_orders.iter()) {
+            outbox::enqueue(&EventType::OrderAnnulled(OrderAnnulledEvent::new(order.clone())), &mut tx).await?;
+        }
         tx.commit().await?;
         Ok(ExpiryResult::new(unclaimed_orders, unpaid_orders))
     }
 
+    async fn request_refund(&self, refund: NewRefund) -> Result<Refund, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let (address, order_id) = (refund.address.as_address(), refund.order_id.as_ref());
+        Self::check_refund(address, order_id, &refund.merchant_id, refund.amount, &mut tx).await?;
+        let refund = refunds::insert_refund(refund, &mut tx).await?;
+        tx.commit().await?;
+        let address = refund.address.as_address().to_base58();
+        info!("🗃️ Refund {} of {} to {address} has been requested", refund.id, refund.amount);
+        Ok(refund)
+    }
+
+    async fn approve_refund(&self, id: i64) -> Result<Refund, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let refund = Self::fetch_refund_with_status(id, RefundStatus::Requested, &mut tx).await?;
+        let (address, order_id) = (refund.address.as_address(), refund.order_id.as_ref());
+        Self::check_refund(address, order_id, &refund.merchant_id, refund.amount, &mut tx).await?;
+        let refund = refunds::update_refund(id, RefundStatus::Approved, None, None, &mut tx).await?;
+        // A refund against an order comes out of the revenue of the order's merchant
+        let merchant_id = match &refund.order_id {
+            Some(order_id) => fetch_order_by_order_id(order_id, &mut tx).await?.map(|order| order.merchant_id),
+            None => None,
+        };
+        ledger::post(&NewLedgerTransaction::refund_approved(&refund, merchant_id.as_deref()), &mut tx).await?;
+        outbox::enqueue(&EventType::RefundApproved(RefundEvent::new(refund.clone())), &mut tx).await?;
+        tx.commit().await?;
+        info!("🗃️ Refund {id} of {} has been approved", refund.amount);
+        Ok(refund)
+    }
+
+    async fn reject_refund(&self, id: i64, reason: &str) -> Result<Refund, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let refund = Self::fetch_refund_with_status(id, RefundStatus::Requested, &mut tx).await?;
+        let reason = format!("{}. Rejected: {reason}", refund.reason);
+        let refund = refunds::update_refund(id, RefundStatus::Rejected, Some(&reason), None, &mut tx).await?;
+        tx.commit().await?;
+        info!("🗃️ Refund {id} has been rejected");
+        Ok(refund)
+    }
+
+    async fn mark_refund_sent(&self, id: i64, payout_txid: &str) -> Result<Refund, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        Self::fetch_refund_with_status(id, RefundStatus::Approved, &mut tx).await?;
+        let refund = refunds::update_refund(id, RefundStatus::Sent, None, Some(payout_txid), &mut tx).await?;
+        ledger::post(&NewLedgerTransaction::refund_sent(&refund), &mut tx).await?;
+        outbox::enqueue(&EventType::RefundSent(RefundEvent::new(refund.clone())), &mut tx).await?;
+        tx.commit().await?;
+        info!("🗃️ Refund {id} has been sent in transaction {payout_txid}");
+        Ok(refund)
+    }
+
+    async fn hold_order(&self, order: &NewOrder, reason: &str) -> Result<HeldOrder, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let id = &order.order_id;
+        if orders::order_exists(id, &mut tx).await?.is_some() ||
+            held_orders::fetch_held_order(id, &mut tx).await?.is_some()
+        {
+            return Err(PaymentGatewayError::OrderAlreadyExists(id.clone()));
+        }
+        let held_order = held_orders::insert_held_order(order, reason, &mut tx).await?;
+        outbox::enqueue(&EventType::OrderHeld(OrderHeldEvent::new(held_order.clone())), &mut tx).await?;
+        tx.commit().await?;
+        warn!("🗃️ Order {id} is being held. {reason}");
+        Ok(held_order)
+    }
+
+    async fn mark_held_order_released(&self, order_id: &OrderId) -> Result<HeldOrder, PaymentGatewayError> {
+        let mut conn = self.pool.acquire().await?;
+        let held_order = held_orders::release_held_order(order_id, &mut conn)
+            .await?
+            .ok_or_else(|| PaymentGatewayError::OrderNotHeld(order_id.clone()))?;
+        info!("🗃️ Held order {order_id} has been released");
+        Ok(held_order)
+    }
+
+    async fn request_approval(&self, request: NewApprovalRequest) -> Result<ApprovalRequest, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let request = approvals::insert_approval_request(request, &mut tx).await?;
+        outbox::enqueue(&EventType::ApprovalRequested(ApprovalEvent::new(request.clone())), &mut tx).await?;
+        tx.commit().await?;
+        info!("🗃️ Approval request {} has been recorded. {}", request.id, request.operation);
+        Ok(request)
+    }
+
+    async fn approve_request(&self, id: i64, approver: &TariAddress) -> Result<ApprovalRequest, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let request = approvals::fetch_approval_request(id, &mut tx)
+            .await?
+            .ok_or(PaymentGatewayError::ApprovalRequestNotFound(id))?;
+        request.check_decision(approver)?;
+        let request = Self::update_approval_request(
+            id,
+            ApprovalStatus::Pending,
+            ApprovalStatus::Approved,
+            Some(approver),
+            None,
+            &mut tx,
+        )
+        .await?;
+        outbox::enqueue(&EventType::OperationApproved(ApprovalEvent::new(request.clone())), &mut tx).await?;
+        tx.commit().await?;
+        info!("🗃️ Approval request {id} has been approved by {}", approver.to_base58());
+        Ok(request)
+    }
+
+    async fn reject_request(
+        &self,
+        id: i64,
+        approver: &TariAddress,
+        reason: &str,
+    ) -> Result<ApprovalRequest, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        let request = approvals::fetch_approval_request(id, &mut tx)
+            .await?
+            .ok_or(PaymentGatewayError::ApprovalRequestNotFound(id))?;
+        request.check_decision(approver)?;
+        let request = Self::update_approval_request(
+            id,
+            ApprovalStatus::Pending,
+            ApprovalStatus::Rejected,
+            Some(approver),
+            Some(reason),
+            &mut tx,
+        )
+        .await?;
+        outbox::enqueue(&EventType::OperationRejected(ApprovalEvent::new(request.clone())), &mut tx).await?;
+        tx.commit().await?;
+        info!("🗃️ Approval request {id} has been rejected by {}", approver.to_base58());
+        Ok(request)
+    }
+
+    async fn mark_approval_failed(&self, id: i64, error: &str) -> Result<ApprovalRequest, PaymentGatewayError> {
+        let mut tx = self.pool.begin().await?;
+        Self::fetch_approval_with_status(id, ApprovalStatus::Approved, &mut tx).await?;
+        let request = Self::update_approval_request(
+            id,
+            ApprovalStatus::Approved,
+            ApprovalStatus::Failed,
+            None,
+            Some(error),
+            &mut tx,
+        )
+        .await?;
+        tx.commit().await?;
+        info!("🗃️ Approval request {id} has failed. {error}");
+        Ok(request)
+    }
+
+    async fn expire_approval_requests(&self) -> Result<Vec<ApprovalRequest>, PaymentGatewayError> {
+        let mut conn = self.pool.acquire().await?;
+        let expired = approvals::expire_approval_requests(&mut conn).await?;
+        if !expired.is_empty() {
+            info!("🗃️ {} approval requests have expired", expired.len());
+        }
+        Ok(expired)
+    }
+
     async fn close(&mut self) -> Result<(), PaymentGatewayError> {
         self.pool.close().await;
         Ok(())
@@ -644,6 +1007,114 @@ impl AccountManagement for SqliteDatabase {
         let ids = transfers::fetch_payments_for_order(order_id, &mut conn).await?;
         Ok(ids)
     }
+
+    async fn fetch_onchain_payments(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Payment>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let payments = transfers::fetch_onchain_payments(since, &mut conn).await?;
+        Ok(payments)
+    }
+
+    async fn fetch_idle_balances(&self, idle: Duration) -> Result<Vec<AddressBalance>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let balances = accounts::fetch_idle_balances(idle, &mut conn).await?;
+        Ok(balances)
+    }
+
+    async fn fetch_refund(&self, id: i64) -> Result<Option<Refund>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let refund = refunds::fetch_refund(id, &mut conn).await?;
+        Ok(refund)
+    }
+
+    async fn fetch_refunds(
+        &self,
+        status: Option<RefundStatus>,
+        pagination: &Pagination,
+    ) -> Result<Vec<Refund>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let refunds = refunds::fetch_refunds(status, pagination, &mut conn).await?;
+        Ok(refunds)
+    }
+
+    async fn fetch_approval_request(&self, id: i64) -> Result<Option<ApprovalRequest>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let request = approvals::fetch_approval_request(id, &mut conn).await?;
+        Ok(request)
+    }
+
+    async fn fetch_approval_requests(
+        &self,
+        status: Option<ApprovalStatus>,
+        pagination: &Pagination,
+    ) -> Result<Vec<ApprovalRequest>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let requests = approvals::fetch_approval_requests(status, pagination, &mut conn).await?;
+        Ok(requests)
+    }
+
+    async fn fetch_held_order(&self, order_id: &OrderId) -> Result<Option<HeldOrder>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let held_order = held_orders::fetch_held_order(order_id, &mut conn).await?;
+        Ok(held_order)
+    }
+
+    async fn fetch_held_orders(&self, pagination: &Pagination) -> Result<Vec<HeldOrder>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let held_orders = held_orders::fetch_held_orders(pagination, &mut conn).await?;
+        Ok(held_orders)
+    }
+
+    async fn fetch_ledger_entries(
+        &self,
+        account: &LedgerAccount,
+        pagination: &Pagination,
+    ) -> Result<Vec<LedgerEntry>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let entries = ledger::fetch_entries(account, pagination, &mut conn).await?;
+        Ok(entries)
+    }
+
+    async fn fetch_ledger_balances(&self) -> Result<Vec<LedgerBalance>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let balances = ledger::fetch_balances(&mut conn).await?;
+        Ok(balances)
+    }
+
+    async fn fetch_trial_balance(&self) -> Result<TrialBalance, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let balances = ledger::fetch_balances(&mut conn).await?;
+        let unbalanced = ledger::fetch_unbalanced_transactions(&mut conn).await?;
+        Ok(TrialBalance::new(balances, unbalanced))
+    }
+
+    async fn fetch_orders_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Order>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let orders = orders::fetch_orders_between(from, to, &mut conn).await?;
+        Ok(orders)
+    }
+
+    async fn fetch_payments_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Payment>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let payments = transfers::fetch_payments_between(from, to, &mut conn).await?;
+        Ok(payments)
+    }
+
+    async fn fetch_settlements_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<SettlementJournalEntry>, AccountApiError> {
+        let mut conn = self.pool.acquire().await?;
+        accounts::settlements_between(from, to, &mut conn).await
+    }
 }
 
 impl AuthManagement for SqliteDatabase {
@@ -691,6 +1162,103 @@ impl AuthManagement for SqliteDatabase {
         let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
         auth::remove_roles(address, roles, &mut conn).await
     }
+
+    async fn fetch_merchant_roles_for_address(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+    ) -> Result<Vec<Role>, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        let roles = auth::merchant_roles_for_address(address, merchant_id, &mut conn).await?;
+        Ok(roles.into_iter().collect())
+    }
+
+    async fn assign_merchant_roles(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+        roles: &[Role],
+    ) -> Result<(), AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::assign_merchant_roles(address, merchant_id, roles, &mut conn).await?;
+        debug!("🔑️ Roles {roles:?} assigned to {} for merchant {merchant_id}", address.to_base58());
+        Ok(())
+    }
+
+    async fn remove_merchant_roles(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+        roles: &[Role],
+    ) -> Result<u64, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::remove_merchant_roles(address, merchant_id, roles, &mut conn).await
+    }
+
+    async fn fetch_role_definitions(&self) -> Result<Vec<RoleDefinition>, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::fetch_role_definitions(&mut conn).await
+    }
+
+    async fn save_role(&self, role: &RoleDefinition) -> Result<(), AuthApiError> {
+        let mut tx = self.pool.begin().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::save_role(role, &mut tx).await?;
+        tx.commit().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        debug!("🔑️ Role {} now grants {:?}", role.role, role.permissions);
+        Ok(())
+    }
+
+    async fn delete_role(&self, role: &Role) -> Result<(), AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        auth::delete_role(role, &mut conn).await?;
+        debug!("🔑️ Role {role} deleted");
+        Ok(())
+    }
+
+    async fn create_session(&self, session: NewAuthSession) -> Result<AuthSession, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        let session = sessions::insert_session(session, &mut conn).await?;
+        debug!("🔑️ Session #{} opened for {}", session.id, session.address);
+        Ok(session)
+    }
+
+    async fn fetch_session(&self, id: i64) -> Result<Option<AuthSession>, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        sessions::fetch_session(id, &mut conn).await
+    }
+
+    async fn refresh_session(
+        &self,
+        refresh_token_hash: &str,
+        new_refresh_token_hash: &str,
+    ) -> Result<AuthSession, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        sessions::refresh_session(refresh_token_hash, new_refresh_token_hash, &mut conn).await
+    }
+
+    async fn fetch_sessions_for_address(&self, address: &TariAddress) -> Result<Vec<AuthSession>, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        sessions::fetch_sessions_for_address(address, &mut conn).await
+    }
+
+    async fn revoke_session(&self, id: i64) -> Result<AuthSession, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        let session = sessions::revoke_session(id, &mut conn).await?;
+        debug!("🔑️ Session #{id} for {} revoked", session.address);
+        Ok(session)
+    }
+
+    async fn revoke_sessions_for_address(&self, address: &TariAddress) -> Result<Vec<i64>, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        let ids = sessions::revoke_sessions_for_address(address, &mut conn).await?;
+        debug!("🔑️ {} sessions for {} revoked", ids.len(), address.to_base58());
+        Ok(ids)
+    }
+
+    async fn fetch_revoked_sessions(&self, since: DateTime<Utc>) -> Result<Vec<i64>, AuthApiError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| AuthApiError::DatabaseError(e.to_string()))?;
+        sessions::fetch_revoked_sessions(since, &mut conn).await
+    }
 }
 
 impl WalletAuth for SqliteDatabase {
@@ -734,6 +1302,38 @@ impl ExchangeRates for SqliteDatabase {
         exchange_rates::fetch_last_rate(currency, &mut conn).await
     }
 
+    async fn fetch_merchant_rate(&self, merchant_id: &str, currency: &str) -> Result<ExchangeRate, ExchangeRateError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+        exchange_rates::fetch_merchant_rate(merchant_id, currency, &mut conn).await
+    }
+
+    async fn fetch_rate_by_id(&self, id: i64) -> Result<ExchangeRate, ExchangeRateError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+        exchange_rates::fetch_rate_by_id(id, &mut conn).await
+    }
+
+    async fn fetch_rate_at(&self, currency: &str, timestamp: DateTime<Utc>) -> Result<ExchangeRate, ExchangeRateError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+        exchange_rates::fetch_rate_at(currency, timestamp, &mut conn).await
+    }
+
+    async fn fetch_previous_rate(&self, rate: &ExchangeRate) -> Result<Option<ExchangeRate>, ExchangeRateError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+        exchange_rates::fetch_previous_rate(rate, &mut conn).await
+    }
+
+    async fn fetch_rate_history(
+        &self,
+        merchant_id: &str,
+        currency: &str,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        pagination: &Pagination,
+    ) -> Result<Vec<ExchangeRate>, ExchangeRateError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+        exchange_rates::fetch_rate_history(merchant_id, currency, since, until, pagination, &mut conn).await
+    }
+
     /// Save the exchange rate for the given currency to the backend storage
     ///
     /// The `updated_at` field of the exchange rate is ignored. The backend will set this field to the current time.
@@ -741,6 +1341,183 @@ impl ExchangeRates for SqliteDatabase {
         let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
         exchange_rates::set_exchange_rate(new_rate, &mut conn).await
     }
+
+    async fn confirm_exchange_rate(&self, rate: &ExchangeRate) -> Result<(), ExchangeRateError> {
+        let mut conn = self.pool.acquire().await.map_err(|e| ExchangeRateError::DatabaseError(e.to_string()))?;
+        exchange_rates::confirm_exchange_rate(rate.id, &mut conn).await
+    }
+}
+
+impl EventOutbox for SqliteDatabase {
+    async fn enqueue_events(&self, events: &[EventType]) -> Result<Vec<OutboxEvent>, OutboxError> {
+        let mut tx = self.pool.begin().await?;
+        let mut result = Vec::with_capacity(events.len());
+        for event in events {
+            let id = outbox::enqueue(event, &mut tx).await?;
+            let event = outbox::fetch_event(id, &mut tx).await?.ok_or(OutboxError::EventNotFound(id))?;
+            result.push(event);
+        }
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    async fn fetch_due_events(&self, limit: i64) -> Result<Vec<OutboxEvent>, OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::fetch_due_events(limit, &mut conn).await
+    }
+
+    async fn claim_due_events(&self, limit: i64, lease: Duration) -> Result<Vec<OutboxEvent>, OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::claim_due_events(limit, Utc::now() + lease, &mut conn).await
+    }
+
+    async fn fetch_undelivered_events(&self, pagination: &Pagination) -> Result<Vec<OutboxEvent>, OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::fetch_undelivered_events(pagination, &mut conn).await
+    }
+
+    async fn fetch_outbox_event(&self, id: i64) -> Result<Option<OutboxEvent>, OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::fetch_event(id, &mut conn).await
+    }
+
+    async fn mark_event_delivered(&self, id: i64) -> Result<OutboxEvent, OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::mark_delivered(id, &mut conn).await
+    }
+
+    async fn record_failed_delivery(
+        &self,
+        id: i64,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<OutboxEvent, OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::record_failure(id, error, retry_at, &mut conn).await
+    }
+
+    async fn fetch_delivered_handlers(&self, id: i64) -> Result<Vec<String>, OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::fetch_delivered_handlers(id, &mut conn).await
+    }
+
+    async fn mark_handler_delivered(&self, id: i64, handler: &str) -> Result<(), OutboxError> {
+        let mut conn = self.pool.acquire().await?;
+        outbox::mark_handler_delivered(id, handler, &mut conn).await
+    }
+
+    async fn replay_event(&self, id: i64) -> Result<OutboxEvent, OutboxError> {
+        let mut tx = self.pool.begin().await?;
+        let event = outbox::replay(id, &mut tx).await?;
+        tx.commit().await?;
+        Ok(event)
+    }
+}
+
+impl WebhookManagement for SqliteDatabase {
+    async fn create_webhook(&self, webhook: &NewWebhookSubscription) -> Result<WebhookSubscription, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::insert_subscription(webhook, &mut conn).await
+    }
+
+    async fn fetch_webhooks(&self) -> Result<Vec<WebhookSubscription>, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::fetch_subscriptions(&mut conn).await
+    }
+
+    async fn fetch_webhook(&self, id: i64) -> Result<Option<WebhookSubscription>, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::fetch_subscription(id, &mut conn).await
+    }
+
+    async fn set_webhook_active(&self, id: i64, active: bool) -> Result<WebhookSubscription, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::set_active(id, active, &mut conn).await
+    }
+
+    async fn delete_webhook(&self, id: i64) -> Result<(), WebhookError> {
+        let mut tx = self.pool.begin().await?;
+        webhooks::delete_subscription(id, &mut tx).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn queue_webhook_deliveries(&self, event: &OutboxEvent) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::queue_deliveries(event, &mut conn).await
+    }
+
+    async fn fetch_due_webhook_deliveries(&self, limit: i64) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::fetch_due_deliveries(limit, &mut conn).await
+    }
+
+    async fn claim_due_webhook_deliveries(
+        &self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::claim_due_deliveries(limit, Utc::now() + lease, &mut conn).await
+    }
+
+    async fn fetch_webhook_deliveries(
+        &self,
+        subscription_id: Option<i64>,
+        pagination: &Pagination,
+    ) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::fetch_deliveries(subscription_id, pagination, &mut conn).await
+    }
+
+    async fn mark_webhook_delivered(&self, id: i64, status: i64) -> Result<WebhookDelivery, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::mark_delivered(id, status, &mut conn).await
+    }
+
+    async fn record_failed_webhook_delivery(
+        &self,
+        id: i64,
+        status: Option<i64>,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<WebhookDelivery, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::record_failure(id, status, error, retry_at, &mut conn).await
+    }
+
+    async fn replay_webhook_delivery(&self, id: i64) -> Result<WebhookDelivery, WebhookError> {
+        let mut conn = self.pool.acquire().await?;
+        webhooks::replay(id, &mut conn).await
+    }
+}
+
+impl AuditLog for SqliteDatabase {
+    async fn record_admin_action(&self, action: &NewAdminAction) -> Result<AdminAction, AuditLogError> {
+        let mut conn = self.pool.acquire().await?;
+        audit::insert_admin_action(action, &mut conn).await
+    }
+
+    async fn fetch_admin_actions(
+        &self,
+        filter: &AdminActionFilter,
+        pagination: &Pagination,
+    ) -> Result<Vec<AdminAction>, AuditLogError> {
+        let mut conn = self.pool.acquire().await?;
+        audit::fetch_admin_actions(filter, pagination, &mut conn).await
+    }
+}
+
+impl RateLimitStore for SqliteDatabase {
+    async fn take_token(&self, key: &str, limit: &RateLimit) -> Result<RateLimitDecision, RateLimitError> {
+        let mut conn = self.pool.acquire().await?;
+        Ok(rate_limits::take_token(key, limit, &mut conn).await?)
+    }
+
+    async fn prune_rate_limits(&self) -> Result<u64, RateLimitError> {
+        let mut conn = self.pool.acquire().await?;
+        Ok(rate_limits::prune_rate_limits(&mut conn).await?)
+    }
 }
 
 impl SqliteDatabase {
@@ -762,35 +1539,59 @@ impl SqliteDatabase {
         &self.pool
     }
 
+    /// Each order is paid from the funds that the address holds with the order's merchant. Once a merchant's order
+    /// cannot be paid, none of that merchant's later orders are paid either.
     async fn pay_orders_for_address_with_conn(
         &self,
         address: &TariAddress,
         orders: &[&Order],
         tx: &mut sqlx::SqliteConnection,
     ) -> Result<Option<MultiAccountPayment>, PaymentGatewayError> {
-        let balance = accounts::fetch_address_balance(address, tx).await?;
-        let mut remaining_credit = balance.current_balance();
-        trace!("🗃️ Address balance of {} is {remaining_credit}", address.to_base58());
+        let mut credit = HashMap::<&str, MicroTari>::new();
+        let mut unpayable = HashSet::<&str>::new();
         let mut paid_orders = Vec::with_capacity(orders.len());
         let mut settlements = Vec::with_capacity(orders.len());
         for &order in orders {
+            if order.quote_has_expired() {
+                debug!(
+                    "🗃️ The price quote for order [{}] has expired. It will be paid once it is re-priced",
+                    order.order_id
+                );
+                continue;
+            }
+            let merchant_id = order.merchant_id.as_str();
+            if unpayable.contains(merchant_id) {
+                continue;
+            }
+            let remaining_credit = match credit.get(merchant_id) {
+                Some(remaining) => *remaining,
+                None => {
+                    let balance = accounts::fetch_merchant_balance(address, merchant_id, tx).await?;
+                    trace!("🗃️ Balance of {} with {merchant_id} is {}", address.to_base58(), balance.current_balance());
+                    balance.current_balance()
+                },
+            };
             // We must be able to pay for the entire order, or no deal.
             trace!("🗃️ Checking if there's enough credit ({remaining_credit}) to pay for order [{}]", order.order_id);
             if order.total_price > remaining_credit {
-                break;
+                unpayable.insert(merchant_id);
+                continue;
             }
             trace!("🗃️ Order [{}] can be paid", order.order_id);
-            remaining_credit -= order.total_price;
+            credit.insert(merchant_id, remaining_credit - order.total_price);
             let settlement = NewSettlementJournalEntry {
                 order_id: order.order_id.clone(),
                 payment_address: SerializedTariAddress::from(address.clone()),
                 amount: order.total_price,
                 settlement_type: SettlementType::Single,
+                merchant_id: order.merchant_id.clone(),
             };
             let settlement = accounts::insert_settlement(settlement, tx).await?;
+            ledger::post(&NewLedgerTransaction::settlement(&settlement, &order.merchant_id), tx).await?;
             trace!("🗃️ Settlement journal entry created for order [{}] (id: {})", order.order_id, order.id);
             settlements.push(settlement);
             let updated_order = orders::update_order_status(order.id, OrderStatusType::Paid, tx).await?;
+            outbox::enqueue(&EventType::OrderPaid(OrderEvent::new(updated_order.clone())), tx).await?;
             debug!("🗃️ Order {} paid for during multi-account payment", order.id);
             paid_orders.push(updated_order);
         }
@@ -799,6 +1600,91 @@ impl SqliteDatabase {
         Ok(result)
     }
 
+    async fn fetch_refund_with_status(
+        id: i64,
+        status: RefundStatus,
+        conn: &mut SqliteConnection,
+    ) -> Result<Refund, PaymentGatewayError> {
+        let refund = refunds::fetch_refund(id, conn).await?.ok_or(PaymentGatewayError::RefundNotFound(id))?;
+        if refund.status != status {
+            return Err(PaymentGatewayError::InvalidRefund(format!(
+                "Refund {id} has status {} instead of '{status}'",
+                refund.status
+            )));
+        }
+        Ok(refund)
+    }
+
+    /// Moves the approval request on from the `current` status. Concurrent decisions on the same request both pass
+    /// [`ApprovalRequest::check_decision`], but only the first one gets to update the request.
+    async fn update_approval_request(
+        id: i64,
+        current: ApprovalStatus,
+        status: ApprovalStatus,
+        decided_by: Option<&TariAddress>,
+        reason: Option<&str>,
+        conn: &mut SqliteConnection,
+    ) -> Result<ApprovalRequest, PaymentGatewayError> {
+        approvals::update_approval_request(id, current, status, decided_by, reason, conn).await?.ok_or_else(|| {
+            PaymentGatewayError::InvalidApproval(format!(
+                "Approval request {id} is no longer '{current}', so it cannot be moved to '{status}'"
+            ))
+        })
+    }
+
+    async fn fetch_approval_with_status(
+        id: i64,
+        status: ApprovalStatus,
+        conn: &mut SqliteConnection,
+    ) -> Result<ApprovalRequest, PaymentGatewayError> {
+        let request = approvals::fetch_approval_request(id, conn)
+            .await?
+            .ok_or(PaymentGatewayError::ApprovalRequestNotFound(id))?;
+        if request.status != status {
+            return Err(PaymentGatewayError::InvalidApproval(format!(
+                "Approval request {id} has status {} instead of '{status}'",
+                request.status
+            )));
+        }
+        Ok(request)
+    }
+
+    /// Checks that `amount` can be refunded to `address`, either from what the address paid towards the order, or
+    /// from its current balance with `merchant_id`.
+    async fn check_refund(
+        address: &TariAddress,
+        order_id: Option<&OrderId>,
+        merchant_id: &str,
+        amount: MicroTari,
+        conn: &mut SqliteConnection,
+    ) -> Result<(), PaymentGatewayError> {
+        if amount <= MicroTari::from(0) {
+            return Err(PaymentGatewayError::InvalidRefund("The refund amount must be positive".into()));
+        }
+        let available = match order_id {
+            Some(order_id) => {
+                let order = fetch_order_by_order_id(order_id, conn)
+                    .await?
+                    .ok_or_else(|| PaymentGatewayError::OrderNotFound(order_id.clone()))?;
+                if order.status != OrderStatusType::Paid {
+                    return Err(PaymentGatewayError::InvalidRefund(format!(
+                        "Order {order_id} has status {} and cannot be refunded",
+                        order.status
+                    )));
+                }
+                refunds::refundable_for_order(order_id, address, conn).await?
+            },
+            None => accounts::fetch_merchant_balance(address, merchant_id, conn).await?.current_balance(),
+        };
+        if amount > available {
+            return Err(PaymentGatewayError::InvalidRefund(format!(
+                "{amount} exceeds the {available} that can be refunded to {}",
+                address.to_base58()
+            )));
+        }
+        Ok(())
+    }
+
     async fn fetch_order_by_id(
         id: &OrderId,
         strict_mode: bool,
@@ -825,6 +1711,8 @@ impl SqliteDatabase {
         }
         let order = orders::update_order_status(order.id, OrderStatusType::New, tx).await?;
         accounts::link_address_to_customer(address, &order.customer_id, tx).await?;
+        let event = EventType::OrderClaimed(OrderClaimedEvent::new(order.clone(), address.clone()));
+        outbox::enqueue(&event, tx).await?;
         info!("🗃️ Address {addr58} has been linked with customer id {}", order.customer_id);
         Ok(order)
     }
@@ -0,0 +1,1349 @@
+//! # Backend conformance suite
+//!
+//! Every backend must behave identically as far as the engine traits are concerned. The checks in this module are
+//! written against the traits only, and are run against each backend by the `backend_conformance` integration test.
+//! Each check expects a freshly created, empty database.
+//!
+//! When you add behaviour to a backend, add a check here rather than in a backend-specific test, so that the other
+//! backends (and in particular the [`crate::InMemoryDatabase`] reference model) are held to it too.
+use std::{net::IpAddr, str::FromStr, time::Duration as StdDuration};
+
+use chrono::{Duration, Utc};
+use tari_common_types::tari_address::TariAddress;
+use tpg_common::MicroTari;
+
+use crate::{
+    db_types::{
+        AdminActionFilter,
+        ApprovalOperation,
+        ApprovalStatus,
+        AuditOutcome,
+        CreditNote,
+        EntrySide,
+        HeldOrder,
+        LedgerAccount,
+        LedgerTransactionType,
+        NewAdminAction,
+        NewApprovalRequest,
+        NewAuthSession,
+        NewOrder,
+        NewPayment,
+        NewRefund,
+        NewWebhookSubscription,
+        Order,
+        OrderId,
+        OrderStatusType,
+        Permission,
+        RefundStatus,
+        Role,
+        RoleDefinition,
+        SerializedTariAddress,
+        SettlementType,
+        TransferStatus,
+        DEFAULT_MERCHANT,
+    },
+    events::{EventProducers, EventType, OrderEvent},
+    helpers::create_dummy_address_for_cust_id,
+    order_objects::OrderQueryFilter,
+    tpe_api::{
+        account_objects::Pagination,
+        exchange_objects::{ExchangeRate, QuoteExpiryAction, QuotePolicy},
+        export_api::ExportApi,
+        export_objects::{ExportError, ExportRecord, ExportRequest, PaymentRecord, ReportFormat, ReportType},
+        payment_objects::{ConfirmationPolicy, OverpaymentPolicy},
+        rate_limit_objects::RateLimit,
+        reconciliation_api::ReconciliationApi,
+        reconciliation_objects::WalletTransaction,
+    },
+    traits::{
+        AccountApiError,
+        AuditLog,
+        AuthApiError,
+        AuthManagement,
+        EventOutbox,
+        ExchangeRateError,
+        ExchangeRates,
+        NewWalletInfo,
+        OutboxError,
+        PaymentGatewayDatabase,
+        PaymentGatewayError,
+        RateLimitStore,
+        WalletAuth,
+        WalletAuthApiError,
+        WalletManagement,
+        WebhookError,
+        WebhookManagement,
+    },
+    OrderFlowApi,
+};
+
+fn address(name: &str) -> TariAddress {
+    create_dummy_address_for_cust_id(&format!("conformance-{name}"))
+}
+
+fn tari(amount: i64) -> MicroTari {
+    MicroTari::from_tari(amount)
+}
+
+fn new_order(order_id: &str, customer_id: &str, price: i64) -> NewOrder {
+    NewOrder::new(OrderId::new(order_id), customer_id.to_string(), tari(price))
+}
+
+async fn fetch_order<B: PaymentGatewayDatabase>(db: &B, order_id: &str) -> Order {
+    db.fetch_order_by_order_id(&OrderId::new(order_id)).await.unwrap().expect("Order should exist")
+}
+
+/// Inserting the same order twice returns the original order, and flags it as not new.
+pub async fn orders_are_inserted_idempotently<B: PaymentGatewayDatabase>(db: &B) {
+    let (order, is_new) = db.insert_order(new_order("oid-1", "alice", 100)).await.unwrap();
+    assert!(is_new);
+    assert_eq!(order.status, OrderStatusType::Unclaimed);
+    let (again, is_new) = db.insert_order(new_order("oid-1", "alice", 250)).await.unwrap();
+    assert!(!is_new);
+    assert_eq!(again.id, order.id);
+    assert_eq!(again.total_price, tari(100));
+}
+
+/// Orders can be found by their alt id, but an order_id match always takes precedence.
+pub async fn orders_can_be_found_by_alt_id<B: PaymentGatewayDatabase>(db: &B) {
+    let mut order = new_order("oid-1", "alice", 100);
+    order.alt_order_id = Some(OrderId::new("#1001"));
+    db.insert_order(order).await.unwrap();
+    let mut order = new_order("#1002", "bob", 100);
+    order.alt_order_id = Some(OrderId::new("oid-3"));
+    db.insert_order(order).await.unwrap();
+    db.insert_order(new_order("oid-3", "carol", 100)).await.unwrap();
+
+    let by_alt = db.fetch_order_by_alt_id(&OrderId::new("#1001")).await.unwrap().unwrap();
+    assert_eq!(by_alt.order_id.as_str(), "oid-1");
+    let either = db.fetch_order_by_id_or_alt(&OrderId::new("#1001")).await.unwrap().unwrap();
+    assert_eq!(either.order_id.as_str(), "oid-1");
+    let either = db.fetch_order_by_id_or_alt(&OrderId::new("oid-3")).await.unwrap().unwrap();
+    assert_eq!(either.customer_id, "carol");
+    assert!(db.fetch_order_by_id(&OrderId::new("#1001"), true).await.is_err());
+    assert!(db.fetch_order_by_id(&OrderId::new("#1001"), false).await.is_ok());
+    let query = OrderQueryFilter::default().with_customer_id("bob".to_string());
+    let orders = db.search_orders(query).await.unwrap();
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].order_id.as_str(), "#1002");
+}
+
+/// A confirmed payment that names an order claims it, pays for it, and leaves the change in the sender's balance.
+pub async fn confirmed_payment_pays_for_order<B: PaymentGatewayDatabase>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    api.process_new_order(new_order("oid-1", "alice", 50), false, true).await.unwrap();
+    let mut payment = NewPayment::new(address("a"), tari(75), "tx-1".into());
+    payment.order_id = Some(OrderId::new("oid-1"));
+    let payment = api.process_new_payment(payment, true).await.unwrap();
+    assert_eq!(payment.status, TransferStatus::Received);
+    assert_eq!(fetch_order(db, "oid-1").await.status, OrderStatusType::New);
+    assert_eq!(db.fetch_pending_payments_for_address(&address("a")).await.unwrap().len(), 1);
+
+    api.confirm_payment("tx-1".into(), true).await.unwrap();
+    assert_eq!(fetch_order(db, "oid-1").await.status, OrderStatusType::Paid);
+    assert!(db.fetch_pending_payments_for_address(&address("a")).await.unwrap().is_empty());
+    let balance = db.fetch_address_balance(&address("a")).await.unwrap();
+    assert_eq!(balance.total_confirmed(), tari(75));
+    assert_eq!(balance.total_paid(), tari(50));
+    assert_eq!(balance.current_balance(), tari(25));
+    let payments = db.fetch_payments_for_order(&OrderId::new("oid-1")).await.unwrap();
+    assert_eq!(payments.len(), 1);
+}
+
+/// An order that no single address can cover is settled from several addresses belonging to the same customer.
+pub async fn order_is_settled_from_multiple_addresses<B: PaymentGatewayDatabase>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    api.process_new_order(new_order("oid-1", "alice", 100), false, true).await.unwrap();
+    db.claim_order(&OrderId::new("oid-1"), &address("a"), true).await.unwrap();
+    db.claim_order(&OrderId::new("oid-1"), &address("b"), true).await.unwrap();
+    for (name, txid) in [("a", "tx-a"), ("b", "tx-b")] {
+        api.process_new_payment(NewPayment::new(address(name), tari(60), txid.into()), true).await.unwrap();
+        api.confirm_payment(txid.into(), true).await.unwrap();
+    }
+    // Neither payment could pay for the order in isolation
+    let order = fetch_order(db, "oid-1").await;
+    assert_eq!(order.status, OrderStatusType::New);
+
+    let payment = api.try_pay_order(&order, true).await.unwrap().expect("Order should be paid");
+    assert_eq!(payment.orders_paid.len(), 1);
+    assert_eq!(payment.orders_paid[0].status, OrderStatusType::Paid);
+    assert_eq!(payment.settlements.len(), 2);
+    assert!(payment.settlements.iter().all(|s| s.settlement_type == SettlementType::Multiple));
+    assert_eq!(payment.total_paid(), tari(100));
+    let balance = db.fetch_customer_balance("alice").await.unwrap();
+    assert_eq!(balance.total_confirmed(), tari(120));
+    assert_eq!(balance.current_balance(), tari(20));
+}
+
+/// Insufficient funds leave the order untouched and write no settlements.
+pub async fn insufficient_funds_do_not_settle<B: PaymentGatewayDatabase>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    api.process_new_order(new_order("oid-1", "alice", 100), false, true).await.unwrap();
+    db.claim_order(&OrderId::new("oid-1"), &address("a"), true).await.unwrap();
+    api.process_new_payment(NewPayment::new(address("a"), tari(40), "tx-1".into()), true).await.unwrap();
+    api.confirm_payment("tx-1".into(), true).await.unwrap();
+    let order = fetch_order(db, "oid-1").await;
+    let err = db.try_pay_order(&order, true).await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::AccountError(AccountApiError::InsufficientFunds)));
+    assert_eq!(fetch_order(db, "oid-1").await.status, OrderStatusType::New);
+    let history = db.history_for_address(&address("a")).await.unwrap();
+    assert!(history.settlements.is_empty());
+}
+
+/// Payments are unique by txid, and may only move out of the `Received` state.
+pub async fn payment_status_transitions<B: PaymentGatewayDatabase>(db: &B) {
+    db.process_new_payment(NewPayment::new(address("a"), tari(10), "tx-1".into()), true).await.unwrap();
+    let dup = db.process_new_payment(NewPayment::new(address("a"), tari(10), "tx-1".into()), true).await;
+    assert!(matches!(dup, Err(PaymentGatewayError::PaymentAlreadyExists(_))));
+    let missing = db.update_payment_status("tx-missing", TransferStatus::Confirmed).await;
+    assert!(matches!(missing, Err(PaymentGatewayError::PaymentStatusUpdateError(_))));
+    let noop = db.update_payment_status("tx-1", TransferStatus::Received).await;
+    assert!(matches!(noop, Err(PaymentGatewayError::PaymentModificationNoOp)));
+    let payment = db.update_payment_status("tx-1", TransferStatus::Confirmed).await.unwrap();
+    assert_eq!(payment.status, TransferStatus::Confirmed);
+    let err = db.update_payment_status("tx-1", TransferStatus::Cancelled).await;
+    assert!(matches!(err, Err(PaymentGatewayError::PaymentStatusUpdateError(_))));
+    assert_eq!(db.fetch_payment_by_tx_id("tx-1").await.unwrap().status, TransferStatus::Confirmed);
+    assert!(matches!(db.fetch_payment_by_tx_id("tx-2").await, Err(PaymentGatewayError::PaymentNotFound(_))));
+}
+
+/// Notifier confirmations don't say how deep a transaction is, so they are not applied to payments that the
+/// confirmation policy wants deeper than its default.
+pub async fn notified_confirmations_follow_policy<B: PaymentGatewayDatabase + EventOutbox>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    let policy = ConfirmationPolicy::new(3).with_threshold(tari(100), 6);
+    let deferred_from = policy.deeper_than_default_from();
+    api.process_new_payment(NewPayment::new(address("a"), tari(99), "tx-1".into()), true).await.unwrap();
+    api.process_new_payment(NewPayment::new(address("a"), tari(100), "tx-2".into()), true).await.unwrap();
+    let payment = api.confirm_notified_payment("tx-1".into(), deferred_from, true).await.unwrap();
+    assert_eq!(payment.map(|p| p.status), Some(TransferStatus::Confirmed));
+    assert!(api.confirm_notified_payment("tx-2".into(), deferred_from, true).await.unwrap().is_none());
+    assert_eq!(db.fetch_payment_by_tx_id("tx-2").await.unwrap().status, TransferStatus::Received);
+    // The wallet watcher still confirms it once it is deep enough
+    let payment = api.update_confirmations("tx-2", Some(10), 6, &policy, true).await.unwrap();
+    assert_eq!(payment.status, TransferStatus::Confirmed);
+    // Without thresholds, the notifier confirms every payment
+    api.process_new_payment(NewPayment::new(address("a"), tari(1000), "tx-3".into()), true).await.unwrap();
+    assert!(api.confirm_notified_payment("tx-3".into(), None, true).await.unwrap().is_some());
+}
+
+/// Payments are confirmed once they are deep enough for their amount. A confirmed payment that is lost in a reorg is
+/// reverted, and the most recent orders that it paid for are unpaid again until it is confirmed again.
+pub async fn reorged_payments_are_reverted<B: PaymentGatewayDatabase + EventOutbox>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    let policy = ConfirmationPolicy::new(3).with_threshold(tari(100), 6);
+    api.process_new_order(new_order("oid-1", "alice", 50), false, true).await.unwrap();
+    db.claim_order(&OrderId::new("oid-1"), &address("a"), true).await.unwrap();
+    api.process_new_payment(NewPayment::new(address("a"), tari(100), "tx-1".into()), true).await.unwrap();
+    let payment = api.update_confirmations("tx-1", Some(10), 3, &policy, true).await.unwrap();
+    assert_eq!(payment.status, TransferStatus::Received);
+    assert_eq!((payment.block_height, payment.confirmations), (Some(10), 3));
+    let payment = api.update_confirmations("tx-1", Some(10), 6, &policy, true).await.unwrap();
+    assert_eq!(payment.status, TransferStatus::Confirmed);
+    assert_eq!(fetch_order(db, "oid-1").await.status, OrderStatusType::Paid);
+    // Confirmed payments are only updated when their block changes
+    let payment = api.update_confirmations("tx-1", Some(10), 9, &policy, true).await.unwrap();
+    assert_eq!(payment.confirmations, 6);
+
+    api.process_new_order(new_order("oid-2", "alice", 30), false, true).await.unwrap();
+    let order = db.claim_order(&OrderId::new("oid-2"), &address("a"), true).await.unwrap();
+    api.try_pay_orders_from_address(&address("a"), &[&order]).await.unwrap().expect("Order should be paid");
+    api.process_new_payment(NewPayment::new(address("a"), tari(60), "tx-2".into()), true).await.unwrap();
+    api.update_confirmations("tx-2", Some(12), 3, &policy, true).await.unwrap();
+    assert_eq!(db.fetch_address_balance(&address("a")).await.unwrap().current_balance(), tari(80));
+
+    // tx-1 drops out of the chain. The remaining 60 XTR still covers oid-1, but not oid-2 as well.
+    let payment = api.update_confirmations("tx-1", None, 0, &policy, true).await.unwrap();
+    assert_eq!(payment.status, TransferStatus::Received);
+    assert_eq!((payment.block_height, payment.confirmations), (None, 0));
+    assert_eq!(fetch_order(db, "oid-1").await.status, OrderStatusType::Paid);
+    assert_eq!(fetch_order(db, "oid-2").await.status, OrderStatusType::New);
+    let balance = db.fetch_address_balance(&address("a")).await.unwrap();
+    assert_eq!(balance.total_paid(), tari(50));
+    assert_eq!(balance.current_balance(), tari(10));
+    let err = db.revert_payment("tx-1").await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::PaymentStatusUpdateError(_)));
+    assert!(matches!(db.revert_payment("tx-9").await, Err(PaymentGatewayError::PaymentNotFound(_))));
+    let names = db.fetch_due_events(100).await.unwrap().iter().map(|e| e.event_type()).collect::<Vec<_>>();
+    assert_eq!(names.iter().filter(|&&n| n == "PaymentReverted").count(), 1);
+
+    // The transaction is mined again in a later block
+    let payment = api.update_confirmations("tx-1", Some(14), 6, &policy, true).await.unwrap();
+    assert_eq!(payment.status, TransferStatus::Confirmed);
+    assert_eq!(fetch_order(db, "oid-2").await.status, OrderStatusType::Paid);
+    assert_eq!(db.fetch_address_balance(&address("a")).await.unwrap().current_balance(), tari(80));
+}
+
+/// Payments are reconciled against the wallet's history by txid, and missing payments can be imported.
+pub async fn payments_are_reconciled_with_wallet_history<B: PaymentGatewayDatabase>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    let reconciler = ReconciliationApi::new(db.clone(), EventProducers::default());
+    api.process_new_order(new_order("oid-1", "alice", 50), false, true).await.unwrap();
+    db.claim_order(&OrderId::new("oid-1"), &address("a"), true).await.unwrap();
+    for (txid, sender, amount) in [("tx-1", "a", 20), ("tx-2", "a", 10), ("tx-9", "b", 5)] {
+        api.process_new_payment(NewPayment::new(address(sender), tari(amount), txid.into()), true).await.unwrap();
+    }
+    api.confirm_payment("tx-1".into(), true).await.unwrap();
+    api.cancel_payment("tx-2".into()).await.unwrap();
+    // Credit notes are not on-chain payments, so they are not reconciled
+    api.issue_credit_note(CreditNote::new("bob".into(), tari(5)), true).await.unwrap();
+
+    let tx = |txid: &str, sender: &str, amount: i64| {
+        WalletTransaction::new(txid.into(), address(sender).into(), tari(amount))
+    };
+    let history =
+        vec![tx("tx-1", "a", 20), tx("tx-2", "a", 10), tx("tx-3", "a", 40).with_confirmed(true), tx("tx-4", "b", 7)];
+    let mut report = reconciler.reconcile(&history, None).await.unwrap();
+    assert_eq!(report.matched, 1);
+    assert_eq!(report.missing.iter().map(|t| t.txid.as_str()).collect::<Vec<_>>(), vec!["tx-3", "tx-4"]);
+    assert_eq!(report.extra.iter().map(|p| p.txid.as_str()).collect::<Vec<_>>(), vec!["tx-9"]);
+    assert_eq!(report.mismatched.len(), 1);
+    assert_eq!(report.mismatched[0].payment.txid, "tx-2");
+
+    // Transactions that the wallet doesn't know about are not imported, whatever the report says
+    report.missing.push(tx("tx-fake", "a", 1000).with_confirmed(true));
+    reconciler.import_missing(&history, &mut report, true, |_| {}).await.unwrap();
+    assert_eq!(report.imported.len(), 2);
+    assert_eq!(report.import_failures.iter().map(|f| f.txid.as_str()).collect::<Vec<_>>(), vec!["tx-fake"]);
+    assert!(db.fetch_payment_by_tx_id("tx-fake").await.is_err());
+    // Imported payments are only received, even if the wallet has confirmed them
+    assert_eq!(db.fetch_payment_by_tx_id("tx-3").await.unwrap().status, TransferStatus::Received);
+    assert_eq!(db.fetch_payment_by_tx_id("tx-4").await.unwrap().status, TransferStatus::Received);
+    assert_ne!(fetch_order(db, "oid-1").await.status, OrderStatusType::Paid);
+    api.confirm_payment("tx-3".into(), true).await.unwrap();
+    assert_eq!(fetch_order(db, "oid-1").await.status, OrderStatusType::Paid);
+    // Importing again fails, since the payments now exist
+    report.missing.pop();
+    report.import_failures.clear();
+    reconciler.import_missing(&history, &mut report, true, |_| {}).await.unwrap();
+    assert_eq!(report.import_failures.len(), 2);
+
+    let report = reconciler.reconcile(&history, None).await.unwrap();
+    assert_eq!((report.matched, report.missing.len()), (3, 0));
+}
+
+/// Credit notes credit the customer's dummy wallet, and pay for any outstanding orders.
+pub async fn credit_notes_pay_for_orders<B: PaymentGatewayDatabase>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    api.process_new_order(new_order("oid-1", "alice", 30), false, true).await.unwrap();
+    let note = CreditNote::new("alice".into(), tari(50)).with_reason("Goodwill");
+    let paid = api.issue_credit_note(note, true).await.unwrap().expect("The order should be paid");
+    assert_eq!(paid.orders_paid.len(), 1);
+    assert_eq!(fetch_order(db, "oid-1").await.status, OrderStatusType::Paid);
+    let dummy = create_dummy_address_for_cust_id("alice");
+    assert_eq!(db.fetch_customer_ids_for_address(&dummy).await.unwrap(), vec!["alice".to_string()]);
+    assert_eq!(db.fetch_address_balance(&dummy).await.unwrap().current_balance(), tari(20));
+}
+
+/// Admins can mark new or unclaimed orders as paid, but nothing else.
+pub async fn orders_can_be_marked_as_paid<B: PaymentGatewayDatabase>(db: &B) {
+    let (order, _) = db.insert_order(new_order("oid-1", "alice", 30)).await.unwrap();
+    let paid = db.mark_new_or_unclaimed_order_as_paid(order, "Paid in cash").await.unwrap();
+    assert_eq!(paid.status, OrderStatusType::Paid);
+    let err = db.mark_new_or_unclaimed_order_as_paid(paid, "Again").await;
+    assert!(matches!(err, Err(PaymentGatewayError::OrderModificationForbidden)));
+    let customer = db.fetch_customer_balance("alice").await.unwrap();
+    assert_eq!(customer.total_paid(), tari(30));
+    assert_eq!(customer.current_balance(), tari(0));
+}
+
+/// Cancelled orders cannot be cancelled again, but can be reset to `New`.
+pub async fn orders_can_be_cancelled_and_reset<B: PaymentGatewayDatabase>(db: &B) {
+    db.insert_order(new_order("oid-1", "alice", 30)).await.unwrap();
+    let id = OrderId::new("oid-1");
+    let order = db.cancel_or_expire_order(&id, OrderStatusType::Cancelled, "Out of stock", true).await.unwrap();
+    assert_eq!(order.status, OrderStatusType::Cancelled);
+    assert_eq!(order.memo.as_deref(), Some("Out of stock"));
+    let err = db.cancel_or_expire_order(&id, OrderStatusType::Cancelled, "Again", true).await;
+    assert!(matches!(err, Err(PaymentGatewayError::OrderModificationForbidden)));
+    let changed = db.reset_order(&id).await.unwrap();
+    assert_eq!(changed.old_order.status, OrderStatusType::Cancelled);
+    assert_eq!(changed.new_order.status, OrderStatusType::New);
+    assert!(matches!(db.reset_order(&id).await, Err(PaymentGatewayError::OrderModificationForbidden)));
+}
+
+/// Moving an order to a customer with credit pays for the order immediately.
+pub async fn reassigned_orders_are_paid_by_new_customer<B: PaymentGatewayDatabase>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    api.issue_credit_note(CreditNote::new("bob".into(), tari(100)), true).await.unwrap();
+    api.process_new_order(new_order("oid-1", "alice", 50), false, true).await.unwrap();
+    db.claim_order(&OrderId::new("oid-1"), &address("a"), true).await.unwrap();
+    let id = OrderId::new("oid-1");
+    let err = db.modify_customer_id_for_order(&id, "alice", true).await;
+    assert!(matches!(err, Err(PaymentGatewayError::OrderModificationNoOp)));
+    let moved = db.modify_customer_id_for_order(&id, "bob", true).await.unwrap();
+    assert_eq!(moved.orders.old_order.customer_id, "alice");
+    assert_eq!(moved.orders.new_order.customer_id, "bob");
+    assert_eq!(moved.orders.new_order.status, OrderStatusType::Paid);
+    assert_eq!(moved.settlements.len(), 1);
+    assert_eq!(moved.total_paid(), tari(50));
+    let err = db.modify_customer_id_for_order(&id, "carol", true).await;
+    assert!(matches!(err, Err(PaymentGatewayError::OrderModificationForbidden)));
+}
+
+/// Prices can only be changed on `New` orders. Memos can be changed at any time.
+pub async fn order_price_and_memo_updates<B: PaymentGatewayDatabase>(db: &B) {
+    db.insert_order(new_order("oid-1", "alice", 30)).await.unwrap();
+    let id = OrderId::new("oid-1");
+    let err = db.modify_total_price_for_order(&id, tari(40), true).await;
+    assert!(matches!(err, Err(PaymentGatewayError::OrderModificationForbidden)));
+    db.claim_order(&id, &address("a"), true).await.unwrap();
+    let err = db.modify_total_price_for_order(&id, tari(30), true).await;
+    assert!(matches!(err, Err(PaymentGatewayError::OrderModificationNoOp)));
+    let changed = db.modify_total_price_for_order(&id, tari(40), true).await.unwrap();
+    assert_eq!(changed.old_order.total_price, tari(30));
+    assert_eq!(changed.new_order.total_price, tari(40));
+    let order = db.modify_memo_for_order(&id, "Gift wrap, please").await.unwrap();
+    assert_eq!(order.memo.as_deref(), Some("Gift wrap, please"));
+    assert_eq!(order.total_price, tari(40));
+    let err = db.modify_memo_for_order(&OrderId::new("oid-missing"), "memo").await;
+    assert!(err.is_err());
+}
+
+/// Unclaimed and unpaid orders past their deadlines are expired. Paid orders are never expired.
+pub async fn old_orders_expire<B: PaymentGatewayDatabase>(db: &B) {
+    db.insert_order(new_order("oid-1", "alice", 30)).await.unwrap();
+    db.insert_order(new_order("oid-2", "alice", 30)).await.unwrap();
+    db.claim_order(&OrderId::new("oid-2"), &address("a"), true).await.unwrap();
+    let (paid, _) = db.insert_order(new_order("oid-3", "alice", 30)).await.unwrap();
+    db.mark_new_or_unclaimed_order_as_paid(paid, "Paid in cash").await.unwrap();
+    // A negative limit means that every order qualifies, without having to wait
+    let expired = db.expire_old_orders(Duration::seconds(-1), Duration::seconds(-1)).await.unwrap();
+    assert_eq!(expired.unclaimed_count(), 1);
+    assert_eq!(expired.unclaimed[0].order_id.as_str(), "oid-1");
+    assert_eq!(expired.unpaid_count(), 1);
+    assert_eq!(expired.unpaid[0].order_id.as_str(), "oid-2");
+    assert_eq!(fetch_order(db, "oid-3").await.status, OrderStatusType::Paid);
+    let expired = db.expire_old_orders(Duration::seconds(-1), Duration::seconds(-1)).await.unwrap();
+    assert_eq!(expired.total_count(), 0);
+}
+
+/// The account queries agree on customers, addresses, creditors and histories.
+pub async fn account_queries<B: PaymentGatewayDatabase>(db: &B) {
+    for (oid, cid, price) in
+        [("oid-1", "carol", 10), ("oid-2", "alice", 20), ("oid-3", "bob", 30), ("oid-4", "alice", 5)]
+    {
+        db.insert_order(new_order(oid, cid, price)).await.unwrap();
+    }
+    db.claim_order(&OrderId::new("oid-2"), &address("a"), true).await.unwrap();
+    db.claim_order(&OrderId::new("oid-4"), &address("a"), true).await.unwrap();
+    db.process_new_payment(NewPayment::new(address("a"), tari(5), "tx-1".into()), true).await.unwrap();
+    db.process_new_payment(NewPayment::new(address("b"), tari(5), "tx-2".into()), true).await.unwrap();
+
+    let all = Pagination { offset: None, count: None };
+    assert_eq!(db.fetch_customer_ids(&all).await.unwrap(), vec!["alice", "bob", "carol"]);
+    let page = Pagination { offset: Some(1), count: Some(1) };
+    assert_eq!(db.fetch_customer_ids(&page).await.unwrap(), vec!["bob"]);
+    let addresses = db.fetch_addresses(&all).await.unwrap();
+    assert_eq!(addresses.len(), 2);
+    assert!(addresses.contains(&address("a")) && addresses.contains(&address("b")));
+    assert_eq!(db.fetch_customer_ids_for_address(&address("a")).await.unwrap(), vec!["alice"]);
+    assert_eq!(db.fetch_orders_for_address(&address("a")).await.unwrap().len(), 2);
+    assert_eq!(db.fetch_payable_orders_for_address(&address("a")).await.unwrap().len(), 2);
+    assert_eq!(db.fetch_payments_for_address(&address("b")).await.unwrap().len(), 1);
+
+    let creditors = db.creditors().await.unwrap();
+    assert_eq!(creditors.len(), 1);
+    assert_eq!(creditors[0].customer_id, "alice");
+    assert_eq!(creditors[0].total_orders, tari(25));
+    let balance = db.fetch_customer_order_balance("alice").await.unwrap();
+    assert_eq!(balance.total_current, tari(25));
+    assert_eq!(balance.total_paid, tari(0));
+    let history = db.history_for_customer("alice").await.unwrap();
+    assert_eq!(history.orders.len(), 2);
+    // Unconfirmed payments don't count towards the balance
+    assert_eq!(history.balance.current_balance(), tari(0));
+}
+
+/// Login nonces must strictly increase, and role checks respect the default `User` role.
+pub async fn auth_nonces_and_roles<B: AuthManagement>(db: &B) {
+    let user = address("a");
+    assert!(!db.check_auth_account_exists(&user).await.unwrap());
+    db.upsert_nonce_for_address(&user, 1).await.unwrap();
+    assert!(db.check_auth_account_exists(&user).await.unwrap());
+    assert!(matches!(db.upsert_nonce_for_address(&user, 1).await, Err(AuthApiError::InvalidNonce)));
+    db.upsert_nonce_for_address(&user, 5).await.unwrap();
+    assert!(matches!(db.upsert_nonce_for_address(&user, 4).await, Err(AuthApiError::InvalidNonce)));
+
+    assert_eq!(db.fetch_roles_for_address(&user).await.unwrap(), vec![Role::User]);
+    db.check_address_has_roles(&user, &[]).await.unwrap();
+    db.check_address_has_roles(&user, &[Role::User]).await.unwrap();
+    db.assign_roles(&user, &[Role::ReadAll, Role::Write]).await.unwrap();
+    db.check_address_has_roles(&user, &[Role::User, Role::ReadAll, Role::Write]).await.unwrap();
+    let err = db.check_address_has_roles(&user, &[Role::ReadAll, Role::SuperAdmin]).await;
+    assert!(matches!(err, Err(AuthApiError::RoleNotAllowed(1))));
+    assert_eq!(db.remove_roles(&user, &[Role::Write, Role::SuperAdmin]).await.unwrap(), 1);
+    let roles = db.fetch_roles_for_address(&user).await.unwrap();
+    assert_eq!(roles.len(), 2);
+    assert!(roles.contains(&Role::User) && roles.contains(&Role::ReadAll));
+}
+
+/// Wallets can be registered and deregistered, and their nonces must strictly increase.
+pub async fn wallet_management<B: WalletAuth + WalletManagement>(db: &B) {
+    let wallet = address("wallet");
+    let ip_address = IpAddr::from_str("192.168.1.100").unwrap();
+    let address = SerializedTariAddress::from(&wallet);
+    let info = NewWalletInfo { address, ip_address, initial_nonce: Some(3), merchant_id: "merchant-a".into() };
+    db.register_wallet(info.clone()).await.unwrap();
+    assert!(db.register_wallet(info).await.is_err(), "Wallets cannot be registered twice");
+    let fetched = db.get_wallet_info(&wallet).await.unwrap();
+    assert_eq!(fetched.ip_address, ip_address);
+    assert_eq!(fetched.merchant_id, "merchant-a");
+    assert_eq!(fetched.last_nonce, 3);
+    db.update_wallet_nonce(&wallet, 4).await.unwrap();
+    assert!(matches!(db.update_wallet_nonce(&wallet, 4).await, Err(WalletAuthApiError::InvalidNonce)));
+    let unknown = address("unknown");
+    assert!(matches!(db.get_wallet_info(&unknown).await, Err(WalletAuthApiError::WalletNotFound)));
+    assert!(matches!(db.update_wallet_nonce(&unknown, 1).await, Err(WalletAuthApiError::WalletNotFound)));
+    assert_eq!(db.fetch_authorized_wallets().await.unwrap().len(), 1);
+    db.deregister_wallet(&wallet).await.unwrap();
+    assert!(db.deregister_wallet(&wallet).await.is_err());
+    assert!(db.fetch_authorized_wallets().await.unwrap().is_empty());
+}
+
+/// Exchange rates are stored per currency.
+pub async fn exchange_rates<B: ExchangeRates>(db: &B) {
+    assert!(matches!(db.fetch_last_rate("USD").await, Err(ExchangeRateError::RateDoesNotExist(_))));
+    db.set_exchange_rate(&ExchangeRate::new("USD".into(), MicroTari::from(250), None)).await.unwrap();
+    db.set_exchange_rate(&ExchangeRate::new("EUR".into(), MicroTari::from(300), None)).await.unwrap();
+    assert_eq!(db.fetch_last_rate("USD").await.unwrap().rate, MicroTari::from(250));
+    assert_eq!(db.fetch_last_rate("EUR").await.unwrap().rate, MicroTari::from(300));
+    let usd = db.fetch_last_rate("USD").await.unwrap();
+    assert_eq!(db.fetch_rate_by_id(usd.id).await.unwrap().base_currency, "USD");
+    assert!(matches!(db.fetch_rate_by_id(-1).await, Err(ExchangeRateError::RateDoesNotExist(_))));
+
+    // Confirming a rate leaves it in place in the rate history
+    db.confirm_exchange_rate(&usd).await.unwrap();
+    let confirmed = db.fetch_last_rate("USD").await.unwrap();
+    assert_eq!(confirmed.id, usd.id);
+    assert_eq!(confirmed.updated_at, usd.updated_at);
+    assert!(confirmed.confirmed_at >= usd.confirmed_at);
+    let missing = ExchangeRate { id: -1, ..usd };
+    assert!(matches!(db.confirm_exchange_rate(&missing).await, Err(ExchangeRateError::RateDoesNotExist(_))));
+}
+
+/// Rate history is returned oldest first, filtered by currency and time range, and paginated.
+pub async fn exchange_rate_history<B: ExchangeRates>(db: &B) {
+    let later = Utc::now() + Duration::minutes(1);
+    let earlier = Utc::now() - Duration::hours(1);
+    assert!(matches!(db.fetch_rate_at("USD", later).await, Err(ExchangeRateError::RateDoesNotExist(_))));
+    for rate in [100, 200, 300] {
+        db.set_exchange_rate(&ExchangeRate::new("USD".into(), MicroTari::from(rate), None)).await.unwrap();
+    }
+    db.set_exchange_rate(&ExchangeRate::new("EUR".into(), MicroTari::from(400), None)).await.unwrap();
+    assert_eq!(db.fetch_rate_at("USD", later).await.unwrap().rate, MicroTari::from(300));
+    assert!(matches!(db.fetch_rate_at("USD", earlier).await, Err(ExchangeRateError::RateDoesNotExist(_))));
+
+    let all = Pagination { offset: None, count: None };
+    let rates = |history: Vec<ExchangeRate>| history.into_iter().map(|r| r.rate.value()).collect::<Vec<_>>();
+    let history = db.fetch_rate_history(DEFAULT_MERCHANT, "USD", None, None, &all).await.unwrap();
+    assert_eq!(rates(history), vec![100, 200, 300]);
+    let history = db.fetch_rate_history(DEFAULT_MERCHANT, "USD", Some(earlier), Some(later), &all).await.unwrap();
+    assert_eq!(history.len(), 3);
+    assert!(db.fetch_rate_history(DEFAULT_MERCHANT, "USD", Some(later), None, &all).await.unwrap().is_empty());
+    assert!(db.fetch_rate_history(DEFAULT_MERCHANT, "USD", None, Some(earlier), &all).await.unwrap().is_empty());
+    let page = Pagination { offset: Some(1), count: Some(1) };
+    assert_eq!(rates(db.fetch_rate_history(DEFAULT_MERCHANT, "USD", None, None, &page).await.unwrap()), vec![200]);
+    assert_eq!(rates(db.fetch_rate_history(DEFAULT_MERCHANT, "EUR", None, None, &all).await.unwrap()), vec![400]);
+
+    let latest = db.fetch_last_rate("USD").await.unwrap();
+    let previous = db.fetch_previous_rate(&latest).await.unwrap().expect("There should be a previous rate");
+    assert_eq!(previous.rate, MicroTari::from(200));
+    let first = db.fetch_previous_rate(&previous).await.unwrap().expect("There should be a first rate");
+    assert!(db.fetch_previous_rate(&first).await.unwrap().is_none());
+    let eur = db.fetch_last_rate("EUR").await.unwrap();
+    assert!(db.fetch_previous_rate(&eur).await.unwrap().is_none());
+}
+
+/// Orders cannot be paid once their exchange rate quote has expired. They are re-priced at the current rate, or
+/// expired, depending on the quote policy.
+pub async fn expired_quotes_are_repriced<B: PaymentGatewayDatabase + ExchangeRates + EventOutbox>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    db.set_exchange_rate(&ExchangeRate::new("USD".into(), tari(50), None)).await.unwrap();
+    let quoted = db.fetch_last_rate("USD").await.unwrap();
+    let usd_order = |order_id: &str, price: i64, expires_in: Duration| {
+        let mut order = new_order(order_id, "alice", price).with_quote(quoted.id, Utc::now() + expires_in);
+        order.currency = "USD".into();
+        order
+    };
+    api.process_new_order(usd_order("oid-1", 500, Duration::minutes(-1)), false, true).await.unwrap();
+    api.process_new_order(usd_order("oid-2", 5000, Duration::hours(1)), false, true).await.unwrap();
+    let mut payment = NewPayment::new(address("a"), tari(600), "tx-1".into());
+    payment.order_id = Some(OrderId::new("oid-1"));
+    api.process_new_payment(payment, true).await.unwrap();
+    api.confirm_payment("tx-1".into(), true).await.unwrap();
+    let order = fetch_order(db, "oid-1").await;
+    assert_eq!(order.status, OrderStatusType::New);
+    assert_eq!(order.rate_id, Some(quoted.id));
+    let expired = db.fetch_orders_with_expired_quotes().await.unwrap();
+    assert_eq!(expired.iter().map(|o| o.order_id.as_str()).collect::<Vec<_>>(), vec!["oid-1"]);
+    let err = db.try_pay_order(&order, true).await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::QuoteExpired(_)));
+
+    // Tari has fallen, so the order now costs more, but the payment still covers it
+    db.set_exchange_rate(&ExchangeRate::new("USD".into(), tari(60), None)).await.unwrap();
+    let rate = db.fetch_last_rate("USD").await.unwrap();
+    let reprice = QuotePolicy::new(Duration::hours(1), QuoteExpiryAction::Reprice);
+    let updated = api.process_expired_quotes(&reprice, true).await.unwrap();
+    assert_eq!(updated.len(), 1);
+    let order = fetch_order(db, "oid-1").await;
+    assert_eq!(order.status, OrderStatusType::Paid);
+    assert_eq!(order.total_price, tari(600));
+    assert_eq!(order.rate_id, Some(rate.id));
+    assert!(!order.quote_has_expired());
+    assert!(db.fetch_orders_with_expired_quotes().await.unwrap().is_empty());
+    let names = db.fetch_due_events(100).await.unwrap().iter().map(|e| e.event_type()).collect::<Vec<_>>();
+    assert!(names.contains(&"OrderModified"));
+
+    api.process_new_order(usd_order("oid-3", 500, Duration::minutes(-1)), false, true).await.unwrap();
+    let expire = QuotePolicy::new(Duration::hours(1), QuoteExpiryAction::Expire);
+    let updated = api.process_expired_quotes(&expire, true).await.unwrap();
+    assert_eq!(updated.len(), 1);
+    assert_eq!(fetch_order(db, "oid-3").await.status, OrderStatusType::Expired);
+    assert_ne!(fetch_order(db, "oid-2").await.status, OrderStatusType::Expired);
+
+    // A copy of the order taken before its quote expired cannot be used to pay for it at the old price
+    let mut order = new_order("oid-4", "bob", 100).with_quote(rate.id, Utc::now() + Duration::hours(1));
+    order.currency = "USD".into();
+    api.process_new_order(order, false, true).await.unwrap();
+    let stale = fetch_order(db, "oid-4").await;
+    db.requote_order(&OrderId::new("oid-4"), tari(100), rate.id, Utc::now() - Duration::minutes(1)).await.unwrap();
+    let mut payment = NewPayment::new(address("b"), tari(100), "tx-2".into());
+    payment.order_id = Some(OrderId::new("oid-4"));
+    api.process_new_payment(payment, true).await.unwrap();
+    api.confirm_payment("tx-2".into(), true).await.unwrap();
+    assert_eq!(fetch_order(db, "oid-4").await.status, OrderStatusType::New);
+    let err = db.try_pay_order(&stale, true).await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::QuoteExpired(_)));
+    assert!(api.try_pay_order(&stale, true).await.unwrap().is_none());
+    assert_eq!(fetch_order(db, "oid-4").await.status, OrderStatusType::New);
+}
+
+/// Held orders stay out of the orders table until they are released, after which they are processed like any other new
+/// order.
+pub async fn held_orders_are_released<B: PaymentGatewayDatabase + EventOutbox>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    let usd_order = |order_id: &str| {
+        let mut order = new_order(order_id, "alice", 0);
+        order.currency = "USD".into();
+        order.original_price = Some("12.50".into());
+        order
+    };
+    let held = api.hold_order(&usd_order("oid-1"), "Stale rate").await.unwrap();
+    assert_eq!(held.original_price, "12.50");
+    assert_eq!(held.reason, "Stale rate");
+    assert!(!held.is_released());
+    api.hold_order(&usd_order("oid-2"), "Stale rate").await.unwrap();
+    let err = api.hold_order(&usd_order("oid-1"), "Stale rate").await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::OrderAlreadyExists(_)));
+    db.insert_order(new_order("oid-3", "bob", 100)).await.unwrap();
+    let err = db.hold_order(&usd_order("oid-3"), "Stale rate").await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::OrderAlreadyExists(_)));
+    assert!(db.fetch_order_by_order_id(&OrderId::new("oid-1")).await.unwrap().is_none());
+
+    let all = Pagination { offset: None, count: None };
+    let ids = |orders: Vec<HeldOrder>| orders.into_iter().map(|o| o.order_id.to_string()).collect::<Vec<_>>();
+    assert_eq!(ids(db.fetch_held_orders(&all).await.unwrap()), vec!["oid-1", "oid-2"]);
+    let page = Pagination { offset: Some(1), count: Some(1) };
+    assert_eq!(ids(db.fetch_held_orders(&page).await.unwrap()), vec!["oid-2"]);
+
+    let mut priced = NewOrder::from(held);
+    priced.total_price = tari(625);
+    let order = api.release_held_order(priced.clone(), false, true).await.unwrap();
+    assert_eq!(order.total_price, tari(625));
+    assert_eq!(order.original_price.as_deref(), Some("12.50"));
+    assert_eq!(order.currency, "USD");
+    assert_eq!(ids(db.fetch_held_orders(&all).await.unwrap()), vec!["oid-2"]);
+    assert!(db.fetch_held_order(&OrderId::new("oid-1")).await.unwrap().unwrap().is_released());
+    let err = api.release_held_order(priced, false, true).await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::OrderNotHeld(_)));
+    let err = db.mark_held_order_released(&OrderId::new("oid-9")).await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::OrderNotHeld(_)));
+    assert!(db.fetch_held_order(&OrderId::new("oid-9")).await.unwrap().is_none());
+
+    let names = db.fetch_due_events(100).await.unwrap().iter().map(|e| e.event_type()).collect::<Vec<_>>();
+    assert_eq!(names.iter().filter(|&&n| n == "OrderHeld").count(), 2);
+}
+
+/// Orders remember the merchant that created them, and roles can be granted for a single merchant.
+pub async fn merchants_scope_orders_and_roles<B: PaymentGatewayDatabase + AuthManagement>(db: &B) {
+    db.insert_order(new_order("oid-1", "alice", 100)).await.unwrap();
+    db.insert_order(new_order("oid-2", "alice", 100).with_merchant("acme")).await.unwrap();
+    let held = db.hold_order(&new_order("oid-3", "bob", 0).with_merchant("acme"), "Stale rate").await.unwrap();
+    assert_eq!(held.merchant_id, "acme");
+    assert_eq!(NewOrder::from(held).merchant_id, "acme");
+    assert_eq!(fetch_order(db, "oid-1").await.merchant_id, DEFAULT_MERCHANT);
+    assert_eq!(fetch_order(db, "oid-2").await.merchant_id, "acme");
+    let query = OrderQueryFilter::default().with_merchant_id("acme");
+    let orders = db.search_orders(query).await.unwrap();
+    assert_eq!(orders.iter().map(|o| o.order_id.as_str()).collect::<Vec<_>>(), vec!["oid-2"]);
+    let query = OrderQueryFilter::default().with_merchant_id(DEFAULT_MERCHANT).with_customer_id("alice".into());
+    assert_eq!(db.search_orders(query).await.unwrap().len(), 1);
+
+    let admin = address("admin");
+    db.assign_roles(&admin, &[Role::ReadAll]).await.unwrap();
+    db.assign_merchant_roles(&admin, "acme", &[Role::Write]).await.unwrap();
+    // Assigning the same role twice is not an error
+    db.assign_merchant_roles(&admin, "acme", &[Role::Write]).await.unwrap();
+    assert_eq!(db.fetch_merchant_roles_for_address(&admin, "acme").await.unwrap(), vec![Role::Write]);
+    assert!(db.fetch_merchant_roles_for_address(&admin, "other").await.unwrap().is_empty());
+    db.check_address_has_merchant_roles(&admin, "acme", &[Role::User, Role::ReadAll, Role::Write]).await.unwrap();
+    let err = db.check_address_has_merchant_roles(&admin, "other", &[Role::ReadAll, Role::Write]).await;
+    assert!(matches!(err, Err(AuthApiError::RoleNotAllowed(1))));
+    // Merchant roles do not leak into the instance-wide roles
+    assert!(matches!(db.check_address_has_roles(&admin, &[Role::Write]).await, Err(AuthApiError::RoleNotAllowed(1))));
+    assert_eq!(db.remove_merchant_roles(&admin, "acme", &[Role::Write, Role::SuperAdmin]).await.unwrap(), 1);
+    assert!(db.fetch_merchant_roles_for_address(&admin, "acme").await.unwrap().is_empty());
+}
+
+/// Funds can only be spent on the orders of the merchant that received them, and merchants may set their own exchange
+/// rates.
+pub async fn merchants_scope_payments_and_rates<B: PaymentGatewayDatabase + ExchangeRates>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    let alice = address("alice");
+    api.process_new_order(new_order("oid-1", "alice", 40).with_merchant("acme"), false, true).await.unwrap();
+    api.process_new_order(new_order("oid-2", "alice", 100), false, true).await.unwrap();
+    let mut payment = NewPayment::new(alice.clone(), tari(150), "tx-1".into());
+    payment.order_id = Some(OrderId::new("oid-2"));
+    api.process_new_payment(payment, true).await.unwrap();
+    api.confirm_payment("tx-1".into(), true).await.unwrap();
+    assert_eq!(fetch_order(db, "oid-2").await.status, OrderStatusType::Paid);
+    // Alice has 50 Tari left, but it was paid to the default merchant, so it cannot pay for acme's order
+    assert_ne!(fetch_order(db, "oid-1").await.status, OrderStatusType::Paid);
+
+    let mut payment = NewPayment::new(alice.clone(), tari(60), "tx-2".into()).with_merchant("acme");
+    payment.order_id = Some(OrderId::new("oid-1"));
+    let payment = api.process_new_payment(payment, true).await.unwrap();
+    assert_eq!(payment.merchant_id, "acme");
+    api.confirm_payment("tx-2".into(), true).await.unwrap();
+    assert_eq!(fetch_order(db, "oid-1").await.status, OrderStatusType::Paid);
+    assert_eq!(db.fetch_address_balance(&alice).await.unwrap().current_balance(), tari(70));
+    let mut idle = db
+        .fetch_idle_balances(Duration::zero())
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|b| (b.merchant_id().map(String::from), b.current_balance()))
+        .collect::<Vec<_>>();
+    idle.sort();
+    assert_eq!(idle, vec![(Some("acme".to_string()), tari(20)), (Some(DEFAULT_MERCHANT.to_string()), tari(50))]);
+    // Refunds of an unspent balance come out of the funds held with the refund's merchant
+    let refund = NewRefund::new(alice.clone(), tari(30), "Overpaid".into()).with_merchant("acme");
+    assert!(matches!(db.request_refund(refund).await, Err(PaymentGatewayError::InvalidRefund(_))));
+    let refund = NewRefund::new(alice.clone(), tari(20), "Overpaid".into()).with_merchant("acme");
+    assert_eq!(db.request_refund(refund).await.unwrap().merchant_id, "acme");
+
+    // Merchants without a rate of their own use the default merchant's rate
+    db.set_exchange_rate(&ExchangeRate::new("USD".into(), MicroTari::from(250), None)).await.unwrap();
+    let rate = db.fetch_merchant_rate("acme", "USD").await.unwrap();
+    assert_eq!((rate.rate, rate.merchant_id.as_str()), (MicroTari::from(250), DEFAULT_MERCHANT));
+    db.set_exchange_rate(&ExchangeRate::new("USD".into(), MicroTari::from(300), None).for_merchant("acme"))
+        .await
+        .unwrap();
+    let rate = db.fetch_merchant_rate("acme", "USD").await.unwrap();
+    assert_eq!((rate.rate, rate.merchant_id.as_str()), (MicroTari::from(300), "acme"));
+    assert_eq!(db.fetch_last_rate("USD").await.unwrap().rate, MicroTari::from(250));
+    assert!(db.fetch_previous_rate(&rate).await.unwrap().is_none());
+    let all = Pagination { offset: None, count: None };
+    assert_eq!(db.fetch_rate_history("acme", "USD", None, None, &all).await.unwrap().len(), 1);
+    assert_eq!(db.fetch_rate_history(DEFAULT_MERCHANT, "USD", None, None, &all).await.unwrap().len(), 1);
+}
+
+/// The built-in roles come with their default permissions. Custom roles can be defined, assigned and deleted, but the
+/// built-in roles cannot be deleted and the `super_admin` role cannot be changed.
+pub async fn roles_can_be_defined_and_deleted<B: AuthManagement>(db: &B) {
+    assert_eq!(db.fetch_role_definitions().await.unwrap(), RoleDefinition::built_in());
+
+    let support = Role::from_str("support").unwrap();
+    let alice = address("alice");
+    // Custom roles must be defined before they can be assigned
+    assert!(matches!(db.assign_roles(&alice, &[support.clone()]).await, Err(AuthApiError::RoleNotFound)));
+    let role = RoleDefinition::new(support.clone(), &[Permission::OrdersCancel, Permission::CreditIssue]);
+    db.save_role(&role).await.unwrap();
+    db.assign_roles(&alice, &[support.clone()]).await.unwrap();
+    db.assign_merchant_roles(&alice, "acme", &[support.clone()]).await.unwrap();
+    db.check_address_has_roles(&alice, &[Role::User, support.clone()]).await.unwrap();
+    // Saving a role again replaces its permissions
+    let role = RoleDefinition::new(support.clone(), &[Permission::OrdersCancel, Permission::OrdersRead]);
+    db.save_role(&role).await.unwrap();
+    db.save_role(&RoleDefinition::new(Role::Write, &[Permission::OrdersFulfil])).await.unwrap();
+    let roles = db.fetch_role_definitions().await.unwrap();
+    assert_eq!(roles.len(), 6);
+    let permissions = |role: &Role| roles.iter().find(|d| &d.role == role).unwrap().permissions.clone();
+    assert_eq!(permissions(&support), vec![Permission::OrdersRead, Permission::OrdersCancel]);
+    assert_eq!(permissions(&Role::Write), vec![Permission::OrdersFulfil]);
+
+    let err = db.save_role(&RoleDefinition::new(Role::SuperAdmin, &[])).await;
+    assert!(matches!(err, Err(AuthApiError::BuiltInRole(Role::SuperAdmin))));
+    assert!(matches!(db.delete_role(&Role::Write).await, Err(AuthApiError::BuiltInRole(Role::Write))));
+    // Deleting a role takes it away from every address that held it
+    db.delete_role(&support).await.unwrap();
+    assert!(!db.fetch_roles_for_address(&alice).await.unwrap().contains(&support));
+    assert!(db.fetch_merchant_roles_for_address(&alice, "acme").await.unwrap().is_empty());
+    assert!(matches!(db.delete_role(&support).await, Err(AuthApiError::RoleNotFound)));
+    assert_eq!(db.fetch_role_definitions().await.unwrap().len(), 5);
+}
+
+/// Refresh tokens can only be used once, and revoked or expired sessions cannot be refreshed.
+pub async fn sessions_can_be_refreshed_and_revoked<B: AuthManagement>(db: &B) {
+    let alice = address("alice");
+    let expires_at = Utc::now() + Duration::days(30);
+    let session = |hash: &str, merchant: Option<&str>| NewAuthSession {
+        address: alice.clone(),
+        roles: vec![Role::User, Role::Write],
+        merchant: merchant.map(String::from),
+        refresh_token_hash: hash.to_string(),
+        expires_at,
+    };
+    let first = db.create_session(session("hash-1", None)).await.unwrap();
+    assert_eq!(first.roles, vec![Role::User, Role::Write]);
+    assert!(first.is_active());
+    let second = db.create_session(session("hash-2", Some("acme"))).await.unwrap();
+    assert_eq!(second.merchant.as_deref(), Some("acme"));
+    let expired = NewAuthSession { expires_at: Utc::now() - Duration::days(1), ..session("hash-3", None) };
+    let expired = db.create_session(expired).await.unwrap();
+    let ids = db.fetch_sessions_for_address(&alice).await.unwrap().iter().map(|s| s.id).collect::<Vec<_>>();
+    assert_eq!(ids, vec![second.id, first.id]);
+
+    let refreshed = db.refresh_session("hash-1", "hash-1b").await.unwrap();
+    assert_eq!(refreshed.id, first.id);
+    assert_eq!(refreshed.refresh_token_hash, "hash-1b");
+    // The old refresh token cannot be used again, and expired sessions cannot be refreshed
+    assert!(matches!(db.refresh_session("hash-1", "hash-1c").await, Err(AuthApiError::InvalidRefreshToken)));
+    assert!(matches!(db.refresh_session("hash-3", "hash-3b").await, Err(AuthApiError::InvalidRefreshToken)));
+
+    let since = Utc::now() - Duration::seconds(5);
+    let revoked = db.revoke_session(first.id).await.unwrap();
+    assert!(revoked.revoked_at.is_some());
+    assert!(!revoked.is_active());
+    assert!(matches!(db.refresh_session("hash-1b", "hash-1c").await, Err(AuthApiError::InvalidRefreshToken)));
+    assert!(matches!(db.revoke_session(999).await, Err(AuthApiError::SessionNotFound(999))));
+    // Only active sessions are revoked when logging out everywhere
+    assert_eq!(db.revoke_sessions_for_address(&alice).await.unwrap(), vec![second.id]);
+    assert!(db.fetch_sessions_for_address(&alice).await.unwrap().is_empty());
+    assert!(db.fetch_session(expired.id).await.unwrap().unwrap().revoked_at.is_none());
+    let mut revoked = db.fetch_revoked_sessions(since).await.unwrap();
+    revoked.sort_unstable();
+    assert_eq!(revoked, vec![first.id, second.id]);
+    assert!(db.fetch_revoked_sessions(Utc::now() + Duration::minutes(1)).await.unwrap().is_empty());
+}
+
+/// Admin actions are returned newest first, and can be filtered by actor, endpoint, outcome and time.
+pub async fn admin_actions_are_logged<B: AuditLog>(db: &B) {
+    let alice = address("alice");
+    let bob = address("bob");
+    let action = |actor: &TariAddress, endpoint: &str, outcome: AuditOutcome, status_code: i64| NewAdminAction {
+        actor: actor.clone(),
+        roles: vec![Role::User, Role::Write],
+        merchant: None,
+        endpoint: endpoint.to_string(),
+        method: "PATCH".to_string(),
+        path: format!("/api/{endpoint}"),
+        parameters: serde_json::json!({ "body": { "order_id": "audit-1", "new_price": 250 } }),
+        reason: Some("Customer complaint".to_string()),
+        client_ip: Some("10.0.0.1".to_string()),
+        outcome,
+        status_code,
+        error: (outcome != AuditOutcome::Succeeded).then(|| "Insufficient permissions.".to_string()),
+    };
+    let first = db.record_admin_action(&action(&alice, "update_price", AuditOutcome::Succeeded, 200)).await.unwrap();
+    assert_eq!(first.actor.as_address(), &alice);
+    assert_eq!(first.roles, vec![Role::User, Role::Write]);
+    assert_eq!(first.parameters["body"]["new_price"], 250);
+    assert_eq!(first.reason.as_deref(), Some("Customer complaint"));
+    assert_eq!(first.client_ip.as_deref(), Some("10.0.0.1"));
+    assert!(first.error.is_none());
+    db.record_admin_action(&action(&bob, "cancel_order", AuditOutcome::Denied, 403)).await.unwrap();
+    db.record_admin_action(&action(&alice, "cancel_order", AuditOutcome::Failed, 404)).await.unwrap();
+
+    let all = Pagination { offset: None, count: None };
+    let actions = db.fetch_admin_actions(&AdminActionFilter::default(), &all).await.unwrap();
+    assert_eq!(actions.iter().map(|a| a.status_code).collect::<Vec<_>>(), vec![404, 403, 200]);
+    assert_eq!(actions[2], first);
+    let by_alice = AdminActionFilter { actor: Some(alice.into()), ..Default::default() };
+    assert_eq!(db.fetch_admin_actions(&by_alice, &all).await.unwrap().len(), 2);
+    let cancellations = AdminActionFilter { endpoint: Some("cancel_order".to_string()), ..Default::default() };
+    assert_eq!(db.fetch_admin_actions(&cancellations, &all).await.unwrap().len(), 2);
+    let denied = AdminActionFilter { outcome: Some(AuditOutcome::Denied), ..Default::default() };
+    let denied = db.fetch_admin_actions(&denied, &all).await.unwrap();
+    assert_eq!(denied.len(), 1);
+    assert_eq!(denied[0].actor.as_address(), &bob);
+    let later = AdminActionFilter { since: Some(Utc::now() + Duration::hours(1)), ..Default::default() };
+    assert!(db.fetch_admin_actions(&later, &all).await.unwrap().is_empty());
+    let earlier = AdminActionFilter { until: Some(Utc::now() - Duration::hours(1)), ..Default::default() };
+    assert!(db.fetch_admin_actions(&earlier, &all).await.unwrap().is_empty());
+    let page = Pagination { offset: Some(1), count: Some(1) };
+    let page = db.fetch_admin_actions(&AdminActionFilter::default(), &page).await.unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].outcome, AuditOutcome::Denied);
+}
+
+/// Each key has its own bucket, which refuses requests once it is empty and refills over time. Buckets are pruned
+/// once they have refilled completely.
+pub async fn rate_limit_buckets_refill<B: RateLimitStore>(db: &B) {
+    let limit = RateLimit::new(2, StdDuration::from_secs(60));
+    assert!(db.take_token("auth:ip:10.0.0.1", &limit).await.unwrap().allowed);
+    assert!(db.take_token("auth:ip:10.0.0.1", &limit).await.unwrap().allowed);
+    let refused = db.take_token("auth:ip:10.0.0.1", &limit).await.unwrap();
+    assert!(!refused.allowed);
+    let retry_after = refused.retry_after.unwrap();
+    assert!(retry_after > StdDuration::from_secs(25) && retry_after <= StdDuration::from_secs(30));
+    assert!(db.take_token("auth:ip:10.0.0.2", &limit).await.unwrap().allowed);
+
+    let fast = RateLimit::new(1, StdDuration::from_secs(1));
+    assert!(db.take_token("claim:ip:10.0.0.1", &fast).await.unwrap().allowed);
+    assert!(!db.take_token("claim:ip:10.0.0.1", &fast).await.unwrap().allowed);
+    tokio::time::sleep(StdDuration::from_millis(1100)).await;
+    assert!(db.take_token("claim:ip:10.0.0.1", &fast).await.unwrap().allowed);
+    tokio::time::sleep(StdDuration::from_millis(1100)).await;
+    assert_eq!(db.prune_rate_limits().await.unwrap(), 1);
+    assert!(!db.take_token("auth:ip:10.0.0.1", &limit).await.unwrap().allowed);
+}
+
+/// State changes write their events to the outbox, and deliveries, failures and replays are tracked per event.
+pub async fn event_outbox_tracks_deliveries<B: PaymentGatewayDatabase + EventOutbox>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    api.process_new_order(new_order("oid-1", "alice", 50), false, true).await.unwrap();
+    let mut payment = NewPayment::new(address("a"), tari(75), "tx-1".into());
+    payment.order_id = Some(OrderId::new("oid-1"));
+    api.process_new_payment(payment, true).await.unwrap();
+    api.confirm_payment("tx-1".into(), true).await.unwrap();
+
+    let due = db.fetch_due_events(100).await.unwrap();
+    let names = due.iter().map(|e| e.event_type()).collect::<Vec<_>>();
+    for name in ["NewOrder", "OrderClaimed", "PaymentReceived", "Confirmation", "OrderPaid"] {
+        assert!(names.contains(&name), "Missing {name} event in {names:?}");
+    }
+    assert!(due.windows(2).all(|w| w[0].id < w[1].id), "Events must be returned oldest first");
+    assert_eq!(db.fetch_due_events(2).await.unwrap().len(), 2);
+
+    let delivered = db.mark_event_delivered(due[0].id).await.unwrap();
+    assert!(delivered.is_delivered());
+    let retry = db.record_failed_delivery(due[1].id, "timeout", Some(Utc::now() + Duration::hours(1))).await.unwrap();
+    assert_eq!(retry.attempts, 1);
+    assert_eq!(retry.last_error.as_deref(), Some("timeout"));
+    let parked = db.record_failed_delivery(due[2].id, "rejected", None).await.unwrap();
+    assert!(parked.next_attempt_at.is_none());
+
+    let still_due = db.fetch_due_events(100).await.unwrap();
+    assert_eq!(still_due.len(), due.len() - 3);
+    let all = Pagination { offset: None, count: None };
+    assert_eq!(db.fetch_undelivered_events(&all).await.unwrap().len(), due.len() - 1);
+    let page = Pagination { offset: Some(1), count: Some(1) };
+    let undelivered = db.fetch_undelivered_events(&page).await.unwrap();
+    assert_eq!(undelivered.len(), 1);
+    assert_eq!(undelivered[0].id, due[2].id);
+
+    db.replay_event(due[2].id).await.unwrap();
+    db.replay_event(due[0].id).await.unwrap();
+    let replayed = db.fetch_due_events(100).await.unwrap();
+    assert_eq!(replayed.len(), due.len() - 1);
+    assert_eq!(replayed[0].id, due[0].id);
+    assert!(!replayed[0].is_delivered());
+    assert!(matches!(db.replay_event(-1).await, Err(OutboxError::EventNotFound(-1))));
+
+    let order = fetch_order(db, "oid-1").await;
+    let enqueued = db.enqueue_events(&[EventType::OrderPaid(OrderEvent::new(order))]).await.unwrap();
+    assert_eq!(enqueued.len(), 1);
+    let fetched = db.fetch_outbox_event(enqueued[0].id).await.unwrap().expect("Event should exist");
+    assert_eq!(fetched.event_type(), "OrderPaid");
+    assert_eq!(fetched.attempts, 0);
+}
+
+/// Claimed events are leased to the claimant, and each handler's delivery is recorded separately.
+pub async fn event_outbox_claims_are_exclusive<B: PaymentGatewayDatabase + EventOutbox>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    api.process_new_order(new_order("oid-1", "alice", 50), false, true).await.unwrap();
+    api.process_new_order(new_order("oid-2", "alice", 50), false, true).await.unwrap();
+    let due = db.fetch_due_events(100).await.unwrap();
+    assert!(due.len() >= 2);
+
+    let claimed = db.claim_due_events(1, Duration::minutes(5)).await.unwrap();
+    assert_eq!(claimed.len(), 1);
+    assert_eq!(claimed[0].id, due[0].id);
+    let rest = db.claim_due_events(100, Duration::minutes(5)).await.unwrap();
+    assert_eq!(rest.len(), due.len() - 1);
+    assert!(rest.iter().all(|e| e.id != claimed[0].id), "An event must not be claimed twice");
+    assert!(db.claim_due_events(100, Duration::minutes(5)).await.unwrap().is_empty());
+    assert!(db.fetch_due_events(100).await.unwrap().is_empty());
+
+    // Recording the outcome releases the lease
+    let id = claimed[0].id;
+    db.mark_handler_delivered(id, "storefronts").await.unwrap();
+    db.mark_handler_delivered(id, "storefronts").await.unwrap();
+    assert_eq!(db.fetch_delivered_handlers(id).await.unwrap(), vec!["storefronts".to_string()]);
+    db.record_failed_delivery(id, "webhooks: timeout", Some(Utc::now() - Duration::seconds(1))).await.unwrap();
+    let reclaimed = db.claim_due_events(100, Duration::minutes(5)).await.unwrap();
+    assert_eq!(reclaimed.iter().map(|e| e.id).collect::<Vec<_>>(), vec![id]);
+    // A lease that has run out can be claimed again
+    db.replay_event(rest[0].id).await.unwrap();
+    assert_eq!(db.claim_due_events(100, Duration::seconds(-1)).await.unwrap().len(), 1);
+    assert_eq!(db.claim_due_events(100, Duration::minutes(5)).await.unwrap().len(), 1);
+
+    // Replaying a parked event keeps the handlers that succeeded; replaying a delivered one clears them
+    db.replay_event(id).await.unwrap();
+    assert_eq!(db.fetch_delivered_handlers(id).await.unwrap().len(), 1);
+    db.mark_event_delivered(id).await.unwrap();
+    db.replay_event(id).await.unwrap();
+    assert!(db.fetch_delivered_handlers(id).await.unwrap().is_empty());
+}
+
+/// Refunds only affect balances once approved. Refunds against an order are capped at what the address paid for it,
+/// and refunds of the balance at the unspent balance.
+pub async fn refunds_debit_balances<B: PaymentGatewayDatabase + EventOutbox>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    api.process_new_order(new_order("oid-1", "alice", 100), false, true).await.unwrap();
+    api.process_new_order(new_order("oid-2", "alice", 500), false, true).await.unwrap();
+    let mut payment = NewPayment::new(address("a"), tari(250), "tx-1".into());
+    payment.order_id = Some(OrderId::new("oid-1"));
+    api.process_new_payment(payment, true).await.unwrap();
+    api.confirm_payment("tx-1".into(), true).await.unwrap();
+    assert_eq!(fetch_order(db, "oid-1").await.status, OrderStatusType::Paid);
+    let order_refund =
+        |amount| NewRefund::new(address("a"), tari(amount), "Damaged".into()).for_order(OrderId::new("oid-1"));
+
+    let err = db.request_refund(order_refund(101)).await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::InvalidRefund(_)));
+    let unpaid = NewRefund::new(address("a"), tari(10), "Unpaid".into()).for_order(OrderId::new("oid-2"));
+    assert!(matches!(db.request_refund(unpaid).await, Err(PaymentGatewayError::InvalidRefund(_))));
+    let refund = db.request_refund(order_refund(40)).await.unwrap();
+    assert_eq!(refund.status, RefundStatus::Requested);
+    let balance = db.fetch_address_balance(&address("a")).await.unwrap();
+    assert_eq!(balance.total_refunded(), tari(0));
+    assert_eq!(balance.current_balance(), tari(150));
+
+    let refund = db.approve_refund(refund.id).await.unwrap();
+    assert_eq!(refund.status, RefundStatus::Approved);
+    assert!(matches!(db.approve_refund(refund.id).await, Err(PaymentGatewayError::InvalidRefund(_))));
+    let balance = db.fetch_address_balance(&address("a")).await.unwrap();
+    assert_eq!(balance.total_paid(), tari(60));
+    assert_eq!(balance.total_refunded(), tari(40));
+    assert_eq!(balance.current_balance(), tari(150));
+    assert!(matches!(db.request_refund(order_refund(61)).await, Err(PaymentGatewayError::InvalidRefund(_))));
+
+    let overpaid = || NewRefund::new(address("a"), tari(50), "Overpayment".into());
+    let err = db.request_refund(NewRefund::new(address("a"), tari(151), "Overpayment".into())).await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::InvalidRefund(_)));
+    let rejected = db.request_refund(overpaid()).await.unwrap();
+    let rejected = db.reject_refund(rejected.id, "Store credit issued").await.unwrap();
+    assert_eq!(rejected.status, RefundStatus::Rejected);
+    assert!(rejected.reason.starts_with("Overpayment"));
+    assert!(rejected.reason.ends_with("Store credit issued"));
+    let balance_refund = db.request_refund(overpaid()).await.unwrap();
+    assert!(matches!(
+        db.mark_refund_sent(balance_refund.id, "payout-1").await,
+        Err(PaymentGatewayError::InvalidRefund(_))
+    ));
+    db.approve_refund(balance_refund.id).await.unwrap();
+    let sent = db.mark_refund_sent(balance_refund.id, "payout-1").await.unwrap();
+    assert_eq!(sent.status, RefundStatus::Sent);
+    assert_eq!(sent.payout_txid.as_deref(), Some("payout-1"));
+    assert!(matches!(db.approve_refund(-1).await, Err(PaymentGatewayError::RefundNotFound(-1))));
+
+    let balance = db.fetch_address_balance(&address("a")).await.unwrap();
+    assert_eq!(balance.total_confirmed(), tari(250));
+    assert_eq!(balance.total_paid(), tari(60));
+    assert_eq!(balance.total_refunded(), tari(90));
+    assert_eq!(balance.current_balance(), tari(100));
+
+    let all = Pagination { offset: None, count: None };
+    let refunds = db.fetch_refunds(None, &all).await.unwrap();
+    assert_eq!(refunds.iter().map(|r| r.id).collect::<Vec<_>>(), vec![balance_refund.id, rejected.id, refund.id]);
+    let sent_refunds = db.fetch_refunds(Some(RefundStatus::Sent), &all).await.unwrap();
+    assert_eq!(sent_refunds, vec![sent.clone()]);
+    let page = Pagination { offset: Some(1), count: Some(1) };
+    assert_eq!(db.fetch_refunds(None, &page).await.unwrap()[0].id, rejected.id);
+    assert_eq!(db.fetch_refund(sent.id).await.unwrap(), Some(sent));
+    assert!(db.fetch_refund(-1).await.unwrap().is_none());
+
+    let names = db.fetch_due_events(100).await.unwrap().iter().map(|e| e.event_type()).collect::<Vec<_>>();
+    assert_eq!(names.iter().filter(|&&n| n == "RefundApproved").count(), 2);
+    assert_eq!(names.iter().filter(|&&n| n == "RefundSent").count(), 1);
+}
+
+/// High-risk operations wait for a second admin's approval and are carried out once it is given. Requests cannot be
+/// decided by the admin that made them, or once they have been decided or have expired.
+pub async fn operations_need_a_second_approver<B: PaymentGatewayDatabase + EventOutbox>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    api.process_new_order(new_order("oid-1", "alice", 100), false, true).await.unwrap();
+    let expires_at = Utc::now() + Duration::hours(1);
+    let request = |operation| NewApprovalRequest::new(address("a"), operation, expires_at);
+    let reprice = ApprovalOperation::UpdatePrice {
+        order_id: OrderId::new("oid-1"),
+        old_price: tari(100),
+        new_price: tari(50),
+        reason: "Loyalty discount".into(),
+    };
+
+    let repriced = api.request_approval(request(reprice.clone())).await.unwrap();
+    assert_eq!(repriced.status, ApprovalStatus::Pending);
+    assert_eq!(repriced.requested_by.as_address(), &address("a"));
+    assert_eq!(fetch_order(db, "oid-1").await.total_price, tari(100));
+    let err = api.approve_operation(repriced.id, &address("a"), true).await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::InvalidApproval(_)));
+    let err = api.reject_operation(repriced.id, &address("a"), "Changed my mind").await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::InvalidApproval(_)));
+    let repriced = api.approve_operation(repriced.id, &address("b"), true).await.unwrap();
+    assert_eq!(repriced.status, ApprovalStatus::Approved);
+    assert_eq!(repriced.decided_by.as_ref().map(|a| a.as_address()), Some(&address("b")));
+    assert_eq!(fetch_order(db, "oid-1").await.total_price, tari(50));
+    let err = api.reject_operation(repriced.id, &address("c"), "Too late").await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::InvalidApproval(_)));
+
+    // The price has moved since this request was made, so it fails once it is approved
+    let stale = api.request_approval(request(reprice)).await.unwrap();
+    let err = api.approve_operation(stale.id, &address("b"), true).await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::InvalidApproval(_)));
+    let stale = db.fetch_approval_request(stale.id).await.unwrap().unwrap();
+    assert_eq!(stale.status, ApprovalStatus::Failed);
+    assert!(stale.decision_reason.unwrap().contains("has changed"));
+    assert_eq!(fetch_order(db, "oid-1").await.total_price, tari(50));
+
+    let credit = ApprovalOperation::IssueCredit { note: CreditNote::new("alice".into(), tari(50)) };
+    let rejected = api.request_approval(request(credit.clone())).await.unwrap();
+    let rejected = api.reject_operation(rejected.id, &address("b"), "No reason to credit").await.unwrap();
+    assert_eq!(rejected.status, ApprovalStatus::Rejected);
+    assert_eq!(rejected.decision_reason.as_deref(), Some("No reason to credit"));
+    assert_eq!(fetch_order(db, "oid-1").await.status, OrderStatusType::New);
+
+    let late = NewApprovalRequest::new(address("a"), credit, Utc::now() - Duration::seconds(1));
+    let expired = api.request_approval(late).await.unwrap();
+    let err = api.approve_operation(expired.id, &address("b"), true).await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::InvalidApproval(_)));
+    let expired_now = api.expire_approval_requests().await.unwrap();
+    assert_eq!(expired_now.iter().map(|r| r.id).collect::<Vec<_>>(), vec![expired.id]);
+    assert_eq!(expired_now[0].status, ApprovalStatus::Expired);
+    assert!(api.expire_approval_requests().await.unwrap().is_empty());
+    assert_eq!(fetch_order(db, "oid-1").await.status, OrderStatusType::New);
+
+    let fulfil = ApprovalOperation::FulfilOrder { order_id: OrderId::new("oid-1"), reason: "Paid in store".into() };
+    let fulfilled = api.request_approval(request(fulfil)).await.unwrap();
+    api.approve_operation(fulfilled.id, &address("b"), true).await.unwrap();
+    assert_eq!(fetch_order(db, "oid-1").await.status, OrderStatusType::Paid);
+    let err = api.approve_operation(-1, &address("b"), true).await.unwrap_err();
+    assert!(matches!(err, PaymentGatewayError::ApprovalRequestNotFound(-1)));
+
+    let all = Pagination { offset: None, count: None };
+    let requests = db.fetch_approval_requests(None, &all).await.unwrap();
+    let ids = requests.iter().map(|r| r.id).collect::<Vec<_>>();
+    assert_eq!(ids, vec![fulfilled.id, expired.id, rejected.id, stale.id, repriced.id]);
+    let approved = db.fetch_approval_requests(Some(ApprovalStatus::Approved), &all).await.unwrap();
+    assert_eq!(approved.iter().map(|r| r.id).collect::<Vec<_>>(), vec![fulfilled.id, repriced.id]);
+    assert!(db.fetch_approval_requests(Some(ApprovalStatus::Pending), &all).await.unwrap().is_empty());
+    let page = Pagination { offset: Some(1), count: Some(1) };
+    assert_eq!(db.fetch_approval_requests(None, &page).await.unwrap()[0].id, expired.id);
+    assert_eq!(db.fetch_approval_request(rejected.id).await.unwrap(), Some(rejected));
+    assert!(db.fetch_approval_request(-1).await.unwrap().is_none());
+
+    let names = db.fetch_due_events(100).await.unwrap().iter().map(|e| e.event_type()).collect::<Vec<_>>();
+    assert_eq!(names.iter().filter(|&&n| n == "ApprovalRequested").count(), 5);
+    assert_eq!(names.iter().filter(|&&n| n == "OperationApproved").count(), 3);
+    assert_eq!(names.iter().filter(|&&n| n == "OperationRejected").count(), 1);
+}
+
+/// Payments, cancellations, settlements, credit notes and refunds all post balanced entries to the ledger, and the
+/// customer wallet accounts agree with the address balances.
+pub async fn ledger_postings_balance<B: PaymentGatewayDatabase>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    api.process_new_order(new_order("oid-1", "alice", 100), false, true).await.unwrap();
+    let mut payment = NewPayment::new(address("a"), tari(250), "tx-1".into());
+    payment.order_id = Some(OrderId::new("oid-1"));
+    api.process_new_payment(payment, true).await.unwrap();
+    let trial_balance = db.fetch_trial_balance().await.unwrap();
+    assert_eq!(trial_balance.balance(&LedgerAccount::pending_payments(&address("a"))), -tari(250));
+    api.confirm_payment("tx-1".into(), true).await.unwrap();
+    assert_eq!(fetch_order(db, "oid-1").await.status, OrderStatusType::Paid);
+    db.process_new_payment(NewPayment::new(address("b"), tari(20), "tx-2".into()), true).await.unwrap();
+    db.update_payment_status("tx-2", TransferStatus::Cancelled).await.unwrap();
+    let note = db.process_credit_note_for_customer(CreditNote::new("bob".into(), tari(30))).await.unwrap();
+    let refund = NewRefund::new(address("a"), tari(40), "Damaged".into()).for_order(OrderId::new("oid-1"));
+    let refund = db.request_refund(refund).await.unwrap();
+    db.approve_refund(refund.id).await.unwrap();
+    db.mark_refund_sent(refund.id, "payout-1").await.unwrap();
+
+    let trial_balance = db.fetch_trial_balance().await.unwrap();
+    assert!(trial_balance.is_balanced());
+    assert_eq!(trial_balance.total_debits, tari(750));
+    for owner in [address("a"), note.sender.to_address()] {
+        let balance = db.fetch_address_balance(&owner).await.unwrap();
+        assert_eq!(trial_balance.balance(&LedgerAccount::customer_wallet(&owner)), -balance.current_balance());
+    }
+    assert_eq!(trial_balance.balance(&LedgerAccount::pending_payments(&address("a"))), tari(0));
+    assert_eq!(trial_balance.balance(&LedgerAccount::pending_payments(&address("b"))), tari(0));
+    assert_eq!(trial_balance.balance(&LedgerAccount::merchant_revenue(DEFAULT_MERCHANT)), -tari(60));
+    assert_eq!(trial_balance.balance(&LedgerAccount::hot_wallet()), tari(210));
+    assert_eq!(trial_balance.balance(&LedgerAccount::credits()), tari(30));
+    assert_eq!(trial_balance.balance(&LedgerAccount::refunds()), tari(0));
+    assert_eq!(db.fetch_ledger_balances().await.unwrap(), trial_balance.accounts);
+
+    let wallet = LedgerAccount::customer_wallet(&address("a"));
+    let all = Pagination { offset: None, count: None };
+    let entries = db.fetch_ledger_entries(&wallet, &all).await.unwrap();
+    let types = entries.iter().map(|e| e.transaction_type).collect::<Vec<_>>();
+    assert_eq!(types, vec![LedgerTransactionType::PaymentConfirmed, LedgerTransactionType::Settlement]);
+    let page = Pagination { offset: Some(1), count: Some(1) };
+    let settlement = db.fetch_ledger_entries(&wallet, &page).await.unwrap().remove(0);
+    assert_eq!(settlement.account(), wallet);
+    assert_eq!((settlement.side, settlement.amount), (EntrySide::Debit, tari(100)));
+    assert_eq!(settlement.reference, "oid-1");
+}
+
+/// Exports cover a half-open period. Records are valued at the rate that their order was priced at, and otherwise at
+/// the rate for the requested currency.
+pub async fn accounting_exports_cover_a_period<B: PaymentGatewayDatabase + ExchangeRates>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    let start = Utc::now() - Duration::seconds(5);
+    db.set_exchange_rate(&ExchangeRate::new("USD".into(), tari(2), None)).await.unwrap();
+    let quoted = db.fetch_last_rate("USD").await.unwrap();
+    let mut order = new_order("oid-1", "alice", 100).with_quote(quoted.id, Utc::now() + Duration::hours(1));
+    order.currency = "USD".into();
+    api.process_new_order(order, false, true).await.unwrap();
+    api.process_new_order(new_order("oid-2", "bob", 50), false, true).await.unwrap();
+    db.set_exchange_rate(&ExchangeRate::new("USD".into(), tari(4), None)).await.unwrap();
+    let mut payment = NewPayment::new(address("a"), tari(100), "tx-1".into());
+    payment.order_id = Some(OrderId::new("oid-1"));
+    api.process_new_payment(payment, true).await.unwrap();
+    api.confirm_payment("tx-1".into(), true).await.unwrap();
+    api.process_new_payment(NewPayment::new(address("b"), tari(30), "tx-2".into()), true).await.unwrap();
+    db.process_credit_note_for_customer(CreditNote::new("bob".into(), tari(10))).await.unwrap();
+    let end = Utc::now() + Duration::seconds(5);
+
+    let orders = db.fetch_orders_between(start, end).await.unwrap();
+    assert_eq!(orders.iter().map(|o| o.order_id.as_str()).collect::<Vec<_>>(), vec!["oid-1", "oid-2"]);
+    assert!(db.fetch_orders_between(end, end + Duration::hours(1)).await.unwrap().is_empty());
+    assert!(db.fetch_orders_between(start - Duration::hours(1), start).await.unwrap().is_empty());
+    assert_eq!(db.fetch_payments_between(start, end).await.unwrap().len(), 3);
+    assert!(db.fetch_payments_between(end, end + Duration::hours(1)).await.unwrap().is_empty());
+    let settlements = db.fetch_settlements_between(start, end).await.unwrap();
+    assert_eq!(settlements.len(), 1);
+    assert_eq!(settlements[0].order_id.as_str(), "oid-1");
+    assert!(db.fetch_settlements_between(start - Duration::hours(1), start).await.unwrap().is_empty());
+
+    let exports = ExportApi::new(db.clone(), db.clone());
+    let request = ExportRequest::new(ReportType::Payments, ReportFormat::Csv, start, end).with_currency("USD");
+    let report = exports.export(&request).await.unwrap();
+    assert_eq!(report.record_count, 2);
+    let lines = report.body.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], PaymentRecord::COLUMNS.join(","));
+    // tx-1 pays for oid-1, so it is valued at the quoted rate. tx-2 is valued at the current rate.
+    assert!(lines[1].contains(&format!(",100.000000,USD,50.00,{},2.000000,", quoted.id)), "{}", lines[1]);
+    assert!(lines[2].contains(",30.000000,USD,7.50,"), "{}", lines[2]);
+    let request = ExportRequest::new(ReportType::CreditNotes, ReportFormat::JsonLines, start, end);
+    let report = exports.export(&request).await.unwrap();
+    let note = serde_json::from_str::<serde_json::Value>(report.body.trim()).unwrap();
+    assert_eq!(note["customer_ids"], "bob");
+    assert_eq!(note["amount_xtr"], "10.000000");
+    assert!(note["fiat_amount"].is_null());
+    let request = ExportRequest::new(ReportType::Settlements, ReportFormat::Ofx, start, end);
+    let report = exports.export(&request).await.unwrap();
+    assert_eq!(report.body.matches("<STMTTRN>").count(), 1);
+    assert!(report.body.contains("<CURSYM>USD</CURSYM>"));
+    let request = ExportRequest::new(ReportType::Orders, ReportFormat::Csv, end, start);
+    assert!(matches!(exports.export(&request).await, Err(ExportError::InvalidPeriod(_))));
+}
+
+/// Idle surplus balances are kept, flagged for review or refunded, depending on the overpayment policy.
+pub async fn unspent_balances_follow_overpayment_policy<B: PaymentGatewayDatabase>(db: &B) {
+    let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+    let alice = TariAddress::from_str("14s9vDTwrweZvWEgQ9gNhXXPX68DPXSSAHNFWYEPi5JsBQY").unwrap();
+    let bob = TariAddress::from_str("14AYt2hhhn4VydAXNJ6i7ZfRNZGoGSp713dHjMYCoK5hYw2").unwrap();
+    api.process_new_order(new_order("oid-1", "alice", 100), false, true).await.unwrap();
+    api.process_new_order(new_order("oid-2", "bob", 100), false, true).await.unwrap();
+    for (address, amount, txid, order_id) in [(&alice, 150, "tx-1", "oid-1"), (&bob, 30, "tx-2", "oid-2")] {
+        let mut payment = NewPayment::new(address.clone(), tari(amount), txid.into());
+        payment.order_id = Some(OrderId::new(order_id));
+        api.process_new_payment(payment, true).await.unwrap();
+        api.confirm_payment(txid.into(), true).await.unwrap();
+    }
+    api.issue_credit_note(CreditNote::new("carol".into(), tari(20)), true).await.unwrap();
+    assert_eq!(fetch_order(db, "oid-1").await.status, OrderStatusType::Paid);
+    assert_eq!(db.fetch_idle_balances(Duration::zero()).await.unwrap().len(), 3);
+    assert!(db.fetch_idle_balances(Duration::days(1)).await.unwrap().is_empty());
+
+    let zero = Duration::zero();
+    assert!(api.process_unspent_balances(OverpaymentPolicy::StoreCredit).await.unwrap().is_empty());
+    assert!(api.process_unspent_balances(OverpaymentPolicy::Review(Duration::days(1))).await.unwrap().is_empty());
+    // Bob still owes money on his order, and Carol's credit note is not a payment that can be sent back
+    let flagged = api.process_unspent_balances(OverpaymentPolicy::Review(zero)).await.unwrap();
+    assert_eq!(flagged.len(), 1);
+    assert_eq!(flagged[0].address.as_address(), &alice);
+    assert_eq!(flagged[0].amount, tari(50));
+    assert_eq!(flagged[0].status, RefundStatus::Requested);
+    assert!(api.process_unspent_balances(OverpaymentPolicy::AutoRefund(zero)).await.unwrap().is_empty());
+
+    db.reject_refund(flagged[0].id, "Customer asked for store credit").await.unwrap();
+    let refunded = api.process_unspent_balances(OverpaymentPolicy::AutoRefund(zero)).await.unwrap();
+    assert_eq!(refunded.len(), 1);
+    assert_eq!(refunded[0].amount, tari(50));
+    assert_eq!(refunded[0].status, RefundStatus::Approved);
+    assert_eq!(db.fetch_address_balance(&alice).await.unwrap().current_balance(), tari(0));
+    assert!(api.process_unspent_balances(OverpaymentPolicy::AutoRefund(zero)).await.unwrap().is_empty());
+}
+
+/// Webhook deliveries are queued once per event per active subscriber, and retried independently.
+pub async fn webhook_deliveries_are_tracked<B: PaymentGatewayDatabase + EventOutbox + WebhookManagement>(db: &B) {
+    let subscribe = |url: &str, event_type: &str| NewWebhookSubscription {
+        url: url.to_string(),
+        event_type: event_type.to_string(),
+        secret: "a-very-secret-key".to_string(),
+    };
+    let erp = db.create_webhook(&subscribe("https://erp.example.com/hook", "NewOrder")).await.unwrap();
+    let mail = db.create_webhook(&subscribe("https://mail.example.com/hook", "NewOrder")).await.unwrap();
+    let paid = db.create_webhook(&subscribe("https://erp.example.com/hook", "OrderPaid")).await.unwrap();
+    assert!(erp.active);
+    assert!(matches!(
+        db.create_webhook(&subscribe("https://erp.example.com/hook", "NewOrder")).await,
+        Err(WebhookError::InvalidSubscription(_))
+    ));
+    assert_eq!(db.fetch_webhooks().await.unwrap().len(), 3);
+    assert_eq!(db.fetch_webhook(mail.id).await.unwrap().unwrap().secret, "a-very-secret-key");
+
+    db.insert_order(new_order("oid-1", "alice", 100)).await.unwrap();
+    let event = db.fetch_due_events(10).await.unwrap().into_iter().find(|e| e.event_type() == "NewOrder").unwrap();
+    let deliveries = db.queue_webhook_deliveries(&event).await.unwrap();
+    assert_eq!(deliveries.len(), 2);
+    assert!(deliveries.iter().all(|d| d.event_id == event.id && d.event_type == "NewOrder"));
+    assert!(deliveries.iter().all(|d| d.subscription_id != paid.id));
+    assert!(db.queue_webhook_deliveries(&event).await.unwrap().is_empty(), "Queueing must be idempotent");
+
+    let erp_delivery = deliveries.iter().find(|d| d.subscription_id == erp.id).unwrap();
+    let mail_delivery = deliveries.iter().find(|d| d.subscription_id == mail.id).unwrap();
+    let delivered = db.mark_webhook_delivered(erp_delivery.id, 200).await.unwrap();
+    assert!(delivered.is_delivered());
+    assert_eq!(delivered.last_status, Some(200));
+    let failed = db.record_failed_webhook_delivery(mail_delivery.id, Some(503), "Unavailable", None).await.unwrap();
+    assert_eq!(failed.attempts, 1);
+    assert!(db.fetch_due_webhook_deliveries(10).await.unwrap().is_empty());
+
+    // Disabled subscriptions hold back their deliveries
+    db.replay_webhook_delivery(mail_delivery.id).await.unwrap();
+    db.set_webhook_active(mail.id, false).await.unwrap();
+    assert!(db.fetch_due_webhook_deliveries(10).await.unwrap().is_empty());
+    db.set_webhook_active(mail.id, true).await.unwrap();
+    let due = db.fetch_due_webhook_deliveries(10).await.unwrap();
+    assert_eq!(due.len(), 1);
+    assert_eq!(due[0].id, mail_delivery.id);
+    assert_eq!(due[0].payload, mail_delivery.payload);
+    // Claimed deliveries are leased until the outcome is recorded
+    let claimed = db.claim_due_webhook_deliveries(10, Duration::minutes(5)).await.unwrap();
+    assert_eq!(claimed.iter().map(|d| d.id).collect::<Vec<_>>(), vec![mail_delivery.id]);
+    assert!(db.claim_due_webhook_deliveries(10, Duration::minutes(5)).await.unwrap().is_empty());
+    assert!(db.fetch_due_webhook_deliveries(10).await.unwrap().is_empty());
+    db.record_failed_webhook_delivery(mail_delivery.id, None, "Timeout", Some(Utc::now() - Duration::seconds(1)))
+        .await
+        .unwrap();
+    assert_eq!(db.claim_due_webhook_deliveries(10, Duration::seconds(-1)).await.unwrap().len(), 1);
+    // A lease that has run out can be claimed again
+    assert_eq!(db.claim_due_webhook_deliveries(10, Duration::minutes(5)).await.unwrap().len(), 1);
+
+    let all = Pagination { offset: None, count: None };
+    let log = db.fetch_webhook_deliveries(None, &all).await.unwrap();
+    assert_eq!(log.len(), 2);
+    assert!(log[0].id > log[1].id, "The delivery log is newest first");
+    assert_eq!(db.fetch_webhook_deliveries(Some(erp.id), &all).await.unwrap().len(), 1);
+
+    db.delete_webhook(mail.id).await.unwrap();
+    assert!(matches!(db.delete_webhook(mail.id).await, Err(WebhookError::SubscriptionNotFound(_))));
+    assert_eq!(db.fetch_webhook_deliveries(None, &all).await.unwrap().len(), 1);
+    assert!(matches!(db.replay_webhook_delivery(mail_delivery.id).await, Err(WebhookError::DeliveryNotFound(_))));
+}
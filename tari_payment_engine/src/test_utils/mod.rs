@@ -1 +1,4 @@
+pub mod conformance;
 pub mod prepare_env;
+#[cfg(feature = "postgres")]
+pub mod prepare_pg_env;
@@ -1,12 +1,15 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
+use tpg_common::MicroTari;
 
 use crate::{
     db_types::{
         AddressBalance,
         CustomerBalance,
         CustomerOrderBalance,
+        LedgerAccount,
+        LedgerBalance,
         Order,
         Payment,
         SerializedTariAddress,
@@ -99,6 +102,34 @@ impl CustomerHistory {
     }
 }
 
+/// The balance of every ledger account, as a check that the ledger is consistent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrialBalance {
+    pub accounts: Vec<LedgerBalance>,
+    pub total_debits: MicroTari,
+    pub total_credits: MicroTari,
+    /// The ids of ledger transactions whose own debits and credits do not match. There should never be any.
+    pub unbalanced_transactions: Vec<i64>,
+}
+
+impl TrialBalance {
+    pub fn new(accounts: Vec<LedgerBalance>, unbalanced_transactions: Vec<i64>) -> Self {
+        let total_debits = accounts.iter().map(|a| a.debits).sum();
+        let total_credits = accounts.iter().map(|a| a.credits).sum();
+        Self { accounts, total_debits, total_credits, unbalanced_transactions }
+    }
+
+    /// True if the total debits equal the total credits, and every transaction balances.
+    pub fn is_balanced(&self) -> bool {
+        self.total_debits == self.total_credits && self.unbalanced_transactions.is_empty()
+    }
+
+    /// The balance of the account, or zero if nothing has been posted to it.
+    pub fn balance(&self, account: &LedgerAccount) -> MicroTari {
+        self.accounts.iter().find(|a| &a.account() == account).map(LedgerBalance::balance).unwrap_or_default()
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
 pub struct CustomerId {
     pub customer_id: String,
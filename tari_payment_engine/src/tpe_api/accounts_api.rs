@@ -6,10 +6,25 @@ use log::*;
 use tari_common_types::tari_address::TariAddress;
 
 use crate::{
-    db_types::{AddressBalance, CustomerBalance, CustomerOrders, Order, OrderId, Payment},
+    db_types::{
+        AddressBalance,
+        ApprovalRequest,
+        ApprovalStatus,
+        CustomerBalance,
+        CustomerOrders,
+        HeldOrder,
+        LedgerAccount,
+        LedgerBalance,
+        LedgerEntry,
+        Order,
+        OrderId,
+        Payment,
+        Refund,
+        RefundStatus,
+    },
     order_objects::{OrderQueryFilter, OrderResult},
     tpe_api::{
-        account_objects::{AddressHistory, CustomerHistory, Pagination},
+        account_objects::{AddressHistory, CustomerHistory, Pagination, TrialBalance},
         payment_objects::PaymentsResult,
     },
     traits::{AccountApiError, AccountManagement},
@@ -107,4 +122,63 @@ where B: AccountManagement
     pub async fn fetch_payments_for_order(&self, order_id: &OrderId) -> Result<Vec<Payment>, AccountApiError> {
         self.db.fetch_payments_for_order(order_id).await
     }
+
+    pub async fn fetch_refund(&self, id: i64) -> Result<Option<Refund>, AccountApiError> {
+        self.db.fetch_refund(id).await
+    }
+
+    pub async fn fetch_refunds(
+        &self,
+        status: Option<RefundStatus>,
+        pagination: &Pagination,
+    ) -> Result<Vec<Refund>, AccountApiError> {
+        self.db.fetch_refunds(status, pagination).await
+    }
+
+    pub async fn fetch_approval_request(&self, id: i64) -> Result<Option<ApprovalRequest>, AccountApiError> {
+        self.db.fetch_approval_request(id).await
+    }
+
+    pub async fn fetch_approval_requests(
+        &self,
+        status: Option<ApprovalStatus>,
+        pagination: &Pagination,
+    ) -> Result<Vec<ApprovalRequest>, AccountApiError> {
+        self.db.fetch_approval_requests(status, pagination).await
+    }
+
+    pub async fn fetch_held_order(&self, order_id: &OrderId) -> Result<Option<HeldOrder>, AccountApiError> {
+        self.db.fetch_held_order(order_id).await
+    }
+
+    /// The orders that are waiting to be released, oldest first.
+    pub async fn fetch_held_orders(&self, pagination: &Pagination) -> Result<Vec<HeldOrder>, AccountApiError> {
+        self.db.fetch_held_orders(pagination).await
+    }
+
+    /// The entries posted to a ledger account, oldest first.
+    pub async fn fetch_ledger_entries(
+        &self,
+        account: &LedgerAccount,
+        pagination: &Pagination,
+    ) -> Result<Vec<LedgerEntry>, AccountApiError> {
+        self.db.fetch_ledger_entries(account, pagination).await
+    }
+
+    pub async fn fetch_ledger_balances(&self) -> Result<Vec<LedgerBalance>, AccountApiError> {
+        self.db.fetch_ledger_balances().await
+    }
+
+    /// The trial balance of the ledger. If it does not balance, the ledger and the balances that are built on it can't
+    /// be trusted.
+    pub async fn fetch_trial_balance(&self) -> Result<TrialBalance, AccountApiError> {
+        let trial_balance = self.db.fetch_trial_balance().await?;
+        if !trial_balance.is_balanced() {
+            error!(
+                "📋️ The ledger does not balance. Debits: {}, credits: {}, unbalanced transactions: {:?}",
+                trial_balance.total_debits, trial_balance.total_credits, trial_balance.unbalanced_transactions
+            );
+        }
+        Ok(trial_balance)
+    }
 }
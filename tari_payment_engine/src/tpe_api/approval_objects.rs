@@ -0,0 +1,105 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Duration, Utc};
+use tpg_common::MicroTari;
+
+/// Which admin operations are held back until a second admin approves them. See
+/// [`crate::OrderFlowApi::request_approval`].
+///
+/// The default policy needs no approvals at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApprovalPolicy {
+    /// Credit notes of this amount or more need approval. Each note is checked on its own, so notes for the same
+    /// customer are not added up.
+    pub credit_threshold: Option<MicroTari>,
+    /// Price changes of this many percent of the current price or more, up or down, need approval
+    pub price_change_threshold: Option<u64>,
+    /// Whether marking an order as paid by hand needs approval
+    pub fulfilment: bool,
+    /// How long a request waits for a decision before it expires
+    pub lifetime: Duration,
+}
+
+impl Default for ApprovalPolicy {
+    fn default() -> Self {
+        Self { credit_threshold: None, price_change_threshold: None, fulfilment: false, lifetime: Duration::hours(24) }
+    }
+}
+
+impl ApprovalPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.credit_threshold.is_some() || self.price_change_threshold.is_some() || self.fulfilment
+    }
+
+    /// Whether a single credit note of `amount` needs approval. Earlier notes to the same customer are not counted.
+    pub fn credit_needs_approval(&self, amount: MicroTari) -> bool {
+        self.credit_threshold.is_some_and(|threshold| amount >= threshold)
+    }
+
+    pub fn price_change_needs_approval(&self, old_price: MicroTari, new_price: MicroTari) -> bool {
+        let Some(threshold) = self.price_change_threshold else {
+            return false;
+        };
+        if old_price == new_price {
+            return false;
+        }
+        let change = u128::from((new_price.value() - old_price.value()).unsigned_abs()) * 100;
+        change >= u128::from(threshold) * u128::from(old_price.value().unsigned_abs())
+    }
+
+    pub fn fulfilment_needs_approval(&self) -> bool {
+        self.fulfilment
+    }
+
+    /// When a request made now expires
+    pub fn expiry(&self) -> DateTime<Utc> {
+        Utc::now() + self.lifetime
+    }
+}
+
+impl Display for ApprovalPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.is_enabled() {
+            return write!(f, "no approvals needed");
+        }
+        let mut rules = Vec::new();
+        if let Some(threshold) = self.credit_threshold {
+            rules.push(format!("credit from {threshold}"));
+        }
+        if let Some(threshold) = self.price_change_threshold {
+            rules.push(format!("price changes from {threshold}%"));
+        }
+        if self.fulfilment {
+            rules.push("manual fulfilment".to_string());
+        }
+        write!(f, "{} need approval within {} hours", rules.join(", "), self.lifetime.num_hours())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn thresholds() {
+        let policy = ApprovalPolicy {
+            credit_threshold: Some(MicroTari::from_tari(1_000)),
+            price_change_threshold: Some(20),
+            ..Default::default()
+        };
+        assert!(policy.is_enabled());
+        assert!(!policy.credit_needs_approval(MicroTari::from_tari(999)));
+        assert!(policy.credit_needs_approval(MicroTari::from_tari(1_000)));
+        let price = MicroTari::from_tari(100);
+        assert!(!policy.price_change_needs_approval(price, MicroTari::from_tari(119)));
+        assert!(policy.price_change_needs_approval(price, MicroTari::from_tari(120)));
+        assert!(policy.price_change_needs_approval(price, MicroTari::from_tari(80)));
+        assert!(!policy.price_change_needs_approval(price, MicroTari::from_tari(81)));
+        assert!(policy.price_change_needs_approval(MicroTari::from(0), MicroTari::from(1)));
+        assert!(!policy.fulfilment_needs_approval());
+        let policy = ApprovalPolicy::default();
+        assert!(!policy.is_enabled());
+        assert!(!policy.credit_needs_approval(MicroTari::from_tari(1_000_000)));
+        assert!(!policy.price_change_needs_approval(price, MicroTari::from(0)));
+    }
+}
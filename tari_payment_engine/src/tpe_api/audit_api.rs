@@ -0,0 +1,40 @@
+//! The AuditApi records the privileged calls that admins make to the server, and lets admins search the record.
+
+use std::fmt::Debug;
+
+use crate::{
+    db_types::{AdminAction, AdminActionFilter, NewAdminAction},
+    tpe_api::account_objects::Pagination,
+    traits::{AuditLog, AuditLogError},
+};
+
+pub struct AuditApi<B> {
+    db: B,
+}
+
+impl<B> Debug for AuditApi<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AuditApi")
+    }
+}
+
+impl<B> AuditApi<B>
+where B: AuditLog
+{
+    pub fn new(db: B) -> Self {
+        Self { db }
+    }
+
+    pub async fn record_admin_action(&self, action: &NewAdminAction) -> Result<AdminAction, AuditLogError> {
+        self.db.record_admin_action(action).await
+    }
+
+    /// Searches the audit log. The most recent actions are returned first.
+    pub async fn fetch_admin_actions(
+        &self,
+        filter: &AdminActionFilter,
+        pagination: &Pagination,
+    ) -> Result<Vec<AdminAction>, AuditLogError> {
+        self.db.fetch_admin_actions(filter, pagination).await
+    }
+}
@@ -1,9 +1,10 @@
 use std::fmt::Debug;
 
+use chrono::{DateTime, Utc};
 use tari_common_types::tari_address::TariAddress;
 
 use crate::{
-    db_types::Role,
+    db_types::{AuthSession, NewAuthSession, Role, RoleDefinition},
     traits::{AuthApiError, AuthManagement},
 };
 
@@ -49,4 +50,83 @@ where B: AuthManagement
     pub async fn remove_roles(&self, address: &TariAddress, roles: &[Role]) -> Result<u64, AuthApiError> {
         self.db.remove_roles(address, roles).await
     }
+
+    pub async fn check_address_has_merchant_roles(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+        roles: &[Role],
+    ) -> Result<(), AuthApiError> {
+        self.db.check_address_has_merchant_roles(address, merchant_id, roles).await
+    }
+
+    pub async fn fetch_merchant_roles_for_address(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+    ) -> Result<Vec<Role>, AuthApiError> {
+        self.db.fetch_merchant_roles_for_address(address, merchant_id).await
+    }
+
+    pub async fn assign_merchant_roles(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+        roles: &[Role],
+    ) -> Result<(), AuthApiError> {
+        self.db.assign_merchant_roles(address, merchant_id, roles).await
+    }
+
+    pub async fn remove_merchant_roles(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+        roles: &[Role],
+    ) -> Result<u64, AuthApiError> {
+        self.db.remove_merchant_roles(address, merchant_id, roles).await
+    }
+
+    pub async fn fetch_role_definitions(&self) -> Result<Vec<RoleDefinition>, AuthApiError> {
+        self.db.fetch_role_definitions().await
+    }
+
+    pub async fn save_role(&self, role: &RoleDefinition) -> Result<(), AuthApiError> {
+        self.db.save_role(role).await
+    }
+
+    pub async fn delete_role(&self, role: &Role) -> Result<(), AuthApiError> {
+        self.db.delete_role(role).await
+    }
+
+    pub async fn create_session(&self, session: NewAuthSession) -> Result<AuthSession, AuthApiError> {
+        self.db.create_session(session).await
+    }
+
+    pub async fn fetch_session(&self, id: i64) -> Result<Option<AuthSession>, AuthApiError> {
+        self.db.fetch_session(id).await
+    }
+
+    pub async fn refresh_session(
+        &self,
+        refresh_token_hash: &str,
+        new_refresh_token_hash: &str,
+    ) -> Result<AuthSession, AuthApiError> {
+        self.db.refresh_session(refresh_token_hash, new_refresh_token_hash).await
+    }
+
+    pub async fn fetch_sessions_for_address(&self, address: &TariAddress) -> Result<Vec<AuthSession>, AuthApiError> {
+        self.db.fetch_sessions_for_address(address).await
+    }
+
+    pub async fn revoke_session(&self, id: i64) -> Result<AuthSession, AuthApiError> {
+        self.db.revoke_session(id).await
+    }
+
+    pub async fn revoke_sessions_for_address(&self, address: &TariAddress) -> Result<Vec<i64>, AuthApiError> {
+        self.db.revoke_sessions_for_address(address).await
+    }
+
+    pub async fn fetch_revoked_sessions(&self, since: DateTime<Utc>) -> Result<Vec<i64>, AuthApiError> {
+        self.db.fetch_revoked_sessions(since).await
+    }
 }
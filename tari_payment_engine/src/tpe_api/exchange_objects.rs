@@ -1,15 +1,25 @@
 use std::fmt::Display;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use sqlx::FromRow;
 use tpg_common::MicroTari;
 
+use crate::db_types::default_merchant;
+
 #[derive(Debug, Clone, FromRow)]
 pub struct ExchangeRate {
+    /// The id of the stored rate. Rates that have not been stored have an id of zero.
+    pub id: i64,
     pub base_currency: String,
     /// the exchange rate, in hundredths of the base unit (i.e. how many Tari in one cent of the base currency)
     pub rate: MicroTari,
     pub updated_at: DateTime<Utc>,
+    /// When a rate source last reported this rate. It is the same as `updated_at` until the rate feed polls the same
+    /// rate again.
+    pub confirmed_at: DateTime<Utc>,
+    /// The merchant that set the rate. Rates for the default merchant apply to every merchant that has not set its
+    /// own.
+    pub merchant_id: String,
 }
 
 impl ExchangeRate {
@@ -18,7 +28,20 @@ impl ExchangeRate {
     /// *NB* The rate is in hundreds of the base unit (i.e. how many microTari in one cent of the base currency)
     pub fn new(currency: String, rate_per_cent: MicroTari, updated_at: Option<DateTime<Utc>>) -> Self {
         let updated_at = updated_at.unwrap_or_else(Utc::now);
-        Self { base_currency: currency, rate: rate_per_cent, updated_at }
+        Self {
+            id: 0,
+            base_currency: currency,
+            rate: rate_per_cent,
+            updated_at,
+            confirmed_at: updated_at,
+            merchant_id: default_merchant(),
+        }
+    }
+
+    /// Sets the rate for the given merchant only
+    pub fn for_merchant<S: Into<String>>(mut self, merchant_id: S) -> Self {
+        self.merchant_id = merchant_id.into();
+        self
     }
 
     /// Create a new ExchangeRate object with a rate of 1 base unit per Tari
@@ -35,6 +58,120 @@ impl ExchangeRate {
     pub fn convert_to_tari_from_cents(&self, cents: i64) -> MicroTari {
         MicroTari::from(self.rate.value() * cents / 100)
     }
+
+    /// Convert a Tari amount to base currency cents, rounding to the nearest cent. A zero rate converts to zero.
+    pub fn convert_to_cents(&self, amount: MicroTari) -> i64 {
+        let rate = i128::from(self.rate.value());
+        if rate == 0 {
+            return 0;
+        }
+        let scaled = i128::from(amount.value()) * 100;
+        let cents = (2 * scaled + scaled.signum() * rate) / (2 * rate);
+        i64::try_from(cents).unwrap_or(if cents < 0 { i64::MIN } else { i64::MAX })
+    }
+
+    /// Converts a Tari price that was calculated at the `quoted` rate into the equivalent price at this rate
+    pub fn reprice(&self, price: MicroTari, quoted: &ExchangeRate) -> MicroTari {
+        if quoted.rate.value() == 0 {
+            return price;
+        }
+        let value = i128::from(price.value()) * i128::from(self.rate.value()) / i128::from(quoted.rate.value());
+        MicroTari::from(i64::try_from(value).unwrap_or(i64::MAX))
+    }
+}
+
+/// What happens to an unpaid order when its exchange rate quote expires
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuoteExpiryAction {
+    /// Re-price the order at the current exchange rate, and give it a new quote
+    #[default]
+    Reprice,
+    /// Mark the order as expired
+    Expire,
+}
+
+impl Display for QuoteExpiryAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Reprice => write!(f, "re-price"),
+            Self::Expire => write!(f, "expire"),
+        }
+    }
+}
+
+/// Orders that are priced in another currency are only payable at the quoted exchange rate for `lifetime`. After
+/// that, `on_expiry` decides what happens to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotePolicy {
+    pub lifetime: Duration,
+    pub on_expiry: QuoteExpiryAction,
+}
+
+impl QuotePolicy {
+    pub fn new(lifetime: Duration, on_expiry: QuoteExpiryAction) -> Self {
+        Self { lifetime, on_expiry }
+    }
+
+    /// When a quote issued now will expire
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        Utc::now() + self.lifetime
+    }
+}
+
+/// Protects new orders from being priced at an exchange rate that is out of date, or that has just moved by an unusual
+/// amount (e.g. because a price feed misbehaved). When the breaker trips, new orders are held until an admin releases
+/// them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RateCircuitBreaker {
+    /// Rates that were last confirmed by a rate source longer ago than this are stale
+    pub max_age: Option<Duration>,
+    /// The largest change from the previous rate, in percent, that is accepted without review
+    pub max_change_pct: Option<i64>,
+}
+
+impl RateCircuitBreaker {
+    pub fn new(max_age: Option<Duration>, max_change_pct: Option<i64>) -> Self {
+        Self { max_age, max_change_pct }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_age.is_some() || self.max_change_pct.is_some()
+    }
+
+    /// Checks whether `rate` can be used to price new orders. `previous` is the rate that `rate` replaced, if any.
+    ///
+    /// Returns the reason the breaker tripped, or `None` if the rate is fine.
+    pub fn check(&self, rate: &ExchangeRate, previous: Option<&ExchangeRate>) -> Option<String> {
+        let currency = &rate.base_currency;
+        if let Some(max_age) = self.max_age {
+            let age = Utc::now() - rate.confirmed_at;
+            if age > max_age {
+                return Some(format!(
+                    "The {currency} exchange rate was last confirmed {} minutes ago. The limit is {} minutes.",
+                    age.num_minutes(),
+                    max_age.num_minutes()
+                ));
+            }
+        }
+        let (Some(max_change), Some(previous)) = (self.max_change_pct, previous) else {
+            return None;
+        };
+        let old = i128::from(previous.rate.value());
+        if old == 0 {
+            return None;
+        }
+        let change = (i128::from(rate.rate.value()) - old).abs();
+        if change * 100 > old * i128::from(max_change) {
+            return Some(format!(
+                "The {currency} exchange rate changed by {}% (from {} to {}) in its last update. The limit is \
+                 {max_change}%.",
+                change * 100 / old,
+                previous.rate,
+                rate.rate
+            ));
+        }
+        None
+    }
 }
 
 impl Display for ExchangeRate {
@@ -45,7 +182,7 @@ impl Display for ExchangeRate {
 
 impl Default for ExchangeRate {
     fn default() -> Self {
-        Self { base_currency: "XTR".to_string(), rate: MicroTari::from_tari(1), updated_at: Utc::now() }
+        Self::parity("XTR".to_string())
     }
 }
 
@@ -77,4 +214,60 @@ mod test {
         assert_eq!(rate.convert_to_tari(1), MicroTari::from_tari(1));
         assert_eq!(format!("{rate}"), "1 USD => 1.000τ");
     }
+
+    #[test]
+    fn convert_to_cents() {
+        // 50 XTR/$
+        let rate = ExchangeRate::new("USD".to_string(), MicroTari::from_tari(50), None);
+        assert_eq!(rate.convert_to_cents(MicroTari::from_tari(250)), 500);
+        assert_eq!(rate.convert_to_cents(rate.convert_to_tari_from_cents(1234)), 1234);
+        // Half a cent is 250,000 microTari
+        assert_eq!(rate.convert_to_cents(MicroTari::from(249_999)), 0);
+        assert_eq!(rate.convert_to_cents(MicroTari::from(250_000)), 1);
+        assert_eq!(rate.convert_to_cents(MicroTari::from(-250_000)), -1);
+        assert_eq!(rate.convert_to_cents(MicroTari::from(200_000)), 0);
+        let zero = ExchangeRate::new("USD".to_string(), MicroTari::from(0), None);
+        assert_eq!(zero.convert_to_cents(MicroTari::from_tari(300)), 0);
+    }
+
+    #[test]
+    fn circuit_breaker() {
+        let rate = |tari: i64, minutes_ago: i64| {
+            let updated_at = Utc::now() - Duration::minutes(minutes_ago);
+            ExchangeRate::new("USD".to_string(), MicroTari::from_tari(tari), Some(updated_at))
+        };
+        let breaker = RateCircuitBreaker::default();
+        assert!(!breaker.is_enabled());
+        assert!(breaker.check(&rate(100, 10_000), Some(&rate(1, 20_000))).is_none());
+
+        let breaker = RateCircuitBreaker::new(Some(Duration::minutes(60)), Some(10));
+        assert!(breaker.is_enabled());
+        assert!(breaker.check(&rate(100, 5), None).is_none());
+        assert!(breaker.check(&rate(100, 5), Some(&rate(91, 90))).is_none());
+        assert!(breaker.check(&rate(100, 5), Some(&rate(110, 90))).is_none());
+        let reason = breaker.check(&rate(100, 61), None).unwrap();
+        assert!(reason.starts_with("The USD exchange rate was last confirmed 61 minutes ago."), "{reason}");
+        // An old rate that a rate source has just confirmed is not stale
+        let mut confirmed = rate(100, 120);
+        confirmed.confirmed_at = Utc::now() - Duration::minutes(5);
+        assert!(breaker.check(&confirmed, None).is_none());
+        let reason = breaker.check(&rate(100, 5), Some(&rate(80, 90))).unwrap();
+        assert!(reason.starts_with("The USD exchange rate changed by 25%"), "{reason}");
+        assert!(breaker.check(&rate(100, 5), Some(&rate(0, 90))).is_none());
+    }
+
+    #[test]
+    fn test_reprice() {
+        let quoted = ExchangeRate::new("USD".to_string(), MicroTari::from_tari(50), None);
+        let rate = ExchangeRate::new("USD".to_string(), MicroTari::from_tari(40), None);
+        let price = quoted.convert_to_tari_from_cents(1250);
+        assert_eq!(rate.reprice(price, &quoted), rate.convert_to_tari_from_cents(1250));
+        assert_eq!(quoted.reprice(price, &quoted), price);
+        // 3 XTR/c to 7 XTR/c
+        let quoted = ExchangeRate::new("USD".to_string(), MicroTari::from_tari(3), None);
+        let rate = ExchangeRate::new("USD".to_string(), MicroTari::from_tari(7), None);
+        assert_eq!(rate.reprice(MicroTari::from_tari(300), &quoted), MicroTari::from_tari(700));
+        let zero = ExchangeRate::new("USD".to_string(), MicroTari::from(0), None);
+        assert_eq!(rate.reprice(MicroTari::from_tari(300), &zero), MicroTari::from_tari(300));
+    }
 }
@@ -3,8 +3,13 @@
 
 use std::fmt::Debug;
 
+use chrono::{DateTime, Utc};
+
 use crate::{
-    tpe_api::exchange_objects::ExchangeRate,
+    tpe_api::{
+        account_objects::Pagination,
+        exchange_objects::{ExchangeRate, RateCircuitBreaker},
+    },
     traits::{ExchangeRateError, ExchangeRates},
 };
 
@@ -25,10 +30,57 @@ where B: ExchangeRates
         Self { db }
     }
 
+    pub fn db(&self) -> &B {
+        &self.db
+    }
+
     pub async fn fetch_last_rate(&self, currency: &str) -> Result<ExchangeRate, ExchangeRateError> {
         self.db.fetch_last_rate(currency).await
     }
 
+    /// The rate that applies to `merchant_id`'s orders: its own rate if it has set one, otherwise the default rate.
+    pub async fn fetch_merchant_rate(
+        &self,
+        merchant_id: &str,
+        currency: &str,
+    ) -> Result<ExchangeRate, ExchangeRateError> {
+        self.db.fetch_merchant_rate(merchant_id, currency).await
+    }
+
+    /// The rate that applied at `timestamp`. Use this to reconstruct the rate that an order was priced at.
+    pub async fn fetch_rate_at(
+        &self,
+        currency: &str,
+        timestamp: DateTime<Utc>,
+    ) -> Result<ExchangeRate, ExchangeRateError> {
+        self.db.fetch_rate_at(currency, timestamp).await
+    }
+
+    /// Checks whether `rate` trips the circuit breaker, and returns the reason if it does. The previous rate is only
+    /// fetched if the breaker limits the change between rates.
+    pub async fn check_circuit_breaker(
+        &self,
+        rate: &ExchangeRate,
+        breaker: &RateCircuitBreaker,
+    ) -> Result<Option<String>, ExchangeRateError> {
+        let previous = match breaker.max_change_pct {
+            Some(_) => self.db.fetch_previous_rate(rate).await?,
+            None => None,
+        };
+        Ok(breaker.check(rate, previous.as_ref()))
+    }
+
+    pub async fn fetch_rate_history(
+        &self,
+        merchant_id: &str,
+        currency: &str,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        pagination: &Pagination,
+    ) -> Result<Vec<ExchangeRate>, ExchangeRateError> {
+        self.db.fetch_rate_history(merchant_id, currency, since, until, pagination).await
+    }
+
     pub async fn set_exchange_rate(&self, rate: &ExchangeRate) -> Result<(), ExchangeRateError> {
         self.db.set_exchange_rate(rate).await
     }
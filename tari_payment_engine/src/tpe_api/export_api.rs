@@ -0,0 +1,210 @@
+//! The ExportApi produces accounting reports of orders, payments, settlements and credit notes.
+//!
+//! Reports cover a half-open period `[from, to)`, and are rendered in one of the formats in
+//! [`crate::tpe_api::export_objects::ReportFormat`].
+//!
+//! Every record is valued in fiat at the exchange rate that was locked in when the order was priced:
+//! * Records that are tied to an order that was priced in a fiat currency use the order's rate. This is the rate that
+//!   the order's price quote was made at, or the rate that was current when the order was created.
+//! * Other records (e.g. payments that have not been applied to an order, and credit notes) are valued in the request's
+//!   currency, at the rate that was current when the record was created.
+//!
+//! If no such rate exists, the fiat columns are left empty.
+use std::{collections::HashMap, fmt::Debug};
+
+use chrono::{DateTime, Utc};
+use log::*;
+use tpg_common::{MicroTari, TARI_CURRENCY_CODE};
+
+use crate::{
+    db_types::{Order, OrderId, PaymentType},
+    tpe_api::{
+        exchange_objects::ExchangeRate,
+        export_objects::{
+            render,
+            CreditNoteRecord,
+            ExportError,
+            ExportRecord,
+            ExportRequest,
+            ExportedReport,
+            FiatValue,
+            OrderRecord,
+            PaymentRecord,
+            ReportType,
+            SettlementRecord,
+        },
+    },
+    traits::{AccountManagement, ExchangeRateError, ExchangeRates},
+};
+
+pub struct ExportApi<B, R> {
+    accounts: B,
+    rates: R,
+}
+
+impl<B, R> Debug for ExportApi<B, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ExportApi")
+    }
+}
+
+impl<B, R> ExportApi<B, R>
+where
+    B: AccountManagement,
+    R: ExchangeRates,
+{
+    pub fn new(accounts: B, rates: R) -> Self {
+        Self { accounts, rates }
+    }
+
+    /// Produces the requested report. The period must not be empty.
+    pub async fn export(&self, request: &ExportRequest) -> Result<ExportedReport, ExportError> {
+        if request.from >= request.to {
+            return Err(ExportError::InvalidPeriod(format!(
+                "The start of the period ({}) must be before the end ({})",
+                request.from, request.to
+            )));
+        }
+        let mut valuer = Valuer::new(self, request.currency.clone());
+        let (body, record_count) = match request.report {
+            ReportType::Orders => rendered(&valuer.order_records(request).await?, request)?,
+            ReportType::Payments => rendered(&valuer.payment_records(request).await?, request)?,
+            ReportType::Settlements => rendered(&valuer.settlement_records(request).await?, request)?,
+            ReportType::CreditNotes => rendered(&valuer.credit_note_records(request).await?, request)?,
+        };
+        debug!(
+            "📋️ Exported {record_count} records for the {} report from {} to {}",
+            request.report, request.from, request.to
+        );
+        Ok(ExportedReport {
+            file_name: request.file_name(),
+            content_type: request.format.content_type().to_string(),
+            record_count,
+            body,
+        })
+    }
+}
+
+fn rendered<T: ExportRecord>(records: &[T], request: &ExportRequest) -> Result<(String, usize), ExportError> {
+    Ok((render(records, request)?, records.len()))
+}
+
+/// Works out the fiat value of records. The orders and rates that it looks up are cached for the length of the export.
+struct Valuer<'a, B, R> {
+    api: &'a ExportApi<B, R>,
+    currency: Option<String>,
+    orders: HashMap<String, Option<Order>>,
+    rates: HashMap<i64, Option<ExchangeRate>>,
+}
+
+impl<'a, B, R> Valuer<'a, B, R>
+where
+    B: AccountManagement,
+    R: ExchangeRates,
+{
+    fn new(api: &'a ExportApi<B, R>, currency: Option<String>) -> Self {
+        Self { api, currency, orders: HashMap::new(), rates: HashMap::new() }
+    }
+
+    async fn order_records(&mut self, request: &ExportRequest) -> Result<Vec<OrderRecord>, ExportError> {
+        let orders = self.api.accounts.fetch_orders_between(request.from, request.to).await?;
+        let mut records = Vec::with_capacity(orders.len());
+        for order in orders {
+            self.orders.insert(order.order_id.to_string(), Some(order.clone()));
+            let fiat = self.value(Some(&order.order_id), order.total_price, order.created_at).await?;
+            records.push(OrderRecord::new(order, fiat));
+        }
+        Ok(records)
+    }
+
+    async fn payment_records(&mut self, request: &ExportRequest) -> Result<Vec<PaymentRecord>, ExportError> {
+        let payments = self.api.accounts.fetch_payments_between(request.from, request.to).await?;
+        let mut records = Vec::with_capacity(payments.len());
+        for payment in payments.into_iter().filter(|p| p.payment_type == PaymentType::OnChain) {
+            let fiat = self.value(payment.order_id.as_ref(), payment.amount, payment.created_at).await?;
+            records.push(PaymentRecord::new(payment, fiat));
+        }
+        Ok(records)
+    }
+
+    async fn credit_note_records(&mut self, request: &ExportRequest) -> Result<Vec<CreditNoteRecord>, ExportError> {
+        let payments = self.api.accounts.fetch_payments_between(request.from, request.to).await?;
+        let mut records = Vec::new();
+        for payment in payments.into_iter().filter(|p| p.payment_type == PaymentType::Manual) {
+            let customer_ids = self.api.accounts.fetch_customer_ids_for_address(payment.sender.as_address()).await?;
+            let fiat = self.value(payment.order_id.as_ref(), payment.amount, payment.created_at).await?;
+            records.push(CreditNoteRecord::new(payment, &customer_ids, fiat));
+        }
+        Ok(records)
+    }
+
+    async fn settlement_records(&mut self, request: &ExportRequest) -> Result<Vec<SettlementRecord>, ExportError> {
+        let entries = self.api.accounts.fetch_settlements_between(request.from, request.to).await?;
+        let mut records = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let merchant_id = self.order(&entry.order_id).await?.map(|o| o.merchant_id);
+            let fiat = self.value(Some(&entry.order_id), entry.amount, entry.created_at).await?;
+            records.push(SettlementRecord::new(entry, merchant_id, fiat));
+        }
+        Ok(records)
+    }
+
+    /// The fiat value of `amount`. It uses the locked rate of the order, if there is one, and otherwise the rate for
+    /// the request's currency at `timestamp`.
+    async fn value(
+        &mut self,
+        order_id: Option<&OrderId>,
+        amount: MicroTari,
+        timestamp: DateTime<Utc>,
+    ) -> Result<Option<FiatValue>, ExportError> {
+        let order = match order_id {
+            Some(id) => self.order(id).await?,
+            None => None,
+        };
+        if let Some(order) = order {
+            if let Some(rate) = self.locked_rate(&order).await? {
+                return Ok(Some(FiatValue::at_rate(amount, &rate)));
+            }
+        }
+        let Some(currency) = self.currency.clone() else {
+            return Ok(None);
+        };
+        let rate = optional(self.api.rates.fetch_rate_at(&currency, timestamp).await)?;
+        Ok(rate.map(|r| FiatValue::at_rate(amount, &r)))
+    }
+
+    async fn order(&mut self, order_id: &OrderId) -> Result<Option<Order>, ExportError> {
+        let key = order_id.to_string();
+        if let Some(order) = self.orders.get(&key) {
+            return Ok(order.clone());
+        }
+        let order = self.api.accounts.fetch_order_by_order_id(order_id).await?;
+        self.orders.insert(key, order.clone());
+        Ok(order)
+    }
+
+    /// The rate that an order was priced at. Orders that were priced in Tari have no locked rate.
+    async fn locked_rate(&mut self, order: &Order) -> Result<Option<ExchangeRate>, ExportError> {
+        if order.currency.eq_ignore_ascii_case(TARI_CURRENCY_CODE) {
+            return Ok(None);
+        }
+        let Some(rate_id) = order.rate_id else {
+            return optional(self.api.rates.fetch_rate_at(&order.currency, order.created_at).await);
+        };
+        if let Some(rate) = self.rates.get(&rate_id) {
+            return Ok(rate.clone());
+        }
+        let rate = optional(self.api.rates.fetch_rate_by_id(rate_id).await)?;
+        self.rates.insert(rate_id, rate.clone());
+        Ok(rate)
+    }
+}
+
+/// A missing rate means that the record has no fiat value, rather than that the export failed
+fn optional(result: Result<ExchangeRate, ExchangeRateError>) -> Result<Option<ExchangeRate>, ExportError> {
+    match result {
+        Ok(rate) => Ok(Some(rate)),
+        Err(ExchangeRateError::RateDoesNotExist(_)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
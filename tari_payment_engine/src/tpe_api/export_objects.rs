@@ -0,0 +1,810 @@
+//! Data types for the accounting exports.
+//!
+//! A report covers one type of record ([`ReportType`]) over a half-open period `[from, to)`, and can be rendered as
+//! CSV, OFX or JSON Lines ([`ReportFormat`]). Each record type has a fixed set of columns ([`ExportRecord::COLUMNS`]).
+//! New columns are only ever appended, so that bookkeeping tools can rely on the column order.
+//!
+//! Tari amounts are written in Tari, with six decimal places, and fiat amounts in the fiat currency, with two decimal
+//! places. Both are written as text, so that no precision is lost to floating point.
+use std::{fmt::Display, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, Serializer};
+use thiserror::Error;
+use tpg_common::{MicroTari, TARI_CURRENCY_CODE};
+
+use crate::{
+    db_types::{ConversionError, Order, Payment, SettlementJournalEntry, TransferStatus},
+    tpe_api::exchange_objects::ExchangeRate,
+    traits::{AccountApiError, ExchangeRateError},
+};
+
+#[derive(Debug, Clone, Error)]
+pub enum ExportError {
+    #[error("Invalid reporting period. {0}")]
+    InvalidPeriod(String),
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+    #[error("The report could not be rendered. {0}")]
+    RenderError(String),
+}
+
+impl From<AccountApiError> for ExportError {
+    fn from(e: AccountApiError) -> Self {
+        Self::DatabaseError(e.to_string())
+    }
+}
+
+impl From<ExchangeRateError> for ExportError {
+    fn from(e: ExchangeRateError) -> Self {
+        Self::DatabaseError(e.to_string())
+    }
+}
+
+//--------------------------------------    Report requests    --------------------------------------------------------
+
+/// The records that a report covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportType {
+    /// Orders, by the time they were created
+    Orders,
+    /// On-chain payments, by the time they were received
+    Payments,
+    /// Settlement journal entries, i.e. payments being applied to orders
+    Settlements,
+    /// Credit notes, i.e. manual payments
+    CreditNotes,
+}
+
+impl Display for ReportType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReportType::Orders => write!(f, "orders"),
+            ReportType::Payments => write!(f, "payments"),
+            ReportType::Settlements => write!(f, "settlements"),
+            ReportType::CreditNotes => write!(f, "credit_notes"),
+        }
+    }
+}
+
+impl FromStr for ReportType {
+    type Err = ConversionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "orders" => Ok(Self::Orders),
+            "payments" => Ok(Self::Payments),
+            "settlements" => Ok(Self::Settlements),
+            "credit_notes" => Ok(Self::CreditNotes),
+            s => Err(ConversionError(format!("Invalid report type: {s}"))),
+        }
+    }
+}
+
+/// The file format of a report
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    #[default]
+    Csv,
+    /// OFX 2.2, as a bank statement for an account held in Tari
+    Ofx,
+    /// One JSON object per line
+    #[serde(rename = "jsonl")]
+    JsonLines,
+}
+
+impl ReportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "text/csv",
+            ReportFormat::Ofx => "application/x-ofx",
+            ReportFormat::JsonLines => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "csv",
+            ReportFormat::Ofx => "ofx",
+            ReportFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+impl Display for ReportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+impl FromStr for ReportFormat {
+    type Err = ConversionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "ofx" => Ok(Self::Ofx),
+            "jsonl" => Ok(Self::JsonLines),
+            s => Err(ConversionError(format!("Invalid report format: {s}"))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportRequest {
+    pub report: ReportType,
+    #[serde(default)]
+    pub format: ReportFormat,
+    /// The start of the period, inclusive
+    pub from: DateTime<Utc>,
+    /// The end of the period, exclusive
+    pub to: DateTime<Utc>,
+    /// The fiat currency to value records in when they are not tied to an order that was priced in fiat, e.g.
+    /// payments that have not been applied to an order yet. If `None`, such records have no fiat value.
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+impl ExportRequest {
+    pub fn new(report: ReportType, format: ReportFormat, from: DateTime<Utc>, to: DateTime<Utc>) -> Self {
+        Self { report, format, from, to, currency: None }
+    }
+
+    pub fn with_currency<S: Into<String>>(mut self, currency: S) -> Self {
+        self.currency = Some(currency.into());
+        self
+    }
+
+    /// A file name for the report, e.g. `payments_20240601_20240701.csv`
+    pub fn file_name(&self) -> String {
+        format!(
+            "{}_{}_{}.{}",
+            self.report,
+            self.from.format("%Y%m%d"),
+            self.to.format("%Y%m%d"),
+            self.format.extension()
+        )
+    }
+}
+
+/// A rendered report, ready to be saved or sent as a download
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedReport {
+    pub file_name: String,
+    pub content_type: String,
+    pub record_count: usize,
+    pub body: String,
+}
+
+//--------------------------------------        Records        --------------------------------------------------------
+
+/// The fiat value of a Tari amount, at the exchange rate that applies to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FiatValue {
+    pub currency: String,
+    pub cents: i64,
+    pub rate_id: i64,
+    /// The rate, in µT per unit of the currency
+    pub rate: MicroTari,
+}
+
+impl FiatValue {
+    pub fn at_rate(amount: MicroTari, rate: &ExchangeRate) -> Self {
+        Self {
+            currency: rate.base_currency.clone(),
+            cents: rate.convert_to_cents(amount),
+            rate_id: rate.id,
+            rate: rate.rate,
+        }
+    }
+}
+
+/// The columns shared by every record type that describe the fiat value of the amount. They are empty if the record
+/// has no fiat value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct FiatColumns {
+    currency: Option<String>,
+    amount: Option<i64>,
+    rate_id: Option<i64>,
+    rate: Option<MicroTari>,
+}
+
+impl From<Option<FiatValue>> for FiatColumns {
+    fn from(value: Option<FiatValue>) -> Self {
+        match value {
+            Some(v) => {
+                Self { currency: Some(v.currency), amount: Some(v.cents), rate_id: Some(v.rate_id), rate: Some(v.rate) }
+            },
+            None => Self::default(),
+        }
+    }
+}
+
+/// A transaction on an OFX statement
+#[derive(Debug, Clone)]
+pub struct OfxTransaction {
+    /// Unique within the report
+    pub fitid: String,
+    pub posted: DateTime<Utc>,
+    /// Positive amounts are credits and negative amounts are debits
+    pub amount: MicroTari,
+    pub name: String,
+    pub memo: Option<String>,
+    /// The fiat currency and rate (in µT per unit) that the amount was valued at
+    pub original_currency: Option<(String, MicroTari)>,
+}
+
+/// A row in a report
+pub trait ExportRecord: Serialize {
+    /// The column names, in the order that the fields are serialized
+    const COLUMNS: &'static [&'static str];
+
+    /// The record as a transaction on an OFX statement. Records that did not move any funds, such as cancelled
+    /// payments, are left off the statement.
+    fn ofx_transaction(&self) -> Option<OfxTransaction>;
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderRecord {
+    pub created_at: DateTime<Utc>,
+    pub order_id: String,
+    pub alt_id: Option<String>,
+    pub customer_id: String,
+    pub merchant_id: String,
+    pub status: String,
+    pub original_price: Option<String>,
+    pub currency: String,
+    #[serde(rename = "amount_xtr", serialize_with = "serialize_tari")]
+    pub amount: MicroTari,
+    fiat_currency: Option<String>,
+    #[serde(rename = "fiat_amount", serialize_with = "serialize_cents")]
+    fiat_amount: Option<i64>,
+    #[serde(rename = "exchange_rate_id")]
+    rate_id: Option<i64>,
+    #[serde(rename = "exchange_rate", serialize_with = "serialize_rate")]
+    rate: Option<MicroTari>,
+    pub memo: Option<String>,
+}
+
+impl OrderRecord {
+    pub fn new(order: Order, fiat: Option<FiatValue>) -> Self {
+        let fiat = FiatColumns::from(fiat);
+        Self {
+            created_at: order.created_at,
+            order_id: order.order_id.to_string(),
+            alt_id: order.alt_id.map(|id| id.to_string()),
+            customer_id: order.customer_id,
+            merchant_id: order.merchant_id,
+            status: order.status.to_string(),
+            original_price: order.original_price,
+            currency: order.currency,
+            amount: order.total_price,
+            fiat_currency: fiat.currency,
+            fiat_amount: fiat.amount,
+            rate_id: fiat.rate_id,
+            rate: fiat.rate,
+            memo: order.memo,
+        }
+    }
+}
+
+impl ExportRecord for OrderRecord {
+    const COLUMNS: &'static [&'static str] = &[
+        "created_at",
+        "order_id",
+        "alt_id",
+        "customer_id",
+        "merchant_id",
+        "status",
+        "original_price",
+        "currency",
+        "amount_xtr",
+        "fiat_currency",
+        "fiat_amount",
+        "exchange_rate_id",
+        "exchange_rate",
+        "memo",
+    ];
+
+    /// Orders are charges against the customer, so they are debits. Cancelled and expired orders are left off.
+    fn ofx_transaction(&self) -> Option<OfxTransaction> {
+        if matches!(self.status.as_str(), "Cancelled" | "Expired") {
+            return None;
+        }
+        Some(OfxTransaction {
+            fitid: format!("order-{}", self.order_id),
+            posted: self.created_at,
+            amount: -self.amount,
+            name: format!("Order {}", self.alt_id.as_ref().unwrap_or(&self.order_id)),
+            memo: self.memo.clone(),
+            original_currency: original_currency(self.fiat_currency.as_ref(), self.rate),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PaymentRecord {
+    pub created_at: DateTime<Utc>,
+    pub txid: String,
+    pub sender: String,
+    pub status: String,
+    pub confirmations: i64,
+    pub order_id: Option<String>,
+    #[serde(rename = "amount_xtr", serialize_with = "serialize_tari")]
+    pub amount: MicroTari,
+    fiat_currency: Option<String>,
+    #[serde(rename = "fiat_amount", serialize_with = "serialize_cents")]
+    fiat_amount: Option<i64>,
+    #[serde(rename = "exchange_rate_id")]
+    rate_id: Option<i64>,
+    #[serde(rename = "exchange_rate", serialize_with = "serialize_rate")]
+    rate: Option<MicroTari>,
+    pub memo: Option<String>,
+}
+
+impl PaymentRecord {
+    pub fn new(payment: Payment, fiat: Option<FiatValue>) -> Self {
+        let fiat = FiatColumns::from(fiat);
+        Self {
+            created_at: payment.created_at,
+            txid: payment.txid,
+            sender: payment.sender.as_base58(),
+            status: payment.status.to_string(),
+            confirmations: payment.confirmations,
+            order_id: payment.order_id.map(|id| id.to_string()),
+            amount: payment.amount,
+            fiat_currency: fiat.currency,
+            fiat_amount: fiat.amount,
+            rate_id: fiat.rate_id,
+            rate: fiat.rate,
+            memo: payment.memo,
+        }
+    }
+}
+
+impl ExportRecord for PaymentRecord {
+    const COLUMNS: &'static [&'static str] = &[
+        "created_at",
+        "txid",
+        "sender",
+        "status",
+        "confirmations",
+        "order_id",
+        "amount_xtr",
+        "fiat_currency",
+        "fiat_amount",
+        "exchange_rate_id",
+        "exchange_rate",
+        "memo",
+    ];
+
+    fn ofx_transaction(&self) -> Option<OfxTransaction> {
+        if self.status == TransferStatus::Cancelled.to_string() {
+            return None;
+        }
+        Some(OfxTransaction {
+            fitid: self.txid.clone(),
+            posted: self.created_at,
+            amount: self.amount,
+            name: format!("Payment from {}", self.sender),
+            memo: self.memo.clone(),
+            original_currency: original_currency(self.fiat_currency.as_ref(), self.rate),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CreditNoteRecord {
+    pub created_at: DateTime<Utc>,
+    pub txid: String,
+    pub address: String,
+    /// The customer ids linked to the address, separated by semicolons
+    pub customer_ids: String,
+    pub status: String,
+    #[serde(rename = "amount_xtr", serialize_with = "serialize_tari")]
+    pub amount: MicroTari,
+    fiat_currency: Option<String>,
+    #[serde(rename = "fiat_amount", serialize_with = "serialize_cents")]
+    fiat_amount: Option<i64>,
+    #[serde(rename = "exchange_rate_id")]
+    rate_id: Option<i64>,
+    #[serde(rename = "exchange_rate", serialize_with = "serialize_rate")]
+    rate: Option<MicroTari>,
+    pub reason: Option<String>,
+}
+
+impl CreditNoteRecord {
+    pub fn new(payment: Payment, customer_ids: &[String], fiat: Option<FiatValue>) -> Self {
+        let fiat = FiatColumns::from(fiat);
+        Self {
+            created_at: payment.created_at,
+            txid: payment.txid,
+            address: payment.sender.as_base58(),
+            customer_ids: customer_ids.join(";"),
+            status: payment.status.to_string(),
+            amount: payment.amount,
+            fiat_currency: fiat.currency,
+            fiat_amount: fiat.amount,
+            rate_id: fiat.rate_id,
+            rate: fiat.rate,
+            reason: payment.memo,
+        }
+    }
+}
+
+impl ExportRecord for CreditNoteRecord {
+    const COLUMNS: &'static [&'static str] = &[
+        "created_at",
+        "txid",
+        "address",
+        "customer_ids",
+        "status",
+        "amount_xtr",
+        "fiat_currency",
+        "fiat_amount",
+        "exchange_rate_id",
+        "exchange_rate",
+        "reason",
+    ];
+
+    fn ofx_transaction(&self) -> Option<OfxTransaction> {
+        if self.status == TransferStatus::Cancelled.to_string() {
+            return None;
+        }
+        Some(OfxTransaction {
+            fitid: self.txid.clone(),
+            posted: self.created_at,
+            amount: self.amount,
+            name: format!("Credit note for {}", self.customer_ids),
+            memo: self.reason.clone(),
+            original_currency: original_currency(self.fiat_currency.as_ref(), self.rate),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SettlementRecord {
+    pub created_at: DateTime<Utc>,
+    pub id: i64,
+    pub order_id: String,
+    pub merchant_id: Option<String>,
+    pub payment_address: String,
+    pub settlement_type: String,
+    #[serde(rename = "amount_xtr", serialize_with = "serialize_tari")]
+    pub amount: MicroTari,
+    fiat_currency: Option<String>,
+    #[serde(rename = "fiat_amount", serialize_with = "serialize_cents")]
+    fiat_amount: Option<i64>,
+    #[serde(rename = "exchange_rate_id")]
+    rate_id: Option<i64>,
+    #[serde(rename = "exchange_rate", serialize_with = "serialize_rate")]
+    rate: Option<MicroTari>,
+}
+
+impl SettlementRecord {
+    /// `merchant_id` is the merchant of the settled order, if the order still exists.
+    pub fn new(entry: SettlementJournalEntry, merchant_id: Option<String>, fiat: Option<FiatValue>) -> Self {
+        let fiat = FiatColumns::from(fiat);
+        Self {
+            created_at: entry.created_at,
+            id: entry.id,
+            order_id: entry.order_id.to_string(),
+            merchant_id,
+            payment_address: entry.payment_address.as_base58(),
+            settlement_type: entry.settlement_type.to_string(),
+            amount: entry.amount,
+            fiat_currency: fiat.currency,
+            fiat_amount: fiat.amount,
+            rate_id: fiat.rate_id,
+            rate: fiat.rate,
+        }
+    }
+}
+
+impl ExportRecord for SettlementRecord {
+    const COLUMNS: &'static [&'static str] = &[
+        "created_at",
+        "id",
+        "order_id",
+        "merchant_id",
+        "payment_address",
+        "settlement_type",
+        "amount_xtr",
+        "fiat_currency",
+        "fiat_amount",
+        "exchange_rate_id",
+        "exchange_rate",
+    ];
+
+    /// Settlements move funds into merchant revenue, so they are credits. Reversing entries have negative amounts,
+    /// and are debits.
+    fn ofx_transaction(&self) -> Option<OfxTransaction> {
+        Some(OfxTransaction {
+            fitid: format!("settlement-{}", self.id),
+            posted: self.created_at,
+            amount: self.amount,
+            name: format!("Settlement of order {}", self.order_id),
+            memo: Some(format!("{} settlement from {}", self.settlement_type, self.payment_address)),
+            original_currency: original_currency(self.fiat_currency.as_ref(), self.rate),
+        })
+    }
+}
+
+fn original_currency(currency: Option<&String>, rate: Option<MicroTari>) -> Option<(String, MicroTari)> {
+    currency.cloned().zip(rate)
+}
+
+//--------------------------------------       Formatting      --------------------------------------------------------
+
+/// Formats µT as Tari, with six decimal places, e.g. `-1.500000`
+pub fn format_tari(amount: MicroTari) -> String {
+    format_fixed(amount.value(), 1_000_000, 6)
+}
+
+/// Formats cents as a fiat amount, with two decimal places, e.g. `12.05`
+pub fn format_cents(cents: i64) -> String {
+    format_fixed(cents, 100, 2)
+}
+
+fn format_fixed(value: i64, scale: u64, decimals: usize) -> String {
+    let sign = if value < 0 { "-" } else { "" };
+    let abs = value.unsigned_abs();
+    format!("{sign}{}.{:0decimals$}", abs / scale, abs % scale)
+}
+
+fn serialize_tari<S: Serializer>(amount: &MicroTari, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&format_tari(*amount))
+}
+
+fn serialize_cents<S: Serializer>(cents: &Option<i64>, s: S) -> Result<S::Ok, S::Error> {
+    match cents {
+        Some(c) => s.serialize_str(&format_cents(*c)),
+        None => s.serialize_none(),
+    }
+}
+
+/// Rates are written in Tari per unit of the fiat currency
+fn serialize_rate<S: Serializer>(rate: &Option<MicroTari>, s: S) -> Result<S::Ok, S::Error> {
+    match rate {
+        Some(r) => s.serialize_str(&format_tari(*r)),
+        None => s.serialize_none(),
+    }
+}
+
+//--------------------------------------       Rendering       --------------------------------------------------------
+
+/// Renders the records in the requested format.
+pub fn render<R: ExportRecord>(records: &[R], request: &ExportRequest) -> Result<String, ExportError> {
+    match request.format {
+        ReportFormat::Csv => render_csv(records),
+        ReportFormat::JsonLines => render_json_lines(records),
+        ReportFormat::Ofx => Ok(render_ofx(records, request)),
+    }
+}
+
+/// The header is written from [`ExportRecord::COLUMNS`], so that it is present even if there are no records.
+fn render_csv<R: ExportRecord>(records: &[R]) -> Result<String, ExportError> {
+    let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+    writer.write_record(R::COLUMNS).map_err(|e| ExportError::RenderError(e.to_string()))?;
+    for record in records {
+        writer.serialize(record).map_err(|e| ExportError::RenderError(e.to_string()))?;
+    }
+    let data = writer.into_inner().map_err(|e| ExportError::RenderError(e.to_string()))?;
+    String::from_utf8(data).map_err(|e| ExportError::RenderError(e.to_string()))
+}
+
+fn render_json_lines<R: ExportRecord>(records: &[R]) -> Result<String, ExportError> {
+    let mut body = String::new();
+    for record in records {
+        let line = serde_json::to_string(record).map_err(|e| ExportError::RenderError(e.to_string()))?;
+        body.push_str(&line);
+        body.push('\n');
+    }
+    Ok(body)
+}
+
+/// OFX names are limited to 32 characters
+const OFX_NAME_LENGTH: usize = 32;
+
+/// Renders the records as an OFX 2.2 bank statement for an account held in Tari. The closing balance is the sum of the
+/// transactions on the statement.
+fn render_ofx<R: ExportRecord>(records: &[R], request: &ExportRequest) -> String {
+    let transactions = records.iter().filter_map(ExportRecord::ofx_transaction).collect::<Vec<_>>();
+    let balance = transactions.iter().map(|t| t.amount).sum::<MicroTari>();
+    let mut body = String::new();
+    body.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n");
+    body.push_str(
+        "<?OFX OFXHEADER=\"200\" VERSION=\"220\" SECURITY=\"NONE\" OLDFILEUID=\"NONE\" NEWFILEUID=\"NONE\"?>\n",
+    );
+    body.push_str("<OFX>\n<SIGNONMSGSRSV1>\n<SONRS>\n");
+    body.push_str("<STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>\n");
+    body.push_str(&format!("<DTSERVER>{}</DTSERVER>\n<LANGUAGE>ENG</LANGUAGE>\n", ofx_date(Utc::now())));
+    body.push_str("</SONRS>\n</SIGNONMSGSRSV1>\n<BANKMSGSRSV1>\n<STMTTRNRS>\n<TRNUID>0</TRNUID>\n");
+    body.push_str("<STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>\n<STMTRS>\n");
+    body.push_str(&format!("<CURDEF>{TARI_CURRENCY_CODE}</CURDEF>\n"));
+    body.push_str(&format!(
+        "<BANKACCTFROM><BANKID>TARI</BANKID><ACCTID>{}</ACCTID><ACCTTYPE>CHECKING</ACCTTYPE></BANKACCTFROM>\n",
+        request.report
+    ));
+    body.push_str(&format!(
+        "<BANKTRANLIST>\n<DTSTART>{}</DTSTART>\n<DTEND>{}</DTEND>\n",
+        ofx_date(request.from),
+        ofx_date(request.to)
+    ));
+    for tx in &transactions {
+        body.push_str(&ofx_statement_transaction(tx));
+    }
+    body.push_str("</BANKTRANLIST>\n");
+    body.push_str(&format!(
+        "<LEDGERBAL><BALAMT>{}</BALAMT><DTASOF>{}</DTASOF></LEDGERBAL>\n",
+        format_tari(balance),
+        ofx_date(request.to)
+    ));
+    body.push_str("</STMTRS>\n</STMTTRNRS>\n</BANKMSGSRSV1>\n</OFX>\n");
+    body
+}
+
+fn ofx_statement_transaction(tx: &OfxTransaction) -> String {
+    let trntype = if tx.amount.value() < 0 { "DEBIT" } else { "CREDIT" };
+    let name = tx.name.chars().take(OFX_NAME_LENGTH).collect::<String>();
+    let mut result = format!(
+        "<STMTTRN>\n<TRNTYPE>{trntype}</TRNTYPE>\n<DTPOSTED>{}</DTPOSTED>\n<TRNAMT>{}</TRNAMT>\n<FITID>{}</FITID>\\
+         n<NAME>{}</NAME>\n",
+        ofx_date(tx.posted),
+        format_tari(tx.amount),
+        xml_escape(&tx.fitid),
+        xml_escape(&name)
+    );
+    if let Some(memo) = tx.memo.as_ref().filter(|m| !m.trim().is_empty()) {
+        result.push_str(&format!("<MEMO>{}</MEMO>\n", xml_escape(memo)));
+    }
+    if let Some((currency, rate)) = &tx.original_currency {
+        result.push_str(&format!(
+            "<ORIGCURRENCY><CURRATE>{}</CURRATE><CURSYM>{}</CURSYM></ORIGCURRENCY>\n",
+            format_tari(*rate),
+            xml_escape(currency)
+        ));
+    }
+    result.push_str("</STMTTRN>\n");
+    result
+}
+
+fn ofx_date(t: DateTime<Utc>) -> String {
+    t.format("%Y%m%d%H%M%S.%3f[0:GMT]").to_string()
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::TimeZone;
+
+    use super::*;
+    use crate::db_types::{PaymentType, SerializedTariAddress, DEFAULT_MERCHANT};
+
+    const ALICE: &str = "14AYt2hhhn4VydAXNJ6i7ZfRNZGoGSp713dHjMYCoK5hYw2";
+
+    fn payment(txid: &str, amount: i64, status: TransferStatus, memo: Option<&str>) -> Payment {
+        let t = Utc.with_ymd_and_hms(2024, 6, 1, 12, 30, 0).unwrap();
+        Payment {
+            txid: txid.to_string(),
+            created_at: t,
+            updated_at: t,
+            sender: ALICE.parse::<SerializedTariAddress>().unwrap(),
+            amount: MicroTari::from(amount),
+            memo: memo.map(String::from),
+            payment_type: PaymentType::OnChain,
+            status,
+            order_id: None,
+            block_height: None,
+            confirmations: 3,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
+        }
+    }
+
+    fn usd(rate: i64) -> ExchangeRate {
+        let mut rate = ExchangeRate::new("USD".to_string(), MicroTari::from(rate), None);
+        rate.id = 7;
+        rate
+    }
+
+    fn request(format: ReportFormat) -> ExportRequest {
+        let from = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let to = Utc.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap();
+        ExportRequest::new(ReportType::Payments, format, from, to)
+    }
+
+    #[test]
+    fn amounts_are_formatted_without_loss() {
+        assert_eq!(format_tari(MicroTari::from(1_500_000)), "1.500000");
+        assert_eq!(format_tari(MicroTari::from(-25)), "-0.000025");
+        assert_eq!(format_tari(MicroTari::from(0)), "0.000000");
+        assert_eq!(format_cents(1205), "12.05");
+        assert_eq!(format_cents(-7), "-0.07");
+    }
+
+    #[test]
+    fn csv_header_matches_the_columns() {
+        let fiat = FiatValue::at_rate(MicroTari::from(25_000_000), &usd(2_000_000));
+        assert_eq!(fiat.cents, 1250);
+        let records = vec![
+            PaymentRecord::new(
+                payment("1", 25_000_000, TransferStatus::Confirmed, Some("order #12, thanks")),
+                Some(fiat),
+            ),
+            PaymentRecord::new(payment("2", 100, TransferStatus::Received, None), None),
+        ];
+        let csv = render(&records, &request(ReportFormat::Csv)).unwrap();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], PaymentRecord::COLUMNS.join(","));
+        assert_eq!(
+            lines[1],
+            format!("2024-06-01T12:30:00Z,1,{ALICE},Confirmed,3,,25.000000,USD,12.50,7,2.000000,\"order #12, thanks\"")
+        );
+        assert_eq!(lines[2], format!("2024-06-01T12:30:00Z,2,{ALICE},Received,3,,0.000100,,,,,"));
+        let empty = render::<SettlementRecord>(&[], &request(ReportFormat::Csv)).unwrap();
+        assert_eq!(empty, format!("{}\n", SettlementRecord::COLUMNS.join(",")));
+    }
+
+    #[test]
+    fn json_lines_use_the_same_columns() {
+        let records = vec![PaymentRecord::new(payment("1", 100, TransferStatus::Received, None), None)];
+        let body = render(&records, &request(ReportFormat::JsonLines)).unwrap();
+        assert_eq!(body.lines().count(), 1);
+        let value = serde_json::from_str::<serde_json::Value>(body.trim()).unwrap();
+        let mut keys = value.as_object().unwrap().keys().cloned().collect::<Vec<_>>();
+        keys.sort();
+        let mut columns = PaymentRecord::COLUMNS.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        columns.sort();
+        assert_eq!(keys, columns);
+        assert_eq!(value["amount_xtr"], "0.000100");
+        assert!(value["fiat_amount"].is_null());
+    }
+
+    #[test]
+    fn ofx_statements() {
+        let fiat = FiatValue::at_rate(MicroTari::from(25_000_000), &usd(2_000_000));
+        let records = vec![
+            PaymentRecord::new(payment("1", 25_000_000, TransferStatus::Confirmed, Some("Fish & <chips>")), Some(fiat)),
+            PaymentRecord::new(payment("2", 5_000_000, TransferStatus::Cancelled, None), None),
+            PaymentRecord::new(payment("3", 1_000_000, TransferStatus::Received, None), None),
+        ];
+        let ofx = render(&records, &request(ReportFormat::Ofx)).unwrap();
+        assert_eq!(ofx.matches("<STMTTRN>").count(), 2);
+        assert!(ofx.contains("<CURDEF>XTR</CURDEF>"));
+        assert!(ofx.contains("<DTSTART>20240601000000.000[0:GMT]</DTSTART>"));
+        assert!(ofx.contains("<TRNTYPE>CREDIT</TRNTYPE>\n<DTPOSTED>20240601123000.000[0:GMT]</DTPOSTED>"));
+        assert!(ofx.contains("<TRNAMT>25.000000</TRNAMT>\n<FITID>1</FITID>"));
+        assert!(ofx.contains("<MEMO>Fish &amp; &lt;chips&gt;</MEMO>"));
+        assert!(ofx.contains("<ORIGCURRENCY><CURRATE>2.000000</CURRATE><CURSYM>USD</CURSYM></ORIGCURRENCY>"));
+        assert!(ofx.contains("<BALAMT>26.000000</BALAMT>"));
+        assert!(!ofx.contains("<FITID>2</FITID>"));
+        let name = ofx.lines().find(|l| l.starts_with("<NAME>")).unwrap();
+        assert_eq!(name.len(), "<NAME></NAME>".len() + OFX_NAME_LENGTH);
+    }
+
+    #[test]
+    fn report_names() {
+        let request = request(ReportFormat::JsonLines);
+        assert_eq!(request.file_name(), "payments_20240601_20240701.jsonl");
+        assert_eq!("credit_notes".parse::<ReportType>().unwrap(), ReportType::CreditNotes);
+        assert_eq!("ofx".parse::<ReportFormat>().unwrap(), ReportFormat::Ofx);
+        assert!("xls".parse::<ReportFormat>().is_err());
+        assert_eq!(ReportFormat::Ofx.content_type(), "application/x-ofx");
+    }
+}
@@ -7,10 +7,17 @@
 //!
 //! * [`accounts_api`] provides methods for interacting with user accounts, including fetching order and payment
 //!   histories, status, and metadata.
+//! * [`audit_api`] records the privileged calls that admins make to the server, and searches the record.
 //! * [`auth_api`] manages nonce state for authentication tokens, and managing user [`crate::db_types::Role`]s
+//! * [`export_api`] produces accounting reports of orders, payments, settlements and credit notes in CSV, OFX and JSON
+//!   Lines.
 //! * [`order_flow_api`] is the primary API for handling order and payment flows in response to merchant order events
 //!   and wallet payment events.
+//! * [`outbox_api`] lets admins list and replay events in the durable event outbox.
+//! * [`reconciliation_api`] compares the payments with the hot wallet's transaction history, and imports the payments
+//!   that were never recorded.
 //! * [`wallet_api`] provides methods for interacting with the hot wallet authorization and authentication.
+//! * [`webhook_api`] manages outbound webhook subscriptions and their delivery log.
 //!
 //! The other submodules in this module are support and utility functions and types.
 //!
@@ -32,6 +39,8 @@
 //! ```
 
 pub mod accounts_api;
+pub mod approval_objects;
+pub mod audit_api;
 pub mod auth_api;
 
 pub mod account_objects;
@@ -39,8 +48,15 @@ pub mod account_objects;
 pub mod exchange_objects;
 
 pub mod exchange_rate_api;
+pub mod export_api;
+pub mod export_objects;
 pub mod order_flow_api;
 pub mod order_objects;
+pub mod outbox_api;
 pub mod payment_objects;
+pub mod rate_limit_objects;
+pub mod reconciliation_api;
+pub mod reconciliation_objects;
 
 pub mod wallet_api;
+pub mod webhook_api;
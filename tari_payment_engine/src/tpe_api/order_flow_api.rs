@@ -1,4 +1,4 @@
-use std::fmt::Debug;
+use std::{collections::HashSet, fmt::Debug};
 
 use chrono::Duration;
 use log::*;
@@ -6,17 +6,53 @@ use tari_common_types::tari_address::TariAddress;
 use tpg_common::MicroTari;
 
 use crate::{
-    db_types::{CreditNote, NewOrder, NewPayment, Order, OrderId, OrderStatusType, Payment, TransferStatus},
-    events::{EventProducers, OrderAnnulledEvent, OrderClaimedEvent, OrderEvent, OrderModifiedEvent, PaymentEvent},
-    helpers::MemoSignature,
+    db_types::{
+        ApprovalOperation,
+        ApprovalRequest,
+        CreditNote,
+        HeldOrder,
+        NewApprovalRequest,
+        NewOrder,
+        NewPayment,
+        NewRefund,
+        Order,
+        OrderId,
+        OrderStatusType,
+        Payment,
+        Refund,
+        RefundStatus,
+        SerializedTariAddress,
+        TransferStatus,
+        DEFAULT_MERCHANT,
+    },
+    events::{
+        ApprovalEvent,
+        EventProducers,
+        OrderAnnulledEvent,
+        OrderClaimedEvent,
+        OrderEvent,
+        OrderHeldEvent,
+        OrderModifiedEvent,
+        PaymentEvent,
+        PaymentRevertedEvent,
+        RefundEvent,
+    },
+    helpers::{is_dummy_address, MemoSignature},
     order_objects::{ClaimedOrder, OrderChanged, OrderQueryFilter},
+    tpe_api::{
+        account_objects::Pagination,
+        exchange_objects::{QuoteExpiryAction, QuotePolicy},
+        payment_objects::{ConfirmationPolicy, OverpaymentPolicy},
+    },
     traits::{
         AccountApiError,
+        ExchangeRates,
         ExpiryResult,
         MultiAccountPayment,
         OrderMovedResult,
         PaymentGatewayDatabase,
         PaymentGatewayError,
+        PaymentReversal,
     },
 };
 
@@ -107,6 +143,35 @@ where B: PaymentGatewayDatabase
         Ok(order)
     }
 
+    /// Holds a new order instead of processing it, because it could not be priced safely (see
+    /// [`crate::tpe_api::exchange_objects::RateCircuitBreaker`]). The order is not inserted until it is released
+    /// with [`Self::release_held_order`]. The `OrderHeld` event is triggered, so that admins can be alerted.
+    pub async fn hold_order(&self, order: &NewOrder, reason: &str) -> Result<HeldOrder, PaymentGatewayError> {
+        let held_order = self.db.hold_order(order, reason).await?;
+        warn!("🔄️✋️ Order [{}] is being held. {reason}", held_order.order_id);
+        self.call_order_held_hook(&held_order).await;
+        Ok(held_order)
+    }
+
+    /// Releases a held order. `order` is the held order, priced at the current exchange rate. It is processed like any
+    /// other new order (see [`Self::process_new_order`]), and the held order is marked as released.
+    pub async fn release_held_order(
+        &self,
+        order: NewOrder,
+        auto_claim: bool,
+        strict_mode: bool,
+    ) -> Result<Order, PaymentGatewayError> {
+        let id = order.order_id.clone();
+        match self.db.fetch_held_order(&id).await? {
+            Some(held_order) if !held_order.is_released() => {},
+            _ => return Err(PaymentGatewayError::OrderNotHeld(id)),
+        }
+        let order = self.process_new_order(order, auto_claim, strict_mode).await?;
+        self.db.mark_held_order_released(&id).await?;
+        info!("🔄️✋️ Held order [{id}] has been released for {}", order.total_price);
+        Ok(order)
+    }
+
     /// Claims an order for a Tari wallet address.
     ///
     /// This function:
@@ -208,6 +273,56 @@ where B: PaymentGatewayDatabase
         }
     }
 
+    async fn call_payment_reverted_hook(&self, reversal: &PaymentReversal) {
+        debug!("🔄️⏪️ Notifying payment reverted hook subscribers");
+        let event = PaymentRevertedEvent::new(reversal.payment.clone(), reversal.orders_reverted.clone());
+        for emitter in &self.producers.payment_reverted_producer {
+            emitter.publish_event(event.clone()).await;
+        }
+    }
+
+    async fn call_refund_approved_hook(&self, refund: &Refund) {
+        debug!("🔄️↩️ Notifying refund approved hook subscribers");
+        for emitter in &self.producers.refund_approved_producer {
+            emitter.publish_event(RefundEvent::new(refund.clone())).await;
+        }
+    }
+
+    async fn call_refund_sent_hook(&self, refund: &Refund) {
+        debug!("🔄️↩️ Notifying refund sent hook subscribers");
+        for emitter in &self.producers.refund_sent_producer {
+            emitter.publish_event(RefundEvent::new(refund.clone())).await;
+        }
+    }
+
+    async fn call_approval_requested_hook(&self, request: &ApprovalRequest) {
+        debug!("🔄️👀️ Notifying approval requested hook subscribers");
+        for emitter in &self.producers.approval_requested_producer {
+            emitter.publish_event(ApprovalEvent::new(request.clone())).await;
+        }
+    }
+
+    async fn call_operation_approved_hook(&self, request: &ApprovalRequest) {
+        debug!("🔄️👀️ Notifying operation approved hook subscribers");
+        for emitter in &self.producers.operation_approved_producer {
+            emitter.publish_event(ApprovalEvent::new(request.clone())).await;
+        }
+    }
+
+    async fn call_operation_rejected_hook(&self, request: &ApprovalRequest) {
+        debug!("🔄️👀️ Notifying operation rejected hook subscribers");
+        for emitter in &self.producers.operation_rejected_producer {
+            emitter.publish_event(ApprovalEvent::new(request.clone())).await;
+        }
+    }
+
+    async fn call_order_held_hook(&self, held_order: &HeldOrder) {
+        debug!("🔄️✋️ Notifying order held hook subscribers");
+        for emitter in &self.producers.order_held_producer {
+            emitter.publish_event(OrderHeldEvent::new(held_order.clone())).await;
+        }
+    }
+
     /// Submit a new payment to the order manager.
     ///
     /// This should be a brand-new payment. If the payment already exists, the order manager will return an error.
@@ -256,6 +371,30 @@ where B: PaymentGatewayDatabase
         Ok(payment)
     }
 
+    /// Confirms a payment that a wallet notifier has reported as confirmed. The notifier does not say how deep the
+    /// transaction is, so payments of `deferred_from` or more, which the confirmation policy wants deeper than its
+    /// default, are not confirmed here. The wallet watcher confirms them once they are deep enough. `Ok(None)` is
+    /// returned for a deferred payment.
+    pub async fn confirm_notified_payment(
+        &self,
+        txid: String,
+        deferred_from: Option<MicroTari>,
+        strict_mode: bool,
+    ) -> Result<Option<Payment>, PaymentGatewayError> {
+        if let Some(limit) = deferred_from {
+            let payment = self.db.fetch_payment_by_tx_id(&txid).await?;
+            if payment.amount >= limit {
+                info!(
+                    "🔄️✅️ Payment {txid} of {} needs more confirmations than the notifier waits for. It is left for \
+                     the wallet watcher to confirm.",
+                    payment.amount
+                );
+                return Ok(None);
+            }
+        }
+        self.confirm_payment(txid, strict_mode).await.map(Some)
+    }
+
     /// Try and pay for orders after a confirmation. If `isolated` is true, then _only_ funds in the confirmed payment
     /// address are used to pay for orders. If `isolated` is false, then all orders for the customer are considered.
     async fn post_confirm(
@@ -286,6 +425,74 @@ where B: PaymentGatewayDatabase
         Ok(result)
     }
 
+    /// Records where a payment's transaction is in the chain, as reported by the wallet. `block_height` is the height
+    /// of the block that the transaction was mined in, or `None` if it is not mined, and `confirmations` is how
+    /// many blocks deep it is, including its own block.
+    ///
+    /// * A `Received` payment is confirmed (see [`Self::confirm_payment`]) once it has as many confirmations as the
+    ///   `policy` requires for its amount.
+    /// * A `Confirmed` payment whose transaction is no longer mined, or has moved to a block that is not deep enough,
+    ///   has been lost in a reorg, and is reverted (see [`Self::revert_payment`]). It is confirmed again once its
+    ///   transaction is deep enough.
+    ///
+    /// The position of a confirmed payment is only updated if its block changes, so that confirmed payments are not
+    /// written to every block.
+    ///
+    /// Returns the payment in its new state.
+    pub async fn update_confirmations(
+        &self,
+        txid: &str,
+        block_height: Option<i64>,
+        confirmations: i64,
+        policy: &ConfirmationPolicy,
+        strict_mode: bool,
+    ) -> Result<Payment, PaymentGatewayError> {
+        let payment = self.db.fetch_payment_by_tx_id(txid).await?;
+        let required = i64::try_from(policy.required_confirmations(payment.amount)).unwrap_or(i64::MAX);
+        let deep_enough = block_height.is_some() && confirmations >= required;
+        match payment.status {
+            TransferStatus::Cancelled => Ok(payment),
+            TransferStatus::Received => {
+                if (payment.block_height, payment.confirmations) != (block_height, confirmations) {
+                    trace!("🔄️⛓️ Payment {txid} is {confirmations} blocks deep. It needs {required}.");
+                    self.db.update_payment_confirmations(txid, block_height, confirmations).await?;
+                }
+                if deep_enough {
+                    self.confirm_payment(txid.to_string(), strict_mode).await
+                } else {
+                    self.db.fetch_payment_by_tx_id(txid).await
+                }
+            },
+            TransferStatus::Confirmed if payment.block_height == block_height => Ok(payment),
+            TransferStatus::Confirmed => {
+                let reorged = match (payment.block_height, block_height) {
+                    (_, None) => true,
+                    (Some(_), Some(_)) => !deep_enough,
+                    (None, Some(_)) => false,
+                };
+                if reorged {
+                    self.revert_payment(txid).await?;
+                }
+                self.db.update_payment_confirmations(txid, block_height, confirmations).await
+            },
+        }
+    }
+
+    /// Reverts a confirmed payment whose transaction has been lost in a chain reorg. The payment is `Received` again,
+    /// and the orders that its sender can no longer pay for go back to `New` (see
+    /// [`PaymentGatewayDatabase::revert_payment`]). The `PaymentReverted` event is triggered.
+    pub async fn revert_payment(&self, txid: &str) -> Result<PaymentReversal, PaymentGatewayError> {
+        let reversal = self.db.revert_payment(txid).await?;
+        let orders = reversal.orders_reverted.iter().map(|o| o.order_id.to_string()).collect::<Vec<_>>();
+        warn!(
+            "🔄️⏪️ Payment {txid} was lost in a reorg and has been reverted. {} orders are no longer paid: [{}]",
+            orders.len(),
+            orders.join(", ")
+        );
+        self.call_payment_reverted_hook(&reversal).await;
+        Ok(reversal)
+    }
+
     /// Mark a payment as cancelled and update orders and accounts as necessary.
     pub async fn cancel_payment(&self, txid: String) -> Result<(), PaymentGatewayError> {
         trace!("🔄️❌️ Payment {txid} is being marked as cancelled");
@@ -386,6 +593,10 @@ where B: PaymentGatewayDatabase
                 Ok(Some(result))
             },
             Err(PaymentGatewayError::AccountError(AccountApiError::InsufficientFunds)) => Ok(None),
+            Err(PaymentGatewayError::QuoteExpired(id)) => {
+                info!("🔄️📦️ Order [{id}] cannot be paid until it has been re-priced, since its quote has expired");
+                Ok(None)
+            },
             Err(e) => Err(e),
         }
     }
@@ -558,6 +769,164 @@ where B: PaymentGatewayDatabase
         Ok(result)
     }
 
+    /// Records a refund request. Nothing is debited from the address until the refund is approved.
+    pub async fn request_refund(&self, refund: NewRefund) -> Result<Refund, PaymentGatewayError> {
+        let refund = self.db.request_refund(refund).await?;
+        info!("🔄️↩️ Refund {} of {} has been requested", refund.id, refund.amount);
+        Ok(refund)
+    }
+
+    /// Approves a refund, debits it from the address balance and triggers the `RefundApproved` event, which tells the
+    /// payout wallet to send the refund.
+    pub async fn approve_refund(&self, id: i64) -> Result<Refund, PaymentGatewayError> {
+        let refund = self.db.approve_refund(id).await?;
+        self.call_refund_approved_hook(&refund).await;
+        Ok(refund)
+    }
+
+    pub async fn reject_refund(&self, id: i64, reason: &str) -> Result<Refund, PaymentGatewayError> {
+        let refund = self.db.reject_refund(id, reason).await?;
+        info!("🔄️↩️ Refund {id} was rejected. {reason}");
+        Ok(refund)
+    }
+
+    /// Records the payout of an approved refund and triggers the `RefundSent` event.
+    pub async fn mark_refund_sent(&self, id: i64, payout_txid: &str) -> Result<Refund, PaymentGatewayError> {
+        let refund = self.db.mark_refund_sent(id, payout_txid).await?;
+        self.call_refund_sent_hook(&refund).await;
+        Ok(refund)
+    }
+
+    /// Holds back a high-risk operation until a second admin approves it with [`Self::approve_operation`]. Nothing is
+    /// changed until then.
+    pub async fn request_approval(&self, request: NewApprovalRequest) -> Result<ApprovalRequest, PaymentGatewayError> {
+        let request = self.db.request_approval(request).await?;
+        info!("🔄️👀️ Approval request {} has been made. {}", request.id, request.operation);
+        self.call_approval_requested_hook(&request).await;
+        Ok(request)
+    }
+
+    /// Approves a pending request on behalf of `approver`, and carries out the operation.
+    ///
+    /// The approval is recorded before the operation is carried out. If the operation then fails, e.g. because the
+    /// order has been paid in the meantime, the request is marked as `Failed`, and the error is returned.
+    ///
+    /// The caller must check that `approver` is allowed to carry out the operation themselves.
+    pub async fn approve_operation(
+        &self,
+        id: i64,
+        approver: &TariAddress,
+        strict_mode: bool,
+    ) -> Result<ApprovalRequest, PaymentGatewayError> {
+        let request = self.db.approve_request(id, approver).await?;
+        info!("🔄️👀️ Approval request {id} was approved by {}. {}", approver.to_base58(), request.operation);
+        self.call_operation_approved_hook(&request).await;
+        if let Err(e) = self.execute_operation(&request.operation, strict_mode).await {
+            warn!("🔄️👀️ The operation for approval request {id} failed after it was approved. {e}");
+            self.db.mark_approval_failed(id, &e.to_string()).await?;
+            return Err(e);
+        }
+        Ok(request)
+    }
+
+    pub async fn reject_operation(
+        &self,
+        id: i64,
+        approver: &TariAddress,
+        reason: &str,
+    ) -> Result<ApprovalRequest, PaymentGatewayError> {
+        let request = self.db.reject_request(id, approver, reason).await?;
+        info!("🔄️👀️ Approval request {id} was rejected by {}. {reason}", approver.to_base58());
+        self.call_operation_rejected_hook(&request).await;
+        Ok(request)
+    }
+
+    /// Marks the pending approval requests whose time is up as `Expired`.
+    pub async fn expire_approval_requests(&self) -> Result<Vec<ApprovalRequest>, PaymentGatewayError> {
+        self.db.expire_approval_requests().await
+    }
+
+    async fn execute_operation(
+        &self,
+        operation: &ApprovalOperation,
+        strict_mode: bool,
+    ) -> Result<(), PaymentGatewayError> {
+        match operation {
+            ApprovalOperation::IssueCredit { note } => {
+                self.issue_credit_note(note.clone(), strict_mode).await?;
+            },
+            ApprovalOperation::FulfilOrder { order_id, reason } => {
+                self.mark_new_order_as_paid(order_id, reason, strict_mode).await?;
+            },
+            ApprovalOperation::UpdatePrice { order_id, old_price, new_price, .. } => {
+                // The approver agreed to a change from the old price, so the price must not have moved since
+                let order = self.db.fetch_order_by_id(order_id, strict_mode).await?;
+                if order.total_price != *old_price {
+                    return Err(PaymentGatewayError::InvalidApproval(format!(
+                        "The price of order {order_id} has changed from {old_price} to {} since the request was made",
+                        order.total_price
+                    )));
+                }
+                self.update_price_for_order(order_id, *new_price, strict_mode).await?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Applies the overpayment policy to the addresses whose unspent balance has been idle for longer than the
+    /// policy's idle period. Under [`OverpaymentPolicy::AutoRefund`], the whole balance is refunded and approved, so
+    /// that the payout wallet sends it. Under [`OverpaymentPolicy::Review`], a refund is only requested, and an admin
+    /// decides whether to approve it.
+    ///
+    /// Balances are held per merchant, and each merchant's balance is refunded separately. An address keeps its
+    /// balance with a merchant if it still has unpaid orders with that merchant, if a refund of that balance is already
+    /// awaiting approval, or if it is one of the dummy addresses that hold credit notes.
+    ///
+    /// Returns the refunds that were created.
+    pub async fn process_unspent_balances(
+        &self,
+        policy: OverpaymentPolicy,
+    ) -> Result<Vec<Refund>, PaymentGatewayError> {
+        let Some(idle) = policy.idle_period() else {
+            return Ok(Vec::new());
+        };
+        let balances = self.db.fetch_idle_balances(idle).await?;
+        let all = Pagination { offset: None, count: None };
+        let pending = self.db.fetch_refunds(Some(RefundStatus::Requested), &all).await?;
+        let pending = pending.into_iter().map(|r| (r.address, r.merchant_id)).collect::<HashSet<_>>();
+        let mut refunds = Vec::with_capacity(balances.len());
+        for balance in balances {
+            let address = balance.address();
+            let merchant_id = balance.merchant_id().unwrap_or(DEFAULT_MERCHANT);
+            if is_dummy_address(address) ||
+                pending.contains(&(SerializedTariAddress::from(address), merchant_id.to_string()))
+            {
+                continue;
+            }
+            let orders = self.db.fetch_payable_orders_for_address(address).await?;
+            if orders.iter().any(|o| o.merchant_id == merchant_id) {
+                debug!(
+                    "🔄️↩️ {} has unpaid orders with {merchant_id}, so its unspent balance is kept",
+                    address.to_base58()
+                );
+                continue;
+            }
+            let reason = format!("Unspent balance, idle since {}", balance.last_update().format("%Y-%m-%d"));
+            let refund = NewRefund::new(address.clone(), balance.current_balance(), reason).with_merchant(merchant_id);
+            let result = match self.request_refund(refund).await {
+                Ok(refund) if matches!(policy, OverpaymentPolicy::AutoRefund(_)) => {
+                    self.approve_refund(refund.id).await
+                },
+                result => result,
+            };
+            match result {
+                Ok(refund) => refunds.push(refund),
+                Err(e) => warn!("🔄️↩️ Could not refund the unspent balance of {}. {e}", address.to_base58()),
+            }
+        }
+        Ok(refunds)
+    }
+
     pub async fn settle_orders_for_address(
         &self,
         address: &TariAddress,
@@ -605,3 +974,79 @@ where B: PaymentGatewayDatabase
         &mut self.db
     }
 }
+
+impl<B> OrderFlowApi<B>
+where B: PaymentGatewayDatabase + ExchangeRates
+{
+    /// Applies the quote policy to the unpaid orders whose exchange rate quote has expired.
+    ///
+    /// With [`QuoteExpiryAction::Reprice`], the order's price is converted to the current exchange rate for its
+    /// currency, and it gets a new quote that expires after the policy's lifetime. This triggers the `OrderModified`
+    /// event, and the order is paid if the customer's existing funds now cover it. If the rate has not changed, the
+    /// order keeps its price, but still gets a new quote.
+    ///
+    /// With [`QuoteExpiryAction::Expire`], the order is marked as `Expired`, and the `OrderAnnulled` event is
+    /// triggered.
+    ///
+    /// Returns the orders that were changed, in their new state.
+    pub async fn process_expired_quotes(
+        &self,
+        policy: &QuotePolicy,
+        strict_mode: bool,
+    ) -> Result<Vec<Order>, PaymentGatewayError> {
+        let orders = self.db.fetch_orders_with_expired_quotes().await?;
+        let mut updated = Vec::with_capacity(orders.len());
+        for order in orders {
+            let result = match policy.on_expiry {
+                QuoteExpiryAction::Reprice => self.requote_order(&order, policy, strict_mode).await,
+                QuoteExpiryAction::Expire => self
+                    .cancel_or_expire_order(&order.order_id, OrderStatusType::Expired, "Price quote expired", true)
+                    .await
+                    .map(Some),
+            };
+            match result {
+                Ok(Some(order)) => updated.push(order),
+                Ok(None) => {},
+                Err(e) => warn!("🔄️💱️ Could not {} order [{}]. {e}", policy.on_expiry, order.order_id),
+            }
+        }
+        Ok(updated)
+    }
+
+    async fn requote_order(
+        &self,
+        order: &Order,
+        policy: &QuotePolicy,
+        strict_mode: bool,
+    ) -> Result<Option<Order>, PaymentGatewayError> {
+        let Some(rate_id) = order.rate_id else {
+            warn!("🔄️💱️ Order [{}] has a price quote but no exchange rate, so it cannot be re-priced", order.order_id);
+            return Ok(None);
+        };
+        let rates = match self.db.fetch_rate_by_id(rate_id).await {
+            Ok(quoted) => {
+                self.db.fetch_merchant_rate(&order.merchant_id, &order.currency).await.map(|rate| (quoted, rate))
+            },
+            Err(e) => Err(e),
+        };
+        let (quoted, rate) = match rates {
+            Ok(rates) => rates,
+            Err(e) => {
+                warn!("🔄️💱️ Order [{}] cannot be re-priced. {e}", order.order_id);
+                return Ok(None);
+            },
+        };
+        let new_price = rate.reprice(order.total_price, &quoted);
+        let changes = self.db.requote_order(&order.order_id, new_price, rate.id, policy.expires_at()).await?;
+        info!(
+            "🔄️💱️ Order [{}] re-priced from {} to {new_price} at {rate}",
+            order.order_id, changes.old_order.total_price
+        );
+        self.call_order_modified_hook("total_price", changes.clone()).await;
+        let mut new_order = changes.new_order;
+        if let Some(mut payment) = self.try_pay_order(&new_order, strict_mode).await? {
+            new_order = payment.orders_paid.remove(0);
+        }
+        Ok(Some(new_order))
+    }
+}
@@ -50,6 +50,7 @@ pub struct OrderQueryFilter {
     pub until: Option<DateTime<Utc>>,
     #[serde(default, deserialize_with = "string_to_statuses")]
     pub status: Option<Vec<OrderStatusType>>,
+    pub merchant_id: Option<String>,
 }
 
 impl OrderQueryFilter {
@@ -98,6 +99,11 @@ impl OrderQueryFilter {
         self
     }
 
+    pub fn with_merchant_id<S: Into<String>>(mut self, merchant_id: S) -> Self {
+        self.merchant_id = Some(merchant_id.into());
+        self
+    }
+
     pub fn with_status(mut self, status: OrderStatusType) -> Self {
         if self.status.is_none() {
             self.status = Some(vec![status]);
@@ -115,7 +121,8 @@ impl OrderQueryFilter {
             self.currency.is_none() &&
             self.status.is_none() &&
             self.since.is_none() &&
-            self.until.is_none()
+            self.until.is_none() &&
+            self.merchant_id.is_none()
     }
 }
 
@@ -140,6 +147,9 @@ impl Display for OrderQueryFilter {
         if let Some(currency) = &self.currency {
             write!(f, "currency: {currency}. ")?;
         }
+        if let Some(merchant_id) = &self.merchant_id {
+            write!(f, "merchant_id: {merchant_id}. ")?;
+        }
         if let Some(since) = &self.since {
             write!(f, "since {since}. ")?;
         }
@@ -0,0 +1,44 @@
+//! The OutboxApi gives admins visibility into the event outbox, so that events that could not be delivered can be
+//! inspected and replayed once the underlying problem has been fixed.
+
+use std::fmt::Debug;
+
+use crate::{
+    db_types::OutboxEvent,
+    tpe_api::account_objects::Pagination,
+    traits::{EventOutbox, OutboxError},
+};
+
+pub struct OutboxApi<B> {
+    db: B,
+}
+
+impl<B> Debug for OutboxApi<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OutboxApi")
+    }
+}
+
+impl<B> OutboxApi<B>
+where B: EventOutbox
+{
+    pub fn new(db: B) -> Self {
+        Self { db }
+    }
+
+    /// Fetches events that have not been delivered yet, including those that are waiting for a retry and those that
+    /// have run out of retries.
+    pub async fn fetch_undelivered_events(&self, pagination: &Pagination) -> Result<Vec<OutboxEvent>, OutboxError> {
+        self.db.fetch_undelivered_events(pagination).await
+    }
+
+    pub async fn fetch_event(&self, id: i64) -> Result<Option<OutboxEvent>, OutboxError> {
+        self.db.fetch_outbox_event(id).await
+    }
+
+    /// Schedules the event for immediate delivery. Handlers must be idempotent, since replaying an event that has
+    /// already been delivered will deliver it again.
+    pub async fn replay_event(&self, id: i64) -> Result<OutboxEvent, OutboxError> {
+        self.db.replay_event(id).await
+    }
+}
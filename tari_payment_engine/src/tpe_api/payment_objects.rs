@@ -1,3 +1,6 @@
+use std::fmt::Display;
+
+use chrono::Duration;
 use serde::{Deserialize, Serialize};
 use tpg_common::MicroTari;
 
@@ -11,3 +14,111 @@ pub struct PaymentsResult {
     pub total_payments: MicroTari,
     pub payments: Vec<Payment>,
 }
+
+/// What to do with Tari that is left over in an address balance once its orders have been paid, e.g. after an
+/// overpayment, or a payment for an order that was cancelled or has expired.
+///
+/// The surplus is the address's current balance, i.e. its confirmed payments less its settlement journal entries and
+/// refunds, so refunds of the surplus are accounted for in the same way as every other movement of funds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverpaymentPolicy {
+    /// Keep the surplus as store credit for future orders.
+    #[default]
+    StoreCredit,
+    /// Refund the surplus automatically once the balance has been idle for the given time.
+    AutoRefund(Duration),
+    /// Once the balance has been idle for the given time, request a refund of the surplus and leave it for an admin to
+    /// approve or reject.
+    Review(Duration),
+}
+
+impl OverpaymentPolicy {
+    /// How long a balance must be idle before the policy applies to it, or `None` if the surplus is always kept.
+    pub fn idle_period(&self) -> Option<Duration> {
+        match self {
+            Self::StoreCredit => None,
+            Self::AutoRefund(idle) | Self::Review(idle) => Some(*idle),
+        }
+    }
+}
+
+impl Display for OverpaymentPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::StoreCredit => write!(f, "keep as store credit"),
+            Self::AutoRefund(idle) => write!(f, "refund after {} days", idle.num_days()),
+            Self::Review(idle) => write!(f, "flag for review after {} days", idle.num_days()),
+        }
+    }
+}
+
+/// How many blocks deep a payment's transaction must be before the payment is confirmed. The count includes the block
+/// that the transaction was mined in.
+///
+/// Larger payments are more tempting targets for a double-spend, so they can be made to wait for more blocks. A payment
+/// needs the confirmations of the largest threshold that it meets, or `default` if it meets none of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfirmationPolicy {
+    default: u64,
+    /// (minimum amount, confirmations) pairs, in ascending order of amount
+    thresholds: Vec<(MicroTari, u64)>,
+}
+
+impl ConfirmationPolicy {
+    pub fn new(default: u64) -> Self {
+        Self { default, thresholds: Vec::new() }
+    }
+
+    /// Payments of `amount` or more need `confirmations` blocks.
+    pub fn with_threshold(mut self, amount: MicroTari, confirmations: u64) -> Self {
+        self.thresholds.retain(|(a, _)| *a != amount);
+        self.thresholds.push((amount, confirmations));
+        self.thresholds.sort_by_key(|(a, _)| *a);
+        self
+    }
+
+    /// The number of confirmations that a payment of `amount` needs.
+    pub fn required_confirmations(&self, amount: MicroTari) -> u64 {
+        self.thresholds.iter().rev().find(|(a, _)| amount >= *a).map(|(_, n)| *n).unwrap_or(self.default)
+    }
+
+    /// The smallest amount that needs more than the default number of confirmations, if any threshold raises it.
+    pub fn deeper_than_default_from(&self) -> Option<MicroTari> {
+        self.thresholds.iter().find(|(_, n)| *n > self.default).map(|(a, _)| *a)
+    }
+}
+
+impl Default for ConfirmationPolicy {
+    fn default() -> Self {
+        Self::new(3)
+    }
+}
+
+impl Display for ConfirmationPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} blocks", self.default)?;
+        for (amount, confirmations) in &self.thresholds {
+            write!(f, ", {confirmations} blocks from {amount}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn large_payments_need_more_confirmations() {
+        let policy = ConfirmationPolicy::new(3)
+            .with_threshold(MicroTari::from_tari(10_000), 10)
+            .with_threshold(MicroTari::from_tari(1_000), 6);
+        assert_eq!(policy.required_confirmations(MicroTari::from_tari(5)), 3);
+        assert_eq!(policy.required_confirmations(MicroTari::from_tari(1_000)), 6);
+        assert_eq!(policy.required_confirmations(MicroTari::from_tari(9_999)), 6);
+        assert_eq!(policy.required_confirmations(MicroTari::from_tari(50_000)), 10);
+        assert_eq!(ConfirmationPolicy::default().required_confirmations(MicroTari::from_tari(50_000)), 3);
+        assert_eq!(policy.deeper_than_default_from(), Some(MicroTari::from_tari(1_000)));
+        assert_eq!(ConfirmationPolicy::default().deeper_than_default_from(), None);
+    }
+}
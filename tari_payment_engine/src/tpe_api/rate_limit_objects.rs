@@ -0,0 +1,150 @@
+//! Token bucket rate limits.
+//!
+//! Each client (an IP address, or a wallet address, say) has a bucket of tokens for each rate limit. Every request
+//! takes a token, and requests are refused while the bucket is empty. The bucket holds up to [`RateLimit::burst`]
+//! tokens, and refills at `burst` tokens per [`RateLimit::period`], so a client can make a burst of requests at once,
+//! but no more than `burst` per period on average.
+//!
+//! The buckets themselves are kept by a [`crate::traits::RateLimitStore`].
+use std::{
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::db_types::ConversionError;
+
+/// The longest period a rate limit can be defined over
+pub const MAX_RATE_LIMIT_PERIOD: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// The number of requests a client can make at once, and per period
+    pub burst: u32,
+    /// The time it takes an empty bucket to refill
+    pub period: Duration,
+}
+
+impl RateLimit {
+    pub fn new(burst: u32, period: Duration) -> Self {
+        Self { burst, period }
+    }
+
+    pub fn capacity(&self) -> f64 {
+        f64::from(self.burst)
+    }
+
+    /// The number of tokens added to a bucket every second
+    pub fn refill_rate(&self) -> f64 {
+        self.capacity() / self.period.as_secs_f64()
+    }
+
+    /// The decision for a request, given the tokens left in the bucket once the request was counted
+    pub fn decision(&self, tokens: f64, allowed: bool) -> RateLimitDecision {
+        let retry_after = (!allowed).then(|| Duration::from_secs_f64(((1.0 - tokens) / self.refill_rate()).max(0.0)));
+        RateLimitDecision { allowed, retry_after }
+    }
+}
+
+/// Rate limits are written as `<requests>/<seconds>`, e.g. `10/60` for ten requests a minute.
+impl FromStr for RateLimit {
+    type Err = ConversionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ConversionError(format!("Invalid rate limit: {s}. Use <requests>/<seconds>, e.g. 10/60"));
+        let (burst, period) = s.trim().split_once('/').ok_or_else(invalid)?;
+        let burst = burst.trim().parse::<u32>().map_err(|_| invalid())?;
+        let period = Duration::from_secs(period.trim().parse::<u64>().map_err(|_| invalid())?);
+        if burst == 0 || period.is_zero() {
+            return Err(invalid());
+        }
+        if period > MAX_RATE_LIMIT_PERIOD {
+            return Err(ConversionError(format!("Invalid rate limit: {s}. The period cannot be longer than a week")));
+        }
+        Ok(Self { burst, period })
+    }
+}
+
+impl Display for RateLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} requests per {}s", self.burst, self.period.as_secs())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    /// When the client may try again, if the request was refused
+    pub retry_after: Option<Duration>,
+}
+
+/// A bucket that is kept in memory
+#[derive(Debug, Clone, Copy)]
+pub struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+    /// The bucket is full again by this time, and can be forgotten
+    expires_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn full(limit: &RateLimit, now: Instant) -> Self {
+        Self { tokens: limit.capacity(), updated_at: now, expires_at: now + limit.period }
+    }
+
+    /// Refills the bucket for the time since it was last used, and takes a token from it if there is one
+    pub fn take(&mut self, limit: &RateLimit, now: Instant) -> RateLimitDecision {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        let tokens = (self.tokens + elapsed * limit.refill_rate()).min(limit.capacity());
+        let allowed = tokens >= 1.0;
+        self.tokens = if allowed { tokens - 1.0 } else { tokens };
+        self.updated_at = now;
+        self.expires_at = now + limit.period;
+        limit.decision(self.tokens, allowed)
+    }
+
+    pub fn is_expired(&self, now: Instant) -> bool {
+        now >= self.expires_at
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_rate_limits() {
+        let limit = "10/60".parse::<RateLimit>().unwrap();
+        assert_eq!(limit, RateLimit::new(10, Duration::from_secs(60)));
+        assert_eq!(limit.to_string(), "10 requests per 60s");
+        assert_eq!(" 5 / 1 ".parse::<RateLimit>().unwrap(), RateLimit::new(5, Duration::from_secs(1)));
+        assert!("0/60".parse::<RateLimit>().is_err());
+        assert!("10/0".parse::<RateLimit>().is_err());
+        assert!("10".parse::<RateLimit>().is_err());
+        assert!("10/minute".parse::<RateLimit>().is_err());
+        assert!("10/864000".parse::<RateLimit>().is_err());
+    }
+
+    #[test]
+    fn buckets_refill_over_time() {
+        let limit = RateLimit::new(2, Duration::from_secs(2));
+        let start = Instant::now();
+        let mut bucket = TokenBucket::full(&limit, start);
+        assert!(bucket.take(&limit, start).allowed);
+        assert!(bucket.take(&limit, start).allowed);
+        let refused = bucket.take(&limit, start);
+        assert!(!refused.allowed);
+        assert_eq!(refused.retry_after, Some(Duration::from_secs(1)));
+        // One token every second
+        assert!(!bucket.take(&limit, start + Duration::from_millis(500)).allowed);
+        assert!(bucket.take(&limit, start + Duration::from_secs(1)).allowed);
+        assert!(!bucket.take(&limit, start + Duration::from_secs(1)).allowed);
+        // The bucket never holds more than the burst
+        let later = start + Duration::from_secs(60);
+        assert!(bucket.take(&limit, later).allowed);
+        assert!(bucket.take(&limit, later).allowed);
+        assert!(!bucket.take(&limit, later).allowed);
+        assert!(!bucket.is_expired(later + Duration::from_secs(1)));
+        assert!(bucket.is_expired(later + Duration::from_secs(2)));
+    }
+}
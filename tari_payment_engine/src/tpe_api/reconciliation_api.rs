@@ -0,0 +1,131 @@
+//! The ReconciliationApi compares the `payments` table with the hot wallet's transaction history.
+//!
+//! If a wallet notification is lost, the payment is never recorded and the customer's order is never paid. Reconciling
+//! finds these transactions, along with payments that the wallet knows nothing about and payments whose amount or
+//! sender differs from the wallet's record. Missing transactions can then be imported as payments.
+use std::{collections::HashMap, fmt::Debug};
+
+use chrono::{DateTime, Utc};
+use log::*;
+
+use crate::{
+    db_types::NewPayment,
+    events::EventProducers,
+    tpe_api::reconciliation_objects::{ImportFailure, ReconciliationError, ReconciliationReport, WalletTransaction},
+    traits::PaymentGatewayDatabase,
+    OrderFlowApi,
+};
+
+/// A source of the hot wallet's transaction history, e.g. a wallet export, or the wallet itself.
+#[allow(async_fn_in_trait)]
+pub trait WalletHistorySource {
+    /// Fetches the wallet's inbound payments. Outbound, cancelled and rejected transactions must be left out.
+    async fn fetch_wallet_transactions(&self) -> Result<Vec<WalletTransaction>, ReconciliationError>;
+}
+
+impl WalletHistorySource for Vec<WalletTransaction> {
+    async fn fetch_wallet_transactions(&self) -> Result<Vec<WalletTransaction>, ReconciliationError> {
+        Ok(self.clone())
+    }
+}
+
+pub struct ReconciliationApi<B> {
+    orders: OrderFlowApi<B>,
+}
+
+impl<B> Debug for ReconciliationApi<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ReconciliationApi")
+    }
+}
+
+impl<B> ReconciliationApi<B>
+where B: PaymentGatewayDatabase
+{
+    pub fn new(db: B, producers: EventProducers) -> Self {
+        Self { orders: OrderFlowApi::new(db, producers) }
+    }
+
+    /// Compares the wallet's history with the on-chain payments in the database.
+    ///
+    /// If `since` is given, only payments received since then, and transactions with a timestamp since then, are
+    /// compared. Use this when the history only covers recent transactions. Transactions without a timestamp are always
+    /// compared.
+    pub async fn reconcile<S: WalletHistorySource>(
+        &self,
+        source: &S,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<ReconciliationReport, ReconciliationError> {
+        let transactions = source
+            .fetch_wallet_transactions()
+            .await?
+            .into_iter()
+            .filter(|tx| match (since, tx.timestamp) {
+                (Some(since), Some(timestamp)) => timestamp >= since,
+                _ => true,
+            })
+            .collect::<Vec<_>>();
+        let payments = self.orders.db().fetch_onchain_payments(since).await?;
+        debug!("🧾️ Reconciling {} wallet transactions against {} payments", transactions.len(), payments.len());
+        let report = ReconciliationReport::compare(transactions, payments);
+        if report.is_reconciled() {
+            info!("🧾️ The payments match the wallet's history. {report}");
+        } else {
+            warn!("🧾️ The payments do not match the wallet's history. {report}");
+        }
+        Ok(report)
+    }
+
+    /// Records the report's missing transactions as payments. They go through the same flow as payment notifications
+    /// from the wallet, so they trigger the same events.
+    ///
+    /// The report may have been built from a history that the caller supplied, so every transaction is looked up in
+    /// `wallet` first, and is only imported if the wallet has a transaction with the same txid, sender and amount.
+    /// Imported payments are only received. They are confirmed by the usual confirmation flow, i.e. the wallet watcher
+    /// or the wallet's confirmation notifications, which apply the confirmation policy.
+    ///
+    /// `prepare` is called on each payment before it is recorded, e.g. to extract an order claim from the memo.
+    pub async fn import_missing<S, F>(
+        &self,
+        wallet: &S,
+        report: &mut ReconciliationReport,
+        strict_mode: bool,
+        mut prepare: F,
+    ) -> Result<(), ReconciliationError>
+    where
+        S: WalletHistorySource,
+        F: FnMut(&mut NewPayment),
+    {
+        let wallet_transactions = wallet
+            .fetch_wallet_transactions()
+            .await?
+            .into_iter()
+            .map(|tx| (tx.txid.clone(), tx))
+            .collect::<HashMap<_, _>>();
+        for transaction in &report.missing {
+            let txid = transaction.txid.clone();
+            let verified = wallet_transactions
+                .get(&txid)
+                .filter(|tx| tx.sender == transaction.sender && tx.amount == transaction.amount);
+            let Some(verified) = verified else {
+                warn!("🧾️ Missing payment {txid} is not in the wallet's history. It will not be imported.");
+                let reason = "The wallet has no transaction with this txid, sender and amount".to_string();
+                report.import_failures.push(ImportFailure { txid, reason });
+                continue;
+            };
+            let mut payment = verified.to_new_payment();
+            prepare(&mut payment);
+            match self.orders.process_new_payment(payment, strict_mode).await {
+                Ok(payment) => {
+                    info!("🧾️ Imported missing payment {txid} for {} from {}", payment.amount, payment.sender);
+                    report.imported.push(payment);
+                },
+                Err(e) => {
+                    warn!("🧾️ Could not import missing payment {txid}. {e}");
+                    report.import_failures.push(ImportFailure { txid, reason: e.to_string() });
+                },
+            }
+        }
+        Ok(())
+    }
+}
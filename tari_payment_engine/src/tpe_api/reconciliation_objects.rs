@@ -0,0 +1,412 @@
+//! Data types for reconciling the `payments` table against the hot wallet's transaction history.
+//!
+//! The wallet's history is a list of [`WalletTransaction`]s. It can be read from a console wallet export with
+//! [`parse_wallet_export`], or fetched from any [`crate::tpe_api::reconciliation_api::WalletHistorySource`].
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tpg_common::MicroTari;
+
+use crate::{
+    db_types::{NewPayment, Payment, SerializedTariAddress, TransferStatus},
+    traits::{AccountApiError, PaymentGatewayError},
+};
+
+#[derive(Debug, Clone, Error)]
+pub enum ReconciliationError {
+    #[error("The wallet export could not be read. {0}")]
+    InvalidExport(String),
+    #[error("The wallet history could not be fetched. {0}")]
+    SourceUnavailable(String),
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+}
+
+impl From<AccountApiError> for ReconciliationError {
+    fn from(e: AccountApiError) -> Self {
+        Self::DatabaseError(e.to_string())
+    }
+}
+
+impl From<PaymentGatewayError> for ReconciliationError {
+    fn from(e: PaymentGatewayError) -> Self {
+        Self::DatabaseError(e.to_string())
+    }
+}
+
+/// An inbound transaction in the hot wallet's history.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletTransaction {
+    /// The wallet's transaction id. Payments are recorded under this id by the notifier script and the wallet watcher.
+    pub txid: String,
+    pub sender: SerializedTariAddress,
+    pub amount: MicroTari,
+    pub memo: Option<String>,
+    /// When the wallet received the transaction, if the source reports it
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Whether the wallet considers the transaction to be confirmed on the blockchain
+    #[serde(default)]
+    pub confirmed: bool,
+}
+
+impl WalletTransaction {
+    pub fn new(txid: String, sender: SerializedTariAddress, amount: MicroTari) -> Self {
+        Self { txid, sender, amount, memo: None, timestamp: None, confirmed: false }
+    }
+
+    pub fn with_memo(mut self, memo: String) -> Self {
+        self.memo = Some(memo);
+        self
+    }
+
+    pub fn with_timestamp(mut self, timestamp: DateTime<Utc>) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    pub fn with_confirmed(mut self, confirmed: bool) -> Self {
+        self.confirmed = confirmed;
+        self
+    }
+
+    /// The payment that the transaction should have been recorded as.
+    pub fn to_new_payment(&self) -> NewPayment {
+        let mut payment = NewPayment::new(self.sender.as_address().clone(), self.amount, self.txid.clone());
+        if let Some(memo) = self.memo.as_ref().filter(|m| !m.trim().is_empty()) {
+            payment.with_memo(memo.clone());
+        }
+        payment
+    }
+}
+
+/// A wallet transaction that has been recorded as a payment, but with different details.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentMismatch {
+    pub transaction: WalletTransaction,
+    pub payment: Payment,
+    /// A description of each difference, e.g. the two amounts
+    pub differences: Vec<String>,
+}
+
+/// A missing transaction that could not be imported as a payment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportFailure {
+    pub txid: String,
+    pub reason: String,
+}
+
+/// The outcome of comparing the wallet's history with the `payments` table.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReconciliationReport {
+    /// The number of transactions whose payment matches exactly
+    pub matched: usize,
+    /// Transactions that have not been recorded as payments
+    pub missing: Vec<WalletTransaction>,
+    /// On-chain payments that are not in the wallet's history. Cancelled payments are not included.
+    pub extra: Vec<Payment>,
+    /// Transactions whose payment has a different amount or sender, or has been cancelled
+    pub mismatched: Vec<PaymentMismatch>,
+    /// The payments that were created for missing transactions, if they were imported
+    pub imported: Vec<Payment>,
+    pub import_failures: Vec<ImportFailure>,
+}
+
+impl ReconciliationReport {
+    /// Compares the wallet transactions with the payments, by txid. If a txid appears more than once in
+    /// `transactions`, only the first one is used.
+    pub fn compare(transactions: Vec<WalletTransaction>, payments: Vec<Payment>) -> Self {
+        let mut payments = payments.into_iter().map(|p| (p.txid.clone(), p)).collect::<HashMap<_, _>>();
+        let mut seen = HashSet::new();
+        let mut report = Self::default();
+        for transaction in transactions.into_iter().filter(|tx| seen.insert(tx.txid.clone())) {
+            match payments.remove(&transaction.txid) {
+                None => report.missing.push(transaction),
+                Some(payment) => {
+                    let differences = differences(&transaction, &payment);
+                    if differences.is_empty() {
+                        report.matched += 1;
+                    } else {
+                        report.mismatched.push(PaymentMismatch { transaction, payment, differences });
+                    }
+                },
+            }
+        }
+        report.extra = payments.into_values().filter(|p| p.status != TransferStatus::Cancelled).collect();
+        report.extra.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.txid.cmp(&b.txid)));
+        report
+    }
+
+    /// True if every transaction matched its payment and there are no extra payments.
+    pub fn is_reconciled(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+impl Display for ReconciliationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} matched, {} missing, {} extra, {} mismatched",
+            self.matched,
+            self.missing.len(),
+            self.extra.len(),
+            self.mismatched.len()
+        )?;
+        if !self.imported.is_empty() || !self.import_failures.is_empty() {
+            write!(f, ". {} imported, {} failed to import", self.imported.len(), self.import_failures.len())?;
+        }
+        Ok(())
+    }
+}
+
+fn differences(transaction: &WalletTransaction, payment: &Payment) -> Vec<String> {
+    let mut differences = Vec::new();
+    if transaction.amount != payment.amount {
+        differences
+            .push(format!("The wallet received {}, but the payment is for {}", transaction.amount, payment.amount));
+    }
+    if transaction.sender.as_address() != payment.sender.as_address() {
+        differences.push(format!(
+            "The wallet received it from {}, but the payment is from {}",
+            transaction.sender.as_base58(),
+            payment.sender.as_base58()
+        ));
+    }
+    if payment.status == TransferStatus::Cancelled {
+        differences.push("The payment was cancelled, but the wallet still has the transaction".to_string());
+    }
+    differences
+}
+
+//--------------------------------------    Wallet exports     --------------------------------------------------------
+
+/// The format of a wallet transaction export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+impl ExportFormat {
+    /// Guesses the format from a file name, e.g. `transactions.csv`.
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        match name.rsplit_once('.')?.1.to_ascii_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// A field that may be written as a number or as text
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum Scalar {
+    Number(u64),
+    Text(String),
+}
+
+impl Display for Scalar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{n}"),
+            Self::Text(s) => write!(f, "{}", s.trim()),
+        }
+    }
+}
+
+/// A transaction in a console wallet export. The field names follow the wallet's gRPC `TransactionInfo` message, with
+/// a few common alternatives. Amounts are in µT, and timestamps are Unix timestamps or RFC 3339 dates.
+#[derive(Debug, Clone, Deserialize)]
+struct ExportedTransaction {
+    #[serde(alias = "txid", alias = "TxId")]
+    tx_id: Scalar,
+    #[serde(alias = "sender", alias = "source")]
+    source_address: String,
+    amount: Scalar,
+    #[serde(default)]
+    direction: Option<String>,
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    is_cancelled: Option<bool>,
+    #[serde(default, alias = "memo", alias = "payment_id")]
+    message: Option<String>,
+    #[serde(default)]
+    timestamp: Option<Scalar>,
+}
+
+impl ExportedTransaction {
+    /// Only inbound transactions that the wallet has not cancelled or rejected are payments. Coinbases are not
+    /// payments either.
+    fn is_payment(&self) -> bool {
+        let inbound = self.direction.as_ref().map_or(true, |d| d.to_ascii_lowercase().contains("inbound"));
+        let status = self.status.as_deref().unwrap_or_default().to_ascii_lowercase();
+        let void = ["cancel", "reject", "coinbase"].iter().any(|s| status.contains(s));
+        inbound && !void && !self.is_cancelled.unwrap_or(false)
+    }
+
+    fn try_into_transaction(self) -> Result<WalletTransaction, String> {
+        let txid = self.tx_id.to_string();
+        let sender = self
+            .source_address
+            .trim()
+            .parse::<SerializedTariAddress>()
+            .map_err(|e| format!("Transaction {txid} has an invalid sender address. {e}"))?;
+        let amount = match &self.amount {
+            Scalar::Number(n) => MicroTari::try_from(*n).ok(),
+            Scalar::Text(s) => s.trim().parse::<u64>().ok().and_then(|n| MicroTari::try_from(n).ok()),
+        }
+        .ok_or_else(|| format!("Transaction {txid} has an invalid amount: {}", self.amount))?;
+        let status = self.status.as_deref().unwrap_or_default().to_ascii_lowercase();
+        let confirmed = status.contains("confirmed") && !status.contains("unconfirmed");
+        let mut transaction = WalletTransaction::new(txid, sender, amount).with_confirmed(confirmed);
+        if let Some(memo) = self.message.filter(|m| !m.trim().is_empty()) {
+            transaction = transaction.with_memo(memo);
+        }
+        let timestamp = match self.timestamp {
+            None => None,
+            Some(Scalar::Text(s)) if s.trim().is_empty() => None,
+            Some(Scalar::Number(secs)) => i64::try_from(secs).ok().and_then(|secs| DateTime::from_timestamp(secs, 0)),
+            Some(Scalar::Text(s)) => match s.trim().parse::<i64>() {
+                Ok(secs) => DateTime::from_timestamp(secs, 0),
+                Err(_) => DateTime::parse_from_rfc3339(s.trim()).ok().map(|t| t.with_timezone(&Utc)),
+            },
+        };
+        if let Some(timestamp) = timestamp {
+            transaction = transaction.with_timestamp(timestamp);
+        }
+        Ok(transaction)
+    }
+}
+
+/// Reads the inbound payments from a wallet transaction export. Outbound, cancelled and rejected transactions are
+/// skipped.
+pub fn parse_wallet_export(data: &str, format: ExportFormat) -> Result<Vec<WalletTransaction>, ReconciliationError> {
+    let rows = match format {
+        ExportFormat::Csv => csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(data.as_bytes())
+            .deserialize::<ExportedTransaction>()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ReconciliationError::InvalidExport(e.to_string()))?,
+        ExportFormat::Json => serde_json::from_str::<Vec<ExportedTransaction>>(data)
+            .map_err(|e| ReconciliationError::InvalidExport(e.to_string()))?,
+    };
+    rows.into_iter()
+        .filter(ExportedTransaction::is_payment)
+        .map(|row| row.try_into_transaction().map_err(ReconciliationError::InvalidExport))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+    use tari_common_types::tari_address::TariAddress;
+
+    use super::*;
+    use crate::db_types::{PaymentType, DEFAULT_MERCHANT};
+
+    const ALICE: &str = "14AYt2hhhn4VydAXNJ6i7ZfRNZGoGSp713dHjMYCoK5hYw2";
+    const BOB: &str = "14s9vDTwrweZvWEgQ9gNhXXPX68DPXSSAHNFWYEPi5JsBQY";
+
+    fn payment(txid: &str, sender: &str, amount: i64, status: TransferStatus) -> Payment {
+        Payment {
+            txid: txid.to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            sender: sender.parse().unwrap(),
+            amount: MicroTari::from(amount),
+            memo: None,
+            payment_type: PaymentType::OnChain,
+            status,
+            order_id: None,
+            block_height: None,
+            confirmations: 0,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
+        }
+    }
+
+    fn transaction(txid: &str, sender: &str, amount: i64) -> WalletTransaction {
+        WalletTransaction::new(txid.to_string(), sender.parse().unwrap(), MicroTari::from(amount))
+    }
+
+    #[test]
+    fn wallet_history_is_compared_with_payments() {
+        let transactions = vec![
+            transaction("1", ALICE, 100),
+            transaction("2", ALICE, 200),
+            transaction("3", ALICE, 300),
+            transaction("4", BOB, 400),
+            transaction("5", BOB, 500),
+            transaction("1", ALICE, 100),
+        ];
+        let payments = vec![
+            payment("1", ALICE, 100, TransferStatus::Confirmed),
+            payment("3", ALICE, 333, TransferStatus::Received),
+            payment("4", ALICE, 400, TransferStatus::Confirmed),
+            payment("5", BOB, 500, TransferStatus::Cancelled),
+            payment("6", BOB, 600, TransferStatus::Received),
+            payment("7", BOB, 700, TransferStatus::Cancelled),
+        ];
+        let report = ReconciliationReport::compare(transactions, payments);
+        assert_eq!(report.matched, 1);
+        assert_eq!(report.missing, vec![transaction("2", ALICE, 200)]);
+        let extra = report.extra.iter().map(|p| p.txid.as_str()).collect::<Vec<_>>();
+        assert_eq!(extra, vec!["6"]);
+        let mismatched = report.mismatched.iter().map(|m| m.payment.txid.as_str()).collect::<Vec<_>>();
+        assert_eq!(mismatched, vec!["3", "4", "5"]);
+        assert!(report.mismatched.iter().all(|m| m.differences.len() == 1));
+        assert!(!report.is_reconciled());
+        assert_eq!(report.to_string(), "1 matched, 1 missing, 1 extra, 3 mismatched");
+    }
+
+    #[test]
+    fn csv_exports_are_parsed() {
+        let alice = TariAddress::from_base58(ALICE).unwrap();
+        let csv = format!(
+            "tx_id,source_address,amount,direction,status,is_cancelled,message,timestamp\n101,{ALICE},5000000,Inbound,\
+             Mined_Confirmed,false,\"order 12, \
+             thanks\",1718000000\n102,{ALICE},1000,Outbound,Broadcast,false,,\n103,{},2000,Inbound,Broadcast,true,,\\
+             n104,{},3000,Inbound,Completed,false,,2024-06-10T06:13:20Z\n",
+            alice.to_hex(),
+            alice.to_hex()
+        );
+        let transactions = parse_wallet_export(&csv, ExportFormat::Csv).unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].txid, "101");
+        assert_eq!(transactions[0].amount, MicroTari::from(5_000_000));
+        assert_eq!(transactions[0].memo.as_deref(), Some("order 12, thanks"));
+        assert_eq!(transactions[0].timestamp, DateTime::from_timestamp(1_718_000_000, 0));
+        assert!(transactions[0].confirmed);
+        assert!(!transactions[1].confirmed);
+        assert_eq!(transactions[1].sender.as_address(), &alice);
+        assert_eq!(transactions[1].memo, None);
+        assert_eq!(transactions[1].timestamp, DateTime::from_timestamp(1_718_000_000, 0));
+    }
+
+    #[test]
+    fn json_exports_are_parsed() {
+        let json = format!(
+            r#"[
+              {{"tx_id": 7, "source_address": "{ALICE}", "amount": 1500, "direction": "TRANSACTION_DIRECTION_INBOUND"}},
+              {{"txid": "8", "sender": "{BOB}", "amount": "2500", "memo": "Payment for order 9"}},
+              {{"tx_id": 9, "source_address": "{BOB}", "amount": 100, "status": "TRANSACTION_STATUS_COINBASE"}}
+            ]"#
+        );
+        let transactions = parse_wallet_export(&json, ExportFormat::Json).unwrap();
+        assert_eq!(transactions, vec![
+            transaction("7", ALICE, 1500),
+            transaction("8", BOB, 2500).with_memo("Payment for order 9".to_string())
+        ]);
+        let err = parse_wallet_export(r#"[{"tx_id": 1, "source_address": "nope", "amount": 1}]"#, ExportFormat::Json);
+        assert!(matches!(err, Err(ReconciliationError::InvalidExport(_))));
+        assert_eq!(ExportFormat::from_file_name("history.CSV"), Some(ExportFormat::Csv));
+        assert_eq!(ExportFormat::from_file_name("history"), None);
+    }
+}
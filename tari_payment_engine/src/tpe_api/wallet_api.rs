@@ -42,41 +42,46 @@ where B: WalletAuth
     /// Authenticates the wallet signature against the state stored in the database.
     ///
     /// In particular:
-    /// - The signature is internally valid
     /// - The address of the wallet sending the message matches the record in the database
-    /// - The nonce is greater than the nonce stored in the database
     /// - The remote IP address matches the IP address stored in the database
+    /// - The signature is internally valid
+    /// - The nonce is greater than the nonce stored in the database
     /// - Updating the nonce in the database is successful
+    ///
+    /// The IP whitelist is checked before the signature, so that requests from unknown IP addresses are refused
+    /// without verifying their signatures.
+    ///
+    /// Returns the wallet's details, so that the caller knows which merchant the wallet belongs to.
     pub async fn authenticate_wallet<T: Serialize>(
         &self,
         sig: WalletSignature,
         remote_ip: Option<&IpAddr>,
         payload: &T,
         disable_ip_check: bool,
-    ) -> Result<(), WalletAuthApiError> {
-        if !sig.is_valid(payload) {
-            return Err(WalletAuthApiError::InvalidSignature);
-        }
-        trace!("Wallet signature for {} is valid", sig.address.as_base58());
+    ) -> Result<WalletInfo, WalletAuthApiError> {
         let address = sig.address.as_address();
         let wallet_info = self.db.get_wallet_info(address).await?;
         if wallet_info.address != sig.address {
             return Err(WalletAuthApiError::WalletNotFound);
         }
-        // The DB will usually trigger a constraint violation if the nonce is not greater than the last nonce,
-        // but we check here in case the backend does not
-        if wallet_info.last_nonce >= sig.nonce {
-            return Err(WalletAuthApiError::InvalidNonce);
-        }
-        let ip_matches = remote_ip.map(|ip| wallet_info.ip_address != *ip).unwrap_or(false);
+        let ip_mismatch = remote_ip.map(|ip| wallet_info.ip_address != *ip).unwrap_or(false);
         if disable_ip_check {
             info!("Wallet whitelist checks are DISABLED.");
         }
-        if !disable_ip_check && ip_matches {
+        if !disable_ip_check && ip_mismatch {
             return Err(WalletAuthApiError::InvalidIpAddress);
         }
+        if !sig.is_valid(payload) {
+            return Err(WalletAuthApiError::InvalidSignature);
+        }
+        trace!("Wallet signature for {} is valid", sig.address.as_base58());
+        // The DB will usually trigger a constraint violation if the nonce is not greater than the last nonce,
+        // but we check here in case the backend does not
+        if wallet_info.last_nonce >= sig.nonce {
+            return Err(WalletAuthApiError::InvalidNonce);
+        }
         self.update_wallet_nonce(address, sig.nonce).await?;
-        Ok(())
+        Ok(wallet_info)
     }
 }
 
@@ -0,0 +1,101 @@
+//! The WebhookApi manages outbound webhook subscriptions, so that external services (ERPs, notification services and
+//! the like) can receive engine events over HTTPS without any code changes.
+
+use std::fmt::Debug;
+
+use crate::{
+    db_types::{NewWebhookSubscription, WebhookDelivery, WebhookSubscription},
+    events::EVENT_TYPE_NAMES,
+    tpe_api::account_objects::Pagination,
+    traits::{WebhookError, WebhookManagement},
+};
+
+/// Webhook secrets shorter than this are rejected.
+pub const MIN_WEBHOOK_SECRET_LENGTH: usize = 16;
+
+pub struct WebhookApi<B> {
+    db: B,
+}
+
+impl<B> Debug for WebhookApi<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WebhookApi")
+    }
+}
+
+impl<B> WebhookApi<B>
+where B: WebhookManagement
+{
+    pub fn new(db: B) -> Self {
+        Self { db }
+    }
+
+    /// Subscribes a URL to an event type. The URL must use HTTPS, and the event type must be one of
+    /// [`EVENT_TYPE_NAMES`].
+    pub async fn create_webhook(&self, webhook: NewWebhookSubscription) -> Result<WebhookSubscription, WebhookError> {
+        validate_subscription(&webhook)?;
+        self.db.create_webhook(&webhook).await
+    }
+
+    pub async fn fetch_webhooks(&self) -> Result<Vec<WebhookSubscription>, WebhookError> {
+        self.db.fetch_webhooks().await
+    }
+
+    pub async fn set_webhook_active(&self, id: i64, active: bool) -> Result<WebhookSubscription, WebhookError> {
+        self.db.set_webhook_active(id, active).await
+    }
+
+    pub async fn delete_webhook(&self, id: i64) -> Result<(), WebhookError> {
+        self.db.delete_webhook(id).await
+    }
+
+    pub async fn fetch_deliveries(
+        &self,
+        subscription_id: Option<i64>,
+        pagination: &Pagination,
+    ) -> Result<Vec<WebhookDelivery>, WebhookError> {
+        self.db.fetch_webhook_deliveries(subscription_id, pagination).await
+    }
+
+    pub async fn replay_delivery(&self, id: i64) -> Result<WebhookDelivery, WebhookError> {
+        self.db.replay_webhook_delivery(id).await
+    }
+}
+
+fn validate_subscription(webhook: &NewWebhookSubscription) -> Result<(), WebhookError> {
+    if !webhook.url.starts_with("https://") || webhook.url.len() <= "https://".len() {
+        return Err(WebhookError::InvalidSubscription(format!("{} is not an HTTPS URL", webhook.url)));
+    }
+    if !EVENT_TYPE_NAMES.contains(&webhook.event_type.as_str()) {
+        return Err(WebhookError::InvalidSubscription(format!(
+            "{} is not a known event type. Use one of {}",
+            webhook.event_type,
+            EVENT_TYPE_NAMES.join(", ")
+        )));
+    }
+    if webhook.secret.len() < MIN_WEBHOOK_SECRET_LENGTH {
+        return Err(WebhookError::InvalidSubscription(format!(
+            "The secret must be at least {MIN_WEBHOOK_SECRET_LENGTH} characters long"
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn webhook(url: &str, event_type: &str, secret: &str) -> NewWebhookSubscription {
+        NewWebhookSubscription { url: url.into(), event_type: event_type.into(), secret: secret.into() }
+    }
+
+    #[test]
+    fn subscriptions_are_validated() {
+        let secret = "0123456789abcdef";
+        assert!(validate_subscription(&webhook("https://erp.example.com/tari", "OrderPaid", secret)).is_ok());
+        assert!(validate_subscription(&webhook("http://erp.example.com/tari", "OrderPaid", secret)).is_err());
+        assert!(validate_subscription(&webhook("https://", "OrderPaid", secret)).is_err());
+        assert!(validate_subscription(&webhook("https://erp.example.com/tari", "OrderShipped", secret)).is_err());
+        assert!(validate_subscription(&webhook("https://erp.example.com/tari", "OrderPaid", "short")).is_err());
+    }
+}
@@ -1,10 +1,28 @@
+use chrono::{DateTime, Duration, Utc};
 use tari_common_types::tari_address::TariAddress;
 use thiserror::Error;
 
 use crate::{
-    db_types::{AddressBalance, CustomerBalance, CustomerOrderBalance, CustomerOrders, Order, OrderId, Payment},
+    db_types::{
+        AddressBalance,
+        ApprovalRequest,
+        ApprovalStatus,
+        CustomerBalance,
+        CustomerOrderBalance,
+        CustomerOrders,
+        HeldOrder,
+        LedgerAccount,
+        LedgerBalance,
+        LedgerEntry,
+        Order,
+        OrderId,
+        Payment,
+        Refund,
+        RefundStatus,
+        SettlementJournalEntry,
+    },
     order_objects::OrderQueryFilter,
-    tpe_api::account_objects::{AddressHistory, CustomerHistory, Pagination},
+    tpe_api::account_objects::{AddressHistory, CustomerHistory, Pagination, TrialBalance},
 };
 
 #[derive(Debug, Clone, Error)]
@@ -122,4 +140,72 @@ pub trait AccountManagement {
 
     /// Fetches payments that are explicitly linked to an order id
     async fn fetch_payments_for_order(&self, order_id: &OrderId) -> Result<Vec<Payment>, AccountApiError>;
+
+    /// Fetches the on-chain payments (i.e. not credit notes) that were received since `since`, or all of them if
+    /// `since` is `None`, oldest first.
+    async fn fetch_onchain_payments(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Payment>, AccountApiError>;
+
+    /// Fetches the balances of addresses that hold unspent funds, and have not seen any activity (payments,
+    /// settlements or refunds) for at least `idle`. The balances are per merchant, so an address that holds funds
+    /// with several merchants has a balance for each of them.
+    async fn fetch_idle_balances(&self, idle: Duration) -> Result<Vec<AddressBalance>, AccountApiError>;
+
+    async fn fetch_refund(&self, id: i64) -> Result<Option<Refund>, AccountApiError>;
+
+    /// Fetches refunds, newest first. If `status` is given, only refunds with that status are returned.
+    async fn fetch_refunds(
+        &self,
+        status: Option<RefundStatus>,
+        pagination: &Pagination,
+    ) -> Result<Vec<Refund>, AccountApiError>;
+
+    /// Fetches a held order, whether or not it has been released.
+    async fn fetch_held_order(&self, order_id: &OrderId) -> Result<Option<HeldOrder>, AccountApiError>;
+
+    /// Fetches the orders that are still being held, oldest first.
+    async fn fetch_held_orders(&self, pagination: &Pagination) -> Result<Vec<HeldOrder>, AccountApiError>;
+
+    /// Fetches the entries posted to a ledger account, oldest first.
+    async fn fetch_ledger_entries(
+        &self,
+        account: &LedgerAccount,
+        pagination: &Pagination,
+    ) -> Result<Vec<LedgerEntry>, AccountApiError>;
+
+    /// Fetches the total debits and credits of every ledger account that has entries.
+    async fn fetch_ledger_balances(&self) -> Result<Vec<LedgerBalance>, AccountApiError>;
+
+    /// Builds the trial balance of the ledger. If the ledger is consistent, the debits and credits balance, both
+    /// overall and for each transaction.
+    async fn fetch_trial_balance(&self) -> Result<TrialBalance, AccountApiError>;
+
+    /// Fetches the orders that were created in the period `[from, to)`, oldest first.
+    async fn fetch_orders_between(&self, from: DateTime<Utc>, to: DateTime<Utc>)
+        -> Result<Vec<Order>, AccountApiError>;
+
+    /// Fetches every payment, including credit notes, that was received in the period `[from, to)`, oldest first.
+    async fn fetch_payments_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Payment>, AccountApiError>;
+
+    /// Fetches the settlement journal entries that were made in the period `[from, to)`, oldest first.
+    async fn fetch_settlements_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<SettlementJournalEntry>, AccountApiError>;
+
+    async fn fetch_approval_request(&self, id: i64) -> Result<Option<ApprovalRequest>, AccountApiError>;
+
+    /// Fetches approval requests, newest first. If `status` is given, only requests with that status are returned.
+    ///
+    /// Pending requests whose expiry time has passed are returned as they are stored, i.e. as `Pending`, until
+    /// [`crate::traits::PaymentGatewayDatabase::expire_approval_requests`] marks them as expired.
+    async fn fetch_approval_requests(
+        &self,
+        status: Option<ApprovalStatus>,
+        pagination: &Pagination,
+    ) -> Result<Vec<ApprovalRequest>, AccountApiError>;
 }
@@ -0,0 +1,42 @@
+use thiserror::Error;
+
+use crate::{
+    db_types::{AdminAction, AdminActionFilter, NewAdminAction},
+    tpe_api::account_objects::Pagination,
+};
+
+#[derive(Debug, Clone, Error)]
+pub enum AuditLogError {
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+    #[error("Could not (de)serialize an admin action. {0}")]
+    SerializationError(String),
+}
+
+impl From<sqlx::Error> for AuditLogError {
+    fn from(e: sqlx::Error) -> Self {
+        AuditLogError::DatabaseError(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for AuditLogError {
+    fn from(e: serde_json::Error) -> Self {
+        AuditLogError::SerializationError(e.to_string())
+    }
+}
+
+/// The admin audit log.
+///
+/// The `orders_log` and `payments_log` tables record what changed, but not who changed it. The server records every
+/// privileged API call here, whether it succeeded or not, along with the caller's address and roles, the parameters
+/// and reason they gave, and where the call came from. Records are never changed or deleted.
+#[allow(async_fn_in_trait)]
+pub trait AuditLog {
+    async fn record_admin_action(&self, action: &NewAdminAction) -> Result<AdminAction, AuditLogError>;
+    /// The admin actions that match the filter, newest first.
+    async fn fetch_admin_actions(
+        &self,
+        filter: &AdminActionFilter,
+        pagination: &Pagination,
+    ) -> Result<Vec<AdminAction>, AuditLogError>;
+}
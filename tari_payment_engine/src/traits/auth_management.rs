@@ -1,7 +1,8 @@
+use chrono::{DateTime, Utc};
 use tari_common_types::tari_address::TariAddress;
 use thiserror::Error;
 
-use crate::db_types::Role;
+use crate::db_types::{AuthSession, NewAuthSession, Role, RoleDefinition};
 
 /// The `AuthManagement` trait defines behaviour for managing authentication and authorisation.
 ///
@@ -13,6 +14,16 @@ use crate::db_types::Role;
 /// used to create and update login records for users. See the Authentication documentation for `tari_payment_server` ,
 /// which is stateless on the user side. However, the server must keep track
 /// of a nonce for each user to ensure that authentication tokens cannot be replayed.
+///
+/// ## Roles and permissions
+/// Routes require [`crate::db_types::Permission`]s, and addresses are granted permissions through the roles assigned
+/// to them. The built-in roles always exist, but the permissions they grant can be edited, and new roles can be
+/// defined with [`AuthManagement::save_role`].
+///
+/// ## Sessions
+/// Every successful login opens an [`AuthSession`]. The session holds (a hash of) the refresh token that the user
+/// trades for new access tokens, and access tokens carry the id of their session. Revoking a session therefore
+/// invalidates both the refresh token and any access tokens that were issued for it.
 #[allow(async_fn_in_trait)]
 pub trait AuthManagement {
     /// Checks whether an account exists for the given address. The function succeeds if the query succeeds, returning
@@ -52,6 +63,89 @@ pub trait AuthManagement {
     /// Removes the given roles from the address. The number of roles actually removed is returned. This function must
     /// be idempotent.
     async fn remove_roles(&self, address: &TariAddress, roles: &[Role]) -> Result<u64, AuthApiError>;
+
+    /// Fetches the roles the address holds for a single merchant. Roles that apply to every merchant, i.e. those
+    /// returned by [`Self::fetch_roles_for_address`], are not included.
+    async fn fetch_merchant_roles_for_address(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+    ) -> Result<Vec<Role>, AuthApiError>;
+
+    /// Checks whether an address is authorised for **all** of the given roles for the given merchant. Roles that apply
+    /// to every merchant count as well. As with [`Self::check_address_has_roles`], the error
+    /// [`AuthApiError::RoleNotAllowed(usize)`] gives the number of missing roles.
+    async fn check_address_has_merchant_roles(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+        roles: &[Role],
+    ) -> Result<(), AuthApiError> {
+        let mut assigned = self.fetch_roles_for_address(address).await?;
+        assigned.extend(self.fetch_merchant_roles_for_address(address, merchant_id).await?);
+        let missing = roles.iter().filter(|r| !assigned.contains(r)).count();
+        if missing == 0 {
+            Ok(())
+        } else {
+            Err(AuthApiError::RoleNotAllowed(missing))
+        }
+    }
+
+    /// Assigns the given roles to the address for the given merchant only. This function must be idempotent.
+    async fn assign_merchant_roles(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+        roles: &[Role],
+    ) -> Result<(), AuthApiError>;
+
+    /// Removes roles that were assigned to the address for the given merchant. The number of roles actually removed is
+    /// returned. This function must be idempotent.
+    async fn remove_merchant_roles(
+        &self,
+        address: &TariAddress,
+        merchant_id: &str,
+        roles: &[Role],
+    ) -> Result<u64, AuthApiError>;
+
+    /// Fetches every role, built-in and custom, along with the permissions that it grants. Roles are ordered by name.
+    async fn fetch_role_definitions(&self) -> Result<Vec<RoleDefinition>, AuthApiError>;
+
+    /// Creates the role if it does not exist yet, and replaces the permissions that it grants. The `super_admin` role
+    /// always grants every permission, so [`AuthApiError::BuiltInRole`] is returned if it is given.
+    async fn save_role(&self, role: &RoleDefinition) -> Result<(), AuthApiError>;
+
+    /// Deletes a custom role, and removes it from every address that holds it. Built-in roles cannot be deleted
+    /// ([`AuthApiError::BuiltInRole`]). If the role does not exist, [`AuthApiError::RoleNotFound`] is returned.
+    async fn delete_role(&self, role: &Role) -> Result<(), AuthApiError>;
+
+    /// Opens a new login session and returns it.
+    async fn create_session(&self, session: NewAuthSession) -> Result<AuthSession, AuthApiError>;
+
+    /// Fetches a session by id. Revoked and expired sessions are returned too.
+    async fn fetch_session(&self, id: i64) -> Result<Option<AuthSession>, AuthApiError>;
+
+    /// Swaps the refresh token of the active session that holds `refresh_token_hash` for `new_refresh_token_hash`, and
+    /// returns the updated session. Refresh tokens can only be used once, so if no active session holds the token,
+    /// [`AuthApiError::InvalidRefreshToken`] is returned.
+    async fn refresh_session(
+        &self,
+        refresh_token_hash: &str,
+        new_refresh_token_hash: &str,
+    ) -> Result<AuthSession, AuthApiError>;
+
+    /// Fetches the active sessions for the address, newest first.
+    async fn fetch_sessions_for_address(&self, address: &TariAddress) -> Result<Vec<AuthSession>, AuthApiError>;
+
+    /// Revokes the session with the given id. Sessions that have already been revoked are left as they are. If the
+    /// session does not exist, [`AuthApiError::SessionNotFound`] is returned.
+    async fn revoke_session(&self, id: i64) -> Result<AuthSession, AuthApiError>;
+
+    /// Revokes every active session for the address, and returns the ids of the sessions that were revoked.
+    async fn revoke_sessions_for_address(&self, address: &TariAddress) -> Result<Vec<i64>, AuthApiError>;
+
+    /// Fetches the ids of the sessions that were revoked at or after `since`.
+    async fn fetch_revoked_sessions(&self, since: DateTime<Utc>) -> Result<Vec<i64>, AuthApiError>;
 }
 
 #[derive(Debug, Clone, Error)]
@@ -66,6 +160,12 @@ pub enum AuthApiError {
     RoleNotAllowed(usize),
     #[error("The requested role does not exist")]
     RoleNotFound,
+    #[error("The built-in role {0} cannot be changed")]
+    BuiltInRole(Role),
+    #[error("Session {0} does not exist")]
+    SessionNotFound(i64),
+    #[error("The refresh token is invalid, has expired or has been revoked")]
+    InvalidRefreshToken,
 }
 
 impl From<sqlx::Error> for AuthApiError {
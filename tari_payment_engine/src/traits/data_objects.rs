@@ -5,7 +5,15 @@ use sqlx::FromRow;
 use tpg_common::MicroTari;
 
 use crate::{
-    db_types::{Order, SerializedTariAddress, SettlementJournalEntry},
+    db_types::{
+        default_merchant,
+        NewSettlementJournalEntry,
+        Order,
+        OrderId,
+        Payment,
+        SerializedTariAddress,
+        SettlementJournalEntry,
+    },
     order_objects::OrderChanged,
 };
 
@@ -14,6 +22,9 @@ pub struct NewWalletInfo {
     pub address: SerializedTariAddress,
     pub ip_address: IpAddr,
     pub initial_nonce: Option<i64>,
+    /// The merchant that the wallet collects payments for
+    #[serde(default = "default_merchant")]
+    pub merchant_id: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -21,6 +32,9 @@ pub struct WalletInfo {
     pub address: SerializedTariAddress,
     pub ip_address: IpAddr,
     pub last_nonce: i64,
+    /// Payments that the wallet reports are credited to this merchant
+    #[serde(default = "default_merchant")]
+    pub merchant_id: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -107,6 +121,75 @@ impl Display for MultiAccountPayment {
     }
 }
 
+/// The outcome of reverting a confirmed payment whose transaction was lost in a chain reorg.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentReversal {
+    /// The payment, which is back to `Received`
+    pub payment: Payment,
+    /// The orders that were paid with the lost funds. They are back to `New`.
+    pub orders_reverted: Vec<Order>,
+    /// The journal entries that undo the settlements of the reverted orders
+    pub settlements: Vec<SettlementJournalEntry>,
+}
+
+impl PaymentReversal {
+    pub fn new(payment: Payment, orders_reverted: Vec<Order>, settlements: Vec<SettlementJournalEntry>) -> Self {
+        Self { payment, orders_reverted, settlements }
+    }
+
+    /// Chooses the orders to un-settle when an address has lost `shortfall` more than its balance covers.
+    ///
+    /// `entries` are the address's settlement journal entries. The most recently settled orders are chosen first, until
+    /// the funds they return to the address make up the shortfall.
+    pub fn orders_to_revert(entries: &[SettlementJournalEntry], shortfall: MicroTari) -> Vec<OrderId> {
+        // (order id, latest entry id, net amount settled)
+        let mut settled = Vec::<(&OrderId, i64, MicroTari)>::new();
+        for entry in entries {
+            match settled.iter_mut().find(|(id, _, _)| *id == &entry.order_id) {
+                Some((_, last_id, net)) => {
+                    *last_id = (*last_id).max(entry.id);
+                    *net = *net + entry.amount;
+                },
+                None => settled.push((&entry.order_id, entry.id, entry.amount)),
+            }
+        }
+        settled.retain(|(_, _, net)| *net > MicroTari::from(0));
+        settled.sort_by_key(|(_, last_id, _)| std::cmp::Reverse(*last_id));
+        let mut recovered = MicroTari::from(0);
+        let mut orders = Vec::new();
+        for (order_id, _, net) in settled {
+            if recovered >= shortfall {
+                break;
+            }
+            recovered = recovered + net;
+            orders.push(order_id.clone());
+        }
+        orders
+    }
+
+    /// The journal entries that undo an order's settlements. `entries` are the order's settlement journal entries.
+    ///
+    /// The journal is never edited, so each address that paid towards the order gets an entry for the negative of what
+    /// it paid.
+    pub fn reversing_entries(entries: &[SettlementJournalEntry]) -> Vec<NewSettlementJournalEntry> {
+        let mut paid = Vec::<NewSettlementJournalEntry>::new();
+        for entry in entries {
+            match paid.iter_mut().find(|p| p.payment_address == entry.payment_address) {
+                Some(p) => p.amount = p.amount - entry.amount,
+                None => paid.push(NewSettlementJournalEntry {
+                    order_id: entry.order_id.clone(),
+                    payment_address: entry.payment_address.clone(),
+                    settlement_type: entry.settlement_type,
+                    amount: -entry.amount,
+                    merchant_id: entry.merchant_id.clone(),
+                }),
+            }
+        }
+        paid.retain(|p| p.amount < MicroTari::from(0));
+        paid
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExpiryResult {
     pub unclaimed: Vec<Order>,
@@ -0,0 +1,63 @@
+use chrono::{DateTime, Duration, Utc};
+use thiserror::Error;
+
+use crate::{db_types::OutboxEvent, events::EventType, tpe_api::account_objects::Pagination};
+
+#[derive(Debug, Clone, Error)]
+pub enum OutboxError {
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+    #[error("Outbox event {0} does not exist")]
+    EventNotFound(i64),
+    #[error("Could not (de)serialize an outbox event. {0}")]
+    SerializationError(String),
+}
+
+impl From<sqlx::Error> for OutboxError {
+    fn from(e: sqlx::Error) -> Self {
+        OutboxError::DatabaseError(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for OutboxError {
+    fn from(e: serde_json::Error) -> Self {
+        OutboxError::SerializationError(e.to_string())
+    }
+}
+
+/// The event outbox is a durable queue of events.
+///
+/// Backends write the events for their own state changes (orders being paid, payments being confirmed, and so on) to
+/// the outbox as part of the same transaction as the change itself, so an event can never be lost if the process dies
+/// before the hooks have run. The [`crate::events::OutboxDispatcher`] uses this trait to deliver the events and
+/// record the outcome.
+#[allow(async_fn_in_trait)]
+pub trait EventOutbox {
+    /// Writes events that do not originate from a backend state change to the outbox.
+    async fn enqueue_events(&self, events: &[EventType]) -> Result<Vec<OutboxEvent>, OutboxError>;
+    /// Fetches up to `limit` undelivered events that are due for a delivery attempt, oldest first. Events that another
+    /// dispatcher has claimed are left out. This does not claim the events.
+    async fn fetch_due_events(&self, limit: i64) -> Result<Vec<OutboxEvent>, OutboxError>;
+    /// Atomically claims up to `limit` due events, oldest first. The claimed events are leased to the caller for
+    /// `lease`, during which no other call will claim them. Recording the outcome of the delivery releases the lease.
+    async fn claim_due_events(&self, limit: i64, lease: Duration) -> Result<Vec<OutboxEvent>, OutboxError>;
+    /// Fetches all undelivered events, including those that have run out of retries, oldest first.
+    async fn fetch_undelivered_events(&self, pagination: &Pagination) -> Result<Vec<OutboxEvent>, OutboxError>;
+    async fn fetch_outbox_event(&self, id: i64) -> Result<Option<OutboxEvent>, OutboxError>;
+    async fn mark_event_delivered(&self, id: i64) -> Result<OutboxEvent, OutboxError>;
+    /// Records a failed delivery attempt. If `retry_at` is `None`, the event is parked and will not be retried until
+    /// it is replayed.
+    async fn record_failed_delivery(
+        &self,
+        id: i64,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<OutboxEvent, OutboxError>;
+    /// The names of the handlers that have already delivered the event.
+    async fn fetch_delivered_handlers(&self, id: i64) -> Result<Vec<String>, OutboxError>;
+    /// Records that the named handler has delivered the event, so that it is not run again when the event is retried.
+    async fn mark_handler_delivered(&self, id: i64, handler: &str) -> Result<(), OutboxError>;
+    /// Schedules the event for immediate (re)delivery, whether or not it has been delivered before. Replaying an event
+    /// that was delivered runs every handler again, while replaying a parked event only runs the handlers that failed.
+    async fn replay_event(&self, id: i64) -> Result<OutboxEvent, OutboxError>;
+}
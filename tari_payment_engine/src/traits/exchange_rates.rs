@@ -1,6 +1,7 @@
+use chrono::{DateTime, Utc};
 use thiserror::Error;
 
-use crate::tpe_api::exchange_objects::ExchangeRate;
+use crate::tpe_api::{account_objects::Pagination, exchange_objects::ExchangeRate};
 
 #[derive(Debug, Clone, Error)]
 pub enum ExchangeRateError {
@@ -12,9 +13,37 @@ pub enum ExchangeRateError {
 
 #[allow(async_fn_in_trait)]
 pub trait ExchangeRates {
-    /// Fetch the last exchange rate for the given currency. If the rate does not exist, the error
-    /// [`ExchangeRateError::RateDoesNotExist`] is returned.
+    /// Fetch the last exchange rate for the given currency, as set for the default merchant. If the rate does not
+    /// exist, the error [`ExchangeRateError::RateDoesNotExist`] is returned.
     async fn fetch_last_rate(&self, currency: &str) -> Result<ExchangeRate, ExchangeRateError>;
+    /// Fetch the last exchange rate that `merchant_id` set for the given currency. Merchants that have not set a rate
+    /// for the currency use the default merchant's rate. If neither exists, the error
+    /// [`ExchangeRateError::RateDoesNotExist`] is returned.
+    async fn fetch_merchant_rate(&self, merchant_id: &str, currency: &str) -> Result<ExchangeRate, ExchangeRateError>;
+    /// Fetch a stored exchange rate by its id. If the rate does not exist, the error
+    /// [`ExchangeRateError::RateDoesNotExist`] is returned.
+    async fn fetch_rate_by_id(&self, id: i64) -> Result<ExchangeRate, ExchangeRateError>;
+    /// Fetch the exchange rate that was in effect for the given currency at `timestamp`, i.e. the last rate that was
+    /// set at or before that time, as set for the default merchant. If there was no rate yet, the error
+    /// [`ExchangeRateError::RateDoesNotExist`] is returned.
+    async fn fetch_rate_at(&self, currency: &str, timestamp: DateTime<Utc>) -> Result<ExchangeRate, ExchangeRateError>;
+    /// Fetch the rate for the same currency and merchant that was stored just before `rate`, i.e. the rate that
+    /// `rate` replaced. Returns `None` if `rate` is the first rate for its currency.
+    async fn fetch_previous_rate(&self, rate: &ExchangeRate) -> Result<Option<ExchangeRate>, ExchangeRateError>;
+    /// Fetch the history of exchange rates that `merchant_id` set for the given currency, oldest first. `since` and
+    /// `until` are inclusive, and either may be omitted to leave that end of the range open.
+    async fn fetch_rate_history(
+        &self,
+        merchant_id: &str,
+        currency: &str,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        pagination: &Pagination,
+    ) -> Result<Vec<ExchangeRate>, ExchangeRateError>;
     /// Save the exchange rate for the given currency to the backend storage
     async fn set_exchange_rate(&self, rate: &ExchangeRate) -> Result<(), ExchangeRateError>;
+    /// Record that a rate source has just reported the stored rate `rate` again. Only `confirmed_at` changes, so the
+    /// rate history is unaffected. If the rate does not exist, the error [`ExchangeRateError::RateDoesNotExist`] is
+    /// returned.
+    async fn confirm_exchange_rate(&self, rate: &ExchangeRate) -> Result<(), ExchangeRateError>;
 }
@@ -20,19 +20,38 @@
 //! * [`AuthManagement`] defines behavior for managing authentication.
 //! * [`AccountManagement`] provides methods for querying information about user accounts, orders and payments.
 //! * [`WalletManagement`] defines behavior for managing the set of authorized hot wallets associated with the server.
+//! * [`EventOutbox`] defines the durable event queue that backs the payment engine's event hooks.
+//! * [`WebhookManagement`] manages outbound webhook subscriptions and their delivery log.
+//! * [`AuditLog`] records the privileged calls that admins make to the server.
+//! * [`RateLimitStore`] keeps the token buckets for rate limits that are shared by several server instances.
 mod account_management;
+mod audit_log;
 mod auth_management;
+mod event_outbox;
 
 mod exchange_rates;
 mod payment_gateway_database;
+mod rate_limits;
 
 mod wallet_management;
+mod webhook_management;
 
 mod data_objects;
 
 pub use account_management::{AccountApiError, AccountManagement};
+pub use audit_log::{AuditLog, AuditLogError};
 pub use auth_management::{AuthApiError, AuthManagement};
-pub use data_objects::{ExpiryResult, MultiAccountPayment, NewWalletInfo, OrderMovedResult, WalletInfo};
+pub use data_objects::{
+    ExpiryResult,
+    MultiAccountPayment,
+    NewWalletInfo,
+    OrderMovedResult,
+    PaymentReversal,
+    WalletInfo,
+};
+pub use event_outbox::{EventOutbox, OutboxError};
 pub use exchange_rates::{ExchangeRateError, ExchangeRates};
 pub use payment_gateway_database::{PaymentGatewayDatabase, PaymentGatewayError};
+pub use rate_limits::{RateLimitError, RateLimitStore};
 pub use wallet_management::{WalletAuth, WalletAuthApiError, WalletManagement, WalletManagementError};
+pub use webhook_management::{WebhookError, WebhookManagement};
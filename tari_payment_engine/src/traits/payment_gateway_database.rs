@@ -1,13 +1,27 @@
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
 use tari_common_types::tari_address::TariAddress;
 use thiserror::Error;
 use tpg_common::MicroTari;
 
 use crate::{
-    db_types::{CreditNote, NewOrder, NewPayment, Order, OrderId, OrderStatusType, Payment, TransferStatus},
+    db_types::{
+        ApprovalRequest,
+        CreditNote,
+        HeldOrder,
+        NewApprovalRequest,
+        NewOrder,
+        NewPayment,
+        NewRefund,
+        Order,
+        OrderId,
+        OrderStatusType,
+        Payment,
+        Refund,
+        TransferStatus,
+    },
     order_objects::OrderChanged,
     traits::{
-        data_objects::{ExpiryResult, MultiAccountPayment, OrderMovedResult},
+        data_objects::{ExpiryResult, MultiAccountPayment, OrderMovedResult, PaymentReversal},
         AccountApiError,
         AccountManagement,
     },
@@ -86,6 +100,10 @@ pub trait PaymentGatewayDatabase: Clone + AccountManagement {
     /// Tries to pay for an order using any addresses associated with the customer id attached to this order.
     /// If you've claimed an order, or otherwise know which address you want to pay from, use
     /// [`try_pay_orders_from_address`] instead.
+    ///
+    /// The order is read again in the settlement transaction, so a stale copy cannot be paid at an out-of-date price.
+    /// If its quote has expired, [`PaymentGatewayError::QuoteExpired`] is returned and the order is left unpaid until
+    /// it has been re-priced.
     async fn try_pay_order(
         &self,
         order: &Order,
@@ -113,6 +131,31 @@ pub trait PaymentGatewayDatabase: Clone + AccountManagement {
     /// Fetches the payment for the given transaction id.
     async fn fetch_payment_by_tx_id(&self, tx_id: &str) -> Result<Payment, PaymentGatewayError>;
 
+    /// Records where the payment's transaction is in the chain: the height of the block it was mined in (`None` if it
+    /// is not mined), and how many blocks deep it is. The payment status is not changed.
+    ///
+    /// ## Failure modes:
+    /// - If the payment does not exist.
+    async fn update_payment_confirmations(
+        &self,
+        tx_id: &str,
+        block_height: Option<i64>,
+        confirmations: i64,
+    ) -> Result<Payment, PaymentGatewayError>;
+
+    /// Reverts a `Confirmed` payment to `Received`, because its transaction has been lost in a chain reorg. Its block
+    /// height and confirmation count are cleared.
+    ///
+    /// If the rest of the sender's balance does not cover the payment, the orders paid from the address are
+    /// un-settled, most recent first, until it does. Reversing entries are added to the settlement journal for each of
+    /// these orders (including the parts paid from other addresses), and the orders go back to `New`. A
+    /// `PaymentReverted` event is written to the outbox.
+    ///
+    /// ## Failure modes:
+    /// - If the payment does not exist.
+    /// - If the payment is not `Confirmed`.
+    async fn revert_payment(&self, tx_id: &str) -> Result<PaymentReversal, PaymentGatewayError>;
+
     /// A manual order status transition from `New` to `Paid` status.
     /// This method is called by the default implementation of [`modify_status_for_order`] when the new status is
     /// `Paid`. When this happens, the following side effects occur:
@@ -212,6 +255,27 @@ pub trait PaymentGatewayDatabase: Clone + AccountManagement {
         strict_mode: bool,
     ) -> Result<OrderChanged, PaymentGatewayError>;
 
+    /// Fetches the `New` and `Unclaimed` orders whose exchange rate quote has expired. These orders cannot be paid
+    /// until they have been re-priced with [`Self::requote_order`].
+    async fn fetch_orders_with_expired_quotes(&self) -> Result<Vec<Order>, PaymentGatewayError>;
+
+    /// Re-prices an unpaid order at the exchange rate with id `rate_id`, and gives it a new quote expiry time.
+    ///
+    /// Unlike [`Self::modify_total_price_for_order`], `Unclaimed` orders can be re-priced too. The order's
+    /// `updated_at` time is not changed, so re-pricing an order does not postpone its expiry. An `OrderModified` event
+    /// is queued.
+    ///
+    /// ## Failure modes:
+    /// - If the order does not exist.
+    /// - If the order status is not `New` or `Unclaimed`.
+    async fn requote_order(
+        &self,
+        order_id: &OrderId,
+        new_total_price: MicroTari,
+        rate_id: i64,
+        quote_expires_at: DateTime<Utc>,
+    ) -> Result<OrderChanged, PaymentGatewayError>;
+
     /// Since only XTR is supported currently, this method will always return an error.
     async fn modify_currency_for_order(
         &self,
@@ -235,6 +299,81 @@ pub trait PaymentGatewayDatabase: Clone + AccountManagement {
         unpaid_limit: Duration,
     ) -> Result<ExpiryResult, PaymentGatewayError>;
 
+    /// Records a request to return Tari to a customer. See [`NewRefund`] for the difference between refunds against
+    /// an order and refunds of an unspent balance.
+    ///
+    /// The refund is checked against the funds available to it, but it does not affect the address balance until it
+    /// is approved.
+    ///
+    /// ## Failure modes:
+    /// - If the amount is zero or negative.
+    /// - If the order does not exist, or has not been paid.
+    /// - If the amount exceeds what the address paid towards the order, less the approved refunds against it.
+    /// - If the refund is not against an order, and the amount exceeds the current balance of the address.
+    async fn request_refund(&self, refund: NewRefund) -> Result<Refund, PaymentGatewayError>;
+
+    /// Approves a `Requested` refund, and debits it from the address balance. The checks made in
+    /// [`Self::request_refund`] are repeated, since the funds may have been spent in the meantime.
+    ///
+    /// A `RefundApproved` event is written to the outbox, so that the payout wallet can send the refund.
+    async fn approve_refund(&self, id: i64) -> Result<Refund, PaymentGatewayError>;
+
+    /// Turns down a `Requested` refund. The reason is appended to the refund's reason.
+    async fn reject_refund(&self, id: i64, reason: &str) -> Result<Refund, PaymentGatewayError>;
+
+    /// Marks an `Approved` refund as `Sent`, and records the transaction id of the payout.
+    /// A `RefundSent` event is written to the outbox.
+    async fn mark_refund_sent(&self, id: i64, payout_txid: &str) -> Result<Refund, PaymentGatewayError>;
+
+    /// Holds a new order instead of inserting it, because it could not be priced safely. `reason` explains why. The
+    /// order's `total_price` is ignored, since the order will be priced when it is released.
+    ///
+    /// An `OrderHeld` event is written to the outbox, so that admins can be alerted.
+    ///
+    /// ## Failure modes:
+    /// - If the order already exists, or is already being held.
+    async fn hold_order(&self, order: &NewOrder, reason: &str) -> Result<HeldOrder, PaymentGatewayError>;
+
+    /// Marks a held order as released. This does not insert the order; that is up to the caller (see
+    /// [`crate::OrderFlowApi::release_held_order`]).
+    ///
+    /// ## Failure modes:
+    /// - If the order is not being held.
+    async fn mark_held_order_released(&self, order_id: &OrderId) -> Result<HeldOrder, PaymentGatewayError>;
+
+    /// Records a request for a second admin to approve a high-risk operation. The operation is not carried out.
+    ///
+    /// An `ApprovalRequested` event is written to the outbox, so that other admins can be alerted.
+    async fn request_approval(&self, request: NewApprovalRequest) -> Result<ApprovalRequest, PaymentGatewayError>;
+
+    /// Approves a `Pending` request on behalf of `approver`. This only records the decision. Carrying out the
+    /// operation is up to the caller (see [`crate::OrderFlowApi::approve_operation`]).
+    ///
+    /// An `OperationApproved` event is written to the outbox.
+    ///
+    /// ## Failure modes:
+    /// - If the request does not exist, is not `Pending`, or has expired.
+    /// - If `approver` is the address that asked for the operation.
+    async fn approve_request(&self, id: i64, approver: &TariAddress) -> Result<ApprovalRequest, PaymentGatewayError>;
+
+    /// Turns down a `Pending` request on behalf of `approver`. The failure modes are the same as for
+    /// [`Self::approve_request`].
+    ///
+    /// An `OperationRejected` event is written to the outbox.
+    async fn reject_request(
+        &self,
+        id: i64,
+        approver: &TariAddress,
+        reason: &str,
+    ) -> Result<ApprovalRequest, PaymentGatewayError>;
+
+    /// Marks an `Approved` request as `Failed`, because the operation could not be carried out. `error` is recorded as
+    /// the request's decision reason.
+    async fn mark_approval_failed(&self, id: i64, error: &str) -> Result<ApprovalRequest, PaymentGatewayError>;
+
+    /// Marks the `Pending` requests whose expiry time has passed as `Expired`, and returns them.
+    async fn expire_approval_requests(&self) -> Result<Vec<ApprovalRequest>, PaymentGatewayError>;
+
     /// Closes the database connection.
     async fn close(&mut self) -> Result<(), PaymentGatewayError> {
         Ok(())
@@ -275,6 +414,20 @@ pub enum PaymentGatewayError {
     InvalidSignature,
     #[error("The requested payment does not exist for txid {0}")]
     PaymentNotFound(String),
+    #[error("The requested refund {0} does not exist")]
+    RefundNotFound(i64),
+    #[error("Invalid refund. {0}")]
+    InvalidRefund(String),
+    #[error("The price quote for order {0} has expired, so it must be re-priced before it can be paid")]
+    QuoteExpired(OrderId),
+    #[error("Order {0} is not being held")]
+    OrderNotHeld(OrderId),
+    #[error("The ledger transaction for {0} does not balance")]
+    UnbalancedLedgerTransaction(String),
+    #[error("The requested approval request {0} does not exist")]
+    ApprovalRequestNotFound(i64),
+    #[error("Invalid approval. {0}")]
+    InvalidApproval(String),
 }
 
 impl From<sqlx::Error> for PaymentGatewayError {
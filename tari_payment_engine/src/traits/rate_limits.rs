@@ -0,0 +1,29 @@
+use thiserror::Error;
+
+use crate::tpe_api::rate_limit_objects::{RateLimit, RateLimitDecision};
+
+#[derive(Debug, Clone, Error)]
+pub enum RateLimitError {
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+}
+
+impl From<sqlx::Error> for RateLimitError {
+    fn from(e: sqlx::Error) -> Self {
+        RateLimitError::DatabaseError(e.to_string())
+    }
+}
+
+/// Keeps the token buckets for rate limits (see [`crate::tpe_api::rate_limit_objects`]).
+///
+/// A server that keeps its buckets in its own memory only limits the requests that it sees itself. Backends that
+/// several server instances connect to can keep the buckets instead, so that the instances share their limits.
+#[allow(async_fn_in_trait)]
+pub trait RateLimitStore {
+    /// Takes a token from the bucket for `key`, which starts out full. Taking a token and refilling the bucket must be
+    /// atomic, so that concurrent requests cannot take the same token.
+    async fn take_token(&self, key: &str, limit: &RateLimit) -> Result<RateLimitDecision, RateLimitError>;
+    /// Forgets the buckets that have had time to refill since they were last used. Returns the number of buckets that
+    /// were removed.
+    async fn prune_rate_limits(&self) -> Result<u64, RateLimitError>;
+}
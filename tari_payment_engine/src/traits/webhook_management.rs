@@ -0,0 +1,87 @@
+use chrono::{DateTime, Duration, Utc};
+use thiserror::Error;
+
+use crate::{
+    db_types::{NewWebhookSubscription, OutboxEvent, WebhookDelivery, WebhookSubscription},
+    tpe_api::account_objects::Pagination,
+};
+
+#[derive(Debug, Clone, Error)]
+pub enum WebhookError {
+    #[error("Database error: {0}")]
+    DatabaseError(String),
+    #[error("Webhook subscription {0} does not exist")]
+    SubscriptionNotFound(i64),
+    #[error("Webhook delivery {0} does not exist")]
+    DeliveryNotFound(i64),
+    #[error("Invalid webhook subscription. {0}")]
+    InvalidSubscription(String),
+    #[error("Could not serialize the webhook payload. {0}")]
+    SerializationError(String),
+}
+
+impl From<sqlx::Error> for WebhookError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::Database(ref db_err) if db_err.is_unique_violation() => {
+                WebhookError::InvalidSubscription("The URL is already subscribed to this event type".to_string())
+            },
+            _ => WebhookError::DatabaseError(e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for WebhookError {
+    fn from(e: serde_json::Error) -> Self {
+        WebhookError::SerializationError(e.to_string())
+    }
+}
+
+/// Outbound webhook subscriptions, and the queue of deliveries to them.
+///
+/// Deliveries are created from outbox events (see [`crate::traits::EventOutbox`]) with
+/// [`WebhookManagement::queue_webhook_deliveries`]. Each delivery is then retried independently, so that one
+/// misbehaving subscriber does not hold up the others.
+#[allow(async_fn_in_trait)]
+pub trait WebhookManagement {
+    async fn create_webhook(&self, webhook: &NewWebhookSubscription) -> Result<WebhookSubscription, WebhookError>;
+    async fn fetch_webhooks(&self) -> Result<Vec<WebhookSubscription>, WebhookError>;
+    async fn fetch_webhook(&self, id: i64) -> Result<Option<WebhookSubscription>, WebhookError>;
+    /// Enables or disables a subscription. Pending deliveries for a disabled subscription are held back until it is
+    /// enabled again.
+    async fn set_webhook_active(&self, id: i64, active: bool) -> Result<WebhookSubscription, WebhookError>;
+    /// Deletes the subscription, along with its delivery log.
+    async fn delete_webhook(&self, id: i64) -> Result<(), WebhookError>;
+    /// Creates a delivery of the event for every active subscription to its event type. This is idempotent: an event
+    /// that has already been queued for a subscription is not queued for it again. Only the newly created deliveries
+    /// are returned.
+    async fn queue_webhook_deliveries(&self, event: &OutboxEvent) -> Result<Vec<WebhookDelivery>, WebhookError>;
+    /// Fetches up to `limit` undelivered deliveries to active subscriptions that are due for an attempt, oldest first.
+    /// Deliveries that another worker has claimed are left out. This does not claim the deliveries.
+    async fn fetch_due_webhook_deliveries(&self, limit: i64) -> Result<Vec<WebhookDelivery>, WebhookError>;
+    /// Atomically claims up to `limit` due deliveries, oldest first. The claimed deliveries are leased to the caller
+    /// for `lease`, during which no other call will claim them. Recording the outcome releases the lease.
+    async fn claim_due_webhook_deliveries(
+        &self,
+        limit: i64,
+        lease: Duration,
+    ) -> Result<Vec<WebhookDelivery>, WebhookError>;
+    /// The delivery log, newest first, optionally restricted to a single subscription.
+    async fn fetch_webhook_deliveries(
+        &self,
+        subscription_id: Option<i64>,
+        pagination: &Pagination,
+    ) -> Result<Vec<WebhookDelivery>, WebhookError>;
+    async fn mark_webhook_delivered(&self, id: i64, status: i64) -> Result<WebhookDelivery, WebhookError>;
+    /// Records a failed delivery attempt. If `retry_at` is `None`, the delivery is parked and will not be retried
+    /// until it is replayed.
+    async fn record_failed_webhook_delivery(
+        &self,
+        id: i64,
+        status: Option<i64>,
+        error: &str,
+        retry_at: Option<DateTime<Utc>>,
+    ) -> Result<WebhookDelivery, WebhookError>;
+    /// Schedules the delivery for an immediate attempt, whether or not it has been delivered before.
+    async fn replay_webhook_delivery(&self, id: i64) -> Result<WebhookDelivery, WebhookError>;
+}
@@ -0,0 +1,120 @@
+//! Runs the shared conformance suite in `test_utils::conformance` against every backend.
+//!
+//! The Postgres checks are only compiled with the `postgres` feature, and need `TPG_TEST_POSTGRES_URL` to be set
+//! (see `postgres_backend.rs`).
+//!
+//! Run with `cargo test -p tari_payment_engine --features test_utils --test backend_conformance`
+
+/// Generates one test per conformance check. `$new_db` must resolve to a fresh, empty database along with whatever
+/// `$teardown` needs to clean it up once the check has passed.
+macro_rules! conformance_suite {
+    ($new_db:expr, $teardown:expr; $($check:ident),+ $(,)?) => {
+        $(
+            #[tokio::test]
+            async fn $check() {
+                let (db, context) = $new_db.await;
+                tari_payment_engine::test_utils::conformance::$check(&db).await;
+                $teardown(db, context).await;
+            }
+        )+
+    };
+}
+
+macro_rules! all_checks {
+    ($new_db:expr, $teardown:expr) => {
+        conformance_suite!($new_db, $teardown;
+            orders_are_inserted_idempotently,
+            orders_can_be_found_by_alt_id,
+            confirmed_payment_pays_for_order,
+            order_is_settled_from_multiple_addresses,
+            insufficient_funds_do_not_settle,
+            payment_status_transitions,
+            reorged_payments_are_reverted,
+            notified_confirmations_follow_policy,
+            payments_are_reconciled_with_wallet_history,
+            credit_notes_pay_for_orders,
+            orders_can_be_marked_as_paid,
+            orders_can_be_cancelled_and_reset,
+            reassigned_orders_are_paid_by_new_customer,
+            order_price_and_memo_updates,
+            old_orders_expire,
+            account_queries,
+            auth_nonces_and_roles,
+            wallet_management,
+            exchange_rates,
+            exchange_rate_history,
+            expired_quotes_are_repriced,
+            held_orders_are_released,
+            merchants_scope_orders_and_roles,
+            merchants_scope_payments_and_rates,
+            roles_can_be_defined_and_deleted,
+            sessions_can_be_refreshed_and_revoked,
+            admin_actions_are_logged,
+            rate_limit_buckets_refill,
+            event_outbox_tracks_deliveries,
+            event_outbox_claims_are_exclusive,
+            webhook_deliveries_are_tracked,
+            refunds_debit_balances,
+            operations_need_a_second_approver,
+            unspent_balances_follow_overpayment_policy,
+            ledger_postings_balance,
+            accounting_exports_cover_a_period,
+        );
+    };
+}
+
+mod memory {
+    use tari_payment_engine::InMemoryDatabase;
+
+    async fn new_db() -> (InMemoryDatabase, ()) {
+        (InMemoryDatabase::new(), ())
+    }
+
+    async fn teardown(_db: InMemoryDatabase, _context: ()) {}
+
+    all_checks!(new_db(), teardown);
+}
+
+mod sqlite {
+    use tari_payment_engine::{
+        test_utils::prepare_env::{prepare_test_env, random_db_path},
+        traits::PaymentGatewayDatabase,
+        SqliteDatabase,
+    };
+
+    async fn new_db() -> (SqliteDatabase, ()) {
+        let url = random_db_path();
+        prepare_test_env(&url).await;
+        let db = SqliteDatabase::new_with_url(&url, 5).await.expect("Error creating database");
+        (db, ())
+    }
+
+    async fn teardown(mut db: SqliteDatabase, _context: ()) {
+        db.close().await.expect("Error closing database");
+    }
+
+    all_checks!(new_db(), teardown);
+}
+
+#[cfg(feature = "postgres")]
+mod postgres {
+    use tari_payment_engine::{
+        test_utils::prepare_pg_env::{drop_database, prepare_pg_test_env, random_db_url},
+        traits::PaymentGatewayDatabase,
+        PostgresDatabase,
+    };
+
+    async fn new_db() -> (PostgresDatabase, String) {
+        let url = random_db_url();
+        prepare_pg_test_env(&url).await;
+        let db = PostgresDatabase::new_with_url(&url, 5).await.expect("Error creating database");
+        (db, url)
+    }
+
+    async fn teardown(mut db: PostgresDatabase, url: String) {
+        db.close().await.expect("Error closing database");
+        drop_database(&url).await;
+    }
+
+    all_checks!(new_db(), teardown);
+}
@@ -0,0 +1,7 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Use the vendored protoc, so that building the server doesn't need protobuf to be installed
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    println!("cargo:rerun-if-changed=proto/wallet.proto");
+    tonic_build::configure().compile_protos(&["proto/wallet.proto"], &["proto"])?;
+    Ok(())
+}
@@ -1,9 +1,15 @@
-use std::time::Duration;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, PoisonError, RwLock},
+};
 
 use actix_jwt_auth_middleware::{Authority, FromRequest, TokenSigner};
 use actix_web::{error::Error as ActixWebError, Handler};
+use chrono::{Duration, Utc};
 use log::*;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tari_common_types::tari_address::TariAddress;
 use tari_jwt::{
     jwt_compact::{AlgorithmExt, Claims, Header, UntrustedToken},
@@ -11,14 +17,54 @@ use tari_jwt::{
     Ristretto256SigningKey,
     Ristretto256VerifyingKey,
 };
-use tari_payment_engine::db_types::{LoginToken, Roles};
+use tari_payment_engine::{
+    db_types::{AuthSession, LoginToken, NewAuthSession, Permission, Role, RoleDefinition, Roles},
+    traits::{AuthApiError, AuthManagement},
+    AuthApi,
+};
+
+use crate::{
+    config::{AuthConfig, SessionConfig},
+    errors::AuthError,
+};
 
-use crate::{config::AuthConfig, errors::AuthError};
+/// The header that carries the login token on `/auth` requests
+pub const LOGIN_TOKEN_HEADER: &str = "tpg_auth_token";
+/// The header that carries the access token on authenticated requests
+pub const ACCESS_TOKEN_HEADER: &str = "tpg_access_token";
+/// The header that carries the refresh token, both in the responses of the auth endpoints and in refresh requests
+pub const REFRESH_TOKEN_HEADER: &str = "tpg_refresh_token";
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRequest)]
 pub struct JwtClaims {
     pub address: TariAddress,
     pub roles: Roles,
+    /// Tokens issued for a single merchant only grant their roles for that merchant's orders. Tokens without a
+    /// merchant apply to the whole instance.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merchant: Option<String>,
+    /// The login session the token was issued for. Tokens whose session has been revoked are refused, and so are
+    /// tokens without a session, since they cannot be revoked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<i64>,
+}
+
+impl From<&AuthSession> for JwtClaims {
+    fn from(session: &AuthSession) -> Self {
+        Self {
+            address: session.address.as_address().clone(),
+            roles: session.roles.clone(),
+            merchant: session.merchant.clone(),
+            session: Some(session.id),
+        }
+    }
+}
+
+impl JwtClaims {
+    /// True if these claims may see and modify orders belonging to `merchant_id`
+    pub fn can_access_merchant(&self, merchant_id: &str) -> bool {
+        self.merchant.as_deref().map_or(true, |m| m == merchant_id)
+    }
 }
 
 pub type TpsAuthority = Authority<JwtClaims, Ristretto256, impl Handler<(), Output = Result<(), ActixWebError>>, ()>;
@@ -39,8 +85,8 @@ pub fn build_tps_authority(auth_config: AuthConfig) -> TpsAuthority {
         .refresh_authorizer(|| async { Ok(()) })
         .enable_header_tokens(true)
         .renew_access_token_automatically(false)
-        .access_token_name("tpg_access_token")
-        .refresh_token_name("tpg_refresh_token")
+        .access_token_name(ACCESS_TOKEN_HEADER)
+        .refresh_token_name(REFRESH_TOKEN_HEADER)
         .algorithm(Ristretto256)
         .verifying_key(jwt_verification_key)
         .token_signer(Some(token_signer))
@@ -64,26 +110,172 @@ pub fn check_login_token_signature<S: AsRef<str>>(token: S) -> Result<LoginToken
     Ok(claims.custom)
 }
 
+/// The address that a login token claims to come from, without checking the signature. Only use this where a forged
+/// address does no harm, such as to pick a rate limit bucket.
+pub fn unverified_login_address<S: AsRef<str>>(token: S) -> Option<TariAddress> {
+    let untrusted_token = UntrustedToken::new(token.as_ref()).ok()?;
+    let claims: Claims<LoginToken> = untrusted_token.deserialize_claims_unchecked().ok()?;
+    Some(claims.custom.address)
+}
+
 pub struct TokenIssuer {
     signer: TokenSigner<JwtClaims, Ristretto256>,
+    sessions: SessionConfig,
 }
 
 impl TokenIssuer {
     pub fn new(config: &AuthConfig) -> Self {
         let signer = build_jwt_signer(config.jwt_signing_key.clone());
-        Self { signer }
+        Self { signer, sessions: SessionConfig::default() }
+    }
+
+    pub fn with_session_config(mut self, sessions: SessionConfig) -> Self {
+        self.sessions = sessions;
+        self
     }
 
-    /// Issue a new access token for the given login token
+    /// Prepares a new session for the given login token. The refresh token is returned alongside the session, and must
+    /// be handed to the user. It is not kept anywhere else, since only its hash is stored.
     /// This method DOES NOT verify that the `login_token` contains legitimate information.
-    /// This must be done prior to calling `issue_token`.
-    pub fn issue_token(&self, login_token: LoginToken, duration: Option<Duration>) -> Result<String, AuthError> {
-        let claim = JwtClaims { address: login_token.address, roles: login_token.desired_roles };
-        let duration = duration.unwrap_or_else(|| Duration::from_secs(60 * 60 * 24));
+    /// This must be done prior to calling `new_session`.
+    pub fn new_session(&self, login_token: &LoginToken) -> (String, NewAuthSession) {
+        let refresh_token = generate_refresh_token();
+        let expires_at = Utc::now() + self.sessions.session_lifetime;
+        let session = NewAuthSession::new(login_token, hash_refresh_token(&refresh_token), expires_at);
+        (refresh_token, session)
+    }
+
+    /// Issue a new access token with the given claims. The claims should come from the session that the token is
+    /// issued for (see [`JwtClaims::from`]).
+    pub fn issue_token(&self, claims: &JwtClaims) -> Result<String, AuthError> {
+        let duration = self
+            .sessions
+            .access_token_lifetime
+            .to_std()
+            .map_err(|e| AuthError::ValidationError(format!("Invalid access token lifetime. {e}")))?;
         let token = self
             .signer
-            .create_signed_token(&claim, duration)
+            .create_signed_token(claims, duration)
             .map_err(|e| AuthError::ValidationError(format!("{e:?}")))?;
         Ok(token)
     }
 }
+
+/// Refresh tokens are 32 random bytes, base64url-encoded
+pub fn generate_refresh_token() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+/// The hash of a refresh token, which is what the database stores
+pub fn hash_refresh_token(refresh_token: &str) -> String {
+    base64::encode(Sha256::digest(refresh_token.as_bytes()))
+}
+
+/// The sessions whose access tokens must be refused, even though the tokens have not expired yet. The ACL middleware
+/// checks every request against this list.
+///
+/// The list is kept in memory. It is loaded from the database when the server starts, updated by the endpoints that
+/// revoke sessions, and reloaded periodically so that sessions revoked by other server instances are refused too (see
+/// [`crate::session_sync_worker`]). Sessions that were revoked longer ago than the access token lifetime are left
+/// out, since their access tokens have expired anyway.
+#[derive(Debug, Clone, Default)]
+pub struct RevocationList {
+    sessions: Arc<RwLock<HashSet<i64>>>,
+}
+
+impl RevocationList {
+    pub fn new<I: IntoIterator<Item = i64>>(sessions: I) -> Self {
+        Self { sessions: Arc::new(RwLock::new(sessions.into_iter().collect())) }
+    }
+
+    /// Loads the sessions that were revoked within the last `access_token_lifetime`
+    pub async fn load<A: AuthManagement>(
+        api: &AuthApi<A>,
+        access_token_lifetime: Duration,
+    ) -> Result<Self, AuthApiError> {
+        let sessions = api.fetch_revoked_sessions(Utc::now() - access_token_lifetime).await?;
+        info!("🔐️ {} revoked sessions may still have live access tokens", sessions.len());
+        Ok(Self::new(sessions))
+    }
+
+    /// Adds the sessions that were revoked within the last `access_token_lifetime` and are not in the list yet.
+    /// Returns the number of sessions that were added.
+    pub async fn sync<A: AuthManagement>(
+        &self,
+        api: &AuthApi<A>,
+        access_token_lifetime: Duration,
+    ) -> Result<usize, AuthApiError> {
+        let sessions = api.fetch_revoked_sessions(Utc::now() - access_token_lifetime).await?;
+        let mut list = self.sessions.write().unwrap_or_else(PoisonError::into_inner);
+        let count = list.len();
+        list.extend(sessions);
+        Ok(list.len() - count)
+    }
+
+    pub fn revoke(&self, sessions: &[i64]) {
+        self.sessions.write().unwrap_or_else(PoisonError::into_inner).extend(sessions);
+    }
+
+    pub fn is_revoked(&self, session: i64) -> bool {
+        self.sessions.read().unwrap_or_else(PoisonError::into_inner).contains(&session)
+    }
+}
+
+/// The permissions that each role grants. The ACL middleware uses this to decide whether the roles in an access token
+/// grant the permissions that a route requires.
+///
+/// Like the [`RevocationList`], the table is kept in memory. It is loaded from the database when the server starts,
+/// updated by the endpoints that edit roles, and reloaded periodically to pick up the changes made on other server
+/// instances. Changes apply to access tokens that have already been issued.
+#[derive(Debug, Clone)]
+pub struct RolePermissions {
+    roles: Arc<RwLock<HashMap<Role, HashSet<Permission>>>>,
+}
+
+impl Default for RolePermissions {
+    /// The built-in roles, with their default permissions
+    fn default() -> Self {
+        Self::new(RoleDefinition::built_in())
+    }
+}
+
+impl RolePermissions {
+    pub fn new<I: IntoIterator<Item = RoleDefinition>>(roles: I) -> Self {
+        let roles = roles.into_iter().map(|d| (d.role, d.permissions.into_iter().collect())).collect();
+        Self { roles: Arc::new(RwLock::new(roles)) }
+    }
+
+    pub async fn load<A: AuthManagement>(api: &AuthApi<A>) -> Result<Self, AuthApiError> {
+        let roles = api.fetch_role_definitions().await?;
+        info!("🔐️ Loaded the permissions for {} roles", roles.len());
+        Ok(Self::new(roles))
+    }
+
+    /// Replaces the table with the role definitions in the database. Returns the number of roles.
+    pub async fn sync<A: AuthManagement>(&self, api: &AuthApi<A>) -> Result<usize, AuthApiError> {
+        let roles = api.fetch_role_definitions().await?;
+        let table = roles.into_iter().map(|d| (d.role, d.permissions.into_iter().collect())).collect::<HashMap<_, _>>();
+        let count = table.len();
+        *self.roles.write().unwrap_or_else(PoisonError::into_inner) = table;
+        Ok(count)
+    }
+
+    pub fn update(&self, role: RoleDefinition) {
+        let permissions = role.permissions.into_iter().collect();
+        self.roles.write().unwrap_or_else(PoisonError::into_inner).insert(role.role, permissions);
+    }
+
+    pub fn remove(&self, role: &Role) {
+        self.roles.write().unwrap_or_else(PoisonError::into_inner).remove(role);
+    }
+
+    /// Whether the roles, between them, grant every one of the permissions. The `super_admin` role grants everything.
+    pub fn allows(&self, roles: &[Role], permissions: &[Permission]) -> bool {
+        if roles.contains(&Role::SuperAdmin) {
+            return true;
+        }
+        let table = self.roles.read().unwrap_or_else(PoisonError::into_inner);
+        permissions.iter().all(|p| roles.iter().any(|r| table.get(r).is_some_and(|granted| granted.contains(p))))
+    }
+}
@@ -1,4 +1,9 @@
-use std::{env, io::Write, net::IpAddr};
+use std::{
+    collections::HashMap,
+    env,
+    io::Write,
+    net::{IpAddr, SocketAddr},
+};
 
 use actix_jwt_auth_middleware::FromRequest;
 use chrono::Duration;
@@ -15,15 +20,36 @@ use tari_jwt::{
     Ristretto256SigningKey,
     Ristretto256VerifyingKey,
 };
+use tari_payment_engine::{
+    db_types::DEFAULT_MERCHANT,
+    events::OutboxConfig,
+    tpe_api::{
+        approval_objects::ApprovalPolicy,
+        exchange_objects::{QuoteExpiryAction, QuotePolicy, RateCircuitBreaker},
+        payment_objects::{ConfirmationPolicy, OverpaymentPolicy},
+        rate_limit_objects::RateLimit,
+    },
+};
 use tempfile::NamedTempFile;
-use tpg_common::Secret;
+use tpg_common::{MicroTari, Secret};
 
-use crate::errors::ServerError;
+use crate::{errors::ServerError, integrations::rate_sources::HttpRateSource};
 
 const DEFAULT_TPG_HOST: &str = "127.0.0.1";
 const DEFAULT_TPG_PORT: u16 = 8360;
 const DEFAULT_UNCLAIMED_ORDER_TIMEOUT: Duration = Duration::hours(2);
 const DEFAULT_UNPAID_ORDER_TIMEOUT: Duration = Duration::hours(48);
+const DEFAULT_OVERPAYMENT_IDLE_DAYS: i64 = 30;
+const DEFAULT_QUOTE_LIFETIME: Duration = Duration::minutes(60);
+const DEFAULT_RATE_FEED_INTERVAL: std::time::Duration = std::time::Duration::from_secs(300);
+const DEFAULT_RATE_FEED_MAX_DEVIATION: i64 = 10;
+const DEFAULT_WOOCOMMERCE_MERCHANT: &str = "woocommerce";
+const DEFAULT_WALLET_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+const DEFAULT_WALLET_CONFIRMATIONS: u64 = 3;
+const DEFAULT_ACCESS_TOKEN_LIFETIME: Duration = Duration::hours(24);
+const DEFAULT_SESSION_LIFETIME: Duration = Duration::days(30);
+const DEFAULT_SESSION_SYNC_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+const DEFAULT_APPROVAL_EXPIRY_HOURS: i64 = 24;
 
 #[derive(Clone, Debug)]
 #[allow(clippy::struct_excessive_bools)]
@@ -32,6 +58,8 @@ pub struct ServerConfig {
     pub port: u16,
     pub database_url: String,
     pub auth: AuthConfig,
+    /// How long access tokens and login sessions last
+    pub sessions: SessionConfig,
     /// If true, the X-Forwarded-For header will be used to determine the client's IP address, rather than the
     /// connection's remote address.
     pub use_x_forwarded_for: bool,
@@ -50,8 +78,35 @@ pub struct ServerConfig {
     pub unclaimed_order_timeout: Duration,
     /// The time before an unpaid order is considered expired and marked as such.
     pub unpaid_order_timeout: Duration,
-    /// Shopify storefront configuration
+    /// Shopify storefront configuration for the default merchant
     pub shopify_config: ShopifyConfig,
+    /// Additional merchants, each with their own Shopify storefront. Orders from these stores are tagged with the
+    /// merchant's id.
+    pub merchants: Vec<MerchantConfig>,
+    /// WooCommerce storefront configuration. If `None`, the WooCommerce endpoints are disabled.
+    pub woocommerce: Option<WooCommerceConfig>,
+    /// Retry policy for delivering events from the event outbox
+    pub outbox: OutboxConfig,
+    /// What to do with surplus funds left in an address balance after its orders have been paid
+    pub overpayment_policy: OverpaymentPolicy,
+    /// Which admin operations need a second admin's approval before they are carried out
+    pub approval_policy: ApprovalPolicy,
+    /// Rate limits on the public and wallet endpoints
+    pub rate_limits: RateLimitConfig,
+    /// The address to serve the Prometheus metrics on. The metrics are kept off the main address, since they do not
+    /// need an access token. If `None`, the metrics are not served.
+    pub metrics_address: Option<SocketAddr>,
+    /// How long orders priced in another currency can be paid at the quoted exchange rate, and what happens to them
+    /// after that. If `None`, orders keep the price they were created with.
+    pub rate_quotes: Option<QuotePolicy>,
+    /// The exchange rate sources to poll. If `None`, exchange rates are only set by hand. The feed only sets the
+    /// default merchant's rates.
+    pub rate_feed: Option<RateFeedConfig>,
+    /// New orders in other currencies are held instead of priced if the exchange rate breaches these limits
+    pub rate_circuit_breaker: RateCircuitBreaker,
+    /// The hot wallet's gRPC interface. If set, the server watches the wallet for payments itself. If `None`, the
+    /// wallet must post payments to the `/wallet` endpoints.
+    pub wallet_grpc: Option<WalletGrpcConfig>,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -71,6 +126,26 @@ pub struct ShopifyConfig {
     pub order_id_field: OrderIdField,
 }
 
+/// A merchant that runs its own storefront on this server, alongside the default merchant.
+#[derive(Clone, Debug)]
+pub struct MerchantConfig {
+    pub id: String,
+    pub shopify_config: ShopifyConfig,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct WooCommerceConfig {
+    /// The url of the WordPress site that runs the store. e.g. "https://shop.example.com"
+    pub url: String,
+    pub consumer_key: String,
+    pub consumer_secret: Secret<String>,
+    /// The secret that WooCommerce signs webhooks with
+    pub webhook_secret: Secret<String>,
+    pub hmac_checks: bool,
+    /// The merchant that orders from this store belong to
+    pub merchant_id: String,
+}
+
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
@@ -78,6 +153,7 @@ impl Default for ServerConfig {
             port: DEFAULT_TPG_PORT,
             database_url: String::default(),
             auth: AuthConfig::default(),
+            sessions: SessionConfig::default(),
             use_x_forwarded_for: false,
             use_forwarded: false,
             strict_mode: true,
@@ -86,6 +162,89 @@ impl Default for ServerConfig {
             unclaimed_order_timeout: DEFAULT_UNCLAIMED_ORDER_TIMEOUT,
             unpaid_order_timeout: DEFAULT_UNPAID_ORDER_TIMEOUT,
             shopify_config: ShopifyConfig::default(),
+            merchants: Vec::new(),
+            woocommerce: None,
+            outbox: OutboxConfig::default(),
+            overpayment_policy: OverpaymentPolicy::default(),
+            approval_policy: ApprovalPolicy::default(),
+            rate_limits: RateLimitConfig::default(),
+            metrics_address: None,
+            rate_quotes: Some(QuotePolicy::new(DEFAULT_QUOTE_LIFETIME, QuoteExpiryAction::default())),
+            rate_feed: None,
+            rate_circuit_breaker: RateCircuitBreaker::default(),
+            wallet_grpc: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RateFeedConfig {
+    pub sources: Vec<HttpRateSource>,
+    /// The currencies to fetch rates for
+    pub currencies: Vec<String>,
+    pub poll_interval: std::time::Duration,
+    /// Rates that differ from the median of all the sources by more than this percentage are ignored
+    pub max_deviation_pct: i64,
+    /// The minimum number of sources that must agree before the rate is updated
+    pub min_sources: usize,
+}
+
+impl Default for RateFeedConfig {
+    fn default() -> Self {
+        Self {
+            sources: Vec::new(),
+            currencies: vec!["USD".to_string()],
+            poll_interval: DEFAULT_RATE_FEED_INTERVAL,
+            max_deviation_pct: DEFAULT_RATE_FEED_MAX_DEVIATION,
+            min_sources: 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WalletGrpcConfig {
+    /// The address of the console wallet's gRPC server, e.g. "http://127.0.0.1:18143"
+    pub address: String,
+    /// The credentials for the gRPC server, if it has basic authentication enabled
+    pub username: Option<String>,
+    pub password: Secret<String>,
+    /// How many blocks deep a payment must be before it is confirmed, depending on its amount
+    pub confirmations: ConfirmationPolicy,
+    pub poll_interval: std::time::Duration,
+    /// The merchant that owns the hot wallet. The payments that it receives can only pay for this merchant's orders.
+    pub merchant_id: String,
+}
+
+impl Default for WalletGrpcConfig {
+    fn default() -> Self {
+        Self {
+            address: String::default(),
+            username: None,
+            password: Secret::default(),
+            confirmations: ConfirmationPolicy::new(DEFAULT_WALLET_CONFIRMATIONS),
+            poll_interval: DEFAULT_WALLET_POLL_INTERVAL,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
+        }
+    }
+}
+
+/// The database backends that the server knows how to run against. The backend is selected from the scheme of
+/// `TPG_DATABASE_URL`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DatabaseBackend {
+    Sqlite,
+    Postgres,
+}
+
+impl DatabaseBackend {
+    /// Determines the database backend from the scheme of the given database URL. Returns `None` if the scheme is not
+    /// recognised.
+    pub fn from_url(url: &str) -> Option<Self> {
+        let scheme = url.split_once(':').map(|(scheme, _)| scheme.to_ascii_lowercase())?;
+        match scheme.as_str() {
+            "sqlite" => Some(Self::Sqlite),
+            "postgres" | "postgresql" => Some(Self::Postgres),
+            _ => None,
         }
     }
 }
@@ -107,6 +266,24 @@ impl ServerConfig {
         Self { host: host.to_string(), port, ..Default::default() }
     }
 
+    /// The database backend implied by the scheme of `database_url`.
+    pub fn database_backend(&self) -> Result<DatabaseBackend, ServerError> {
+        DatabaseBackend::from_url(&self.database_url).ok_or_else(|| {
+            ServerError::InitializeError(format!(
+                "Unsupported database URL scheme in TPG_DATABASE_URL: {}. Use sqlite:// or postgres://",
+                self.database_url.split_once(':').map(|(s, _)| s).unwrap_or_default()
+            ))
+        })
+    }
+
+    /// The HMAC secrets for the additional merchants' stores, keyed by shop domain.
+    pub fn shopify_hmac_secrets(&self) -> HashMap<String, Secret<String>> {
+        self.merchants
+            .iter()
+            .map(|m| (m.shopify_config.shop.to_lowercase(), m.shopify_config.hmac_secret.clone()))
+            .collect()
+    }
+
     pub fn from_env_or_default() -> Self {
         let host = env::var("TPG_HOST").ok().unwrap_or_else(|| DEFAULT_TPG_HOST.into());
         let port = env::var("TPG_PORT")
@@ -131,7 +308,10 @@ impl ServerConfig {
             );
             AuthConfig::default()
         });
+        let sessions = configure_sessions();
         let shopify_config = ShopifyConfig::from_env_or_defaults();
+        let merchants = configure_merchants(&shopify_config);
+        let woocommerce = configure_woocommerce();
         let use_x_forwarded_for =
             env::var("TPG_USE_X_FORWARDED_FOR").map(|s| &s == "1" || &s == "true").unwrap_or(false);
         let use_forwarded = env::var("TPG_USE_FORWARDED").map(|s| &s == "1" || &s == "true").unwrap_or(false);
@@ -141,11 +321,23 @@ impl ServerConfig {
         let disable_memo_signature_check =
             env::var("TPG_DISABLE_MEMO_SIGNATURE_CHECK").map(|s| &s == "1" || &s == "true").unwrap_or(false);
         let (unclaimed_order_timeout, unpaid_order_timeout) = configure_order_timeouts();
+        let outbox = configure_outbox();
+        let overpayment_policy = configure_overpayment_policy();
+        let approval_policy = configure_approval_policy();
+        let rate_limits = configure_rate_limits();
+        let metrics_address = configure_metrics_address();
+        let rate_quotes = configure_rate_quotes();
+        let rate_feed = configure_rate_feed();
+        let rate_circuit_breaker = configure_rate_circuit_breaker();
+        let wallet_grpc = configure_wallet_grpc();
         Self {
             host,
             port,
             shopify_config,
+            merchants,
+            woocommerce,
             auth,
+            sessions,
             database_url,
             use_forwarded,
             use_x_forwarded_for,
@@ -154,6 +346,15 @@ impl ServerConfig {
             disable_memo_signature_check,
             unclaimed_order_timeout,
             unpaid_order_timeout,
+            outbox,
+            overpayment_policy,
+            approval_policy,
+            rate_limits,
+            metrics_address,
+            rate_quotes,
+            rate_feed,
+            rate_circuit_breaker,
+            wallet_grpc,
         }
     }
 }
@@ -242,6 +443,78 @@ impl ShopifyConfig {
     }
 }
 
+/// Reads the additional merchants from `TPG_MERCHANTS`. Each merchant has its own shop, access tokens and secrets, and
+/// shares the remaining Shopify settings with the default merchant.
+fn configure_merchants(default: &ShopifyConfig) -> Vec<MerchantConfig> {
+    let Ok(ids) = env::var("TPG_MERCHANTS") else {
+        return Vec::new();
+    };
+    let merchants = ids
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .filter_map(|id| {
+            if id == DEFAULT_MERCHANT {
+                warn!("🪛️ Ignoring merchant '{id}' in TPG_MERCHANTS. It is reserved for the default storefront.");
+                return None;
+            }
+            let var = |field: &str| env::var(format!("TPG_MERCHANT_{}_{field}", id.to_uppercase())).ok();
+            let Some(shop) = var("SHOPIFY_SHOP") else {
+                warn!("🪛️ Ignoring merchant {id}. TPG_MERCHANT_{}_SHOPIFY_SHOP must be set.", id.to_uppercase());
+                return None;
+            };
+            // An empty HMAC secret would let anyone forge this shop's webhooks, so the merchant is left out instead
+            let mut missing = Vec::new();
+            let mut secret = |field: &'static str| {
+                let value = var(field).filter(|v| !v.trim().is_empty());
+                if value.is_none() {
+                    missing.push(format!("TPG_MERCHANT_{}_{field}", id.to_uppercase()));
+                }
+                Secret::new(value.unwrap_or_default())
+            };
+            let api_secret = secret("SHOPIFY_API_SECRET");
+            let hmac_secret = secret("SHOPIFY_HMAC_SECRET");
+            let admin_access_token = secret("SHOPIFY_ADMIN_ACCESS_TOKEN");
+            let storefront_access_token = secret("SHOPIFY_STOREFRONT_ACCESS_TOKEN");
+            if !missing.is_empty() {
+                error!("🪛️ Ignoring merchant {id}. These must be set and not empty: {}", missing.join(", "));
+                return None;
+            }
+            let shopify_config = ShopifyConfig {
+                shop,
+                api_secret,
+                hmac_secret,
+                admin_access_token,
+                storefront_access_token,
+                ..default.clone()
+            };
+            Some(MerchantConfig { id: id.to_string(), shopify_config })
+        })
+        .collect::<Vec<_>>();
+    if !merchants.is_empty() {
+        let ids = merchants.iter().map(|m| m.id.as_str()).collect::<Vec<_>>().join(", ");
+        info!("🪛️ Additional merchants: {ids}");
+    }
+    merchants
+}
+
+fn configure_woocommerce() -> Option<WooCommerceConfig> {
+    let url = env::var("TPG_WOOCOMMERCE_URL").ok()?;
+    let var = |name: &str| {
+        env::var(name).ok().unwrap_or_else(|| {
+            error!("🪛️ {name} is not set. WooCommerce orders cannot be updated or verified without it.");
+            String::default()
+        })
+    };
+    let consumer_key = var("TPG_WOOCOMMERCE_CONSUMER_KEY");
+    let consumer_secret = Secret::new(var("TPG_WOOCOMMERCE_CONSUMER_SECRET"));
+    let webhook_secret = Secret::new(var("TPG_WOOCOMMERCE_WEBHOOK_SECRET"));
+    let hmac_checks = env::var("TPG_WOOCOMMERCE_HMAC_CHECKS").map(|s| &s != "0" && &s != "false").unwrap_or(true);
+    let merchant_id = env::var("TPG_WOOCOMMERCE_MERCHANT_ID").unwrap_or_else(|_| DEFAULT_WOOCOMMERCE_MERCHANT.into());
+    info!("🪛️ WooCommerce store at {url} is configured for merchant {merchant_id}");
+    Some(WooCommerceConfig { url, consumer_key, consumer_secret, webhook_secret, hmac_checks, merchant_id })
+}
+
 fn configure_order_timeouts() -> (Duration, Duration) {
     let unclaimed_order_timeout = env::var("TPG_UNCLAIMED_ORDER_TIMEOUT")
         .map_err(|_| {
@@ -274,6 +547,339 @@ fn configure_order_timeouts() -> (Duration, Duration) {
     (unclaimed_order_timeout, unpaid_order_timeout)
 }
 
+fn configure_outbox() -> OutboxConfig {
+    let mut config = OutboxConfig::default();
+    if let Ok(s) = env::var("TPG_OUTBOX_MAX_ATTEMPTS") {
+        match s.parse::<i64>() {
+            Ok(n) if n > 0 => config.max_attempts = n,
+            _ => {
+                warn!("🪛️ Invalid configuration value for TPG_OUTBOX_MAX_ATTEMPTS: {s}. It must be a positive integer.")
+            },
+        }
+    }
+    if let Ok(s) = env::var("TPG_OUTBOX_POLL_INTERVAL") {
+        match s.parse::<u64>() {
+            Ok(n) if n > 0 => config.poll_interval = std::time::Duration::from_secs(n),
+            _ => warn!(
+                "🪛️ Invalid configuration value for TPG_OUTBOX_POLL_INTERVAL: {s}. It must be a positive integer."
+            ),
+        }
+    }
+    config
+}
+
+fn configure_sessions() -> SessionConfig {
+    let mut config = SessionConfig::default();
+    if let Ok(s) = env::var("TPG_ACCESS_TOKEN_LIFETIME") {
+        match s.parse::<i64>() {
+            Ok(n) if n > 0 => config.access_token_lifetime = Duration::minutes(n),
+            _ => warn!(
+                "🪛️ Invalid configuration value for TPG_ACCESS_TOKEN_LIFETIME: {s}. It must be a positive integer. \
+                 Using the default value of {} minutes.",
+                DEFAULT_ACCESS_TOKEN_LIFETIME.num_minutes()
+            ),
+        }
+    }
+    if let Ok(s) = env::var("TPG_SESSION_LIFETIME") {
+        match s.parse::<i64>() {
+            Ok(n) if n > 0 => config.session_lifetime = Duration::days(n),
+            _ => warn!(
+                "🪛️ Invalid configuration value for TPG_SESSION_LIFETIME: {s}. It must be a positive integer. Using \
+                 the default value of {} days.",
+                DEFAULT_SESSION_LIFETIME.num_days()
+            ),
+        }
+    }
+    if let Ok(s) = env::var("TPG_SESSION_SYNC_INTERVAL") {
+        match s.parse::<u64>() {
+            Ok(n) if n > 0 => config.sync_interval = std::time::Duration::from_secs(n),
+            _ => warn!(
+                "🪛️ Invalid configuration value for TPG_SESSION_SYNC_INTERVAL: {s}. It must be a positive integer. \
+                 Using the default value of {} seconds.",
+                DEFAULT_SESSION_SYNC_INTERVAL.as_secs()
+            ),
+        }
+    }
+    config
+}
+
+fn configure_overpayment_policy() -> OverpaymentPolicy {
+    let idle = match env::var("TPG_OVERPAYMENT_IDLE_DAYS") {
+        Ok(s) => match s.parse::<i64>() {
+            Ok(n) if n >= 0 => Duration::days(n),
+            _ => {
+                warn!(
+                    "🪛️ Invalid configuration value for TPG_OVERPAYMENT_IDLE_DAYS: {s}. It must be a non-negative \
+                     integer. Using the default value of {DEFAULT_OVERPAYMENT_IDLE_DAYS} days."
+                );
+                Duration::days(DEFAULT_OVERPAYMENT_IDLE_DAYS)
+            },
+        },
+        Err(_) => Duration::days(DEFAULT_OVERPAYMENT_IDLE_DAYS),
+    };
+    let policy = match env::var("TPG_OVERPAYMENT_POLICY").map(|s| s.to_lowercase()) {
+        Ok(s) if s == "refund" => OverpaymentPolicy::AutoRefund(idle),
+        Ok(s) if s == "review" => OverpaymentPolicy::Review(idle),
+        Ok(s) if s == "credit" => OverpaymentPolicy::StoreCredit,
+        Ok(s) => {
+            warn!(
+                "🪛️ Invalid configuration value for TPG_OVERPAYMENT_POLICY: {s}. Use 'credit', 'refund' or 'review'. \
+                 Overpayments will be kept as store credit."
+            );
+            OverpaymentPolicy::StoreCredit
+        },
+        Err(_) => OverpaymentPolicy::StoreCredit,
+    };
+    info!("🪛️ Overpayment policy: {policy}");
+    policy
+}
+
+fn configure_approval_policy() -> ApprovalPolicy {
+    let positive = |var: &str| match env::var(var) {
+        Ok(s) => match s.parse::<i64>() {
+            Ok(n) if n > 0 => Some(n),
+            Ok(0) => None,
+            _ => {
+                warn!("🪛️ Invalid configuration value for {var}: {s}. It must be a non-negative integer. Ignoring it.");
+                None
+            },
+        },
+        Err(_) => None,
+    };
+    let lifetime = Duration::hours(positive("TPG_APPROVAL_EXPIRY_HOURS").unwrap_or(DEFAULT_APPROVAL_EXPIRY_HOURS));
+    let policy = ApprovalPolicy {
+        credit_threshold: positive("TPG_APPROVAL_CREDIT_THRESHOLD").map(MicroTari::from_tari),
+        price_change_threshold: positive("TPG_APPROVAL_PRICE_CHANGE").map(i64::unsigned_abs),
+        fulfilment: env::var("TPG_APPROVAL_FULFILMENT").map(|s| &s == "1" || &s == "true").unwrap_or(false),
+        lifetime,
+    };
+    info!("🪛️ Approval policy: {policy}");
+    policy
+}
+
+fn configure_metrics_address() -> Option<SocketAddr> {
+    let s = env::var("TPG_METRICS_ADDRESS").ok()?;
+    match s.parse::<SocketAddr>() {
+        Ok(address) => {
+            info!("🪛️ Prometheus metrics will be served at {address}");
+            Some(address)
+        },
+        Err(e) => {
+            warn!(
+                "🪛️ Invalid configuration value for TPG_METRICS_ADDRESS: {s}. {e}. It must be an IP address and port, \
+                 such as 127.0.0.1:9464. The metrics will not be served."
+            );
+            None
+        },
+    }
+}
+
+fn configure_rate_limits() -> RateLimitConfig {
+    let limit = |var: &str| match env::var(var) {
+        Ok(s) => match s.parse::<RateLimit>() {
+            Ok(limit) => {
+                info!("🪛️ {var}: {limit}");
+                Some(limit)
+            },
+            Err(e) => {
+                warn!("🪛️ Invalid configuration value for {var}. {e}. These endpoints will not be rate limited.");
+                None
+            },
+        },
+        Err(_) => None,
+    };
+    let config = RateLimitConfig {
+        auth: limit("TPG_RATE_LIMIT_AUTH"),
+        claim: limit("TPG_RATE_LIMIT_CLAIM"),
+        wallet: limit("TPG_RATE_LIMIT_WALLET"),
+        webhooks: limit("TPG_RATE_LIMIT_WEBHOOKS"),
+        shared: env::var("TPG_RATE_LIMIT_SHARED").map(|s| &s == "1" || &s == "true").unwrap_or(false),
+    };
+    if config.shared {
+        info!("🪛️ Rate limits are shared with other server instances through the database");
+    }
+    config
+}
+
+fn configure_rate_quotes() -> Option<QuotePolicy> {
+    let lifetime = match env::var("TPG_QUOTE_LIFETIME") {
+        Ok(s) => match s.parse::<i64>() {
+            Ok(0) => {
+                info!("🪛️ Exchange rate quotes are disabled. Orders will keep the price they were created with.");
+                return None;
+            },
+            Ok(n) if n > 0 => Duration::minutes(n),
+            _ => {
+                warn!(
+                    "🪛️ Invalid configuration value for TPG_QUOTE_LIFETIME: {s}. It must be a non-negative integer. \
+                     Using the default value of {} minutes.",
+                    DEFAULT_QUOTE_LIFETIME.num_minutes()
+                );
+                DEFAULT_QUOTE_LIFETIME
+            },
+        },
+        Err(_) => DEFAULT_QUOTE_LIFETIME,
+    };
+    let on_expiry = match env::var("TPG_QUOTE_EXPIRY_ACTION").map(|s| s.to_lowercase()) {
+        Ok(s) if s == "reprice" => QuoteExpiryAction::Reprice,
+        Ok(s) if s == "expire" => QuoteExpiryAction::Expire,
+        Ok(s) => {
+            warn!(
+                "🪛️ Invalid configuration value for TPG_QUOTE_EXPIRY_ACTION: {s}. Use 'reprice' or 'expire'. Orders \
+                 will be re-priced when their quote expires."
+            );
+            QuoteExpiryAction::Reprice
+        },
+        Err(_) => QuoteExpiryAction::default(),
+    };
+    info!("🪛️ Exchange rate quotes are valid for {} minutes. On expiry: {on_expiry}", lifetime.num_minutes());
+    Some(QuotePolicy::new(lifetime, on_expiry))
+}
+
+fn configure_rate_circuit_breaker() -> RateCircuitBreaker {
+    let positive = |var: &str| match env::var(var) {
+        Ok(s) => match s.parse::<i64>() {
+            Ok(n) if n > 0 => Some(n),
+            Ok(0) => None,
+            _ => {
+                warn!("🪛️ Invalid configuration value for {var}: {s}. It must be a non-negative integer. Ignoring it.");
+                None
+            },
+        },
+        Err(_) => None,
+    };
+    let max_age = positive("TPG_RATE_MAX_AGE").map(Duration::minutes);
+    let max_change_pct = positive("TPG_RATE_MAX_CHANGE");
+    let breaker = RateCircuitBreaker::new(max_age, max_change_pct);
+    if breaker.is_enabled() {
+        let age = max_age.map(|a| format!("{} minutes", a.num_minutes())).unwrap_or_else(|| "unlimited".into());
+        let change = max_change_pct.map(|c| format!("{c}%")).unwrap_or_else(|| "unlimited".into());
+        info!("🪛️ Exchange rate circuit breaker is on. Maximum rate age: {age}. Maximum change per update: {change}");
+    } else {
+        info!("🪛️ Exchange rate circuit breaker is off. New orders are always priced at the latest exchange rate.");
+    }
+    breaker
+}
+
+fn configure_rate_feed() -> Option<RateFeedConfig> {
+    let names = env::var("TPG_RATE_SOURCES").ok()?;
+    let sources = names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| {
+            let var = |field: &str| env::var(format!("TPG_RATE_SOURCE_{}_{field}", name.to_uppercase())).ok();
+            match (var("URL"), var("FIELD")) {
+                (Some(url), Some(field)) => Some(HttpRateSource::new(name, &url, &field)),
+                _ => {
+                    warn!(
+                        "🪛️ Ignoring exchange rate source {name}. Both TPG_RATE_SOURCE_{0}_URL and \
+                         TPG_RATE_SOURCE_{0}_FIELD must be set.",
+                        name.to_uppercase()
+                    );
+                    None
+                },
+            }
+        })
+        .collect::<Vec<_>>();
+    if sources.is_empty() {
+        warn!("🪛️ No valid exchange rate sources are configured. Exchange rates will only be updated by hand.");
+        return None;
+    }
+    let mut config = RateFeedConfig { sources, ..RateFeedConfig::default() };
+    if let Ok(s) = env::var("TPG_RATE_FEED_CURRENCIES") {
+        config.currencies = s.split(',').map(|c| c.trim().to_uppercase()).filter(|c| !c.is_empty()).collect();
+    }
+    if let Ok(s) = env::var("TPG_RATE_FEED_INTERVAL") {
+        match s.parse::<u64>() {
+            Ok(n) if n > 0 => config.poll_interval = std::time::Duration::from_secs(n),
+            _ => {
+                warn!("🪛️ Invalid configuration value for TPG_RATE_FEED_INTERVAL: {s}. It must be a positive integer.")
+            },
+        }
+    }
+    if let Ok(s) = env::var("TPG_RATE_FEED_MAX_DEVIATION") {
+        match s.parse::<i64>() {
+            Ok(n) if n >= 0 => config.max_deviation_pct = n,
+            _ => warn!(
+                "🪛️ Invalid configuration value for TPG_RATE_FEED_MAX_DEVIATION: {s}. It must be a non-negative \
+                 integer."
+            ),
+        }
+    }
+    if let Ok(s) = env::var("TPG_RATE_FEED_MIN_SOURCES") {
+        match s.parse::<usize>() {
+            Ok(n) if n > 0 => config.min_sources = n,
+            _ => warn!(
+                "🪛️ Invalid configuration value for TPG_RATE_FEED_MIN_SOURCES: {s}. It must be a positive integer."
+            ),
+        }
+    }
+    info!(
+        "🪛️ Exchange rates for {} will be fetched from {} sources every {}s",
+        config.currencies.join(", "),
+        config.sources.len(),
+        config.poll_interval.as_secs()
+    );
+    Some(config)
+}
+
+fn configure_wallet_grpc() -> Option<WalletGrpcConfig> {
+    let address = env::var("TPG_WALLET_GRPC_ADDRESS").ok()?;
+    let mut config = WalletGrpcConfig { address, ..WalletGrpcConfig::default() };
+    config.username = env::var("TPG_WALLET_GRPC_USERNAME").ok();
+    if let Ok(s) = env::var("TPG_WALLET_GRPC_PASSWORD") {
+        config.password = Secret::new(s);
+    }
+    if let Ok(s) = env::var("TPG_WALLET_GRPC_MERCHANT_ID") {
+        config.merchant_id = s;
+    }
+    if let Ok(s) = env::var("TPG_WALLET_CONFIRMATIONS") {
+        match s.parse::<u64>() {
+            Ok(n) if n > 0 => config.confirmations = ConfirmationPolicy::new(n),
+            _ => warn!(
+                "🪛️ Invalid configuration value for TPG_WALLET_CONFIRMATIONS: {s}. It must be a positive integer."
+            ),
+        }
+    }
+    if let Ok(s) = env::var("TPG_WALLET_CONFIRMATION_THRESHOLDS") {
+        for threshold in s.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            match parse_confirmation_threshold(threshold) {
+                Some((amount, n)) => config.confirmations = config.confirmations.with_threshold(amount, n),
+                None => warn!(
+                    "🪛️ Invalid confirmation threshold in TPG_WALLET_CONFIRMATION_THRESHOLDS: {threshold}. Thresholds \
+                     must look like 1000:6, i.e. payments of 1000 XTR or more need 6 confirmations."
+                ),
+            }
+        }
+    }
+    if let Ok(s) = env::var("TPG_WALLET_POLL_INTERVAL") {
+        match s.parse::<u64>() {
+            Ok(n) if n > 0 => config.poll_interval = std::time::Duration::from_secs(n),
+            _ => {
+                warn!(
+                    "🪛️ Invalid configuration value for TPG_WALLET_POLL_INTERVAL: {s}. It must be a positive integer."
+                )
+            },
+        }
+    }
+    info!(
+        "🪛️ The hot wallet at {} will be checked for payments every {}s. Payments are confirmed after {}.",
+        config.address,
+        config.poll_interval.as_secs(),
+        config.confirmations
+    );
+    Some(config)
+}
+
+/// Parses an `<amount in XTR>:<confirmations>` pair
+fn parse_confirmation_threshold(s: &str) -> Option<(MicroTari, u64)> {
+    let (amount, confirmations) = s.split_once(':')?;
+    let amount = amount.trim().parse::<i64>().ok().filter(|a| *a > 0)?;
+    let confirmations = confirmations.trim().parse::<u64>().ok().filter(|n| *n > 0)?;
+    Some((MicroTari::from_tari(amount), confirmations))
+}
+
 //-------------------------------------------------  AuthConfig  -------------------------------------------------------
 #[derive(Clone, Debug)]
 pub struct AuthConfig {
@@ -342,6 +948,45 @@ impl AuthConfig {
     }
 }
 
+//-------------------------------------------------  SessionConfig  ----------------------------------------------------
+#[derive(Clone, Copy, Debug)]
+pub struct SessionConfig {
+    /// How long an access token is valid for. After this, clients trade their refresh token for a new access token.
+    pub access_token_lifetime: Duration,
+    /// How long a login session lasts. After this, the refresh token is refused and users have to log in again.
+    pub session_lifetime: Duration,
+    /// How often the revoked sessions and role permissions are reloaded from the database, to pick up the changes that
+    /// other server instances have made.
+    pub sync_interval: std::time::Duration,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            access_token_lifetime: DEFAULT_ACCESS_TOKEN_LIFETIME,
+            session_lifetime: DEFAULT_SESSION_LIFETIME,
+            sync_interval: DEFAULT_SESSION_SYNC_INTERVAL,
+        }
+    }
+}
+
+//-------------------------------------------------  RateLimitConfig  --------------------------------------------------
+/// Token bucket rate limits on the endpoints that anyone can call. Endpoints without a limit are not limited.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RateLimitConfig {
+    /// Logins at `/auth`, per client IP address and per address in the login token
+    pub auth: Option<RateLimit>,
+    /// Order claims at `/order/claim`, per client IP address and per wallet address that signed the claim
+    pub claim: Option<RateLimit>,
+    /// Payment and confirmation notifications from the hot wallets, per client IP address and per wallet address
+    pub wallet: Option<RateLimit>,
+    /// Shopify and WooCommerce webhooks, per client IP address
+    pub webhooks: Option<RateLimit>,
+    /// If true, the buckets are kept in the database, so that every server instance that uses the database shares the
+    /// limits. Otherwise, each instance keeps its own buckets in memory.
+    pub shared: bool,
+}
+
 //-------------------------------------------------  ServerOptions  ----------------------------------------------------
 /// A subset of the server configuration that is used to configure the server's behaviour. Generally we try to keep this
 /// as small as possible, and exclude secrets to avoid passing sensitive information around the system.
@@ -354,6 +999,12 @@ pub struct ServerOptions {
     pub disable_memo_signature_check: bool,
     pub shopify_order_field: OrderIdField,
     pub strict_mode: bool,
+    pub rate_quotes: Option<QuotePolicy>,
+    pub rate_circuit_breaker: RateCircuitBreaker,
+    pub approval_policy: ApprovalPolicy,
+    /// Notifier confirmations of payments of this amount or more are deferred to the wallet watcher, because the
+    /// confirmation policy wants them deeper than the notifier waits for.
+    pub deferred_confirmations_from: Option<MicroTari>,
 }
 
 impl ServerOptions {
@@ -365,6 +1016,39 @@ impl ServerOptions {
             disable_memo_signature_check: config.disable_memo_signature_check,
             shopify_order_field: config.shopify_config.order_id_field,
             strict_mode: config.strict_mode,
+            rate_quotes: config.rate_quotes,
+            rate_circuit_breaker: config.rate_circuit_breaker,
+            approval_policy: config.approval_policy,
+            deferred_confirmations_from: config
+                .wallet_grpc
+                .as_ref()
+                .and_then(|wallet| wallet.confirmations.deeper_than_default_from()),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use tpg_common::MicroTari;
+
+    use super::{parse_confirmation_threshold, DatabaseBackend};
+
+    #[test]
+    fn database_backend_from_url() {
+        assert_eq!(DatabaseBackend::from_url("sqlite://data/tari_store.db"), Some(DatabaseBackend::Sqlite));
+        assert_eq!(DatabaseBackend::from_url("sqlite::memory:"), Some(DatabaseBackend::Sqlite));
+        assert_eq!(DatabaseBackend::from_url("postgres://localhost/tari_store"), Some(DatabaseBackend::Postgres));
+        assert_eq!(DatabaseBackend::from_url("PostgreSQL://localhost/tari_store"), Some(DatabaseBackend::Postgres));
+        assert_eq!(DatabaseBackend::from_url("mysql://localhost/tari_store"), None);
+        assert_eq!(DatabaseBackend::from_url("data/tari_store.db"), None);
+    }
+
+    #[test]
+    fn confirmation_thresholds() {
+        assert_eq!(parse_confirmation_threshold("1000:6"), Some((MicroTari::from_tari(1000), 6)));
+        assert_eq!(parse_confirmation_threshold(" 50 : 4 "), Some((MicroTari::from_tari(50), 4)));
+        assert_eq!(parse_confirmation_threshold("1000"), None);
+        assert_eq!(parse_confirmation_threshold("1000:0"), None);
+        assert_eq!(parse_confirmation_threshold("-5:6"), None);
+    }
+}
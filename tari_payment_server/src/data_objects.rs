@@ -1,10 +1,23 @@
 use std::fmt::Display;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use tari_payment_engine::{
-    db_types::{NewPayment, OrderId, Role, SerializedTariAddress},
+    db_types::{
+        default_merchant,
+        AdminActionFilter,
+        ApprovalStatus,
+        AuditOutcome,
+        LedgerAccount,
+        LedgerAccountType,
+        NewPayment,
+        OrderId,
+        RefundStatus,
+        Role,
+        SerializedTariAddress,
+    },
     helpers::WalletSignature,
-    tpe_api::exchange_objects::ExchangeRate,
+    tpe_api::{account_objects::Pagination, exchange_objects::ExchangeRate, reconciliation_objects::WalletTransaction},
 };
 use tpg_common::MicroTari;
 
@@ -15,6 +28,9 @@ pub struct RoleUpdateRequest {
     pub apply: Vec<Role>,
     #[serde(default)]
     pub revoke: Vec<Role>,
+    /// If given, the roles are applied and revoked for this merchant only
+    #[serde(default)]
+    pub merchant: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -93,12 +109,19 @@ pub struct ExchangeRateUpdate {
     pub currency: String,
     // The price of 1 unit of the currency in MicroTari
     pub rate: u64,
+    /// If given, the rate only applies to this merchant's orders. Otherwise it is the default rate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merchant: Option<String>,
 }
 
 impl From<ExchangeRateUpdate> for ExchangeRate {
     fn from(update: ExchangeRateUpdate) -> Self {
         #[allow(clippy::cast_possible_wrap)]
-        Self::new(update.currency, MicroTari::from(update.rate as i64), None)
+        let rate = Self::new(update.currency, MicroTari::from(update.rate as i64), None);
+        match update.merchant {
+            Some(merchant) => rate.for_merchant(merchant),
+            None => rate,
+        }
     }
 }
 
@@ -107,10 +130,175 @@ pub struct ExchangeRateResult {
     pub currency: String,
     pub rate: i64,
     pub updated_at: String,
+    /// The merchant that set the rate
+    #[serde(default = "default_merchant")]
+    pub merchant_id: String,
 }
 
 impl From<ExchangeRate> for ExchangeRateResult {
     fn from(rate: ExchangeRate) -> Self {
-        Self { currency: rate.base_currency, rate: rate.rate.value(), updated_at: rate.updated_at.to_rfc3339() }
+        Self {
+            currency: rate.base_currency,
+            rate: rate.rate.value(),
+            updated_at: rate.updated_at.to_rfc3339(),
+            merchant_id: rate.merchant_id,
+        }
+    }
+}
+
+/// Query parameters for the exchange rate history. `since` and `until` are RFC 3339 timestamps, and both are
+/// inclusive. If `at` is given, only the rate that applied at that time is returned and the other parameters are
+/// ignored.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RateHistoryQuery {
+    /// The merchant whose rates are listed. The default merchant's rates are listed if it is not given.
+    pub merchant: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub at: Option<DateTime<Utc>>,
+    pub offset: Option<i64>,
+    pub count: Option<i64>,
+}
+
+impl RateHistoryQuery {
+    pub fn pagination(&self) -> Pagination {
+        Pagination { offset: self.offset, count: self.count }
+    }
+}
+
+/// Query parameters for the list of wallets that accept payments
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WalletQuery {
+    /// Only list the wallets of this merchant
+    pub merchant: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateWebhookParams {
+    pub active: bool,
+}
+
+/// Query parameters for the webhook delivery log. `Pagination` can't be flattened into this struct, since query
+/// string deserialization does not support numbers in flattened fields.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebhookDeliveryQuery {
+    pub subscription_id: Option<i64>,
+    pub offset: Option<i64>,
+    pub count: Option<i64>,
+}
+
+impl WebhookDeliveryQuery {
+    pub fn pagination(&self) -> Pagination {
+        Pagination { offset: self.offset, count: self.count }
+    }
+}
+
+/// Query parameters for the refund list. Like [`WebhookDeliveryQuery`], the pagination fields are inlined.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RefundQuery {
+    pub status: Option<RefundStatus>,
+    pub offset: Option<i64>,
+    pub count: Option<i64>,
+}
+
+impl RefundQuery {
+    pub fn pagination(&self) -> Pagination {
+        Pagination { offset: self.offset, count: self.count }
+    }
+}
+
+/// Query parameters for the approval request list. Like [`RefundQuery`], the pagination fields are inlined.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ApprovalQuery {
+    pub status: Option<ApprovalStatus>,
+    pub offset: Option<i64>,
+    pub count: Option<i64>,
+}
+
+impl ApprovalQuery {
+    pub fn pagination(&self) -> Pagination {
+        Pagination { offset: self.offset, count: self.count }
+    }
+}
+
+/// Query parameters for the entries of a ledger account. The account id is left out for accounts that are not kept per
+/// address or merchant.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntriesQuery {
+    pub account_type: LedgerAccountType,
+    #[serde(default)]
+    pub account_id: String,
+    pub offset: Option<i64>,
+    pub count: Option<i64>,
+}
+
+impl LedgerEntriesQuery {
+    pub fn account(&self) -> LedgerAccount {
+        LedgerAccount::new(self.account_type, self.account_id.clone())
     }
+
+    pub fn pagination(&self) -> Pagination {
+        Pagination { offset: self.offset, count: self.count }
+    }
+}
+
+/// Query parameters for the admin audit log. Like [`WebhookDeliveryQuery`], the pagination fields are inlined.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditQuery {
+    pub actor: Option<SerializedTariAddress>,
+    pub endpoint: Option<String>,
+    pub outcome: Option<AuditOutcome>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub offset: Option<i64>,
+    pub count: Option<i64>,
+}
+
+impl AuditQuery {
+    pub fn filter(&self) -> AdminActionFilter {
+        AdminActionFilter {
+            actor: self.actor.clone(),
+            endpoint: self.endpoint.clone(),
+            outcome: self.outcome,
+            since: self.since,
+            until: self.until,
+        }
+    }
+
+    pub fn pagination(&self) -> Pagination {
+        Pagination { offset: self.offset, count: self.count }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectRefundParams {
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectApprovalParams {
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RefundSentParams {
+    pub payout_txid: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReconcileParams {
+    /// The wallet's inbound transactions, e.g. from a wallet export. If omitted, the history is fetched from the hot
+    /// wallet over gRPC.
+    #[serde(default)]
+    pub transactions: Option<Vec<WalletTransaction>>,
+    /// Only reconcile payments and transactions from this time onwards
+    #[serde(default)]
+    pub since: Option<DateTime<Utc>>,
+    /// Record the missing transactions as payments
+    #[serde(default)]
+    pub import_missing: bool,
+    /// The merchant that owns the wallet, and so the imported payments. Defaults to the hot wallet's merchant if the
+    /// history is fetched over gRPC, and to the default merchant otherwise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merchant: Option<String>,
 }
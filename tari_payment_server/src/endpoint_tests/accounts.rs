@@ -27,6 +27,8 @@ async fn fetch_my_account_expired_token() {
     let claims = JwtClaims {
         address: TariAddress::from_base58("14AYt2hhhn4VydAXNJ6i7ZfRNZGoGSp713dHjMYCoK5hYw2").unwrap(),
         roles: vec![Role::User],
+        merchant: None,
+        session: Some(1),
     };
     let expired = Utc::now() - Days::new(1);
     debug!("Calling /account with expired token {claims:?}");
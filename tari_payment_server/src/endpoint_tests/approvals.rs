@@ -0,0 +1,71 @@
+use actix_jwt_auth_middleware::AuthenticationService;
+use actix_web::{http::StatusCode, test, test::TestRequest, web, App};
+use chrono::{Days, Utc};
+use tari_common_types::tari_address::TariAddress;
+use tari_payment_engine::{
+    db_types::{ApprovalRequest, ApprovalStatus, CreditNote, Role},
+    events::EventProducers,
+    helpers::create_dummy_address_for_cust_id,
+    tpe_api::{approval_objects::ApprovalPolicy, audit_api::AuditApi},
+    traits::AccountManagement,
+    InMemoryDatabase,
+    OrderFlowApi,
+};
+use tpg_common::MicroTari;
+
+use super::helpers::{get_auth_config, issue_token};
+use crate::{
+    auth::{build_tps_authority, JwtClaims, RevocationList, RolePermissions},
+    config::{ServerConfig, ServerOptions},
+    middleware::AuditTrail,
+    routes::{ApproveOperationRoute, IssueCreditRoute},
+};
+
+const ADMIN: &str = "14AYt2hhhn4VydAXNJ6i7ZfRNZGoGSp713dHjMYCoK5hYw2";
+const SECOND_ADMIN: &str = "14zCiH6ybX18HCm3SfLfHSoT48vfzxWUtbNPMt7k61akqDv";
+
+fn token(address: &str) -> String {
+    let address = TariAddress::from_base58(address).unwrap();
+    let claims = JwtClaims { address, roles: vec![Role::User, Role::Write], merchant: None, session: Some(1) };
+    issue_token(claims, Utc::now() + Days::new(1))
+}
+
+#[actix_web::test]
+async fn large_credit_needs_a_second_admin() {
+    let _ = env_logger::try_init().ok();
+    let db = InMemoryDatabase::new();
+    let policy = ApprovalPolicy { credit_threshold: Some(MicroTari::from_tari(1_000)), ..ApprovalPolicy::default() };
+    let config = ServerConfig { approval_policy: policy, ..ServerConfig::default() };
+    let app = App::new()
+        .app_data(web::Data::new(RevocationList::default()))
+        .app_data(web::Data::new(RolePermissions::default()))
+        .app_data(web::Data::new(AuditTrail::new(AuditApi::new(db.clone()))))
+        .app_data(web::Data::new(OrderFlowApi::new(db.clone(), EventProducers::default())))
+        .app_data(web::Data::new(ServerOptions::from_config(&config)))
+        .wrap(AuthenticationService::new(build_tps_authority(get_auth_config())))
+        .service(IssueCreditRoute::<InMemoryDatabase>::new())
+        .service(ApproveOperationRoute::<InMemoryDatabase>::new());
+    let app = test::init_service(app).await;
+
+    let credit = CreditNote::new("alice".into(), MicroTari::from_tari(1_000));
+    let req = TestRequest::post().uri("/credit").insert_header(("tpg_access_token", token(ADMIN))).set_json(&credit);
+    let res = test::call_service(&app, req.to_request()).await;
+    assert_eq!(res.status(), StatusCode::ACCEPTED);
+    let request: ApprovalRequest = test::read_body_json(res).await;
+    assert_eq!(request.status, ApprovalStatus::Pending);
+    let credited = create_dummy_address_for_cust_id("alice");
+    assert!(db.fetch_payments_for_address(&credited).await.unwrap().is_empty());
+
+    let uri = format!("/approvals/{}/approve", request.id);
+    let req = TestRequest::post().uri(&uri).insert_header(("tpg_access_token", token(ADMIN)));
+    let res = test::call_service(&app, req.to_request()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let req = TestRequest::post().uri(&uri).insert_header(("tpg_access_token", token(SECOND_ADMIN)));
+    let res = test::call_service(&app, req.to_request()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let request = db.fetch_approval_request(request.id).await.unwrap().unwrap();
+    assert_eq!(request.status, ApprovalStatus::Approved);
+    assert_eq!(request.decided_by.unwrap().as_base58(), SECOND_ADMIN);
+    assert_eq!(db.fetch_payments_for_address(&credited).await.unwrap()[0].amount, MicroTari::from_tari(1_000));
+}
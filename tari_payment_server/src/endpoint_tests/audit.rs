@@ -0,0 +1,82 @@
+use actix_jwt_auth_middleware::AuthenticationService;
+use actix_web::{test, test::TestRequest, web, App};
+use chrono::{Days, Utc};
+use serde_json::json;
+use tari_common_types::tari_address::TariAddress;
+use tari_payment_engine::{
+    db_types::{AdminAction, AdminActionFilter, AuditOutcome, Role},
+    tpe_api::{account_objects::Pagination, audit_api::AuditApi},
+    traits::AuditLog,
+    AuthApi,
+    InMemoryDatabase,
+};
+
+use super::{
+    helpers::{get_auth_config, issue_token},
+    mocks::MockAuthManager,
+};
+use crate::{
+    auth::{build_tps_authority, JwtClaims, RevocationList, RolePermissions},
+    middleware::{AuditTrail, AUDIT_REASON_HEADER},
+    routes::UpdateRolesRoute,
+};
+
+const ADMIN: &str = "14AYt2hhhn4VydAXNJ6i7ZfRNZGoGSp713dHjMYCoK5hYw2";
+
+#[actix_web::test]
+async fn privileged_calls_are_recorded() {
+    let _ = env_logger::try_init().ok();
+    let actions = update_roles(vec![Role::User, Role::SuperAdmin]).await;
+    assert_eq!(actions.len(), 1);
+    let action = &actions[0];
+    assert_eq!(action.actor.as_base58(), ADMIN);
+    assert_eq!(action.roles, vec![Role::User, Role::SuperAdmin]);
+    assert_eq!(action.endpoint, "update_roles");
+    assert_eq!(action.method, "POST");
+    assert_eq!(action.path, "/roles");
+    assert_eq!(action.parameters["body"][0]["apply"], json!(["write"]));
+    assert_eq!(action.reason.as_deref(), Some("New support staff"));
+    assert_eq!(action.outcome, AuditOutcome::Succeeded);
+    assert_eq!(action.status_code, 200);
+    assert!(action.error.is_none());
+}
+
+#[actix_web::test]
+async fn refused_calls_are_recorded() {
+    let _ = env_logger::try_init().ok();
+    let actions = update_roles(vec![Role::User]).await;
+    assert_eq!(actions.len(), 1);
+    let action = &actions[0];
+    assert_eq!(action.endpoint, "update_roles");
+    assert_eq!(action.outcome, AuditOutcome::Denied);
+    assert_eq!(action.status_code, 403);
+    assert_eq!(action.error.as_deref(), Some("Insufficient permissions."));
+}
+
+/// Assigns a role with a token carrying `roles`, and returns the audit log afterwards
+async fn update_roles(roles: Vec<Role>) -> Vec<AdminAction> {
+    let db = InMemoryDatabase::new();
+    let mut auth_manager = MockAuthManager::new();
+    auth_manager.expect_assign_roles().returning(|_, _| Ok(()));
+    auth_manager.expect_remove_roles().returning(|_, _| Ok(0));
+    let claims =
+        JwtClaims { address: TariAddress::from_base58(ADMIN).unwrap(), roles, merchant: None, session: Some(1) };
+    let token = issue_token(claims, Utc::now() + Days::new(1));
+    let app = App::new()
+        .app_data(web::Data::new(RevocationList::default()))
+        .app_data(web::Data::new(RolePermissions::default()))
+        .app_data(web::Data::new(AuditTrail::new(AuditApi::new(db.clone()))))
+        .app_data(web::Data::new(AuthApi::new(auth_manager)))
+        .wrap(AuthenticationService::new(build_tps_authority(get_auth_config())))
+        .service(UpdateRolesRoute::<MockAuthManager>::new());
+    let app = test::init_service(app).await;
+    let req = TestRequest::post()
+        .uri("/roles")
+        .insert_header(("tpg_access_token", token))
+        .insert_header((AUDIT_REASON_HEADER, "New support staff"))
+        .set_json(json!([{ "address": ADMIN, "apply": ["write"] }]))
+        .to_request();
+    let _ = test::try_call_service(&app, req).await;
+    let all = Pagination { offset: None, count: None };
+    db.fetch_admin_actions(&AdminActionFilter::default(), &all).await.unwrap()
+}
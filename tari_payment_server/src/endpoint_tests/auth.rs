@@ -1,18 +1,23 @@
 use actix_web::{body::MessageBody, http::StatusCode, test, test::TestRequest, web, web::ServiceConfig, App};
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use log::*;
+use tari_common_types::tari_address::TariAddress;
 use tari_jwt::{
     jwt_compact::{AlgorithmExt, Claims, UntrustedToken},
     Ristretto256,
     Ristretto256VerifyingKey,
 };
-use tari_payment_engine::{db_types::Role, traits::AuthApiError, AuthApi};
+use tari_payment_engine::{
+    db_types::{AuthSession, NewAuthSession, Role},
+    traits::AuthApiError,
+    AuthApi,
+};
 
 use super::mocks::*;
 use crate::{
-    auth::{JwtClaims, TokenIssuer},
+    auth::{hash_refresh_token, JwtClaims, RevocationList, TokenIssuer, REFRESH_TOKEN_HEADER},
     config::AuthConfig,
-    routes::AuthRoute,
+    routes::{AuthRoute, RefreshAccessTokenRoute},
 };
 
 #[actix_web::test]
@@ -58,6 +63,31 @@ async fn login_with_valid_token() {
     assert!(status.is_success());
     assert_eq!(token.address.to_base58(), "14zCiH6ybX18HCm3SfLfHSoT48vfzxWUtbNPMt7k61akqDv");
     assert_eq!(&token.roles, &[Role::User, Role::Write]);
+    assert_eq!(token.session, Some(1));
+}
+
+#[actix_web::test]
+async fn refresh_with_valid_token() {
+    let _ = env_logger::try_init().ok();
+    let (status, body, refresh_token, config) = refresh_request(Some("a refresh token")).await;
+    assert!(status.is_success(), "was: {body}");
+    let token = validate_token(&body, &config.jwt_verification_key).unwrap();
+    assert_eq!(token.address.to_base58(), "14zCiH6ybX18HCm3SfLfHSoT48vfzxWUtbNPMt7k61akqDv");
+    assert_eq!(&token.roles, &[Role::User]);
+    assert_eq!(token.session, Some(1));
+    let refresh_token = refresh_token.expect("Expected a new refresh token");
+    assert_ne!(refresh_token, "a refresh token");
+}
+
+#[actix_web::test]
+async fn refresh_with_invalid_token() {
+    let _ = env_logger::try_init().ok();
+    let (status, body, refresh_token, _) = refresh_request(Some("a stale refresh token")).await;
+    assert_eq!(status.as_u16(), StatusCode::UNAUTHORIZED.as_u16());
+    assert!(body.contains("Refresh token is invalid"), "was: {body}");
+    assert!(refresh_token.is_none());
+    let (status, _, _, _) = refresh_request(None).await;
+    assert_eq!(status.as_u16(), StatusCode::UNAUTHORIZED.as_u16());
 }
 
 #[actix_web::test]
@@ -96,11 +126,44 @@ fn configure_app(config: AuthConfig, update_nonce_result: Result<(), AuthApiErro
         auth_manager.expect_update_nonce_for_address().return_const(update_nonce_result);
         auth_manager.expect_check_auth_account_exists().returning(move |_| Ok(true));
         auth_manager.expect_check_address_has_roles().returning(|_a, _b| Ok(()));
+        auth_manager.expect_create_session().returning(|s| Ok(new_session(s)));
+        auth_manager.expect_refresh_session().returning(|old, new| {
+            if old == hash_refresh_token("a refresh token") {
+                let address = TariAddress::from_base58("14zCiH6ybX18HCm3SfLfHSoT48vfzxWUtbNPMt7k61akqDv").unwrap();
+                let session = NewAuthSession {
+                    address,
+                    roles: vec![Role::User],
+                    merchant: None,
+                    refresh_token_hash: new.to_string(),
+                    expires_at: Utc::now() + Duration::days(30),
+                };
+                Ok(new_session(session))
+            } else {
+                Err(AuthApiError::InvalidRefreshToken)
+            }
+        });
         let auth_api = AuthApi::new(auth_manager);
         let jwt_signer = TokenIssuer::new(&config.clone());
         cfg.app_data(web::Data::new(auth_api))
             .app_data(web::Data::new(jwt_signer))
-            .service(AuthRoute::<MockAuthManager>::new());
+            .app_data(web::Data::new(RevocationList::default()))
+            .service(AuthRoute::<MockAuthManager>::new())
+            .service(RefreshAccessTokenRoute::<MockAuthManager>::new());
+    }
+}
+
+fn new_session(session: NewAuthSession) -> AuthSession {
+    let now = Utc::now();
+    AuthSession {
+        id: 1,
+        address: session.address.into(),
+        roles: session.roles,
+        merchant: session.merchant,
+        refresh_token_hash: session.refresh_token_hash,
+        created_at: now,
+        refreshed_at: now,
+        expires_at: session.expires_at,
+        revoked_at: None,
     }
 }
 
@@ -118,6 +181,22 @@ async fn post_request(
     let body = String::from_utf8_lossy(&res.into_body().try_into_bytes().unwrap()).into_owned();
     (status, body, config)
 }
+
+async fn refresh_request(refresh_token: Option<&str>) -> (StatusCode, String, Option<String>, AuthConfig) {
+    let mut req = TestRequest::post().uri("/auth/refresh");
+    if let Some(token) = refresh_token {
+        req = req.insert_header((REFRESH_TOKEN_HEADER, token));
+    }
+    let config = AuthConfig::default();
+    let app = App::new().configure(configure_app(config.clone(), Ok(())));
+    let app = test::init_service(app).await;
+    let (_, res) = test::call_service(&app, req.to_request()).await.into_parts();
+    let status = res.status();
+    let refresh_token = res.headers().get(REFRESH_TOKEN_HEADER).map(|h| h.to_str().unwrap().to_string());
+    let body = String::from_utf8_lossy(&res.into_body().try_into_bytes().unwrap()).into_owned();
+    (status, body, refresh_token, config)
+}
+
 fn validate_token(token: &str, verifying_key: &Ristretto256VerifyingKey) -> Result<JwtClaims, String> {
     debug!("Validating token: {token}");
     let untrusted_token = UntrustedToken::new(token).map_err(|e| format!("Invalid token format: {e:?}"))?;
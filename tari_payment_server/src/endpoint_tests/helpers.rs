@@ -1,5 +1,5 @@
 use actix_jwt_auth_middleware::AuthenticationService;
-use actix_web::{body::MessageBody, http::StatusCode, test, test::TestRequest, web::ServiceConfig, App};
+use actix_web::{body::MessageBody, http::StatusCode, test, test::TestRequest, web, web::ServiceConfig, App};
 use chrono::{DateTime, Utc};
 use log::debug;
 use tari_jwt::{
@@ -14,7 +14,7 @@ use tari_jwt::{
 };
 
 use crate::{
-    auth::{build_tps_authority, JwtClaims},
+    auth::{build_tps_authority, JwtClaims, RevocationList, RolePermissions},
     config::AuthConfig,
 };
 
@@ -51,7 +51,11 @@ pub async fn get_request(
     let req = req.to_request();
     let config = get_auth_config();
     let authority = build_tps_authority(config.clone());
-    let app = App::new().wrap(AuthenticationService::new(authority)).configure(configure);
+    let app = App::new()
+        .app_data(web::Data::new(RevocationList::default()))
+        .app_data(web::Data::new(RolePermissions::default()))
+        .wrap(AuthenticationService::new(authority))
+        .configure(configure);
 
     let service = test::init_service(app).await;
     debug!("Making request");
@@ -1,9 +1,31 @@
+use chrono::{DateTime, Duration, Utc};
 use mockall::mock;
 use tari_common_types::tari_address::TariAddress;
 use tari_payment_engine::{
-    db_types::{AddressBalance, CustomerBalance, CustomerOrderBalance, CustomerOrders, Order, OrderId, Payment, Role},
+    db_types::{
+        AddressBalance,
+        ApprovalRequest,
+        ApprovalStatus,
+        AuthSession,
+        CustomerBalance,
+        CustomerOrderBalance,
+        CustomerOrders,
+        HeldOrder,
+        LedgerAccount,
+        LedgerBalance,
+        LedgerEntry,
+        NewAuthSession,
+        Order,
+        OrderId,
+        Payment,
+        Refund,
+        RefundStatus,
+        Role,
+        RoleDefinition,
+        SettlementJournalEntry,
+    },
     order_objects::OrderQueryFilter,
-    tpe_api::account_objects::{AddressHistory, CustomerHistory, Pagination},
+    tpe_api::account_objects::{AddressHistory, CustomerHistory, Pagination, TrialBalance},
     traits::{AccountApiError, AccountManagement, AuthApiError, AuthManagement},
 };
 
@@ -26,6 +48,20 @@ mock! {
         async fn fetch_customer_order_balance(&self, customer_id: &str) -> Result<CustomerOrderBalance, AccountApiError>;
         async fn fetch_customer_ids_for_address(&self, address: &TariAddress) -> Result<Vec<String>, AccountApiError>;
         async fn fetch_payments_for_order(&self, order_id: &OrderId) -> Result<Vec<Payment>, AccountApiError>;
+        async fn fetch_onchain_payments(&self, since: Option<DateTime<Utc>>) -> Result<Vec<Payment>, AccountApiError>;
+        async fn fetch_idle_balances(&self, idle: Duration) -> Result<Vec<AddressBalance>, AccountApiError>;
+        async fn fetch_refund(&self, id: i64) -> Result<Option<Refund>, AccountApiError>;
+        async fn fetch_refunds(&self, status: Option<RefundStatus>, pagination: &Pagination) -> Result<Vec<Refund>, AccountApiError>;
+        async fn fetch_held_order(&self, order_id: &OrderId) -> Result<Option<HeldOrder>, AccountApiError>;
+        async fn fetch_held_orders(&self, pagination: &Pagination) -> Result<Vec<HeldOrder>, AccountApiError>;
+        async fn fetch_ledger_entries(&self, account: &LedgerAccount, pagination: &Pagination) -> Result<Vec<LedgerEntry>, AccountApiError>;
+        async fn fetch_ledger_balances(&self) -> Result<Vec<LedgerBalance>, AccountApiError>;
+        async fn fetch_trial_balance(&self) -> Result<TrialBalance, AccountApiError>;
+        async fn fetch_orders_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<Order>, AccountApiError>;
+        async fn fetch_payments_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<Payment>, AccountApiError>;
+        async fn fetch_settlements_between(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<SettlementJournalEntry>, AccountApiError>;
+        async fn fetch_approval_request(&self, id: i64) -> Result<Option<ApprovalRequest>, AccountApiError>;
+        async fn fetch_approval_requests(&self, status: Option<ApprovalStatus>, pagination: &Pagination) -> Result<Vec<ApprovalRequest>, AccountApiError>;
     }
 }
 
@@ -39,5 +75,18 @@ mock! {
         async fn fetch_roles_for_address(&self, address: &TariAddress) -> Result<Vec<Role>, AuthApiError>;
         async fn assign_roles(&self, address: &TariAddress, roles: &[Role]) -> Result<(), AuthApiError>;
         async fn remove_roles(&self, address: &TariAddress, roles: &[Role]) -> Result<u64, AuthApiError>;
+        async fn fetch_merchant_roles_for_address(&self, address: &TariAddress, merchant_id: &str) -> Result<Vec<Role>, AuthApiError>;
+        async fn assign_merchant_roles(&self, address: &TariAddress, merchant_id: &str, roles: &[Role]) -> Result<(), AuthApiError>;
+        async fn remove_merchant_roles(&self, address: &TariAddress, merchant_id: &str, roles: &[Role]) -> Result<u64, AuthApiError>;
+        async fn fetch_role_definitions(&self) -> Result<Vec<RoleDefinition>, AuthApiError>;
+        async fn save_role(&self, role: &RoleDefinition) -> Result<(), AuthApiError>;
+        async fn delete_role(&self, role: &Role) -> Result<(), AuthApiError>;
+        async fn create_session(&self, session: NewAuthSession) -> Result<AuthSession, AuthApiError>;
+        async fn fetch_session(&self, id: i64) -> Result<Option<AuthSession>, AuthApiError>;
+        async fn refresh_session(&self, refresh_token_hash: &str, new_refresh_token_hash: &str) -> Result<AuthSession, AuthApiError>;
+        async fn fetch_sessions_for_address(&self, address: &TariAddress) -> Result<Vec<AuthSession>, AuthApiError>;
+        async fn revoke_session(&self, id: i64) -> Result<AuthSession, AuthApiError>;
+        async fn revoke_sessions_for_address(&self, address: &TariAddress) -> Result<Vec<i64>, AuthApiError>;
+        async fn fetch_revoked_sessions(&self, since: DateTime<Utc>) -> Result<Vec<i64>, AuthApiError>;
     }
 }
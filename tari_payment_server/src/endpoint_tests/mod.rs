@@ -1,6 +1,10 @@
 mod accounts;
+mod approvals;
+mod audit;
 mod auth;
 mod helpers;
 mod misc;
 mod mocks;
 mod orders;
+mod rate_limits;
+mod woocommerce;
@@ -3,7 +3,7 @@ use chrono::{Days, TimeZone, Utc};
 use log::debug;
 use tari_common_types::tari_address::TariAddress;
 use tari_payment_engine::{
-    db_types::{Order, OrderId, OrderStatusType, Role},
+    db_types::{Order, OrderId, OrderStatusType, Role, DEFAULT_MERCHANT},
     traits::AccountApiError,
     AccountApi,
 };
@@ -73,6 +73,8 @@ fn valid_token(roles: Vec<Role>) -> String {
         JwtClaims {
             address: TariAddress::from_base58("14AYt2hhhn4VydAXNJ6i7ZfRNZGoGSp713dHjMYCoK5hYw2").unwrap(),
             roles,
+            merchant: None,
+            session: Some(1),
         },
         Utc::now() + Days::new(1),
     )
@@ -102,6 +104,9 @@ fn orders_response(_: &TariAddress) -> Result<Vec<Order>, AccountApiError> {
             created_at: Utc.with_ymd_and_hms(2024, 2, 29, 13, 30, 0).unwrap(),
             updated_at: Utc.with_ymd_and_hms(2024, 2, 29, 13, 30, 0).unwrap(),
             status: OrderStatusType::Paid,
+            rate_id: None,
+            quote_expires_at: None,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
         },
         Order {
             id: 1,
@@ -115,8 +120,11 @@ fn orders_response(_: &TariAddress) -> Result<Vec<Order>, AccountApiError> {
             created_at: Utc.with_ymd_and_hms(2024, 3, 15, 18, 30, 0).unwrap(),
             updated_at: Utc.with_ymd_and_hms(2024, 3, 16, 11, 20, 0).unwrap(),
             status: OrderStatusType::Cancelled,
+            rate_id: None,
+            quote_expires_at: None,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
         },
     ])
 }
 
-const ORDERS_JSON: &str = r##"{"address":"14AYt2hhhn4VydAXNJ6i7ZfRNZGoGSp713dHjMYCoK5hYw2","total_orders":250,"orders":[{"id":0,"order_id":"0000001","alt_id":"#1001","customer_id":"1","memo":null,"total_price":100,"original_price":null,"currency":"XTR","created_at":"2024-02-29T13:30:00Z","updated_at":"2024-02-29T13:30:00Z","status":"Paid"},{"id":1,"order_id":"0000002","alt_id":"#1002","customer_id":"1","memo":null,"total_price":150,"original_price":null,"currency":"XTR","created_at":"2024-03-15T18:30:00Z","updated_at":"2024-03-16T11:20:00Z","status":"Cancelled"}]}"##;
+const ORDERS_JSON: &str = r##"{"address":"14AYt2hhhn4VydAXNJ6i7ZfRNZGoGSp713dHjMYCoK5hYw2","total_orders":250,"orders":[{"id":0,"order_id":"0000001","alt_id":"#1001","customer_id":"1","memo":null,"total_price":100,"original_price":null,"currency":"XTR","created_at":"2024-02-29T13:30:00Z","updated_at":"2024-02-29T13:30:00Z","status":"Paid","merchant_id":"default"},{"id":1,"order_id":"0000002","alt_id":"#1002","customer_id":"1","memo":null,"total_price":150,"original_price":null,"currency":"XTR","created_at":"2024-03-15T18:30:00Z","updated_at":"2024-03-16T11:20:00Z","status":"Cancelled","merchant_id":"default"}]}"##;
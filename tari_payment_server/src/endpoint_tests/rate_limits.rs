@@ -0,0 +1,60 @@
+use std::{net::SocketAddr, time::Duration};
+
+use actix_web::{
+    http::{header::RETRY_AFTER, StatusCode},
+    test,
+    test::TestRequest,
+    web,
+    App,
+};
+use serde_json::json;
+use tari_payment_engine::{
+    events::EventProducers,
+    tpe_api::rate_limit_objects::RateLimit,
+    InMemoryDatabase,
+    OrderFlowApi,
+};
+
+use crate::{
+    config::{RateLimitConfig, ServerConfig, ServerOptions},
+    middleware::{MemoryRateLimits, RateLimiter},
+    routes::{metrics, ClaimOrderRoute},
+};
+
+const ALICE: &str = "14AYt2hhhn4VydAXNJ6i7ZfRNZGoGSp713dHjMYCoK5hYw2";
+const BOB: &str = "14zCiH6ybX18HCm3SfLfHSoT48vfzxWUtbNPMt7k61akqDv";
+
+fn claim(ip: &str, address: &str) -> TestRequest {
+    let body = json!({ "address": address, "order_id": "1", "signature": "00" });
+    TestRequest::post().uri("/order/claim").peer_addr(SocketAddr::new(ip.parse().unwrap(), 8080)).set_json(body)
+}
+
+#[actix_web::test]
+async fn claims_are_limited_per_ip_and_per_address() {
+    let _ = env_logger::try_init().ok();
+    let db = InMemoryDatabase::new();
+    let limits = RateLimitConfig { claim: Some(RateLimit::new(1, Duration::from_secs(60))), ..Default::default() };
+    let app = App::new()
+        .app_data(web::Data::new(RateLimiter::new(MemoryRateLimits::default(), limits)))
+        .app_data(web::Data::new(OrderFlowApi::new(db.clone(), EventProducers::default())))
+        .app_data(web::Data::new(ServerOptions::from_config(&ServerConfig::default())))
+        .service(metrics)
+        .service(ClaimOrderRoute::<InMemoryDatabase>::new());
+    let app = test::init_service(app).await;
+
+    // The claim itself is invalid, but it still counts
+    let res = test::call_service(&app, claim("10.0.0.1", ALICE).to_request()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let res = test::call_service(&app, claim("10.0.0.1", BOB).to_request()).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(res.headers().get(RETRY_AFTER).unwrap(), "60");
+    let res = test::call_service(&app, claim("10.0.0.2", ALICE).to_request()).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    let res = test::call_service(&app, claim("10.0.0.3", BOB).to_request()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+
+    let req = TestRequest::get().uri("/metrics").to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains(r#"tpg_rate_limit_requests_total{group="claim",outcome="limited"}"#));
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use actix_web::{http::StatusCode, test, web, App};
+use serde_json::json;
+use tari_payment_engine::{
+    db_types::OrderId,
+    events::EventProducers,
+    tpe_api::exchange_rate_api::ExchangeRateApi,
+    traits::AccountManagement,
+    InMemoryDatabase,
+    OrderFlowApi,
+};
+use tpg_common::{MicroTari, Secret};
+
+use crate::{
+    config::{ServerConfig, ServerOptions, WooCommerceConfig},
+    helpers::calculate_hmac,
+    integrations::{
+        storefront::Storefronts,
+        woocommerce::{WooCommerceStorefront, WOOCOMMERCE_HMAC_HEADER, WOOCOMMERCE_TOPIC_HEADER},
+    },
+    woocommerce_routes::configure_routes,
+};
+
+const WEBHOOK_SECRET: &str = "woocommerce-webhook-secret";
+
+fn order_json() -> String {
+    json!({
+        "id": 727,
+        "number": "727",
+        "status": "on-hold",
+        "currency": "XTR",
+        "total": "25.00",
+        "customer_id": 12,
+        "customer_note": "",
+        "date_created_gmt": "2024-03-22T16:28:02",
+        "billing": { "email": "alice@example.com" }
+    })
+    .to_string()
+}
+
+/// Sends a request to the webhook endpoint, wired up the same way as in the server, and returns the response status
+async fn post_webhook(db: &InMemoryDatabase, body: &str, signature: Option<&str>) -> StatusCode {
+    let config = WooCommerceConfig {
+        url: "http://127.0.0.1:1".to_string(),
+        consumer_key: "ck".to_string(),
+        consumer_secret: Secret::new("cs".to_string()),
+        webhook_secret: Secret::new(WEBHOOK_SECRET.to_string()),
+        hmac_checks: true,
+        merchant_id: "woo".to_string(),
+    };
+    let mut storefronts = Storefronts::default();
+    storefronts.add(Arc::new(WooCommerceStorefront::new(&config)));
+    let app = App::new()
+        .app_data(web::Data::new(OrderFlowApi::new(db.clone(), EventProducers::default())))
+        .app_data(web::Data::new(ExchangeRateApi::new(db.clone())))
+        .app_data(web::Data::new(storefronts))
+        .app_data(web::Data::new(ServerOptions::from_config(&ServerConfig::default())))
+        .configure(|cfg| configure_routes::<InMemoryDatabase>(cfg, &config));
+    let app = test::init_service(app).await;
+    let mut req = test::TestRequest::post().uri("/woocommerce/webhook/order_created").set_payload(body.to_string());
+    if let Some(signature) = signature {
+        req = req
+            .insert_header((WOOCOMMERCE_HMAC_HEADER, signature))
+            .insert_header((WOOCOMMERCE_TOPIC_HEADER, "order.created"));
+    }
+    match test::try_call_service(&app, req.to_request()).await {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    }
+}
+
+#[actix_web::test]
+async fn woocommerce_ping() {
+    let db = InMemoryDatabase::new();
+    let status = post_webhook(&db, "webhook_id=12", None).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn woocommerce_order_with_invalid_signature() {
+    let db = InMemoryDatabase::new();
+    let signature = calculate_hmac("not-the-secret", order_json().as_bytes());
+    let status = post_webhook(&db, &order_json(), Some(&signature)).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let order = db.fetch_order_by_order_id(&OrderId::from("727".to_string())).await.unwrap();
+    assert!(order.is_none());
+}
+
+#[actix_web::test]
+async fn woocommerce_new_order() {
+    let db = InMemoryDatabase::new();
+    let signature = calculate_hmac(WEBHOOK_SECRET, order_json().as_bytes());
+    let status = post_webhook(&db, &order_json(), Some(&signature)).await;
+    assert_eq!(status, StatusCode::OK);
+    let order = db.fetch_order_by_order_id(&OrderId::from("727".to_string())).await.unwrap().unwrap();
+    assert_eq!(order.merchant_id, "woo");
+    assert_eq!(order.customer_id, "12");
+    assert_eq!(order.total_price, MicroTari::from_tari(25));
+    // Repeated deliveries are accepted, but don't create a second order
+    let status = post_webhook(&db, &order_json(), Some(&signature)).await;
+    assert_eq!(status, StatusCode::OK);
+}
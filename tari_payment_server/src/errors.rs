@@ -1,13 +1,19 @@
 use actix_web::{
     error::ResponseError,
-    http::{header::ContentType, StatusCode},
+    http::{
+        header::{ContentType, RETRY_AFTER},
+        StatusCode,
+    },
     HttpResponse,
 };
 use log::error;
-use tari_payment_engine::traits::{AccountApiError, AuthApiError, PaymentGatewayError};
+use tari_payment_engine::{
+    tpe_api::{export_objects::ExportError, reconciliation_objects::ReconciliationError},
+    traits::{AccountApiError, AuditLogError, AuthApiError, OutboxError, PaymentGatewayError, WebhookError},
+};
 use thiserror::Error;
 
-use crate::integrations::shopify::OrderConversionError;
+use crate::integrations::storefront::OrderConversionError;
 
 #[derive(Debug, Error)]
 pub enum ServerError {
@@ -45,6 +51,8 @@ pub enum ServerError {
     CannotCompleteRequest(String),
     #[error("This endpoint is not supported on this configuration. {0}")]
     UnsupportedAction(String),
+    #[error("Too many requests. Try again in {0} seconds.")]
+    TooManyRequests(u64),
 }
 
 impl ResponseError for ServerError {
@@ -61,6 +69,7 @@ impl ResponseError for ServerError {
                 AuthError::PoorlyFormattedToken(_) => StatusCode::BAD_REQUEST,
                 AuthError::AccountNotFound => StatusCode::FORBIDDEN,
                 AuthError::ForbiddenPeer => StatusCode::FORBIDDEN,
+                AuthError::InvalidRefreshToken => StatusCode::UNAUTHORIZED,
             },
             Self::InitializeError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::BackendError(_) => StatusCode::INTERNAL_SERVER_ERROR,
@@ -74,13 +83,17 @@ impl ResponseError for ServerError {
             Self::InsufficientPermissions(_) => StatusCode::FORBIDDEN,
             Self::UnauthorizedWalletRequest => StatusCode::UNAUTHORIZED,
             Self::UnsupportedAction(_) => StatusCode::NOT_IMPLEMENTED,
+            Self::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
         }
     }
 
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code())
-            .insert_header(ContentType::json())
-            .body(serde_json::json!({ "error": self.to_string() }).to_string())
+        let mut response = HttpResponse::build(self.status_code());
+        response.insert_header(ContentType::json());
+        if let Self::TooManyRequests(seconds) = self {
+            response.insert_header((RETRY_AFTER, seconds.to_string()));
+        }
+        response.body(serde_json::json!({ "error": self.to_string() }).to_string())
     }
 }
 
@@ -98,6 +111,8 @@ pub enum AuthError {
     AccountNotFound,
     #[error("Request was made from a forbidden peer")]
     ForbiddenPeer,
+    #[error("Refresh token is invalid, has expired or has been revoked. Please log in again.")]
+    InvalidRefreshToken,
 }
 
 impl From<AuthApiError> for ServerError {
@@ -109,9 +124,10 @@ impl From<AuthApiError> for ServerError {
                 Self::AuthenticationError(AuthError::InsufficientPermissions(e.to_string()))
             },
             AuthApiError::DatabaseError(e) => Self::BackendError(format!("Database error: {e}")),
-            AuthApiError::RoleNotFound => {
-                Self::BackendError(format!("Role definitions in Database and Code have diverged. {e}"))
-            },
+            AuthApiError::RoleNotFound => Self::NoRecordFound(e.to_string()),
+            AuthApiError::BuiltInRole(_) => Self::CannotCompleteRequest(e.to_string()),
+            AuthApiError::SessionNotFound(_) => Self::NoRecordFound(e.to_string()),
+            AuthApiError::InvalidRefreshToken => Self::AuthenticationError(AuthError::InvalidRefreshToken),
         }
     }
 }
@@ -123,10 +139,63 @@ impl From<PaymentGatewayError> for ServerError {
             AccountError(AccountApiError::InsufficientFunds) => ServerError::CannotCompleteRequest(e.to_string()),
             OrderModificationNoOp => ServerError::CannotCompleteRequest(e.to_string()),
             OrderModificationForbidden => ServerError::CannotCompleteRequest(e.to_string()),
-            AccountShouldExistForOrder(_) | OrderNotFound(_) => ServerError::NoRecordFound(e.to_string()),
+            AccountShouldExistForOrder(_) |
+            OrderNotFound(_) |
+            RefundNotFound(_) |
+            OrderNotHeld(_) |
+            ApprovalRequestNotFound(_) => ServerError::NoRecordFound(e.to_string()),
+            InvalidRefund(_) | InvalidApproval(_) | QuoteExpired(_) => {
+                ServerError::CannotCompleteRequest(e.to_string())
+            },
             UnsupportedAction(_) => ServerError::CannotCompleteRequest(e.to_string()),
             InvalidSignature => ServerError::AuthenticationError(AuthError::ValidationError(e.to_string())),
             _ => ServerError::BackendError(e.to_string()),
         }
     }
 }
+
+impl From<OutboxError> for ServerError {
+    fn from(e: OutboxError) -> Self {
+        match e {
+            OutboxError::EventNotFound(_) => ServerError::NoRecordFound(e.to_string()),
+            _ => ServerError::BackendError(e.to_string()),
+        }
+    }
+}
+
+impl From<AuditLogError> for ServerError {
+    fn from(e: AuditLogError) -> Self {
+        ServerError::BackendError(e.to_string())
+    }
+}
+
+impl From<WebhookError> for ServerError {
+    fn from(e: WebhookError) -> Self {
+        match e {
+            WebhookError::SubscriptionNotFound(_) | WebhookError::DeliveryNotFound(_) => {
+                ServerError::NoRecordFound(e.to_string())
+            },
+            WebhookError::InvalidSubscription(_) => ServerError::InvalidRequestBody(e.to_string()),
+            _ => ServerError::BackendError(e.to_string()),
+        }
+    }
+}
+
+impl From<ReconciliationError> for ServerError {
+    fn from(e: ReconciliationError) -> Self {
+        match e {
+            ReconciliationError::InvalidExport(_) => ServerError::InvalidRequestBody(e.to_string()),
+            ReconciliationError::SourceUnavailable(_) => ServerError::CannotCompleteRequest(e.to_string()),
+            ReconciliationError::DatabaseError(_) => ServerError::BackendError(e.to_string()),
+        }
+    }
+}
+
+impl From<ExportError> for ServerError {
+    fn from(e: ExportError) -> Self {
+        match e {
+            ExportError::InvalidPeriod(_) => ServerError::InvalidRequestBody(e.to_string()),
+            ExportError::DatabaseError(_) | ExportError::RenderError(_) => ServerError::BackendError(e.to_string()),
+        }
+    }
+}
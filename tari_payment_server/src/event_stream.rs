@@ -0,0 +1,312 @@
+//! Live event stream
+//!
+//! The [`EventStream`] republishes order and payment events to clients connected to the `/api/events` endpoint as
+//! [Server-Sent Events](https://html.spec.whatwg.org/multipage/server-sent-events.html). It is fed from the engine's
+//! [`EventHooks`], so clients see events as soon as the hooks fire.
+//!
+//! Only the events that are of interest to a wallet owner are streamed: `OrderClaimed`, `PaymentReceived`,
+//! `Confirmation`, `OrderPaid` and `PaymentReverted`. Each SSE message carries the event type in the `event` field and
+//! the JSON-encoded event in the `data` field.
+//!
+//! Regular users only receive events for their own address (see [`EventStreamFilter`]). Admins with the `ReadAll`
+//! role receive every event. Merchant-scoped tokens only receive the events of their merchant's orders and payments.
+//!
+//! The stream is best-effort. A client that falls too far behind skips the events it missed, and events that fire
+//! while a client is disconnected are not replayed. Use the webhooks for guaranteed delivery.
+use std::{collections::HashSet, time::Duration};
+
+use bytes::Bytes;
+use futures::{stream, Stream};
+use log::*;
+use tari_common_types::tari_address::TariAddress;
+use tari_payment_engine::events::{EventHooks, EventType};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// The number of events buffered for each client before slow clients start missing events.
+pub const EVENT_STREAM_CAPACITY: usize = 256;
+/// How often a comment line is sent to idle clients, so that proxies do not drop the connection.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Clone)]
+pub struct EventStream {
+    sender: broadcast::Sender<EventType>,
+}
+
+impl Default for EventStream {
+    fn default() -> Self {
+        Self::new(EVENT_STREAM_CAPACITY)
+    }
+}
+
+impl EventStream {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Creates the event hooks that feed this stream. Pass these to [`tari_payment_engine::events::EventHandlers`],
+    /// and the resulting producers to the order flow API.
+    pub fn hooks(&self) -> EventHooks {
+        let mut hooks = EventHooks::default();
+        let sender = self.sender.clone();
+        hooks.on_order_claimed(move |ev| {
+            sender.send(EventType::OrderClaimed(ev)).ok();
+            Box::pin(async {})
+        });
+        let sender = self.sender.clone();
+        hooks.on_payment_received(move |ev| {
+            sender.send(EventType::PaymentReceived(ev)).ok();
+            Box::pin(async {})
+        });
+        let sender = self.sender.clone();
+        hooks.on_payment_confirmed(move |ev| {
+            sender.send(EventType::Confirmation(ev)).ok();
+            Box::pin(async {})
+        });
+        let sender = self.sender.clone();
+        hooks.on_order_paid(move |ev| {
+            sender.send(EventType::OrderPaid(ev)).ok();
+            Box::pin(async {})
+        });
+        let sender = self.sender.clone();
+        hooks.on_payment_reverted(move |ev| {
+            sender.send(EventType::PaymentReverted(ev)).ok();
+            Box::pin(async {})
+        });
+        hooks
+    }
+
+    /// Subscribes to the stream. The returned stream yields SSE-encoded messages for every event that passes the
+    /// filter, interleaved with keep-alive comments. It ends when the server shuts down.
+    pub fn subscribe(&self, filter: EventStreamFilter) -> impl Stream<Item = Result<Bytes, actix_web::Error>> {
+        let receiver = self.sender.subscribe();
+        let mut keep_alive = tokio::time::interval(KEEP_ALIVE_INTERVAL);
+        keep_alive.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        stream::unfold((receiver, filter, keep_alive), |(mut receiver, mut filter, mut keep_alive)| async move {
+            loop {
+                let event = tokio::select! {
+                    _ = keep_alive.tick() => {
+                        return Some((Ok(Bytes::from_static(b": keep-alive\n\n")), (receiver, filter, keep_alive)));
+                    },
+                    event = receiver.recv() => event,
+                };
+                match event {
+                    Ok(event) if filter.matches(&event) => match sse_message(&event) {
+                        Ok(msg) => return Some((Ok(msg), (receiver, filter, keep_alive))),
+                        Err(e) => error!("📡️ Could not serialize {} event for the event stream. {e}", event.name()),
+                    },
+                    Ok(_) => {},
+                    Err(RecvError::Lagged(n)) => warn!("📡️ Event stream client fell behind and missed {n} events"),
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
+
+/// Decides which events a subscriber may see.
+///
+/// Payment events are matched on the sender's address, and claim events on the claimant's. Orders don't carry an
+/// address, so `OrderPaid` events are matched on the customer ids the address is linked to. The filter starts with
+/// the customer ids of the address's existing orders, and learns new ones from the `OrderClaimed` events it sees.
+///
+/// If the filter is restricted to a merchant, only the events of that merchant's orders and payments get through.
+#[derive(Debug, Clone)]
+pub struct EventStreamFilter {
+    address: Option<TariAddress>,
+    customer_ids: HashSet<String>,
+    merchant: Option<String>,
+}
+
+impl EventStreamFilter {
+    /// A filter that lets every event through
+    pub fn all() -> Self {
+        Self { address: None, customer_ids: HashSet::new(), merchant: None }
+    }
+
+    pub fn for_address<I: IntoIterator<Item = String>>(address: TariAddress, customer_ids: I) -> Self {
+        Self { address: Some(address), customer_ids: customer_ids.into_iter().collect(), merchant: None }
+    }
+
+    /// Restricts the filter to the events of `merchant`'s orders and payments
+    pub fn for_merchant<S: Into<String>>(mut self, merchant: S) -> Self {
+        self.merchant = Some(merchant.into());
+        self
+    }
+
+    pub fn matches(&mut self, event: &EventType) -> bool {
+        if let Some(merchant) = &self.merchant {
+            if merchant_of(event) != Some(merchant.as_str()) {
+                return false;
+            }
+        }
+        let Some(address) = &self.address else {
+            return is_streamed(event);
+        };
+        match event {
+            EventType::OrderClaimed(ev) if ev.claimant.as_address() == address => {
+                self.customer_ids.insert(ev.order.customer_id.clone());
+                true
+            },
+            EventType::PaymentReceived(ev) | EventType::Confirmation(ev) => ev.payment.sender.as_address() == address,
+            EventType::PaymentReverted(ev) => ev.payment.sender.as_address() == address,
+            EventType::OrderPaid(ev) => self.customer_ids.contains(&ev.order.customer_id),
+            _ => false,
+        }
+    }
+}
+
+/// The merchant that a streamed event belongs to
+fn merchant_of(event: &EventType) -> Option<&str> {
+    match event {
+        EventType::OrderClaimed(ev) => Some(ev.order.merchant_id.as_str()),
+        EventType::PaymentReceived(ev) | EventType::Confirmation(ev) => Some(ev.payment.merchant_id.as_str()),
+        EventType::PaymentReverted(ev) => Some(ev.payment.merchant_id.as_str()),
+        EventType::OrderPaid(ev) => Some(ev.order.merchant_id.as_str()),
+        _ => None,
+    }
+}
+
+fn is_streamed(event: &EventType) -> bool {
+    matches!(
+        event,
+        EventType::OrderClaimed(_) |
+            EventType::PaymentReceived(_) |
+            EventType::Confirmation(_) |
+            EventType::OrderPaid(_) |
+            EventType::PaymentReverted(_)
+    )
+}
+
+fn sse_message(event: &EventType) -> Result<Bytes, serde_json::Error> {
+    let data = match event {
+        EventType::NewOrder(ev) | EventType::OrderPaid(ev) => serde_json::to_string(ev),
+        EventType::OrderAnnulled(ev) => serde_json::to_string(ev),
+        EventType::OrderModified(ev) => serde_json::to_string(ev),
+        EventType::OrderClaimed(ev) => serde_json::to_string(ev),
+        EventType::PaymentReceived(ev) | EventType::Confirmation(ev) => serde_json::to_string(ev),
+        EventType::RefundApproved(ev) | EventType::RefundSent(ev) => serde_json::to_string(ev),
+        EventType::OrderHeld(ev) => serde_json::to_string(ev),
+        EventType::PaymentReverted(ev) => serde_json::to_string(ev),
+        EventType::ApprovalRequested(ev) | EventType::OperationApproved(ev) | EventType::OperationRejected(ev) => {
+            serde_json::to_string(ev)
+        },
+    }?;
+    Ok(Bytes::from(format!("event: {}\ndata: {data}\n\n", event.name())))
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use chrono::Utc;
+    use tari_payment_engine::{
+        db_types::{Order, OrderId, OrderStatusType, Payment, PaymentType, TransferStatus, DEFAULT_MERCHANT},
+        events::{OrderClaimedEvent, OrderEvent, PaymentEvent},
+    };
+    use tpg_common::MicroTari;
+
+    use super::*;
+
+    const ALICE: &str = "14s9vDTwrweZvWEgQ9gNhXXPX68DPXSSAHNFWYEPi5JsBQY";
+    const BOB: &str = "14AYt2hhhn4VydAXNJ6i7ZfRNZGoGSp713dHjMYCoK5hYw2";
+
+    fn address(s: &str) -> TariAddress {
+        TariAddress::from_str(s).unwrap()
+    }
+
+    fn order(customer_id: &str) -> Order {
+        Order {
+            id: 1,
+            order_id: OrderId(format!("order-{customer_id}")),
+            alt_id: None,
+            customer_id: customer_id.to_string(),
+            memo: None,
+            total_price: MicroTari::from_tari(100),
+            original_price: None,
+            currency: "XTR".to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            status: OrderStatusType::Paid,
+            rate_id: None,
+            quote_expires_at: None,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
+        }
+    }
+
+    fn payment(sender: &str) -> PaymentEvent {
+        PaymentEvent::new(Payment {
+            txid: "tx1".to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            sender: address(sender).into(),
+            amount: MicroTari::from_tari(100),
+            memo: None,
+            payment_type: PaymentType::OnChain,
+            status: TransferStatus::Received,
+            order_id: None,
+            block_height: None,
+            confirmations: 0,
+            merchant_id: DEFAULT_MERCHANT.to_string(),
+        })
+    }
+
+    #[test]
+    fn users_only_see_their_own_events() {
+        let mut filter = EventStreamFilter::for_address(address(ALICE), vec!["alice".to_string()]);
+        assert!(filter.matches(&EventType::PaymentReceived(payment(ALICE))));
+        assert!(filter.matches(&EventType::Confirmation(payment(ALICE))));
+        assert!(!filter.matches(&EventType::PaymentReceived(payment(BOB))));
+        assert!(filter.matches(&EventType::OrderPaid(OrderEvent::new(order("alice")))));
+        assert!(!filter.matches(&EventType::OrderPaid(OrderEvent::new(order("bob")))));
+        assert!(!filter.matches(&EventType::NewOrder(OrderEvent::new(order("alice")))));
+    }
+
+    #[test]
+    fn claimed_orders_are_followed_until_paid() {
+        let mut filter = EventStreamFilter::for_address(address(ALICE), Vec::new());
+        let paid = EventType::OrderPaid(OrderEvent::new(order("alice")));
+        assert!(!filter.matches(&paid));
+        assert!(!filter.matches(&EventType::OrderClaimed(OrderClaimedEvent::new(order("alice"), address(BOB)))));
+        assert!(!filter.matches(&paid));
+        assert!(filter.matches(&EventType::OrderClaimed(OrderClaimedEvent::new(order("alice"), address(ALICE)))));
+        assert!(filter.matches(&paid));
+    }
+
+    #[test]
+    fn admins_see_everything_that_is_streamed() {
+        let mut filter = EventStreamFilter::all();
+        assert!(filter.matches(&EventType::PaymentReceived(payment(BOB))));
+        assert!(filter.matches(&EventType::OrderPaid(OrderEvent::new(order("bob")))));
+        assert!(!filter.matches(&EventType::NewOrder(OrderEvent::new(order("bob")))));
+    }
+
+    #[test]
+    fn merchant_tokens_only_see_their_merchant() {
+        let acme_order = || {
+            let mut order = order("bob");
+            order.merchant_id = "acme".to_string();
+            OrderEvent::new(order)
+        };
+        let mut acme_payment = payment(BOB);
+        acme_payment.payment.merchant_id = "acme".to_string();
+        let mut filter = EventStreamFilter::all().for_merchant("acme");
+        assert!(filter.matches(&EventType::OrderPaid(acme_order())));
+        assert!(filter.matches(&EventType::PaymentReceived(acme_payment)));
+        assert!(!filter.matches(&EventType::OrderPaid(OrderEvent::new(order("bob")))));
+        assert!(!filter.matches(&EventType::PaymentReceived(payment(BOB))));
+        assert!(!filter.matches(&EventType::NewOrder(acme_order())));
+
+        let mut filter = EventStreamFilter::for_address(address(BOB), vec!["bob".to_string()]).for_merchant("acme");
+        assert!(filter.matches(&EventType::OrderPaid(acme_order())));
+        assert!(!filter.matches(&EventType::OrderPaid(OrderEvent::new(order("bob")))));
+    }
+
+    #[test]
+    fn events_are_encoded_as_sse_messages() {
+        let msg = sse_message(&EventType::OrderPaid(OrderEvent::new(order("alice")))).unwrap();
+        let msg = String::from_utf8(msg.to_vec()).unwrap();
+        assert!(msg.starts_with("event: OrderPaid\ndata: {\"order\":"));
+        assert!(msg.ends_with("}\n\n"));
+    }
+}
@@ -1,16 +1,31 @@
 use chrono::Duration;
 use log::*;
-use tari_payment_engine::{db_types::Order, events::EventProducers, OrderFlowApi, SqliteDatabase};
+use tari_payment_engine::{
+    db_types::Order,
+    events::EventProducers,
+    tpe_api::exchange_objects::QuotePolicy,
+    traits::{ExchangeRates, PaymentGatewayDatabase, RateLimitStore},
+    OrderFlowApi,
+};
 use tokio::task::JoinHandle;
 
 /// Starts the expiry worker. Do not await the returned JoinHandle, as it will run indefinitely.
-pub fn start_expiry_worker(
-    db: SqliteDatabase,
+///
+/// Besides expiring old orders, the worker expires approval requests that nobody decided on in time, forgets the rate
+/// limit buckets in the database that have refilled, and applies the `rate_quotes` policy to orders whose exchange
+/// rate quote has expired.
+///
+/// The futures returned by the database traits are not guaranteed to be `Send`, so the worker is spawned onto the
+/// current (actix) thread's local task set rather than the multithreaded tokio executor.
+pub fn start_expiry_worker<B: PaymentGatewayDatabase + ExchangeRates + RateLimitStore + 'static>(
+    db: B,
     producers: EventProducers,
     unclaimed_expiry: Duration,
     unpaid_expiry: Duration,
+    rate_quotes: Option<QuotePolicy>,
+    strict_mode: bool,
 ) -> JoinHandle<()> {
-    tokio::spawn(async move {
+    actix_web::rt::spawn(async move {
         let mut timer = tokio::time::interval(std::time::Duration::from_secs(60));
         let api = OrderFlowApi::new(db, producers);
         info!("🕰️ Unclaimed order expiry worker started");
@@ -31,6 +46,25 @@ pub fn start_expiry_worker(
                     error!("🕰️ Error running unclaimed order expiry job: {e}");
                 },
             }
+            match api.expire_approval_requests().await {
+                Ok(expired) if expired.is_empty() => {},
+                Ok(expired) => info!("🕰️ {} approval requests expired", expired.len()),
+                Err(e) => error!("🕰️ Error expiring approval requests: {e}"),
+            }
+            match api.db().prune_rate_limits().await {
+                Ok(0) => {},
+                Ok(count) => debug!("🕰️ {count} idle rate limit buckets removed"),
+                Err(e) => error!("🕰️ Error removing idle rate limit buckets: {e}"),
+            }
+            let Some(policy) = &rate_quotes else { continue };
+            match api.process_expired_quotes(policy, strict_mode).await {
+                Ok(orders) if orders.is_empty() => {},
+                Ok(orders) => {
+                    info!("🕰️ {} orders with expired quotes were updated ({})", orders.len(), policy.on_expiry);
+                    debug!("🕰️ Orders with expired quotes: {}", order_list(&orders));
+                },
+                Err(e) => error!("🕰️ Error processing orders with expired quotes: {e}"),
+            }
         }
     })
 }
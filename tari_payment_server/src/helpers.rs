@@ -1,6 +1,7 @@
 use std::{net::IpAddr, str::FromStr};
 
-use actix_web::HttpRequest;
+use actix_http::h1;
+use actix_web::{dev::Payload, web, HttpRequest};
 use base64::encode;
 use hmac::{Hmac, Mac};
 use log::{debug, trace};
@@ -53,6 +54,14 @@ pub fn get_remote_ip(req: &HttpRequest, use_x_forwarded_for: bool, use_forwarded
     })
 }
 
+/// Turns a request body that has already been read back into a payload, so that middleware can inspect the body and
+/// still pass it on to the route.
+pub fn bytes_to_payload(buf: web::Bytes) -> Payload {
+    let (_, mut pl) = h1::Payload::create(true);
+    pl.unread_data(buf);
+    Payload::from(pl)
+}
+
 pub fn calculate_hmac(secret: &str, data: &[u8]) -> String {
     let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
     mac.update(data);
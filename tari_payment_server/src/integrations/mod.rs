@@ -1 +1,6 @@
+pub mod rate_sources;
 pub mod shopify;
+pub mod storefront;
+pub mod wallet_grpc;
+pub mod webhooks;
+pub mod woocommerce;
@@ -0,0 +1,184 @@
+//! Exchange rate feeds
+//!
+//! An [`ExchangeRateSource`] provides the current price of Tari in another currency, usually by polling a price API.
+//! The rate feed worker ([`crate::rate_feed_worker`]) polls every configured source, combines the results with
+//! [`aggregate_rates`] and stores the result as the new exchange rate.
+//!
+//! [`HttpRateSource`] covers the common case of a JSON API that returns the price of 1 XTR in the quoted currency
+//! (e.g. `{"tari": {"usd": 0.0051}}`). The URL and the [JSON pointer](https://datatracker.ietf.org/doc/html/rfc6901)
+//! to the price are configurable, and `{currency}` in either is replaced with the lowercase currency code.
+use futures::{future::LocalBoxFuture, FutureExt};
+use reqwest::Client;
+use serde_json::Value;
+use thiserror::Error;
+use tpg_common::MicroTari;
+
+const SOURCE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+const MICRO_TARI_PER_TARI: f64 = 1_000_000.0;
+
+#[derive(Debug, Clone, Error)]
+pub enum RateSourceError {
+    #[error("Could not reach the rate source. {0}")]
+    RequestFailed(String),
+    #[error("The rate source returned an invalid response. {0}")]
+    InvalidResponse(String),
+}
+
+/// A source of Tari prices.
+///
+/// The futures are not `Send`, for the same reason as the database futures, so sources are polled on the local task
+/// set.
+pub trait ExchangeRateSource {
+    /// A short name for the source, used in the logs
+    fn name(&self) -> &str;
+    /// Fetches the current price of one unit of `currency`, in Tari.
+    fn fetch_rate<'a>(&'a self, currency: &'a str) -> LocalBoxFuture<'a, Result<MicroTari, RateSourceError>>;
+}
+
+/// A rate source that reads the price of 1 XTR in the given currency from a JSON API.
+#[derive(Debug, Clone)]
+pub struct HttpRateSource {
+    name: String,
+    url: String,
+    pointer: String,
+    client: Client,
+}
+
+impl HttpRateSource {
+    pub fn new(name: &str, url: &str, pointer: &str) -> Self {
+        let client = Client::builder().timeout(SOURCE_TIMEOUT).build().unwrap_or_default();
+        Self { name: name.to_string(), url: url.to_string(), pointer: pointer.to_string(), client }
+    }
+
+    async fn fetch_price(&self, currency: &str) -> Result<f64, RateSourceError> {
+        let currency = currency.to_lowercase();
+        let url = self.url.replace("{currency}", &currency);
+        let pointer = self.pointer.replace("{currency}", &currency);
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| RateSourceError::RequestFailed(e.to_string()))?;
+        let body = response.json::<Value>().await.map_err(|e| RateSourceError::InvalidResponse(e.to_string()))?;
+        let price = match body.pointer(&pointer) {
+            Some(Value::Number(n)) => n.as_f64(),
+            // Several exchanges return prices as strings, to avoid rounding errors
+            Some(Value::String(s)) => s.parse::<f64>().ok(),
+            _ => None,
+        };
+        price.ok_or_else(|| RateSourceError::InvalidResponse(format!("No price found at {pointer}")))
+    }
+}
+
+impl ExchangeRateSource for HttpRateSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn fetch_rate<'a>(&'a self, currency: &'a str) -> LocalBoxFuture<'a, Result<MicroTari, RateSourceError>> {
+        async move {
+            let price = self.fetch_price(currency).await?;
+            if !price.is_finite() || price <= 0.0 {
+                return Err(RateSourceError::InvalidResponse(format!("{price} is not a valid price")));
+            }
+            // The source quotes the price of 1 XTR, but exchange rates are stored as the price of 1 unit of the
+            // currency
+            #[allow(clippy::cast_possible_truncation)]
+            let rate = (MICRO_TARI_PER_TARI / price).round() as i64;
+            Ok(MicroTari::from(rate))
+        }
+        .boxed_local()
+    }
+}
+
+/// Combines the rates reported by several sources into a single rate.
+///
+/// Rates that differ from the median by more than `max_deviation_pct` percent are discarded as outliers, and the
+/// median of the remaining rates is returned. Returns `None` if fewer than `min_sources` rates are left.
+pub fn aggregate_rates(rates: &[MicroTari], max_deviation_pct: i64, min_sources: usize) -> Option<MicroTari> {
+    let values = rates.iter().map(|r| r.value()).collect::<Vec<i64>>();
+    let median = median(values.clone())?;
+    let accepted = values
+        .into_iter()
+        .filter(|v| i128::from((v - median).abs()) * 100 <= i128::from(median) * i128::from(max_deviation_pct))
+        .collect::<Vec<i64>>();
+    if accepted.len() < min_sources.max(1) {
+        return None;
+    }
+    median(accepted).map(MicroTari::from)
+}
+
+fn median(mut values: Vec<i64>) -> Option<i64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_unstable();
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        Some(values[mid - 1] + (values[mid] - values[mid - 1]) / 2)
+    } else {
+        Some(values[mid])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use serde_json::json;
+
+    use super::*;
+
+    fn rates(values: &[i64]) -> Vec<MicroTari> {
+        values.iter().copied().map(MicroTari::from).collect()
+    }
+
+    #[test]
+    fn median_of_rates() {
+        assert_eq!(aggregate_rates(&rates(&[300, 100, 200]), 100, 1), Some(MicroTari::from(200)));
+        assert_eq!(aggregate_rates(&rates(&[100, 200, 300, 400]), 100, 1), Some(MicroTari::from(250)));
+        assert_eq!(aggregate_rates(&[], 10, 1), None);
+    }
+
+    #[test]
+    fn outliers_are_rejected() {
+        // 500 is 150% away from the median and is discarded
+        let result = aggregate_rates(&rates(&[190, 200, 210, 500]), 10, 1);
+        assert_eq!(result, Some(MicroTari::from(200)));
+        // Once the outliers are gone, there aren't enough sources left
+        assert_eq!(aggregate_rates(&rates(&[100, 200, 400]), 10, 2), None);
+        assert_eq!(aggregate_rates(&rates(&[195, 200, 400]), 10, 2), Some(MicroTari::from(197)));
+    }
+
+    fn stub_server() -> String {
+        let server = HttpServer::new(|| {
+            App::new()
+                .route(
+                    "/simple/{currency}",
+                    web::get().to(|| async { HttpResponse::Ok().json(json!({"tari": {"usd": 0.005}})) }),
+                )
+                .route("/ticker", web::get().to(|| async { HttpResponse::Ok().json(json!({"price": "0.004"})) }))
+                .route("/broken", web::get().to(|| async { HttpResponse::InternalServerError().finish() }))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .expect("Could not bind the stub server");
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        format!("http://{addr}")
+    }
+
+    #[actix_web::test]
+    async fn http_sources() {
+        let url = stub_server();
+        let source = HttpRateSource::new("simple", &format!("{url}/simple/{{currency}}"), "/tari/{currency}");
+        assert_eq!(source.fetch_rate("USD").await.unwrap(), MicroTari::from_tari(200));
+        let source = HttpRateSource::new("ticker", &format!("{url}/ticker"), "/price");
+        assert_eq!(source.fetch_rate("USD").await.unwrap(), MicroTari::from_tari(250));
+        let source = HttpRateSource::new("missing", &format!("{url}/ticker"), "/last");
+        assert!(matches!(source.fetch_rate("USD").await, Err(RateSourceError::InvalidResponse(_))));
+        let source = HttpRateSource::new("broken", &format!("{url}/broken"), "/price");
+        assert!(matches!(source.fetch_rate("USD").await, Err(RateSourceError::RequestFailed(_))));
+    }
+}
@@ -1,160 +1,168 @@
+//! Shopify storefront integration
+//!
+//! Shopify sends new orders to the `/shopify/webhook/checkout_create` endpoint, and each order is sent back to the
+//! store it came from through the Shopify API once it has been paid or annulled. See [`ShopifyStorefront`].
+use std::any::Any;
+
 use chrono::{DateTime, Utc};
-use futures::future::BoxFuture;
+use futures::{future::LocalBoxFuture, FutureExt};
 use log::*;
-use shopify_tools::{
-    helpers::parse_shopify_price,
-    ShopifyApi,
-    ShopifyApiError,
-    ShopifyConfig as ShopifyApiConfig,
-    ShopifyOrder,
-};
+use shopify_tools::{ExchangeRate as ShopifyExchangeRate, ShopifyApi, ShopifyApiError, ShopifyOrder};
 use tari_payment_engine::{
-    db_types::{NewOrder, Order, OrderId},
-    events::{EventHandlers, EventHooks, OrderAnnulledEvent},
-    helpers::MemoSignatureError,
-    tpe_api::{exchange_objects::ExchangeRate, exchange_rate_api::ExchangeRateApi},
-    traits::ExchangeRates,
+    db_types::{NewOrder, Order, OrderId, DEFAULT_MERCHANT},
+    tpe_api::exchange_objects::ExchangeRate,
 };
-use thiserror::Error;
-use tpg_common::TARI_CURRENCY_CODE;
-
-#[derive(Debug, Error)]
-#[error("Could not convert shopify order into a new order. {0}.")]
-pub enum OrderConversionError {
-    #[error("The Shopify order contained invalid data. {0}")]
-    FormatError(String),
-    #[error("{0} is not a supported currency.")]
-    UnsupportedCurrency(String),
-    #[error("The memo signature was invalid. {0}")]
-    InvalidMemoSignature(#[from] MemoSignatureError),
-}
+use tpg_common::MicroTari;
+
+use crate::{
+    config::ShopifyConfig,
+    integrations::storefront::{OrderConversionError, StorefrontError, StorefrontIntegration},
+};
+
+pub const SHOPIFY_PLATFORM: &str = "shopify";
+/// The header that Shopify uses to identify the store that sent a webhook.
+pub const SHOP_DOMAIN_HEADER: &str = "X-Shopify-Shop-Domain";
 
-pub async fn new_order_from_shopify_order<B: ExchangeRates>(
-    value: ShopifyOrder,
-    fx: &ExchangeRateApi<B>,
-) -> Result<NewOrder, OrderConversionError> {
+/// Converts a Shopify order into a new order, without pricing it. The total price is zero until the order is priced
+/// with [`crate::integrations::storefront::price_new_order`].
+pub fn unpriced_order_from_shopify_order(value: ShopifyOrder) -> Result<NewOrder, OrderConversionError> {
     trace!("Converting ShopifyOrder to NewOrder: {:?}", value);
-    let currency = value.currency.as_str().to_uppercase();
-    let rate = if currency == TARI_CURRENCY_CODE {
-        ExchangeRate::default()
-    } else {
-        let rate = fx
-            .fetch_last_rate(&currency)
-            .await
-            .map_err(|e| OrderConversionError::UnsupportedCurrency(e.to_string()))?;
-        info!("Shopify order is not in Tari. Using a conversion rate of {rate}");
-        rate
-    };
-    // Net price in cents.
-    let total_price =
-        parse_shopify_price(&value.total_price).map_err(|e| OrderConversionError::FormatError(e.to_string()))?;
-    let total_price = rate.convert_to_tari_from_cents(total_price);
     let timestamp =
         value.created_at.parse::<DateTime<Utc>>().map_err(|e| OrderConversionError::FormatError(e.to_string()))?;
-    let memo = value.note;
-    let mut order = NewOrder {
+    Ok(NewOrder {
         order_id: OrderId::from(value.id),
         alt_order_id: Some(OrderId::from(value.name)),
         customer_id: value.customer.id.to_string(),
         currency: value.currency,
         original_price: Some(value.total_price),
-        memo,
+        memo: value.note,
         address: None,
         created_at: timestamp,
-        total_price,
-    };
-    if let Err(e) = order.try_extract_address() {
+        total_price: MicroTari::from(0),
+        rate_id: None,
+        quote_expires_at: None,
+        merchant_id: DEFAULT_MERCHANT.to_string(),
+    })
+}
+
+/// A merchant's Shopify store.
+///
+/// Paid orders are marked as paid on the store with a manual payment transaction, and annulled orders are cancelled.
+/// If an order is expired from the Shopify Admin UI, then the cancellation is spurious, but no harm is done.
+#[derive(Clone)]
+pub struct ShopifyStorefront {
+    merchant_id: String,
+    shop: String,
+    api: ShopifyApi,
+}
+
+impl ShopifyStorefront {
+    pub fn new(merchant_id: &str, config: &ShopifyConfig) -> Result<Self, ShopifyApiError> {
+        let api = ShopifyApi::new(config.shopify_api_config())?;
+        Ok(Self { merchant_id: merchant_id.to_string(), shop: config.shop.to_lowercase(), api })
+    }
+
+    pub fn api(&self) -> &ShopifyApi {
+        &self.api
+    }
+
+    fn convert_order(&self, order: ShopifyOrder) -> Result<NewOrder, OrderConversionError> {
+        unpriced_order_from_shopify_order(order).map(|o| o.with_merchant(self.merchant_id.as_str()))
+    }
+
+    async fn mark_paid(&self, order: &Order) -> Result<(), StorefrontError> {
+        let order_id = parse_shopify_order_id(order)?;
+        let Some(original_price) = order.original_price.clone() else {
+            // TODO: Calculate the original price from the prevailing Tari price.
+            return Err(StorefrontError::Undeliverable(format!(
+                "Order {order_id} does not have an original price. Shopify orders should have populated this field."
+            )));
+        };
+        let tx = api_call(self.api.mark_order_as_paid(order_id, original_price, order.currency.clone()).await)?;
+        info!(
+            "🛍️ Order {order_id} marked as paid on Shopify. New status: {}. Tx id: {}. Errors (if any): {} {}",
+            tx.status,
+            tx.id,
+            tx.error_code.unwrap_or_else(|| "None".to_string()),
+            tx.message
+        );
+        Ok(())
+    }
+
+    async fn cancel(&self, order: &Order) -> Result<(), StorefrontError> {
+        let order_id = parse_shopify_order_id(order)?;
+        debug!("🛍️ Order {order_id} has been annulled. Sending cancellation request to Shopify.");
+        let o = api_call(self.api.cancel_order(order_id).await)?;
         info!(
-            "Order {} did not contain a valid signature. This order is going to remain unclaimed. Error: {e}. Memo: {}",
-            order.order_id,
-            order.memo.as_ref().unwrap_or(&"No memo provided".to_string())
+            "🛍️ Order {order_id} has been cancelled on Shopify. Reason: {}. Timestamp: {}",
+            o.cancel_reason.unwrap_or_default(),
+            o.cancelled_at.unwrap_or_default()
         );
+        Ok(())
     }
-    Ok(order)
 }
 
-pub const SHOPIFY_EVENT_BUFFER_SIZE: usize = 25;
+impl StorefrontIntegration for ShopifyStorefront {
+    fn platform(&self) -> &str {
+        SHOPIFY_PLATFORM
+    }
 
-/// Assigns event handlers to the Shopify API.
-///
-/// Only the following events are relevant to interacting with the Shopify API:
-///
-/// 1. OrderPaidEvent - Once an order is marked as paid in the payment engine, we send a REST request to the Shopify API
-///    to mark the order as fulfilled.
-/// 2. OrderAnnulledEvent - If an order is cancelled or expires, we send a REST request to the Shopify API to mark the
-///    order as cancelled. If an order is expired from the Shopify Admin UI, then this REST call will be spurious, but
-///    no harm will be done.
-pub fn create_shopify_event_handlers(config: ShopifyApiConfig) -> Result<EventHandlers, ShopifyApiError> {
-    let mut hooks = EventHooks::default();
-    let api = ShopifyApi::new(config)?;
-    let api_clone = api.clone();
-    // --- On OrderPaid Handler ---
-    hooks.on_order_paid(move |ev| {
-        let order = ev.order;
-        let order_id = match parse_shopify_order_id(&order) {
-            Some(value) => value,
-            None => return no_op(),
-        };
-        let Some(original_price) = order.original_price else {
-            error!(
-                "🛍️ The order that has just been marked as paid does not have an original price. Shopify orders \
-                 should
-            have populated this field. TODO: Calculate the original price from the prevailing Tari price. Order \
-                 details: {order:?}"
-            );
-            return no_op();
-        };
-        let api_clone = api_clone.clone();
-        Box::pin(async move {
-            match api_clone.mark_order_as_paid(order_id, original_price, order.currency).await {
-                Ok(tx) => info!(
-                    "🛍️ Order {order_id} marked as paid on Shopify. New status: {}. Tx id: {}. Errors (if any): {} {}",
-                    tx.status,
-                    tx.id,
-                    tx.error_code.unwrap_or_else(|| "None".to_string()),
-                    tx.message
-                ),
-                Err(e) => error!("🛍️ Error marking order {order_id} as paid on Shopify. {e}"),
-            }
-        })
-    });
-    // --- On OrderAnnulled Handler ---
-    hooks.on_order_annulled(move |ev| {
-        let OrderAnnulledEvent { order, status } = ev;
-        let order_id = match parse_shopify_order_id(&order) {
-            Some(value) => value,
-            None => return no_op(),
-        };
-        let api_clone = api.clone();
-        debug!("🛍️ Order {order_id} has been annulled. Reason: {status}. Sending cancellation request to Shopify.");
-        Box::pin(async move {
-            match api_clone.cancel_order(order_id).await {
-                Ok(o) => info!(
-                    "🛍️ Order {order_id} has been cancelled on Shopify. Reason: {}. Timestamp: {}",
-                    o.cancel_reason.unwrap_or_default(),
-                    o.cancelled_at.unwrap_or_default()
-                ),
-                Err(e) => error!("🛍️ Error marking order {order_id} as paid on Shopify. {e}"),
-            }
-        })
-    });
-    let handlers = EventHandlers::new(SHOPIFY_EVENT_BUFFER_SIZE, hooks);
-    Ok(handlers)
-}
+    fn store_id(&self) -> &str {
+        &self.shop
+    }
+
+    fn merchant_id(&self) -> &str {
+        &self.merchant_id
+    }
+
+    fn parse_order(&self, body: &[u8]) -> Result<NewOrder, OrderConversionError> {
+        let order = serde_json::from_slice::<ShopifyOrder>(body)
+            .map_err(|e| OrderConversionError::FormatError(e.to_string()))?;
+        self.convert_order(order)
+    }
+
+    fn fetch_open_orders(
+        &self,
+    ) -> LocalBoxFuture<'_, Result<Vec<Result<NewOrder, OrderConversionError>>, StorefrontError>> {
+        async move {
+            let orders = api_call(self.api.fetch_all_open_orders(None).await)?;
+            Ok(orders.into_iter().map(|o| self.convert_order(o)).collect())
+        }
+        .boxed_local()
+    }
 
-fn parse_shopify_order_id(order: &Order) -> Option<u64> {
-    match order.order_id.as_str().parse::<u64>() {
-        Ok(v) => Some(v),
-        Err(e) => {
-            error!(
-                "🛍️ Shopify order ids must be integers. An order that has just been marked as paid could not be \
-                 converted into a Shopify Order id. Error: {e}. Order details: {order:?}"
-            );
-            None
-        },
+    fn mark_order_paid<'a>(&'a self, order: &'a Order) -> LocalBoxFuture<'a, Result<(), StorefrontError>> {
+        self.mark_paid(order).boxed_local()
     }
+
+    fn cancel_order<'a>(&'a self, order: &'a Order) -> LocalBoxFuture<'a, Result<(), StorefrontError>> {
+        self.cancel(order).boxed_local()
+    }
+
+    fn update_prices<'a>(&'a self, rate: &'a ExchangeRate) -> LocalBoxFuture<'a, Result<(), StorefrontError>> {
+        async move {
+            let rate = ShopifyExchangeRate::new(rate.base_currency.clone(), rate.rate);
+            let variants = api_call(self.api.update_all_prices(rate).await)?;
+            info!("🛍️ {} variant prices updated on the {} Shopify storefront.", variants.len(), self.merchant_id);
+            Ok(())
+        }
+        .boxed_local()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+fn api_call<T>(result: Result<T, ShopifyApiError>) -> Result<T, StorefrontError> {
+    result.map_err(|e| StorefrontError::RequestFailed(e.to_string()))
 }
 
-fn no_op() -> BoxFuture<'static, ()> {
-    Box::pin(async {})
+fn parse_shopify_order_id(order: &Order) -> Result<u64, StorefrontError> {
+    order.order_id.as_str().parse::<u64>().map_err(|e| {
+        StorefrontError::Undeliverable(format!(
+            "Shopify order ids must be integers. Order {} could not be converted into a Shopify order id. {e}",
+            order.order_id
+        ))
+    })
 }
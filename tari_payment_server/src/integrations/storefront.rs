@@ -0,0 +1,349 @@
+//! Storefront integrations
+//!
+//! A [`StorefrontIntegration`] connects the payment server to one store on a shop platform, such as Shopify or
+//! WooCommerce. It converts the platform's orders into [`NewOrder`]s, fetches the store's open orders, marks orders as
+//! paid or cancelled on the store, and pushes Tari prices to the store.
+//!
+//! The configured stores are collected into a [`Storefronts`] registry when the server starts (see
+//! [`Storefronts::from_config`]). The server only talks to the registry, so adding a new shop platform means
+//! implementing the trait, registering the platform here, and adding its webhook routes in
+//! [`crate::storefront_routes`].
+//!
+//! Like the exchange rate sources, the futures are not `Send`, so storefront calls are made on the local task set.
+use std::{any::Any, sync::Arc};
+
+use futures::{future::LocalBoxFuture, FutureExt};
+use log::*;
+use shopify_tools::helpers::parse_shopify_price;
+use tari_payment_engine::{
+    db_types::{NewOrder, Order, OutboxEvent, DEFAULT_MERCHANT},
+    events::EventType,
+    helpers::MemoSignatureError,
+    tpe_api::{
+        exchange_objects::{ExchangeRate, QuotePolicy, RateCircuitBreaker},
+        exchange_rate_api::ExchangeRateApi,
+    },
+    traits::ExchangeRates,
+};
+use thiserror::Error;
+use tpg_common::TARI_CURRENCY_CODE;
+
+use crate::{
+    config::ServerConfig,
+    errors::ServerError,
+    integrations::{shopify::ShopifyStorefront, woocommerce::WooCommerceStorefront},
+};
+
+pub type Storefront = Arc<dyn StorefrontIntegration + Send + Sync>;
+
+#[derive(Debug, Error)]
+#[error("Could not convert the storefront order into a new order. {0}.")]
+pub enum OrderConversionError {
+    #[error("The storefront order contained invalid data. {0}")]
+    FormatError(String),
+    #[error("{0} is not a supported currency.")]
+    UnsupportedCurrency(String),
+    #[error("The memo signature was invalid. {0}")]
+    InvalidMemoSignature(#[from] MemoSignatureError),
+    #[error("The order cannot be priced safely. {0}")]
+    RateCircuitBreaker(String),
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum StorefrontError {
+    /// The call failed, but might succeed if it is tried again later
+    #[error("The storefront request failed. {0}")]
+    RequestFailed(String),
+    /// The order can never be sent to the storefront, e.g. because its id is not a valid id on the platform
+    #[error("The order cannot be sent to the storefront. {0}")]
+    Undeliverable(String),
+}
+
+/// A connection to a single store on a shop platform.
+pub trait StorefrontIntegration {
+    /// The name of the shop platform, e.g. "shopify"
+    fn platform(&self) -> &str;
+    /// Identifies the store on its platform, e.g. the Shopify shop domain, so that webhooks can be matched to it
+    fn store_id(&self) -> &str;
+    /// The merchant that the store's orders belong to
+    fn merchant_id(&self) -> &str;
+    /// Converts the body of an inbound order webhook into a new order for this store's merchant, without pricing it.
+    /// The order is priced with [`price_new_order`].
+    fn parse_order(&self, body: &[u8]) -> Result<NewOrder, OrderConversionError>;
+    /// Fetches the orders that are still waiting for payment on the store, converted as for [`Self::parse_order`].
+    fn fetch_open_orders(
+        &self,
+    ) -> LocalBoxFuture<'_, Result<Vec<Result<NewOrder, OrderConversionError>>, StorefrontError>>;
+    /// Marks the order as paid on the store
+    fn mark_order_paid<'a>(&'a self, order: &'a Order) -> LocalBoxFuture<'a, Result<(), StorefrontError>>;
+    /// Cancels the order on the store
+    fn cancel_order<'a>(&'a self, order: &'a Order) -> LocalBoxFuture<'a, Result<(), StorefrontError>>;
+    /// Updates the Tari prices shown on the store. Stores that don't show Tari prices can ignore this.
+    fn update_prices<'a>(&'a self, _rate: &'a ExchangeRate) -> LocalBoxFuture<'a, Result<(), StorefrontError>> {
+        async { Ok(()) }.boxed_local()
+    }
+    /// Gives platform-specific routes access to the concrete type
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// The stores that this server takes payments for. Each merchant has at most one store.
+#[derive(Clone, Default)]
+pub struct Storefronts {
+    storefronts: Vec<Storefront>,
+}
+
+impl Storefronts {
+    /// Creates a storefront for every store in the configuration. The default merchant's Shopify store always comes
+    /// first.
+    pub fn from_config(config: &ServerConfig) -> Result<Self, ServerError> {
+        let mut storefronts = Self::default();
+        let shopify = std::iter::once((DEFAULT_MERCHANT, &config.shopify_config))
+            .chain(config.merchants.iter().map(|m| (m.id.as_str(), &m.shopify_config)));
+        for (merchant_id, shopify_config) in shopify {
+            let storefront = ShopifyStorefront::new(merchant_id, shopify_config)
+                .map_err(|e| ServerError::InitializeError(format!("Failed to create Shopify API: {e}")))?;
+            storefronts.add(Arc::new(storefront));
+        }
+        if let Some(woocommerce) = &config.woocommerce {
+            storefronts.add(Arc::new(WooCommerceStorefront::new(woocommerce)));
+        }
+        Ok(storefronts)
+    }
+
+    /// Adds a store to the registry. If the merchant already has a store, the new one is ignored.
+    pub fn add(&mut self, storefront: Storefront) {
+        if let Some(existing) = self.for_merchant(storefront.merchant_id()) {
+            warn!(
+                "🛍️ Merchant {} already has a {} store. Ignoring the {} store {}.",
+                storefront.merchant_id(),
+                existing.platform(),
+                storefront.platform(),
+                storefront.store_id()
+            );
+            return;
+        }
+        self.storefronts.push(storefront);
+    }
+
+    /// The merchant's store, if it has one
+    pub fn for_merchant(&self, merchant_id: &str) -> Option<&Storefront> {
+        self.storefronts.iter().find(|s| s.merchant_id() == merchant_id)
+    }
+
+    /// The store on `platform` that sent a webhook. If the store is not given or not recognised, the first store on
+    /// the platform is used.
+    pub fn find_store(&self, platform: &str, store_id: Option<&str>) -> Option<&Storefront> {
+        let mut stores = self.storefronts.iter().filter(|s| s.platform() == platform);
+        let store_id = store_id.map(|id| id.trim_end_matches('/'));
+        match store_id.and_then(|id| stores.clone().find(|s| s.store_id().eq_ignore_ascii_case(id))) {
+            Some(store) => Some(store),
+            None => stores.next(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Storefront> {
+        self.storefronts.iter()
+    }
+
+    /// Pushes the new exchange rate to the stores that use it. A merchant's own rate only goes to its store, while the
+    /// default merchant's rate goes to every store whose merchant has not set its own rate for the currency. All the
+    /// stores are updated, even if some of them fail. Returns the errors from the stores that failed.
+    pub async fn update_prices<B: ExchangeRates>(&self, rate: &ExchangeRate, db: &B) -> Vec<String> {
+        let mut errors = vec![];
+        for storefront in self.iter() {
+            if !uses_rate(storefront.merchant_id(), rate, db).await {
+                continue;
+            }
+            let name = format!("{} {} store", storefront.merchant_id(), storefront.platform());
+            match storefront.update_prices(rate).await {
+                Ok(()) => {
+                    debug!("🛍️ Prices on the {name} have been updated to 1 {} = {}", rate.base_currency, rate.rate)
+                },
+                Err(e) => {
+                    error!("🛍️ Could not update prices on the {name}. {e}");
+                    errors.push(format!("{name}: {e}"));
+                },
+            }
+        }
+        errors
+    }
+
+    /// Creates the storefront handler for the event outbox dispatcher.
+    ///
+    /// Only the following events are relevant to the storefronts:
+    ///
+    /// 1. OrderPaid - Once an order is marked as paid in the payment engine, it is marked as paid on the store.
+    /// 2. OrderAnnulled - If an order is cancelled or expires, it is cancelled on the store. If the order was cancelled
+    ///    on the store in the first place, this call is spurious, but no harm is done.
+    ///
+    /// Each order is sent to the store of the merchant it belongs to. If the call fails, the handler returns an error
+    /// so that the dispatcher retries the event later. Orders that can never be sent to the store are logged and
+    /// treated as delivered, since retrying them would not help.
+    pub fn create_outbox_handler(
+        &self,
+    ) -> impl Fn(OutboxEvent) -> LocalBoxFuture<'static, Result<(), String>> + 'static {
+        let storefronts = self.clone();
+        move |event: OutboxEvent| {
+            let (order, paid) = match event.event {
+                EventType::OrderPaid(ev) => (ev.order, true),
+                EventType::OrderAnnulled(ev) => (ev.order, false),
+                _ => return async { Ok(()) }.boxed_local(),
+            };
+            let Some(storefront) = storefronts.for_merchant(&order.merchant_id).cloned() else {
+                debug!("🛍️ Order {} belongs to merchant {}, which has no store.", order.order_id, order.merchant_id);
+                return async { Ok(()) }.boxed_local();
+            };
+            async move {
+                let (action, result) = if paid {
+                    ("mark order as paid", storefront.mark_order_paid(&order).await)
+                } else {
+                    ("cancel order", storefront.cancel_order(&order).await)
+                };
+                let platform = storefront.platform();
+                match result {
+                    Ok(()) => Ok(()),
+                    Err(StorefrontError::Undeliverable(e)) => {
+                        error!("🛍️ {platform}: Could not {action} {}. It will not be retried. {e}", order.order_id);
+                        Ok(())
+                    },
+                    Err(e) => {
+                        error!("🛍️ {platform}: Could not {action} {}. {e}", order.order_id);
+                        Err(format!("Could not {action} {} on {platform}. {e}", order.order_id))
+                    },
+                }
+            }
+            .boxed_local()
+        }
+    }
+}
+
+/// Whether the merchant's prices follow `rate`, i.e. it is the merchant's own rate, or a default rate that the merchant
+/// has not overridden.
+async fn uses_rate<B: ExchangeRates>(merchant_id: &str, rate: &ExchangeRate, db: &B) -> bool {
+    if rate.merchant_id == merchant_id {
+        return true;
+    }
+    if rate.merchant_id != DEFAULT_MERCHANT {
+        return false;
+    }
+    match db.fetch_merchant_rate(merchant_id, &rate.base_currency).await {
+        Ok(current) => current.merchant_id == DEFAULT_MERCHANT,
+        Err(e) => {
+            warn!("🛍️ Could not fetch the {} rate for merchant {merchant_id}. {e}", rate.base_currency);
+            true
+        },
+    }
+}
+
+/// Sets the Tari price of the order from its original price, using the latest exchange rate that applies to the order's
+/// merchant and currency, and extracts the customer's address from the memo, if there is one.
+///
+/// If the exchange rate trips the `breaker`, [`OrderConversionError::RateCircuitBreaker`] is returned, and the order
+/// should be held rather than processed. Orders that are priced in Tari never trip the breaker.
+pub async fn price_new_order<B: ExchangeRates>(
+    mut order: NewOrder,
+    fx: &ExchangeRateApi<B>,
+    rate_quotes: Option<&QuotePolicy>,
+    breaker: &RateCircuitBreaker,
+) -> Result<NewOrder, OrderConversionError> {
+    let currency = order.currency.to_uppercase();
+    let rate = if currency == TARI_CURRENCY_CODE {
+        ExchangeRate::default()
+    } else {
+        let rate = fx
+            .fetch_merchant_rate(&order.merchant_id, &currency)
+            .await
+            .map_err(|e| OrderConversionError::UnsupportedCurrency(e.to_string()))?;
+        let tripped = fx
+            .check_circuit_breaker(&rate, breaker)
+            .await
+            .map_err(|e| OrderConversionError::UnsupportedCurrency(e.to_string()))?;
+        if let Some(reason) = tripped {
+            return Err(OrderConversionError::RateCircuitBreaker(reason));
+        }
+        info!("Order is not in Tari. Using a conversion rate of {rate}");
+        rate
+    };
+    let original_price = order
+        .original_price
+        .as_deref()
+        .ok_or_else(|| OrderConversionError::FormatError("The order has no original price".to_string()))?;
+    // Net price in cents.
+    let total_price =
+        parse_shopify_price(original_price).map_err(|e| OrderConversionError::FormatError(e.to_string()))?;
+    order.total_price = rate.convert_to_tari_from_cents(total_price);
+    // Only orders that were converted at a stored exchange rate have a quote that can expire
+    let quote_expires_at = rate_quotes.filter(|_| rate.id > 0).map(QuotePolicy::expires_at);
+    order.rate_id = quote_expires_at.map(|_| rate.id);
+    order.quote_expires_at = quote_expires_at;
+    if let Err(e) = order.try_extract_address() {
+        info!(
+            "Order {} did not contain a valid signature. This order is going to remain unclaimed. Error: {e}. Memo: {}",
+            order.order_id,
+            order.memo.as_ref().unwrap_or(&"No memo provided".to_string())
+        );
+    }
+    Ok(order)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct TestStore(&'static str, &'static str, &'static str);
+
+    impl StorefrontIntegration for TestStore {
+        fn platform(&self) -> &str {
+            self.0
+        }
+
+        fn store_id(&self) -> &str {
+            self.1
+        }
+
+        fn merchant_id(&self) -> &str {
+            self.2
+        }
+
+        fn parse_order(&self, _body: &[u8]) -> Result<NewOrder, OrderConversionError> {
+            Err(OrderConversionError::FormatError("not supported".to_string()))
+        }
+
+        fn fetch_open_orders(
+            &self,
+        ) -> LocalBoxFuture<'_, Result<Vec<Result<NewOrder, OrderConversionError>>, StorefrontError>> {
+            async { Ok(vec![]) }.boxed_local()
+        }
+
+        fn mark_order_paid<'a>(&'a self, _order: &'a Order) -> LocalBoxFuture<'a, Result<(), StorefrontError>> {
+            async { Ok(()) }.boxed_local()
+        }
+
+        fn cancel_order<'a>(&'a self, _order: &'a Order) -> LocalBoxFuture<'a, Result<(), StorefrontError>> {
+            async { Ok(()) }.boxed_local()
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn find_the_store_for_a_webhook() {
+        let mut storefronts = Storefronts::default();
+        storefronts.add(Arc::new(TestStore("shopify", "main.myshopify.com", DEFAULT_MERCHANT)));
+        storefronts.add(Arc::new(TestStore("shopify", "second.myshopify.com", "second")));
+        storefronts.add(Arc::new(TestStore("woocommerce", "https://shop.example.com", "woo")));
+        // A merchant only has one store
+        storefronts.add(Arc::new(TestStore("woocommerce", "https://other.example.com", "second")));
+        assert_eq!(storefronts.iter().count(), 3);
+
+        let merchant = |platform, store_id| storefronts.find_store(platform, store_id).map(|s| s.merchant_id());
+        assert_eq!(merchant("shopify", Some("Second.myshopify.com")), Some("second"));
+        assert_eq!(merchant("shopify", Some("unknown.myshopify.com")), Some(DEFAULT_MERCHANT));
+        assert_eq!(merchant("shopify", None), Some(DEFAULT_MERCHANT));
+        assert_eq!(merchant("woocommerce", Some("https://shop.example.com/")), Some("woo"));
+        assert_eq!(merchant("magento", None), None);
+        assert_eq!(storefronts.for_merchant("second").map(|s| s.platform()), Some("shopify"));
+    }
+}
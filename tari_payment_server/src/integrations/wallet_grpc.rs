@@ -0,0 +1,169 @@
+//! Console wallet gRPC client
+//!
+//! Instead of waiting for the hot wallet's notify script to call the `/wallet` endpoints, the server can watch the
+//! wallet for payments itself (see [`crate::wallet_worker`]). [`WalletGrpcClient`] covers the small part of the console
+//! wallet's gRPC interface that this needs.
+//!
+//! The messages are generated from `proto/wallet.proto`, which is a subset of the console wallet's own `wallet.proto`.
+//!
+//! The client is also a [`WalletHistorySource`], so the payments can be reconciled against the wallet's history.
+use chrono::DateTime;
+use log::warn;
+use tari_common_types::tari_address::TariAddress;
+use tari_payment_engine::tpe_api::{
+    reconciliation_api::WalletHistorySource,
+    reconciliation_objects::{ReconciliationError, WalletTransaction},
+};
+use thiserror::Error;
+use tonic::{
+    metadata::{errors::InvalidMetadataValue, AsciiMetadataValue},
+    transport::Channel,
+    Request,
+    Status,
+};
+use tpg_common::MicroTari;
+
+use crate::config::WalletGrpcConfig;
+
+#[allow(clippy::all, clippy::pedantic)]
+pub mod tari_rpc {
+    tonic::include_proto!("tari.rpc");
+}
+
+use tari_rpc::{
+    wallet_client::WalletClient,
+    GetCompletedTransactionsRequest,
+    GetStateRequest,
+    TransactionDirection,
+    TransactionInfo,
+    TransactionStatus,
+};
+
+/// Fetching the full transaction history can take a while on a busy wallet
+const WALLET_GRPC_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Debug, Clone, Error)]
+pub enum WalletGrpcError {
+    #[error("The wallet gRPC configuration is invalid. {0}")]
+    InvalidConfiguration(String),
+    #[error("The wallet gRPC request failed. {0}")]
+    RequestFailed(String),
+}
+
+impl From<Status> for WalletGrpcError {
+    fn from(status: Status) -> Self {
+        Self::RequestFailed(format!("{}: {}", status.code(), status.message()))
+    }
+}
+
+impl From<InvalidMetadataValue> for WalletGrpcError {
+    fn from(e: InvalidMetadataValue) -> Self {
+        Self::InvalidConfiguration(format!("Invalid credentials. {e}"))
+    }
+}
+
+/// A client for the console wallet's gRPC interface.
+#[derive(Clone)]
+pub struct WalletGrpcClient {
+    client: WalletClient<Channel>,
+    authorization: Option<AsciiMetadataValue>,
+    merchant_id: String,
+}
+
+impl WalletGrpcClient {
+    /// Creates a client for the wallet in `config`. The connection is only made when the first request is sent, and is
+    /// re-established if it drops, so the wallet does not have to be running when the server starts.
+    pub fn new(config: &WalletGrpcConfig) -> Result<Self, WalletGrpcError> {
+        let channel = Channel::from_shared(config.address.clone())
+            .map_err(|e| WalletGrpcError::InvalidConfiguration(format!("Invalid address {}. {e}", config.address)))?
+            .timeout(WALLET_GRPC_TIMEOUT)
+            .connect_lazy();
+        let authorization = match &config.username {
+            Some(username) => {
+                let credentials = base64::encode(format!("{username}:{}", config.password.reveal()));
+                Some(AsciiMetadataValue::try_from(format!("Basic {credentials}"))?)
+            },
+            None => None,
+        };
+        Ok(Self { client: WalletClient::new(channel), authorization, merchant_id: config.merchant_id.clone() })
+    }
+
+    /// The merchant that owns the wallet, and so receives its payments
+    pub fn merchant_id(&self) -> &str {
+        &self.merchant_id
+    }
+
+    /// The height of the chain, as far as the wallet has scanned it.
+    pub async fn scanned_height(&self) -> Result<u64, WalletGrpcError> {
+        let response = self.client.clone().get_state(self.request(GetStateRequest {})).await?;
+        Ok(response.into_inner().scanned_height)
+    }
+
+    /// Every completed transaction in the wallet, inbound and outbound.
+    pub async fn completed_transactions(&self) -> Result<Vec<TransactionInfo>, WalletGrpcError> {
+        self.completed_transactions_matching(|_| true).await
+    }
+
+    /// The completed transactions that `keep` accepts. The wallet can't filter its history itself, so every
+    /// transaction is streamed, but the rejected ones are dropped as they arrive rather than collected.
+    pub async fn completed_transactions_matching<F>(
+        &self,
+        mut keep: F,
+    ) -> Result<Vec<TransactionInfo>, WalletGrpcError>
+    where
+        F: FnMut(&TransactionInfo) -> bool,
+    {
+        let request = self.request(GetCompletedTransactionsRequest {});
+        let mut stream = self.client.clone().get_completed_transactions(request).await?.into_inner();
+        let mut transactions = Vec::new();
+        while let Some(response) = stream.message().await? {
+            transactions.extend(response.transaction.filter(|tx| keep(tx)));
+        }
+        Ok(transactions)
+    }
+
+    fn request<T>(&self, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        if let Some(authorization) = &self.authorization {
+            request.metadata_mut().insert("authorization", authorization.clone());
+        }
+        request
+    }
+}
+
+impl WalletHistorySource for WalletGrpcClient {
+    async fn fetch_wallet_transactions(&self) -> Result<Vec<WalletTransaction>, ReconciliationError> {
+        let transactions =
+            self.completed_transactions().await.map_err(|e| ReconciliationError::SourceUnavailable(e.to_string()))?;
+        Ok(transactions.into_iter().filter(is_inbound_payment).filter_map(wallet_transaction).collect())
+    }
+}
+
+/// Inbound transactions that the wallet has not cancelled or rejected. Coinbases are not payments.
+fn is_inbound_payment(tx: &TransactionInfo) -> bool {
+    use TransactionStatus::*;
+    let void =
+        matches!(tx.status(), Rejected | Coinbase | CoinbaseUnconfirmed | CoinbaseConfirmed | CoinbaseNotInBlockChain);
+    tx.direction() == TransactionDirection::Inbound && !tx.is_cancelled && !void
+}
+
+fn wallet_transaction(tx: TransactionInfo) -> Option<WalletTransaction> {
+    let txid = tx.tx_id.to_string();
+    let Ok(sender) = TariAddress::from_bytes(&tx.source_address) else {
+        warn!("👛️ Transaction {txid} has an invalid sender address. It is left out of the wallet history.");
+        return None;
+    };
+    let Ok(amount) = MicroTari::try_from(tx.amount) else {
+        warn!("👛️ Transaction {txid} has an invalid amount: {}. It is left out of the wallet history.", tx.amount);
+        return None;
+    };
+    let confirmed = matches!(tx.status(), TransactionStatus::MinedConfirmed | TransactionStatus::OneSidedConfirmed);
+    let mut transaction = WalletTransaction::new(txid, sender.into(), amount).with_confirmed(confirmed);
+    if !tx.message.trim().is_empty() {
+        transaction = transaction.with_memo(tx.message);
+    }
+    if let Some(timestamp) = i64::try_from(tx.timestamp).ok().and_then(|t| DateTime::from_timestamp(t, 0)) {
+        transaction = transaction.with_timestamp(timestamp);
+    }
+    Some(transaction)
+}
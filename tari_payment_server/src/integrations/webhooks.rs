@@ -0,0 +1,152 @@
+//! Outbound webhooks
+//!
+//! Webhook subscribers receive engine events in two steps. The outbox dispatcher hands every event to the handler from
+//! [`create_webhook_outbox_handler`], which queues one delivery per subscriber. The webhook worker
+//! ([`start_webhook_worker`]) then claims the due deliveries, POSTs up to [`WEBHOOK_CONCURRENCY`] of them at a time,
+//! and retries failed deliveries with the same backoff policy as the outbox. Each subscriber is retried independently,
+//! so one slow or broken endpoint does not hold up the others, or the storefront.
+//!
+//! The request body is a JSON [`tari_payment_engine::db_types::WebhookPayload`]. Every request carries these headers:
+//! * `X-Tpg-Hmac-Sha256` - the base64-encoded HMAC-SHA256 of the request body, keyed with the subscription secret. This
+//!   is the same scheme that Shopify uses to sign its webhooks (see [`crate::middleware::HmacMiddlewareFactory`]).
+//! * `X-Tpg-Event` - the event type.
+//! * `X-Tpg-Delivery` - the delivery id. Deliveries are at-least-once, so subscribers should use this to discard
+//!   duplicates.
+use std::collections::HashMap;
+
+use futures::{future::LocalBoxFuture, stream, FutureExt, StreamExt};
+use log::*;
+use reqwest::{header::CONTENT_TYPE, Client};
+use tari_payment_engine::{
+    db_types::{OutboxEvent, WebhookDelivery, WebhookSubscription},
+    events::OutboxConfig,
+    traits::{WebhookError, WebhookManagement},
+};
+use tokio::task::JoinHandle;
+
+use crate::helpers::calculate_hmac;
+
+pub const WEBHOOK_HMAC_HEADER: &str = "X-Tpg-Hmac-Sha256";
+pub const WEBHOOK_EVENT_HEADER: &str = "X-Tpg-Event";
+pub const WEBHOOK_DELIVERY_HEADER: &str = "X-Tpg-Delivery";
+const WEBHOOK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// The maximum number of webhook requests in flight at once
+pub const WEBHOOK_CONCURRENCY: usize = 8;
+
+/// Creates the outbox handler that queues a webhook delivery for every active subscriber to the event's type.
+pub fn create_webhook_outbox_handler<B>(db: B) -> impl Fn(OutboxEvent) -> LocalBoxFuture<'static, Result<(), String>>
+where B: WebhookManagement + Clone + 'static {
+    move |event: OutboxEvent| {
+        let db = db.clone();
+        async move {
+            let deliveries = db.queue_webhook_deliveries(&event).await.map_err(|e| e.to_string())?;
+            if !deliveries.is_empty() {
+                debug!("🪝️ Queued {} webhook deliveries for event {}", deliveries.len(), event.id);
+            }
+            Ok(())
+        }
+        .boxed_local()
+    }
+}
+
+/// Starts the webhook delivery worker. Do not await the returned JoinHandle, as it will run indefinitely.
+///
+/// Spawned on the local task set, like the [expiry worker](crate::expiry_worker::start_expiry_worker).
+pub fn start_webhook_worker<B: WebhookManagement + 'static>(db: B, retry_policy: OutboxConfig) -> JoinHandle<()> {
+    actix_web::rt::spawn(async move {
+        let client = Client::new();
+        let mut timer = tokio::time::interval(retry_policy.poll_interval);
+        info!("🪝️ Webhook delivery worker started");
+        loop {
+            timer.tick().await;
+            if let Err(e) = deliver_due_webhooks(&db, &client, &retry_policy).await {
+                error!("🪝️ Could not deliver webhooks. {e}");
+            }
+        }
+    })
+}
+
+/// Claims every webhook delivery that is due, makes one attempt at each, and records the outcome. Up to
+/// [`WEBHOOK_CONCURRENCY`] requests are made at once, so a slow subscriber only holds up its own deliveries.
+pub async fn deliver_due_webhooks<B: WebhookManagement>(
+    db: &B,
+    client: &Client,
+    retry_policy: &OutboxConfig,
+) -> Result<(), WebhookError> {
+    let deliveries = db.claim_due_webhook_deliveries(retry_policy.batch_size, retry_policy.lease).await?;
+    if deliveries.is_empty() {
+        return Ok(());
+    }
+    let subscriptions =
+        db.fetch_webhooks().await?.into_iter().map(|w| (w.id, w)).collect::<HashMap<i64, WebhookSubscription>>();
+    let results = stream::iter(deliveries)
+        .filter_map(|delivery| {
+            // The subscription may have been deleted after the deliveries were claimed
+            let webhook = subscriptions.get(&delivery.subscription_id);
+            async move { webhook.map(|w| (w, delivery)) }
+        })
+        .map(|(webhook, delivery)| deliver_webhook(db, client, retry_policy, webhook, delivery))
+        .buffer_unordered(WEBHOOK_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+    results.into_iter().collect()
+}
+
+/// Makes one attempt at the delivery, and records the outcome.
+async fn deliver_webhook<B: WebhookManagement>(
+    db: &B,
+    client: &Client,
+    retry_policy: &OutboxConfig,
+    webhook: &WebhookSubscription,
+    delivery: WebhookDelivery,
+) -> Result<(), WebhookError> {
+    match post_delivery(client, webhook, &delivery).await {
+        Ok(status) => {
+            db.mark_webhook_delivered(delivery.id, status).await?;
+            trace!("🪝️ Webhook delivery {} to {} succeeded", delivery.id, webhook.url);
+        },
+        Err((status, error)) => {
+            let attempts = delivery.attempts + 1;
+            let retry_at = retry_policy.next_attempt_at(attempts);
+            db.record_failed_webhook_delivery(delivery.id, status, &error, retry_at).await?;
+            match retry_at {
+                Some(t) => {
+                    debug!("🪝️ Webhook delivery {} to {} failed. Retrying at {t}. {error}", delivery.id, webhook.url)
+                },
+                None => warn!(
+                    "🪝️ Webhook delivery {} to {} failed after {attempts} attempts and has been parked. {error}",
+                    delivery.id, webhook.url
+                ),
+            }
+        },
+    }
+    Ok(())
+}
+
+/// POSTs the delivery to the subscriber, returning the HTTP status code on success. On failure, the status code (if
+/// the subscriber responded at all) is returned along with the reason.
+async fn post_delivery(
+    client: &Client,
+    webhook: &WebhookSubscription,
+    delivery: &WebhookDelivery,
+) -> Result<i64, (Option<i64>, String)> {
+    let signature = calculate_hmac(&webhook.secret, delivery.payload.as_bytes());
+    let response = client
+        .post(&webhook.url)
+        .timeout(WEBHOOK_TIMEOUT)
+        .header(CONTENT_TYPE, "application/json")
+        .header(WEBHOOK_HMAC_HEADER, signature)
+        .header(WEBHOOK_EVENT_HEADER, &delivery.event_type)
+        .header(WEBHOOK_DELIVERY_HEADER, delivery.id.to_string())
+        .body(delivery.payload.clone())
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+    let status = response.status();
+    let code = i64::from(status.as_u16());
+    if status.is_success() {
+        Ok(code)
+    } else {
+        Err((Some(code), format!("The subscriber responded with {status}")))
+    }
+}
@@ -0,0 +1,441 @@
+//! WooCommerce storefront integration
+//!
+//! WooCommerce sends new orders to the `/woocommerce/webhook/order_created` endpoint. Webhooks are signed with the
+//! secret that was entered when the webhook was created in the WooCommerce admin, and the base64-encoded HMAC-SHA256 of
+//! the body is sent in the `X-WC-Webhook-Signature` header. This is the same scheme that Shopify uses, so the requests
+//! are checked with the [`crate::middleware::HmacMiddlewareFactory`].
+//!
+//! When an order is paid or annulled, the [`WooCommerceStorefront`] updates the order through the
+//! [WooCommerce REST API](https://woocommerce.github.io/woocommerce-rest-api-docs/). The API is authenticated with a
+//! consumer key and secret over HTTPS.
+//!
+//! Every order from the store is tagged with the store's merchant id, and only that merchant's orders are sent back to
+//! the store.
+use std::any::Any;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::{future::LocalBoxFuture, FutureExt};
+use log::*;
+use reqwest::{Client, Method};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use tari_payment_engine::db_types::{NewOrder, Order, OrderId};
+use thiserror::Error;
+use tpg_common::{MicroTari, Secret};
+
+use crate::{
+    config::WooCommerceConfig,
+    integrations::storefront::{OrderConversionError, StorefrontError, StorefrontIntegration},
+};
+
+pub const WOOCOMMERCE_PLATFORM: &str = "woocommerce";
+pub const WOOCOMMERCE_HMAC_HEADER: &str = "X-WC-Webhook-Signature";
+pub const WOOCOMMERCE_TOPIC_HEADER: &str = "X-WC-Webhook-Topic";
+/// The header that WooCommerce uses to identify the site that sent a webhook
+pub const WOOCOMMERCE_SOURCE_HEADER: &str = "X-WC-Webhook-Source";
+/// Orders in these states are waiting for payment
+const OPEN_ORDER_STATUSES: &str = "pending,on-hold";
+const ORDERS_PER_PAGE: usize = 100;
+const WOOCOMMERCE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+#[derive(Debug, Clone, Error)]
+pub enum WooCommerceApiError {
+    #[error("Could not reach the WooCommerce API. {0}")]
+    RequestFailed(String),
+    #[error("WooCommerce rejected the request. {0}")]
+    Rejected(String),
+    #[error("The WooCommerce API returned an invalid response. {0}")]
+    InvalidResponse(String),
+}
+
+/// A WooCommerce order, as sent in webhooks and returned by the REST API. Only the fields that the payment server uses
+/// are included.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WooCommerceOrder {
+    pub id: u64,
+    /// The order number shown to the customer. This is the same as the id unless a plugin changes it.
+    #[serde(default)]
+    pub number: String,
+    #[serde(default)]
+    pub status: String,
+    pub currency: String,
+    /// The order total, in `currency` units, e.g. "29.35"
+    pub total: String,
+    /// The customer id, or zero for guest checkouts
+    #[serde(default)]
+    pub customer_id: u64,
+    #[serde(default)]
+    pub customer_note: String,
+    /// The creation time in UTC, without a timezone, e.g. "2024-03-22T16:28:02"
+    pub date_created_gmt: String,
+    #[serde(default)]
+    pub billing: WooCommerceBilling,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WooCommerceBilling {
+    #[serde(default)]
+    pub email: String,
+}
+
+/// Converts a WooCommerce order into a new order for `merchant_id`, without pricing it. The total price is zero until
+/// the order is priced with [`crate::integrations::storefront::price_new_order`].
+///
+/// Guest checkouts don't have a customer id, so the billing email is used to identify the customer instead.
+pub fn unpriced_order_from_woocommerce_order(
+    value: WooCommerceOrder,
+    merchant_id: &str,
+) -> Result<NewOrder, OrderConversionError> {
+    trace!("Converting WooCommerceOrder to NewOrder: {:?}", value);
+    let created_at = value
+        .date_created_gmt
+        .parse::<NaiveDateTime>()
+        .map(|t| DateTime::<Utc>::from_naive_utc_and_offset(t, Utc))
+        .map_err(|e| OrderConversionError::FormatError(e.to_string()))?;
+    let customer_id = match (value.customer_id, value.billing.email.trim()) {
+        (0, "") => return Err(OrderConversionError::FormatError("The order has no customer".to_string())),
+        (0, email) => email.to_lowercase(),
+        (id, _) => id.to_string(),
+    };
+    let alt_order_id = Some(value.number).filter(|n| !n.is_empty()).map(OrderId::from);
+    let memo = Some(value.customer_note).filter(|n| !n.trim().is_empty());
+    let mut order =
+        NewOrder::new(OrderId::from(value.id.to_string()), customer_id, MicroTari::from(0)).with_merchant(merchant_id);
+    order.alt_order_id = alt_order_id;
+    order.memo = memo;
+    order.currency = value.currency;
+    order.original_price = Some(value.total);
+    order.created_at = created_at;
+    Ok(order)
+}
+
+/// A client for the parts of the WooCommerce REST API (v3) that the payment server needs.
+#[derive(Clone)]
+pub struct WooCommerceApi {
+    url: String,
+    consumer_key: String,
+    consumer_secret: Secret<String>,
+    client: Client,
+}
+
+impl WooCommerceApi {
+    /// Creates a new client for the store at `url`, e.g. "https://shop.example.com".
+    pub fn new(url: &str, consumer_key: &str, consumer_secret: Secret<String>) -> Self {
+        let client = Client::builder().timeout(WOOCOMMERCE_TIMEOUT).build().unwrap_or_default();
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+            consumer_key: consumer_key.to_string(),
+            consumer_secret,
+            client,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Marks the order as paid. WooCommerce moves paid orders to `processing`, or to `completed` if they only contain
+    /// virtual products.
+    pub async fn mark_order_as_paid(&self, order_id: u64) -> Result<WooCommerceOrder, WooCommerceApiError> {
+        self.update_order(order_id, json!({ "set_paid": true })).await
+    }
+
+    pub async fn cancel_order(&self, order_id: u64) -> Result<WooCommerceOrder, WooCommerceApiError> {
+        self.update_order(order_id, json!({ "status": "cancelled" })).await
+    }
+
+    /// Fetches all the orders that are waiting for payment, oldest first.
+    pub async fn fetch_open_orders(&self) -> Result<Vec<WooCommerceOrder>, WooCommerceApiError> {
+        let mut orders = Vec::new();
+        for page in 1.. {
+            let params = [
+                ("status", OPEN_ORDER_STATUSES.to_string()),
+                ("orderby", "date".to_string()),
+                ("order", "asc".to_string()),
+                ("per_page", ORDERS_PER_PAGE.to_string()),
+                ("page", page.to_string()),
+            ];
+            let batch = self.request::<Vec<WooCommerceOrder>>(Method::GET, "orders", &params, None).await?;
+            let done = batch.len() < ORDERS_PER_PAGE;
+            orders.extend(batch);
+            if done {
+                break;
+            }
+        }
+        Ok(orders)
+    }
+
+    async fn update_order(&self, order_id: u64, update: Value) -> Result<WooCommerceOrder, WooCommerceApiError> {
+        self.request(Method::PUT, &format!("orders/{order_id}"), &[], Some(update)).await
+    }
+
+    async fn request<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        params: &[(&str, String)],
+        body: Option<Value>,
+    ) -> Result<T, WooCommerceApiError> {
+        let url = format!("{}/wp-json/wc/v3/{path}", self.url);
+        let mut req =
+            self.client.request(method, &url).basic_auth(&self.consumer_key, Some(self.consumer_secret.reveal()));
+        if !params.is_empty() {
+            req = req.query(params);
+        }
+        if let Some(body) = body {
+            req = req.json(&body);
+        }
+        let response = req.send().await.map_err(|e| WooCommerceApiError::RequestFailed(e.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(WooCommerceApiError::Rejected(format!("{status}. {body}")));
+        }
+        response.json::<T>().await.map_err(|e| WooCommerceApiError::InvalidResponse(e.to_string()))
+    }
+}
+
+/// A merchant's WooCommerce store.
+///
+/// Paid orders are marked as paid on the store and annulled orders are cancelled. WooCommerce shows prices in the
+/// store's own currency, so exchange rate updates are not pushed to the store.
+#[derive(Clone)]
+pub struct WooCommerceStorefront {
+    merchant_id: String,
+    api: WooCommerceApi,
+}
+
+impl WooCommerceStorefront {
+    pub fn new(config: &WooCommerceConfig) -> Self {
+        let api = WooCommerceApi::new(&config.url, &config.consumer_key, config.consumer_secret.clone());
+        Self::with_api(&config.merchant_id, api)
+    }
+
+    pub fn with_api(merchant_id: &str, api: WooCommerceApi) -> Self {
+        Self { merchant_id: merchant_id.to_string(), api }
+    }
+
+    async fn mark_paid(&self, order: &Order) -> Result<(), StorefrontError> {
+        let order_id = parse_woocommerce_order_id(order)?;
+        let o = api_call(self.api.mark_order_as_paid(order_id).await)?;
+        info!("🛒️ Order {order_id} marked as paid on WooCommerce. New status: {}", o.status);
+        Ok(())
+    }
+
+    async fn cancel(&self, order: &Order) -> Result<(), StorefrontError> {
+        let order_id = parse_woocommerce_order_id(order)?;
+        debug!("🛒️ Order {order_id} has been annulled. Sending cancellation request to WooCommerce.");
+        let o = api_call(self.api.cancel_order(order_id).await)?;
+        info!("🛒️ Order {order_id} has been cancelled on WooCommerce. New status: {}", o.status);
+        Ok(())
+    }
+}
+
+impl StorefrontIntegration for WooCommerceStorefront {
+    fn platform(&self) -> &str {
+        WOOCOMMERCE_PLATFORM
+    }
+
+    fn store_id(&self) -> &str {
+        self.api.url()
+    }
+
+    fn merchant_id(&self) -> &str {
+        &self.merchant_id
+    }
+
+    fn parse_order(&self, body: &[u8]) -> Result<NewOrder, OrderConversionError> {
+        let order = serde_json::from_slice::<WooCommerceOrder>(body)
+            .map_err(|e| OrderConversionError::FormatError(e.to_string()))?;
+        unpriced_order_from_woocommerce_order(order, &self.merchant_id)
+    }
+
+    fn fetch_open_orders(
+        &self,
+    ) -> LocalBoxFuture<'_, Result<Vec<Result<NewOrder, OrderConversionError>>, StorefrontError>> {
+        async move {
+            let orders = api_call(self.api.fetch_open_orders().await)?;
+            Ok(orders.into_iter().map(|o| unpriced_order_from_woocommerce_order(o, &self.merchant_id)).collect())
+        }
+        .boxed_local()
+    }
+
+    fn mark_order_paid<'a>(&'a self, order: &'a Order) -> LocalBoxFuture<'a, Result<(), StorefrontError>> {
+        self.mark_paid(order).boxed_local()
+    }
+
+    fn cancel_order<'a>(&'a self, order: &'a Order) -> LocalBoxFuture<'a, Result<(), StorefrontError>> {
+        self.cancel(order).boxed_local()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+fn api_call<T>(result: Result<T, WooCommerceApiError>) -> Result<T, StorefrontError> {
+    result.map_err(|e| StorefrontError::RequestFailed(e.to_string()))
+}
+
+fn parse_woocommerce_order_id(order: &Order) -> Result<u64, StorefrontError> {
+    order.order_id.as_str().parse::<u64>().map_err(|e| {
+        StorefrontError::Undeliverable(format!(
+            "WooCommerce order ids must be integers. Order {} could not be converted into a WooCommerce order id. {e}",
+            order.order_id
+        ))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use chrono::TimeZone;
+    use tari_payment_engine::{
+        db_types::{OrderStatusType, OutboxEvent, DEFAULT_MERCHANT},
+        events::{EventType, OrderAnnulledEvent, OrderEvent},
+    };
+
+    use super::*;
+    use crate::integrations::storefront::Storefronts;
+
+    type Requests = Arc<Mutex<Vec<(String, Option<String>, Value)>>>;
+
+    fn woocommerce_order() -> WooCommerceOrder {
+        WooCommerceOrder {
+            id: 727,
+            number: "727".to_string(),
+            status: "on-hold".to_string(),
+            currency: "USD".to_string(),
+            total: "29.35".to_string(),
+            customer_id: 0,
+            customer_note: "Paying with Tari".to_string(),
+            date_created_gmt: "2024-03-22T16:28:02".to_string(),
+            billing: WooCommerceBilling { email: "Alice@example.com".to_string() },
+        }
+    }
+
+    /// A stub WooCommerce REST API that records the order updates it receives
+    fn stub_server(requests: Requests) -> String {
+        let server = HttpServer::new(move || {
+            let requests = requests.clone();
+            App::new().route(
+                "/wp-json/wc/v3/orders/{id}",
+                web::put().to(move |req: HttpRequest, path: web::Path<u64>, body: web::Json<Value>| {
+                    let requests = requests.clone();
+                    async move {
+                        let auth = req.headers().get("Authorization").and_then(|h| h.to_str().ok()).map(String::from);
+                        let body = body.into_inner();
+                        requests.lock().unwrap().push((path.to_string(), auth, body.clone()));
+                        let status = if body["set_paid"] == json!(true) { "processing" } else { "cancelled" };
+                        let mut order = woocommerce_order();
+                        order.id = path.into_inner();
+                        order.status = status.to_string();
+                        HttpResponse::Ok().json(order)
+                    }
+                }),
+            )
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))
+        .expect("Could not bind the stub server");
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        format!("http://{addr}")
+    }
+
+    fn order(order_id: &str, merchant_id: &str) -> Order {
+        let new_order = NewOrder::new(OrderId::from(order_id.to_string()), "alice".to_string(), MicroTari::from(100))
+            .with_merchant(merchant_id);
+        Order {
+            id: 1,
+            order_id: new_order.order_id,
+            alt_id: None,
+            customer_id: new_order.customer_id,
+            memo: None,
+            total_price: new_order.total_price,
+            original_price: None,
+            currency: new_order.currency,
+            created_at: new_order.created_at,
+            updated_at: new_order.created_at,
+            status: OrderStatusType::Paid,
+            rate_id: None,
+            quote_expires_at: None,
+            merchant_id: new_order.merchant_id,
+        }
+    }
+
+    fn storefronts(api: WooCommerceApi) -> Storefronts {
+        let mut storefronts = Storefronts::default();
+        storefronts.add(Arc::new(WooCommerceStorefront::with_api("woo", api)));
+        storefronts
+    }
+
+    fn event(event: EventType) -> OutboxEvent {
+        OutboxEvent {
+            id: 1,
+            event,
+            created_at: Utc::now(),
+            attempts: 0,
+            next_attempt_at: None,
+            last_error: None,
+            delivered_at: None,
+        }
+    }
+
+    #[test]
+    fn convert_woocommerce_order() {
+        let order = unpriced_order_from_woocommerce_order(woocommerce_order(), "woo").unwrap();
+        assert_eq!(order.order_id.as_str(), "727");
+        assert_eq!(order.alt_order_id.as_ref().map(|o| o.as_str()), Some("727"));
+        assert_eq!(order.customer_id, "alice@example.com");
+        assert_eq!(order.memo.as_deref(), Some("Paying with Tari"));
+        assert_eq!(order.original_price.as_deref(), Some("29.35"));
+        assert_eq!(order.currency, "USD");
+        assert_eq!(order.created_at, Utc.with_ymd_and_hms(2024, 3, 22, 16, 28, 2).unwrap());
+        assert_eq!(order.merchant_id, "woo");
+        assert_eq!(order.total_price, MicroTari::from(0));
+
+        let registered = WooCommerceOrder { customer_id: 12, customer_note: " ".into(), ..woocommerce_order() };
+        let order = unpriced_order_from_woocommerce_order(registered, "woo").unwrap();
+        assert_eq!(order.customer_id, "12");
+        assert!(order.memo.is_none());
+
+        let anonymous = WooCommerceOrder { billing: WooCommerceBilling::default(), ..woocommerce_order() };
+        assert!(unpriced_order_from_woocommerce_order(anonymous, "woo").is_err());
+        let bad_date = WooCommerceOrder { date_created_gmt: "yesterday".into(), ..woocommerce_order() };
+        assert!(unpriced_order_from_woocommerce_order(bad_date, "woo").is_err());
+    }
+
+    #[actix_web::test]
+    async fn outbox_handler_updates_the_store() {
+        let requests = Requests::default();
+        let url = stub_server(requests.clone());
+        let api = WooCommerceApi::new(&format!("{url}/"), "ck_test", Secret::new("cs_test".to_string()));
+        let handler = storefronts(api).create_outbox_handler();
+
+        handler(event(EventType::OrderPaid(OrderEvent::new(order("727", "woo"))))).await.unwrap();
+        let annulled = OrderAnnulledEvent::new(order("728", "woo"));
+        handler(event(EventType::OrderAnnulled(annulled))).await.unwrap();
+        // Other merchants' orders, and orders that can't be WooCommerce orders, are not sent to the store
+        handler(event(EventType::OrderPaid(OrderEvent::new(order("729", DEFAULT_MERCHANT))))).await.unwrap();
+        handler(event(EventType::OrderPaid(OrderEvent::new(order("#730", "woo"))))).await.unwrap();
+
+        let requests = requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 2);
+        // Basic auth with ck_test:cs_test
+        let auth = Some("Basic Y2tfdGVzdDpjc190ZXN0".to_string());
+        assert_eq!(requests[0], ("727".to_string(), auth.clone(), json!({ "set_paid": true })));
+        assert_eq!(requests[1], ("728".to_string(), auth, json!({ "status": "cancelled" })));
+    }
+
+    #[actix_web::test]
+    async fn failed_updates_are_retried() {
+        // Nothing is listening on this port, so the update fails and the dispatcher should try again later
+        let api = WooCommerceApi::new("http://127.0.0.1:1", "ck_test", Secret::new("cs_test".to_string()));
+        let handler = storefronts(api).create_outbox_handler();
+        let result = handler(event(EventType::OrderPaid(OrderEvent::new(order("727", "woo"))))).await;
+        assert!(result.is_err());
+    }
+}
@@ -11,6 +11,7 @@
 //! The server exposes the following routes:
 //! * `/health`: A health check route that returns a 200 OK response.
 //! * `/webhook/checkout_create`: The webhook route for receiving checkout create events from Shopify.
+//! * `/woocommerce/webhook/order_created`: The webhook route for receiving new orders from WooCommerce.
 
 #![feature(type_alias_impl_trait)]
 
@@ -52,8 +53,13 @@ pub mod cli;
 pub mod config;
 pub mod data_objects;
 pub mod errors;
+pub mod event_stream;
 
 pub mod expiry_worker;
+pub mod overpayment_worker;
+pub mod rate_feed_worker;
+pub mod session_sync_worker;
+pub mod wallet_worker;
 
 pub mod helpers;
 
@@ -62,6 +68,8 @@ pub mod middleware;
 pub mod routes;
 pub mod server;
 pub mod shopify_routes;
+pub mod storefront_routes;
+pub mod woocommerce_routes;
 
 pub mod integrations;
 
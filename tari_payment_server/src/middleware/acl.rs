@@ -1,21 +1,27 @@
 //! Access control list middleware for the Tari Payment Server.
 //! This middleware can be placed on any route or service.
 //!
-//! It will check the incoming request for a valid JWT token and then check the claims in the token against the required
-//! roles for the route. If the token is valid and the user has the required roles, the request will be allowed to
-//! continue. Otherwise, a 403 Forbidden response will be returned.
+//! It will check the incoming request for a valid JWT token and then check the roles in the token against the
+//! permissions that the route requires. If the token is valid and its roles grant the required permissions, the request
+//! will be allowed to continue. Otherwise, a 403 Forbidden response will be returned.
 //!
 //! Specifically, the following checks are performed:
 //! * The JWT token signature is valid (user cannot manipulate the token claims)
 //! * The public key matches the one specified in the server config (User cannot simply generate their own signatures)
 //! * The token has not expired
-//! * The user has the required roles to access the route
+//! * The token belongs to a login session that has not been revoked (see [`RevocationList`]). Revoked tokens are
+//!   refused with a 401 Unauthorized response, so that clients know to log in again.
+//! * The user's roles grant the permissions that the route requires. Which permissions a role grants is looked up in
+//!   the [`RolePermissions`] table, so edits to roles apply to tokens that have already been issued.
+//! * Access tokens that are scoped to a merchant are only used on routes that are aware of merchants, unless the route
+//!   does not require any permissions
 
 use std::rc::Rc;
 
 use actix_web::{
     dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
-    error::{ErrorForbidden, ErrorInternalServerError},
+    error::{ErrorForbidden, ErrorInternalServerError, ErrorUnauthorized},
+    web,
     Error,
     HttpMessage,
 };
@@ -24,17 +30,30 @@ use futures::{
     FutureExt,
 };
 use log::*;
-use tari_payment_engine::db_types::Role;
+use tari_payment_engine::db_types::Permission;
 
-use crate::auth::JwtClaims;
+use crate::auth::{JwtClaims, RevocationList, RolePermissions};
 
 pub struct AclMiddlewareFactory {
-    required_roles: Vec<Role>,
+    required_permissions: Vec<Permission>,
+    merchant_aware: bool,
 }
 
 impl AclMiddlewareFactory {
-    pub fn new(required_roles: &[Role]) -> Self {
-        AclMiddlewareFactory { required_roles: required_roles.to_vec() }
+    pub fn new(required_permissions: &[Permission]) -> Self {
+        AclMiddlewareFactory { required_permissions: required_permissions.to_vec(), merchant_aware: false }
+    }
+
+    /// For routes that restrict themselves to the merchant in the access token, so that merchant-scoped tokens may
+    /// use them.
+    pub fn for_merchants(required_permissions: &[Permission]) -> Self {
+        AclMiddlewareFactory { required_permissions: required_permissions.to_vec(), merchant_aware: true }
+    }
+
+    /// Only checks that the access token has not been revoked. Wrap authenticated scopes in this, so that the routes in
+    /// them that do not need any permissions are covered as well.
+    pub fn authenticated() -> Self {
+        AclMiddlewareFactory { required_permissions: Vec::new(), merchant_aware: true }
     }
 }
 
@@ -51,12 +70,17 @@ where
     type Transform = AclMiddlewareService<S>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ok(AclMiddlewareService { required_roles: self.required_roles.clone(), service: Rc::new(service) })
+        ok(AclMiddlewareService {
+            required_permissions: self.required_permissions.clone(),
+            merchant_aware: self.merchant_aware,
+            service: Rc::new(service),
+        })
     }
 }
 
 pub struct AclMiddlewareService<S> {
-    required_roles: Vec<Role>,
+    required_permissions: Vec<Permission>,
+    merchant_aware: bool,
     service: Rc<S>,
 }
 
@@ -73,7 +97,8 @@ where
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let service = Rc::clone(&self.service);
-        let required_roles = self.required_roles.clone();
+        let required_permissions = self.required_permissions.clone();
+        let merchant_aware = self.merchant_aware;
         async move {
             trace!("🔐️ Checking ACL for request");
             let jwt_claims = req
@@ -86,13 +111,22 @@ where
                 })?
                 .clone();
             trace!("🔐️ Claims decoded. {jwt_claims:?}");
-            // SuperAdmin can access any route
-            let mut approved = jwt_claims.roles.contains(&Role::SuperAdmin);
-            if approved {
-                info!("🔐️ SuperAdmin access granted to {} for {}", jwt_claims.address, req.uri());
+            check_session(&req, &jwt_claims)?;
+            // Roles in a merchant-scoped token only apply to that merchant's orders
+            let needs_admin = !required_permissions.is_empty();
+            if let Some(merchant) = jwt_claims.merchant.as_ref().filter(|_| needs_admin && !merchant_aware) {
+                warn!(
+                    "🔐️ Token for '{}' is scoped to merchant {merchant}. Denying access to {}",
+                    jwt_claims.address,
+                    req.uri()
+                );
+                return Err(ErrorForbidden("This endpoint is not available to merchant-scoped tokens."));
             }
-            approved |= required_roles.iter().all(|role| jwt_claims.roles.contains(role));
-            if approved {
+            let role_permissions = req.app_data::<web::Data<RolePermissions>>().ok_or_else(|| {
+                error!("🔐️ No role permissions have been configured. Denying access to {}", req.uri());
+                ErrorInternalServerError("No role permissions have been configured")
+            })?;
+            if role_permissions.allows(&jwt_claims.roles, &required_permissions) {
                 trace!("🔐️ User approved for endpoint {}", req.uri());
                 service.call(req).await
             } else {
@@ -103,3 +137,20 @@ where
         .boxed_local()
     }
 }
+
+/// Access tokens must belong to a session that has not been revoked
+fn check_session(req: &ServiceRequest, claims: &JwtClaims) -> Result<(), Error> {
+    let Some(session) = claims.session else {
+        warn!("🔐️ Token for '{}' does not belong to a session. Denying access to {}", claims.address, req.uri());
+        return Err(ErrorUnauthorized("This access token cannot be used any more. Please log in again."));
+    };
+    let revocations = req.app_data::<web::Data<RevocationList>>().ok_or_else(|| {
+        error!("🔐️ No revocation list has been configured. Denying access to {}", req.uri());
+        ErrorInternalServerError("No revocation list has been configured")
+    })?;
+    if revocations.is_revoked(session) {
+        warn!("🔐️ Session #{session} for '{}' has been revoked. Denying access to {}", claims.address, req.uri());
+        return Err(ErrorUnauthorized("This access token has been revoked. Please log in again."));
+    }
+    Ok(())
+}
@@ -0,0 +1,220 @@
+//! Audit middleware for the Tari Payment Server.
+//!
+//! Every route that requires permissions is wrapped in this middleware (see the `route!` macro). It records each call
+//! that changes something in the admin audit log, along with:
+//! * the address, roles and merchant in the caller's access token,
+//! * the name of the route, and the path and query parameters and request body of the call,
+//! * the reason for the call. This is the `reason` field of the request body if there is one, or else the
+//!   [`AUDIT_REASON_HEADER`] header,
+//! * the client's IP address, which takes the proxy settings in [`ServerOptions`] into account,
+//! * the outcome of the call, including calls that were refused for lack of permissions.
+//!
+//! `GET` requests are not recorded, since they do not change anything.
+//!
+//! The middleware must sit outside the ACL middleware, so that refused calls are recorded too. Calls are recorded once
+//! the route has responded. If the record cannot be written, the error is logged, but the response is still returned,
+//! since the action has already taken place.
+
+use std::{collections::HashMap, rc::Rc};
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    error::ErrorInternalServerError,
+    http::{Method, StatusCode},
+    web,
+    Error,
+    HttpMessage,
+};
+use futures::{
+    future::{ok, LocalBoxFuture, Ready},
+    FutureExt,
+};
+use log::*;
+use serde_json::{json, Map, Value};
+use tari_payment_engine::{
+    db_types::{AdminAction, AuditOutcome, NewAdminAction},
+    tpe_api::audit_api::AuditApi,
+    traits::{AuditLog, AuditLogError},
+};
+
+use crate::{
+    auth::JwtClaims,
+    config::ServerOptions,
+    helpers::{bytes_to_payload, get_remote_ip},
+};
+
+/// Callers can give a reason for calls whose request body has no `reason` field in this header.
+pub const AUDIT_REASON_HEADER: &str = "X-Audit-Reason";
+
+/// Fields of the request body that are never written to the audit log
+const REDACTED_FIELDS: &[&str] = &["secret"];
+
+type Recorder = dyn Fn(NewAdminAction) -> LocalBoxFuture<'static, Result<AdminAction, AuditLogError>>;
+
+/// Writes admin actions to the audit log. The middleware is not generic over the database backend, so the backend is
+/// hidden behind this type, which is added to the app data.
+#[derive(Clone)]
+pub struct AuditTrail {
+    recorder: Rc<Recorder>,
+}
+
+impl AuditTrail {
+    pub fn new<B: AuditLog + 'static>(api: AuditApi<B>) -> Self {
+        let api = Rc::new(api);
+        let recorder = move |action: NewAdminAction| {
+            let api = Rc::clone(&api);
+            async move { api.record_admin_action(&action).await }.boxed_local()
+        };
+        Self { recorder: Rc::new(recorder) }
+    }
+
+    pub async fn record(&self, action: NewAdminAction) -> Result<AdminAction, AuditLogError> {
+        (self.recorder)(action).await
+    }
+}
+
+pub struct AuditMiddlewareFactory {
+    endpoint: &'static str,
+}
+
+impl AuditMiddlewareFactory {
+    /// `endpoint` is the name that the route is recorded under, e.g. `update_price`
+    pub fn new(endpoint: &'static str) -> Self {
+        AuditMiddlewareFactory { endpoint }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AuditMiddlewareFactory
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Error = Error;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+    type InitError = ();
+    type Response = ServiceResponse<B>;
+    type Transform = AuditMiddlewareService<S>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AuditMiddlewareService { endpoint: self.endpoint, service: Rc::new(service) })
+    }
+}
+
+pub struct AuditMiddlewareService<S> {
+    endpoint: &'static str,
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for AuditMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Response = ServiceResponse<B>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let endpoint = self.endpoint;
+        async move {
+            if [Method::GET, Method::HEAD, Method::OPTIONS].contains(req.method()) {
+                return service.call(req).await;
+            }
+            // Requests without claims are refused by the ACL middleware, and there is no actor to record
+            let Some(claims) = req.extensions().get::<JwtClaims>().cloned() else {
+                return service.call(req).await;
+            };
+            let trail = req.app_data::<web::Data<AuditTrail>>().cloned().ok_or_else(|| {
+                error!("📜️ No audit trail has been configured. Refusing {endpoint}");
+                ErrorInternalServerError("No audit trail has been configured")
+            })?;
+            let body = req.extract::<web::Bytes>().await?;
+            let action = pending_action(endpoint, &req, &claims, &body);
+            req.set_payload(bytes_to_payload(body));
+            let result = service.call(req).await;
+            let action = match &result {
+                Ok(res) => action.finish(res.status(), res.response().error().map(ToString::to_string)),
+                Err(e) => action.finish(e.as_response_error().status_code(), Some(e.to_string())),
+            };
+            match trail.record(action).await {
+                Ok(action) => {
+                    debug!(
+                        "📜️ Recorded {} call to {endpoint} by {} as admin action #{}",
+                        action.outcome, claims.address, action.id
+                    )
+                },
+                Err(e) => {
+                    error!("📜️ Could not record the call to {endpoint} by {} in the audit log. {e}", claims.address)
+                },
+            }
+            result
+        }
+        .boxed_local()
+    }
+}
+
+/// The parts of an admin action that are known before the route is called
+struct PendingAction(NewAdminAction);
+
+impl PendingAction {
+    fn finish(self, status: StatusCode, error: Option<String>) -> NewAdminAction {
+        let outcome = match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => AuditOutcome::Denied,
+            s if s.is_success() || s.is_redirection() => AuditOutcome::Succeeded,
+            _ => AuditOutcome::Failed,
+        };
+        NewAdminAction { outcome, status_code: i64::from(status.as_u16()), error, ..self.0 }
+    }
+}
+
+fn pending_action(endpoint: &str, req: &ServiceRequest, claims: &JwtClaims, body: &[u8]) -> PendingAction {
+    let body = request_body(body);
+    let reason = body
+        .get("reason")
+        .and_then(Value::as_str)
+        .map(String::from)
+        .or_else(|| req.headers().get(AUDIT_REASON_HEADER).and_then(|v| v.to_str().ok()).map(String::from));
+    let path = req.match_info().iter().map(|(k, v)| (k.to_string(), Value::from(v))).collect::<Map<_, _>>();
+    let query = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(web::Query::into_inner)
+        .unwrap_or_default();
+    let (use_x_forwarded_for, use_forwarded) = req
+        .app_data::<web::Data<ServerOptions>>()
+        .map(|o| (o.use_x_forwarded_for, o.use_forwarded))
+        .unwrap_or_default();
+    let client_ip = get_remote_ip(req.request(), use_x_forwarded_for, use_forwarded).map(|ip| ip.to_string());
+    PendingAction(NewAdminAction {
+        actor: claims.address.clone(),
+        roles: claims.roles.clone(),
+        merchant: claims.merchant.clone(),
+        endpoint: endpoint.to_string(),
+        method: req.method().to_string(),
+        path: req.path().to_string(),
+        parameters: json!({ "path": path, "query": query, "body": body }),
+        reason,
+        client_ip,
+        outcome: AuditOutcome::Failed,
+        status_code: 0,
+        error: None,
+    })
+}
+
+/// The request body as JSON. Bodies that are not JSON are recorded as text.
+fn request_body(body: &[u8]) -> Value {
+    if body.is_empty() {
+        return Value::Null;
+    }
+    let mut value = serde_json::from_slice(body).unwrap_or_else(|_| Value::from(String::from_utf8_lossy(body)));
+    if let Some(fields) = value.as_object_mut() {
+        for field in REDACTED_FIELDS {
+            if let Some(v) = fields.get_mut(*field) {
+                *v = Value::from("<redacted>");
+            }
+        }
+    }
+    value
+}
@@ -9,15 +9,19 @@
 //!
 //! You can use this middleware to verify the HMAC signature of incoming requests by wrapping all shopify webhook
 //! calls with this middleware.
+//!
+//! When several stores send webhooks to the same server, each store signs them with its own secret. Use
+//! [`HmacMiddlewareFactory::with_shop_keys`] to look up the secret from the header that identifies the store. Requests
+//! from unknown stores are checked against the default key.
 
 use std::{
+    collections::HashMap,
     future::{ready, Ready},
     rc::Rc,
 };
 
-use actix_http::h1;
 use actix_web::{
-    dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
     error::{ErrorBadRequest, ErrorForbidden},
     web,
     Error,
@@ -26,18 +30,40 @@ use futures::future::LocalBoxFuture;
 use log::{trace, warn};
 use tpg_common::Secret;
 
-use crate::helpers::calculate_hmac;
+use crate::helpers::{bytes_to_payload, calculate_hmac};
 
 pub struct HmacMiddlewareFactory {
     hmac_header: String,
     key: Secret<String>,
+    shop_keys: Rc<ShopKeys>,
     // If false, then the middleware will not check the HMAC signature and always allow the call
     enabled: bool,
 }
 
+/// The secrets for individual stores, keyed by the (lowercase) value of `shop_header`
+#[derive(Default)]
+struct ShopKeys {
+    shop_header: String,
+    keys: HashMap<String, Secret<String>>,
+}
+
+impl ShopKeys {
+    fn key_for(&self, req: &ServiceRequest) -> Option<&Secret<String>> {
+        let shop = req.headers().get(&self.shop_header)?.to_str().ok()?;
+        self.keys.get(&shop.to_lowercase())
+    }
+}
+
 impl HmacMiddlewareFactory {
     pub fn new(hmac_header: &str, key: Secret<String>, enabled: bool) -> Self {
-        HmacMiddlewareFactory { hmac_header: hmac_header.into(), key, enabled }
+        HmacMiddlewareFactory { hmac_header: hmac_header.into(), key, shop_keys: Rc::default(), enabled }
+    }
+
+    /// Checks requests that carry one of the given shop domains in `shop_header` against that shop's key, rather than
+    /// the default key.
+    pub fn with_shop_keys(mut self, shop_header: &str, keys: HashMap<String, Secret<String>>) -> Self {
+        self.shop_keys = Rc::new(ShopKeys { shop_header: shop_header.into(), keys });
+        self
     }
 }
 
@@ -57,6 +83,7 @@ where
         ready(Ok(HmacMiddlewareService {
             hmac_header: self.hmac_header.clone(),
             key: self.key.clone(),
+            shop_keys: Rc::clone(&self.shop_keys),
             enabled: self.enabled,
             service: Rc::new(service),
         }))
@@ -66,6 +93,7 @@ where
 pub struct HmacMiddlewareService<S> {
     hmac_header: String,
     key: Secret<String>,
+    shop_keys: Rc<ShopKeys>,
     enabled: bool,
     service: Rc<S>,
 }
@@ -84,7 +112,7 @@ where
 
     fn call(&self, mut req: ServiceRequest) -> Self::Future {
         let service = Rc::clone(&self.service);
-        let secret = self.key.reveal().clone();
+        let secret = self.shop_keys.key_for(&req).unwrap_or(&self.key).reveal().clone();
         let hmac_header = self.hmac_header.clone();
         let enabled = self.enabled;
         Box::pin(async move {
@@ -114,9 +142,3 @@ where
         })
     }
 }
-
-fn bytes_to_payload(buf: web::Bytes) -> Payload {
-    let (_, mut pl) = h1::Payload::create(true);
-    pl.unread_data(buf);
-    Payload::from(pl)
-}
@@ -1,5 +1,16 @@
 mod acl;
+mod audit;
 mod hmac;
+mod rate_limit;
 
 pub use acl::{AclMiddlewareFactory, AclMiddlewareService};
+pub use audit::{AuditMiddlewareFactory, AuditMiddlewareService, AuditTrail, AUDIT_REASON_HEADER};
 pub use hmac::{HmacMiddlewareFactory, HmacMiddlewareService};
+pub use rate_limit::{
+    MemoryRateLimits,
+    RateLimitGroup,
+    RateLimitKey,
+    RateLimitMiddlewareFactory,
+    RateLimitMiddlewareService,
+    RateLimiter,
+};
@@ -0,0 +1,330 @@
+//! Rate limiting middleware for the Tari Payment Server.
+//!
+//! The public endpoints and the wallet endpoints verify signatures or write to the database on every call, so a
+//! misbehaving client can tie up the server by hammering them. Each of these routes is limited as part of a
+//! [`RateLimitGroup`], which says which of the limits in [`RateLimitConfig`] applies, and which clients get a bucket of
+//! their own ([`RateLimitKey`]). See [`tari_payment_engine::tpe_api::rate_limit_objects`] for how the buckets work.
+//!
+//! Refused requests get a `429 Too Many Requests` response with a `Retry-After` header. Every decision is counted in
+//! the `tpg_rate_limit_requests_total` Prometheus counter, which is served at `/metrics` on the metrics address
+//! (`TPG_METRICS_ADDRESS`).
+//!
+//! The buckets are kept in a [`RateLimitStore`]. By default each server keeps its own buckets in memory
+//! ([`MemoryRateLimits`]). When several instances run behind a load balancer, they can share their buckets through the
+//! database instead (`TPG_RATE_LIMIT_SHARED`). If the store cannot be reached, the error is logged and the request is
+//! let through, so that an outage of a shared store does not take the server down with it.
+
+use std::{
+    collections::HashMap,
+    rc::Rc,
+    sync::{Arc, Mutex, OnceLock, PoisonError},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+    web,
+    Error,
+    HttpMessage,
+};
+use futures::{
+    future::{ok, LocalBoxFuture, Ready},
+    FutureExt,
+};
+use log::*;
+use prometheus::{register_int_counter_vec, IntCounterVec};
+use serde_json::Value;
+use tari_common_types::tari_address::TariAddress;
+use tari_payment_engine::{
+    tpe_api::rate_limit_objects::{RateLimit, RateLimitDecision, TokenBucket},
+    traits::{RateLimitError, RateLimitStore},
+};
+
+use crate::{
+    auth::{unverified_login_address, JwtClaims, LOGIN_TOKEN_HEADER},
+    config::{RateLimitConfig, ServerOptions},
+    errors::ServerError,
+    helpers::{bytes_to_payload, get_remote_ip},
+};
+
+/// How often the in-memory store forgets the buckets that have refilled
+const MEMORY_PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Who a bucket belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitKey {
+    /// The client's IP address, which takes the proxy settings in [`ServerOptions`] into account
+    RemoteIp,
+    /// The address in the caller's access token, or in the login token on `/auth`
+    Address,
+    /// The wallet address that signed the request body. This is `auth.address` in wallet notifications, and `address`
+    /// in order claims.
+    WalletAddress,
+}
+
+impl RateLimitKey {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::RemoteIp => "ip",
+            Self::Address => "address",
+            Self::WalletAddress => "wallet",
+        }
+    }
+}
+
+/// A set of routes that share a rate limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitGroup {
+    /// `/auth`
+    Auth,
+    /// `/order/claim`
+    Claim,
+    /// The `/wallet` payment and confirmation notifications
+    Wallet,
+    /// The storefront webhooks
+    Webhooks,
+}
+
+impl RateLimitGroup {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Auth => "auth",
+            Self::Claim => "claim",
+            Self::Wallet => "wallet",
+            Self::Webhooks => "webhooks",
+        }
+    }
+
+    /// A request takes a token from the bucket of each of these clients, and is refused if any of them is empty.
+    /// Logins, claims and wallet notifications are limited per address as well as per IP address, so that spreading
+    /// requests over many addresses, or many IP addresses, does not get around the limit. Wallet notifications that
+    /// claim random, unregistered wallet addresses are still limited by the IP address that sends them.
+    pub fn keys(self) -> &'static [RateLimitKey] {
+        match self {
+            Self::Auth => &[RateLimitKey::RemoteIp, RateLimitKey::Address],
+            Self::Claim => &[RateLimitKey::RemoteIp, RateLimitKey::WalletAddress],
+            Self::Wallet => &[RateLimitKey::RemoteIp, RateLimitKey::WalletAddress],
+            Self::Webhooks => &[RateLimitKey::RemoteIp],
+        }
+    }
+
+    pub fn limit(self, config: &RateLimitConfig) -> Option<RateLimit> {
+        match self {
+            Self::Auth => config.auth,
+            Self::Claim => config.claim,
+            Self::Wallet => config.wallet,
+            Self::Webhooks => config.webhooks,
+        }
+    }
+}
+
+type TokenTaker = dyn Fn(String, RateLimit) -> LocalBoxFuture<'static, Result<RateLimitDecision, RateLimitError>>;
+
+/// Takes tokens from the buckets in a [`RateLimitStore`]. The middleware is not generic over the store, so the store
+/// is hidden behind this type, which is added to the app data. Routes are not limited if there is no rate limiter in
+/// the app data.
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    taker: Rc<TokenTaker>,
+}
+
+impl RateLimiter {
+    pub fn new<S: RateLimitStore + 'static>(store: S, config: RateLimitConfig) -> Self {
+        let store = Rc::new(store);
+        let taker = move |key: String, limit: RateLimit| {
+            let store = Rc::clone(&store);
+            async move { store.take_token(&key, &limit).await }.boxed_local()
+        };
+        Self { config, taker: Rc::new(taker) }
+    }
+
+    pub fn config(&self) -> &RateLimitConfig {
+        &self.config
+    }
+
+    pub async fn take_token(&self, key: String, limit: RateLimit) -> Result<RateLimitDecision, RateLimitError> {
+        (self.taker)(key, limit).await
+    }
+}
+
+/// Keeps the buckets in this server's memory. Clones share their buckets, so a single store can serve every worker.
+#[derive(Clone)]
+pub struct MemoryRateLimits {
+    buckets: Arc<Mutex<MemoryBuckets>>,
+}
+
+struct MemoryBuckets {
+    buckets: HashMap<String, TokenBucket>,
+    pruned_at: Instant,
+}
+
+impl Default for MemoryRateLimits {
+    fn default() -> Self {
+        let buckets = MemoryBuckets { buckets: HashMap::new(), pruned_at: Instant::now() };
+        Self { buckets: Arc::new(Mutex::new(buckets)) }
+    }
+}
+
+impl RateLimitStore for MemoryRateLimits {
+    async fn take_token(&self, key: &str, limit: &RateLimit) -> Result<RateLimitDecision, RateLimitError> {
+        let now = Instant::now();
+        let mut state = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        // There is no background worker for the in-memory store, so it forgets old buckets as it goes
+        if now.duration_since(state.pruned_at) >= MEMORY_PRUNE_INTERVAL {
+            state.buckets.retain(|_, bucket| !bucket.is_expired(now));
+            state.pruned_at = now;
+        }
+        let bucket = state.buckets.entry(key.to_string()).or_insert_with(|| TokenBucket::full(limit, now));
+        Ok(bucket.take(limit, now))
+    }
+
+    async fn prune_rate_limits(&self) -> Result<u64, RateLimitError> {
+        let now = Instant::now();
+        let mut state = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let count = state.buckets.len();
+        state.buckets.retain(|_, bucket| !bucket.is_expired(now));
+        state.pruned_at = now;
+        Ok((count - state.buckets.len()) as u64)
+    }
+}
+
+/// Counts the rate limit decisions, by group and outcome (`allowed`, `limited` or `error`)
+fn requests_counter() -> &'static IntCounterVec {
+    static COUNTER: OnceLock<IntCounterVec> = OnceLock::new();
+    COUNTER.get_or_init(|| {
+        register_int_counter_vec!(
+            "tpg_rate_limit_requests_total",
+            "Requests to rate limited endpoints, by rate limit group and outcome",
+            &["group", "outcome"]
+        )
+        .expect("The rate limit counter is only registered once")
+    })
+}
+
+pub struct RateLimitMiddlewareFactory {
+    group: RateLimitGroup,
+}
+
+impl RateLimitMiddlewareFactory {
+    pub fn new(group: RateLimitGroup) -> Self {
+        RateLimitMiddlewareFactory { group }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimitMiddlewareFactory
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Error = Error;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+    type InitError = ();
+    type Response = ServiceResponse<B>;
+    type Transform = RateLimitMiddlewareService<S>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimitMiddlewareService { group: self.group, service: Rc::new(service) })
+    }
+}
+
+pub struct RateLimitMiddlewareService<S> {
+    group: RateLimitGroup,
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+    type Response = ServiceResponse<B>;
+
+    forward_ready!(service);
+
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let group = self.group;
+        async move {
+            let Some(limiter) = req.app_data::<web::Data<RateLimiter>>().cloned() else {
+                return service.call(req).await;
+            };
+            let Some(limit) = group.limit(limiter.config()) else {
+                return service.call(req).await;
+            };
+            let counter = requests_counter();
+            for key in client_keys(group, &mut req).await? {
+                match limiter.take_token(key.clone(), limit).await {
+                    Ok(decision) if decision.allowed => {},
+                    Ok(decision) => {
+                        counter.with_label_values(&[group.name(), "limited"]).inc();
+                        let retry_after = decision.retry_after.unwrap_or_default();
+                        info!("🚥️ {key} has exceeded the {} rate limit of {limit}", group.name());
+                        let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                        return Err(ServerError::TooManyRequests(seconds).into());
+                    },
+                    Err(e) => {
+                        counter.with_label_values(&[group.name(), "error"]).inc();
+                        error!(
+                            "🚥️ Could not check the {} rate limit for {key}. The request is allowed. {e}",
+                            group.name()
+                        );
+                        return service.call(req).await;
+                    },
+                }
+            }
+            counter.with_label_values(&[group.name(), "allowed"]).inc();
+            service.call(req).await
+        }
+        .boxed_local()
+    }
+}
+
+/// The bucket keys for each of the group's clients. Clients that cannot be identified, such as the wallet address of
+/// a request body that is not a notification, are skipped.
+async fn client_keys(group: RateLimitGroup, req: &mut ServiceRequest) -> Result<Vec<String>, Error> {
+    let mut keys = Vec::with_capacity(group.keys().len());
+    for key in group.keys().iter().copied() {
+        let client = match key {
+            RateLimitKey::RemoteIp => remote_ip(req),
+            RateLimitKey::Address => caller_address(req),
+            RateLimitKey::WalletAddress => {
+                let body = req.extract::<web::Bytes>().await?;
+                let address = signing_wallet(&body);
+                req.set_payload(bytes_to_payload(body));
+                address
+            },
+        };
+        match client {
+            Some(client) => keys.push(format!("{}:{}:{client}", group.name(), key.name())),
+            None => debug!("🚥️ No client {} for the {} rate limit. It is skipped", key.name(), group.name()),
+        }
+    }
+    Ok(keys)
+}
+
+fn remote_ip(req: &ServiceRequest) -> Option<String> {
+    let (use_x_forwarded_for, use_forwarded) = req
+        .app_data::<web::Data<ServerOptions>>()
+        .map(|o| (o.use_x_forwarded_for, o.use_forwarded))
+        .unwrap_or_default();
+    get_remote_ip(req.request(), use_x_forwarded_for, use_forwarded).map(|ip| ip.to_string())
+}
+
+fn caller_address(req: &ServiceRequest) -> Option<String> {
+    if let Some(claims) = req.extensions().get::<JwtClaims>() {
+        return Some(claims.address.to_base58());
+    }
+    let token = req.headers().get(LOGIN_TOKEN_HEADER)?.to_str().ok()?;
+    unverified_login_address(token).map(|address| address.to_base58())
+}
+
+/// Addresses are parsed and written out again, so that the same wallet always has the same bucket
+fn signing_wallet(body: &[u8]) -> Option<String> {
+    let body = serde_json::from_slice::<Value>(body).ok()?;
+    let address = body.pointer("/auth/address").or_else(|| body.get("address"))?.as_str()?;
+    address.parse::<TariAddress>().ok().map(|address| address.to_base58())
+}
@@ -0,0 +1,43 @@
+use log::*;
+use tari_payment_engine::{
+    events::EventProducers,
+    tpe_api::payment_objects::OverpaymentPolicy,
+    traits::PaymentGatewayDatabase,
+    OrderFlowApi,
+};
+use tokio::task::JoinHandle;
+
+/// How often idle balances are checked against the overpayment policy.
+const OVERPAYMENT_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+/// Starts the overpayment worker, which periodically applies the overpayment policy to idle address balances. Do not
+/// await the returned JoinHandle, as it will run indefinitely.
+///
+/// Spawned on the local task set, like the [expiry worker](crate::expiry_worker::start_expiry_worker).
+pub fn start_overpayment_worker<B: PaymentGatewayDatabase + 'static>(
+    db: B,
+    producers: EventProducers,
+    policy: OverpaymentPolicy,
+) -> JoinHandle<()> {
+    actix_web::rt::spawn(async move {
+        let mut timer = tokio::time::interval(OVERPAYMENT_CHECK_INTERVAL);
+        let api = OrderFlowApi::new(db, producers);
+        info!("💸️ Overpayment worker started. Policy: {policy}");
+        loop {
+            timer.tick().await;
+            debug!("💸️ Checking for idle balances");
+            match api.process_unspent_balances(policy).await {
+                Ok(refunds) if refunds.is_empty() => debug!("💸️ No idle balances to refund"),
+                Ok(refunds) => {
+                    for refund in &refunds {
+                        info!(
+                            "💸️ Refund #{} of {} to {} is {}",
+                            refund.id, refund.amount, refund.address, refund.status
+                        );
+                    }
+                },
+                Err(e) => error!("💸️ Error applying the overpayment policy: {e}"),
+            }
+        }
+    })
+}
@@ -0,0 +1,172 @@
+use log::*;
+use tari_payment_engine::{
+    tpe_api::exchange_objects::ExchangeRate,
+    traits::{ExchangeRateError, ExchangeRates},
+};
+use tokio::task::JoinHandle;
+
+use crate::{
+    config::RateFeedConfig,
+    integrations::{
+        rate_sources::{aggregate_rates, ExchangeRateSource},
+        storefront::Storefronts,
+    },
+};
+
+/// The storefront prices are in US dollars, so only changes to the USD rate are pushed to the storefronts.
+const STOREFRONT_CURRENCY: &str = "USD";
+
+/// Starts the rate feed worker, which polls the configured exchange rate sources and stores the aggregated rate. Do
+/// not await the returned JoinHandle, as it will run indefinitely.
+///
+/// Only the default merchant's rates are fed. Merchants that set their own rates keep them, and the rest fall back
+/// to the default merchant's. When the USD rate changes, the Tari prices on every storefront are updated too.
+///
+/// Spawned on the local task set, like the [expiry worker](crate::expiry_worker::start_expiry_worker).
+pub fn start_rate_feed_worker<B: ExchangeRates + 'static>(
+    db: B,
+    storefronts: Storefronts,
+    config: RateFeedConfig,
+) -> JoinHandle<()> {
+    actix_web::rt::spawn(async move {
+        let mut timer = tokio::time::interval(config.poll_interval);
+        let sources = config.sources.iter().map(|s| s as &dyn ExchangeRateSource).collect::<Vec<_>>();
+        let names = sources.iter().map(|s| s.name()).collect::<Vec<_>>().join(", ");
+        info!("📈️ Exchange rate feed started. Sources: {names}");
+        loop {
+            timer.tick().await;
+            for currency in &config.currencies {
+                let rate = match update_exchange_rate(&db, &sources, currency, &config).await {
+                    Ok(Some(rate)) => rate,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        error!("📈️ Could not update the {currency} exchange rate. {e}");
+                        continue;
+                    },
+                };
+                if rate.base_currency == STOREFRONT_CURRENCY {
+                    storefronts.update_prices(&rate, &db).await;
+                }
+            }
+        }
+    })
+}
+
+/// Polls every source for the current rate of `currency`, and stores the aggregated rate if it differs from the last
+/// one. Returns the new rate, or `None` if the rate did not change or the sources did not agree.
+///
+/// An unchanged rate is marked as confirmed instead, so that the rate circuit breaker does not treat a stable price as
+/// a stale one.
+pub async fn update_exchange_rate<B: ExchangeRates>(
+    db: &B,
+    sources: &[&dyn ExchangeRateSource],
+    currency: &str,
+    config: &RateFeedConfig,
+) -> Result<Option<ExchangeRate>, ExchangeRateError> {
+    let mut rates = Vec::with_capacity(sources.len());
+    let results = futures::future::join_all(sources.iter().map(|s| s.fetch_rate(currency))).await;
+    for (source, result) in sources.iter().zip(results) {
+        match result {
+            Ok(rate) => {
+                trace!("📈️ {} reports 1 {currency} = {rate}", source.name());
+                rates.push(rate);
+            },
+            Err(e) => warn!("📈️ Could not fetch the {currency} rate from {}. {e}", source.name()),
+        }
+    }
+    let Some(rate) = aggregate_rates(&rates, config.max_deviation_pct, config.min_sources) else {
+        warn!(
+            "📈️ Only {} of {} sources reported a usable {currency} rate. Need at least {}. The rate has not been \
+             updated.",
+            rates.len(),
+            sources.len(),
+            config.min_sources
+        );
+        return Ok(None);
+    };
+    match db.fetch_last_rate(currency).await {
+        Ok(last) if last.rate == rate => {
+            debug!("📈️ The {currency} rate is unchanged at {rate}");
+            db.confirm_exchange_rate(&last).await?;
+            return Ok(None);
+        },
+        Ok(_) | Err(ExchangeRateError::RateDoesNotExist(_)) => {},
+        Err(e) => return Err(e),
+    }
+    let new_rate = ExchangeRate::new(currency.to_string(), rate, None);
+    db.set_exchange_rate(&new_rate).await?;
+    info!("📈️ Exchange rate updated to 1 {currency} = {rate}");
+    Ok(Some(new_rate))
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Duration;
+    use futures::{future::LocalBoxFuture, FutureExt};
+    use tari_payment_engine::{
+        tpe_api::{exchange_objects::RateCircuitBreaker, exchange_rate_api::ExchangeRateApi},
+        InMemoryDatabase,
+    };
+    use tpg_common::MicroTari;
+
+    use super::*;
+    use crate::integrations::rate_sources::RateSourceError;
+
+    struct FixedSource(Option<i64>);
+
+    impl ExchangeRateSource for FixedSource {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn fetch_rate<'a>(&'a self, _currency: &'a str) -> LocalBoxFuture<'a, Result<MicroTari, RateSourceError>> {
+            let result = self.0.map(MicroTari::from).ok_or_else(|| RateSourceError::RequestFailed("offline".into()));
+            async move { result }.boxed_local()
+        }
+    }
+
+    fn config(min_sources: usize) -> RateFeedConfig {
+        RateFeedConfig { min_sources, max_deviation_pct: 10, ..RateFeedConfig::default() }
+    }
+
+    #[actix_web::test]
+    async fn aggregated_rate_is_stored() {
+        let db = InMemoryDatabase::new();
+        let (a, b, c, offline) =
+            (FixedSource(Some(200)), FixedSource(Some(210)), FixedSource(Some(900)), FixedSource(None));
+        let sources: Vec<&dyn ExchangeRateSource> = vec![&a, &b, &c, &offline];
+        let rate = update_exchange_rate(&db, &sources, "USD", &config(2)).await.unwrap().unwrap();
+        assert_eq!(rate.rate, MicroTari::from(205));
+        assert_eq!(db.fetch_last_rate("USD").await.unwrap().rate, MicroTari::from(205));
+        // An unchanged rate is not stored again
+        assert!(update_exchange_rate(&db, &sources, "USD", &config(2)).await.unwrap().is_none());
+        // Too few sources agree, so the last rate stands
+        let sources: Vec<&dyn ExchangeRateSource> = vec![&a, &c, &offline];
+        assert!(update_exchange_rate(&db, &sources, "USD", &config(2)).await.unwrap().is_none());
+        assert_eq!(db.fetch_last_rate("USD").await.unwrap().rate, MicroTari::from(205));
+    }
+
+    #[actix_web::test]
+    async fn stable_rate_does_not_trip_the_breaker() {
+        let db = InMemoryDatabase::new();
+        let fx = ExchangeRateApi::new(db.clone());
+        let breaker = RateCircuitBreaker::new(Some(Duration::minutes(10)), None);
+        let source = FixedSource(Some(200));
+        let sources: Vec<&dyn ExchangeRateSource> = vec![&source];
+        update_exchange_rate(&db, &sources, "USD", &config(1)).await.unwrap().unwrap();
+        let rate = db.fetch_last_rate("USD").await.unwrap();
+        assert!(fx.check_circuit_breaker(&rate, &breaker).await.unwrap().is_none());
+
+        // The same rate, as it would look an hour later if nothing had polled it
+        let stale = ExchangeRate { confirmed_at: rate.confirmed_at - Duration::hours(1), ..rate.clone() };
+        assert!(fx.check_circuit_breaker(&stale, &breaker).await.unwrap().is_some(), "The rate has not been polled");
+
+        // The feed confirms the unchanged rate, which is fresh again, but the rate history is left alone
+        assert!(update_exchange_rate(&db, &sources, "USD", &config(1)).await.unwrap().is_none());
+        let confirmed = db.fetch_last_rate("USD").await.unwrap();
+        assert_eq!(confirmed.id, rate.id);
+        assert_eq!(confirmed.updated_at, rate.updated_at);
+        assert!(confirmed.confirmed_at >= rate.confirmed_at);
+        assert!(fx.check_circuit_breaker(&confirmed, &breaker).await.unwrap().is_none());
+    }
+}
@@ -23,29 +23,57 @@
 //! ```
 use std::{ops::Deref, str::FromStr};
 
-use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use actix_web::{get, http::header, web, HttpRequest, HttpResponse, Responder};
 use log::*;
+use prometheus::{Encoder, TextEncoder};
 use serde_json::json;
-use shopify_tools::ShopifyApi;
 use tari_common_types::tari_address::TariAddress;
 use tari_payment_engine::{
-    db_types::{CreditNote, Order, OrderId, OrderStatusType, Role, SerializedTariAddress},
+    db_types::{
+        default_merchant,
+        ApprovalOperation,
+        CreditNote,
+        NewApprovalRequest,
+        NewOrder,
+        NewRefund,
+        NewWebhookSubscription,
+        Order,
+        OrderId,
+        OrderStatusType,
+        Permission,
+        Role,
+        RoleDefinition,
+        SerializedTariAddress,
+        DEFAULT_MERCHANT,
+    },
     helpers::MemoSignature,
     order_objects::{OrderQueryFilter, OrderResult},
     tpe_api::{
         account_objects::{AddressHistory, CustomerHistory, Pagination},
+        approval_objects::ApprovalPolicy,
+        audit_api::AuditApi,
+        exchange_objects::RateCircuitBreaker,
         exchange_rate_api::ExchangeRateApi,
+        export_api::ExportApi,
+        export_objects::ExportRequest,
+        outbox_api::OutboxApi,
+        reconciliation_api::ReconciliationApi,
         wallet_api::WalletManagementApi,
+        webhook_api::WebhookApi,
     },
     traits::{
+        AccountApiError,
         AccountManagement,
+        AuditLog,
         AuthManagement,
+        EventOutbox,
         ExchangeRates,
         NewWalletInfo,
         PaymentGatewayDatabase,
         PaymentGatewayError,
         WalletAuth,
         WalletManagement,
+        WebhookManagement,
     },
     AccountApi,
     AuthApi,
@@ -54,28 +82,56 @@ use tari_payment_engine::{
 };
 
 use crate::{
-    auth::{check_login_token_signature, JwtClaims, TokenIssuer},
+    auth::{
+        check_login_token_signature,
+        generate_refresh_token,
+        hash_refresh_token,
+        JwtClaims,
+        RevocationList,
+        RolePermissions,
+        TokenIssuer,
+        LOGIN_TOKEN_HEADER,
+        REFRESH_TOKEN_HEADER,
+    },
     config::ServerOptions,
     data_objects::{
+        ApprovalQuery,
+        AuditQuery,
         ExchangeRateResult,
         JsonResponse,
+        LedgerEntriesQuery,
         ModifyOrderParams,
         MoveOrderParams,
         PaymentNotification,
+        RateHistoryQuery,
+        ReconcileParams,
+        RefundQuery,
+        RefundSentParams,
+        RejectApprovalParams,
+        RejectRefundParams,
         RoleUpdateRequest,
         TransactionConfirmationNotification,
         UpdateMemoParams,
         UpdatePriceParams,
+        UpdateWebhookParams,
+        WalletQuery,
+        WebhookDeliveryQuery,
     },
-    errors::ServerError,
+    errors::{AuthError, ServerError},
+    event_stream::{EventStream, EventStreamFilter},
     helpers::{get_remote_ip, try_extract_order_id},
-    shopify_routes::handle_shopify_order,
+    integrations::{
+        storefront::{price_new_order, Storefronts},
+        wallet_grpc::WalletGrpcClient,
+    },
+    middleware::RateLimitGroup,
+    storefront_routes::{conversion_failure, handle_new_order},
 };
 
 // Web-actix cannot handle generics in handlers, so it's implemented manually using the `route!` macro
 #[macro_export]
 macro_rules! route {
-    ($name:ident => $method:ident $path:literal requires [$($roles:ty),*]) => {
+    ($name:ident => $method:ident $path:literal requires [$($permissions:expr),*]) => {
         paste::paste! { pub struct [<$name:camel Route>];}
         paste::paste! {
                 impl [<$name:camel Route>] {
@@ -90,7 +146,8 @@ macro_rules! route {
                         .name(stringify!($name))
                         .guard(actix_web::guard::$method())
                         .to($name)
-                        .wrap($crate::middleware::AclMiddlewareFactory::new(&[$($roles),+]));
+                        .wrap($crate::middleware::AclMiddlewareFactory::new(&[$($permissions),*]))
+                        .wrap($crate::middleware::AuditMiddlewareFactory::new(stringify!($name)));
                     actix_web::dev::HttpServiceFactory::register(res, config);
                 }
             }
@@ -119,7 +176,42 @@ macro_rules! route {
         }}
     };
 
-    ($name:ident => $method:ident $path:literal impl $( $bounds:ty ),+ where requires [$($roles:ty),*])  => {
+    ($name:ident => $method:ident $path:literal impl $( $bounds:ty ),+ where requires [$($permissions:expr),*])  => {
+        $crate::route!(@guarded $name => $method $path impl [$($bounds),+] $crate::middleware::AclMiddlewareFactory::new(&[$($permissions),*]));
+    };
+
+    // Routes that only return or modify the orders of the merchant in the access token, if there is one. Tokens that
+    // are scoped to a merchant are refused on every other route that needs more than the `User` role.
+    ($name:ident => $method:ident $path:literal impl $( $bounds:ty ),+ where merchant requires [$($permissions:expr),*])  => {
+        $crate::route!(@guarded $name => $method $path impl [$($bounds),+] $crate::middleware::AclMiddlewareFactory::for_merchants(&[$($permissions),*]));
+    };
+
+    // Unauthenticated routes that anyone can call are rate limited as part of a `RateLimitGroup`
+    ($name:ident => $method:ident $path:literal impl $( $bounds:ty ),+ where limited by $group:expr)  => {
+        paste::paste! { pub struct [<$name:camel Route>]< $( [< T $bounds:camel> ],)+ >( $( core::marker::PhantomData<fn() -> [< T $bounds:camel> ] >,)+ );}
+        paste::paste! { impl< $( [< T $bounds:camel> ],)+ > [<$name:camel Route>]< $( [< T $bounds:camel> ],)+ > {
+            #[allow(clippy::new_without_default)]
+            pub fn new() -> Self {
+                Self($( core::marker::PhantomData::<fn() -> [< T $bounds:camel> ] >,)+)
+            }
+        }}
+        paste::paste! { impl<$( [< T $bounds:camel >] , )+> actix_web::dev::HttpServiceFactory for [<$name:camel Route>]<$([<T $bounds:camel>],)+>
+        where
+            $([<T $bounds:camel>]: $bounds + 'static,)+
+        {
+            fn register(self, config: &mut actix_web::dev::AppService) {
+                let res = actix_web::Resource::new($path)
+                    .name(stringify!($name))
+                    .guard(actix_web::guard::$method())
+                    .to($name::< $( [< T $bounds:camel >], )+ >)
+                    .wrap($crate::middleware::RateLimitMiddlewareFactory::new($group));
+                actix_web::dev::HttpServiceFactory::register(res, config);
+            }
+        }}
+    };
+
+    // The audit middleware wraps the ACL middleware, so that refused calls are recorded in the audit log as well
+    (@guarded $name:ident => $method:ident $path:literal impl [$( $bounds:ty ),+] $acl:expr)  => {
         paste::paste! { pub struct [<$name:camel Route>]< $( [< T $bounds:camel> ],)+ >( $( core::marker::PhantomData<fn() -> [< T $bounds:camel> ] >,)+ );}
         paste::paste! { impl< $( [< T $bounds:camel> ],)+ > [<$name:camel Route>]< $( [< T $bounds:camel> ],)+ > {
             #[allow(clippy::new_without_default)]
@@ -136,7 +228,8 @@ macro_rules! route {
                     .name(stringify!($name))
                     .guard(actix_web::guard::$method())
                     .to($name::< $( [< T $bounds:camel >], )+ >)
-                    .wrap($crate::middleware::AclMiddlewareFactory::new(&[$($roles),+]));
+                    .wrap($acl)
+                    .wrap($crate::middleware::AuditMiddlewareFactory::new(stringify!($name)));
                 actix_web::dev::HttpServiceFactory::register(res, config);
             }
         }}
@@ -150,8 +243,23 @@ pub async fn health() -> impl Responder {
     HttpResponse::Ok().body("👍️\n")
 }
 
+/// Serves the server's Prometheus metrics, such as the rate limit counters, in the Prometheus text format.
+///
+/// The route does not need an access token, so it is only served on the separate metrics address (see
+/// [`crate::server::create_metrics_server`]), and never on the main address.
+#[get("/metrics")]
+pub async fn metrics() -> Result<HttpResponse, ServerError> {
+    trace!("💻️ Received metrics request");
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer).map_err(|e| {
+        warn!("💻️ Could not encode the metrics. {e}");
+        ServerError::Unspecified(e.to_string())
+    })?;
+    Ok(HttpResponse::Ok().content_type(prometheus::TEXT_FORMAT).body(buffer))
+}
+
 //----------------------------------------------   Auth  ----------------------------------------------------
-route!(auth => Post "/auth" impl AuthManagement);
+route!(auth => Post "/auth" impl AuthManagement where limited by RateLimitGroup::Auth);
 /// Route handler for the auth endpoint
 ///
 /// This route is used to authenticate a user and issue a JWT token.
@@ -164,9 +272,13 @@ route!(auth => Post "/auth" impl AuthManagement);
 /// * `nonce` - A unique number that must increase on every call (not necessarily by 1 - a unix time epoch can be used,
 ///   for example).
 /// * `desired_roles` - A list of roles that the user wants to have. This is used to request additional permissions.
+/// * `merchant` - Optional. Requests the roles for a single merchant. Roles assigned for that merchant are allowed as
+///   well as instance-wide roles, and the access token only grants access to that merchant's orders.
 ///
-/// If successful, the server will issue a JWT token that can be used to authenticate future requests.
-/// The JWT is valid for a relatively short period and will NOT refresh.
+/// If successful, the server opens a login session and issues a JWT token that can be used to authenticate future
+/// requests. The JWT is returned in the body and is valid for a relatively short period (`TPG_ACCESS_TOKEN_LIFETIME`).
+/// A refresh token is returned in the `tpg_refresh_token` header. It can be traded for a new access token at
+/// `/auth/refresh` until the session expires (`TPG_SESSION_LIFETIME`) or is revoked.
 //#[post("/auth")]
 pub async fn auth<A>(
     req: HttpRequest,
@@ -177,7 +289,7 @@ where
     A: AuthManagement,
 {
     trace!("💻️ Received auth request");
-    let payload = req.headers().get("tpg_auth_token").ok_or(ServerError::CouldNotDeserializeAuthToken)?;
+    let payload = req.headers().get(LOGIN_TOKEN_HEADER).ok_or(ServerError::CouldNotDeserializeAuthToken)?;
     let login_token = payload.to_str().map_err(|e| {
         debug!("💻️ Could not read auth token. {e}");
         ServerError::CouldNotDeserializeAuthToken
@@ -186,13 +298,64 @@ where
     debug!("💻️ Login token was validated for {token:?}");
     api.upsert_nonce_for_address(&token.address, token.nonce).await?;
     trace!("💻️ Confirming auth request is valid for roles for {}", token.address);
-    api.check_address_has_roles(&token.address, &token.desired_roles).await.map_err(|e| {
+    let role_check = match &token.merchant {
+        Some(merchant) => api.check_address_has_merchant_roles(&token.address, merchant, &token.desired_roles).await,
+        None => api.check_address_has_roles(&token.address, &token.desired_roles).await,
+    };
+    role_check.map_err(|e| {
         debug!("💻️ User cannot be authenticated for requested roles. {e}");
         ServerError::InsufficientPermissions(e.to_string())
     })?;
-    let access_token = signer.issue_token(token, None)?;
-    trace!("💻️ Issued access token");
-    Ok(HttpResponse::Ok().content_type("application/json").body(access_token))
+    let (refresh_token, session) = signer.new_session(&token);
+    let session = api.create_session(session).await?;
+    let access_token = signer.issue_token(&JwtClaims::from(&session))?;
+    trace!("💻️ Issued access token for session #{}", session.id);
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((REFRESH_TOKEN_HEADER, refresh_token))
+        .body(access_token))
+}
+
+route!(refresh_access_token => Post "/auth/refresh" impl AuthManagement);
+/// Route handler for the auth/refresh endpoint
+///
+/// Trades the refresh token in the `tpg_refresh_token` header for a new access token, which is returned in the body.
+/// Refresh tokens can only be used once, so a new refresh token is returned in the `tpg_refresh_token` header.
+///
+/// The session must still be active, and the user must still hold the roles that the session was opened with. If they
+/// do not, the session is revoked and the user has to log in again.
+pub async fn refresh_access_token<A: AuthManagement>(
+    req: HttpRequest,
+    api: web::Data<AuthApi<A>>,
+    signer: web::Data<TokenIssuer>,
+    revocations: web::Data<RevocationList>,
+) -> Result<HttpResponse, ServerError> {
+    trace!("💻️ Received refresh request");
+    let refresh_token = req
+        .headers()
+        .get(REFRESH_TOKEN_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .ok_or(ServerError::AuthenticationError(AuthError::InvalidRefreshToken))?;
+    let new_refresh_token = generate_refresh_token();
+    let session =
+        api.refresh_session(&hash_refresh_token(refresh_token), &hash_refresh_token(&new_refresh_token)).await?;
+    let address = session.address.as_address();
+    let role_check = match &session.merchant {
+        Some(merchant) => api.check_address_has_merchant_roles(address, merchant, &session.roles).await,
+        None => api.check_address_has_roles(address, &session.roles).await,
+    };
+    if let Err(e) = role_check {
+        info!("💻️ {address} no longer holds the roles for session #{}. The session is revoked. {e}", session.id);
+        api.revoke_session(session.id).await?;
+        revocations.revoke(&[session.id]);
+        return Err(ServerError::AuthenticationError(AuthError::InvalidRefreshToken));
+    }
+    let access_token = signer.issue_token(&JwtClaims::from(&session))?;
+    trace!("💻️ Issued access token for session #{}", session.id);
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header((REFRESH_TOKEN_HEADER, new_refresh_token))
+        .body(access_token))
 }
 
 //----------------------------------------------   History  ----------------------------------------------------
@@ -206,7 +369,7 @@ pub async fn my_history<B: AccountManagement>(
     Ok(HttpResponse::Ok().json(history))
 }
 
-route!(history_for_address => Get "/history/address/{address}" impl AccountManagement where requires [Role::ReadAll]);
+route!(history_for_address => Get "/history/address/{address}" impl AccountManagement where requires [Permission::AccountsRead]);
 pub async fn history_for_address<B: AccountManagement>(
     path: web::Path<SerializedTariAddress>,
     api: web::Data<AccountApi<B>>,
@@ -217,7 +380,7 @@ pub async fn history_for_address<B: AccountManagement>(
     Ok(HttpResponse::Ok().json(history))
 }
 
-route!(history_for_customer => Get "/history/customer/{id}" impl AccountManagement where requires [Role::ReadAll]);
+route!(history_for_customer => Get "/history/customer/{id}" impl AccountManagement where requires [Permission::AccountsRead]);
 pub async fn history_for_customer<B: AccountManagement>(
     path: web::Path<String>,
     api: web::Data<AccountApi<B>>,
@@ -267,7 +430,7 @@ pub async fn my_balance<B: AccountManagement>(
     get_balance(&claims.address, api.as_ref()).await
 }
 
-route!(balance => Get "/balance/{address}" impl AccountManagement where requires [Role::ReadAll]);
+route!(balance => Get "/balance/{address}" impl AccountManagement where requires [Permission::AccountsRead]);
 /// Route handler for the balance/{address} endpoint
 ///
 /// This route is used to fetch the balance for the address supplied in the query path
@@ -291,7 +454,7 @@ pub async fn get_balance<B: AccountManagement>(
     Ok(HttpResponse::Ok().json(balance))
 }
 
-route!(creditors => Get "/creditors" impl AccountManagement where requires [Role::ReadAll]);
+route!(creditors => Get "/creditors" impl AccountManagement where requires [Permission::AccountsRead]);
 /// Route handler for the creditors endpoint
 /// Admin users (ReadAll and SuperAdmin roles) can use this endpoint to fetch all accounts that have a positive balance.
 /// This is useful for reconciling accounts and ensuring that all payments have been processed.
@@ -373,7 +536,7 @@ async fn unfulfilled_orders_for_address<B: AccountManagement>(
     Ok(result)
 }
 
-route!(unfulfilled_orders => Get "/unfulfilled_orders/{address}" impl AccountManagement where requires [Role::ReadAll]);
+route!(unfulfilled_orders => Get "/unfulfilled_orders/{address}" impl AccountManagement where requires [Permission::OrdersRead]);
 /// Route handler for the unfulfilled_orders endpoint
 ///
 /// Admins with ReadAll role can use this endpoint to fetch unfulfilled orders for any account.
@@ -387,13 +550,18 @@ pub async fn unfulfilled_orders<B: AccountManagement>(
     Ok(HttpResponse::Ok().json(unfulfilled_orders))
 }
 
-route!(orders_search => Get "/search/orders" impl AccountManagement where requires [Role::ReadAll]);
+route!(orders_search => Get "/search/orders" impl AccountManagement where merchant requires [Permission::OrdersRead]);
+/// Searches for orders matching the query. Merchant-scoped tokens only see their own merchant's orders.
 pub async fn orders_search<B: AccountManagement>(
+    claims: JwtClaims,
     query: web::Query<OrderQueryFilter>,
     api: web::Data<AccountApi<B>>,
 ) -> Result<HttpResponse, ServerError> {
+    let mut query = query.into_inner();
+    if let Some(merchant) = claims.merchant {
+        query.merchant_id = Some(merchant);
+    }
     debug!("💻️ GET orders search for [{query}]");
-    let query = query.into_inner();
     let orders = api.search_orders(query).await.map_err(|e| {
         debug!("💻️ Could not fetch orders. {e}");
         ServerError::BackendError(e.to_string())
@@ -401,7 +569,7 @@ pub async fn orders_search<B: AccountManagement>(
     Ok(HttpResponse::Ok().json(orders))
 }
 
-route!(orders => Get "/orders/{address}" impl AccountManagement where requires [Role::ReadAll]);
+route!(orders => Get "/orders/{address}" impl AccountManagement where requires [Permission::OrdersRead]);
 /// Route handler for the orders/{address} endpoint
 ///
 /// Admin users (ReadAll and SuperAdmin roles) can fetch orders for any account using this endpoint.
@@ -414,43 +582,46 @@ pub async fn orders<B: AccountManagement>(
     get_orders(&address, api.as_ref()).await
 }
 
-route!(order_by_id => Get "/order/id/{order_id}" impl AccountManagement where requires [Role::User]);
+route!(order_by_id => Get "/order/id/{order_id}" impl AccountManagement where merchant requires [Permission::OrdersReadOwn]);
 /// User `/order/id/{order_id}` to fetch a specific order by its order_id.
 ///
-/// Authenticated users can fetch their own orders using this endpoint. The Tari address for the account is extracted
-/// from the JWT token supplied in the `tpg_access_token` header. Any other order ids supplied return null, whether they
-/// exist or not.
+/// Users with the `orders.read_own` permission can fetch their own orders using this endpoint. The Tari address for the
+/// account is extracted from the JWT token supplied in the `tpg_access_token` header. Any other order ids supplied
+/// return null, whether they exist or not.
 ///
-/// Admin users (ReadAll and SuperAdmin roles) will be able to retrieve any order by its order_id.
+/// Admin users (those with the `orders.read` permission as well) will be able to retrieve any order by its order_id.
+/// Tokens that are scoped to a merchant only see that merchant's orders.
 pub async fn order_by_id<B: AccountManagement>(
     claims: JwtClaims,
     path: web::Path<OrderId>,
     api: web::Data<AccountApi<B>>,
+    role_permissions: web::Data<RolePermissions>,
 ) -> Result<HttpResponse, ServerError> {
     let order_id = path.into_inner();
     debug!("💻️ GET order_by_id({order_id})");
-    let address = claims.address;
 
-    // There's no particular ACL on this route, so check that the order belongs to the user,
-    // OR they have the `ReadAll`/`SuperAdmin` role
-    let is_admin = claims.roles.contains(&Role::ReadAll) || claims.roles.contains(&Role::SuperAdmin);
+    // The ACL only checks for `orders.read_own`, so check that the order belongs to the user,
+    // OR they have the `orders.read` permission
+    let is_admin = role_permissions.allows(&claims.roles, &[Permission::OrdersRead]);
     if is_admin {
         let order = api.as_ref().fetch_order_by_id_or_alt(&order_id).await.map_err(|e| {
             debug!("💻️ Could not fetch order. {e}");
             ServerError::BackendError(e.to_string())
         })?;
+        let order = order.filter(|o| claims.can_access_merchant(&o.merchant_id));
         return Ok(HttpResponse::Ok().json(order));
     }
     // We need to do some extra checks to make sure the user may see this order
-    let orders = api.orders_for_address(&address).await.map_err(|e| {
+    let orders = api.orders_for_address(&claims.address).await.map_err(|e| {
         debug!("💻️ Could not fetch order. {e}");
         ServerError::BackendError(e.to_string())
     })?;
-    let result = orders.orders.into_iter().find(|o| o.order_id == order_id);
+    let result =
+        orders.orders.into_iter().find(|o| o.order_id == order_id && claims.can_access_merchant(&o.merchant_id));
     Ok(HttpResponse::Ok().json(result))
 }
 
-route!(claim_order => Post "/order/claim" impl PaymentGatewayDatabase);
+route!(claim_order => Post "/order/claim" impl PaymentGatewayDatabase where limited by RateLimitGroup::Claim);
 /// Users can claim an order (that is, associate a new order with their wallet address) using the `/order/claim`
 /// endpoint.
 ///
@@ -475,6 +646,43 @@ pub async fn claim_order<B: PaymentGatewayDatabase>(
     Ok(HttpResponse::Ok().json(result))
 }
 
+/// Merchant-scoped tokens may only modify orders that belong to their merchant. If the order does not exist, the check
+/// passes and the caller reports the missing order as usual. Any other error refuses the request.
+async fn check_merchant_scope<B: PaymentGatewayDatabase>(
+    claims: &JwtClaims,
+    order_id: &OrderId,
+    api: &OrderFlowApi<B>,
+    strict_mode: bool,
+) -> Result<(), ServerError> {
+    let Some(merchant) = claims.merchant.as_deref() else {
+        return Ok(());
+    };
+    match api.db().fetch_order_by_id(order_id, strict_mode).await {
+        Ok(order) if order.merchant_id != merchant => {
+            warn!("💻️ {} tried to modify order {order_id}, which does not belong to {merchant}", claims.address);
+            Err(ServerError::InsufficientPermissions(format!("Order {order_id} does not belong to {merchant}")))
+        },
+        Ok(_) | Err(AccountApiError::OrderDoesNotExist(_)) => Ok(()),
+        Err(e) => Err(ServerError::BackendError(e.to_string())),
+    }
+}
+
+/// Holds back `operation` until a second admin approves it. The response is `202 Accepted` with the approval request.
+async fn request_approval<B: PaymentGatewayDatabase>(
+    claims: &JwtClaims,
+    operation: ApprovalOperation,
+    api: &OrderFlowApi<B>,
+    policy: &ApprovalPolicy,
+) -> Result<HttpResponse, ServerError> {
+    let request = NewApprovalRequest::new(claims.address.clone(), operation, policy.expiry());
+    let request = api.request_approval(request).await.map_err(|e| {
+        info!("💻️ Could not record approval request. {e}");
+        ServerError::from(e)
+    })?;
+    info!("💻️ Approval request {} is waiting for a second admin. {}", request.id, request.operation);
+    Ok(HttpResponse::Accepted().json(request))
+}
+
 pub async fn get_orders<B: AccountManagement>(
     address: &TariAddress,
     api: &AccountApi<B>,
@@ -488,7 +696,7 @@ pub async fn get_orders<B: AccountManagement>(
     }
 }
 
-route!(customer_ids => Get "/customer_ids" impl AccountManagement where requires [Role::ReadAll]);
+route!(customer_ids => Get "/customer_ids" impl AccountManagement where requires [Permission::AccountsRead]);
 /// Utility endpoint to return all customer ids. Pagination is supported.
 pub async fn customer_ids<B: AccountManagement>(
     api: web::Data<AccountApi<B>>,
@@ -502,7 +710,7 @@ pub async fn customer_ids<B: AccountManagement>(
     Ok(HttpResponse::Ok().json(customer_ids))
 }
 
-route!(addresses => Get "/addresses" impl AccountManagement where requires [Role::ReadAll]);
+route!(addresses => Get "/addresses" impl AccountManagement where requires [Permission::AccountsRead]);
 /// Utility endpoint to return all addresses. Pagination is supported.
 /// Admin users (ReadAll and SuperAdmin roles) can use this endpoint to fetch all addresses on the system.
 pub async fn addresses<B: AccountManagement>(
@@ -523,7 +731,7 @@ pub async fn addresses<B: AccountManagement>(
     Ok(HttpResponse::Ok().json(addresses))
 }
 
-route!(settle_address => Post "/settle/address/{address}" impl PaymentGatewayDatabase where requires [Role::Write]);
+route!(settle_address => Post "/settle/address/{address}" impl PaymentGatewayDatabase where requires [Permission::AccountsSettle]);
 /// Forces a check for whether any orders for `address` are able to be paid.
 pub async fn settle_address<B: PaymentGatewayDatabase>(
     path: web::Path<SerializedTariAddress>,
@@ -539,7 +747,7 @@ pub async fn settle_address<B: PaymentGatewayDatabase>(
     Ok(HttpResponse::Ok().json(result))
 }
 
-route!(settle_customer => Post "/settle/customer/{customer_id}" impl PaymentGatewayDatabase where requires [Role::Write]);
+route!(settle_customer => Post "/settle/customer/{customer_id}" impl PaymentGatewayDatabase where requires [Permission::AccountsSettle]);
 /// Forces a check for whether any orders for `customer_id` are able to be paid.
 pub async fn settle_customer<B: PaymentGatewayDatabase>(
     path: web::Path<String>,
@@ -589,7 +797,7 @@ pub async fn my_payments<B: AccountManagement>(
     get_payments(&claims.address, api.as_ref()).await
 }
 
-route!(payments => Get "/payments/{address}" impl AccountManagement where requires [Role::ReadAll]);
+route!(payments => Get "/payments/{address}" impl AccountManagement where requires [Permission::PaymentsRead]);
 /// Route handler for the payments/{address} endpoint
 ///
 /// Admin users (ReadAll and SuperAdmin roles) can fetch payments for any account using this endpoint. Other users
@@ -614,7 +822,7 @@ where B: AccountManagement {
     }
 }
 
-route!(payment_for_order => Get "/payments-for-order/{order_id}" impl AccountManagement where requires [Role::ReadAll]);
+route!(payment_for_order => Get "/payments-for-order/{order_id}" impl AccountManagement where requires [Permission::PaymentsRead]);
 /// Route handler for the payments-for-order/{order_id} endpoint
 pub async fn payment_for_order<B: AccountManagement>(
     path: web::Path<OrderId>,
@@ -631,19 +839,27 @@ pub async fn payment_for_order<B: AccountManagement>(
 
 //----------------------------------------------   Modify ----------------------------------------------------
 
-route!(issue_credit => Post "/credit" impl PaymentGatewayDatabase where requires [Role::Write]);
+route!(issue_credit => Post "/credit" impl PaymentGatewayDatabase where requires [Permission::CreditIssue]);
 /// Route handler for the credit endpoint
 /// Admin users (Write role) can use this endpoint to issue a credit note against a customer id.
 /// The user's account will be credited, and any eligible orders will immediately be fulfilled.
 ///
 /// Any fulfilled orders will be returned in the response.
+///
+/// If the approval policy holds back credit of this size, nothing is credited yet. The response is `202 Accepted`
+/// with the approval request instead, and the credit is issued once a second admin approves it.
 pub async fn issue_credit<B: PaymentGatewayDatabase>(
+    claims: JwtClaims,
     body: web::Json<CreditNote>,
     api: web::Data<OrderFlowApi<B>>,
     config: web::Data<ServerOptions>,
 ) -> Result<HttpResponse, ServerError> {
     let note = body.into_inner();
     debug!("💻️ Credit note request for {note:?}");
+    if config.approval_policy.credit_needs_approval(note.amount) {
+        let operation = ApprovalOperation::IssueCredit { note };
+        return request_approval(&claims, operation, &api, &config.approval_policy).await;
+    }
     let result = api.issue_credit_note(note, config.strict_mode).await.map_err(|e| {
         debug!("💻️ Could not issue credit. {e}");
         ServerError::BackendError(e.to_string())
@@ -657,14 +873,22 @@ pub async fn issue_credit<B: PaymentGatewayDatabase>(
     }
 }
 
-route!(fulfil_order => Post "/fulfill" impl PaymentGatewayDatabase where requires [Role::Write]);
+route!(fulfil_order => Post "/fulfill" impl PaymentGatewayDatabase where merchant requires [Permission::OrdersFulfil]);
+/// Marks an order as paid by hand. If the approval policy holds back manual fulfilment, the response is
+/// `202 Accepted` with the approval request instead.
 pub async fn fulfil_order<B: PaymentGatewayDatabase>(
+    claims: JwtClaims,
     body: web::Json<ModifyOrderParams>,
     api: web::Data<OrderFlowApi<B>>,
     config: web::Data<ServerOptions>,
 ) -> Result<HttpResponse, ServerError> {
     let ModifyOrderParams { order_id, reason } = body.into_inner();
     debug!("💻️ Fulfilment request for {order_id} with reason: {reason}");
+    check_merchant_scope(&claims, &order_id, &api, config.strict_mode).await?;
+    if config.approval_policy.fulfilment_needs_approval() {
+        let operation = ApprovalOperation::FulfilOrder { order_id, reason };
+        return request_approval(&claims, operation, &api, &config.approval_policy).await;
+    }
     let order = api.mark_new_order_as_paid(&order_id, &reason, config.strict_mode).await.map_err(|e| {
         debug!("💻️ Could not fulfil order. {e}");
         e
@@ -672,7 +896,7 @@ pub async fn fulfil_order<B: PaymentGatewayDatabase>(
     Ok(HttpResponse::Ok().json(order))
 }
 
-route!(cancel_order => Post "/cancel" impl PaymentGatewayDatabase where requires [Role::Write]);
+route!(cancel_order => Post "/cancel" impl PaymentGatewayDatabase where merchant requires [Permission::OrdersCancel]);
 /// Order cancellation
 ///
 /// Admin users (Write role) can use this endpoint to cancel an order. The order will be marked as cancelled, the
@@ -686,12 +910,14 @@ route!(cancel_order => Post "/cancel" impl PaymentGatewayDatabase where requires
 /// ## Returns
 /// The cancelled order object.
 pub async fn cancel_order<B: PaymentGatewayDatabase>(
+    claims: JwtClaims,
     body: web::Json<ModifyOrderParams>,
     api: web::Data<OrderFlowApi<B>>,
     config: web::Data<ServerOptions>,
 ) -> Result<HttpResponse, ServerError> {
     let ModifyOrderParams { order_id, reason } = body.into_inner();
     info!("💻️ Cancel order request for {order_id}. Reason: {reason}");
+    check_merchant_scope(&claims, &order_id, &api, config.strict_mode).await?;
     let order = api
         .cancel_or_expire_order(&order_id, OrderStatusType::Cancelled, &reason, config.strict_mode)
         .await
@@ -702,7 +928,7 @@ pub async fn cancel_order<B: PaymentGatewayDatabase>(
     Ok(HttpResponse::Ok().json(order))
 }
 
-route!(update_order_memo => Patch "/order_memo" impl PaymentGatewayDatabase where requires [Role::Write]);
+route!(update_order_memo => Patch "/order_memo" impl PaymentGatewayDatabase where merchant requires [Permission::OrdersEdit]);
 /// Update an order's memo field.
 ///
 /// Admin users (Write role) can use this endpoint to update an order's memo field.
@@ -724,6 +950,7 @@ route!(update_order_memo => Patch "/order_memo" impl PaymentGatewayDatabase wher
 /// so. Right now, it's not clear whether the UX would be any better than
 /// re-doing the order.
 pub async fn update_order_memo<B: PaymentGatewayDatabase>(
+    claims: JwtClaims,
     body: web::Json<UpdateMemoParams>,
     api: web::Data<OrderFlowApi<B>>,
     config: web::Data<ServerOptions>,
@@ -731,6 +958,7 @@ pub async fn update_order_memo<B: PaymentGatewayDatabase>(
     let UpdateMemoParams { order_id, new_memo, reason } = body.into_inner();
     let reason = reason.unwrap_or_else(|| "No reason provided".to_string());
     info!("💻️ Update order memos request for {order_id}. Reason: {reason}");
+    check_merchant_scope(&claims, &order_id, &api, config.strict_mode).await?;
     let order = api.update_memo_for_order(&order_id, &new_memo, config.strict_mode).await.map_err(|e| {
         debug!("💻️ Could not update order memo. {e}");
         e
@@ -738,7 +966,7 @@ pub async fn update_order_memo<B: PaymentGatewayDatabase>(
     Ok(HttpResponse::Ok().json(order))
 }
 
-route!(update_price => Patch "/order_price" impl PaymentGatewayDatabase where requires [Role::Write]);
+route!(update_price => Patch "/order_price" impl PaymentGatewayDatabase where merchant requires [Permission::OrdersPrice]);
 /// Provides an endpoint for admins to adjust the price of an order.
 ///
 /// Admins can call PATCH /api/order_price with the order_id, new price, and
@@ -749,7 +977,11 @@ route!(update_price => Patch "/order_price" impl PaymentGatewayDatabase where re
 /// automatically be filled.
 ///
 /// The new price must be positive.
+///
+/// If the approval policy holds back price changes of this size, the response is `202 Accepted` with the approval
+/// request instead.
 pub async fn update_price<B: PaymentGatewayDatabase>(
+    claims: JwtClaims,
     body: web::Json<UpdatePriceParams>,
     api: web::Data<OrderFlowApi<B>>,
     config: web::Data<ServerOptions>,
@@ -757,6 +989,19 @@ pub async fn update_price<B: PaymentGatewayDatabase>(
     let UpdatePriceParams { order_id, new_price, reason } = body.into_inner();
     let reason = reason.unwrap_or_else(|| "No reason provided".to_string());
     info!("💻️ Update order price request for {order_id}. Reason: {reason}");
+    check_merchant_scope(&claims, &order_id, &api, config.strict_mode).await?;
+    let policy = config.approval_policy;
+    if policy.price_change_threshold.is_some() {
+        let order = api.db().fetch_order_by_id(&order_id, config.strict_mode).await.map_err(|e| {
+            debug!("💻️ Could not fetch order {order_id}. {e}");
+            ServerError::BackendError(e.to_string())
+        })?;
+        let old_price = order.total_price;
+        if policy.price_change_needs_approval(old_price, new_price) {
+            let operation = ApprovalOperation::UpdatePrice { order_id, old_price, new_price, reason };
+            return request_approval(&claims, operation, &api, &policy).await;
+        }
+    }
     let order = api.update_price_for_order(&order_id, new_price, config.strict_mode).await.map_err(|e| {
         debug!("💻️ Could not update order price. {e}");
         e
@@ -764,7 +1009,7 @@ pub async fn update_price<B: PaymentGatewayDatabase>(
     Ok(HttpResponse::Ok().json(order))
 }
 
-route!(reassign_order => Patch "/reassign_order" impl PaymentGatewayDatabase where requires [Role::Write]);
+route!(reassign_order => Patch "/reassign_order" impl PaymentGatewayDatabase where merchant requires [Permission::OrdersReassign]);
 /// Provides an endpoint for admins to reassign an order to a different customer id.
 /// Admins can call `PATCH /api/reassign_order` with the order_id, new customer_id, and a reason to reassign an order
 /// to a different customer.
@@ -781,12 +1026,14 @@ route!(reassign_order => Patch "/reassign_order" impl PaymentGatewayDatabase whe
 ///  }
 /// ```
 pub async fn reassign_order<B: PaymentGatewayDatabase>(
+    claims: JwtClaims,
     body: web::Json<MoveOrderParams>,
     api: web::Data<OrderFlowApi<B>>,
     config: web::Data<ServerOptions>,
 ) -> Result<HttpResponse, ServerError> {
     let MoveOrderParams { order_id, new_customer_id, reason } = body.into_inner();
     info!("💻️ Assigning existing order {order_id} to customer {new_customer_id}. Reason: {reason}");
+    check_merchant_scope(&claims, &order_id, &api, config.strict_mode).await?;
     let order =
         api.assign_order_to_new_customer(&order_id, &new_customer_id, config.strict_mode).await.map_err(|e| {
             debug!("💻️ Could not assign order. {e}");
@@ -795,7 +1042,7 @@ pub async fn reassign_order<B: PaymentGatewayDatabase>(
     Ok(HttpResponse::Ok().json(order))
 }
 
-route!(reset_order => Patch "/reset_order/{order_id}" impl PaymentGatewayDatabase where requires [Role::Write]);
+route!(reset_order => Patch "/reset_order/{order_id}" impl PaymentGatewayDatabase where requires [Permission::OrdersReset]);
 /// Provides an endpoint for admins to reset an order to the `New` state.
 ///
 /// `reset_order` is a PATCH HTTP method.
@@ -828,34 +1075,40 @@ pub async fn reset_order<B: PaymentGatewayDatabase>() -> Result<HttpResponse, Se
     Err(ServerError::UnsupportedAction("Resetting orders is not supported in Shopify".to_string()))
 }
 
-route!(rescan_open_orders => Post "/rescan_open_orders" impl PaymentGatewayDatabase, ExchangeRates where requires [Role::Write]);
+route!(rescan_open_orders => Post "/rescan_open_orders" impl PaymentGatewayDatabase, ExchangeRates where requires [Permission::OrdersProcess]);
 pub async fn rescan_open_orders<BPay, BFx>(
     api: web::Data<OrderFlowApi<BPay>>,
     fx: web::Data<ExchangeRateApi<BFx>>,
-    shopify_api: web::Data<ShopifyApi>,
+    storefronts: web::Data<Storefronts>,
     config: web::Data<ServerOptions>,
 ) -> Result<HttpResponse, ServerError>
 where
     BPay: PaymentGatewayDatabase,
     BFx: ExchangeRates,
 {
-    info!("🛍️ Starting to re-scan all open orders from Shopify");
-    let open_orders = shopify_api.fetch_all_open_orders(None).await.map_err(|e| {
-        error!("🛍️️ Could not fetch open orders from Shopify. {e}");
-        ServerError::CannotCompleteRequest(e.to_string())
-    })?;
     let mut results = vec![];
-    info!("🛍️ Found {} open orders in Shopify. Adding them to the database", open_orders.len());
-    for order in open_orders {
-        let result = handle_shopify_order(order, &fx, &api, &config).await;
-        results.push(result);
+    for store in storefronts.iter() {
+        let (merchant_id, platform) = (store.merchant_id(), store.platform());
+        info!("🛍️ Starting to re-scan all open orders from the {merchant_id} {platform} store");
+        let open_orders = store.fetch_open_orders().await.map_err(|e| {
+            error!("🛍️️ Could not fetch open orders from the {merchant_id} {platform} store. {e}");
+            ServerError::CannotCompleteRequest(e.to_string())
+        })?;
+        info!("🛍️ Found {} open orders in the {merchant_id} store. Adding them to the database", open_orders.len());
+        for order in open_orders {
+            let result = match order {
+                Ok(unpriced) => handle_new_order(unpriced, &fx, &api, &config).await,
+                Err(e) => conversion_failure(e),
+            };
+            results.push(result);
+        }
     }
-    info!("🛍️ Finished re-scanning all open orders from Shopify");
+    info!("🛍️ Finished re-scanning all open orders from the storefronts");
     Ok(HttpResponse::Ok().json(results))
 }
 
 //------------------------------------------   Incoming payments  ---------------------------------------------
-route!(incoming_payment_notification => Post "/incoming_payment" impl PaymentGatewayDatabase, WalletAuth );
+route!(incoming_payment_notification => Post "/incoming_payment" impl PaymentGatewayDatabase, WalletAuth where limited by RateLimitGroup::Wallet);
 pub async fn incoming_payment_notification<BOrder, BAuth>(
     req: HttpRequest,
     config: web::Data<ServerOptions>,
@@ -895,17 +1148,21 @@ where
     info!("💻️ New payment notification received from IP {peer_addr:?}.");
     info!("💻️ Payment: {}", serde_json::to_string(&payment).unwrap_or_else(|e| format!("{e}")));
     info!("💻️ Auth: {}", serde_json::to_string(&auth).unwrap_or_else(|e| format!("{e}")));
-    trace!("💻️ Verifying wallet signature");
-    if !auth.is_valid(&payment) {
-        warn!("💻️ Invalid wallet signature received from {peer_addr:?}. The request is rejected.");
-        return HttpResponse::Unauthorized().finish();
-    }
+    // The whitelist is checked before the (more expensive) signature
+    trace!("💻️ Verifying wallet whitelist and signature");
     let auth_api = auth_api.as_ref();
-    if let Err(e) = auth_api.authenticate_wallet(auth, peer_addr.as_ref(), &payment, disable_whitelist).await {
-        warn!("💻️ Unauthorized wallet signature received from {peer_addr:?}. Reason: {e}. The request is rejected.");
-        return HttpResponse::Unauthorized().finish();
-    }
+    let wallet = match auth_api.authenticate_wallet(auth, peer_addr.as_ref(), &payment, disable_whitelist).await {
+        Ok(wallet) => wallet,
+        Err(e) => {
+            warn!(
+                "💻️ Unauthorized wallet signature received from {peer_addr:?}. Reason: {e}. The request is rejected."
+            );
+            return HttpResponse::Unauthorized().finish();
+        },
+    };
     // -- from here on, we trust that the notification is legitimate.
+    // -- the funds belong to the merchant whose wallet received them
+    payment.merchant_id = wallet.merchant_id;
     // -- extract the order_id from the memo signature, if present
     match try_extract_order_id(&mut payment, require_memo_signature, config.shopify_order_field) {
         Some(true) => {
@@ -937,7 +1194,7 @@ where
     HttpResponse::Ok().json(result)
 }
 
-route!(tx_confirmation_notification => Post "/tx_confirmation" impl PaymentGatewayDatabase, WalletAuth );
+route!(tx_confirmation_notification => Post "/tx_confirmation" impl PaymentGatewayDatabase, WalletAuth where limited by RateLimitGroup::Wallet);
 pub async fn tx_confirmation_notification<BOrder, BAuth>(
     req: HttpRequest,
     config: web::Data<ServerOptions>,
@@ -975,11 +1232,8 @@ where
     info!("💻️ New transaction confirmation received from IP {peer_addr:?}.");
     info!("💻️ Confirmation: {}", serde_json::to_string(&confirmation).unwrap_or_else(|e| format!("{e}")));
     info!("💻️ Auth: {}", serde_json::to_string(&auth).unwrap_or_else(|e| format!("{e}")));
-    trace!("💻️ Verifying wallet signature");
-    if !auth.is_valid(&confirmation) {
-        warn!("💻️ Invalid wallet signature received from {peer_addr:?}. The request is rejected.");
-        return HttpResponse::Unauthorized().finish();
-    }
+    // The whitelist is checked before the (more expensive) signature
+    trace!("💻️ Verifying wallet whitelist and signature");
     let auth_api = auth_api.as_ref();
     if let Err(e) = auth_api.authenticate_wallet(auth, peer_addr.as_ref(), &confirmation, disable_whitelist).await {
         warn!(
@@ -990,7 +1244,8 @@ where
     }
     // -- from here on, we trust that the notification is legitimate.
     let tx_id = confirmation.txid.clone();
-    let result = match order_api.confirm_payment(confirmation.txid, config.strict_mode).await {
+    let deferred_from = config.deferred_confirmations_from;
+    let result = match order_api.confirm_notified_payment(confirmation.txid, deferred_from, config.strict_mode).await {
         Err(PaymentGatewayError::PaymentModificationNoOp) => {
             info!("💻️ Payment {} already confirmed.", tx_id);
             JsonResponse::success("Payment already confirmed.")
@@ -999,19 +1254,27 @@ where
             error!("💻️ Could not confirm payment. {e}");
             JsonResponse::failure(String::from("Could not confirm payment."))
         },
-        Ok(payment) => {
+        Ok(Some(payment)) => {
             info!("💻️ Payment {} confirmed successfully.", payment.txid);
             debug!("💻️ Payment details: {payment:?}");
             JsonResponse::success(format!("Payment {tx_id} confirmed successfully."))
         },
+        Ok(None) => JsonResponse::success(format!(
+            "Payment {tx_id} needs more confirmations. It will be confirmed once the wallet watcher sees it deep \
+             enough."
+        )),
     };
     HttpResponse::Ok().json(result)
 }
 
-//----------------------------------------------   SuperAdmin  ----------------------------------------------------
-route!(update_roles => Post "/roles" impl AuthManagement where requires [Role::SuperAdmin]);
+//----------------------------------------------   Roles  ---------------------------------------------------------
+route!(update_roles => Post "/roles" impl AuthManagement where requires [Permission::RolesManage]);
+/// Assigns and revokes roles. Custom roles must have been created with `PUT /roles/{role}` before they are assigned.
+/// When roles are revoked from an address, all of its sessions are revoked too, so that access tokens carrying the old
+/// roles stop working straight away.
 pub async fn update_roles<B: AuthManagement>(
     api: web::Data<AuthApi<B>>,
+    revocations: web::Data<RevocationList>,
     body: web::Json<Vec<RoleUpdateRequest>>,
 ) -> Result<HttpResponse, ServerError> {
     for acl_request in body.into_inner() {
@@ -1021,35 +1284,191 @@ pub async fn update_roles<B: AuthManagement>(
             ServerError::InvalidRequestPath(e.to_string())
         })?;
         debug!("💻️ POST update roles for {address}");
-        api.assign_roles(&address, &acl_request.apply).await?;
-        api.remove_roles(&address, &acl_request.revoke).await?;
+        let removed = match acl_request.merchant.as_deref() {
+            Some(merchant) => {
+                api.assign_merchant_roles(&address, merchant, &acl_request.apply).await?;
+                api.remove_merchant_roles(&address, merchant, &acl_request.revoke).await?
+            },
+            None => {
+                api.assign_roles(&address, &acl_request.apply).await?;
+                api.remove_roles(&address, &acl_request.revoke).await?
+            },
+        };
+        if removed > 0 {
+            let sessions = api.revoke_sessions_for_address(&address).await?;
+            revocations.revoke(&sessions);
+            info!("💻️ {removed} roles were revoked from {address}. {} sessions were revoked.", sessions.len());
+        }
     }
     Ok(HttpResponse::Ok().finish())
 }
 
-route!(get_authorized_wallets => Get "/wallets" impl WalletManagement where requires [Role::ReadAll]);
+route!(role_definitions => Get "/roles" impl AuthManagement where requires [Permission::RolesRead]);
+/// Lists every role, and the permissions that it grants.
+pub async fn role_definitions<B: AuthManagement>(api: web::Data<AuthApi<B>>) -> Result<HttpResponse, ServerError> {
+    debug!("💻️ GET role definitions");
+    let roles = api.fetch_role_definitions().await?;
+    Ok(HttpResponse::Ok().json(roles))
+}
+
+route!(save_role => Put "/roles/{role}" impl AuthManagement where requires [Permission::RolesManage]);
+/// Creates a role, or replaces the permissions of an existing one. The body is the list of permissions that the role
+/// grants, e.g. `["orders.read", "orders.cancel", "credit.issue"]`.
+///
+/// The change applies straight away, including to access tokens that have already been issued.
+pub async fn save_role<B: AuthManagement>(
+    path: web::Path<String>,
+    body: web::Json<Vec<Permission>>,
+    api: web::Data<AuthApi<B>>,
+    role_permissions: web::Data<RolePermissions>,
+) -> Result<HttpResponse, ServerError> {
+    let role = Role::from_str(&path.into_inner()).map_err(|e| ServerError::InvalidRequestPath(e.to_string()))?;
+    let role = RoleDefinition::new(role, &body.into_inner());
+    debug!("💻️ PUT role {} with permissions {:?}", role.role, role.permissions);
+    api.save_role(&role).await?;
+    role_permissions.update(role.clone());
+    info!("💻️ Role {} now grants {} permissions", role.role, role.permissions.len());
+    Ok(HttpResponse::Ok().json(role))
+}
+
+route!(delete_role => Delete "/roles/{role}" impl AuthManagement where requires [Permission::RolesManage]);
+/// Deletes a custom role, and takes it away from every address that holds it. Built-in roles cannot be deleted.
+pub async fn delete_role<B: AuthManagement>(
+    path: web::Path<String>,
+    api: web::Data<AuthApi<B>>,
+    role_permissions: web::Data<RolePermissions>,
+) -> Result<HttpResponse, ServerError> {
+    let role = Role::from_str(&path.into_inner()).map_err(|e| ServerError::InvalidRequestPath(e.to_string()))?;
+    debug!("💻️ DELETE role {role}");
+    api.delete_role(&role).await?;
+    role_permissions.remove(&role);
+    info!("💻️ Role {role} was deleted");
+    Ok(HttpResponse::Ok().finish())
+}
+
+//----------------------------------------------   Sessions  ----------------------------------------------------
+route!(my_sessions => Get "/sessions" impl AuthManagement);
+/// Lists the active login sessions for the address in the access token, newest first.
+pub async fn my_sessions<B: AuthManagement>(
+    claims: JwtClaims,
+    api: web::Data<AuthApi<B>>,
+) -> Result<HttpResponse, ServerError> {
+    debug!("💻️ GET sessions for {}", claims.address);
+    let sessions = api.fetch_sessions_for_address(&claims.address).await?;
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+route!(sessions_for_address => Get "/sessions/{address}" impl AuthManagement where requires [Permission::SessionsRead]);
+/// Lists the active login sessions for the given address, newest first.
+pub async fn sessions_for_address<B: AuthManagement>(
+    path: web::Path<SerializedTariAddress>,
+    api: web::Data<AuthApi<B>>,
+) -> Result<HttpResponse, ServerError> {
+    let address = path.into_inner().to_address();
+    debug!("💻️ GET sessions for {address}");
+    let sessions = api.fetch_sessions_for_address(&address).await?;
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+route!(revoke_session => Delete "/sessions/{id}" impl AuthManagement);
+/// Revokes a login session. Its refresh token and access tokens stop working immediately.
+///
+/// Users can revoke their own sessions. Users with the `sessions.manage` permission can revoke anyone's sessions.
+pub async fn revoke_session<B: AuthManagement>(
+    claims: JwtClaims,
+    path: web::Path<i64>,
+    api: web::Data<AuthApi<B>>,
+    revocations: web::Data<RevocationList>,
+    role_permissions: web::Data<RolePermissions>,
+) -> Result<HttpResponse, ServerError> {
+    let id = path.into_inner();
+    debug!("💻️ DELETE session #{id} for {}", claims.address);
+    let session = api.fetch_session(id).await?.ok_or_else(|| ServerError::NoRecordFound(format!("Session {id}")))?;
+    let can_manage = claims.merchant.is_none() && role_permissions.allows(&claims.roles, &[Permission::SessionsManage]);
+    if session.address.as_address() != &claims.address && !can_manage {
+        warn!("💻️ {} tried to revoke session #{id}, which belongs to {}", claims.address, session.address);
+        return Err(ServerError::InsufficientPermissions("You can only revoke your own sessions.".to_string()));
+    }
+    let session = api.revoke_session(id).await?;
+    revocations.revoke(&[id]);
+    Ok(HttpResponse::Ok().json(session))
+}
+
+route!(logout => Post "/logout" impl AuthManagement);
+/// Revokes the session that the access token belongs to.
+pub async fn logout<B: AuthManagement>(
+    claims: JwtClaims,
+    api: web::Data<AuthApi<B>>,
+    revocations: web::Data<RevocationList>,
+) -> Result<HttpResponse, ServerError> {
+    debug!("💻️ POST logout for {}", claims.address);
+    if let Some(id) = claims.session {
+        api.revoke_session(id).await?;
+        revocations.revoke(&[id]);
+    }
+    Ok(HttpResponse::Ok().json(JsonResponse::success("You have been logged out.")))
+}
+
+route!(logout_everywhere => Post "/logout_all" impl AuthManagement);
+/// Revokes every session for the address in the access token, including the current one.
+pub async fn logout_everywhere<B: AuthManagement>(
+    claims: JwtClaims,
+    api: web::Data<AuthApi<B>>,
+    revocations: web::Data<RevocationList>,
+) -> Result<HttpResponse, ServerError> {
+    debug!("💻️ POST logout_all for {}", claims.address);
+    let sessions = api.revoke_sessions_for_address(&claims.address).await?;
+    revocations.revoke(&sessions);
+    let msg = format!("{} sessions were logged out.", sessions.len());
+    Ok(HttpResponse::Ok().json(JsonResponse::success(msg)))
+}
+
+route!(revoke_sessions_for_address => Post "/sessions/{address}/revoke" impl AuthManagement where requires [Permission::SessionsManage]);
+/// Revokes every session for the given address, logging it out everywhere.
+pub async fn revoke_sessions_for_address<B: AuthManagement>(
+    path: web::Path<SerializedTariAddress>,
+    api: web::Data<AuthApi<B>>,
+    revocations: web::Data<RevocationList>,
+) -> Result<HttpResponse, ServerError> {
+    let address = path.into_inner().to_address();
+    debug!("💻️ POST revoke sessions for {address}");
+    let sessions = api.revoke_sessions_for_address(&address).await?;
+    revocations.revoke(&sessions);
+    let msg = format!("{} sessions were logged out.", sessions.len());
+    Ok(HttpResponse::Ok().json(JsonResponse::success(msg)))
+}
+
+route!(get_authorized_wallets => Get "/wallets" impl WalletManagement where merchant requires [Permission::WalletsRead]);
 /// Get all wallets that are authorized to receive funds on behalf of the payment gateway.
 ///
-/// This endpoint is only accessible to users with the `ReadAll` role.
+/// This endpoint is only accessible to users with the `ReadAll` role. Merchant-scoped tokens only see their merchant's
+/// wallets.
 pub async fn get_authorized_wallets<W: WalletManagement>(
+    claims: JwtClaims,
     api: web::Data<WalletManagementApi<W>>,
 ) -> Result<HttpResponse, ServerError> {
     debug!("💻️ GET wallets");
-    let wallets = api.fetch_authorized_wallets().await.map_err(|e| {
+    let mut wallets = api.fetch_authorized_wallets().await.map_err(|e| {
         debug!("💻️ Could not fetch wallets. {e}");
         ServerError::BackendError(e.to_string())
     })?;
+    if let Some(merchant) = claims.merchant {
+        wallets.retain(|w| w.merchant_id == merchant);
+    }
     Ok(HttpResponse::Ok().json(wallets))
 }
 
 route!(get_authorized_addresses => Get "/send_to" impl WalletManagement);
 /// Get all wallet addresses that are authorized to receive funds on behalf of the payment gateway.
 ///
-/// Only addresses are returned. IP addresses and nonces are not included.
+/// Only addresses are returned. IP addresses and nonces are not included. Funds can only pay for the orders of the
+/// merchant that owns the receiving wallet, so storefronts should pass their merchant id in the `merchant` query
+/// parameter.
 ///
 /// This is a publicly accessible endpoint.
 pub async fn get_authorized_addresses<W: WalletManagement>(
     api: web::Data<WalletManagementApi<W>>,
+    query: web::Query<WalletQuery>,
 ) -> Result<HttpResponse, ServerError> {
     debug!("💻️ GET wallets");
     let wallets = api
@@ -1060,20 +1479,33 @@ pub async fn get_authorized_addresses<W: WalletManagement>(
             ServerError::BackendError(e.to_string())
         })?
         .into_iter()
+        .filter(|w| query.merchant.as_ref().map(|m| &w.merchant_id == m).unwrap_or(true))
         .map(|w| w.address)
         .collect::<Vec<_>>();
     Ok(HttpResponse::Ok().json(wallets))
 }
 
-route!(remove_authorized_wallet => Delete "/wallets/{address}" impl WalletManagement where requires [Role::SuperAdmin]);
+route!(remove_authorized_wallet => Delete "/wallets/{address}" impl WalletManagement where merchant requires [Permission::WalletsManage]);
 /// Remove a wallet from the list of authorized wallets.
-/// This endpoint is only accessible to users with the `SuperAdmin` role.
+/// This endpoint is only accessible to users with the `SuperAdmin` role. Merchant-scoped tokens can only remove their
+/// merchant's wallets.
 pub async fn remove_authorized_wallet<W: WalletManagement>(
+    claims: JwtClaims,
     api: web::Data<WalletManagementApi<W>>,
     address: web::Path<SerializedTariAddress>,
 ) -> Result<HttpResponse, ServerError> {
-    let address = address.into_inner().to_address();
+    let address = address.into_inner();
     debug!("💻️ DELETE wallet {address}");
+    if let Some(merchant) = claims.merchant.as_deref() {
+        let wallets = api.fetch_authorized_wallets().await.map_err(|e| ServerError::BackendError(e.to_string()))?;
+        if wallets.iter().any(|w| w.address == address && w.merchant_id != merchant) {
+            warn!("💻️ {} tried to remove wallet {address}, which does not belong to {merchant}", claims.address);
+            return Err(ServerError::InsufficientPermissions(format!(
+                "Wallet {address} does not belong to {merchant}"
+            )));
+        }
+    }
+    let address = address.to_address();
     api.deregister_wallet(&address).await.map_err(|e| {
         info!("💻️ Could not remove wallet. {e}");
         ServerError::BackendError(e.to_string())
@@ -1081,14 +1513,20 @@ pub async fn remove_authorized_wallet<W: WalletManagement>(
     Ok(HttpResponse::Ok().finish())
 }
 
-route!(add_authorized_wallet => Post "/wallets" impl WalletManagement where requires [Role::SuperAdmin]);
-/// Add a wallet to the list of authorized wallets.
-/// This endpoint is only accessible to users with the `SuperAdmin` role.
+route!(add_authorized_wallet => Post "/wallets" impl WalletManagement where merchant requires [Permission::WalletsManage]);
+/// Add a wallet to the list of authorized wallets. The payments that the wallet receives belong to the merchant in the
+/// request body, or the default merchant if it is omitted.
+/// This endpoint is only accessible to users with the `SuperAdmin` role. Merchant-scoped tokens can only add wallets
+/// for their own merchant.
 pub async fn add_authorized_wallet<W: WalletManagement>(
+    claims: JwtClaims,
     api: web::Data<WalletManagementApi<W>>,
     body: web::Json<NewWalletInfo>,
 ) -> Result<HttpResponse, ServerError> {
-    let wallet = body.into_inner();
+    let mut wallet = body.into_inner();
+    if let Some(merchant) = claims.merchant {
+        wallet.merchant_id = merchant;
+    }
     debug!("💻️ POST authorize_new_wallet {}", wallet.address.as_base58());
     api.register_wallet(wallet).await.map_err(|e| {
         info!("💻️ Could not add wallet. {e}");
@@ -1098,23 +1536,596 @@ pub async fn add_authorized_wallet<W: WalletManagement>(
 }
 
 //----------------------------------------------  Check Token  ----------------------------------------------------
-route!(check_token => Get "/check_token" requires [Role::User]);
+route!(check_token => Get "/check_token" requires []);
 pub async fn check_token(claims: JwtClaims) -> Result<HttpResponse, ServerError> {
     debug!("💻️ GET check_token for {}", claims.address);
     Ok(HttpResponse::Ok().body("Token is valid."))
 }
 
+//----------------------------------------------  Event stream  ----------------------------------------------------
+route!(event_stream => Get "/events" impl AccountManagement where merchant requires []);
+/// Route handler for the `/api/events` endpoint.
+///
+/// Opens a [Server-Sent Events](https://html.spec.whatwg.org/multipage/server-sent-events.html) stream of
+/// `OrderClaimed`, `PaymentReceived`, `Confirmation`, `PaymentReverted` and `OrderPaid` events as they happen. See
+/// [`crate::event_stream`] for the message format.
+///
+/// Authenticated users only receive events for the Tari address in their JWT token: payments they sent, orders they
+/// claimed, and payment notifications for orders belonging to customer ids linked to their address.
+///
+/// Admin users (those with the `events.read` permission) receive every event. Merchant-scoped tokens only receive the
+/// events of their merchant's orders and payments.
+pub async fn event_stream<B: AccountManagement>(
+    claims: JwtClaims,
+    api: web::Data<AccountApi<B>>,
+    events: web::Data<EventStream>,
+    role_permissions: web::Data<RolePermissions>,
+) -> Result<HttpResponse, ServerError> {
+    debug!("💻️ GET event stream for {}", claims.address);
+    let is_admin = role_permissions.allows(&claims.roles, &[Permission::EventsRead]);
+    let merchant = claims.merchant.clone();
+    let filter = if is_admin {
+        EventStreamFilter::all()
+    } else {
+        let orders = api.orders_for_address(&claims.address).await.map_err(|e| {
+            debug!("💻️ Could not fetch orders for event stream. {e}");
+            ServerError::BackendError(e.to_string())
+        })?;
+        let customer_ids = orders.orders.into_iter().map(|o| o.customer_id);
+        EventStreamFilter::for_address(claims.address, customer_ids)
+    };
+    let filter = match merchant {
+        Some(merchant) => filter.for_merchant(merchant),
+        None => filter,
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(events.subscribe(filter)))
+}
+
 //----------------------------------------------   Exchange rates  ----------------------------------------------------
-route!(get_exchange_rate => Get "/exchange_rate/{currency}" impl ExchangeRates where requires [Role::ReadAll]);
+route!(get_exchange_rate => Get "/exchange_rate/{currency}" impl ExchangeRates where merchant requires [Permission::RatesRead]);
+/// Fetches the current exchange rate for the currency. Merchant-scoped tokens get the rate that applies to their
+/// merchant's orders.
 pub async fn get_exchange_rate<B: ExchangeRates>(
+    claims: JwtClaims,
     currency: web::Path<String>,
     api: web::Data<ExchangeRateApi<B>>,
 ) -> Result<HttpResponse, ServerError> {
     let cur = currency.into_inner();
-    let rate = api.fetch_last_rate(cur.as_str()).await.map_err(|e| {
+    let merchant = claims.merchant.as_deref().unwrap_or(DEFAULT_MERCHANT);
+    let rate = api.fetch_merchant_rate(merchant, cur.as_str()).await.map_err(|e| {
         debug!("💻️ Could not fetch exchange rate. {e}");
         ServerError::BackendError(e.to_string())
     })?;
     let rate = ExchangeRateResult::from(rate);
     Ok(HttpResponse::Ok().json(rate))
 }
+
+route!(exchange_rate_history => Get "/exchange_rate/{currency}/history" impl ExchangeRates where merchant requires [Permission::RatesRead]);
+/// Lists the exchange rates for the currency, oldest first. The range can be limited with the `since` and `until`
+/// query parameters, and pagination is supported. The default rates are listed, unless the `merchant` parameter
+/// names a merchant. Merchant-scoped tokens always get their own merchant's rates.
+///
+/// If the `at` parameter is given, the result contains only the default rate that applied at that time, which is what
+/// an order created at that time would have been priced at.
+pub async fn exchange_rate_history<B: ExchangeRates>(
+    claims: JwtClaims,
+    currency: web::Path<String>,
+    query: web::Query<RateHistoryQuery>,
+    api: web::Data<ExchangeRateApi<B>>,
+) -> Result<HttpResponse, ServerError> {
+    let cur = currency.into_inner();
+    let query = query.into_inner();
+    let merchant = claims.merchant.or(query.merchant.clone()).unwrap_or_else(default_merchant);
+    let rates = match query.at {
+        Some(at) => api.fetch_rate_at(&cur, at).await.map(|r| vec![r]),
+        None => api.fetch_rate_history(&merchant, &cur, query.since, query.until, &query.pagination()).await,
+    }
+    .map_err(|e| {
+        debug!("💻️ Could not fetch exchange rate history. {e}");
+        ServerError::BackendError(e.to_string())
+    })?;
+    let rates = rates.into_iter().map(ExchangeRateResult::from).collect::<Vec<_>>();
+    Ok(HttpResponse::Ok().json(rates))
+}
+
+//----------------------------------------------   Event outbox  ----------------------------------------------------
+route!(undelivered_events => Get "/outbox" impl EventOutbox where requires [Permission::EventsRead]);
+/// Lists events that have not been delivered to the storefront yet, oldest first. Pagination is supported.
+///
+/// This includes events that are waiting for a retry, as well as parked events (those with no `next_attempt_at`) that
+/// have run out of retries and will only be delivered again if they are replayed.
+pub async fn undelivered_events<B: EventOutbox>(
+    api: web::Data<OutboxApi<B>>,
+    pagination: web::Query<Pagination>,
+) -> Result<HttpResponse, ServerError> {
+    debug!("💻️ GET outbox");
+    let events = api.fetch_undelivered_events(pagination.deref()).await.map_err(|e| {
+        debug!("💻️ Could not fetch undelivered events. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().json(events))
+}
+
+route!(replay_event => Post "/outbox/{id}/replay" impl EventOutbox where requires [Permission::EventsReplay]);
+/// Schedules an outbox event for immediate delivery, resetting its retry schedule. Events that have already been
+/// delivered can be replayed too, in which case the storefront will receive them again.
+pub async fn replay_event<B: EventOutbox>(
+    api: web::Data<OutboxApi<B>>,
+    id: web::Path<i64>,
+) -> Result<HttpResponse, ServerError> {
+    let id = id.into_inner();
+    debug!("💻️ POST replay outbox event {id}");
+    let event = api.replay_event(id).await.map_err(|e| {
+        info!("💻️ Could not replay outbox event {id}. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().json(event))
+}
+
+//----------------------------------------------     Webhooks     ----------------------------------------------------
+route!(webhooks => Get "/webhooks" impl WebhookManagement where requires [Permission::WebhooksRead]);
+/// Lists all webhook subscriptions. Subscription secrets are not included.
+pub async fn webhooks<B: WebhookManagement>(api: web::Data<WebhookApi<B>>) -> Result<HttpResponse, ServerError> {
+    debug!("💻️ GET webhooks");
+    let webhooks = api.fetch_webhooks().await.map_err(|e| {
+        debug!("💻️ Could not fetch webhooks. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().json(webhooks))
+}
+
+route!(create_webhook => Post "/webhooks" impl WebhookManagement where requires [Permission::WebhooksManage]);
+/// Subscribes an HTTPS URL to an event type. The body is a [`NewWebhookSubscription`]. Deliveries are signed with the
+/// secret, which must be at least 16 characters long.
+///
+/// To subscribe a URL to several event types, create a subscription for each one.
+pub async fn create_webhook<B: WebhookManagement>(
+    api: web::Data<WebhookApi<B>>,
+    body: web::Json<NewWebhookSubscription>,
+) -> Result<HttpResponse, ServerError> {
+    let webhook = body.into_inner();
+    debug!("💻️ POST webhook for {} events to {}", webhook.event_type, webhook.url);
+    let webhook = api.create_webhook(webhook).await.map_err(|e| {
+        info!("💻️ Could not create webhook. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().json(webhook))
+}
+
+route!(update_webhook => Patch "/webhooks/{id}" impl WebhookManagement where requires [Permission::WebhooksManage]);
+/// Enables or disables a webhook subscription. Deliveries to a disabled subscription are held back until it is
+/// enabled again.
+pub async fn update_webhook<B: WebhookManagement>(
+    api: web::Data<WebhookApi<B>>,
+    id: web::Path<i64>,
+    body: web::Json<UpdateWebhookParams>,
+) -> Result<HttpResponse, ServerError> {
+    let id = id.into_inner();
+    debug!("💻️ PATCH webhook {id}. Active: {}", body.active);
+    let webhook = api.set_webhook_active(id, body.active).await.map_err(|e| {
+        info!("💻️ Could not update webhook {id}. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().json(webhook))
+}
+
+route!(delete_webhook => Delete "/webhooks/{id}" impl WebhookManagement where requires [Permission::WebhooksManage]);
+/// Deletes a webhook subscription, along with its delivery log.
+pub async fn delete_webhook<B: WebhookManagement>(
+    api: web::Data<WebhookApi<B>>,
+    id: web::Path<i64>,
+) -> Result<HttpResponse, ServerError> {
+    let id = id.into_inner();
+    debug!("💻️ DELETE webhook {id}");
+    api.delete_webhook(id).await.map_err(|e| {
+        info!("💻️ Could not delete webhook {id}. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+route!(webhook_deliveries => Get "/webhook_deliveries" impl WebhookManagement where requires [Permission::WebhooksRead]);
+/// The webhook delivery log, newest first. Use the `subscription_id` query parameter to restrict the log to a single
+/// subscription. Pagination is supported.
+pub async fn webhook_deliveries<B: WebhookManagement>(
+    api: web::Data<WebhookApi<B>>,
+    query: web::Query<WebhookDeliveryQuery>,
+) -> Result<HttpResponse, ServerError> {
+    debug!("💻️ GET webhook_deliveries");
+    let deliveries = api.fetch_deliveries(query.subscription_id, &query.pagination()).await.map_err(|e| {
+        debug!("💻️ Could not fetch webhook deliveries. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().json(deliveries))
+}
+
+route!(replay_webhook_delivery => Post "/webhook_deliveries/{id}/replay" impl WebhookManagement where requires [Permission::EventsReplay]);
+/// Schedules a webhook delivery for an immediate attempt, resetting its retry schedule.
+pub async fn replay_webhook_delivery<B: WebhookManagement>(
+    api: web::Data<WebhookApi<B>>,
+    id: web::Path<i64>,
+) -> Result<HttpResponse, ServerError> {
+    let id = id.into_inner();
+    debug!("💻️ POST replay webhook delivery {id}");
+    let delivery = api.replay_delivery(id).await.map_err(|e| {
+        info!("💻️ Could not replay webhook delivery {id}. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().json(delivery))
+}
+
+//----------------------------------------------      Refunds     ----------------------------------------------------
+route!(refunds => Get "/refunds" impl AccountManagement where requires [Permission::RefundsRead]);
+/// Lists refunds, newest first. Use the `status` query parameter to only list refunds with that status (e.g.
+/// `requested` for the refunds that are waiting for approval). Pagination is supported.
+pub async fn refunds<B: AccountManagement>(
+    api: web::Data<AccountApi<B>>,
+    query: web::Query<RefundQuery>,
+) -> Result<HttpResponse, ServerError> {
+    debug!("💻️ GET refunds");
+    let refunds = api.fetch_refunds(query.status, &query.pagination()).await.map_err(|e| {
+        debug!("💻️ Could not fetch refunds. {e}");
+        ServerError::BackendError(e.to_string())
+    })?;
+    Ok(HttpResponse::Ok().json(refunds))
+}
+
+route!(refund => Get "/refunds/{id}" impl AccountManagement where requires [Permission::RefundsRead]);
+pub async fn refund<B: AccountManagement>(
+    api: web::Data<AccountApi<B>>,
+    id: web::Path<i64>,
+) -> Result<HttpResponse, ServerError> {
+    let id = id.into_inner();
+    debug!("💻️ GET refund {id}");
+    let refund = api.fetch_refund(id).await.map_err(|e| {
+        debug!("💻️ Could not fetch refund {id}. {e}");
+        ServerError::BackendError(e.to_string())
+    })?;
+    match refund {
+        Some(refund) => Ok(HttpResponse::Ok().json(refund)),
+        None => Err(ServerError::NoRecordFound(format!("Refund {id} does not exist"))),
+    }
+}
+
+route!(request_refund => Post "/refunds" impl PaymentGatewayDatabase where requires [Permission::RefundsRequest]);
+/// Records a refund request. The body is a [`NewRefund`]. If `order_id` is given, the refund is made against the
+/// payment for that order, otherwise it comes out of the address's unspent balance.
+///
+/// The refund does not affect the address balance until it is approved.
+pub async fn request_refund<B: PaymentGatewayDatabase>(
+    api: web::Data<OrderFlowApi<B>>,
+    body: web::Json<NewRefund>,
+) -> Result<HttpResponse, ServerError> {
+    let refund = body.into_inner();
+    debug!("💻️ POST refund of {} for {}", refund.amount, refund.address.as_address());
+    let refund = api.request_refund(refund).await.map_err(|e| {
+        info!("💻️ Could not record refund request. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().json(refund))
+}
+
+route!(approve_refund => Post "/refunds/{id}/approve" impl PaymentGatewayDatabase where requires [Permission::RefundsApprove]);
+/// Approves a requested refund and debits it from the address balance. A `RefundApproved` event is emitted so that the
+/// payout wallet can send the refund.
+pub async fn approve_refund<B: PaymentGatewayDatabase>(
+    api: web::Data<OrderFlowApi<B>>,
+    id: web::Path<i64>,
+) -> Result<HttpResponse, ServerError> {
+    let id = id.into_inner();
+    debug!("💻️ POST approve refund {id}");
+    let refund = api.approve_refund(id).await.map_err(|e| {
+        info!("💻️ Could not approve refund {id}. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().json(refund))
+}
+
+route!(reject_refund => Post "/refunds/{id}/reject" impl PaymentGatewayDatabase where requires [Permission::RefundsApprove]);
+pub async fn reject_refund<B: PaymentGatewayDatabase>(
+    api: web::Data<OrderFlowApi<B>>,
+    id: web::Path<i64>,
+    body: web::Json<RejectRefundParams>,
+) -> Result<HttpResponse, ServerError> {
+    let id = id.into_inner();
+    debug!("💻️ POST reject refund {id}");
+    let refund = api.reject_refund(id, &body.reason).await.map_err(|e| {
+        info!("💻️ Could not reject refund {id}. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().json(refund))
+}
+
+route!(refund_sent => Post "/refunds/{id}/sent" impl PaymentGatewayDatabase where requires [Permission::RefundsSend]);
+/// Records that the payout wallet has sent an approved refund. A `RefundSent` event is emitted.
+pub async fn refund_sent<B: PaymentGatewayDatabase>(
+    api: web::Data<OrderFlowApi<B>>,
+    id: web::Path<i64>,
+    body: web::Json<RefundSentParams>,
+) -> Result<HttpResponse, ServerError> {
+    let id = id.into_inner();
+    debug!("💻️ POST refund {id} sent in {}", body.payout_txid);
+    let refund = api.mark_refund_sent(id, &body.payout_txid).await.map_err(|e| {
+        info!("💻️ Could not mark refund {id} as sent. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().json(refund))
+}
+
+//----------------------------------------------     Approvals    ----------------------------------------------------
+route!(approval_requests => Get "/approvals" impl AccountManagement where requires [Permission::ApprovalsRead]);
+/// Lists approval requests, newest first. Use the `status` query parameter to only list requests with that status (e.g.
+/// `pending` for the requests that are waiting for a decision). Pagination is supported.
+pub async fn approval_requests<B: AccountManagement>(
+    api: web::Data<AccountApi<B>>,
+    query: web::Query<ApprovalQuery>,
+) -> Result<HttpResponse, ServerError> {
+    debug!("💻️ GET approval requests");
+    let requests = api.fetch_approval_requests(query.status, &query.pagination()).await.map_err(|e| {
+        debug!("💻️ Could not fetch approval requests. {e}");
+        ServerError::BackendError(e.to_string())
+    })?;
+    Ok(HttpResponse::Ok().json(requests))
+}
+
+route!(approval_request => Get "/approvals/{id}" impl AccountManagement where requires [Permission::ApprovalsRead]);
+pub async fn approval_request<B: AccountManagement>(
+    api: web::Data<AccountApi<B>>,
+    id: web::Path<i64>,
+) -> Result<HttpResponse, ServerError> {
+    let id = id.into_inner();
+    debug!("💻️ GET approval request {id}");
+    let request = api.fetch_approval_request(id).await.map_err(|e| {
+        debug!("💻️ Could not fetch approval request {id}. {e}");
+        ServerError::BackendError(e.to_string())
+    })?;
+    match request {
+        Some(request) => Ok(HttpResponse::Ok().json(request)),
+        None => Err(ServerError::NoRecordFound(format!("Approval request {id} does not exist"))),
+    }
+}
+
+route!(approve_operation => Post "/approvals/{id}/approve" impl PaymentGatewayDatabase where requires [Permission::ApprovalsDecide]);
+/// Approves a pending request and carries out the operation. The approver must be a different address from the one
+/// that made the request, and must have the permission that the operation itself needs.
+///
+/// If the operation fails once it has been approved, the request is marked as `failed` and the error is returned.
+pub async fn approve_operation<B: PaymentGatewayDatabase>(
+    claims: JwtClaims,
+    api: web::Data<OrderFlowApi<B>>,
+    id: web::Path<i64>,
+    config: web::Data<ServerOptions>,
+    role_permissions: web::Data<RolePermissions>,
+) -> Result<HttpResponse, ServerError> {
+    let id = id.into_inner();
+    debug!("💻️ POST approve operation {id}");
+    check_approver(&claims, id, &api, &role_permissions).await?;
+    let request = api.approve_operation(id, &claims.address, config.strict_mode).await.map_err(|e| {
+        info!("💻️ Could not approve operation {id}. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().json(request))
+}
+
+route!(reject_operation => Post "/approvals/{id}/reject" impl PaymentGatewayDatabase where requires [Permission::ApprovalsDecide]);
+pub async fn reject_operation<B: PaymentGatewayDatabase>(
+    claims: JwtClaims,
+    api: web::Data<OrderFlowApi<B>>,
+    id: web::Path<i64>,
+    body: web::Json<RejectApprovalParams>,
+    role_permissions: web::Data<RolePermissions>,
+) -> Result<HttpResponse, ServerError> {
+    let id = id.into_inner();
+    debug!("💻️ POST reject operation {id}");
+    check_approver(&claims, id, &api, &role_permissions).await?;
+    let request = api.reject_operation(id, &claims.address, &body.reason).await.map_err(|e| {
+        info!("💻️ Could not reject operation {id}. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().json(request))
+}
+
+/// Checks that the caller has the permission that the operation in approval request `id` needs
+async fn check_approver<B: PaymentGatewayDatabase>(
+    claims: &JwtClaims,
+    id: i64,
+    api: &OrderFlowApi<B>,
+    role_permissions: &RolePermissions,
+) -> Result<(), ServerError> {
+    let request = api
+        .db()
+        .fetch_approval_request(id)
+        .await
+        .map_err(|e| ServerError::BackendError(e.to_string()))?
+        .ok_or_else(|| ServerError::NoRecordFound(format!("Approval request {id} does not exist")))?;
+    let permission = request.operation.permission();
+    if !role_permissions.allows(&claims.roles, &[permission]) {
+        warn!("💻️ {} tried to decide on approval request {id} without the {permission} permission", claims.address);
+        return Err(ServerError::InsufficientPermissions(format!(
+            "Deciding on this request needs the {permission} permission."
+        )));
+    }
+    Ok(())
+}
+
+//----------------------------------------------   Held orders    ----------------------------------------------------
+route!(held_orders => Get "/held_orders" impl AccountManagement where requires [Permission::OrdersRead]);
+/// Lists the orders that are being held because the exchange rate circuit breaker tripped when they arrived, oldest
+/// first. Pagination is supported.
+pub async fn held_orders<B: AccountManagement>(
+    api: web::Data<AccountApi<B>>,
+    pagination: web::Query<Pagination>,
+) -> Result<HttpResponse, ServerError> {
+    debug!("💻️ GET held orders");
+    let orders = api.fetch_held_orders(pagination.deref()).await.map_err(|e| {
+        debug!("💻️ Could not fetch held orders. {e}");
+        ServerError::BackendError(e.to_string())
+    })?;
+    Ok(HttpResponse::Ok().json(orders))
+}
+
+route!(release_held_order => Post "/held_orders/{order_id}/release" impl PaymentGatewayDatabase, ExchangeRates where requires [Permission::OrdersProcess]);
+/// Releases a held order. The order is priced at the current exchange rate, whether or not the rate would trip the
+/// circuit breaker, and is then processed like any other new order. Returns the new order.
+pub async fn release_held_order<BPay, BFx>(
+    path: web::Path<OrderId>,
+    api: web::Data<OrderFlowApi<BPay>>,
+    accounts: web::Data<AccountApi<BPay>>,
+    fx: web::Data<ExchangeRateApi<BFx>>,
+    config: web::Data<ServerOptions>,
+) -> Result<HttpResponse, ServerError>
+where
+    BPay: PaymentGatewayDatabase,
+    BFx: ExchangeRates,
+{
+    let order_id = path.into_inner();
+    debug!("💻️ POST release held order {order_id}");
+    let held_order = accounts
+        .fetch_held_order(&order_id)
+        .await
+        .map_err(|e| ServerError::BackendError(e.to_string()))?
+        .filter(|o| !o.is_released())
+        .ok_or_else(|| ServerError::NoRecordFound(format!("Order {order_id} is not being held")))?;
+    let breaker = RateCircuitBreaker::default();
+    let new_order = price_new_order(NewOrder::from(held_order), &fx, config.rate_quotes.as_ref(), &breaker).await?;
+    let order = api.release_held_order(new_order, true, config.strict_mode).await.map_err(|e| {
+        info!("💻️ Could not release held order {order_id}. {e}");
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok().json(order))
+}
+
+//----------------------------------------------   Ledger    ---------------------------------------------------------
+route!(ledger_balances => Get "/ledger/balances" impl AccountManagement where requires [Permission::LedgerRead]);
+/// The total debits and credits of every ledger account that has entries.
+pub async fn ledger_balances<B: AccountManagement>(api: web::Data<AccountApi<B>>) -> Result<HttpResponse, ServerError> {
+    debug!("💻️ GET ledger balances");
+    let balances = api.fetch_ledger_balances().await.map_err(|e| {
+        debug!("💻️ Could not fetch ledger balances. {e}");
+        ServerError::BackendError(e.to_string())
+    })?;
+    Ok(HttpResponse::Ok().json(balances))
+}
+
+route!(trial_balance => Get "/ledger/trial_balance" impl AccountManagement where requires [Permission::LedgerRead]);
+/// The trial balance of the ledger. The total debits and credits must match, and `unbalanced_transactions` must be
+/// empty. Anything else means that the ledger is corrupt.
+pub async fn trial_balance<B: AccountManagement>(api: web::Data<AccountApi<B>>) -> Result<HttpResponse, ServerError> {
+    debug!("💻️ GET trial balance");
+    let trial_balance = api.fetch_trial_balance().await.map_err(|e| {
+        debug!("💻️ Could not fetch the trial balance. {e}");
+        ServerError::BackendError(e.to_string())
+    })?;
+    Ok(HttpResponse::Ok().json(trial_balance))
+}
+
+route!(ledger_entries => Get "/ledger/entries" impl AccountManagement where requires [Permission::LedgerRead]);
+/// The entries posted to a single ledger account, oldest first. The account is given by the `account_type` and
+/// `account_id` query parameters. Pagination is supported.
+pub async fn ledger_entries<B: AccountManagement>(
+    api: web::Data<AccountApi<B>>,
+    query: web::Query<LedgerEntriesQuery>,
+) -> Result<HttpResponse, ServerError> {
+    let account = query.account();
+    debug!("💻️ GET ledger entries for {account}");
+    let entries = api.fetch_ledger_entries(&account, &query.pagination()).await.map_err(|e| {
+        debug!("💻️ Could not fetch ledger entries for {account}. {e}");
+        ServerError::BackendError(e.to_string())
+    })?;
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+//----------------------------------------------   Exports   ---------------------------------------------------------
+route!(export_report => Get "/export" impl AccountManagement, ExchangeRates where requires [Permission::ReportsExport]);
+/// Downloads an accounting report of the orders, payments, settlements or credit notes in a period. The `report`,
+/// `from` and `to` query parameters are required. `format` is one of `csv` (the default), `ofx` or `jsonl`. Records
+/// that are not tied to an order priced in fiat are valued in `currency`, if it is given.
+pub async fn export_report<BAcc, BFx>(
+    api: web::Data<ExportApi<BAcc, BFx>>,
+    query: web::Query<ExportRequest>,
+) -> Result<HttpResponse, ServerError>
+where
+    BAcc: AccountManagement,
+    BFx: ExchangeRates,
+{
+    let request = query.into_inner();
+    debug!("💻️ GET {} export from {} to {} as {}", request.report, request.from, request.to, request.format);
+    let report = api.export(&request).await.map_err(|e| {
+        debug!("💻️ Could not export the {} report. {e}", request.report);
+        ServerError::from(e)
+    })?;
+    Ok(HttpResponse::Ok()
+        .content_type(report.content_type)
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", report.file_name)))
+        .body(report.body))
+}
+
+//----------------------------------------------   Reconciliation   ---------------------------------------------------
+route!(reconcile_payments => Post "/reconcile" impl PaymentGatewayDatabase where requires [Permission::PaymentsReconcile]);
+/// Compares the payments with the hot wallet's transaction history, and returns a report of the missing, extra and
+/// mismatched payments. The history is taken from the request body, or fetched from the hot wallet over gRPC if the
+/// body doesn't include one. If `import_missing` is set, the missing transactions are recorded as payments, in the same
+/// way as payment notifications from the wallet. Importing needs the hot wallet's gRPC interface, since every missing
+/// transaction is looked up in the wallet before it is imported. Imported payments are confirmed by the usual
+/// confirmation flow.
+pub async fn reconcile_payments<B: PaymentGatewayDatabase>(
+    api: web::Data<ReconciliationApi<B>>,
+    wallet: web::Data<Option<WalletGrpcClient>>,
+    config: web::Data<ServerOptions>,
+    body: web::Json<ReconcileParams>,
+) -> Result<HttpResponse, ServerError> {
+    let ReconcileParams { transactions, since, import_missing, merchant } = body.into_inner();
+    debug!("💻️ POST reconcile payments");
+    if import_missing && wallet.is_none() {
+        return Err(ServerError::InvalidRequestBody(
+            "Missing payments can only be imported if the hot wallet's gRPC interface is configured".to_string(),
+        ));
+    }
+    let merchant_id = match (merchant, wallet.get_ref()) {
+        (Some(merchant), _) => merchant,
+        (None, Some(client)) if transactions.is_none() => client.merchant_id().to_string(),
+        (None, _) => default_merchant(),
+    };
+    let result = match (transactions, wallet.get_ref()) {
+        (Some(transactions), _) => api.reconcile(&transactions, since).await,
+        (None, Some(client)) => api.reconcile(client, since).await,
+        (None, None) => {
+            return Err(ServerError::InvalidRequestBody(
+                "No transactions were given, and the hot wallet's gRPC interface is not configured".to_string(),
+            ))
+        },
+    };
+    let mut report = result.map_err(|e| {
+        warn!("💻️ Could not reconcile payments. {e}");
+        ServerError::from(e)
+    })?;
+    if let Some(client) = wallet.get_ref().as_ref().filter(|_| import_missing) {
+        let require_signature = !config.disable_memo_signature_check;
+        let order_field = config.shopify_order_field;
+        api.import_missing(client, &mut report, config.strict_mode, |payment| {
+            payment.merchant_id = merchant_id.clone();
+            try_extract_order_id(payment, require_signature, order_field);
+        })
+        .await
+        .map_err(|e| {
+            warn!("💻️ Could not import the missing payments. {e}");
+            ServerError::from(e)
+        })?;
+    }
+    Ok(HttpResponse::Ok().json(report))
+}
+
+//----------------------------------------------   Audit log  ----------------------------------------------------
+route!(admin_actions => Get "/audit" impl AuditLog where requires [Permission::AuditRead]);
+/// Searches the admin audit log, newest first. The filters and pagination are given in the query string.
+pub async fn admin_actions<B: AuditLog>(
+    api: web::Data<AuditApi<B>>,
+    query: web::Query<AuditQuery>,
+) -> Result<HttpResponse, ServerError> {
+    debug!("💻️ GET admin actions. {query:?}");
+    let actions = api.fetch_admin_actions(&query.filter(), &query.pagination()).await?;
+    Ok(HttpResponse::Ok().json(actions))
+}
@@ -1,20 +1,32 @@
-use std::{net::IpAddr, time::Duration};
+use std::{net::SocketAddr, time::Duration};
 
 use actix_jwt_auth_middleware::use_jwt::UseJWTOnApp;
-use actix_web::{
-    dev::{Server, Service, ServiceRequest},
-    http::KeepAlive,
-    middleware::Logger,
-    web,
-    App,
-    HttpServer,
-};
-use futures::{future::ok, FutureExt};
+use actix_web::{dev::Server, http::KeepAlive, middleware::Logger, web, App, HttpServer};
 use log::*;
-use shopify_tools::ShopifyApi;
 use tari_payment_engine::{
-    events::EventProducers,
-    tpe_api::{exchange_rate_api::ExchangeRateApi, wallet_api::WalletManagementApi},
+    events::{EventHandlers, EventProducers, OutboxDispatcher},
+    tpe_api::{
+        audit_api::AuditApi,
+        exchange_rate_api::ExchangeRateApi,
+        export_api::ExportApi,
+        outbox_api::OutboxApi,
+        payment_objects::OverpaymentPolicy,
+        reconciliation_api::ReconciliationApi,
+        wallet_api::WalletManagementApi,
+        webhook_api::WebhookApi,
+    },
+    traits::{
+        AccountManagement,
+        AuditLog,
+        AuthManagement,
+        EventOutbox,
+        ExchangeRates,
+        PaymentGatewayDatabase,
+        RateLimitStore,
+        WalletAuth,
+        WalletManagement,
+        WebhookManagement,
+    },
     AccountApi,
     AuthApi,
     OrderFlowApi,
@@ -23,36 +35,60 @@ use tari_payment_engine::{
 };
 
 use crate::{
-    auth::{build_tps_authority, TokenIssuer},
-    config::{ServerConfig, ServerOptions},
-    errors::{AuthError, ServerError, ServerError::AuthenticationError},
+    auth::{build_tps_authority, RevocationList, RolePermissions, TokenIssuer},
+    config::{DatabaseBackend, ServerConfig, ServerOptions},
+    errors::ServerError,
+    event_stream::EventStream,
     expiry_worker::start_expiry_worker,
-    helpers::get_remote_ip,
-    integrations::shopify::create_shopify_event_handlers,
-    middleware::HmacMiddlewareFactory,
+    integrations::{
+        storefront::Storefronts,
+        wallet_grpc::WalletGrpcClient,
+        webhooks::{create_webhook_outbox_handler, start_webhook_worker},
+    },
+    middleware::{AclMiddlewareFactory, AuditTrail, MemoryRateLimits, RateLimiter},
+    overpayment_worker::start_overpayment_worker,
+    rate_feed_worker::start_rate_feed_worker,
     routes::{
         health,
+        metrics,
         AddAuthorizedWalletRoute,
         AddressesRoute,
+        AdminActionsRoute,
+        ApprovalRequestRoute,
+        ApprovalRequestsRoute,
+        ApproveOperationRoute,
+        ApproveRefundRoute,
         AuthRoute,
         BalanceRoute,
         CancelOrderRoute,
         CheckTokenRoute,
         ClaimOrderRoute,
+        CreateWebhookRoute,
         CreditorsRoute,
         CustomerIdsRoute,
+        DeleteRoleRoute,
+        DeleteWebhookRoute,
+        EventStreamRoute,
+        ExchangeRateHistoryRoute,
+        ExportReportRoute,
         FulfilOrderRoute,
         GetAuthorizedAddressesRoute,
         GetAuthorizedWalletsRoute,
         GetExchangeRateRoute,
+        HeldOrdersRoute,
         HistoryForAddressRoute,
         HistoryForCustomerRoute,
         IncomingPaymentNotificationRoute,
         IssueCreditRoute,
+        LedgerBalancesRoute,
+        LedgerEntriesRoute,
+        LogoutEverywhereRoute,
+        LogoutRoute,
         MyBalanceRoute,
         MyHistoryRoute,
         MyOrdersRoute,
         MyPaymentsRoute,
+        MySessionsRoute,
         MyUnfulfilledOrdersRoute,
         OrderByIdRoute,
         OrdersRoute,
@@ -60,19 +96,42 @@ use crate::{
         PaymentForOrderRoute,
         PaymentsRoute,
         ReassignOrderRoute,
+        ReconcilePaymentsRoute,
+        RefreshAccessTokenRoute,
+        RefundRoute,
+        RefundSentRoute,
+        RefundsRoute,
+        RejectOperationRoute,
+        RejectRefundRoute,
+        ReleaseHeldOrderRoute,
         RemoveAuthorizedWalletRoute,
+        ReplayEventRoute,
+        ReplayWebhookDeliveryRoute,
+        RequestRefundRoute,
         RescanOpenOrdersRoute,
         ResetOrderRoute,
+        RevokeSessionRoute,
+        RevokeSessionsForAddressRoute,
+        RoleDefinitionsRoute,
+        SaveRoleRoute,
+        SessionsForAddressRoute,
         SettleAddressRoute,
         SettleCustomerRoute,
         SettleMyAccountRoute,
+        TrialBalanceRoute,
         TxConfirmationNotificationRoute,
+        UndeliveredEventsRoute,
         UnfulfilledOrdersRoute,
         UpdateOrderMemoRoute,
         UpdatePriceRoute,
         UpdateRolesRoute,
+        UpdateWebhookRoute,
+        WebhookDeliveriesRoute,
+        WebhooksRoute,
     },
-    shopify_routes::{ShopifyOnProductUpdatedRoute, ShopifyWebhookRoute, UpdateShopifyExchangeRateRoute},
+    session_sync_worker::start_session_sync_worker,
+    storefront_routes::{configure_storefront_routes, UpdateExchangeRateRoute},
+    wallet_worker::start_wallet_worker,
 };
 
 /// Defines the log format for the access log middleware.
@@ -87,156 +146,303 @@ const LOG_FORMAT: &str = concat!(
     "%D ms",                                 // Time taken to serve the request in milliseconds
 );
 
+/// The number of events each in-process event hook can buffer before the order flow API has to wait for it.
+const EVENT_HOOK_BUFFER_SIZE: usize = 64;
+
+/// The full set of database capabilities the server needs from a backend. Any type implementing all the
+/// [`tari_payment_engine::traits`] traits gets this for free, so new backends can be plugged into
+/// [`create_server_instance`] without touching this module.
+pub trait ServerDatabase:
+    PaymentGatewayDatabase
+    + AccountManagement
+    + AuthManagement
+    + WalletAuth
+    + WalletManagement
+    + ExchangeRates
+    + EventOutbox
+    + WebhookManagement
+    + AuditLog
+    + RateLimitStore
+    + Clone
+    + Send
+    + Sync
+    + 'static
+{
+}
+
+impl<B> ServerDatabase for B where B: PaymentGatewayDatabase
+        + AccountManagement
+        + AuthManagement
+        + WalletAuth
+        + WalletManagement
+        + ExchangeRates
+        + EventOutbox
+        + WebhookManagement
+        + AuditLog
+        + RateLimitStore
+        + Clone
+        + Send
+        + Sync
+        + 'static
+{
+}
+
+/// Connects to the database given in `TPG_DATABASE_URL` and runs the server against it. The backend is selected from
+/// the URL scheme (`sqlite:` or `postgres:`).
 pub async fn run_server(config: ServerConfig) -> Result<(), ServerError> {
-    let db = SqliteDatabase::new_with_url(&config.database_url, 25)
+    match config.database_backend()? {
+        DatabaseBackend::Sqlite => {
+            info!("🚦️ Using SQLite database backend");
+            let db = SqliteDatabase::new_with_url(&config.database_url, 25)
+                .await
+                .map_err(|e| ServerError::InitializeError(e.to_string()))?;
+            run_server_with_db(config, db).await
+        },
+        #[cfg(feature = "postgres")]
+        DatabaseBackend::Postgres => {
+            info!("🚦️ Using PostgreSQL database backend");
+            let db = tari_payment_engine::PostgresDatabase::new_with_url(&config.database_url, 25)
+                .await
+                .map_err(|e| ServerError::InitializeError(e.to_string()))?;
+            run_server_with_db(config, db).await
+        },
+        #[cfg(not(feature = "postgres"))]
+        DatabaseBackend::Postgres => Err(ServerError::InitializeError(
+            "TPG_DATABASE_URL points to a PostgreSQL database, but this server was built without the `postgres` \
+             feature"
+                .to_string(),
+        )),
+    }
+}
+
+/// Runs the server, the event handlers and the background workers against an already-connected database backend.
+pub async fn run_server_with_db<B: ServerDatabase>(config: ServerConfig, db: B) -> Result<(), ServerError> {
+    info!("🚦️ Configuring storefront event handlers...");
+    let storefronts = Storefronts::from_config(&config)?;
+    let wallet_client = config
+        .wallet_grpc
+        .as_ref()
+        .map(WalletGrpcClient::new)
+        .transpose()
+        .map_err(|e| ServerError::InitializeError(format!("Failed to create the wallet gRPC client: {e}")))?;
+    // Storefront updates are delivered from the event outbox, which the backend writes to in the same transaction as
+    // the state change, so they survive restarts and are retried if the storefront is unavailable.
+    let mut dispatcher = OutboxDispatcher::new(db.clone(), config.outbox.clone());
+    dispatcher.add_handler("storefronts", storefronts.create_outbox_handler());
+    dispatcher.add_handler("webhooks", create_webhook_outbox_handler(db.clone()));
+    // The in-process hooks feed the live event stream. Unlike the outbox, they are best-effort.
+    let event_stream = EventStream::default();
+    let handlers = EventHandlers::new(EVENT_HOOK_BUFFER_SIZE, event_stream.hooks());
+    let producers = handlers.producers();
+    tokio::spawn(handlers.start_handlers());
+    // Access tokens for sessions that were revoked before a restart must still be refused
+    let revocations = RevocationList::load(&AuthApi::new(db.clone()), config.sessions.access_token_lifetime)
         .await
-        .map_err(|e| ServerError::InitializeError(e.to_string()))?;
-    // Shopify is the only supported integration at the moment. In future, this would be conditional code based on a
-    // configuration file.
-    info!("🚦️ Configuring Shopify event handlers...");
-    let shopify_config = config.shopify_config.shopify_api_config();
-    let shopify_handlers = create_shopify_event_handlers(shopify_config)
-        .map_err(|e| ServerError::InitializeError(format!("Failed to create Shopify event handlers: {e}")))?;
-    let producers = shopify_handlers.producers();
-    let srv = create_server_instance(config.clone(), db.clone(), producers.clone())?;
-    // Start the event handlers
-    tokio::spawn(async move {
-        info!("🚦️ Starting shopify event handlers...");
-        shopify_handlers.start_handlers().await;
-    });
-    let _never_ends =
-        start_expiry_worker(db.clone(), producers.clone(), config.unclaimed_order_timeout, config.unpaid_order_timeout);
+        .map_err(|e| ServerError::InitializeError(format!("Failed to load the revoked sessions: {e}")))?;
+    let role_permissions = RolePermissions::load(&AuthApi::new(db.clone()))
+        .await
+        .map_err(|e| ServerError::InitializeError(format!("Failed to load the role permissions: {e}")))?;
+    let srv = create_server_instance(
+        config.clone(),
+        db.clone(),
+        storefronts.clone(),
+        producers.clone(),
+        event_stream,
+        revocations.clone(),
+        role_permissions.clone(),
+    )?;
+    // The database futures are not guaranteed to be `Send`, so the dispatcher runs on the local task set.
+    let _dispatcher = actix_web::rt::spawn(dispatcher.run());
+    let _webhooks = start_webhook_worker(db.clone(), config.outbox.clone());
+    let _sessions = start_session_sync_worker(db.clone(), revocations, role_permissions, config.sessions);
+    let _never_ends = start_expiry_worker(
+        db.clone(),
+        producers.clone(),
+        config.unclaimed_order_timeout,
+        config.unpaid_order_timeout,
+        config.rate_quotes,
+        config.strict_mode,
+    );
+    if config.overpayment_policy != OverpaymentPolicy::StoreCredit {
+        let _overpayments = start_overpayment_worker(db.clone(), producers.clone(), config.overpayment_policy);
+    }
+    if let Some(rate_feed) = config.rate_feed.clone() {
+        let _rate_feed = start_rate_feed_worker(db.clone(), storefronts, rate_feed);
+    }
+    if let Some(address) = config.metrics_address {
+        let _metrics = actix_web::rt::spawn(create_metrics_server(address)?);
+    }
+    if let (Some(client), Some(wallet)) = (wallet_client, config.wallet_grpc.clone()) {
+        let options = ServerOptions::from_config(&config);
+        let _wallet = start_wallet_worker(db.clone(), producers.clone(), client, wallet, options);
+    }
     srv.await.map_err(|e| ServerError::Unspecified(e.to_string()))
 }
 
+/// Serves the Prometheus metrics on their own address, so that they can be kept on an internal network while the rest
+/// of the API is public.
+pub fn create_metrics_server(address: SocketAddr) -> Result<Server, ServerError> {
+    let srv = HttpServer::new(|| App::new().service(metrics)).workers(1).bind(address)?.run();
+    info!("📊️ Serving the Prometheus metrics at http://{address}/metrics");
+    Ok(srv)
+}
+
 #[allow(clippy::too_many_lines)]
-pub fn create_server_instance(
+pub fn create_server_instance<B: ServerDatabase>(
     config: ServerConfig,
-    db: SqliteDatabase,
+    db: B,
+    storefronts: Storefronts,
     producers: EventProducers,
+    event_stream: EventStream,
+    revocations: RevocationList,
+    role_permissions: RolePermissions,
 ) -> Result<Server, ServerError> {
     let proxy_config = ServerOptions::from_config(&config);
-    let shopify_config = config.shopify_config.shopify_api_config();
     let order_id_field = config.shopify_config.order_id_field;
-    let shopify_api = ShopifyApi::new(shopify_config).map_err(|e| {
-        let msg = format!("Failed to create Shopify API: {e}");
-        error!("{msg}");
-        ServerError::InitializeError(msg)
-    })?;
+    // The reconciliation endpoint fetches the hot wallet's history over gRPC, if it is configured
+    let wallet_client = config
+        .wallet_grpc
+        .as_ref()
+        .map(WalletGrpcClient::new)
+        .transpose()
+        .map_err(|e| ServerError::InitializeError(format!("Failed to create the wallet gRPC client: {e}")))?;
+    // Every worker shares the same buckets, unless they are kept in the database
+    let rate_limit_buckets = MemoryRateLimits::default();
     let srv = HttpServer::new(move || {
         let orders_api = OrderFlowApi::new(db.clone(), producers.clone());
         let auth_api = AuthApi::new(db.clone());
-        let jwt_signer = TokenIssuer::new(&config.auth);
+        let jwt_signer = TokenIssuer::new(&config.auth).with_session_config(config.sessions);
         let authority = build_tps_authority(config.auth.clone());
         let accounts_api = AccountApi::new(db.clone());
         let wallet_auth = WalletAuthApi::new(db.clone());
         let wallet_manager = WalletManagementApi::new(db.clone());
         let exchange_rates = ExchangeRateApi::new(db.clone());
-        let hmac_middleware = HmacMiddlewareFactory::new(
-            "X-Shopify-Hmac-Sha256",
-            config.shopify_config.hmac_secret.clone(),
-            config.shopify_config.hmac_checks,
-        );
+        let outbox_api = OutboxApi::new(db.clone());
+        let webhook_api = WebhookApi::new(db.clone());
+        let reconciliation_api = ReconciliationApi::new(db.clone(), producers.clone());
+        let export_api = ExportApi::new(db.clone(), db.clone());
+        let audit_api = AuditApi::new(db.clone());
+        let audit_trail = AuditTrail::new(AuditApi::new(db.clone()));
+        let rate_limiter = if config.rate_limits.shared {
+            RateLimiter::new(db.clone(), config.rate_limits)
+        } else {
+            RateLimiter::new(rate_limit_buckets.clone(), config.rate_limits)
+        };
 
-        let mut app = App::new()
+        let app = App::new()
             .wrap(Logger::new(LOG_FORMAT).log_target("access_log"))
             .app_data(web::Data::new(orders_api))
             .app_data(web::Data::new(accounts_api))
-            .app_data(web::Data::new(shopify_api.clone()))
+            .app_data(web::Data::new(storefronts.clone()))
             .app_data(web::Data::new(auth_api))
             .app_data(web::Data::new(jwt_signer))
             .app_data(web::Data::new(wallet_auth))
             .app_data(web::Data::new(wallet_manager))
             .app_data(web::Data::new(exchange_rates))
+            .app_data(web::Data::new(outbox_api))
+            .app_data(web::Data::new(webhook_api))
+            .app_data(web::Data::new(reconciliation_api))
+            .app_data(web::Data::new(export_api))
+            .app_data(web::Data::new(audit_api))
+            .app_data(web::Data::new(audit_trail))
+            .app_data(web::Data::new(rate_limiter))
+            .app_data(web::Data::new(wallet_client.clone()))
+            .app_data(web::Data::new(event_stream.clone()))
+            .app_data(web::Data::new(revocations.clone()))
+            .app_data(web::Data::new(role_permissions.clone()))
             .app_data(web::Data::new(proxy_config))
             .app_data(web::Data::new(order_id_field));
-        // Routes that require authentication
-        let auth_scope = web::scope("/api")
-            .service(UpdateRolesRoute::<SqliteDatabase>::new())
-            .service(BalanceRoute::<SqliteDatabase>::new())
-            .service(MyBalanceRoute::<SqliteDatabase>::new())
-            .service(MyHistoryRoute::<SqliteDatabase>::new())
-            .service(HistoryForAddressRoute::<SqliteDatabase>::new())
-            .service(HistoryForCustomerRoute::<SqliteDatabase>::new())
-            .service(MyOrdersRoute::<SqliteDatabase>::new())
-            .service(MyUnfulfilledOrdersRoute::<SqliteDatabase>::new())
-            .service(UnfulfilledOrdersRoute::<SqliteDatabase>::new())
-            .service(OrdersRoute::<SqliteDatabase>::new())
-            .service(OrderByIdRoute::<SqliteDatabase>::new())
-            .service(MyPaymentsRoute::<SqliteDatabase>::new())
-            .service(PaymentsRoute::<SqliteDatabase>::new())
-            .service(PaymentForOrderRoute::<SqliteDatabase>::new())
-            .service(OrdersSearchRoute::<SqliteDatabase>::new())
-            .service(CreditorsRoute::<SqliteDatabase>::new())
-            .service(IssueCreditRoute::<SqliteDatabase>::new())
-            .service(FulfilOrderRoute::<SqliteDatabase>::new())
-            .service(CancelOrderRoute::<SqliteDatabase>::new())
-            .service(UpdateOrderMemoRoute::<SqliteDatabase>::new())
-            .service(UpdatePriceRoute::<SqliteDatabase>::new())
-            .service(ReassignOrderRoute::<SqliteDatabase>::new())
-            .service(ResetOrderRoute::<SqliteDatabase>::new())
-            .service(GetExchangeRateRoute::<SqliteDatabase>::new())
-            .service(UpdateShopifyExchangeRateRoute::<SqliteDatabase>::new())
-            .service(CustomerIdsRoute::<SqliteDatabase>::new())
-            .service(AddressesRoute::<SqliteDatabase>::new())
-            .service(GetAuthorizedWalletsRoute::<SqliteDatabase>::new())
-            .service(RemoveAuthorizedWalletRoute::<SqliteDatabase>::new())
-            .service(AddAuthorizedWalletRoute::<SqliteDatabase>::new())
-            .service(SettleAddressRoute::<SqliteDatabase>::new())
-            .service(SettleCustomerRoute::<SqliteDatabase>::new())
-            .service(SettleMyAccountRoute::<SqliteDatabase>::new())
-            .service(RescanOpenOrdersRoute::<SqliteDatabase, SqliteDatabase>::new())
+        // Routes that require authentication. Every route in the scope refuses access tokens for revoked sessions,
+        // including those that do not need any roles.
+        let auth_routes = web::scope("")
+            .wrap(AclMiddlewareFactory::authenticated())
+            .service(UpdateRolesRoute::<B>::new())
+            .service(RoleDefinitionsRoute::<B>::new())
+            .service(SaveRoleRoute::<B>::new())
+            .service(DeleteRoleRoute::<B>::new())
+            .service(MySessionsRoute::<B>::new())
+            .service(SessionsForAddressRoute::<B>::new())
+            .service(RevokeSessionRoute::<B>::new())
+            .service(RevokeSessionsForAddressRoute::<B>::new())
+            .service(LogoutRoute::<B>::new())
+            .service(LogoutEverywhereRoute::<B>::new())
+            .service(BalanceRoute::<B>::new())
+            .service(MyBalanceRoute::<B>::new())
+            .service(MyHistoryRoute::<B>::new())
+            .service(HistoryForAddressRoute::<B>::new())
+            .service(HistoryForCustomerRoute::<B>::new())
+            .service(MyOrdersRoute::<B>::new())
+            .service(MyUnfulfilledOrdersRoute::<B>::new())
+            .service(UnfulfilledOrdersRoute::<B>::new())
+            .service(OrdersRoute::<B>::new())
+            .service(OrderByIdRoute::<B>::new())
+            .service(MyPaymentsRoute::<B>::new())
+            .service(PaymentsRoute::<B>::new())
+            .service(PaymentForOrderRoute::<B>::new())
+            .service(OrdersSearchRoute::<B>::new())
+            .service(CreditorsRoute::<B>::new())
+            .service(IssueCreditRoute::<B>::new())
+            .service(FulfilOrderRoute::<B>::new())
+            .service(CancelOrderRoute::<B>::new())
+            .service(UpdateOrderMemoRoute::<B>::new())
+            .service(UpdatePriceRoute::<B>::new())
+            .service(ReassignOrderRoute::<B>::new())
+            .service(ResetOrderRoute::<B>::new())
+            .service(GetExchangeRateRoute::<B>::new())
+            .service(ExchangeRateHistoryRoute::<B>::new())
+            .service(UpdateExchangeRateRoute::<B>::new())
+            .service(CustomerIdsRoute::<B>::new())
+            .service(AddressesRoute::<B>::new())
+            .service(GetAuthorizedWalletsRoute::<B>::new())
+            .service(RemoveAuthorizedWalletRoute::<B>::new())
+            .service(AddAuthorizedWalletRoute::<B>::new())
+            .service(SettleAddressRoute::<B>::new())
+            .service(SettleCustomerRoute::<B>::new())
+            .service(SettleMyAccountRoute::<B>::new())
+            .service(RescanOpenOrdersRoute::<B, B>::new())
+            .service(UndeliveredEventsRoute::<B>::new())
+            .service(ReplayEventRoute::<B>::new())
+            .service(WebhooksRoute::<B>::new())
+            .service(CreateWebhookRoute::<B>::new())
+            .service(UpdateWebhookRoute::<B>::new())
+            .service(DeleteWebhookRoute::<B>::new())
+            .service(WebhookDeliveriesRoute::<B>::new())
+            .service(ReplayWebhookDeliveryRoute::<B>::new())
+            .service(RefundsRoute::<B>::new())
+            .service(RefundRoute::<B>::new())
+            .service(RequestRefundRoute::<B>::new())
+            .service(ApproveRefundRoute::<B>::new())
+            .service(RejectRefundRoute::<B>::new())
+            .service(RefundSentRoute::<B>::new())
+            .service(ApprovalRequestsRoute::<B>::new())
+            .service(ApprovalRequestRoute::<B>::new())
+            .service(ApproveOperationRoute::<B>::new())
+            .service(RejectOperationRoute::<B>::new())
+            .service(HeldOrdersRoute::<B>::new())
+            .service(ReleaseHeldOrderRoute::<B, B>::new())
+            .service(EventStreamRoute::<B>::new())
+            .service(LedgerBalancesRoute::<B>::new())
+            .service(TrialBalanceRoute::<B>::new())
+            .service(LedgerEntriesRoute::<B>::new())
+            .service(ReconcilePaymentsRoute::<B>::new())
+            .service(ExportReportRoute::<B, B>::new())
+            .service(AdminActionsRoute::<B>::new())
             .service(CheckTokenRoute::new());
-        let use_x_forwarded_for = config.use_x_forwarded_for;
-        let use_forwarded = config.use_forwarded;
-        let shopify_whitelist = config.shopify_config.whitelist.clone();
-        let shopify_scope = web::scope("/shopify")
-            .wrap_fn(move |req, srv| {
-                let whitelisted = is_whitelisted(use_x_forwarded_for, use_forwarded, &shopify_whitelist, &req);
-                if whitelisted {
-                    srv.call(req)
-                } else {
-                    ok(req.error_response(AuthenticationError(AuthError::ForbiddenPeer))).boxed_local()
-                }
-            })
-            .wrap(hmac_middleware)
-            .service(ShopifyWebhookRoute::<SqliteDatabase, SqliteDatabase>::new())
-            .service(ShopifyOnProductUpdatedRoute::<SqliteDatabase>::new())
-            .service(health);
+        let auth_scope = web::scope("/api").service(auth_routes);
         let wallet_scope = web::scope("/wallet")
-            .service(GetAuthorizedAddressesRoute::<SqliteDatabase>::new())
-            .service(IncomingPaymentNotificationRoute::<SqliteDatabase, SqliteDatabase>::new())
-            .service(TxConfirmationNotificationRoute::<SqliteDatabase, SqliteDatabase>::new());
-        app = app.service(wallet_scope);
+            .service(GetAuthorizedAddressesRoute::<B>::new())
+            .service(IncomingPaymentNotificationRoute::<B, B>::new())
+            .service(TxConfirmationNotificationRoute::<B, B>::new());
         app.use_jwt(authority.clone(), auth_scope)
             .service(health)
-            .service(AuthRoute::<SqliteDatabase>::new())
-            .service(ClaimOrderRoute::<SqliteDatabase>::new())
-            .service(shopify_scope)
+            .service(AuthRoute::<B>::new())
+            .service(RefreshAccessTokenRoute::<B>::new())
+            .service(ClaimOrderRoute::<B>::new())
+            .service(wallet_scope)
+            .configure(|cfg| configure_storefront_routes::<B>(cfg, &config))
     })
     .keep_alive(KeepAlive::Timeout(Duration::from_secs(600)))
     .bind((config.host.as_str(), config.port))?
     .run();
     Ok(srv)
 }
-
-fn is_whitelisted(
-    use_x_forwarded_for: bool,
-    use_forwarded: bool,
-    shopify_whitelist: &Option<Vec<IpAddr>>,
-    req: &ServiceRequest,
-) -> bool {
-    let peer_ip = get_remote_ip(req.request(), use_x_forwarded_for, use_forwarded);
-    match (peer_ip, &shopify_whitelist) {
-        (Some(ip), Some(whitelist)) => {
-            let result = whitelist.contains(&ip);
-            info!("Shopify webhook request from {ip}. Permitted peer: {result}");
-            result
-        },
-        (_, None) => true,
-        (None, Some(_)) => {
-            warn!("No IP address found in shopify remote peer request. denying access.");
-            false
-        },
-    }
-}
@@ -0,0 +1,41 @@
+use log::*;
+use tari_payment_engine::{traits::AuthManagement, AuthApi};
+use tokio::task::JoinHandle;
+
+use crate::{
+    auth::{RevocationList, RolePermissions},
+    config::SessionConfig,
+};
+
+/// Starts the session sync worker, which reloads the revoked sessions and the role permissions from the database every
+/// `SessionConfig::sync_interval`. Do not await the returned JoinHandle, as it will run indefinitely.
+///
+/// Each server instance keeps its own [`RevocationList`] and [`RolePermissions`], so without this worker a session
+/// that is revoked, or a permission that is taken away from a role, on one instance would still be accepted by the
+/// others until they restart.
+///
+/// Spawned on the local task set, like the [expiry worker](crate::expiry_worker::start_expiry_worker).
+pub fn start_session_sync_worker<B: AuthManagement + 'static>(
+    db: B,
+    revocations: RevocationList,
+    role_permissions: RolePermissions,
+    config: SessionConfig,
+) -> JoinHandle<()> {
+    actix_web::rt::spawn(async move {
+        let api = AuthApi::new(db);
+        let mut timer = tokio::time::interval(config.sync_interval);
+        info!("🔐️ Session sync worker started. Polling every {}s", config.sync_interval.as_secs());
+        loop {
+            timer.tick().await;
+            match revocations.sync(&api, config.access_token_lifetime).await {
+                Ok(0) => {},
+                Ok(count) => info!("🔐️ {count} sessions were revoked by another server instance"),
+                Err(e) => error!("🔐️ Could not reload the revoked sessions. {e}"),
+            }
+            match role_permissions.sync(&api).await {
+                Ok(count) => trace!("🔐️ Reloaded the permissions for {count} roles"),
+                Err(e) => error!("🔐️ Could not reload the role permissions. {e}"),
+            }
+        }
+    })
+}
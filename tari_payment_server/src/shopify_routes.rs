@@ -1,37 +1,79 @@
 //----------------------------------------------   Checkout  ----------------------------------------------------
 
-use actix_web::{web, HttpRequest, HttpResponse};
+use std::net::IpAddr;
+
+use actix_web::{
+    dev::{Service, ServiceRequest},
+    web,
+    HttpRequest,
+    HttpResponse,
+};
+use futures::{future::ok, FutureExt};
 use log::{debug, error, info, trace, warn};
 use shopify_tools::{
     data_objects::ExchangeRate as ShopifyExchangeRate,
     helpers::{parse_shopify_price, tari_shopify_price},
-    ShopifyApi,
     ShopifyApiError,
-    ShopifyOrder,
     ShopifyProduct,
 };
 use tari_payment_engine::{
-    db_types::Role,
-    tpe_api::{exchange_objects::ExchangeRate, exchange_rate_api::ExchangeRateApi},
-    traits::{ExchangeRates, PaymentGatewayDatabase, PaymentGatewayError},
+    tpe_api::exchange_rate_api::ExchangeRateApi,
+    traits::{ExchangeRates, PaymentGatewayDatabase},
     OrderFlowApi,
 };
 use tpg_common::MicroTari;
 
 use crate::{
-    config::ServerOptions,
-    data_objects::{ExchangeRateUpdate, JsonResponse},
-    errors::ServerError,
-    integrations::shopify::{new_order_from_shopify_order, OrderConversionError},
+    config::{ServerConfig, ServerOptions},
+    data_objects::JsonResponse,
+    errors::{AuthError, ServerError::AuthenticationError},
+    helpers::get_remote_ip,
+    integrations::{
+        shopify::{ShopifyStorefront, SHOPIFY_PLATFORM, SHOP_DOMAIN_HEADER},
+        storefront::{StorefrontIntegration, Storefronts},
+    },
+    middleware::{HmacMiddlewareFactory, RateLimitGroup},
     route,
+    routes::health,
+    storefront_routes::handle_storefront_order,
 };
 
-route!(shopify_webhook => Post "webhook/checkout_create" impl PaymentGatewayDatabase, ExchangeRates);
+/// Registers the `/shopify` webhook routes. Webhooks must come from a whitelisted peer, and are signed with the HMAC
+/// secret of the shop that sent them.
+pub fn configure_routes<B>(cfg: &mut web::ServiceConfig, config: &ServerConfig)
+where B: PaymentGatewayDatabase + ExchangeRates + 'static {
+    let hmac_middleware = HmacMiddlewareFactory::new(
+        "X-Shopify-Hmac-Sha256",
+        config.shopify_config.hmac_secret.clone(),
+        config.shopify_config.hmac_checks,
+    )
+    .with_shop_keys(SHOP_DOMAIN_HEADER, config.shopify_hmac_secrets());
+    let use_x_forwarded_for = config.use_x_forwarded_for;
+    let use_forwarded = config.use_forwarded;
+    let shopify_whitelist = config.shopify_config.whitelist.clone();
+    let shopify_scope = web::scope("/shopify")
+        .wrap_fn(move |req, srv| {
+            let whitelisted = is_whitelisted(use_x_forwarded_for, use_forwarded, &shopify_whitelist, &req);
+            if whitelisted {
+                srv.call(req)
+            } else {
+                ok(req.error_response(AuthenticationError(AuthError::ForbiddenPeer))).boxed_local()
+            }
+        })
+        .wrap(hmac_middleware)
+        .service(ShopifyWebhookRoute::<B, B>::new())
+        .service(ShopifyOnProductUpdatedRoute::<B>::new())
+        .service(health);
+    cfg.service(shopify_scope);
+}
+
+route!(shopify_webhook => Post "webhook/checkout_create" impl PaymentGatewayDatabase, ExchangeRates where limited by RateLimitGroup::Webhooks);
 pub async fn shopify_webhook<BPay, BFx>(
     req: HttpRequest,
-    body: web::Json<ShopifyOrder>,
+    body: web::Bytes,
     api: web::Data<OrderFlowApi<BPay>>,
     fx: web::Data<ExchangeRateApi<BFx>>,
+    storefronts: web::Data<Storefronts>,
     config: web::Data<ServerOptions>,
 ) -> HttpResponse
 where
@@ -39,71 +81,41 @@ where
     BFx: ExchangeRates,
 {
     trace!("🛍️️ Received webhook request: {}", req.uri());
-    let order = body.into_inner();
     // Webhook responses must always be in 200 range, otherwise Shopify will retry
-    let result = handle_shopify_order(order, &fx, &api, &config).await;
+    let result = match storefronts.find_store(SHOPIFY_PLATFORM, shop_domain(&req)) {
+        Some(store) => handle_storefront_order(store, &body, &fx, &api, &config).await,
+        None => {
+            error!("🛍️️ No Shopify store is configured");
+            JsonResponse::failure("No Shopify store is configured.")
+        },
+    };
     HttpResponse::Ok().json(result)
 }
 
-pub async fn handle_shopify_order<BPay, BFx>(
-    order: ShopifyOrder,
-    fx: &ExchangeRateApi<BFx>,
-    api: &OrderFlowApi<BPay>,
-    config: &ServerOptions,
-) -> JsonResponse
-where
-    BPay: PaymentGatewayDatabase,
-    BFx: ExchangeRates,
-{
-    match new_order_from_shopify_order(order, fx).await {
-        Err(OrderConversionError::FormatError(s)) => {
-            warn!("🛍️️ Could not convert order. {s}");
-            JsonResponse::failure(s)
-        },
-        Err(OrderConversionError::InvalidMemoSignature(e)) => {
-            warn!("🛍️️ Could not verify memo signature. {e}");
-            JsonResponse::failure(e)
-        },
-        Err(OrderConversionError::UnsupportedCurrency(cur)) => {
-            info!("🛍️️ Unsupported currency in incoming order. {cur}");
-            JsonResponse::failure(format!("Unsupported currency: {cur}"))
-        },
-        Ok(new_order) => match api.process_new_order(new_order.clone(), true, config.strict_mode).await {
-            Ok(order) => {
-                info!(
-                    "🛍️️ Order {} for {} processed successfully. Current status is {}",
-                    order.order_id, order.total_price, order.status
-                );
-                JsonResponse::success("Order processed successfully.")
-            },
-            Err(PaymentGatewayError::DatabaseError(e)) => {
-                warn!("🛍️️ Could not process order {}. {e}", new_order.order_id);
-                debug!("🛍️️ Failed order: {new_order}");
-                JsonResponse::failure(e)
-            },
-            Err(PaymentGatewayError::OrderAlreadyExists(id)) => {
-                info!("🛍️️ Order {id} already exists.");
-                JsonResponse::success("Order already exists.")
-            },
-            Err(e) => {
-                warn!("🛍️️ Unexpected error while handling incoming order notification. {e}");
-                JsonResponse::failure("Unexpected error handling order.")
-            },
-        },
-    }
+/// The domain of the store that sent the webhook, if Shopify provided one.
+fn shop_domain(req: &HttpRequest) -> Option<&str> {
+    req.headers().get(SHOP_DOMAIN_HEADER).and_then(|v| v.to_str().ok())
 }
 
-route!(shopify_on_product_updated => Post "webhook/product_updated" impl ExchangeRates);
+route!(shopify_on_product_updated => Post "webhook/product_updated" impl ExchangeRates where limited by RateLimitGroup::Webhooks);
 pub async fn shopify_on_product_updated<BFx>(
+    req: HttpRequest,
     body: web::Json<ShopifyProduct>,
-    shopify_api: web::Data<ShopifyApi>,
+    storefronts: web::Data<Storefronts>,
     fx: web::Data<ExchangeRateApi<BFx>>,
 ) -> HttpResponse
 where
     BFx: ExchangeRates,
 {
     let product = body.into_inner();
-    let current_rate = match fx.fetch_last_rate("USD").await {
+    // Product updates are specific to Shopify, so the route needs the Shopify API itself
+    let store = storefronts.find_store(SHOPIFY_PLATFORM, shop_domain(&req));
+    let Some(shopify) = store.and_then(|s| s.as_any().downcast_ref::<ShopifyStorefront>()) else {
+        error!("🛍️️  No Shopify store is configured");
+        return HttpResponse::Ok().finish();
+    };
+    let shopify_api = shopify.api();
+    let current_rate = match fx.fetch_merchant_rate(shopify.merchant_id(), "USD").await {
         Ok(cr) => cr,
         Err(e) => {
             error!("🛍️️  Could not fetch exchange rate. {e}");
@@ -160,49 +172,23 @@ where
     HttpResponse::Ok().finish()
 }
 
-route!(update_shopify_exchange_rate => Post "/exchange_rate" impl ExchangeRates where requires [Role::Write]);
-pub async fn update_shopify_exchange_rate<B: ExchangeRates>(
-    body: web::Json<ExchangeRateUpdate>,
-    api: web::Data<ExchangeRateApi<B>>,
-    shopify_api: web::Data<ShopifyApi>,
-) -> Result<HttpResponse, ServerError> {
-    let update = body.into_inner();
-    #[allow(clippy::cast_possible_wrap)]
-    let amt = MicroTari::from(update.rate as i64);
-    debug!("🛍️️  POST update exchange rate for {} to {amt}", update.currency);
-    update_local_exchange_rate(update.clone(), api.as_ref()).await?;
-    debug!("🛍️️  Tari price has been updated in the database.");
-    update_shopify_exchange_rate_for(&update, shopify_api.as_ref()).await?;
-    Ok(HttpResponse::Ok().finish())
-}
-
-async fn update_local_exchange_rate<B: ExchangeRates>(
-    update: ExchangeRateUpdate,
-    api: &ExchangeRateApi<B>,
-) -> Result<(), ServerError> {
-    let rate = ExchangeRate::from(update);
-    debug!("🛍️️  POST update exchange rate for {rate}");
-    api.set_exchange_rate(&rate).await.map_err(|e| {
-        debug!("🛍️️  Could not update exchange rate. {e}");
-        ServerError::BackendError(e.to_string())
-    })
-}
-
-async fn update_shopify_exchange_rate_for(
-    update: &ExchangeRateUpdate,
-    shopify_api: &ShopifyApi,
-) -> Result<(), ServerError> {
-    #[allow(clippy::cast_possible_wrap)]
-    let rate = ShopifyExchangeRate::new(update.currency.to_string(), MicroTari::from(update.rate as i64));
-    debug!("🛍️️ Updating prices on Shopify storefront 1 {} = {}", rate.base_currency, rate.rate);
-    match shopify_api.update_all_prices(rate).await {
-        Ok(v) => {
-            info!("🛍️️ {} variant prices updated on shopify storefront.", v.len());
-            Ok(())
+fn is_whitelisted(
+    use_x_forwarded_for: bool,
+    use_forwarded: bool,
+    shopify_whitelist: &Option<Vec<IpAddr>>,
+    req: &ServiceRequest,
+) -> bool {
+    let peer_ip = get_remote_ip(req.request(), use_x_forwarded_for, use_forwarded);
+    match (peer_ip, &shopify_whitelist) {
+        (Some(ip), Some(whitelist)) => {
+            let result = whitelist.contains(&ip);
+            info!("Shopify webhook request from {ip}. Permitted peer: {result}");
+            result
         },
-        Err(e) => {
-            error!("🛍️️ Could not update variant prices on Shopify. {e}");
-            Err(ServerError::BackendError(e.to_string()))
+        (_, None) => true,
+        (None, Some(_)) => {
+            warn!("No IP address found in shopify remote peer request. denying access.");
+            false
         },
     }
 }
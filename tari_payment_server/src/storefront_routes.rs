@@ -0,0 +1,159 @@
+//----------------------------------------------   Storefronts  ----------------------------------------------------
+//! Routes and helpers that are shared by every storefront integration.
+//!
+//! Each shop platform has its own webhook routes (see [`crate::shopify_routes`] and [`crate::woocommerce_routes`]),
+//! which find the store that sent the webhook in the [`Storefronts`] registry and hand the order to
+//! [`handle_storefront_order`]. The platforms' routes are registered with [`configure_storefront_routes`].
+
+use actix_web::{web, HttpResponse};
+use log::{debug, info, warn};
+use tari_payment_engine::{
+    db_types::{NewOrder, Permission},
+    tpe_api::{exchange_objects::ExchangeRate, exchange_rate_api::ExchangeRateApi},
+    traits::{ExchangeRates, PaymentGatewayDatabase, PaymentGatewayError},
+    OrderFlowApi,
+};
+use tpg_common::MicroTari;
+
+use crate::{
+    auth::JwtClaims,
+    config::{ServerConfig, ServerOptions},
+    data_objects::{ExchangeRateUpdate, JsonResponse},
+    errors::ServerError,
+    integrations::storefront::{price_new_order, OrderConversionError, Storefront, Storefronts},
+    route,
+    shopify_routes,
+    woocommerce_routes,
+};
+
+/// Registers the webhook routes of every shop platform in the configuration.
+pub fn configure_storefront_routes<B>(cfg: &mut web::ServiceConfig, config: &ServerConfig)
+where B: PaymentGatewayDatabase + ExchangeRates + 'static {
+    shopify_routes::configure_routes::<B>(cfg, config);
+    if let Some(woocommerce) = &config.woocommerce {
+        woocommerce_routes::configure_routes::<B>(cfg, woocommerce);
+    }
+}
+
+route!(update_exchange_rate => Post "/exchange_rate" impl ExchangeRates where merchant requires [Permission::RatesUpdate]);
+/// Sets the exchange rate, and pushes the new prices to the storefronts that use it. Merchant-scoped tokens can only
+/// set their own merchant's rate.
+pub async fn update_exchange_rate<B: ExchangeRates>(
+    claims: JwtClaims,
+    body: web::Json<ExchangeRateUpdate>,
+    api: web::Data<ExchangeRateApi<B>>,
+    storefronts: web::Data<Storefronts>,
+) -> Result<HttpResponse, ServerError> {
+    let mut update = body.into_inner();
+    if claims.merchant.is_some() {
+        update.merchant = claims.merchant;
+    }
+    #[allow(clippy::cast_possible_wrap)]
+    let amt = MicroTari::from(update.rate as i64);
+    debug!("🛍️️  POST update exchange rate for {} to {amt}", update.currency);
+    let rate = ExchangeRate::from(update);
+    api.set_exchange_rate(&rate).await.map_err(|e| {
+        debug!("🛍️️  Could not update exchange rate. {e}");
+        ServerError::BackendError(e.to_string())
+    })?;
+    debug!("🛍️️  Tari price has been updated in the database.");
+    let errors = storefronts.update_prices(&rate, api.db()).await;
+    if errors.is_empty() {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        Err(ServerError::BackendError(errors.join(". ")))
+    }
+}
+
+/// Converts the body of an order webhook from `store`, prices the order and adds it to the database.
+pub async fn handle_storefront_order<BPay, BFx>(
+    store: &Storefront,
+    body: &[u8],
+    fx: &ExchangeRateApi<BFx>,
+    api: &OrderFlowApi<BPay>,
+    config: &ServerOptions,
+) -> JsonResponse
+where
+    BPay: PaymentGatewayDatabase,
+    BFx: ExchangeRates,
+{
+    match store.parse_order(body) {
+        Ok(unpriced) => handle_new_order(unpriced, fx, api, config).await,
+        Err(e) => conversion_failure(e),
+    }
+}
+
+/// Prices a new storefront order and adds it to the database. If the exchange rate trips the circuit breaker, the order
+/// is held instead.
+pub async fn handle_new_order<BPay, BFx>(
+    unpriced: NewOrder,
+    fx: &ExchangeRateApi<BFx>,
+    api: &OrderFlowApi<BPay>,
+    config: &ServerOptions,
+) -> JsonResponse
+where
+    BPay: PaymentGatewayDatabase,
+    BFx: ExchangeRates,
+{
+    let breaker = &config.rate_circuit_breaker;
+    match price_new_order(unpriced.clone(), fx, config.rate_quotes.as_ref(), breaker).await {
+        Err(OrderConversionError::RateCircuitBreaker(reason)) => match api.hold_order(&unpriced, &reason).await {
+            Ok(held_order) => {
+                warn!("🛍️️ Order {} is being held until an admin releases it. {reason}", held_order.order_id);
+                JsonResponse::success("Order is being held for review.")
+            },
+            Err(PaymentGatewayError::OrderAlreadyExists(id)) => {
+                info!("🛍️️ Order {id} already exists.");
+                JsonResponse::success("Order already exists.")
+            },
+            Err(e) => {
+                warn!("🛍️️ Could not hold order {}. {e}", unpriced.order_id);
+                JsonResponse::failure("Unexpected error handling order.")
+            },
+        },
+        Err(e) => conversion_failure(e),
+        Ok(new_order) => match api.process_new_order(new_order.clone(), true, config.strict_mode).await {
+            Ok(order) => {
+                info!(
+                    "🛍️️ Order {} for {} processed successfully. Current status is {}",
+                    order.order_id, order.total_price, order.status
+                );
+                JsonResponse::success("Order processed successfully.")
+            },
+            Err(PaymentGatewayError::DatabaseError(e)) => {
+                warn!("🛍️️ Could not process order {}. {e}", new_order.order_id);
+                debug!("🛍️️ Failed order: {new_order}");
+                JsonResponse::failure(e)
+            },
+            Err(PaymentGatewayError::OrderAlreadyExists(id)) => {
+                info!("🛍️️ Order {id} already exists.");
+                JsonResponse::success("Order already exists.")
+            },
+            Err(e) => {
+                warn!("🛍️️ Unexpected error while handling incoming order notification. {e}");
+                JsonResponse::failure("Unexpected error handling order.")
+            },
+        },
+    }
+}
+
+pub fn conversion_failure(e: OrderConversionError) -> JsonResponse {
+    match e {
+        OrderConversionError::FormatError(s) => {
+            warn!("🛍️️ Could not convert order. {s}");
+            JsonResponse::failure(s)
+        },
+        OrderConversionError::InvalidMemoSignature(e) => {
+            warn!("🛍️️ Could not verify memo signature. {e}");
+            JsonResponse::failure(e)
+        },
+        OrderConversionError::UnsupportedCurrency(cur) => {
+            info!("🛍️️ Unsupported currency in incoming order. {cur}");
+            JsonResponse::failure(format!("Unsupported currency: {cur}"))
+        },
+        OrderConversionError::RateCircuitBreaker(reason) => {
+            warn!("🛍️️ Could not price order. {reason}");
+            JsonResponse::failure(reason)
+        },
+    }
+}
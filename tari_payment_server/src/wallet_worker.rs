@@ -0,0 +1,543 @@
+use std::collections::{HashMap, HashSet};
+
+use log::*;
+use tari_common_types::tari_address::TariAddress;
+use tari_payment_engine::{
+    db_types::{NewPayment, TransferStatus},
+    events::EventProducers,
+    tpe_api::payment_objects::ConfirmationPolicy,
+    traits::{PaymentGatewayDatabase, PaymentGatewayError},
+    OrderFlowApi,
+};
+use tokio::task::JoinHandle;
+use tpg_common::MicroTari;
+
+use crate::{
+    config::{ServerOptions, WalletGrpcConfig},
+    helpers::try_extract_order_id,
+    integrations::wallet_grpc::{
+        tari_rpc::{TransactionDirection, TransactionInfo, TransactionStatus},
+        WalletGrpcClient,
+        WalletGrpcError,
+    },
+};
+
+/// Transactions that are this many blocks deep (about a day) are treated as final. They are no longer watched for
+/// reorgs, and the watcher forgets about them.
+const REORG_SAFE_DEPTH: u64 = 720;
+
+/// Starts the wallet worker, which watches the hot wallet over gRPC and records its incoming payments. Do not await
+/// the returned JoinHandle, as it will run indefinitely.
+///
+/// This does the same job as the wallet's notify script and the `/wallet` endpoints. Both can be used at the same time,
+/// since payments and confirmations are only recorded once.
+///
+/// Spawned on the local task set, like the [expiry worker](crate::expiry_worker::start_expiry_worker).
+pub fn start_wallet_worker<B: PaymentGatewayDatabase + 'static>(
+    db: B,
+    producers: EventProducers,
+    client: WalletGrpcClient,
+    config: WalletGrpcConfig,
+    options: ServerOptions,
+) -> JoinHandle<()> {
+    actix_web::rt::spawn(async move {
+        let mut timer = tokio::time::interval(config.poll_interval);
+        let api = OrderFlowApi::new(db, producers);
+        let mut watcher = WalletWatcher::new(api, client, config.confirmations, options);
+        info!("👛️ Wallet watcher started for {}", config.address);
+        loop {
+            timer.tick().await;
+            if let Err(e) = watcher.poll().await {
+                warn!("👛️ Could not fetch transactions from the hot wallet. {e}");
+            }
+        }
+    })
+}
+
+/// Turns the hot wallet's inbound transactions into payments.
+///
+/// A payment is recorded as soon as the wallet has completed the transaction, and is confirmed once the transaction is
+/// as many blocks deep as the [`ConfirmationPolicy`] requires for its amount. If the wallet cancels or rejects the
+/// transaction before then, the payment is cancelled.
+///
+/// Confirmed payments are watched for reorgs. If the transaction leaves the chain, the payment is reverted, and is
+/// confirmed again once the transaction has been mined again and is deep enough.
+///
+/// Once a transaction is [`REORG_SAFE_DEPTH`] blocks deep, it is final: the watcher drops it from its state, and
+/// skips it from the next poll on, so the state only covers transactions that can still change. A cancelled
+/// transaction is only looked at if it had been recorded as a payment, or on the first poll after a restart.
+pub struct WalletWatcher<B> {
+    api: OrderFlowApi<B>,
+    client: WalletGrpcClient,
+    policy: ConfirmationPolicy,
+    options: ServerOptions,
+    /// How many blocks deep a transaction must be before it is final
+    final_depth: u64,
+    /// The scanned height at the last successful poll
+    last_height: Option<u64>,
+    /// Transactions that have been recorded as payments, but are not confirmed yet
+    received: HashSet<u64>,
+    /// Transactions whose payments are confirmed, and the height of the block that they were mined in
+    confirmed: HashMap<u64, Option<i64>>,
+    /// Transactions that need no further processing until they are final, and the height that they were mined in (0
+    /// if they have not been mined)
+    settled: HashMap<u64, u64>,
+}
+
+impl<B: PaymentGatewayDatabase> WalletWatcher<B> {
+    pub fn new(
+        api: OrderFlowApi<B>,
+        client: WalletGrpcClient,
+        policy: ConfirmationPolicy,
+        options: ServerOptions,
+    ) -> Self {
+        // The largest amounts need the most confirmations
+        let final_depth = REORG_SAFE_DEPTH.max(policy.required_confirmations(MicroTari::from(i64::MAX)));
+        Self {
+            api,
+            client,
+            policy,
+            options,
+            final_depth,
+            last_height: None,
+            received: HashSet::new(),
+            confirmed: HashMap::new(),
+            settled: HashMap::new(),
+        }
+    }
+
+    /// Fetches the wallet's transactions and brings the payments up to date.
+    pub async fn poll(&mut self) -> Result<(), WalletGrpcError> {
+        let height = self.client.scanned_height().await?;
+        // A transaction that was final at the last poll was handled by then, so it is dropped as it streams in
+        let (last_height, final_depth) = (self.last_height, self.final_depth);
+        let transactions = self
+            .client
+            .completed_transactions_matching(|tx| {
+                tx.direction() == TransactionDirection::Inbound &&
+                    !last_height.is_some_and(|h| is_final(tx.mined_in_block_height, h, final_depth))
+            })
+            .await?;
+        trace!("👛️ Fetched {} unsettled transactions from the hot wallet at height {height}", transactions.len());
+        for tx in transactions {
+            if let Some(mined) = self.settled.get_mut(&tx.tx_id) {
+                *mined = tx.mined_in_block_height;
+                continue;
+            }
+            let known = self.received.contains(&tx.tx_id) || self.confirmed.contains_key(&tx.tx_id);
+            if tx.is_cancelled || tx.status() == TransactionStatus::Rejected {
+                // After the first poll, every payment that could be cancelled is known
+                if known || last_height.is_none() {
+                    self.cancel(&tx).await;
+                }
+                continue;
+            }
+            if !known && !self.receive(&tx).await {
+                continue;
+            }
+            self.track(&tx, height).await;
+        }
+        self.confirmed.retain(|_, mined| !mined.is_some_and(|m| is_final(m.unsigned_abs(), height, final_depth)));
+        self.settled.retain(|_, mined| !is_final(*mined, height, final_depth));
+        self.last_height = Some(height);
+        Ok(())
+    }
+
+    /// Brings the payment's block height and confirmations up to date, which confirms it once it is deep enough, and
+    /// reverts it if it has been lost in a reorg. If the wallet doesn't report block heights, its own confirmation
+    /// status is used instead, and reorgs are not detected.
+    async fn track(&mut self, tx: &TransactionInfo, height: u64) {
+        let Some((block_height, confirmations)) = chain_position(tx, height) else {
+            let wallet_confirmed =
+                matches!(tx.status(), TransactionStatus::MinedConfirmed | TransactionStatus::OneSidedConfirmed);
+            if wallet_confirmed && self.received.contains(&tx.tx_id) {
+                self.confirm(tx).await;
+            }
+            return;
+        };
+        // Confirmed payments only need attention if their transaction moves
+        if self.confirmed.get(&tx.tx_id) == Some(&block_height) {
+            return;
+        }
+        let txid = tx.tx_id.to_string();
+        let strict_mode = self.options.strict_mode;
+        match self.api.update_confirmations(&txid, block_height, confirmations, &self.policy, strict_mode).await {
+            Ok(payment) => match payment.status {
+                TransferStatus::Confirmed => {
+                    if self.received.remove(&tx.tx_id) {
+                        info!("👛️ Payment {txid} confirmed");
+                    }
+                    self.confirmed.insert(tx.tx_id, block_height);
+                },
+                TransferStatus::Received => {
+                    if self.confirmed.remove(&tx.tx_id).is_some() {
+                        warn!(
+                            "👛️ Payment {txid} is no longer confirmed. It will be confirmed again once it is re-mined."
+                        );
+                    }
+                    self.received.insert(tx.tx_id);
+                },
+                TransferStatus::Cancelled => {
+                    self.received.remove(&tx.tx_id);
+                    self.confirmed.remove(&tx.tx_id);
+                    self.settled.insert(tx.tx_id, tx.mined_in_block_height);
+                },
+            },
+            Err(e) => warn!("👛️ Could not update the confirmations of payment {txid}. {e}"),
+        }
+    }
+
+    /// Records the payment. Returns false if it could not be recorded.
+    async fn receive(&mut self, tx: &TransactionInfo) -> bool {
+        let txid = tx.tx_id.to_string();
+        let mut payment = match new_payment(tx, self.client.merchant_id()) {
+            Ok(payment) => payment,
+            Err(e) => {
+                warn!("👛️ Ignoring transaction {txid}. {e}");
+                self.settled.insert(tx.tx_id, tx.mined_in_block_height);
+                return false;
+            },
+        };
+        let require_signature = !self.options.disable_memo_signature_check;
+        match try_extract_order_id(&mut payment, require_signature, self.options.shopify_order_field) {
+            Some(true) => debug!("👛️ Payment {txid} contains a claim for an order"),
+            Some(false) | None => debug!("👛️ Payment {txid} does not contain a claim for an order"),
+        }
+        match self.api.process_new_payment(payment, self.options.strict_mode).await {
+            Ok(payment) => {
+                info!("👛️ Payment {txid} for {} received from {}", payment.amount, payment.sender.as_base58())
+            },
+            Err(PaymentGatewayError::PaymentAlreadyExists(_)) => debug!("👛️ Payment {txid} has already been recorded"),
+            Err(e) => {
+                warn!("👛️ Could not record payment {txid}. {e}");
+                return false;
+            },
+        }
+        self.received.insert(tx.tx_id);
+        true
+    }
+
+    async fn confirm(&mut self, tx: &TransactionInfo) {
+        let txid = tx.tx_id.to_string();
+        match self.api.confirm_payment(txid.clone(), self.options.strict_mode).await {
+            Ok(_) => info!("👛️ Payment {txid} confirmed"),
+            Err(PaymentGatewayError::PaymentModificationNoOp) => debug!("👛️ Payment {txid} was already confirmed"),
+            // e.g. the payment has been cancelled in the meantime. Trying again won't help.
+            Err(PaymentGatewayError::PaymentStatusUpdateError(e)) => {
+                warn!("👛️ Could not confirm payment {txid}. {e}");
+                self.received.remove(&tx.tx_id);
+                self.settled.insert(tx.tx_id, tx.mined_in_block_height);
+                return;
+            },
+            Err(e) => {
+                warn!("👛️ Could not confirm payment {txid}. {e}");
+                return;
+            },
+        }
+        self.received.remove(&tx.tx_id);
+        self.confirmed.insert(tx.tx_id, None);
+    }
+
+    /// Cancels the payment. A payment that was already confirmed is reverted first, since the transaction that paid
+    /// for its orders is gone.
+    async fn cancel(&mut self, tx: &TransactionInfo) {
+        let txid = tx.tx_id.to_string();
+        let was_confirmed = match self.api.db().fetch_payment_by_tx_id(&txid).await {
+            Ok(payment) => payment.status == TransferStatus::Confirmed,
+            Err(_) => false,
+        };
+        if was_confirmed {
+            if let Err(e) = self.api.revert_payment(&txid).await {
+                warn!("👛️ Could not revert payment {txid} before cancelling it. {e}");
+                return;
+            }
+        }
+        match self.api.cancel_payment(txid.clone()).await {
+            Ok(()) => info!("👛️ Payment {txid} was cancelled by the wallet"),
+            // The payment was never recorded, or has already been cancelled or confirmed
+            Err(PaymentGatewayError::PaymentStatusUpdateError(_) | PaymentGatewayError::PaymentModificationNoOp) => {
+                debug!("👛️ Transaction {txid} was cancelled. There is no payment to cancel.");
+            },
+            Err(e) => {
+                warn!("👛️ Could not cancel payment {txid}. {e}");
+                return;
+            },
+        }
+        self.received.remove(&tx.tx_id);
+        self.confirmed.remove(&tx.tx_id);
+    }
+}
+
+/// Whether a transaction mined at `mined` is at least `depth` blocks deep at `height`. Unmined transactions never are.
+fn is_final(mined: u64, height: u64, depth: u64) -> bool {
+    mined > 0 && height >= mined && height - mined + 1 >= depth
+}
+
+/// The block height that the transaction was mined in, and how many blocks deep it is, as far as the wallet knows.
+/// Returns `None` if the wallet says the transaction has been mined but doesn't report the block height.
+fn chain_position(tx: &TransactionInfo, height: u64) -> Option<(Option<i64>, i64)> {
+    match tx.mined_in_block_height {
+        0 => match tx.status() {
+            TransactionStatus::MinedUnconfirmed |
+            TransactionStatus::MinedConfirmed |
+            TransactionStatus::OneSidedUnconfirmed |
+            TransactionStatus::OneSidedConfirmed => None,
+            _ => Some((None, 0)),
+        },
+        mined => {
+            let confirmations = if height >= mined { height - mined + 1 } else { 0 };
+            let mined = i64::try_from(mined).ok()?;
+            Some((Some(mined), i64::try_from(confirmations).unwrap_or(i64::MAX)))
+        },
+    }
+}
+
+/// The payment for an inbound transaction. The wallet's transaction id is used as the txid, as it is by the notify
+/// script, so that payments are not recorded twice when both are in use. The payment belongs to the wallet's merchant.
+fn new_payment(tx: &TransactionInfo, merchant_id: &str) -> Result<NewPayment, String> {
+    let sender = TariAddress::from_bytes(&tx.source_address).map_err(|e| format!("Invalid sender address. {e}"))?;
+    let amount = i64::try_from(tx.amount).map_err(|_| format!("Invalid amount: {}", tx.amount))?;
+    let mut payment = NewPayment::new(sender, MicroTari::from(amount), tx.tx_id.to_string()).with_merchant(merchant_id);
+    if !tx.message.trim().is_empty() {
+        payment.with_memo(tx.message.clone());
+    }
+    Ok(payment)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        pin::Pin,
+        sync::{Arc, Mutex},
+    };
+
+    use futures::Stream;
+    use tari_payment_engine::InMemoryDatabase;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::{transport::Server, Request, Response, Status};
+    use tpg_common::Secret;
+
+    use super::*;
+    use crate::{
+        config::ServerConfig,
+        integrations::wallet_grpc::tari_rpc::{
+            wallet_server::{Wallet, WalletServer},
+            GetCompletedTransactionsRequest,
+            GetCompletedTransactionsResponse,
+            GetStateRequest,
+            GetStateResponse,
+        },
+    };
+
+    const SENDER: &str = "14AYt2hhhn4VydAXNJ6i7ZfRNZGoGSp713dHjMYCoK5hYw2";
+    // Basic auth with user:pass
+    const AUTHORIZATION: &str = "Basic dXNlcjpwYXNz";
+
+    /// A console wallet with a fixed set of transactions
+    #[derive(Clone, Default)]
+    struct MockWallet {
+        state: Arc<Mutex<(u64, Vec<TransactionInfo>)>>,
+    }
+
+    impl MockWallet {
+        fn set_height(&self, height: u64) {
+            self.state.lock().unwrap().0 = height;
+        }
+
+        fn update<F: FnOnce(&mut Vec<TransactionInfo>)>(&self, f: F) {
+            f(&mut self.state.lock().unwrap().1);
+        }
+    }
+
+    fn check_auth<T>(request: &Request<T>) -> Result<(), Status> {
+        match request.metadata().get("authorization").and_then(|v| v.to_str().ok()) {
+            Some(AUTHORIZATION) => Ok(()),
+            _ => Err(Status::unauthenticated("Invalid credentials")),
+        }
+    }
+
+    #[tonic::async_trait]
+    impl Wallet for MockWallet {
+        type GetCompletedTransactionsStream =
+            Pin<Box<dyn Stream<Item = Result<GetCompletedTransactionsResponse, Status>> + Send>>;
+
+        async fn get_state(&self, request: Request<GetStateRequest>) -> Result<Response<GetStateResponse>, Status> {
+            check_auth(&request)?;
+            let scanned_height = self.state.lock().unwrap().0;
+            Ok(Response::new(GetStateResponse { scanned_height }))
+        }
+
+        async fn get_completed_transactions(
+            &self,
+            request: Request<GetCompletedTransactionsRequest>,
+        ) -> Result<Response<Self::GetCompletedTransactionsStream>, Status> {
+            check_auth(&request)?;
+            let transactions = self.state.lock().unwrap().1.clone();
+            let responses =
+                transactions.into_iter().map(|tx| Ok(GetCompletedTransactionsResponse { transaction: Some(tx) }));
+            Ok(Response::new(Box::pin(futures::stream::iter(responses))))
+        }
+    }
+
+    async fn start_mock_wallet(wallet: MockWallet) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::builder().add_service(WalletServer::new(wallet));
+        tokio::spawn(server.serve_with_incoming(TcpListenerStream::new(listener)));
+        format!("http://{addr}")
+    }
+
+    fn transaction(tx_id: u64, direction: TransactionDirection, status: TransactionStatus) -> TransactionInfo {
+        TransactionInfo {
+            tx_id,
+            source_address: TariAddress::from_base58(SENDER).unwrap().to_vec(),
+            status: status.into(),
+            direction: direction.into(),
+            amount: 5_000_000,
+            message: "Payment for order 12".to_string(),
+            ..TransactionInfo::default()
+        }
+    }
+
+    fn watcher(db: &InMemoryDatabase, address: String, username: &str) -> WalletWatcher<InMemoryDatabase> {
+        let config = WalletGrpcConfig {
+            address,
+            username: Some(username.to_string()),
+            password: Secret::new("pass".to_string()),
+            ..WalletGrpcConfig::default()
+        };
+        let client = WalletGrpcClient::new(&config).unwrap();
+        let api = OrderFlowApi::new(db.clone(), EventProducers::default());
+        WalletWatcher::new(api, client, config.confirmations, ServerOptions::from_config(&ServerConfig::default()))
+    }
+
+    async fn status(db: &InMemoryDatabase, txid: &str) -> Option<TransferStatus> {
+        db.fetch_payment_by_tx_id(txid).await.ok().map(|p| p.status)
+    }
+
+    #[actix_web::test]
+    async fn payments_are_received_and_confirmed() {
+        let wallet = MockWallet::default();
+        wallet.set_height(100);
+        wallet.update(|txs| {
+            txs.push(transaction(1, TransactionDirection::Inbound, TransactionStatus::Broadcast));
+            txs.push(transaction(2, TransactionDirection::Outbound, TransactionStatus::Broadcast));
+            txs.push(transaction(3, TransactionDirection::Inbound, TransactionStatus::Rejected));
+        });
+        let address = start_mock_wallet(wallet.clone()).await;
+        let db = InMemoryDatabase::new();
+        let mut watcher = watcher(&db, address, "user");
+
+        watcher.poll().await.unwrap();
+        assert_eq!(status(&db, "1").await, Some(TransferStatus::Received));
+        let payment = db.fetch_payment_by_tx_id("1").await.unwrap();
+        assert_eq!(payment.amount, MicroTari::from(5_000_000));
+        assert_eq!(payment.sender.as_base58(), SENDER);
+        // Outbound and rejected transactions are not payments
+        assert_eq!(status(&db, "2").await, None);
+        assert_eq!(status(&db, "3").await, None);
+
+        // Mined, but only 2 blocks deep
+        wallet.update(|txs| txs[0].mined_in_block_height = 100);
+        wallet.set_height(101);
+        watcher.poll().await.unwrap();
+        assert_eq!(status(&db, "1").await, Some(TransferStatus::Received));
+
+        wallet.set_height(102);
+        watcher.poll().await.unwrap();
+        assert_eq!(status(&db, "1").await, Some(TransferStatus::Confirmed));
+        // Nothing changes on the next poll
+        watcher.poll().await.unwrap();
+        assert_eq!(status(&db, "1").await, Some(TransferStatus::Confirmed));
+    }
+
+    #[actix_web::test]
+    async fn reorged_payments_are_reverted() {
+        let wallet = MockWallet::default();
+        wallet.set_height(102);
+        wallet.update(|txs| {
+            let mut tx = transaction(1, TransactionDirection::Inbound, TransactionStatus::MinedUnconfirmed);
+            tx.mined_in_block_height = 100;
+            txs.push(tx);
+        });
+        let address = start_mock_wallet(wallet.clone()).await;
+        let db = InMemoryDatabase::new();
+        let mut watcher = watcher(&db, address, "user");
+        watcher.poll().await.unwrap();
+        let payment = db.fetch_payment_by_tx_id("1").await.unwrap();
+        assert_eq!(payment.status, TransferStatus::Confirmed);
+        assert_eq!((payment.block_height, payment.confirmations), (Some(100), 3));
+
+        // The block is orphaned, and the wallet is waiting for the transaction to be mined again
+        wallet.update(|txs| {
+            txs[0].mined_in_block_height = 0;
+            txs[0].status = TransactionStatus::Broadcast.into();
+        });
+        watcher.poll().await.unwrap();
+        let payment = db.fetch_payment_by_tx_id("1").await.unwrap();
+        assert_eq!(payment.status, TransferStatus::Received);
+        assert_eq!((payment.block_height, payment.confirmations), (None, 0));
+
+        wallet.update(|txs| {
+            txs[0].mined_in_block_height = 101;
+            txs[0].status = TransactionStatus::MinedUnconfirmed.into();
+        });
+        wallet.set_height(103);
+        watcher.poll().await.unwrap();
+        assert_eq!(status(&db, "1").await, Some(TransferStatus::Confirmed));
+    }
+
+    #[actix_web::test]
+    async fn cancelled_transactions_cancel_the_payment() {
+        let wallet = MockWallet::default();
+        wallet.update(|txs| txs.push(transaction(7, TransactionDirection::Inbound, TransactionStatus::Completed)));
+        let address = start_mock_wallet(wallet.clone()).await;
+        let db = InMemoryDatabase::new();
+        let mut watcher = watcher(&db, address, "user");
+        watcher.poll().await.unwrap();
+        assert_eq!(status(&db, "7").await, Some(TransferStatus::Received));
+
+        wallet.update(|txs| txs[0].is_cancelled = true);
+        watcher.poll().await.unwrap();
+        assert_eq!(status(&db, "7").await, Some(TransferStatus::Cancelled));
+    }
+
+    #[actix_web::test]
+    async fn final_transactions_are_forgotten() {
+        let wallet = MockWallet::default();
+        wallet.set_height(102);
+        wallet.update(|txs| {
+            let mut tx = transaction(1, TransactionDirection::Inbound, TransactionStatus::MinedConfirmed);
+            tx.mined_in_block_height = 100;
+            txs.push(tx);
+            let mut cancelled = transaction(2, TransactionDirection::Inbound, TransactionStatus::Completed);
+            cancelled.is_cancelled = true;
+            txs.push(cancelled);
+        });
+        let address = start_mock_wallet(wallet.clone()).await;
+        let db = InMemoryDatabase::new();
+        let mut watcher = watcher(&db, address, "user");
+        watcher.poll().await.unwrap();
+        assert_eq!(status(&db, "1").await, Some(TransferStatus::Confirmed));
+        assert!(watcher.confirmed.contains_key(&1));
+        assert!(watcher.settled.is_empty());
+
+        wallet.set_height(100 + REORG_SAFE_DEPTH - 1);
+        watcher.poll().await.unwrap();
+        assert!(watcher.confirmed.is_empty());
+        assert!(watcher.received.is_empty());
+        // Final transactions are no longer looked at, even if the wallet's view of them changes
+        wallet.update(|txs| txs[0].is_cancelled = true);
+        watcher.poll().await.unwrap();
+        assert_eq!(status(&db, "1").await, Some(TransferStatus::Confirmed));
+        assert!(watcher.confirmed.is_empty() && watcher.settled.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn wallet_credentials_are_sent() {
+        let address = start_mock_wallet(MockWallet::default()).await;
+        let db = InMemoryDatabase::new();
+        let mut watcher = watcher(&db, address, "someone_else");
+        assert!(matches!(watcher.poll().await, Err(WalletGrpcError::RequestFailed(_))));
+    }
+}
@@ -0,0 +1,88 @@
+//----------------------------------------------   WooCommerce  ----------------------------------------------------
+
+use actix_web::{
+    dev::{Service, ServiceRequest},
+    web,
+    HttpRequest,
+    HttpResponse,
+};
+use futures::{future::ok, FutureExt};
+use log::{error, info, trace};
+use tari_payment_engine::{
+    tpe_api::exchange_rate_api::ExchangeRateApi,
+    traits::{ExchangeRates, PaymentGatewayDatabase},
+    OrderFlowApi,
+};
+
+use crate::{
+    config::{ServerOptions, WooCommerceConfig},
+    data_objects::JsonResponse,
+    integrations::{
+        storefront::Storefronts,
+        woocommerce::{
+            WOOCOMMERCE_HMAC_HEADER,
+            WOOCOMMERCE_PLATFORM,
+            WOOCOMMERCE_SOURCE_HEADER,
+            WOOCOMMERCE_TOPIC_HEADER,
+        },
+    },
+    middleware::{HmacMiddlewareFactory, RateLimitGroup},
+    route,
+    storefront_routes::handle_storefront_order,
+};
+
+/// Registers the `/woocommerce` webhook routes. Webhooks are signed with the store's webhook secret, apart from the
+/// pings that WooCommerce sends when a webhook is created.
+pub fn configure_routes<B>(cfg: &mut web::ServiceConfig, config: &WooCommerceConfig)
+where B: PaymentGatewayDatabase + ExchangeRates + 'static {
+    let hmac_middleware =
+        HmacMiddlewareFactory::new(WOOCOMMERCE_HMAC_HEADER, config.webhook_secret.clone(), config.hmac_checks);
+    let woocommerce_scope = web::scope("/woocommerce")
+        .wrap(hmac_middleware)
+        .wrap_fn(|req, srv| {
+            if is_woocommerce_ping(&req) {
+                info!("🛒️ Received WooCommerce webhook ping");
+                ok(req.into_response(HttpResponse::Ok().finish())).boxed_local()
+            } else {
+                srv.call(req)
+            }
+        })
+        .service(WoocommerceWebhookRoute::<B, B>::new());
+    cfg.service(woocommerce_scope);
+}
+
+route!(woocommerce_webhook => Post "webhook/order_created" impl PaymentGatewayDatabase, ExchangeRates where limited by RateLimitGroup::Webhooks);
+/// Receives new orders from the WooCommerce `order.created` webhook.
+pub async fn woocommerce_webhook<BPay, BFx>(
+    req: HttpRequest,
+    body: web::Bytes,
+    api: web::Data<OrderFlowApi<BPay>>,
+    fx: web::Data<ExchangeRateApi<BFx>>,
+    storefronts: web::Data<Storefronts>,
+    config: web::Data<ServerOptions>,
+) -> HttpResponse
+where
+    BPay: PaymentGatewayDatabase,
+    BFx: ExchangeRates,
+{
+    trace!("🛒️ Received webhook request: {}", req.uri());
+    let source = req.headers().get(WOOCOMMERCE_SOURCE_HEADER).and_then(|v| v.to_str().ok());
+    // Webhook responses must always be in 200 range, otherwise WooCommerce will retry, and eventually disable the
+    // webhook
+    let result = match storefronts.find_store(WOOCOMMERCE_PLATFORM, source) {
+        Some(store) => handle_storefront_order(store, &body, &fx, &api, &config).await,
+        None => {
+            error!("🛒️ No WooCommerce store is configured");
+            JsonResponse::failure("No WooCommerce store is configured.")
+        },
+    };
+    HttpResponse::Ok().json(result)
+}
+
+/// When a webhook is created, WooCommerce sends an unsigned `webhook_id=<id>` ping to check that the endpoint is up.
+/// Pings don't carry the webhook headers, so they can be answered before the signature is checked. Nothing is done
+/// with them.
+pub fn is_woocommerce_ping(req: &ServiceRequest) -> bool {
+    let headers = req.headers();
+    !headers.contains_key(WOOCOMMERCE_HMAC_HEADER) && !headers.contains_key(WOOCOMMERCE_TOPIC_HEADER)
+}
@@ -14,6 +14,8 @@ use tari_common_types::tari_address::TariAddress;
 use tari_payment_engine::{
     db_types::{
         AddressBalance,
+        AdminAction,
+        ApprovalRequest,
         CustomerBalance,
         CustomerOrderBalance,
         CustomerOrders,
@@ -25,6 +27,7 @@ use tari_payment_engine::{
     tpe_api::{
         account_objects::{AddressHistory, CustomerHistory},
         payment_objects::PaymentsResult,
+        reconciliation_objects::{ReconciliationReport, WalletTransaction},
     },
     traits::WalletInfo,
 };
@@ -50,6 +53,7 @@ pub fn format_address_balance(balance: &AddressBalance) -> Result<String> {
     writeln!(f, "Available: {}", balance.current_balance())?;
     writeln!(f, "Total received: {}", balance.total_confirmed())?;
     writeln!(f, "Total spent: {}", balance.total_paid())?;
+    writeln!(f, "Total refunded: {}", balance.total_refunded())?;
     Ok(f)
 }
 
@@ -230,6 +234,23 @@ pub fn format_exchange_rate(rate: ExchangeRateResult) -> String {
     format!("1 {} => {tari} (Last update: {})", rate.currency, rate.updated_at)
 }
 
+/// Formats the rate history as a table, with a bar chart of the rates relative to the highest rate in the history.
+pub fn format_exchange_rate_history(rates: &[ExchangeRateResult]) -> String {
+    const CHART_WIDTH: i64 = 40;
+    if rates.is_empty() {
+        return "No exchange rates in this period".to_string();
+    }
+    let max = rates.iter().map(|r| r.rate).max().unwrap_or_default().max(1);
+    let mut table = Table::new();
+    table.set_titles(row!["Updated At", "Currency", "Rate", ""]);
+    rates.iter().for_each(|rate| {
+        let width = usize::try_from(rate.rate * CHART_WIDTH / max).unwrap_or_default();
+        table.add_row(row![rate.updated_at, rate.currency, MicroTari::from(rate.rate), "█".repeat(width)]);
+    });
+    markdown_style(&mut table);
+    table.to_string()
+}
+
 pub fn print_order(order: &Order) -> Result<String> {
     let mut f = String::new();
     format_order(order, &mut f)?;
@@ -291,6 +312,7 @@ pub fn format_customer_balance(balance: &CustomerBalance) -> Result<String> {
     let mut f = String::new();
     writeln!(f, "Total transfers confirmed: {}", balance.total_confirmed())?;
     writeln!(f, "Total paid: {}", balance.total_paid())?;
+    writeln!(f, "Total refunded: {}", balance.total_refunded())?;
     writeln!(f, "Available balance: {}", balance.current_balance())?;
     writeln!(f, "Associated wallet addresses")?;
     for address in balance.addresses() {
@@ -323,6 +345,56 @@ pub fn format_customer_history(history: &CustomerHistory) -> Result<String> {
     Ok(f)
 }
 
+pub fn format_reconciliation_report(report: &ReconciliationReport) -> Result<String> {
+    let mut f = String::new();
+    writeln!(f, "## Reconciliation summary\n\n{report}\n")?;
+    if !report.missing.is_empty() {
+        writeln!(f, "### Missing payments\n\n{}\n", format_wallet_transactions(&report.missing))?;
+    }
+    if !report.extra.is_empty() {
+        writeln!(f, "### Payments not in the wallet\n\n{}\n", format_payments(&report.extra))?;
+    }
+    if !report.mismatched.is_empty() {
+        let mut table = Table::new();
+        table.set_titles(row!["TX id", "Status", "Differences"]);
+        report.mismatched.iter().for_each(|m| {
+            table.add_row(row![m.payment.txid, m.payment.status, m.differences.join("\n")]);
+        });
+        markdown_style(&mut table);
+        writeln!(f, "### Mismatched payments\n\n{table}\n")?;
+    }
+    if !report.imported.is_empty() {
+        writeln!(f, "### Imported payments\n\n{}\n", format_payments(&report.imported))?;
+    }
+    if !report.import_failures.is_empty() {
+        let mut table = Table::new();
+        table.set_titles(row!["TX id", "Reason"]);
+        report.import_failures.iter().for_each(|failure| {
+            table.add_row(row![failure.txid, failure.reason]);
+        });
+        markdown_style(&mut table);
+        writeln!(f, "### Failed imports\n\n{table}\n")?;
+    }
+    Ok(f)
+}
+
+pub fn format_wallet_transactions(transactions: &[WalletTransaction]) -> String {
+    let mut table = Table::new();
+    table.set_titles(row!["TX id", "Amount", "Sender", "Confirmed", "Memo", "Timestamp"]);
+    transactions.iter().for_each(|tx| {
+        table.add_row(row![
+            tx.txid,
+            tx.amount,
+            tx.sender.as_base58(),
+            tx.confirmed,
+            tx.memo.clone().unwrap_or_default(),
+            tx.timestamp.map(|t| t.to_string()).unwrap_or_default()
+        ]);
+    });
+    markdown_style(&mut table);
+    table.to_string()
+}
+
 pub fn format_customer_orders(orders: &[CustomerOrders]) -> Result<String> {
     let mut f = String::new();
     let mut table = Table::new();
@@ -352,3 +424,47 @@ pub fn format_settlements(settlements: &[SettlementJournalEntry]) -> Result<Stri
     writeln!(f, "{table}")?;
     Ok(f)
 }
+
+pub fn format_approval_requests(requests: &[ApprovalRequest]) -> String {
+    if requests.is_empty() {
+        return "No approval requests found".to_string();
+    }
+    let mut table = Table::new();
+    table.set_titles(row!["Id", "Requested at", "Expires at", "Requested by", "Operation", "Status", "Decided by"]);
+    requests.iter().for_each(|request| {
+        table.add_row(row![
+            request.id,
+            request.created_at,
+            request.expires_at,
+            request.requested_by.as_base58(),
+            request.operation,
+            request.status,
+            request.decided_by.as_ref().map(|a| a.as_base58()).unwrap_or_default()
+        ]);
+    });
+    markdown_style(&mut table);
+    table.to_string()
+}
+
+pub fn format_admin_actions(actions: &[AdminAction]) -> String {
+    if actions.is_empty() {
+        return "No admin actions found".to_string();
+    }
+    let mut table = Table::new();
+    table.set_titles(row!["Id", "Timestamp", "Actor", "Endpoint", "Path", "Outcome", "Status", "Reason", "Client IP"]);
+    actions.iter().for_each(|action| {
+        table.add_row(row![
+            action.id,
+            action.created_at,
+            action.actor.as_base58(),
+            action.endpoint,
+            format!("{} {}", action.method, action.path),
+            action.outcome,
+            action.status_code,
+            action.reason.clone().unwrap_or_default(),
+            action.client_ip.clone().unwrap_or_default()
+        ]);
+    });
+    markdown_style(&mut table);
+    table.to_string()
+}
@@ -8,12 +8,16 @@ pub type Menu = (&'static str, &'static [&'static str]);
 pub mod commands {
     pub const ADD_AUTH_WALLET: &str = "Add authorized wallet";
     pub const ADD_PROFILE: &str = "Add profile";
+    pub const ADMIN_AUDIT_LOG: &str = "Admin audit log";
+    pub const APPROVAL_REQUESTS: &str = "Pending approvals";
+    pub const APPROVE_OPERATION: &str = "Approve operation";
     pub const BALANCE_FOR_ADDRESS: &str = "Balance for Address";
     pub const CANCEL: &str = "Cancel Order";
     pub const CLAIM_ORDER: &str = "Claim Order";
     pub const CREDITORS: &str = "Get all unpaid orders";
     pub const EDIT_MEMO: &str = "Edit memo";
     pub const EXIT: &str = "Exit";
+    pub const EXPORT_REPORT: &str = "Export accounting report";
     pub const FETCH_PAYMENTS_FOR_ORDER: &str = "Fetch Payments for Order";
     pub const FETCH_PRICE: &str = "Fetch Tari price";
     pub const HISTORY_FOR_ACCOUNT_ID: &str = "History for Customer Id";
@@ -35,7 +39,10 @@ pub mod commands {
     pub const ORDER_BY_ID: &str = "Order by Id";
     pub const ORDERS_FOR_ADDRESS: &str = "Orders for Address";
     pub const PAYMENTS_FOR_ADDRESS: &str = "Payments for Address";
+    pub const PRICE_HISTORY: &str = "Tari price history";
     pub const REASSIGN_ORDER: &str = "Reassign Order";
+    pub const RECONCILE_PAYMENTS: &str = "Reconcile payments";
+    pub const REJECT_OPERATION: &str = "Reject operation";
     pub const REMOVE_AUTH_WALLETS: &str = "Remove authorized wallets";
     pub const RESCAN_OPEN_ORDERS: &str = "Re-import Open Orders";
     pub const RESET_ORDER: &str = "Reset Order";
@@ -48,12 +55,13 @@ pub use commands::*;
 
 pub const TOP_MENU: [&str; 5] = [NAV_TO_ADMIN_MENU, NAV_TO_USER_MENU, NAV_TO_SHOPIFY_MENU, LOGOUT, EXIT];
 
-pub const ADMIN_MENU: [&str; 24] = [
+pub const ADMIN_MENU: [&str; 31] = [
     CANCEL,
     MARK_ORDER_PAID,
     RESET_ORDER,
     FETCH_PRICE,
     SET_PRICE,
+    PRICE_HISTORY,
     LOGOUT,
     NAV_BACK,
     ISSUE_CREDIT,
@@ -68,6 +76,12 @@ pub const ADMIN_MENU: [&str; 24] = [
     EDIT_MEMO,
     REASSIGN_ORDER,
     RESCAN_OPEN_ORDERS,
+    RECONCILE_PAYMENTS,
+    EXPORT_REPORT,
+    ADMIN_AUDIT_LOG,
+    APPROVAL_REQUESTS,
+    APPROVE_OPERATION,
+    REJECT_OPERATION,
     ADD_AUTH_WALLET,
     REMOVE_AUTH_WALLETS,
     LIST_AUTH_WALLETS,
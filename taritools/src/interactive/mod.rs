@@ -5,6 +5,7 @@ use std::{
 };
 
 use anyhow::Result;
+use chrono::{NaiveDate, NaiveTime};
 use dialoguer::{console::Style, theme::ColorfulTheme, Confirm, FuzzySelect, MultiSelect, Select};
 use indicatif::{ProgressBar, ProgressStyle};
 use menus::commands::*;
@@ -16,11 +17,22 @@ use tari_crypto::{
     tari_utilities::hex::Hex,
 };
 use tari_payment_engine::{
-    db_types::{OrderId, Role, SerializedTariAddress},
+    db_types::{ApprovalStatus, OrderId, Role, SerializedTariAddress, DEFAULT_MERCHANT},
     helpers::MemoSignature,
+    tpe_api::{
+        export_objects::{ExportRequest, ReportFormat, ReportType},
+        reconciliation_objects::{parse_wallet_export, ExportFormat},
+    },
     traits::NewWalletInfo,
 };
-use tari_payment_server::data_objects::{ModifyOrderParams, MoveOrderParams, UpdateMemoParams};
+use tari_payment_server::data_objects::{
+    ApprovalQuery,
+    AuditQuery,
+    ModifyOrderParams,
+    MoveOrderParams,
+    ReconcileParams,
+    UpdateMemoParams,
+};
 use tokio::join;
 use tpg_common::MicroTari;
 use zeroize::Zeroize;
@@ -31,15 +43,19 @@ use crate::{
             format_address_balance,
             format_address_history,
             format_addresses_with_qr_code,
+            format_admin_actions,
+            format_approval_requests,
             format_claimed_order,
             format_customer_history,
             format_customer_orders,
             format_exchange_rate,
+            format_exchange_rate_history,
             format_order,
             format_order_result,
             format_orders,
             format_payments,
             format_payments_result,
+            format_reconciliation_report,
             format_shopify_orders,
             format_wallet_list,
             print_order,
@@ -147,6 +163,7 @@ impl InteractiveApp {
                 RESET_ORDER => handle_response(self.reset_order().await),
                 MARK_ORDER_PAID => handle_response(self.fulfil_order().await),
                 FETCH_PRICE => self.fetch_tari_price().await,
+                PRICE_HISTORY => handle_response(self.tari_price_history().await),
                 FETCH_PAYMENTS_FOR_ORDER => handle_response(self.payments_for_order().await),
                 SET_PRICE => self.set_tari_price().await,
                 ISSUE_CREDIT => handle_response(self.issue_credit().await),
@@ -164,6 +181,12 @@ impl InteractiveApp {
                 ADD_PROFILE => handle_response(self.add_profile().await),
                 SHOPIFY_OPEN_ORDERS => handle_response(self.shopify_open_orders().await),
                 RESCAN_OPEN_ORDERS => handle_response(self.rescan_open_orders().await),
+                RECONCILE_PAYMENTS => handle_response(self.reconcile_payments().await),
+                EXPORT_REPORT => handle_response(self.export_report().await),
+                ADMIN_AUDIT_LOG => handle_response(self.admin_audit_log().await),
+                APPROVAL_REQUESTS => handle_response(self.approval_requests().await),
+                APPROVE_OPERATION => handle_response(self.approve_operation().await),
+                REJECT_OPERATION => handle_response(self.reject_operation().await),
                 LOGOUT => self.logout(),
                 NAV_BACK => self.pop_menu(),
                 EXIT => break,
@@ -212,7 +235,11 @@ impl InteractiveApp {
         let ip_address =
             dialoguer::Input::<String>::new().with_prompt("IP address for new payment wallet:").interact()?;
         let ip_address = ip_address.parse::<IpAddr>()?;
-        let new_wallet = NewWalletInfo { address, ip_address, initial_nonce: None };
+        let merchant_id = dialoguer::Input::<String>::new()
+            .with_prompt("Merchant that receives the wallet's payments:")
+            .default(DEFAULT_MERCHANT.to_string())
+            .interact()?;
+        let new_wallet = NewWalletInfo { address, ip_address, initial_nonce: None, merchant_id };
         let client = self.client().expect("User is logged in. Client should not be None");
         client.add_authorized_wallet(&new_wallet).await?;
         Ok("New wallet has been added successfully".into())
@@ -349,6 +376,15 @@ impl InteractiveApp {
         handle_response(res)
     }
 
+    async fn tari_price_history(&mut self) -> Result<String> {
+        let _unused = self.login().await?;
+        let days = dialoguer::Input::<i64>::new().with_prompt("Number of days").default(30).interact()?;
+        let since = chrono::Utc::now() - chrono::Duration::days(days);
+        let client = self.client().expect("User is logged in. Client should not be None");
+        let rates = client.exchange_rate_history("USD", since).await?;
+        Ok(format_exchange_rate_history(&rates))
+    }
+
     async fn set_tari_price(&mut self) {
         let mut res = self.login().await;
         if res.is_ok() {
@@ -422,6 +458,144 @@ impl InteractiveApp {
         Ok(result)
     }
 
+    async fn reconcile_payments(&mut self) -> Result<String> {
+        let _unused = self.login().await?;
+        let source = Select::new()
+            .with_prompt("Where should the wallet's transaction history come from?")
+            .items(&["A wallet export file (CSV or JSON)", "The hot wallet, over gRPC"])
+            .default(0)
+            .interact()?;
+        let transactions = match source {
+            0 => {
+                let path = dialoguer::Input::<String>::new().with_prompt("Path to the export file").interact()?;
+                let format = match ExportFormat::from_file_name(&path) {
+                    Some(format) => format,
+                    None => match Select::new().with_prompt("File format").items(&["CSV", "JSON"]).interact()? {
+                        0 => ExportFormat::Csv,
+                        _ => ExportFormat::Json,
+                    },
+                };
+                let data = std::fs::read_to_string(&path)?;
+                let transactions = parse_wallet_export(&data, format)?;
+                println!("Read {} inbound transactions from {path}", transactions.len());
+                Some(transactions)
+            },
+            _ => None,
+        };
+        let days = dialoguer::Input::<i64>::new()
+            .with_prompt("Number of days to reconcile (0 for the full history)")
+            .default(0)
+            .interact()?;
+        let since = (days > 0).then(|| chrono::Utc::now() - chrono::Duration::days(days));
+        let import_missing = Confirm::new().with_prompt("Import missing payments?").default(false).interact()?;
+        let params = ReconcileParams { transactions, since, import_missing, merchant: None };
+        let client = self.client().expect("User is logged in. Client should not be None");
+        let report = client.reconcile_payments(&params).await?;
+        format_reconciliation_report(&report)
+    }
+
+    async fn export_report(&mut self) -> Result<String> {
+        let _unused = self.login().await?;
+        let report = match Select::new()
+            .with_prompt("Which report?")
+            .items(&["Orders", "Payments", "Settlements", "Credit notes"])
+            .default(0)
+            .interact()?
+        {
+            0 => ReportType::Orders,
+            1 => ReportType::Payments,
+            2 => ReportType::Settlements,
+            _ => ReportType::CreditNotes,
+        };
+        let format = match Select::new()
+            .with_prompt("File format")
+            .items(&["CSV", "OFX", "JSON Lines"])
+            .default(0)
+            .interact()?
+        {
+            0 => ReportFormat::Csv,
+            1 => ReportFormat::Ofx,
+            _ => ReportFormat::JsonLines,
+        };
+        let today = chrono::Utc::now().date_naive();
+        let from = read_date("First day of the period (YYYY-MM-DD)", today - chrono::Duration::days(30))?;
+        let to = read_date("Last day of the period (YYYY-MM-DD)", today)?;
+        let to = to.succ_opt().unwrap_or(to);
+        let currency = dialoguer::Input::<String>::new()
+            .with_prompt("Fiat currency for records that are not priced in fiat (leave blank for none)")
+            .allow_empty(true)
+            .interact()?;
+        let mut request = ExportRequest::new(
+            report,
+            format,
+            from.and_time(NaiveTime::MIN).and_utc(),
+            to.and_time(NaiveTime::MIN).and_utc(),
+        );
+        if !currency.trim().is_empty() {
+            request = request.with_currency(currency.trim().to_uppercase());
+        }
+        let path = dialoguer::Input::<String>::new()
+            .with_prompt("Save the report to")
+            .default(request.file_name())
+            .interact()?;
+        let client = self.client().expect("User is logged in. Client should not be None");
+        let body = client.export_report(&request).await?;
+        std::fs::write(&path, body)?;
+        Ok(format!("Saved the {} report to {path}", request.report))
+    }
+
+    async fn admin_audit_log(&mut self) -> Result<String> {
+        let _unused = self.login().await?;
+        let actor = dialoguer::Input::<String>::new()
+            .with_prompt("Only show actions by this address (leave blank for everyone)")
+            .allow_empty(true)
+            .interact()?;
+        let actor = match actor.trim() {
+            "" => None,
+            s => Some(SerializedTariAddress::from(TariAddress::from_base58(s)?)),
+        };
+        let endpoint = dialoguer::Input::<String>::new()
+            .with_prompt("Only show calls to this endpoint, e.g. update_roles (leave blank for all)")
+            .allow_empty(true)
+            .interact()?;
+        let endpoint = Some(endpoint.trim().to_string()).filter(|e| !e.is_empty());
+        let days = dialoguer::Input::<i64>::new()
+            .with_prompt("Number of days to show (0 for the full history)")
+            .default(7)
+            .interact()?;
+        let since = (days > 0).then(|| chrono::Utc::now() - chrono::Duration::days(days));
+        let count = dialoguer::Input::<i64>::new().with_prompt("Maximum number of entries").default(50).interact()?;
+        let query = AuditQuery { actor, endpoint, since, count: Some(count), ..AuditQuery::default() };
+        let client = self.client().expect("User is logged in. Client should not be None");
+        let actions = client.admin_actions(&query).await?;
+        Ok(format_admin_actions(&actions))
+    }
+
+    async fn approval_requests(&mut self) -> Result<String> {
+        let _unused = self.login().await?;
+        let query = ApprovalQuery { status: Some(ApprovalStatus::Pending), ..ApprovalQuery::default() };
+        let client = self.client().expect("User is logged in. Client should not be None");
+        let requests = client.approval_requests(&query).await?;
+        Ok(format_approval_requests(&requests))
+    }
+
+    async fn approve_operation(&mut self) -> Result<String> {
+        let _unused = self.login().await?;
+        let id = dialoguer::Input::<i64>::new().with_prompt("Enter approval request id").interact()?;
+        let client = self.client().expect("User is logged in. Client should not be None");
+        let request = client.approve_operation(id).await?;
+        Ok(format!("Approval request #{id} was approved. {}", request.operation))
+    }
+
+    async fn reject_operation(&mut self) -> Result<String> {
+        let _unused = self.login().await?;
+        let id = dialoguer::Input::<i64>::new().with_prompt("Enter approval request id").interact()?;
+        let reason = dialoguer::Input::<String>::new().with_prompt("Enter reason").interact()?;
+        let client = self.client().expect("User is logged in. Client should not be None");
+        let request = client.reject_operation(id, reason).await?;
+        Ok(format!("Approval request #{id} was rejected. {}", request.operation))
+    }
+
     async fn payments_for_order(&mut self) -> Result<String> {
         let _unused = self.login().await;
         let order_id = dialoguer::Input::<String>::new().with_prompt("Enter order ID").interact()?;
@@ -623,6 +797,11 @@ fn input_tari_amount(prompt: &str) -> Result<MicroTari> {
     }
 }
 
+fn read_date(prompt: &str, default: NaiveDate) -> Result<NaiveDate> {
+    let date = dialoguer::Input::<NaiveDate>::new().with_prompt(prompt).default(default).interact()?;
+    Ok(date)
+}
+
 async fn set_new_tari_price(client: &mut PaymentServerClient) -> Result<String> {
     let price = input_tari_amount("Enter Tari price (per USD)")?;
     let pb = ProgressBar::new_spinner();
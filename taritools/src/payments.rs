@@ -9,7 +9,7 @@ use tari_crypto::{
     tari_utilities::{hex, hex::Hex},
 };
 use tari_payment_engine::{
-    db_types::{NewPayment, OrderId, SerializedTariAddress},
+    db_types::{default_merchant, NewPayment, OrderId, SerializedTariAddress},
     helpers::{extract_order_id_from_str, is_forbidden_pattern, WalletSignature},
 };
 use tari_payment_server::{config::OrderIdField, data_objects::TransactionConfirmation};
@@ -69,7 +69,7 @@ impl From<ReceivedPaymentParams> for NewPayment {
             extract_order_id_from_payment_id(&s, format)
         });
         let txid = params.txid;
-        NewPayment { sender, amount, memo, order_id, txid }
+        NewPayment { sender, amount, memo, order_id, txid, merchant_id: default_merchant() }
     }
 }
 
@@ -187,7 +187,7 @@ fn build_payment(params: &PaymentAuthParams) -> Result<NewPayment> {
     let memo = params.memo.clone();
     let order_id = params.order_id.clone();
     let txid = params.txid.clone();
-    let payment = NewPayment { sender, amount, memo, order_id, txid };
+    let payment = NewPayment { sender, amount, memo, order_id, txid, merchant_id: default_merchant() };
     Ok(payment)
 }
 
@@ -1,20 +1,23 @@
-use std::path::Path;
+use std::{env, path::Path};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::{Args, Subcommand};
 use sqlx::{
     migrate::{MigrateDatabase, Migrator},
+    Postgres,
     Sqlite,
 };
 use tari_payment_engine::{
     db_types::{Role, SerializedTariAddress},
-    sqlite::db::db_url,
     traits::AuthManagement,
+    PostgresDatabase,
     SqliteDatabase,
 };
+use tari_payment_server::config::DatabaseBackend;
 
 /// Setup commands work locally to set up Tari Payment Server. These commands assume that `TPG_DATABASE_URL` is set and
-/// pointing to the location of the database.
+/// pointing to the location of the database. The database backend is selected from the URL scheme, as it is for the
+/// server, and SQLite is used if `TPG_DATABASE_URL` is not set.
 #[derive(Debug, Subcommand)]
 pub enum SetupCommand {
     /// Add a new user to the system with a given set of roles
@@ -51,8 +54,16 @@ pub async fn handle_setup_command(command: SetupCommand) {
 
 async fn add_user(params: AddUserParams) {
     async fn add(params: AddUserParams) -> Result<()> {
-        let db = SqliteDatabase::new(1).await?;
-        db.assign_roles(params.address.as_address(), &params.role).await?;
+        match database_backend()? {
+            DatabaseBackend::Sqlite => {
+                let db = SqliteDatabase::new(1).await?;
+                db.assign_roles(params.address.as_address(), &params.role).await?;
+            },
+            DatabaseBackend::Postgres => {
+                let db = PostgresDatabase::new(1).await?;
+                db.assign_roles(params.address.as_address(), &params.role).await?;
+            },
+        }
         Ok(())
     }
     println!("Adding user with address: {} and roles {:?}", params.address, params.role);
@@ -63,29 +74,39 @@ async fn add_user(params: AddUserParams) {
 }
 
 async fn migrate_db(params: MigrateParams) {
-    async fn migrate_embedded() -> Result<()> {
-        create_database_if_not_exist().await?;
+    async fn migrate_embedded(backend: DatabaseBackend) -> Result<()> {
+        create_database_if_not_exist(backend).await?;
         println!("Running embedded migrations");
-        let db = SqliteDatabase::new(1).await?;
-        let pool = db.pool();
-        sqlx::migrate!("../tari_payment_engine/src/sqlite/migrations").run(pool).await?;
+        match backend {
+            DatabaseBackend::Sqlite => {
+                let db = SqliteDatabase::new(1).await?;
+                sqlx::migrate!("../tari_payment_engine/src/sqlite/migrations").run(db.pool()).await?;
+            },
+            DatabaseBackend::Postgres => {
+                let db = PostgresDatabase::new(1).await?;
+                sqlx::migrate!("../tari_payment_engine/src/postgres/migrations").run(db.pool()).await?;
+            },
+        }
         Ok(())
     }
 
-    async fn migrate_custom(path: &str) -> Result<()> {
-        create_database_if_not_exist().await?;
+    async fn migrate_custom(backend: DatabaseBackend, path: &str) -> Result<()> {
+        create_database_if_not_exist(backend).await?;
         println!("Running migrations at: {path}");
-        let db = SqliteDatabase::new(1).await?;
-        let path = Path::new(path);
-        let migrator = Migrator::new(path).await?;
-        let pool = db.pool();
-        migrator.run(pool).await?;
+        let migrator = Migrator::new(Path::new(path)).await?;
+        match backend {
+            DatabaseBackend::Sqlite => migrator.run(SqliteDatabase::new(1).await?.pool()).await?,
+            DatabaseBackend::Postgres => migrator.run(PostgresDatabase::new(1).await?.pool()).await?,
+        }
         Ok(())
     }
 
-    let result = match &params.path {
-        Some(path) => migrate_custom(path).await,
-        None => migrate_embedded().await,
+    let result = match database_backend() {
+        Ok(backend) => match &params.path {
+            Some(path) => migrate_custom(backend, path).await,
+            None => migrate_embedded(backend).await,
+        },
+        Err(e) => Err(e),
     };
 
     match result {
@@ -94,16 +115,31 @@ async fn migrate_db(params: MigrateParams) {
     }
 }
 
-async fn create_database_if_not_exist() -> Result<()> {
-    let db = db_url();
-    if !database_exists(&db).await? {
-        println!("Creating new database at: {db}");
-        Sqlite::create_database(&db).await?;
-    }
-    Ok(())
+/// The backend named by the scheme of `TPG_DATABASE_URL`. SQLite is the default when the variable is not set.
+fn database_backend() -> Result<DatabaseBackend> {
+    let Ok(url) = env::var("TPG_DATABASE_URL") else {
+        return Ok(DatabaseBackend::Sqlite);
+    };
+    DatabaseBackend::from_url(&url)
+        .ok_or_else(|| anyhow!("Unsupported database URL in TPG_DATABASE_URL: {url}. Use sqlite:// or postgres://"))
 }
 
-async fn database_exists(db: &str) -> Result<bool> {
-    let result = Sqlite::database_exists(db).await?;
-    Ok(result)
+async fn create_database_if_not_exist(backend: DatabaseBackend) -> Result<()> {
+    match backend {
+        DatabaseBackend::Sqlite => {
+            let db = tari_payment_engine::sqlite::db::db_url();
+            if !Sqlite::database_exists(&db).await? {
+                println!("Creating new database at: {db}");
+                Sqlite::create_database(&db).await?;
+            }
+        },
+        DatabaseBackend::Postgres => {
+            let db = tari_payment_engine::postgres::db::db_url();
+            if !Postgres::database_exists(&db).await? {
+                println!("Creating new database at: {db}");
+                Postgres::create_database(&db).await?;
+            }
+        },
+    }
+    Ok(())
 }
@@ -1,7 +1,7 @@
 use std::fmt::Display;
 
 use anyhow::{anyhow, Result};
-use chrono::Utc;
+use chrono::{DateTime, SecondsFormat, Utc};
 use log::info;
 use reqwest::{
     header::{HeaderMap, HeaderValue},
@@ -18,6 +18,8 @@ use tari_jwt::{
 use tari_payment_engine::{
     db_types::{
         AddressBalance,
+        AdminAction,
+        ApprovalRequest,
         CreditNote,
         CustomerOrders,
         LoginToken,
@@ -31,17 +33,23 @@ use tari_payment_engine::{
     order_objects::{ClaimedOrder, OrderChanged, OrderResult},
     tpe_api::{
         account_objects::{AddressHistory, CustomerHistory},
+        export_objects::ExportRequest,
         payment_objects::PaymentsResult,
+        reconciliation_objects::ReconciliationReport,
     },
     traits::{NewWalletInfo, OrderMovedResult, WalletInfo},
 };
 use tari_payment_server::data_objects::{
+    ApprovalQuery,
+    AuditQuery,
     ExchangeRateResult,
     ExchangeRateUpdate,
     JsonResponse,
     ModifyOrderParams,
     MoveOrderParams,
     PaymentNotification,
+    ReconcileParams,
+    RejectApprovalParams,
     TransactionConfirmationNotification,
     UpdateMemoParams,
 };
@@ -170,6 +178,12 @@ impl PaymentServerClient {
         self.auth_get_request(&format!("/api/exchange_rate/{currency}")).await
     }
 
+    /// Fetches the exchange rates for `currency` that were set since the given time, oldest first.
+    pub async fn exchange_rate_history(&self, currency: &str, since: DateTime<Utc>) -> Result<Vec<ExchangeRateResult>> {
+        let since = since.to_rfc3339_opts(SecondsFormat::Secs, true);
+        self.auth_get_request(&format!("/api/exchange_rate/{currency}/history?since={since}")).await
+    }
+
     pub async fn authorized_wallets(&self) -> Result<Vec<WalletInfo>> {
         self.auth_get_request("/api/wallets").await
     }
@@ -187,7 +201,8 @@ impl PaymentServerClient {
 
     pub async fn set_exchange_rate(&self, currency: &str, price_in_tari: MicroTari) -> Result<()> {
         let url = self.url("/api/exchange_rate")?;
-        let rate = ExchangeRateUpdate { currency: currency.to_string(), rate: price_in_tari.value() as u64 };
+        let rate =
+            ExchangeRateUpdate { currency: currency.to_string(), rate: price_in_tari.value() as u64, merchant: None };
         let res =
             self.client.post(url).header("tpg_access_token", self.access_token.clone()).json(&rate).send().await?;
         if !res.status().is_success() {
@@ -319,6 +334,9 @@ impl PaymentServerClient {
             let msg = res.text().await?;
             return Err(anyhow!("Error fulfilling order: {msg}"));
         }
+        if res.status() == StatusCode::ACCEPTED {
+            return Err(approval_pending(res.json().await?));
+        }
         let order = res.json().await?;
         Ok(order)
     }
@@ -352,6 +370,9 @@ impl PaymentServerClient {
             let msg = res.text().await?;
             return Err(anyhow!("Error issuing credit: {msg}"));
         }
+        if res.status() == StatusCode::ACCEPTED {
+            return Err(approval_pending(res.json().await?));
+        }
         let paid_orders = res.json().await?;
         Ok(paid_orders)
     }
@@ -385,6 +406,94 @@ impl PaymentServerClient {
     pub async fn creditors(&self) -> Result<Vec<CustomerOrders>> {
         self.auth_get_request("/api/creditors").await
     }
+
+    pub async fn reconcile_payments(&self, params: &ReconcileParams) -> Result<ReconciliationReport> {
+        let url = self.url("/api/reconcile")?;
+        let res =
+            self.client.post(url).header("tpg_access_token", self.access_token.clone()).json(params).send().await?;
+        let code = res.status();
+        if !res.status().is_success() {
+            let msg = res.text().await?;
+            return Err(anyhow!("Error {code}. Could not reconcile payments. {msg}"));
+        }
+        let report: ReconciliationReport = res.json().await?;
+        Ok(report)
+    }
+
+    /// Downloads an accounting report, and returns its contents.
+    pub async fn export_report(&self, request: &ExportRequest) -> Result<String> {
+        let url = self.url("/api/export")?;
+        let res =
+            self.client.get(url).header("tpg_access_token", self.access_token.clone()).query(request).send().await?;
+        let code = res.status();
+        if !res.status().is_success() {
+            let msg = res.text().await?;
+            return Err(anyhow!("Error {code}. Could not export the {} report. {msg}", request.report));
+        }
+        Ok(res.text().await?)
+    }
+
+    /// Fetches entries from the admin audit log, newest first.
+    pub async fn admin_actions(&self, query: &AuditQuery) -> Result<Vec<AdminAction>> {
+        let url = self.url("/api/audit")?;
+        let res =
+            self.client.get(url).header("tpg_access_token", self.access_token.clone()).query(query).send().await?;
+        let code = res.status();
+        if !res.status().is_success() {
+            let msg = res.text().await?;
+            return Err(anyhow!("Error {code}. Could not fetch the audit log. {msg}"));
+        }
+        let actions: Vec<AdminAction> = res.json().await?;
+        Ok(actions)
+    }
+
+    pub async fn approval_requests(&self, query: &ApprovalQuery) -> Result<Vec<ApprovalRequest>> {
+        let url = self.url("/api/approvals")?;
+        let res =
+            self.client.get(url).header("tpg_access_token", self.access_token.clone()).query(query).send().await?;
+        let code = res.status();
+        if !res.status().is_success() {
+            let msg = res.text().await?;
+            return Err(anyhow!("Error {code}. Could not fetch approval requests. {msg}"));
+        }
+        let requests: Vec<ApprovalRequest> = res.json().await?;
+        Ok(requests)
+    }
+
+    pub async fn approve_operation(&self, id: i64) -> Result<ApprovalRequest> {
+        let url = self.url(&format!("/api/approvals/{id}/approve"))?;
+        let res = self.client.post(url).header("tpg_access_token", self.access_token.clone()).send().await?;
+        let code = res.status();
+        if !res.status().is_success() {
+            let msg = res.text().await?;
+            return Err(anyhow!("Error {code}. Could not approve request {id}. {msg}"));
+        }
+        let request: ApprovalRequest = res.json().await?;
+        Ok(request)
+    }
+
+    pub async fn reject_operation(&self, id: i64, reason: String) -> Result<ApprovalRequest> {
+        let url = self.url(&format!("/api/approvals/{id}/reject"))?;
+        let params = RejectApprovalParams { reason };
+        let res =
+            self.client.post(url).header("tpg_access_token", self.access_token.clone()).json(&params).send().await?;
+        let code = res.status();
+        if !res.status().is_success() {
+            let msg = res.text().await?;
+            return Err(anyhow!("Error {code}. Could not reject request {id}. {msg}"));
+        }
+        let request: ApprovalRequest = res.json().await?;
+        Ok(request)
+    }
+}
+
+/// The server holds back operations that need a second admin's approval, and responds with the approval request
+fn approval_pending(request: ApprovalRequest) -> anyhow::Error {
+    anyhow!(
+        "{} needs a second admin's approval. It will be carried out once approval request #{} is approved.",
+        request.operation,
+        request.id
+    )
 }
 
 impl Display for PaymentServerClient {
@@ -399,7 +508,7 @@ impl Display for PaymentServerClient {
 fn generate_auth_token(profile: &Profile) -> Result<String> {
     let nonce = Utc::now().timestamp() as u64;
     let address = profile.address.clone().to_address();
-    let claims = LoginToken { address, nonce, desired_roles: profile.roles.clone() };
+    let claims = LoginToken { address, nonce, desired_roles: profile.roles.clone(), merchant: None };
     let claims = Claims::new(claims);
     let header = Header::empty().with_token_type("JWT");
     let key = profile.secret_key().ok_or_else(|| anyhow!("Profile {} is missing a secret key", profile.name))?;
@@ -1,7 +1,7 @@
 use std::{
     fmt::Display,
     iter::Sum,
-    ops::{Add, Mul, Neg, Sub, SubAssign},
+    ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign},
 };
 
 use serde::{Deserialize, Serialize};
@@ -20,6 +20,7 @@ pub struct MicroTari(i64);
 
 op!(binary MicroTari, Add, add);
 op!(binary MicroTari, Sub, sub);
+op!(inplace MicroTari, AddAssign, add_assign);
 op!(inplace MicroTari, SubAssign, sub_assign);
 op!(unary MicroTari, Neg, neg);
 